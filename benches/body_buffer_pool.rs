@@ -0,0 +1,154 @@
+//! Allocations per response with and without the body buffer pool.
+//!
+//! Downloads the same small page from a local server many times, dropping each response
+//! as a crawl does after `parse`, and reports the heap allocations and allocated bytes
+//! per response:
+//!
+//! ```text
+//! cargo bench --bench body_buffer_pool
+//! ```
+
+use spider_lib::prelude::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use url::Url;
+
+/// Number of responses downloaded per run.
+const RESPONSES: u64 = 2_000;
+
+/// Size of the page served, in bytes.
+const PAGE_SIZE: usize = 16 * 1024;
+
+/// Size of the chunks the page is sent in, so the body grows while it is read.
+const CHUNK_SIZE: usize = 1024;
+
+/// Counts the allocations made through the system allocator.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Serves a `PAGE_SIZE` page in `CHUNK_SIZE` chunks over keep-alive connections.
+async fn serve() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut response = String::from(
+        "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ntransfer-encoding: chunked\r\n\r\n",
+    );
+    for _ in 0..PAGE_SIZE / CHUNK_SIZE {
+        response.push_str(&format!(
+            "{:x}\r\n{}\r\n",
+            CHUNK_SIZE,
+            "x".repeat(CHUNK_SIZE)
+        ));
+    }
+    response.push_str("0\r\n\r\n");
+    let response: &'static [u8] = response.leak().as_bytes();
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                loop {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                    while let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        request.drain(..end + 4);
+                        if socket.write_all(response).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Downloads `RESPONSES` pages and prints the allocations per response.
+async fn run(name: &str, downloader: HttpDownloader, addr: SocketAddr) -> u64 {
+    let url = Url::parse(&format!("http://{addr}/")).unwrap();
+    // Warms up the connection and the pool.
+    drop(
+        downloader
+            .download(Request::new(url.clone()))
+            .await
+            .unwrap(),
+    );
+
+    let (allocations, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED_BYTES.load(Ordering::Relaxed),
+    );
+    let started = Instant::now();
+    for _ in 0..RESPONSES {
+        let response = downloader
+            .download(Request::new(url.clone()))
+            .await
+            .unwrap();
+        assert_eq!(response.body.len(), PAGE_SIZE);
+    }
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+
+    println!(
+        "{name:>10}: {:>8.1} allocations, {:>9.0} bytes allocated, {:?} per response",
+        allocations as f64 / RESPONSES as f64,
+        bytes as f64 / RESPONSES as f64,
+        elapsed / RESPONSES as u32,
+    );
+    bytes
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let addr = serve().await;
+
+    let fresh = run("fresh", HttpDownloader::new().unwrap(), addr).await;
+    let pooled = run(
+        "pooled",
+        HttpDownloader::builder()
+            .body_buffer_pool(true)
+            .build()
+            .unwrap(),
+        addr,
+    )
+    .await;
+
+    println!(
+        "{:>10}: {:.0} bytes less allocated per response",
+        "saved",
+        fresh.saturating_sub(pooled) as f64 / RESPONSES as f64
+    );
+}
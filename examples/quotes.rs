@@ -50,27 +50,18 @@ impl Spider for QuotesSpider {
         let html = response.to_html()?;
         let mut output = ParseOutput::new();
 
-        for quote in html.select(&".quote".to_selector()?) {
-            let text = quote
-                .select(&".text".to_selector()?)
-                .next()
-                .map(|e| e.text().collect())
-                .unwrap_or_default();
-            let author = quote
-                .select(&".author".to_selector()?)
-                .next()
-                .map(|e| e.text().collect())
-                .unwrap_or_default();
-            output.add_item(QuoteItem { text, author });
+        let quote_selectors = ItemSelectors::new()
+            .field("text", FieldRule::text(".text"))
+            .field("author", FieldRule::text(".author"));
+        for quote in extract_records(&html, ".quote", &quote_selectors)? {
+            output.add_item(QuoteItem {
+                text: quote.text("text"),
+                author: quote.text("author"),
+            });
         }
 
-        if let Some(next_href) = html
-            .select(&".next > a[href]".to_selector()?)
-            .next()
-            .and_then(|a| a.attr("href"))
-        {
-            let next_url = response.url.join(next_href)?;
-            output.add_request(Request::new(next_url));
+        if let Some(next_href) = html.css_attr(".next > a", "href")? {
+            output.add_request(response.follow(&next_href)?);
         }
 
         Ok(output)
@@ -0,0 +1,251 @@
+//! Extension methods for [`CrawlerBuilder`].
+//!
+//! These configure the facade's features with the same builder chain as the engine's
+//! own settings:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .allowed_domains(&["example.com"])
+//!     .max_concurrent_downloads(8)
+//!     .build()
+//!     .await?;
+//! ```
+
+use crate::crawl::CrawlControl;
+use crate::event_log::EventLog;
+use crate::keep_alive::KeepAlive;
+use crate::middleware::{
+    autothrottle::AutoThrottleMiddleware,
+    content_filter::ContentFilterMiddleware,
+    depth::DepthMiddleware,
+    dupe_filter::DupeFilterMiddleware,
+    https_upgrade::HttpsUpgradeMiddleware,
+    offsite::OffsiteMiddleware,
+    path_prefix::PathPrefixMiddleware,
+    politeness::{NoPolitenessMiddleware, Polite},
+    ramp::{ConcurrencyRampMiddleware, RampUpMiddleware},
+    url_length::UrlLengthMiddleware,
+};
+use crate::pending::PendingParses;
+use crate::scheduler::Scheduling;
+use spider_core::{CrawlerBuilder, Downloader, Spider};
+use spider_middleware::rate_limit::RateLimitMiddleware;
+use spider_util::error::SpiderError;
+use spider_util::http_client::HttpClient;
+use std::time::Duration;
+
+/// Extension methods for [`CrawlerBuilder`].
+pub trait CrawlerBuilderExt: Sized {
+    /// Downloads the requests of the spider's parses in the order chosen by the
+    /// scheduler of `scheduling`, see [`crate::scheduler`]. The spider must be wrapped
+    /// with [`Scheduling::wrap`].
+    ///
+    /// This adds [`Scheduling::middleware`], so call it before adding other middlewares.
+    fn scheduler(self, scheduling: &Scheduling) -> Self;
+
+    /// Drops requests more than `depth.max_depth()` links away from the start requests,
+    /// see [`DepthMiddleware`]. The spider must be wrapped with [`DepthMiddleware::wrap`].
+    ///
+    /// The middleware added is a clone, so `depth` reports the counts after the crawl.
+    fn max_depth(self, depth: &DepthMiddleware) -> Self;
+
+    /// Drops requests to hosts outside `domains`, see [`OffsiteMiddleware`].
+    ///
+    /// A domain allows its subdomains too, `*.example.com` only the subdomains.
+    fn allowed_domains(self, domains: &[&str]) -> Self;
+
+    /// Drops off-site requests as `offsite` is configured, for example with the sites of
+    /// [`OffsiteMiddleware::for_spider`], see [`OffsiteMiddleware`].
+    ///
+    /// The middleware added is a clone, so `offsite` reports the drops after the crawl.
+    fn offsite(self, offsite: &OffsiteMiddleware) -> Self;
+
+    /// Drops requests whose URL is longer than `max_length` bytes, see
+    /// [`UrlLengthMiddleware`].
+    fn max_url_length(self, max_length: usize) -> Self;
+
+    /// Drops requests and responses for binary assets, see [`ContentFilterMiddleware`].
+    ///
+    /// The middleware added is a clone, so `filter` reports the counts after the crawl.
+    fn content_filter(self, filter: &ContentFilterMiddleware) -> Self;
+
+    /// Filters duplicates as `filter` is configured, see [`DupeFilterMiddleware`].
+    ///
+    /// Set every duplicate rule on this one middleware, since each middleware added
+    /// keeps its own seen sets. Call it before adding other middlewares, so they see the
+    /// real request URL of exempted requests.
+    fn dupe_filter(self, filter: DupeFilterMiddleware) -> Self;
+
+    /// Upgrades `http://` requests to HTTPS when `enabled`, see [`HttpsUpgradeMiddleware`].
+    /// Add the middleware yourself to also fall back to HTTP.
+    fn upgrade_to_https(self, enabled: bool) -> Self;
+
+    /// Drops requests whose URL path does not start with `prefix`, see
+    /// [`PathPrefixMiddleware`].
+    ///
+    /// Each call adds a separate filter, so call it once. For several prefixes or
+    /// prefixes per host, add a [`PathPrefixMiddleware`] yourself.
+    fn restrict_to_path_prefix(self, prefix: &str) -> Self;
+
+    /// Caps the requests in flight at `start` and raises the cap linearly to `target`
+    /// over `over`, see [`ConcurrencyRampMiddleware`]. Fails with a
+    /// `ConfigurationError` if `start` is zero or above `target`.
+    ///
+    /// The cap holds requests back before the middlewares added after it, so call it
+    /// early, and keep `target` at or below `max_concurrent_downloads`.
+    fn concurrency_ramp(
+        self,
+        start: usize,
+        target: usize,
+        over: Duration,
+    ) -> Result<Self, SpiderError>;
+
+    /// Starts requests at `start` per second and raises the rate linearly to `target`
+    /// over `over`, see [`RampUpMiddleware`]. Fails with a `ConfigurationError` if
+    /// `start` or `target` is not a positive, finite number.
+    ///
+    /// This ramps the rate requests start at, not the number in flight, which
+    /// [`concurrency_ramp`](Self::concurrency_ramp) does. The ramp paces requests before
+    /// the middlewares added after it, so call it early.
+    fn rate_ramp(self, start: f64, target: f64, over: Duration) -> Result<Self, SpiderError>;
+
+    /// Adapts the delay between requests to each host's latency, see
+    /// [`AutoThrottleMiddleware`]. Keep a clone of `throttle` to read the delays.
+    ///
+    /// Without recorded timings the latency is measured from this middleware on, so
+    /// call it after adding the other middlewares.
+    fn autothrottle(self, throttle: &AutoThrottleMiddleware) -> Self;
+
+    /// Paces requests with `rate_limit`, wrapped in [`Polite`] so that
+    /// [`no_politeness`](Self::no_politeness) switches it off.
+    fn rate_limit(self, rate_limit: RateLimitMiddleware) -> Self;
+
+    /// Turns off pacing, jitter, crawl-delays and robots.txt checks for every request,
+    /// see [`NoPolitenessMiddleware`]. Logs a warning.
+    ///
+    /// This covers the facade's politeness middlewares and those added with
+    /// [`rate_limit`](Self::rate_limit) or wrapped in [`Polite`]. The mark is set before
+    /// the middlewares added after it, so call it first.
+    ///
+    /// For benchmarks against servers you run only: never crawl third-party sites with
+    /// it.
+    fn no_politeness(self) -> Self;
+
+    /// Holds back downloaded responses while `pending.limit()` responses wait for
+    /// parsing, see [`crate::pending`]. The spider must be wrapped with
+    /// [`PendingParses::wrap`].
+    ///
+    /// This adds [`PendingParses::middleware`], so call it before adding other
+    /// middlewares.
+    fn max_pending_parses(self, pending: &PendingParses) -> Self;
+
+    /// Keeps the crawl from ending while responses wait to be parsed, see
+    /// [`crate::keep_alive`]. The spider must be wrapped with [`KeepAlive::wrap`].
+    ///
+    /// This adds [`KeepAlive::middleware`], so call it before adding other middlewares.
+    fn keep_alive(self, keep_alive: &KeepAlive) -> Self;
+
+    /// Writes requests, responses and scraped items to `log`, see [`crate::event_log`].
+    /// Wrap the spider with [`EventLog::wrap`] to also record parse errors.
+    ///
+    /// This adds [`EventLog::middleware`] and [`EventLog::pipeline`]. The pipeline counts
+    /// as a configured pipeline, so add an output pipeline as well.
+    fn event_log(self, log: &EventLog) -> Self;
+
+    /// Lets `control` stop the crawl and enforce its byte, item and time limits, see
+    /// [`crate::crawl`]. Run the crawler with [`CrawlControl::run`].
+    ///
+    /// This adds [`CrawlControl::middleware`] and [`CrawlControl::pipeline`], so call it
+    /// before adding other middlewares and pipelines. The pipeline counts as a
+    /// configured pipeline, so add an output pipeline as well.
+    fn crawl_control(self, control: &CrawlControl) -> Self;
+}
+
+impl<S, D> CrawlerBuilderExt for CrawlerBuilder<S, D>
+where
+    S: Spider,
+    D: Downloader,
+    D::Client: HttpClient,
+{
+    fn scheduler(self, scheduling: &Scheduling) -> Self {
+        self.add_middleware(scheduling.middleware())
+    }
+
+    fn max_depth(self, depth: &DepthMiddleware) -> Self {
+        self.add_middleware(depth.clone())
+    }
+
+    fn allowed_domains(self, domains: &[&str]) -> Self {
+        self.add_middleware(OffsiteMiddleware::new(domains))
+    }
+
+    fn offsite(self, offsite: &OffsiteMiddleware) -> Self {
+        self.add_middleware(offsite.clone())
+    }
+
+    fn max_url_length(self, max_length: usize) -> Self {
+        self.add_middleware(UrlLengthMiddleware::with_max_length(max_length))
+    }
+
+    fn content_filter(self, filter: &ContentFilterMiddleware) -> Self {
+        self.add_middleware(filter.clone())
+    }
+
+    fn dupe_filter(self, filter: DupeFilterMiddleware) -> Self {
+        self.add_middleware(filter)
+    }
+
+    fn upgrade_to_https(self, enabled: bool) -> Self {
+        if !enabled {
+            return self;
+        }
+        self.add_middleware(HttpsUpgradeMiddleware::new())
+    }
+
+    fn restrict_to_path_prefix(self, prefix: &str) -> Self {
+        self.add_middleware(PathPrefixMiddleware::new().prefix(prefix))
+    }
+
+    fn concurrency_ramp(
+        self,
+        start: usize,
+        target: usize,
+        over: Duration,
+    ) -> Result<Self, SpiderError> {
+        Ok(self.add_middleware(ConcurrencyRampMiddleware::new(start, target, over)?))
+    }
+
+    fn rate_ramp(self, start: f64, target: f64, over: Duration) -> Result<Self, SpiderError> {
+        Ok(self.add_middleware(RampUpMiddleware::new(start, target, over)?))
+    }
+
+    fn autothrottle(self, throttle: &AutoThrottleMiddleware) -> Self {
+        self.add_middleware(throttle.clone())
+    }
+
+    fn rate_limit(self, rate_limit: RateLimitMiddleware) -> Self {
+        self.add_middleware(Polite::new(rate_limit))
+    }
+
+    fn no_politeness(self) -> Self {
+        self.add_middleware(NoPolitenessMiddleware::new())
+    }
+
+    fn max_pending_parses(self, pending: &PendingParses) -> Self {
+        self.add_middleware(pending.middleware())
+    }
+
+    fn keep_alive(self, keep_alive: &KeepAlive) -> Self {
+        self.add_middleware(keep_alive.middleware())
+    }
+
+    fn event_log(self, log: &EventLog) -> Self {
+        self.add_middleware(log.middleware())
+            .add_pipeline(log.pipeline())
+    }
+
+    fn crawl_control(self, control: &CrawlControl) -> Self {
+        self.add_middleware(control.middleware())
+            .add_pipeline(control.pipeline())
+    }
+}
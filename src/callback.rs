@@ -0,0 +1,414 @@
+//! Routing responses to named callbacks.
+//!
+//! By default every response is handed to [`Spider::parse`]. A [`CallbackSpider`]
+//! registers named callbacks in its [`Callbacks`], and a request naming one with
+//! [`RequestExt::callback`] has its response parsed by that callback instead. The name
+//! is kept in the request meta under [`CALLBACK_KEY`], which travels from the request to
+//! its response, so it survives retries and checkpoints:
+//!
+//! ```rust,ignore
+//! output.add_request(response.follow(&href)?.callback("parse_book"));
+//!
+//! impl CallbackSpider for BooksSpider {
+//!     fn callbacks() -> Callbacks<Self> {
+//!         Callbacks::<Self>::new()
+//!             .add("parse_book", |spider, response, state| {
+//!                 Box::pin(spider.parse_book(response, state))
+//!             })
+//!             .fallback("parse_default")
+//!     }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(BooksSpider.routed()).build().await?;
+//! ```
+//!
+//! A response is resolved to a callback in this order:
+//!
+//! 1. A response without a callback name goes to [`Spider::parse`].
+//! 2. A registered callback name goes to that callback.
+//! 3. An unknown callback name, for instance one restored from a checkpoint written
+//!    before the callback was renamed, goes to the [`fallback`](Callbacks::fallback)
+//!    callback if one is registered, and to [`Spider::parse`] otherwise. A warning naming
+//!    the unknown callback is logged, so the response is never dropped silently.
+//!
+//! A typed callback receives the response already converted by an adapter, for instance
+//! an API page deserialized from JSON with [`add_json`](Callbacks::add_json), along with
+//! the response itself:
+//!
+//! ```rust,ignore
+//! Callbacks::<Self>::new()
+//!     .add_json("parse_api", |spider, page: ApiPage, response, state| {
+//!         Box::pin(spider.parse_api(page, response, state))
+//!     })
+//!     .errback(|spider, error, response, state| {
+//!         Box::pin(spider.parse_error(error, response, state))
+//!     })
+//! ```
+//!
+//! When the adapter fails, the callback is skipped and the response goes to the
+//! [`errback`](Callbacks::errback) with the error. Without an errback, the error is
+//! returned from `parse`. Responses that failed their request's validators, see
+//! [`crate::validate`], are handled the same way, whichever callback they name.
+//!
+//! A request can also name its own errback with [`RequestExt::errback`], to react when
+//! it fails for good, for instance by requesting a mirror:
+//!
+//! ```rust,ignore
+//! Callbacks::<Self>::new().add_errback("try_mirror", |spider, error, request, state| {
+//!     Box::pin(spider.try_mirror(error, request, state))
+//! })
+//!
+//! output.add_request(Request::new(url).errback("try_mirror"));
+//! ```
+//!
+//! The engine drops failed requests without telling the spider, so a failure is
+//! delivered as a response marked under [`FAILURE_KEY`]. [`RetryPolicyMiddleware`]
+//! passes on the response to a request with an errback once its retries are exhausted,
+//! instead of dropping it. Downloads that fail in the engine's own downloader never reach
+//! a middleware; [`HttpDownloader`](crate::downloader::HttpDownloader), where it drives
+//! the downloads, answers them with an empty placeholder response with status
+//! [`FAILED_DOWNLOAD_STATUS`] and the transport error under [`FAILURE_ERROR_KEY`], which
+//! `RetryPolicyMiddleware` retries like the error itself. The errback is called with the
+//! failed request, and the output it returns is scheduled like any other. Failures and
+//! failed validations of a request whose errback is not registered go to the
+//! [`errback`](Callbacks::errback) for all responses, if there is one.
+//!
+//! The middlewares that look at what a server sent, such as content filters, validators
+//! and the WARC writer, pass the placeholder on untouched, see
+//! [`ResponseExt::is_failed_download`]. The engine still counts it as a succeeded request
+//! and a received response; [`HttpDownloader::traffic`] counts the placeholders, so the
+//! readers of the statistics count them as failed requests instead, see
+//! [Synthetic traffic](crate::stats#synthetic-traffic):
+//!
+//! ```rust,ignore
+//! let downloader = HttpDownloader::new()?;
+//! let failed_downloads = downloader.traffic().clone();
+//! let crawler = CrawlerBuilder::new(BooksSpider.routed())
+//!     .downloader(downloader)
+//!     .build()
+//!     .await?;
+//! let stats = crawler.get_stats();
+//! crawler.start_crawl().await?;
+//! let snapshot = stats.snapshot().excluding(&failed_downloads);
+//! ```
+//!
+//! [`RequestExt::callback`]: crate::request::RequestExt::callback
+//! [`RequestExt::errback`]: crate::request::RequestExt::errback
+//! [`RetryPolicyMiddleware`]: crate::middleware::retry::RetryPolicyMiddleware
+//! [`FAILED_DOWNLOAD_STATUS`]: crate::downloader::FAILED_DOWNLOAD_STATUS
+//! [`HttpDownloader::traffic`]: crate::downloader::HttpDownloader::traffic
+
+use crate::request::RequestExt;
+use crate::response::ResponseExt;
+use log::warn;
+use serde::de::DeserializeOwned;
+use spider_core::Spider;
+use spider_util::{error::SpiderError, item::ParseOutput, request::Request, response::Response};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+
+/// Meta key holding the name of the callback that parses a request's response.
+pub const CALLBACK_KEY: &str = "callback";
+
+/// Meta key holding the name of the errback handling a request's failure.
+pub const ERRBACK_KEY: &str = "errback";
+
+/// Meta key marking a placeholder response for a request that failed for good, holding
+/// the reason.
+pub const FAILURE_KEY: &str = "failure";
+
+/// Meta key holding the transport error of a placeholder response whose download
+/// failed in transport, see [`ResponseExt::failure_error`].
+pub const FAILURE_ERROR_KEY: &str = "failure_error";
+
+/// The future returned by a callback.
+pub type CallbackFuture<'a, I> =
+    Pin<Box<dyn Future<Output = Result<ParseOutput<I>, SpiderError>> + Send + 'a>>;
+
+type CallbackFn<S> = Box<
+    dyn for<'a> Fn(
+            &'a S,
+            Response,
+            &'a <S as Spider>::State,
+        ) -> CallbackFuture<'a, <S as Spider>::Item>
+        + Send
+        + Sync,
+>;
+
+type ErrbackFn<S> = Box<
+    dyn for<'a> Fn(
+            &'a S,
+            SpiderError,
+            Response,
+            &'a <S as Spider>::State,
+        ) -> CallbackFuture<'a, <S as Spider>::Item>
+        + Send
+        + Sync,
+>;
+
+type RequestErrbackFn<S> = Box<
+    dyn for<'a> Fn(
+            &'a S,
+            SpiderError,
+            Request,
+            &'a <S as Spider>::State,
+        ) -> CallbackFuture<'a, <S as Spider>::Item>
+        + Send
+        + Sync,
+>;
+
+/// The named callbacks of a [`CallbackSpider`].
+///
+/// Create it with `Callbacks::<Self>::new()`, so the closures passed to
+/// [`add`](Self::add) know the spider type.
+pub struct Callbacks<S: Spider> {
+    callbacks: HashMap<String, CallbackFn<S>>,
+    fallback: Option<String>,
+    errback: Arc<OnceLock<ErrbackFn<S>>>,
+    errbacks: HashMap<String, RequestErrbackFn<S>>,
+}
+
+impl<S: Spider> Callbacks<S> {
+    /// Creates a set without callbacks, where every response goes to [`Spider::parse`].
+    pub fn new() -> Self {
+        Self {
+            callbacks: HashMap::new(),
+            fallback: None,
+            errback: Arc::new(OnceLock::new()),
+            errbacks: HashMap::new(),
+        }
+    }
+
+    /// Registers `callback` under `name`.
+    pub fn add<F>(mut self, name: &str, callback: F) -> Self
+    where
+        F: for<'a> Fn(&'a S, Response, &'a S::State) -> CallbackFuture<'a, S::Item>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.callbacks.insert(name.to_string(), Box::new(callback));
+        self
+    }
+
+    /// Registers `callback` under `name`, receiving the response converted by `adapter`
+    /// as well as the response. Responses `adapter` fails on go to the
+    /// [`errback`](Self::errback) instead.
+    pub fn add_typed<T, A, F>(mut self, name: &str, adapter: A, callback: F) -> Self
+    where
+        T: Send + 'static,
+        A: Fn(&Response) -> Result<T, SpiderError> + Send + Sync + 'static,
+        F: for<'a> Fn(&'a S, T, Response, &'a S::State) -> CallbackFuture<'a, S::Item>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let errback = self.errback.clone();
+        let name = name.to_string();
+        self.callbacks.insert(
+            name.clone(),
+            Box::new(move |spider, response, state| match adapter(&response) {
+                Ok(data) => callback(spider, data, response, state),
+                Err(error) => {
+                    warn!(
+                        "Callback `{}` could not convert {}: {}",
+                        name, response.url, error
+                    );
+                    match errback.get() {
+                        Some(errback) => errback(spider, error, response, state),
+                        None => Box::pin(async move { Err(error) }),
+                    }
+                }
+            }),
+        );
+        self
+    }
+
+    /// Registers `callback` under `name`, receiving the response body deserialized from
+    /// JSON, decoded like [`ResponseExt::json`]. Bodies that are not valid JSON for `T` go
+    /// to the [`errback`](Self::errback).
+    pub fn add_json<T, F>(self, name: &str, callback: F) -> Self
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: for<'a> Fn(&'a S, T, Response, &'a S::State) -> CallbackFuture<'a, S::Item>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.add_typed(
+            name,
+            |response| {
+                ResponseExt::json(response).map_err(|e| SpiderError::JsonError(e.to_string()))
+            },
+            callback,
+        )
+    }
+
+    /// Handles the responses a typed callback's adapter fails on, with the adapter's
+    /// error, and the responses that failed validation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an errback is already registered.
+    pub fn errback<F>(self, errback: F) -> Self
+    where
+        F: for<'a> Fn(&'a S, SpiderError, Response, &'a S::State) -> CallbackFuture<'a, S::Item>
+            + Send
+            + Sync
+            + 'static,
+    {
+        if self.errback.set(Box::new(errback)).is_err() {
+            panic!("an errback is already registered");
+        }
+        self
+    }
+
+    /// Registers `errback` under `name`, for requests naming it with
+    /// [`RequestExt::errback`](crate::request::RequestExt::errback). It receives the
+    /// failure and the failed request.
+    pub fn add_errback<F>(mut self, name: &str, errback: F) -> Self
+    where
+        F: for<'a> Fn(&'a S, SpiderError, Request, &'a S::State) -> CallbackFuture<'a, S::Item>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.errbacks.insert(name.to_string(), Box::new(errback));
+        self
+    }
+
+    /// Parses responses naming an unknown callback with the callback registered under
+    /// `name`, instead of [`Spider::parse`].
+    pub fn fallback(mut self, name: &str) -> Self {
+        self.fallback = Some(name.to_string());
+        self
+    }
+
+    /// Returns whether a callback is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.callbacks.contains_key(name)
+    }
+
+    /// Parses `response` with the callback it names, see the [module docs](self) for the
+    /// resolution order.
+    pub async fn dispatch(
+        &self,
+        spider: &S,
+        response: Response,
+        state: &S::State,
+    ) -> Result<ParseOutput<S::Item>, SpiderError> {
+        if let Some(failure) = response.failure() {
+            let error = SpiderError::GeneralError(format!(
+                "request for {} failed: {}",
+                response.original_request_url(),
+                failure
+            ));
+            return self.fail(spider, error, response, state).await;
+        }
+        if let Some(failure) = response.validation_error() {
+            let error = SpiderError::GeneralError(format!(
+                "response from {} failed validation: {}",
+                response.url, failure
+            ));
+            return self.fail(spider, error, response, state).await;
+        }
+        let name = response
+            .meta
+            .get(CALLBACK_KEY)
+            .and_then(|name| name.as_str().map(str::to_string));
+        let Some(name) = name else {
+            return spider.parse(response, state).await;
+        };
+        if let Some(callback) = self.callbacks.get(&name) {
+            return callback(spider, response, state).await;
+        }
+
+        let fallback = self
+            .fallback
+            .as_ref()
+            .and_then(|fallback| Some((fallback, self.callbacks.get(fallback)?)));
+        match fallback {
+            Some((fallback, callback)) => {
+                warn!(
+                    "Unknown callback `{}` for {}, using `{}`",
+                    name, response.url, fallback
+                );
+                callback(spider, response, state).await
+            }
+            None => {
+                warn!(
+                    "Unknown callback `{}` for {}, using `parse`",
+                    name, response.url
+                );
+                spider.parse(response, state).await
+            }
+        }
+    }
+
+    /// Hands a failed request to its errback, the errback for all responses, or returns
+    /// `error`.
+    async fn fail(
+        &self,
+        spider: &S,
+        error: SpiderError,
+        response: Response,
+        state: &S::State,
+    ) -> Result<ParseOutput<S::Item>, SpiderError> {
+        let request = response.replay_request();
+        if let Some(name) = request.errback_name() {
+            if let Some(errback) = self.errbacks.get(&name) {
+                request.meta.remove(FAILURE_KEY);
+                return errback(spider, error, request, state).await;
+            }
+            warn!("Unknown errback `{}` for {}", name, request.url);
+        }
+        match self.errback.get() {
+            Some(errback) => errback(spider, error, response, state).await,
+            None => Err(error),
+        }
+    }
+}
+
+impl<S: Spider> Default for Callbacks<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A spider with named callbacks besides [`Spider::parse`].
+pub trait CallbackSpider: Spider + Sized {
+    /// Returns the spider's named callbacks.
+    fn callbacks() -> Callbacks<Self>;
+
+    /// Wraps the spider so each response is parsed by the callback its request names.
+    fn routed(self) -> Routed<Self> {
+        Routed {
+            callbacks: Self::callbacks(),
+            spider: self,
+        }
+    }
+}
+
+/// A [`CallbackSpider`] dispatching responses to its callbacks, see
+/// [`CallbackSpider::routed`].
+pub struct Routed<S: Spider> {
+    spider: S,
+    callbacks: Callbacks<S>,
+}
+
+impl<S: Spider> Routed<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Routed<S: Spider> {
+        async fn parse(&self, response, state) {
+            self.callbacks.dispatch(&self.spider, response, state).await
+        }
+    }
+}
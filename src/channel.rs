@@ -0,0 +1,119 @@
+//! Consuming scraped items live from a channel.
+//!
+//! The file pipelines write items out for later. To handle them as they are scraped,
+//! for example to push them to a message queue, a [`ChannelPipeline`] sends each item
+//! to a Tokio channel and the caller keeps the receiver:
+//!
+//! ```rust,ignore
+//! let (pipeline, mut items) = ChannelPipeline::new(100);
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_pipeline(pipeline)
+//!     .build()
+//!     .await?;
+//! let consumer = tokio::spawn(async move {
+//!     while let Some(item) = items.recv().await {
+//!         producer.send(&item).await?;
+//!     }
+//!     Ok::<_, Error>(())
+//! });
+//! crawler.start_crawl().await?;
+//! consumer.await??;
+//! ```
+//!
+//! [`ChannelPipeline::ndjson`] sends each item as one line of JSON instead, ready for a
+//! consumer that only forwards text.
+//!
+//! The channel is bounded. When it is full, the pipeline waits for the consumer, which
+//! holds up the crawl's item processing until there is room again: a slow consumer
+//! slows the crawl down instead of growing a queue. The pipeline passes every item on
+//! to the pipelines after it. When the pipelines are closed at the end of the crawl the
+//! channel is closed too, so the receiver returns `None` once it has taken every item.
+//! If the receiver is dropped, items are passed on without being sent.
+
+use log::{debug, warn};
+use spider_core::{async_trait, tokio};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+/// A pipeline sending each item to a channel, see the [module docs](self).
+pub struct ChannelPipeline<I, T = I> {
+    /// The sending half, taken when the pipeline is closed or the receiver is gone.
+    sender: Mutex<Option<Sender<T>>>,
+    convert: fn(&I) -> T,
+}
+
+impl<I: Clone> ChannelPipeline<I> {
+    /// Creates a pipeline sending items to a channel holding up to `capacity` items,
+    /// and returns it with the channel's receiver.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> (Self, Receiver<I>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (Self::from_sender(sender), receiver)
+    }
+
+    /// Creates a pipeline sending items through `sender`.
+    pub fn from_sender(sender: Sender<I>) -> Self {
+        Self {
+            sender: Mutex::new(Some(sender)),
+            convert: I::clone,
+        }
+    }
+}
+
+impl<I: ScrapedItem> ChannelPipeline<I, String> {
+    /// Creates a pipeline sending each item as a line of JSON, without a trailing
+    /// newline, to a channel holding up to `capacity` lines, and returns it with the
+    /// channel's receiver.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn ndjson(capacity: usize) -> (Self, Receiver<String>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let pipeline = Self {
+            sender: Mutex::new(Some(sender)),
+            convert: |item| item.to_json_value().to_string(),
+        };
+        (pipeline, receiver)
+    }
+}
+
+impl<I, T> ChannelPipeline<I, T> {
+    fn sender(&self) -> std::sync::MutexGuard<'_, Option<Sender<T>>> {
+        self.sender.lock().expect("channel pipeline poisoned")
+    }
+}
+
+#[async_trait]
+impl<I, T> Pipeline<I> for ChannelPipeline<I, T>
+where
+    I: ScrapedItem,
+    T: Send + 'static,
+{
+    fn name(&self) -> &str {
+        "ChannelPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        let Some(sender) = self.sender().clone() else {
+            return Ok(Some(item));
+        };
+        if sender.send((self.convert)(&item)).await.is_err() {
+            warn!("The item channel's receiver was dropped, no longer sending items");
+            self.sender().take();
+        }
+        Ok(Some(item))
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        if self.sender().take().is_some() {
+            debug!("Closing the item channel");
+        }
+        Ok(())
+    }
+}
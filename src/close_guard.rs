@@ -0,0 +1,154 @@
+//! Closing pipelines on every exit path.
+//!
+//! The engine closes its pipelines when a crawl finishes or is interrupted with Ctrl-C.
+//! A crawl that ends any other way drops its pipelines without closing them, and a
+//! pipeline that buffers its output loses the tail of it. This happens when
+//! `start_crawl` is cut short by a timeout and the runtime shuts down, which drops the
+//! crawl's tasks, or when it returns early with an error.
+//!
+//! A [`CloseGuard`] closes the pipeline it wraps when it is dropped, unless the engine
+//! already did. The guard is opt-in: the engine owns its pipelines once they are added,
+//! so neither the builder nor [`CrawlControl`](crate::crawl::CrawlControl) can wrap them,
+//! and each pipeline to flush must be wrapped with
+//! [`close_on_drop`](PipelineExt::close_on_drop) before it is added:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_pipeline(JsonlWriterPipeline::new("items.jsonl")?.close_on_drop())
+//!     .build()
+//!     .await?;
+//!
+//! // Items scraped before the timeout are still flushed to items.jsonl when the
+//! // runtime shuts down at the end of `main`.
+//! let _ = tokio::time::timeout(Duration::from_secs(600), crawler.start_crawl()).await;
+//! ```
+//!
+//! `close` is async and a drop cannot await it, so the guard runs it to completion on a
+//! separate thread with its own runtime, and the dropping thread waits for it. On a
+//! worker of a multi-threaded runtime the wait goes through
+//! [`block_in_place`](tokio::task::block_in_place), which hands the worker's other tasks
+//! to another thread first. A current-thread runtime has no other thread to hand them
+//! to, so its tasks wait for the close. Errors from a close on drop are logged.
+
+use log::{debug, error};
+use serde_json::Value;
+use spider_core::{async_trait, tokio};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// A pipeline that is closed when dropped if the engine did not close it, see
+/// [`PipelineExt::close_on_drop`].
+pub struct CloseGuard<P, I>
+where
+    P: Pipeline<I>,
+    I: ScrapedItem,
+{
+    pipeline: P,
+    closed: AtomicBool,
+    _item: PhantomData<fn(I)>,
+}
+
+impl<P, I> CloseGuard<P, I>
+where
+    P: Pipeline<I>,
+    I: ScrapedItem,
+{
+    /// Wraps `pipeline`.
+    pub fn new(pipeline: P) -> Self {
+        Self {
+            pipeline,
+            closed: AtomicBool::new(false),
+            _item: PhantomData,
+        }
+    }
+
+    /// Returns the wrapped pipeline.
+    pub fn pipeline(&self) -> &P {
+        &self.pipeline
+    }
+}
+
+#[async_trait]
+impl<P, I> Pipeline<I> for CloseGuard<P, I>
+where
+    P: Pipeline<I>,
+    I: ScrapedItem,
+{
+    fn name(&self) -> &str {
+        self.pipeline.name()
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        self.pipeline.process_item(item).await
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.pipeline.close().await
+    }
+
+    async fn get_state(&self) -> Result<Option<Value>, PipelineError> {
+        self.pipeline.get_state().await
+    }
+
+    async fn restore_state(&self, state: Value) -> Result<(), PipelineError> {
+        self.pipeline.restore_state(state).await
+    }
+}
+
+impl<P, I> Drop for CloseGuard<P, I>
+where
+    P: Pipeline<I>,
+    I: ScrapedItem,
+{
+    fn drop(&mut self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        debug!(
+            "Pipeline {} dropped without being closed, closing it",
+            self.pipeline.name()
+        );
+        let pipeline = &self.pipeline;
+        let close = || {
+            std::thread::scope(|scope| {
+                scope
+                    .spawn(|| {
+                        tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .build()
+                            .map_err(PipelineError::from)?
+                            .block_on(pipeline.close())
+                    })
+                    .join()
+            })
+        };
+        let result = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(close)
+            }
+            _ => close(),
+        };
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to close pipeline {}: {}", pipeline.name(), e),
+            Err(_) => error!("Closing pipeline {} panicked", pipeline.name()),
+        }
+    }
+}
+
+/// Adds [`close_on_drop`](PipelineExt::close_on_drop) to every [`Pipeline`].
+pub trait PipelineExt<I: ScrapedItem>: Pipeline<I> + Sized {
+    /// Closes the pipeline when the crawl drops it without closing it, see
+    /// [`crate::close_guard`].
+    fn close_on_drop(self) -> CloseGuard<Self, I> {
+        CloseGuard::new(self)
+    }
+}
+
+impl<P: Pipeline<I>, I: ScrapedItem> PipelineExt<I> for P {}
@@ -0,0 +1,125 @@
+//! Read-only run configuration for spiders.
+//!
+//! Run-specific settings such as API keys, a target category or a date range belong in
+//! neither globals nor the mutable crawl `State`. A [`ContextSpider`] declares a
+//! `Context` type that is handed to `start_requests` and `parse` next to the state, and
+//! [`ContextSpider::with_context`] turns it into a regular [`Spider`] for the builder:
+//!
+//! ```rust,ignore
+//! struct Config {
+//!     api_key: String,
+//!     category: String,
+//! }
+//!
+//! #[async_trait]
+//! impl ContextSpider for ProductsSpider {
+//!     type Item = Product;
+//!     type State = ();
+//!     type Context = Config;
+//!
+//!     fn start_requests(&self, ctx: &Config) -> Result<Vec<Request>, SpiderError> {
+//!         let url = format!("https://api.example.com/{}", ctx.category);
+//!         Ok(vec![Request::new(Url::parse(&url)?)])
+//!     }
+//!
+//!     async fn parse(&self, response: Response, state: &(), ctx: &Config)
+//!         -> Result<ParseOutput<Product>, SpiderError> { ... }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(ProductsSpider.with_context(config))
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Spiders without configuration keep implementing [`Spider`] directly.
+
+use spider_core::async_trait;
+use spider_util::{
+    error::SpiderError,
+    item::{ParseOutput, ScrapedItem},
+    request::Request,
+    response::Response,
+};
+use url::Url;
+
+/// A spider that receives a read-only context in addition to its crawl state.
+///
+/// This mirrors [`Spider`], with the context passed to `start_requests` and `parse`.
+#[async_trait]
+pub trait ContextSpider: Send + Sync + 'static {
+    /// The type of item the spider scrapes.
+    type Item: ScrapedItem;
+
+    /// The mutable state shared by all `parse` calls, as in [`Spider::State`].
+    type State: Default + Send + Sync;
+
+    /// The read-only configuration of a run.
+    type Context: Send + Sync + 'static;
+
+    /// Returns the URLs the crawl starts from, unless `start_requests` is overridden.
+    fn start_urls(&self, _ctx: &Self::Context) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Returns the requests the crawl starts from.
+    fn start_requests(&self, ctx: &Self::Context) -> Result<Vec<Request>, SpiderError> {
+        let urls: Result<Vec<Url>, url::ParseError> =
+            self.start_urls(ctx).into_iter().map(Url::parse).collect();
+        Ok(urls?.into_iter().map(Request::new).collect())
+    }
+
+    /// Parses a response into items and follow-up requests.
+    async fn parse(
+        &self,
+        response: Response,
+        state: &Self::State,
+        ctx: &Self::Context,
+    ) -> Result<ParseOutput<Self::Item>, SpiderError>;
+
+    /// Pairs the spider with the context of a run, giving a [`Spider`] for
+    /// `CrawlerBuilder::new`.
+    fn with_context(self, context: Self::Context) -> WithContext<Self>
+    where
+        Self: Sized,
+    {
+        WithContext {
+            spider: self,
+            context,
+        }
+    }
+}
+
+/// A [`ContextSpider`] together with its context, see [`ContextSpider::with_context`].
+pub struct WithContext<S: ContextSpider> {
+    spider: S,
+    context: S::Context,
+}
+
+impl<S: ContextSpider> WithContext<S> {
+    /// Pairs `spider` with `context`.
+    pub fn new(spider: S, context: S::Context) -> Self {
+        spider.with_context(context)
+    }
+
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+
+    /// Returns the context of the run.
+    pub fn context(&self) -> &S::Context {
+        &self.context
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for WithContext<S: ContextSpider> {
+        fn start_requests(&self) {
+            self.spider.start_requests(&self.context)
+        }
+
+        async fn parse(&self, response, state) {
+            self.spider.parse(response, state, &self.context).await
+        }
+    }
+}
@@ -0,0 +1,599 @@
+//! Running a crawl and finding out why it ended.
+//!
+//! `Crawler::start_crawl` returns `()`, whether the crawl ran out of requests or was
+//! interrupted. [`CrawlControl`] wraps it: [`CrawlControl::run`] returns a
+//! [`CrawlSummary`] with the [`CloseReason`], the crawl statistics and its duration, and
+//! [`CrawlControl::stop`] ends a crawl early from any task.
+//!
+//! Stopping is graceful. The control's middleware drops every request that has not
+//! started downloading, so the crawl drains its in-flight work and shuts down through
+//! the engine's normal path, closing the pipelines on the way. Add the middleware before
+//! the others, so dropped requests skip throttling and the like:
+//!
+//! ```rust,ignore
+//! let control = CrawlControl::new();
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(control.middleware())
+//!     .add_middleware(RetryMiddleware::new())
+//!     .build()
+//!     .await?;
+//!
+//! let summary = control.run(crawler).await;
+//! match summary.reason {
+//!     CloseReason::Finished => println!("done in {:?}", summary.duration),
+//!     reason => println!("stopped early: {reason}"),
+//! }
+//! ```
+//!
+//! Without the middleware the control could not stop the crawl, so [`CrawlControl::run`]
+//! does not start a crawler built without it and reports a [`CloseReason::Error`].
+//!
+//! A control can also enforce a bandwidth budget. With [`CrawlControl::max_bytes`], the
+//! crawl closes with [`CloseReason::MaxBytes`] once the response bodies downloaded exceed
+//! the budget. The control's middleware counts the bytes on the wire, so a body that
+//! [`HttpDownloader`](crate::downloader::HttpDownloader) decoded counts at its compressed
+//! size. The engine's `total_bytes_downloaded` stat counts the decoded body instead, and
+//! is not used for the budget:
+//!
+//! ```rust,ignore
+//! let control = CrawlControl::new().max_bytes(500 * 1024 * 1024);
+//! // ... build the crawler with control.middleware() as above ...
+//! let summary = control.run(crawler).await;
+//! println!("{:?} bytes of budget left", summary.bytes_remaining);
+//! ```
+//!
+//! The first Ctrl-C during [`CrawlControl::run`] stops the crawl like
+//! [`CrawlControl::stop`], with [`CloseReason::Shutdown`]: no new requests are sent,
+//! in-flight downloads, parses and pipeline writes finish, and the pipelines are closed.
+//! With the `checkpoint` feature and `CrawlerBuilder::with_checkpoint_path`, the engine
+//! writes a final checkpoint on the way out, so the crawl can be resumed. A second
+//! Ctrl-C makes [`CrawlControl::run`] return at once with a [`CloseReason::Error`],
+//! abandoning the in-flight work, unless turned off with
+//! [`CrawlControl::force_exit_on_second_interrupt`]. The process is not exited, so the
+//! code after `run`, and the `closed` hook of [`crate::lifecycle`], still run.
+//!
+//! A crawl can also be paused and resumed from any task with [`CrawlControl::pause`] and
+//! [`CrawlControl::resume`]. While paused, requests already downloading, parsing or in the
+//! pipelines finish, but no new request starts: the control's middleware holds the next
+//! one until the crawl is resumed or stopped, without retrying it, so pausing leaves the
+//! retry counts alone. The engine runs the middlewares one request or response at a
+//! time, so responses that arrive while paused reach the spider after the resume. The
+//! held request keeps the crawl alive, so a paused crawl does not end on its own;
+//! stopping it still works.
+//!
+//! ```rust,ignore
+//! let control = CrawlControl::new();
+//! // ... build the crawler with control.middleware() as above ...
+//! let handle = control.clone();
+//! tokio::spawn(async move {
+//!     handle.pause();
+//!     tokio::time::sleep(Duration::from_secs(60)).await;
+//!     handle.resume();
+//! });
+//! let summary = control.run(crawler).await;
+//! ```
+//!
+//! For sampling runs, a control can end the crawl after a number of items or a length of
+//! time. [`CrawlControl::max_items`] closes it with [`CloseReason::ItemLimitReached`]
+//! and [`CrawlControl::max_duration`] with [`CloseReason::TimeLimitReached`]; either
+//! way the crawl stops scheduling and drains like [`CrawlControl::stop`]. The limits are
+//! enforced by [`CrawlControl::run`], which also watches the engine's item count, so
+//! they hold without the control's pipeline too. The pipeline, added first,
+//! keeps the pipelines after it from seeing more than `max_items` items, whatever
+//! parses are still in flight:
+//!
+//! ```rust,ignore
+//! let control = CrawlControl::new()
+//!     .max_items(1_000)
+//!     .max_duration(Duration::from_secs(600));
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .crawl_control(&control)
+//!     .add_pipeline(JsonlWriterPipeline::new("sample.jsonl")?)
+//!     .build()
+//!     .await?;
+//! let summary = control.run(crawler).await;
+//! ```
+//!
+//! For CI dashboards, [`CrawlControl::stats_file`] writes the final statistics to a JSON
+//! file when the run ends, see [`crate::stats`].
+
+use crate::lifecycle::{Lifecycle, LifecycleSpider};
+use crate::middleware::control::ControlMiddleware;
+use crate::stats::{ByteStats, StatCollectorExt, StatsSnapshot, SyntheticTraffic};
+use log::{debug, info, warn};
+use spider_core::{Crawler, Spider, async_trait, stats::StatCollector, tokio};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{
+    error::{PipelineError, SpiderError},
+    item::ScrapedItem,
+};
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// How often [`CrawlControl::run`] compares the crawl statistics with the limits.
+const LIMITS_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Why a crawl ended.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum CloseReason {
+    /// The crawl ran out of requests.
+    Finished,
+    /// The process received Ctrl-C.
+    Shutdown,
+    /// [`CrawlControl::stop`] was called.
+    Stopped,
+    /// The bytes downloaded exceeded [`CrawlControl::max_bytes`].
+    MaxBytes,
+    /// [`CrawlControl::max_items`] items were scraped.
+    ItemLimitReached,
+    /// The crawl ran for [`CrawlControl::max_duration`].
+    TimeLimitReached,
+    /// The crawl failed.
+    Error(SpiderError),
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloseReason::Finished => write!(f, "finished"),
+            CloseReason::Shutdown => write!(f, "shutdown"),
+            CloseReason::Stopped => write!(f, "stopped"),
+            CloseReason::MaxBytes => write!(f, "byte budget exceeded"),
+            CloseReason::ItemLimitReached => write!(f, "item limit reached"),
+            CloseReason::TimeLimitReached => write!(f, "time limit reached"),
+            CloseReason::Error(error) => write!(f, "error: {}", error),
+        }
+    }
+}
+
+/// The outcome of a crawl run through [`CrawlControl::run`].
+#[derive(Debug, Clone)]
+pub struct CrawlSummary {
+    /// Why the crawl ended.
+    pub reason: CloseReason,
+    /// The statistics collected during the crawl.
+    pub stats: Arc<StatCollector>,
+    /// How long the crawl ran.
+    pub duration: Duration,
+    /// The number of requests that failed to download.
+    pub failed_urls_count: usize,
+    /// What was left of the [`CrawlControl::max_bytes`] budget, if one was set.
+    pub bytes_remaining: Option<usize>,
+}
+
+#[derive(Debug)]
+struct ControlState {
+    reason: Mutex<Option<CloseReason>>,
+    /// The byte budget, `usize::MAX` when there is none.
+    max_bytes: AtomicUsize,
+    bytes_downloaded: AtomicUsize,
+    /// The item limit, `usize::MAX` when there is none.
+    max_items: AtomicUsize,
+    items_passed: AtomicUsize,
+    max_duration: Mutex<Option<Duration>>,
+    force_exit: AtomicBool,
+    /// Set once the control's middleware has been created.
+    has_middleware: AtomicBool,
+    paused: AtomicBool,
+    /// Wakes the requests held while paused, on resume and on stop.
+    unpaused: Notify,
+    stats_file: Mutex<Option<PathBuf>>,
+    byte_stats: ByteStats,
+    excluded: Mutex<Vec<SyntheticTraffic>>,
+}
+
+impl Default for ControlState {
+    fn default() -> Self {
+        Self {
+            reason: Mutex::new(None),
+            max_bytes: AtomicUsize::new(usize::MAX),
+            bytes_downloaded: AtomicUsize::new(0),
+            max_items: AtomicUsize::new(usize::MAX),
+            items_passed: AtomicUsize::new(0),
+            max_duration: Mutex::new(None),
+            force_exit: AtomicBool::new(true),
+            has_middleware: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            unpaused: Notify::new(),
+            stats_file: Mutex::new(None),
+            byte_stats: ByteStats::new(),
+            excluded: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// A handle for stopping or pausing a crawl and reporting how it ended.
+///
+/// Clones share the same crawl, so a clone can be moved into another task to stop or
+/// pause it.
+#[derive(Debug, Clone, Default)]
+pub struct CrawlControl {
+    state: Arc<ControlState>,
+}
+
+impl CrawlControl {
+    /// Creates a control for one crawl.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the middleware that enforces this control. Add it to the crawler first;
+    /// [`run`](Self::run) does not start a crawl without it.
+    pub fn middleware(&self) -> ControlMiddleware {
+        ControlMiddleware::new(self.clone())
+    }
+
+    /// Records that the control's middleware was created for a crawler.
+    pub(crate) fn middleware_created(&self) {
+        self.state.has_middleware.store(true, Ordering::SeqCst);
+    }
+
+    /// Closes the crawl with [`CloseReason::MaxBytes`] once the response bodies
+    /// downloaded add up to more than `limit` bytes on the wire. Requests already in
+    /// flight still complete, so the total may end up somewhat above the limit.
+    pub fn max_bytes(self, limit: usize) -> Self {
+        self.state.max_bytes.store(limit, Ordering::SeqCst);
+        self
+    }
+
+    /// Closes the crawl with [`CloseReason::ItemLimitReached`] once `limit` items have
+    /// been scraped. The control's [`pipeline`](Self::pipeline) also drops any item past
+    /// the limit; without it, items from parses in flight still reach the pipelines.
+    pub fn max_items(self, limit: usize) -> Self {
+        self.state.max_items.store(limit, Ordering::SeqCst);
+        self
+    }
+
+    /// Closes the crawl with [`CloseReason::TimeLimitReached`] once [`run`](Self::run)
+    /// has been running for `limit`. In-flight work still completes after that.
+    pub fn max_duration(self, limit: Duration) -> Self {
+        *self
+            .state
+            .max_duration
+            .lock()
+            .expect("crawl control poisoned") = Some(limit);
+        self
+    }
+
+    /// Returns the pipeline that counts items against [`max_items`](Self::max_items).
+    /// Add it before the other pipelines.
+    pub fn pipeline<I>(&self) -> ControlPipeline<I> {
+        ControlPipeline {
+            control: self.clone(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Counts an item against the item limit, closing the crawl when it is reached.
+    /// Returns `false` for the items past the limit.
+    fn admit_item(&self) -> bool {
+        let limit = self.state.max_items.load(Ordering::SeqCst);
+        // fetch_add hands every item its own position, so concurrent parses cannot
+        // both take the last place.
+        let position = self.state.items_passed.fetch_add(1, Ordering::SeqCst) + 1;
+        if position >= limit {
+            self.close(CloseReason::ItemLimitReached);
+        }
+        position <= limit
+    }
+
+    /// Sets whether a second Ctrl-C during [`run`](Self::run) makes it return without
+    /// waiting for in-flight work, closing with [`CloseReason::Error`]. Defaults to
+    /// `true`.
+    pub fn force_exit_on_second_interrupt(self, enabled: bool) -> Self {
+        self.state.force_exit.store(enabled, Ordering::SeqCst);
+        self
+    }
+
+    /// Writes a [`StatsSnapshot`](crate::stats::StatsSnapshot) of the crawl to `path` as
+    /// JSON when [`run`](Self::run) finishes, with the [`byte_stats`](Self::byte_stats)
+    /// under `response_sizes`, see [`crate::stats`].
+    pub fn stats_file(self, path: impl Into<PathBuf>) -> Self {
+        *self
+            .state
+            .stats_file
+            .lock()
+            .expect("crawl control poisoned") = Some(path.into());
+        self
+    }
+
+    /// Leaves the placeholder requests counted by `traffic` out of the
+    /// [`stats_file`](Self::stats_file) and counts its failed downloads in
+    /// [`CrawlSummary::failed_urls_count`], see
+    /// [Synthetic traffic](crate::stats#synthetic-traffic). May be called once per wrapper.
+    pub fn excluding(self, traffic: &SyntheticTraffic) -> Self {
+        self.state
+            .excluded
+            .lock()
+            .expect("crawl control poisoned")
+            .push(traffic.clone());
+        self
+    }
+
+    /// Returns the bytes downloaded and the response sizes seen by the control's
+    /// middleware, see [`crate::stats`].
+    pub fn byte_stats(&self) -> &ByteStats {
+        &self.state.byte_stats
+    }
+
+    /// Returns how many bytes are left of the [`max_bytes`](Self::max_bytes) budget, or
+    /// `None` if no budget was set.
+    pub fn remaining_bytes(&self) -> Option<usize> {
+        let limit = self.state.max_bytes.load(Ordering::SeqCst);
+        (limit != usize::MAX)
+            .then(|| limit.saturating_sub(self.state.bytes_downloaded.load(Ordering::SeqCst)))
+    }
+
+    /// Counts the `wire_bytes` a response body took to download against the budget and
+    /// in the byte stats, with its `body_bytes` once decoded, closing the crawl when the
+    /// budget is exceeded.
+    pub(crate) fn record_bytes(&self, wire_bytes: usize, body_bytes: usize) {
+        let total = self
+            .state
+            .bytes_downloaded
+            .fetch_add(wire_bytes, Ordering::SeqCst)
+            .saturating_add(wire_bytes);
+        self.state.byte_stats.add_bytes(wire_bytes);
+        self.state.byte_stats.record_response(body_bytes);
+        if total > self.state.max_bytes.load(Ordering::SeqCst) {
+            self.close(CloseReason::MaxBytes);
+        }
+    }
+
+    /// Stops the crawl with [`CloseReason::Stopped`].
+    pub fn stop(&self) {
+        self.close(CloseReason::Stopped);
+    }
+
+    /// Stops the crawl with `reason`. The first reason recorded wins.
+    pub fn close(&self, reason: CloseReason) {
+        let mut current = self.state.reason.lock().expect("crawl control poisoned");
+        if current.is_none() {
+            info!("Stopping crawl: {}", reason);
+            *current = Some(reason);
+        }
+        drop(current);
+        self.state.unpaused.notify_waiters();
+    }
+
+    /// Pauses the crawl: no new request starts until [`resume`](Self::resume) is called.
+    /// Work already in flight continues.
+    pub fn pause(&self) {
+        if !self.state.paused.swap(true, Ordering::SeqCst) {
+            info!("Pausing crawl");
+        }
+    }
+
+    /// Resumes a crawl paused with [`pause`](Self::pause).
+    pub fn resume(&self) {
+        if self.state.paused.swap(false, Ordering::SeqCst) {
+            info!("Resuming crawl");
+        }
+        self.state.unpaused.notify_waiters();
+    }
+
+    /// Returns `true` while the crawl is paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the crawl is resumed or asked to stop.
+    pub(crate) async fn wait_while_paused(&self) {
+        loop {
+            // Registered before the check, so a resume in between still wakes it.
+            let unpaused = self.state.unpaused.notified();
+            if !self.is_paused() || self.is_stopping() {
+                return;
+            }
+            unpaused.await;
+        }
+    }
+
+    /// Returns `true` once the crawl has been asked to stop.
+    pub fn is_stopping(&self) -> bool {
+        self.close_reason().is_some()
+    }
+
+    /// Returns the reason the crawl was asked to stop, if it was.
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.state
+            .reason
+            .lock()
+            .expect("crawl control poisoned")
+            .clone()
+    }
+
+    /// Runs `crawler` to completion and reports why it ended.
+    ///
+    /// Ctrl-C triggers a graceful shutdown, reported as [`CloseReason::Shutdown`], and a
+    /// second Ctrl-C returns at once with [`CloseReason::Error`], see the
+    /// [module docs](self).
+    ///
+    /// The crawler must have been built with the control's
+    /// [`middleware`](Self::middleware), usually through `CrawlerBuilderExt::crawl_control`;
+    /// otherwise the crawl is not started and the summary reports a
+    /// `SpiderError::ConfigurationError`.
+    pub async fn run<S, C>(&self, crawler: Crawler<S, C>) -> CrawlSummary
+    where
+        S: Spider + 'static,
+        S::Item: ScrapedItem,
+        C: Send + Sync + Clone + 'static,
+    {
+        let stats = crawler.get_stats();
+        let started = Instant::now();
+        if !self.state.has_middleware.load(Ordering::SeqCst) {
+            warn!("Not starting the crawl, the crawl control's middleware was not added");
+            self.close(CloseReason::Error(SpiderError::ConfigurationError(
+                "CrawlControl::run needs the control's middleware, add it with \
+                 CrawlerBuilderExt::crawl_control or CrawlControl::middleware"
+                    .to_string(),
+            )));
+            return self.summary(stats, started);
+        }
+
+        let control = self.clone();
+        let (interrupted_twice, second_interrupt) = tokio::sync::oneshot::channel();
+        let ctrl_c = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            control.close(CloseReason::Shutdown);
+            if !control.state.force_exit.load(Ordering::SeqCst) {
+                return;
+            }
+            info!("Finishing in-flight work, press Ctrl-C again to stop waiting for it");
+            if tokio::signal::ctrl_c().await.is_ok() {
+                let _ = interrupted_twice.send(());
+            }
+        });
+        let max_duration = *self
+            .state
+            .max_duration
+            .lock()
+            .expect("crawl control poisoned");
+        let control = self.clone();
+        let timer = tokio::spawn(async move {
+            if let Some(limit) = max_duration {
+                tokio::time::sleep(limit).await;
+                control.close(CloseReason::TimeLimitReached);
+            }
+        });
+        let control = self.clone();
+        let limits_stats = stats.clone();
+        let limits = tokio::spawn(async move { control.watch_limits(&limits_stats).await });
+        let result = tokio::select! {
+            result = crawler.start_crawl() => result,
+            Ok(()) = second_interrupt => {
+                warn!("Second Ctrl-C received, abandoning in-flight work");
+                *self.state.reason.lock().expect("crawl control poisoned") =
+                    Some(CloseReason::Error(SpiderError::GeneralError(
+                        "crawl interrupted twice, in-flight work was abandoned".to_string(),
+                    )));
+                Ok(())
+            }
+        };
+        ctrl_c.abort();
+        timer.abort();
+        limits.abort();
+
+        if let Err(error) = result {
+            self.close(CloseReason::Error(error));
+        }
+        // The crawl may have finished between two checks.
+        self.check_limits(&stats);
+        self.summary(stats, started)
+    }
+
+    /// Closes the crawl once the engine's statistics pass the item limit, for items the
+    /// control's pipeline does not see. Bytes are only counted by the control's
+    /// middleware, see [`max_bytes`](Self::max_bytes).
+    async fn watch_limits(&self, stats: &StatCollector) {
+        if self.state.max_items.load(Ordering::SeqCst) == usize::MAX {
+            return;
+        }
+        let mut interval = tokio::time::interval(LIMITS_CHECK_INTERVAL);
+        while !self.is_stopping() {
+            interval.tick().await;
+            self.check_limits(stats);
+        }
+    }
+
+    fn check_limits(&self, stats: &StatCollector) {
+        let items = stats.items_scraped.load(Ordering::SeqCst);
+        if items >= self.state.max_items.load(Ordering::SeqCst) {
+            self.close(CloseReason::ItemLimitReached);
+        }
+    }
+
+    /// Runs `crawler` like [`run`](Self::run), calling the hooks of `spider`, the
+    /// [`Lifecycle`] the crawler was built with, before and after, see
+    /// [`crate::lifecycle`].
+    pub async fn run_with_lifecycle<S, C>(
+        &self,
+        crawler: Crawler<Lifecycle<S>, C>,
+        spider: &Lifecycle<S>,
+    ) -> CrawlSummary
+    where
+        S: LifecycleSpider,
+        S::Item: ScrapedItem,
+        C: Send + Sync + Clone + 'static,
+    {
+        let summary = match spider.spider().opened(spider.state()).await {
+            Ok(()) => self.run(crawler).await,
+            Err(error) => {
+                warn!(
+                    "Not starting the crawl, the spider failed to open: {}",
+                    error
+                );
+                self.close(CloseReason::Error(error));
+                self.summary(crawler.get_stats(), Instant::now())
+            }
+        };
+        spider
+            .spider()
+            .closed(spider.state(), &summary.reason)
+            .await;
+        summary
+    }
+
+    fn summary(&self, stats: Arc<StatCollector>, started: Instant) -> CrawlSummary {
+        let stats_file = self
+            .state
+            .stats_file
+            .lock()
+            .expect("crawl control poisoned")
+            .clone();
+        let excluded = self
+            .state
+            .excluded
+            .lock()
+            .expect("crawl control poisoned")
+            .clone();
+        if let Some(path) = stats_file
+            && let Err(error) = excluded
+                .iter()
+                .fold(stats.snapshot(), StatsSnapshot::excluding)
+                .with_byte_stats(&self.state.byte_stats)
+                .write_json(&path)
+        {
+            warn!("Cannot write crawl stats to {}: {}", path.display(), error);
+        }
+        let failed_downloads: usize = excluded.iter().map(SyntheticTraffic::failed).sum();
+        CrawlSummary {
+            reason: self.close_reason().unwrap_or(CloseReason::Finished),
+            failed_urls_count: stats.requests_failed.load(Ordering::SeqCst) + failed_downloads,
+            bytes_remaining: self.remaining_bytes(),
+            stats,
+            duration: started.elapsed(),
+        }
+    }
+}
+
+/// Counts items against [`CrawlControl::max_items`] and drops those past the limit;
+/// usually obtained from [`CrawlControl::pipeline`].
+pub struct ControlPipeline<I> {
+    control: CrawlControl,
+    _item: PhantomData<fn(I)>,
+}
+
+#[async_trait]
+impl<I: ScrapedItem> Pipeline<I> for ControlPipeline<I> {
+    fn name(&self) -> &str {
+        "ControlPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        if self.control.admit_item() {
+            Ok(Some(item))
+        } else {
+            debug!("Item limit reached, dropping item");
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Recording failed requests and re-crawling just those.
+//!
+//! A [`DeadLetterSink`] appends every request that finally failed to a JSONL file, one
+//! [`DeadLetter`] per line, holding the request's URL, method, headers, body and meta
+//! and the reason it failed. Requests reach the sink from
+//! [`RetryPolicyMiddleware::dead_letter`] once their retries are exhausted, and from
+//! [`DeadLetterMiddleware`] for responses with an error status that are not retried:
+//!
+//! ```rust,ignore
+//! let sink = DeadLetterSink::new("failed.jsonl")?;
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(DeadLetterMiddleware::new(sink.clone()))
+//!     .add_middleware(RetryPolicyMiddleware::default().dead_letter(sink))
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! A later run re-crawls only the failures, giving them more attempts this time:
+//!
+//! ```rust,ignore
+//! let spider = MySpider.retry_failed_from("failed.jsonl")?.max_retries(10);
+//! let crawler = CrawlerBuilder::new(spider).build().await?;
+//! ```
+//!
+//! [`DeadLetterMiddleware`]: crate::middleware::dead_letter::DeadLetterMiddleware
+//! [`RetryPolicyMiddleware::dead_letter`]:
+//!     crate::middleware::retry::RetryPolicyMiddleware::dead_letter
+
+use crate::request::RequestExt;
+use log::warn;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use spider_core::Spider;
+use spider_util::{
+    error::SpiderError,
+    request::{Body, Request},
+};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Meta key the engine counts retry attempts in. It is cleared when a dead letter is
+/// turned back into a request, so the re-crawl starts with a fresh retry budget.
+const RETRY_ATTEMPTS_KEY: &str = "retry_attempts";
+
+/// One line of a dead-letter file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The URL of the failed request.
+    pub url: String,
+    /// The HTTP method of the failed request.
+    pub method: String,
+    /// The request headers, as name/value pairs.
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// The request body, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Body>,
+    /// The request meta.
+    #[serde(default)]
+    pub meta: Map<String, Value>,
+    /// Why the request failed, e.g. `status: 503`.
+    pub reason: String,
+}
+
+impl DeadLetter {
+    /// Records `request` as failed for `reason`.
+    pub fn new(request: &Request, reason: impl Into<String>) -> Self {
+        Self {
+            url: request.url.to_string(),
+            method: request.method.to_string(),
+            headers: request
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            body: request.body.clone(),
+            meta: request
+                .meta
+                .iter()
+                .map(|entry| (entry.key().to_string(), entry.value().clone()))
+                .collect(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Rebuilds the request, with its retry count reset.
+    pub fn into_request(self) -> Result<Request, SpiderError> {
+        let mut request = Request::new(Url::parse(&self.url)?);
+        request.method = Method::from_bytes(self.method.as_bytes()).map_err(|e| {
+            SpiderError::GeneralError(format!("invalid method {:?}: {}", self.method, e))
+        })?;
+        for (name, value) in &self.headers {
+            request = request.with_header(name, value)?;
+        }
+        request.body = self.body;
+        for (key, value) in self.meta {
+            if key != RETRY_ATTEMPTS_KEY {
+                request.meta.insert(key.into(), value);
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// Appends failed requests to a dead-letter file. Clones write to the same file.
+#[derive(Clone)]
+pub struct DeadLetterSink {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl DeadLetterSink {
+    /// Opens the dead-letter file at `path`, appending to it if it exists.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, SpiderError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::from_writer(file))
+    }
+
+    /// Writes dead letters to `writer`.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Records `request` as failed for `reason`. Write errors are logged, not returned,
+    /// so a full disk does not stop the crawl.
+    pub fn record(&self, request: &Request, reason: &str) {
+        let letter = DeadLetter::new(request, reason);
+        let mut line = match serde_json::to_vec(&letter) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize dead letter for {}: {}", request.url, e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().expect("dead-letter writer poisoned");
+        if let Err(e) = writer.write_all(&line).and_then(|()| writer.flush()) {
+            warn!("Failed to write dead letter for {}: {}", request.url, e);
+        }
+    }
+}
+
+impl std::fmt::Debug for DeadLetterSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeadLetterSink").finish_non_exhaustive()
+    }
+}
+
+/// Reads the requests recorded in the dead-letter file at `path`, in file order.
+///
+/// Blank lines are skipped. A line that is not valid JSON for a [`DeadLetter`] is an
+/// error naming its line number.
+pub fn read_dead_letters(path: impl AsRef<Path>) -> Result<Vec<Request>, SpiderError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut requests = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let letter: DeadLetter = serde_json::from_str(&line).map_err(|e| {
+            SpiderError::JsonError(format!("dead letter on line {}: {}", index + 1, e))
+        })?;
+        requests.push(letter.into_request()?);
+    }
+    Ok(requests)
+}
+
+/// A spider whose start requests are the failures of an earlier run, see
+/// [`RetryFailedExt::retry_failed_from`].
+pub struct RetryFailed<S> {
+    spider: S,
+    requests: Vec<Request>,
+}
+
+impl<S> RetryFailed<S> {
+    /// Gives every failed request up to `max_retries` retries, see
+    /// [`RequestExt::max_retries`].
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.requests = self
+            .requests
+            .into_iter()
+            .map(|request| request.max_retries(max_retries))
+            .collect();
+        self
+    }
+
+    /// Returns the requests the re-crawl starts from.
+    pub fn requests(&self) -> &[Request] {
+        &self.requests
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for RetryFailed<S: Spider> {
+        fn start_requests(&self) {
+            Ok(self.requests.clone())
+        }
+
+        async fn parse(&self, response, state) {
+            self.spider.parse(response, state).await
+        }
+    }
+}
+
+/// Adds [`retry_failed_from`](RetryFailedExt::retry_failed_from) to every [`Spider`].
+pub trait RetryFailedExt: Spider + Sized {
+    /// Replaces the spider's start requests with the requests recorded in the
+    /// dead-letter file at `path`. Responses are still parsed by the spider.
+    fn retry_failed_from(self, path: impl AsRef<Path>) -> Result<RetryFailed<Self>, SpiderError> {
+        Ok(RetryFailed {
+            spider: self,
+            requests: read_dead_letters(path)?,
+        })
+    }
+}
+
+impl<S: Spider> RetryFailedExt for S {}
@@ -0,0 +1,311 @@
+//! Replaying a single URL through the middlewares, the downloader and `parse`.
+//!
+//! Reproducing a problem with one page should not need a whole crawl. [`DebugFetch`] is
+//! set up like a crawler, with the spider, a downloader and the middlewares, and
+//! [`DebugFetch::fetch`] runs one request through them the way the engine does,
+//! recording every middleware's decision on the way:
+//!
+//! ```rust,ignore
+//! let mut debug = DebugFetch::new(MySpider, HttpDownloader::new()?)
+//!     .add_middleware(RetryMiddleware::new())
+//!     .add_middleware(ValidationMiddleware::new());
+//!
+//! let report = debug.fetch(Url::parse("https://example.com/broken")?).await;
+//! for step in &report.trace {
+//!     println!("{:?} {}: {:?}", step.stage, step.middleware, step.decision);
+//! }
+//! println!("{} retries, error: {:?}", report.retries, report.error);
+//! ```
+//!
+//! Request middlewares run in the order they were added and response middlewares in
+//! reverse order, each stage stopping at the first middleware that does not continue.
+//! A retry runs the retried request through the chain again, without waiting for the
+//! retry delay, until [`DebugFetch::max_retries`] is reached. Requests a spider returns
+//! are reported in the [`ParseOutput`], not fetched.
+
+use spider_core::{Downloader, Spider};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, item::ParseOutput, request::Request, response::Response};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// How many retries are followed by default.
+const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Which middleware hook a [`MiddlewareStep`] was recorded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// `process_request`, before the download.
+    Request,
+    /// `process_response`, after the download.
+    Response,
+}
+
+/// What a middleware decided in a [`MiddlewareStep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Passed the request or response on.
+    Continue,
+    /// Asked for the request to be retried after the delay.
+    Retry(Duration),
+    /// Dropped the request or response.
+    Drop,
+    /// Answered the request without a download.
+    ReturnResponse,
+    /// Failed with the error message.
+    Error(String),
+}
+
+/// One middleware hook run by [`DebugFetch::fetch`].
+#[derive(Debug, Clone)]
+pub struct MiddlewareStep {
+    /// The middleware's name.
+    pub middleware: String,
+    /// The hook that ran.
+    pub stage: Stage,
+    /// What the middleware decided.
+    pub decision: Decision,
+    /// How long the hook took.
+    pub elapsed: Duration,
+    /// The retry attempt the step belongs to, `0` for the first.
+    pub attempt: u32,
+}
+
+/// Everything that happened to the request replayed by [`DebugFetch::fetch`].
+#[derive(Debug)]
+pub struct DebugReport<I> {
+    /// The middleware hooks in the order they ran.
+    pub trace: Vec<MiddlewareStep>,
+    /// How many times a middleware asked for the request to be retried.
+    pub retries: u32,
+    /// The time the last download took, if the request was downloaded.
+    pub download_time: Option<Duration>,
+    /// The response as handed to `parse`, if one got that far.
+    pub response: Option<Response>,
+    /// What `parse` returned, if it was called and succeeded.
+    pub output: Option<ParseOutput<I>>,
+    /// The error that ended the replay, from a middleware, the download or `parse`.
+    pub error: Option<SpiderError>,
+    /// How long the whole replay took.
+    pub elapsed: Duration,
+}
+
+impl<I> DebugReport<I> {
+    fn new() -> Self {
+        Self {
+            trace: Vec::new(),
+            retries: 0,
+            download_time: None,
+            response: None,
+            output: None,
+            error: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Returns whether a middleware dropped the request or its response.
+    pub fn dropped(&self) -> bool {
+        self.trace
+            .last()
+            .is_some_and(|step| step.decision == Decision::Drop)
+    }
+}
+
+/// Runs single requests through a spider's middlewares, downloader and `parse`, see the
+/// [module docs](self).
+pub struct DebugFetch<S: Spider, D: Downloader> {
+    spider: S,
+    downloader: D,
+    middlewares: Vec<Box<dyn Middleware<D::Client>>>,
+    max_retries: u32,
+}
+
+enum Step<T> {
+    Continue(T),
+    Retry(Request),
+    Answered(Response),
+    Stop,
+}
+
+impl<S: Spider, D: Downloader> DebugFetch<S, D> {
+    /// Creates a replay of `spider`'s requests through `downloader`, without middlewares.
+    pub fn new(spider: S, downloader: D) -> Self {
+        Self {
+            spider,
+            downloader,
+            middlewares: Vec::new(),
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    /// Adds a middleware, like `CrawlerBuilder::add_middleware`.
+    pub fn add_middleware(mut self, middleware: impl Middleware<D::Client>) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Sets how many retries are followed before the replay stops. Defaults to 5.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Replays a request for `url` with a default spider state.
+    pub async fn fetch(&mut self, url: Url) -> DebugReport<S::Item> {
+        self.fetch_request(Request::new(url), &S::State::default())
+            .await
+    }
+
+    /// Replays `request`, parsing its response with `state`.
+    pub async fn fetch_request(
+        &mut self,
+        request: Request,
+        state: &S::State,
+    ) -> DebugReport<S::Item> {
+        let started = Instant::now();
+        let mut report = DebugReport::new();
+        let mut request = request;
+        let response = loop {
+            let attempt = report.retries;
+            let retry = match self.run(request, attempt, &mut report).await {
+                Ok(Step::Continue(response)) | Ok(Step::Answered(response)) => break response,
+                Ok(Step::Retry(retry)) => retry,
+                Ok(Step::Stop) => {
+                    report.elapsed = started.elapsed();
+                    return report;
+                }
+                Err(error) => {
+                    report.error = Some(error);
+                    report.elapsed = started.elapsed();
+                    return report;
+                }
+            };
+            if report.retries == self.max_retries {
+                report.error = Some(SpiderError::GeneralError(format!(
+                    "gave up on {} after {} retries",
+                    retry.url, report.retries
+                )));
+                report.elapsed = started.elapsed();
+                return report;
+            }
+            report.retries += 1;
+            request = retry;
+        };
+
+        report.response = Some(copy_response(&response));
+        match self.spider.parse(response, state).await {
+            Ok(output) => report.output = Some(output),
+            Err(error) => report.error = Some(error),
+        }
+        report.elapsed = started.elapsed();
+        report
+    }
+
+    /// Runs one attempt: the request middlewares, the download and the response
+    /// middlewares.
+    async fn run(
+        &mut self,
+        request: Request,
+        attempt: u32,
+        report: &mut DebugReport<S::Item>,
+    ) -> Result<Step<Response>, SpiderError> {
+        let response = match self.process_request(request, attempt, report).await? {
+            Step::Continue(request) => {
+                let started = Instant::now();
+                let downloaded = self.downloader.download(request).await;
+                report.download_time = Some(started.elapsed());
+                downloaded?
+            }
+            Step::Answered(response) => response,
+            Step::Retry(retry) => return Ok(Step::Retry(retry)),
+            Step::Stop => return Ok(Step::Stop),
+        };
+        self.process_response(response, attempt, report).await
+    }
+
+    async fn process_request(
+        &mut self,
+        request: Request,
+        attempt: u32,
+        report: &mut DebugReport<S::Item>,
+    ) -> Result<Step<Request>, SpiderError> {
+        let client = self.downloader.client();
+        let mut request = request;
+        for middleware in &mut self.middlewares {
+            let started = Instant::now();
+            let action = middleware.process_request(client, request).await;
+            let name = middleware.name().to_string();
+            let (decision, step) = decide(action);
+            report.trace.push(MiddlewareStep {
+                middleware: name,
+                stage: Stage::Request,
+                decision,
+                elapsed: started.elapsed(),
+                attempt,
+            });
+            match step? {
+                Step::Continue(next) => request = next,
+                step => return Ok(step),
+            }
+        }
+        Ok(Step::Continue(request))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+        attempt: u32,
+        report: &mut DebugReport<S::Item>,
+    ) -> Result<Step<Response>, SpiderError> {
+        let mut response = response;
+        for middleware in self.middlewares.iter_mut().rev() {
+            let started = Instant::now();
+            let action = middleware.process_response(response).await;
+            let name = middleware.name().to_string();
+            let (decision, step) = decide(action);
+            report.trace.push(MiddlewareStep {
+                middleware: name,
+                stage: Stage::Response,
+                decision,
+                elapsed: started.elapsed(),
+                attempt,
+            });
+            match step? {
+                Step::Continue(next) => response = next,
+                // Like the engine, a response answered in `process_response` is dropped.
+                Step::Answered(_) => return Ok(Step::Stop),
+                step => return Ok(step),
+            }
+        }
+        Ok(Step::Continue(response))
+    }
+}
+
+/// Turns a middleware's result into its recorded decision and the next step.
+fn decide<T>(
+    action: Result<MiddlewareAction<T>, SpiderError>,
+) -> (Decision, Result<Step<T>, SpiderError>) {
+    match action {
+        Ok(MiddlewareAction::Continue(next)) => (Decision::Continue, Ok(Step::Continue(next))),
+        Ok(MiddlewareAction::Retry(retry, delay)) => {
+            (Decision::Retry(delay), Ok(Step::Retry(*retry)))
+        }
+        Ok(MiddlewareAction::Drop) => (Decision::Drop, Ok(Step::Stop)),
+        Ok(MiddlewareAction::ReturnResponse(response)) => {
+            (Decision::ReturnResponse, Ok(Step::Answered(response)))
+        }
+        Err(error) => (Decision::Error(error.to_string()), Err(error)),
+    }
+}
+
+fn copy_response(response: &Response) -> Response {
+    Response {
+        url: response.url.clone(),
+        status: response.status,
+        headers: response.headers.clone(),
+        body: response.body.clone(),
+        request_url: response.request_url.clone(),
+        meta: response.meta.clone(),
+        cached: response.cached,
+    }
+}
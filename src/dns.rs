@@ -0,0 +1,173 @@
+//! Caching DNS resolution with a limit on concurrent lookups.
+//!
+//! A broad crawl meeting thousands of new hosts sends a burst of lookups to the system
+//! resolver, which then times out on some of them. A [`DnsResolver`] set on an
+//! [`HttpDownloader`](crate::downloader::HttpDownloader) runs at most a fixed number of
+//! lookups at once and caches their answers, so each host is looked up once per TTL:
+//!
+//! ```rust,ignore
+//! let dns = DnsResolver::new()
+//!     .max_concurrent(32)
+//!     .cache_ttl(Duration::from_secs(600));
+//! let downloader = HttpDownloader::builder().dns_resolver(dns.clone()).build()?;
+//!
+//! // After the crawl:
+//! let stats = dns.stats();
+//! println!("{} DNS cache hits, {} misses", stats.hits(), stats.misses());
+//! ```
+//!
+//! [`HttpDownloaderBuilder::max_concurrent_dns`] is a shortcut for the limit alone.
+//! Requests for a host whose lookup is running wait for it instead of starting their own.
+//! Failed lookups are not cached. The system resolver can be replaced with
+//! [`DnsResolver::with_lookup`], for tests or to resolve through a service of your own.
+//!
+//! [`HttpDownloaderBuilder::max_concurrent_dns`]:
+//!     crate::downloader::HttpDownloaderBuilder::max_concurrent_dns
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use spider_core::tokio;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// How long a resolved address is cached by default.
+pub const DEFAULT_DNS_CACHE_TTL: Duration = Duration::from_secs(300);
+
+type Lookup = dyn Fn(String) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send>>
+    + Send
+    + Sync;
+
+/// The cached answer for one host; `None` until its first lookup succeeds.
+type Slot = Arc<tokio::sync::Mutex<Option<(Vec<SocketAddr>, Instant)>>>;
+
+/// Counts of the names a [`DnsResolver`] answered from its cache and looked up.
+#[derive(Debug, Default)]
+pub struct DnsStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DnsStats {
+    /// Returns the number of names answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of names looked up, failed lookups included.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+/// A caching resolver limiting concurrent lookups, see the [module docs](self). Clones
+/// share the cache, the limit and the stats.
+#[derive(Clone)]
+pub struct DnsResolver {
+    lookup: Arc<Lookup>,
+    limit: Option<Arc<Semaphore>>,
+    ttl: Duration,
+    cache: Arc<Mutex<HashMap<String, Slot>>>,
+    stats: Arc<DnsStats>,
+}
+
+impl Default for DnsResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DnsResolver {
+    /// Creates a resolver using the system resolver, without a limit on concurrent
+    /// lookups and caching answers for [`DEFAULT_DNS_CACHE_TTL`].
+    pub fn new() -> Self {
+        Self::with_lookup(|host| async move {
+            Ok(tokio::net::lookup_host((host.as_str(), 0)).await?.collect())
+        })
+    }
+
+    /// Creates a resolver looking names up with `lookup` instead of the system resolver.
+    pub fn with_lookup<F, Fut>(lookup: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'static,
+    {
+        Self {
+            lookup: Arc::new(move |host| Box::pin(lookup(host))),
+            limit: None,
+            ttl: DEFAULT_DNS_CACHE_TTL,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            stats: Arc::new(DnsStats::default()),
+        }
+    }
+
+    /// Runs at most `max` lookups at once. Unlimited by default.
+    pub fn max_concurrent(mut self, max: usize) -> Self {
+        self.limit = Some(Arc::new(Semaphore::new(max.max(1))));
+        self
+    }
+
+    /// Caches resolved addresses for `ttl`. Defaults to [`DEFAULT_DNS_CACHE_TTL`];
+    /// `Duration::ZERO` disables the cache.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns the cache hits and misses so far. Clones of the returned handle keep
+    /// counting.
+    pub fn stats(&self) -> &Arc<DnsStats> {
+        &self.stats
+    }
+
+    /// Resolves `host`, from the cache if it was looked up less than the TTL ago.
+    pub async fn resolve_host(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+        let slot = self
+            .cache
+            .lock()
+            .expect("DNS cache poisoned")
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .clone();
+        let mut cached = slot.lock().await;
+        if let Some((addrs, resolved)) = cached.as_ref()
+            && resolved.elapsed() < self.ttl
+        {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(addrs.clone());
+        }
+
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+        let _permit = match &self.limit {
+            Some(limit) => Some(limit.acquire().await.expect("DNS limit closed")),
+            None => None,
+        };
+        let addrs = (self.lookup)(host.to_string()).await?;
+        *cached = Some((addrs.clone(), Instant::now()));
+        Ok(addrs)
+    }
+}
+
+impl std::fmt::Debug for DnsResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DnsResolver")
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.resolve_host(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,1099 @@
+//! An HTTP [`Downloader`] with a tunable read path.
+//!
+//! [`HttpDownloader`] executes [`Request`]s with `reqwest`, like the engine's
+//! `ReqwestClientDownloader`, but exposes the knobs that matter for large crawls. It
+//! implements the public [`Downloader`] trait, so it can be driven directly or by any
+//! engine that is generic over the downloader:
+//!
+//! ```rust,ignore
+//! let downloader = HttpDownloader::builder()
+//!     .body_buffer_pool(true)
+//!     .body_buffer_pool_cap(256)
+//!     .build()?;
+//!
+//! let response = downloader.download(Request::new(url)).await?;
+//! ```
+//!
+//! With [`HttpDownloaderBuilder::body_buffer_pool`], response bodies are read into
+//! buffers drawn from a [`BufferPool`], and go back to it once the response is dropped
+//! after `parse`. The pool belongs to the downloader, so a crawl gets it by handing the
+//! engine the configured downloader with `CrawlerBuilder::downloader(..)`; the engine's
+//! builder has no setting of its own for how bodies are read.
+//!
+//! [`HttpDownloader::stream`] hands back the response before its body is read, see
+//! [`crate::stream`]. Body limits configured on the builder apply to both paths.
+//!
+//! [`HttpDownloaderBuilder::connection_retries`] retries transient connection failures
+//! in place, such as a refused connect, a failed TLS handshake or a reset. This is
+//! separate from `RetryMiddleware`: the request does not go back through the scheduler
+//! and the middlewares, and the engine's `requests_retried` stat does not count it.
+//! [`HttpDownloader::connection_retries_used`] does.
+//!
+//! Redirects are followed by the downloader itself, under a [`RedirectPolicy`]. Every
+//! `Location` is resolved against the URL that sent it, so relative (`../page`) and
+//! protocol-relative (`//host/page`) targets work, and the absolute URLs that redirected
+//! are recorded in the response meta under [`REDIRECT_CHAIN_KEY`], read back with
+//! [`ResponseExt::redirect_chain`](crate::response::ResponseExt::redirect_chain). Their
+//! statuses are recorded along with them under [`REDIRECT_HOPS_KEY`], read back with
+//! [`ResponseExt::redirect_hops`](crate::response::ResponseExt::redirect_hops), so an
+//! `http` to `https` upgrade can be told apart from a move. Requests marked with
+//! [`RequestExt::no_redirects`] get the `3xx` response itself, to handle in `parse`.
+//!
+//! All requests go through one `reqwest` client and its connection pool, whatever their
+//! headers, so same-host requests and the hops of a redirect reuse idle keep-alive
+//! connections. Requests with a proxy in their meta under [`PROXY_KEY`], as set by a
+//! [`ProxyPoolMiddleware`](crate::middleware::proxy_pool::ProxyPoolMiddleware), go
+//! through a client of their proxy instead, built with the same settings the first time
+//! the proxy is used. [`HttpDownloader::connection_stats`] counts the requests sent and the
+//! connections opened for them; the pool is tuned with
+//! [`HttpDownloaderBuilder::pool_idle_timeout`] and
+//! [`HttpDownloaderBuilder::pool_max_idle_per_host`]. [`HttpDownloader::byte_stats`]
+//! counts the body bytes downloaded as they arrive and the sizes of the bodies read.
+//!
+//! Timeouts are set on the builder. [`HttpDownloaderBuilder::timeout`] bounds a whole
+//! request, 30 seconds by default, and [`RequestExt::timeout`] overrides it for one
+//! request. [`HttpDownloaderBuilder::connect_timeout`] bounds opening a connection, 10
+//! seconds by default, and [`HttpDownloaderBuilder::read_timeout`] bounds the wait for
+//! each read from the connection, unlimited by default, so a server that stalls in the
+//! middle of a body fails early. A request that runs out of time fails with a
+//! `SpiderError::ReqwestError` whose details have `is_timeout` set, which
+//! `RetryPolicyMiddleware` retries like a connection failure; [`is_timeout`] tells such
+//! errors apart. The engine's own `ReqwestClientDownloader` only takes a whole-request timeout,
+//! through `CrawlerBuilder::downloader(ReqwestClientDownloader::new_with_timeout(..))`.
+//!
+//! With [`HttpDownloaderBuilder::allowed_content_types`], the downloader checks the
+//! `Content-Type` of every response as soon as its headers arrive. The body of a response
+//! whose type is not allowed is never read: the response is returned with an empty body
+//! and [`BODY_SKIPPED_KEY`](crate::stream::BODY_SKIPPED_KEY) set in its meta, and its
+//! connection is closed. Pair it with a
+//! [`ContentFilterMiddleware`](crate::middleware::content_filter::ContentFilterMiddleware)
+//! allowing the same types, which drops such responses before they reach the spider.
+//!
+//! Requests ask for compressed bodies with `Accept-Encoding: gzip, deflate, br`, unless
+//! they set the header themselves, and bodies are decoded as they are read, on both the
+//! buffered and the streaming path, see [`crate::stream`]. [`HttpDownloader::byte_stats`]
+//! counts the compressed bytes, while the response body, and so `to_html`, holds the
+//! decoded page. Turn it off with [`HttpDownloaderBuilder::decompress`] to get the raw
+//! bytes. The engine's own `ReqwestClientDownloader` does not ask for compressed bodies,
+//! and does not decode them.
+
+use crate::callback::{ERRBACK_KEY, FAILURE_ERROR_KEY, FAILURE_KEY};
+use crate::dns::{DnsResolver, DnsStats};
+use crate::middleware::content_filter::content_type_allowed;
+use crate::middleware::proxy_pool::PROXY_KEY;
+use crate::request::RequestExt;
+use crate::stats::{ByteStats, SyntheticTraffic};
+use crate::stream::{LimitAction, StreamResponse};
+use crate::timing::instrument::{TimedConnectLayer, TimedResolver};
+use crate::timing::{CONNECT_SLOT, ConnectSlot, PendingTimings, TimingStats};
+use crate::utils::registrable_domain;
+use bytes::{Bytes, BytesMut};
+use log::debug;
+use reqwest::header::{
+    ACCEPT_ENCODING, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HeaderValue, LOCATION,
+};
+use reqwest::{Client, Method, Proxy, RequestBuilder, StatusCode, redirect};
+use serde_json::json;
+use spider_core::tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use spider_core::{Downloader, async_trait};
+use spider_util::{
+    error::{ReqwestErrorDetails, SpiderError},
+    request::{Body, Request},
+    response::Response,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower_layer::Layer;
+use tower_service::Service;
+use url::Url;
+
+/// Status of the placeholder response for a request with an errback whose download
+/// failed, see [`crate::callback`]. The placeholders are counted in
+/// [`HttpDownloader::traffic`].
+pub const FAILED_DOWNLOAD_STATUS: u16 = 599;
+
+/// Prefix of the message of a transport error the downloader recognized as a connection
+/// reset, see [`is_connection_reset`].
+pub const CONNECTION_RESET_PREFIX: &str = "connection reset: ";
+
+/// Response meta key holding the absolute URLs that redirected, in the order they were
+/// requested. The URL the response finally came from is its `url`.
+pub const REDIRECT_CHAIN_KEY: &str = "redirect_chain";
+
+/// Response meta key holding every redirect as a `{"status": ..., "url": ...}` object,
+/// in the order they were followed, where `url` is the URL that answered with `status`.
+pub const REDIRECT_HOPS_KEY: &str = "redirect_hops";
+
+/// Timeout applied to a whole request when none is configured.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout for opening a connection when none is configured.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of redirects followed by default before giving up.
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Number of idle body buffers kept by default when pooling is enabled.
+const DEFAULT_POOL_CAP: usize = 64;
+
+/// Buffers that grew beyond this while reading a body are dropped instead of pooled, so
+/// one huge page does not pin its memory for the rest of the crawl.
+const MAX_POOLED_CAPACITY: usize = 1024 * 1024;
+
+/// A pool of reusable buffers for reading response bodies.
+///
+/// Reading a body into a fresh buffer grows it several times for every response. With a
+/// pool, a buffer that already has the right capacity is reused, and the response body
+/// is that buffer itself, without a copy. The buffer goes back to the pool when the last
+/// clone of the body is dropped, which for a crawl is after `parse` is done with the
+/// response, so a body is never cleared while it is still read. A body kept alive, for
+/// instance in a scraped item, keeps its buffer out of the pool until it is dropped.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    cap: usize,
+    reused: AtomicU64,
+    allocated: AtomicU64,
+}
+
+impl BufferPool {
+    /// Creates a pool that keeps at most `cap` idle buffers.
+    pub fn new(cap: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            cap,
+            reused: AtomicU64::new(0),
+            allocated: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes a buffer from the pool, or allocates a new one if the pool is empty.
+    pub fn take(&self) -> BytesMut {
+        let pooled = self.buffers.lock().expect("buffer pool poisoned").pop();
+        match pooled {
+            Some(buffer) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            None => {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                BytesMut::new()
+            }
+        }
+    }
+
+    /// Clears `buffer` and returns it to the pool, unless the pool is full or the buffer
+    /// grew too large to be worth keeping.
+    pub fn give_back(&self, mut buffer: BytesMut) {
+        if buffer.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        let mut buffers = self.buffers.lock().expect("buffer pool poisoned");
+        if buffers.len() < self.cap {
+            buffers.push(buffer);
+        }
+    }
+
+    /// Returns `buffer` as a response body that goes back to the pool once dropped.
+    pub(crate) fn into_body(self: &Arc<Self>, buffer: BytesMut) -> Bytes {
+        Bytes::from_owner(PooledBody {
+            buffer,
+            pool: Arc::clone(self),
+        })
+    }
+
+    /// Returns the number of buffers handed out from the pool.
+    pub fn reused(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of buffers allocated because the pool was empty.
+    pub fn allocated(&self) -> u64 {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of idle buffers currently in the pool.
+    pub fn idle(&self) -> usize {
+        self.buffers.lock().expect("buffer pool poisoned").len()
+    }
+}
+
+/// A pooled buffer backing a response body, returned to its pool on drop.
+struct PooledBody {
+    buffer: BytesMut,
+    pool: Arc<BufferPool>,
+}
+
+impl AsRef<[u8]> for PooledBody {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Drop for PooledBody {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buffer));
+    }
+}
+
+/// Counts of the requests an [`HttpDownloader`] sent and the connections it opened.
+///
+/// Every redirect hop and in-place retry is a request of its own. A request that did not
+/// open a connection was sent on a pooled keep-alive connection.
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    requests: AtomicU64,
+    created: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Returns the number of requests sent.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of connections opened.
+    pub fn connections_created(&self) -> u64 {
+        self.created.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of requests sent on an already open connection.
+    pub fn connections_reused(&self) -> u64 {
+        self.requests().saturating_sub(self.connections_created())
+    }
+}
+
+/// Counts the connections opened by the wrapped connector.
+#[derive(Clone)]
+struct CountingConnectLayer(Arc<ConnectionStats>);
+
+impl<S> Layer<S> for CountingConnectLayer {
+    type Service = CountingConnect<S>;
+
+    fn layer(&self, inner: S) -> CountingConnect<S> {
+        CountingConnect {
+            inner,
+            stats: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CountingConnect<S> {
+    inner: S,
+    stats: Arc<ConnectionStats>,
+}
+
+impl<S, R> Service<R> for CountingConnect<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let stats = self.stats.clone();
+        let connecting = self.inner.call(request);
+        Box::pin(async move {
+            let connection = connecting.await;
+            if connection.is_ok() {
+                stats.created.fetch_add(1, Ordering::Relaxed);
+            }
+            connection
+        })
+    }
+}
+
+/// How an [`HttpDownloader`] follows redirects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    max_redirects: usize,
+    refuse_downgrade: bool,
+}
+
+impl RedirectPolicy {
+    /// Follows up to 10 redirects, across schemes.
+    pub fn new() -> Self {
+        Self {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            refuse_downgrade: false,
+        }
+    }
+
+    /// Does not follow redirects; the `3xx` response itself is returned.
+    pub fn none() -> Self {
+        Self::new().max_redirects(0)
+    }
+
+    /// Follows up to `max` redirects per request. One more fails the download.
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Fails the download instead of following a redirect from `https` to `http`.
+    pub fn refuse_downgrade(mut self, refuse: bool) -> Self {
+        self.refuse_downgrade = refuse;
+        self
+    }
+
+    /// Resolves the `location` header of a redirect sent by `from` to the absolute URL
+    /// to request next.
+    ///
+    /// Fails for a location that is not a valid URL reference, a target that is not
+    /// `http(s)`, and an `https` to `http` downgrade if those are refused.
+    pub fn target(&self, from: &Url, location: &str) -> Result<Url, SpiderError> {
+        let target = from.join(location).map_err(|e| {
+            SpiderError::GeneralError(format!(
+                "invalid redirect location {location:?} from {from}: {e}"
+            ))
+        })?;
+        if !matches!(target.scheme(), "http" | "https") {
+            return Err(SpiderError::GeneralError(format!(
+                "refusing redirect from {from} to non-HTTP URL {target}"
+            )));
+        }
+        if self.refuse_downgrade && from.scheme() == "https" && target.scheme() == "http" {
+            return Err(SpiderError::GeneralError(format!(
+                "refusing redirect from {from} to insecure {target}"
+            )));
+        }
+        Ok(target)
+    }
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A `reqwest`-based [`Downloader`] configured through [`HttpDownloaderBuilder`].
+pub struct HttpDownloader {
+    client: Client,
+    pool: Option<Arc<BufferPool>>,
+    limits: BodyLimits,
+    timing_stats: Option<Arc<TimingStats>>,
+    connection_retries: u32,
+    connection_retries_used: AtomicU64,
+    connection_stats: Arc<ConnectionStats>,
+    byte_stats: ByteStats,
+    traffic: SyntheticTraffic,
+    redirects: RedirectPolicy,
+    dns: Option<DnsResolver>,
+    allowed_content_types: Vec<String>,
+    decompress: bool,
+    domain_limit: Option<DomainLimit>,
+    /// The settings the clients of [`proxy_clients`](Self::proxy_clients) are built with.
+    settings: HttpDownloaderBuilder,
+    /// A client for each proxy requests were sent through, see [`PROXY_KEY`].
+    proxy_clients: Mutex<HashMap<String, Client>>,
+}
+
+/// Caps the requests in flight to each domain, see
+/// [`HttpDownloaderBuilder::max_concurrent_per_domain`].
+#[derive(Debug)]
+struct DomainLimit {
+    max: usize,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl DomainLimit {
+    /// Waits until a request to the domain of `url` may be sent.
+    async fn acquire(&self, url: &Url) -> OwnedSemaphorePermit {
+        let domain = registrable_domain(url)
+            .or_else(|| url.host_str().map(str::to_ascii_lowercase))
+            .unwrap_or_default();
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock().expect("domain limits poisoned");
+            if !semaphores.contains_key(&domain) {
+                // Forgets the domains nothing is sent to or waiting for, so a broad crawl
+                // only keeps the domains it is busy with. Permits and waiters hold a clone.
+                semaphores.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+            }
+            semaphores
+                .entry(domain)
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max)))
+                .clone()
+        };
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("domain limit closed")
+    }
+}
+
+/// The encodings requested, and decoded, when decompression is on.
+const ACCEPTED_ENCODINGS: &str = "gzip, deflate, br";
+
+/// Body limits applied to every response, see [`StreamResponse`].
+#[derive(Debug, Clone, Copy, Default)]
+struct BodyLimits {
+    bytes: Option<usize>,
+    time: Option<Duration>,
+    on_limit: LimitAction,
+}
+
+impl HttpDownloader {
+    /// Creates a downloader with the default settings.
+    pub fn new() -> Result<Self, SpiderError> {
+        Self::builder().build()
+    }
+
+    /// Returns a builder for configuring a downloader.
+    pub fn builder() -> HttpDownloaderBuilder {
+        HttpDownloaderBuilder::default()
+    }
+
+    /// Returns the body buffer pool, if pooling is enabled.
+    pub fn buffer_pool(&self) -> Option<&Arc<BufferPool>> {
+        self.pool.as_ref()
+    }
+
+    /// Returns the timing histograms of all downloads so far, if timings are recorded.
+    pub fn timing_stats(&self) -> Option<&Arc<TimingStats>> {
+        self.timing_stats.as_ref()
+    }
+
+    /// Returns how many times a request was retried in place after a transient
+    /// connection failure, see [`HttpDownloaderBuilder::connection_retries`].
+    pub fn connection_retries_used(&self) -> u64 {
+        self.connection_retries_used.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many requests were sent and how many connections were opened for
+    /// them. Clones of the returned handle keep counting.
+    pub fn connection_stats(&self) -> &Arc<ConnectionStats> {
+        &self.connection_stats
+    }
+
+    /// Returns the DNS cache hits and misses so far, if a [`DnsResolver`] is set, see
+    /// [`HttpDownloaderBuilder::dns_resolver`].
+    pub fn dns_stats(&self) -> Option<&Arc<DnsStats>> {
+        self.dns.as_ref().map(DnsResolver::stats)
+    }
+
+    /// Returns the body bytes downloaded so far, counted as they arrive, and the sizes of
+    /// the bodies read to the end or to a limit. Clones of the returned handle keep
+    /// counting.
+    pub fn byte_stats(&self) -> &ByteStats {
+        &self.byte_stats
+    }
+
+    /// Returns how many failed downloads were answered with a placeholder response for
+    /// their errback, which the engine counts as succeeded requests. Clones of the
+    /// returned handle keep counting, see
+    /// [Synthetic traffic](crate::stats#synthetic-traffic).
+    pub fn traffic(&self) -> &SyntheticTraffic {
+        &self.traffic
+    }
+
+    /// Sends `request` and returns the response as soon as its headers arrive, with the
+    /// builder's body limits applied. The body is read from the returned
+    /// [`StreamResponse`].
+    pub async fn stream(&self, request: Request) -> Result<StreamResponse, SpiderError> {
+        let permit = match &self.domain_limit {
+            Some(limit) => Some(limit.acquire(&request.url).await),
+            None => None,
+        };
+        let ((response, chain), timings) = match &self.timing_stats {
+            Some(stats) => {
+                let slot = ConnectSlot::default();
+                let started = Instant::now();
+                let sent = CONNECT_SLOT
+                    .scope(slot.clone(), self.send_following(&request))
+                    .await?;
+                let timings = PendingTimings {
+                    ttfb: started.elapsed(),
+                    slot,
+                    stats: stats.clone(),
+                };
+                (sent, Some(timings))
+            }
+            None => (self.send_following(&request).await?, None),
+        };
+
+        let mut stream = StreamResponse::new(response, request)
+            .on_limit(self.limits.on_limit)
+            .with_byte_stats(self.byte_stats.clone());
+        if self.decompress {
+            stream = stream.with_decompression();
+        }
+        if !chain.is_empty() {
+            let urls: Vec<String> = chain.iter().map(|(_, url)| url.to_string()).collect();
+            let hops: Vec<serde_json::Value> = chain
+                .iter()
+                .map(|(status, url)| json!({ "status": status.as_u16(), "url": url.as_str() }))
+                .collect();
+            stream.meta.insert(REDIRECT_CHAIN_KEY.into(), urls.into());
+            stream.meta.insert(REDIRECT_HOPS_KEY.into(), hops.into());
+        }
+        if let Some(timings) = timings {
+            stream = stream.with_timings(timings);
+        }
+        if let Some(permit) = permit {
+            stream = stream.with_domain_permit(permit);
+        }
+        if let Some(bytes) = self.limits.bytes {
+            stream = stream.with_byte_limit(bytes);
+        }
+        if let Some(time) = self.limits.time {
+            stream = stream.with_time_limit(time);
+        }
+        Ok(stream)
+    }
+
+    /// Sends `request` and follows its redirects under the configured policy. Returns the
+    /// final response and the URLs that redirected, with their statuses.
+    async fn send_following(
+        &self,
+        request: &Request,
+    ) -> Result<(reqwest::Response, Vec<(StatusCode, Url)>), SpiderError> {
+        let mut hop = request.clone();
+        let mut chain = Vec::new();
+        let follow = self.redirects.max_redirects > 0 && request.follows_redirects();
+        loop {
+            let response = self
+                .send(self.request_builder(&hop)?, &hop)
+                .await
+                .map_err(transport_error)?;
+            if !response.status().is_redirection() || !follow {
+                return Ok((response, chain));
+            }
+            let Some(location) = response.headers().get(LOCATION) else {
+                return Ok((response, chain));
+            };
+            if chain.len() == self.redirects.max_redirects {
+                return Err(SpiderError::GeneralError(format!(
+                    "too many redirects for {}, stopped at {}",
+                    request.url, hop.url
+                )));
+            }
+            let location = location.to_str().map_err(|e| {
+                SpiderError::GeneralError(format!(
+                    "invalid redirect location from {}: {e}",
+                    hop.url
+                ))
+            })?;
+            let target = self.redirects.target(&hop.url, location)?;
+            debug!("Following redirect from {} to {}", hop.url, target);
+            redirect_request(&mut hop, response.status(), target);
+            chain.push((response.status(), response.url().clone()));
+        }
+    }
+
+    /// Sends the request built by `builder`, retrying transient connection failures up
+    /// to the configured number of times.
+    async fn send(
+        &self,
+        builder: RequestBuilder,
+        request: &Request,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            // Bodies are in memory, so the builder can always be cloned.
+            let Some(retry) = builder.try_clone() else {
+                self.connection_stats
+                    .requests
+                    .fetch_add(1, Ordering::Relaxed);
+                return builder.send().await;
+            };
+            self.connection_stats
+                .requests
+                .fetch_add(1, Ordering::Relaxed);
+            match retry.send().await {
+                Err(e)
+                    if attempt < self.connection_retries && is_transient(&e, &request.method) =>
+                {
+                    attempt += 1;
+                    self.connection_retries_used.fetch_add(1, Ordering::Relaxed);
+                    debug!(
+                        "Connection to {} failed, retrying in place ({}/{}): {}",
+                        request.url, attempt, self.connection_retries, e
+                    );
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Reads the body of `stream`, unless its content type is not allowed.
+    async fn read_body(&self, stream: StreamResponse) -> Result<Response, SpiderError> {
+        if !content_type_allowed(&self.allowed_content_types, &stream.headers) {
+            debug!(
+                "Not reading the body of {}, its content type is not allowed",
+                stream.url
+            );
+            return Ok(stream.skip_body());
+        }
+        stream.collect(self.pool.as_ref()).await
+    }
+
+    /// Returns the client for `request`: the one for its proxy if it has one under
+    /// [`PROXY_KEY`], built on first use with the downloader's settings.
+    fn client_for(&self, request: &Request) -> Result<Client, SpiderError> {
+        let Some(proxy) = request
+            .meta
+            .get(PROXY_KEY)
+            .and_then(|value| value.as_str().map(str::to_string))
+        else {
+            return Ok(self.client.clone());
+        };
+        let mut clients = self.proxy_clients.lock().expect("proxy clients poisoned");
+        if let Some(client) = clients.get(&proxy) {
+            return Ok(client.clone());
+        }
+        debug!("Building a client for proxy {}", proxy);
+        let client = self.settings.client(&self.connection_stats, Some(&proxy))?;
+        clients.insert(proxy, client.clone());
+        Ok(client)
+    }
+
+    fn request_builder(&self, request: &Request) -> Result<RequestBuilder, SpiderError> {
+        let mut builder = self
+            .client_for(request)?
+            .request(request.method.clone(), request.url.clone())
+            .headers(request.headers.clone());
+        if self.decompress && !request.headers.contains_key(ACCEPT_ENCODING) {
+            builder = builder.header(
+                ACCEPT_ENCODING,
+                HeaderValue::from_static(ACCEPTED_ENCODINGS),
+            );
+        }
+        if let Some(timeout) = request.timeout_override() {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(body) = &request.body {
+            builder = match body {
+                Body::Json(value) => builder.json(value),
+                Body::Form(fields) => {
+                    let fields: Vec<(String, String)> = fields
+                        .iter()
+                        .map(|entry| (entry.key().clone(), entry.value().clone()))
+                        .collect();
+                    builder.form(&fields)
+                }
+                Body::Bytes(bytes) => builder.body(bytes.clone()),
+            };
+        }
+        Ok(builder)
+    }
+}
+
+/// Returns the empty placeholder response for `request`, whose download failed with
+/// `error`, marked for its errback.
+fn failure_response(request: Request, error: &SpiderError) -> Response {
+    debug!(
+        "Download of {} failed, handing it to its errback: {}",
+        request.url, error
+    );
+    request
+        .meta
+        .insert(FAILURE_KEY.into(), format!("error: {}", error).into());
+    if let SpiderError::ReqwestError(details) = error {
+        request.meta.insert(
+            FAILURE_ERROR_KEY.into(),
+            json!({
+                "message": details.message,
+                "is_connect": details.is_connect,
+                "is_timeout": details.is_timeout,
+            }),
+        );
+    }
+    Response {
+        url: request.url.clone(),
+        status: StatusCode::from_u16(FAILED_DOWNLOAD_STATUS).expect("valid status code"),
+        headers: Default::default(),
+        body: Default::default(),
+        request_url: request.url,
+        meta: request.meta,
+        cached: false,
+    }
+}
+
+/// Turns `request` into the request for the redirect `target` it was answered with.
+///
+/// Like browsers, a `303 See Other` and a `POST` answered with `301` or `302` continue
+/// as a `GET` without a body; `307` and `308` keep the method and body. Credentials are
+/// not sent on to another host.
+fn redirect_request(request: &mut Request, status: StatusCode, target: Url) {
+    let to_get = status == StatusCode::SEE_OTHER && request.method != Method::HEAD
+        || matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+            && request.method == Method::POST;
+    if to_get {
+        request.method = Method::GET;
+        request.body = None;
+        request.headers.remove(CONTENT_TYPE);
+        request.headers.remove(CONTENT_LENGTH);
+    }
+    if target.host_str() != request.url.host_str()
+        || target.port_or_known_default() != request.url.port_or_known_default()
+    {
+        request.headers.remove(AUTHORIZATION);
+        request.headers.remove(COOKIE);
+    }
+    request.url = target;
+}
+
+/// Returns whether `error` is a request that ran out of time, see the
+/// [module docs](self).
+pub fn is_timeout(error: &SpiderError) -> bool {
+    matches!(error, SpiderError::ReqwestError(details) if details.is_timeout)
+}
+
+/// Returns whether `error` is a connection the server reset, as classified by
+/// [`transport_error`] from the `io::ErrorKind` in the error's source chain. Errors from
+/// other downloaders are never classified as resets.
+pub fn is_connection_reset(error: &SpiderError) -> bool {
+    matches!(error, SpiderError::ReqwestError(details) if is_reset(details))
+}
+
+/// Returns whether `details` describe an error classified as a connection reset.
+pub(crate) fn is_reset(details: &ReqwestErrorDetails) -> bool {
+    details.message.starts_with(CONNECTION_RESET_PREFIX)
+}
+
+/// Converts a `reqwest` error into a `SpiderError`. A connection reset, recognized by the
+/// `io::ErrorKind` in its source chain, gets a message starting with
+/// [`CONNECTION_RESET_PREFIX`], as the error details have no flag for it.
+pub fn transport_error(error: reqwest::Error) -> SpiderError {
+    let reset = io_error_kind(&error) == Some(std::io::ErrorKind::ConnectionReset);
+    let mut spider_error = SpiderError::from(error);
+    if reset && let SpiderError::ReqwestError(details) = &mut spider_error {
+        details.message = format!("{CONNECTION_RESET_PREFIX}{}", details.message);
+    }
+    spider_error
+}
+
+/// Returns the kind of the first `io::Error` in the source chain of `error`.
+fn io_error_kind(error: &reqwest::Error) -> Option<std::io::ErrorKind> {
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return Some(io.kind());
+        }
+        source = cause.source();
+    }
+    None
+}
+
+/// Returns whether `error` is a connection failure worth retrying in place.
+///
+/// Failures to connect, including TLS handshakes, happen before the request is sent and
+/// are always retried. A connection reset while the request is in flight is retried only
+/// for idempotent methods, since the server may already have acted on the request.
+fn is_transient(error: &reqwest::Error, method: &Method) -> bool {
+    if error.is_connect() {
+        return true;
+    }
+    let idempotent = matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    );
+    idempotent
+        && matches!(
+            io_error_kind(error),
+            Some(
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+            )
+        )
+}
+
+#[async_trait]
+impl Downloader for HttpDownloader {
+    type Client = Client;
+
+    fn client(&self) -> &Self::Client {
+        &self.client
+    }
+
+    async fn download(&self, request: Request) -> Result<Response, SpiderError> {
+        if !request.meta.contains_key(ERRBACK_KEY) {
+            let stream = self.stream(request).await?;
+            return self.read_body(stream).await;
+        }
+        let failed = request.clone();
+        let downloaded = match self.stream(request).await {
+            Ok(stream) => self.read_body(stream).await,
+            Err(error) => Err(error),
+        };
+        Ok(downloaded.unwrap_or_else(|error| {
+            self.traffic.record_failure();
+            failure_response(failed, &error)
+        }))
+    }
+}
+
+/// Builder for [`HttpDownloader`].
+#[derive(Debug, Clone)]
+pub struct HttpDownloaderBuilder {
+    timeout: Duration,
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+    buffer_pool: bool,
+    buffer_pool_cap: usize,
+    limits: BodyLimits,
+    record_timings: bool,
+    connection_retries: u32,
+    redirects: RedirectPolicy,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    dns: Option<DnsResolver>,
+    allowed_content_types: Vec<String>,
+    decompress: bool,
+    max_per_domain: Option<usize>,
+}
+
+impl Default for HttpDownloaderBuilder {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: None,
+            buffer_pool: false,
+            buffer_pool_cap: DEFAULT_POOL_CAP,
+            limits: BodyLimits::default(),
+            record_timings: false,
+            connection_retries: 0,
+            redirects: RedirectPolicy::new(),
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            dns: None,
+            allowed_content_types: Vec::new(),
+            decompress: true,
+            max_per_domain: None,
+        }
+    }
+}
+
+impl HttpDownloaderBuilder {
+    /// Sets the timeout for a whole request, from sending it to reading the last body
+    /// byte. Defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout for opening a connection, TLS handshake included. Defaults to 10
+    /// seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Fails a request when a single read from its connection, such as waiting for the
+    /// response headers or the next body chunk, takes longer than `timeout`. Unlimited
+    /// by default, apart from the request [`timeout`](Self::timeout).
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Reads response bodies into buffers from a [`BufferPool`], each returned once its
+    /// response is dropped. Disabled by default.
+    pub fn body_buffer_pool(mut self, enabled: bool) -> Self {
+        self.buffer_pool = enabled;
+        self
+    }
+
+    /// Sets how many idle buffers the pool keeps. Defaults to 64.
+    pub fn body_buffer_pool_cap(mut self, cap: usize) -> Self {
+        self.buffer_pool_cap = cap;
+        self
+    }
+
+    /// Stops reading a response body after `bytes` bytes. Unlimited by default.
+    pub fn max_body_bytes(mut self, bytes: usize) -> Self {
+        self.limits.bytes = Some(bytes);
+        self
+    }
+
+    /// Stops reading a response body once `limit` has passed since its headers arrived.
+    /// Unlimited by default, apart from the request [`timeout`](Self::timeout).
+    pub fn max_body_time(mut self, limit: Duration) -> Self {
+        self.limits.time = Some(limit);
+        self
+    }
+
+    /// Chooses between a truncated body and an error when a body limit is hit. Defaults
+    /// to [`LimitAction::Truncate`].
+    pub fn on_body_limit(mut self, action: LimitAction) -> Self {
+        self.limits.on_limit = action;
+        self
+    }
+
+    /// Records the DNS, connect, time-to-first-byte and body download time of every
+    /// request, see [`crate::timing`]. Disabled by default.
+    pub fn record_timings(mut self, enabled: bool) -> Self {
+        self.record_timings = enabled;
+        self
+    }
+
+    /// Retries a request up to `retries` times, immediately and without going back
+    /// through the scheduler, when the connection fails to open or is reset. Disabled by
+    /// default.
+    ///
+    /// These retries are meant for brief transport blips and are not counted as engine
+    /// retries; errors and bad statuses that outlast them are left to `RetryMiddleware`.
+    pub fn connection_retries(mut self, retries: u32) -> Self {
+        self.connection_retries = retries;
+        self
+    }
+
+    /// Sets how redirects are followed. Defaults to [`RedirectPolicy::new`].
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirects = policy;
+        self
+    }
+
+    /// Closes pooled connections that were idle for `timeout`. Defaults to `reqwest`'s
+    /// 90 seconds; raise it for crawls that pause between requests to the same host.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many idle connections are kept per host. Unlimited by default; `0`
+    /// disables connection reuse.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Resolves host names with `resolver`, which caches them and can limit concurrent
+    /// lookups, see [`crate::dns`]. Uses the uncached system resolver by default.
+    pub fn dns_resolver(mut self, resolver: DnsResolver) -> Self {
+        self.dns = Some(resolver);
+        self
+    }
+
+    /// Runs at most `max` DNS lookups at once, through a caching [`DnsResolver`], or the
+    /// one set with [`dns_resolver`](Self::dns_resolver). Unlimited by default.
+    pub fn max_concurrent_dns(mut self, max: usize) -> Self {
+        self.dns = Some(self.dns.unwrap_or_default().max_concurrent(max));
+        self
+    }
+
+    /// Reads only the bodies of responses whose media type is in `content_types`, such
+    /// as `text/html`, or matches an entry like `text/*`. Other responses are returned
+    /// with an empty body and [`BODY_SKIPPED_KEY`](crate::stream::BODY_SKIPPED_KEY) set.
+    /// Responses without a `Content-Type` are read. Every body is read by default.
+    pub fn allowed_content_types(mut self, content_types: &[&str]) -> Self {
+        self.allowed_content_types = content_types
+            .iter()
+            .map(|content_type| content_type.trim().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Requests compressed bodies and decodes `gzip`, `deflate` and `br` bodies as they
+    /// are read when `enabled`, see [`crate::stream`]. Enabled by default; disable it to
+    /// get the bodies as the server sends them.
+    pub fn decompress(mut self, enabled: bool) -> Self {
+        self.decompress = enabled;
+        self
+    }
+
+    /// Sends at most `limit` requests at once to any one domain. Further requests to the
+    /// domain wait until one of them has read its body or failed, while requests to
+    /// other domains go on. Unlimited by default.
+    ///
+    /// Domains are compared by [`registrable_domain`], so `www.example.com`,
+    /// `shop.example.com` and `http://example.com:8080` share a limit. Hosts without a
+    /// registrable domain, such as IP addresses, are limited by host, whatever the port.
+    ///
+    /// The limit is taken by the request's own URL, and held while its redirects are
+    /// followed. Per-host delays such as `RateLimitMiddleware` run before the download,
+    /// so both can be used together.
+    ///
+    /// The limit lives in this downloader, so it only applies to crawls that download
+    /// with it, installed with `CrawlerBuilder::downloader(..)`, see the
+    /// [module docs](self). The engine's default `ReqwestClientDownloader` does not
+    /// enforce it.
+    pub fn max_concurrent_per_domain(mut self, limit: usize) -> Self {
+        self.max_per_domain = Some(limit.max(1));
+        self
+    }
+
+    /// Builds the downloader.
+    pub fn build(self) -> Result<HttpDownloader, SpiderError> {
+        let connection_stats = Arc::new(ConnectionStats::default());
+        let client = self.client(&connection_stats, None)?;
+        Ok(HttpDownloader {
+            client,
+            pool: self
+                .buffer_pool
+                .then(|| Arc::new(BufferPool::new(self.buffer_pool_cap))),
+            limits: self.limits,
+            timing_stats: self
+                .record_timings
+                .then(|| Arc::new(TimingStats::default())),
+            connection_retries: self.connection_retries,
+            connection_retries_used: AtomicU64::new(0),
+            connection_stats,
+            byte_stats: ByteStats::new(),
+            traffic: SyntheticTraffic::new(),
+            redirects: self.redirects.clone(),
+            dns: self.dns.clone(),
+            allowed_content_types: self.allowed_content_types.clone(),
+            decompress: self.decompress,
+            domain_limit: self.max_per_domain.map(|max| DomainLimit {
+                max,
+                semaphores: Mutex::new(HashMap::new()),
+            }),
+            settings: self,
+            proxy_clients: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Builds a `reqwest` client with these settings, connecting through `proxy` if
+    /// given.
+    fn client(
+        &self,
+        connection_stats: &Arc<ConnectionStats>,
+        proxy: Option<&str>,
+    ) -> Result<Client, SpiderError> {
+        let mut client = Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .redirect(redirect::Policy::none())
+            .connector_layer(CountingConnectLayer(connection_stats.clone()));
+        if let Some(proxy) = proxy {
+            let proxy = Proxy::all(proxy).map_err(|e| {
+                SpiderError::ConfigurationError(format!("invalid proxy {proxy}: {e}"))
+            })?;
+            client = client.proxy(proxy);
+        }
+        if let Some(timeout) = self.read_timeout {
+            client = client.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            client = client.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            client = client.pool_max_idle_per_host(max);
+        }
+        if self.record_timings {
+            client = client
+                .dns_resolver(TimedResolver(self.dns.clone()))
+                .connector_layer(TimedConnectLayer);
+        } else if let Some(dns) = &self.dns {
+            client = client.dns_resolver(dns.clone());
+        }
+        Ok(client.build()?)
+    }
+}
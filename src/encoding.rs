@@ -0,0 +1,121 @@
+//! Character encoding detection for pages that are not served as UTF-8.
+//!
+//! The encoding of a body is determined the way browsers do it: a byte order mark wins,
+//! then the `charset` of the `Content-Type` header, then a `<meta charset>` declaration
+//! near the top of the document. Undeclared bodies that are valid UTF-8 are UTF-8;
+//! otherwise the encoding is guessed statistically from the bytes with `chardetng`, the
+//! detector Firefox uses.
+//!
+//! ```rust,ignore
+//! // Legacy sites often declare their charset only in the document.
+//! println!("{}", response.encoding().name());
+//! let html = response.html()?;
+//!
+//! // Or force the encoding when the page lies about it, for one call...
+//! let html = response.decoded_html(Some(encoding_rs::SHIFT_JIS))?;
+//! // ...or for every decode of the response to a request.
+//! let request = Request::new(url).force_encoding(encoding_rs::WINDOWS_1251);
+//! ```
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use std::borrow::Cow;
+
+/// Browsers only look for `<meta charset>` in the first kilobyte of a document.
+const META_PRESCAN_LIMIT: usize = 1024;
+
+/// Returns the encoding named by the `charset` parameter of a `Content-Type` value.
+///
+/// Unknown labels yield `None`.
+pub fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    let label = content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches(|c| c == '"' || c == '\''))?;
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Returns the encoding declared by a `<meta charset>` or
+/// `<meta http-equiv="Content-Type">` tag in the first kilobyte of `body`.
+pub fn charset_from_meta(body: &[u8]) -> Option<&'static Encoding> {
+    let head = &body[..body.len().min(META_PRESCAN_LIMIT)];
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+
+    for (start, _) in head.match_indices("<meta") {
+        let tag = &head[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        let Some(position) = tag.find("charset") else {
+            continue;
+        };
+        let value = tag[position + "charset".len()..].trim_start();
+        let Some(value) = value.strip_prefix('=') else {
+            continue;
+        };
+        let label: String = value
+            .trim_start()
+            .trim_start_matches(['"', '\''])
+            .chars()
+            .take_while(|c| !matches!(c, '"' | '\'' | ';' | '/' | '>') && !c.is_whitespace())
+            .collect();
+        if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+            // A document that could be read up to its meta tag is not UTF-16.
+            return Some(if encoding == UTF_16BE || encoding == UTF_16LE {
+                UTF_8
+            } else {
+                encoding
+            });
+        }
+    }
+    None
+}
+
+/// Determines the encoding of a response body.
+///
+/// `content_type` is the value of the `Content-Type` response header, if any. Bodies
+/// without a byte order mark or a usable declaration are treated as UTF-8 when they are
+/// valid UTF-8, and otherwise get the encoding `chardetng` guesses from their bytes.
+pub fn detect_encoding(body: &[u8], content_type: Option<&str>) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return encoding;
+    }
+    content_type
+        .and_then(charset_from_content_type)
+        .or_else(|| charset_from_meta(body))
+        .unwrap_or_else(|| {
+            if std::str::from_utf8(body).is_ok() {
+                UTF_8
+            } else {
+                guess_encoding(body)
+            }
+        })
+}
+
+/// Guesses the encoding of an undeclared legacy body from the frequency of its byte
+/// sequences. Falls back to Windows-1252 for bodies too short to tell.
+fn guess_encoding(body: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(body, true);
+    detector.guess(None, false)
+}
+
+/// Decodes `body` to text, detecting its encoding unless one is given.
+///
+/// A byte order mark always takes precedence, as in browsers. Malformed sequences are
+/// replaced with U+FFFD rather than failing.
+pub fn decode_body<'a>(
+    body: &'a [u8],
+    content_type: Option<&str>,
+    encoding: Option<&'static Encoding>,
+) -> Cow<'a, str> {
+    let encoding = encoding.unwrap_or_else(|| detect_encoding(body, content_type));
+    let (text, used, malformed) = encoding.decode(body);
+    if malformed {
+        log::debug!(
+            "Body contains sequences that are invalid in {}",
+            used.name()
+        );
+    }
+    text
+}
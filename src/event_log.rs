@@ -0,0 +1,314 @@
+//! Structured crawl telemetry as a JSONL event log.
+//!
+//! An [`EventLog`] writes one JSON object per crawl event to a file, for auditing, replay
+//! and building dashboards after the crawl. Unlike the `log` output, every line has the
+//! same machine-readable shape:
+//!
+//! ```json
+//! {"timestamp_ms":1760601600000,"event":"response","url":"https://example.com/",
+//!  "status":200,"latency_ms":84,"bytes":5120}
+//! ```
+//!
+//! ```rust,ignore
+//! let events = EventLog::create("crawl-events.jsonl")?;
+//! let crawler = CrawlerBuilder::new(events.wrap(MySpider))
+//!     .event_log(&events)
+//!     .add_pipeline(JsonlWriterPipeline::new("items.jsonl")?)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The engine does not publish its events, so they are collected where the facade sees
+//! them: [`EventLog::middleware`] records each request as it is handed to the downloader
+//! and each response with its latency, [`EventLog::pipeline`] records each scraped item,
+//! and [`EventLog::wrap`] records the errors returned by the spider's `parse`. Requests
+//! whose download fails are not reported by the engine and have no `response` event.
+//!
+//! Lines are buffered and flushed once [`EventLog::flush_interval`] has passed since the
+//! last flush, and when the crawler closes its pipelines at shutdown.
+
+use crate::response::ResponseExt;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spider_core::{Spider, async_trait};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{
+    error::{PipelineError, SpiderError},
+    item::ScrapedItem,
+    request::Request,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// A crawl event, serialized with its kind under `event`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CrawlEvent {
+    /// A request was handed to the downloader.
+    Request {
+        /// The request URL.
+        url: String,
+        /// The HTTP method.
+        method: String,
+    },
+    /// A response was received.
+    Response {
+        /// The URL of the request the response answers.
+        url: String,
+        /// The HTTP status code.
+        status: u16,
+        /// Milliseconds since the request was handed to the downloader, if it was seen.
+        latency_ms: Option<u64>,
+        /// The body size in bytes.
+        bytes: usize,
+    },
+    /// The spider scraped an item.
+    Item {
+        /// The item as JSON.
+        item: Value,
+    },
+    /// Parsing a response failed.
+    Error {
+        /// The URL of the response.
+        url: String,
+        /// The error message.
+        error: String,
+    },
+}
+
+/// One line of an event log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// Milliseconds since the Unix epoch when the event was recorded.
+    pub timestamp_ms: u64,
+    /// The event.
+    #[serde(flatten)]
+    pub event: CrawlEvent,
+}
+
+struct EventWriter {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    last_flush: Instant,
+}
+
+/// Writes crawl events to a JSONL file. Clones write to the same file.
+#[derive(Clone)]
+pub struct EventLog {
+    writer: Arc<Mutex<EventWriter>>,
+    flush_interval: Duration,
+}
+
+impl EventLog {
+    /// Creates the event log at `path`, truncating an existing file.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, SpiderError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self::from_writer(file))
+    }
+
+    /// Writes events to `writer`.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(EventWriter {
+                writer: BufWriter::new(Box::new(writer)),
+                last_flush: Instant::now(),
+            })),
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Flushes buffered events at most every `interval`. Defaults to one second; a zero
+    /// interval flushes every event.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Appends `event` with the current time. Write errors are logged, not returned, so
+    /// a full disk does not stop the crawl.
+    pub fn record(&self, event: CrawlEvent) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut line = match serde_json::to_vec(&EventRecord {
+            timestamp_ms,
+            event,
+        }) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize crawl event: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().expect("event log writer poisoned");
+        if let Err(e) = writer.writer.write_all(&line) {
+            warn!("Failed to write crawl event: {}", e);
+            return;
+        }
+        if writer.last_flush.elapsed() >= self.flush_interval {
+            writer.last_flush = Instant::now();
+            if let Err(e) = writer.writer.flush() {
+                warn!("Failed to flush event log: {}", e);
+            }
+        }
+    }
+
+    /// Writes all buffered events.
+    pub fn flush(&self) -> Result<(), SpiderError> {
+        let mut writer = self.writer.lock().expect("event log writer poisoned");
+        writer.last_flush = Instant::now();
+        writer.writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the middleware recording requests and responses.
+    pub fn middleware(&self) -> EventLogMiddleware {
+        EventLogMiddleware {
+            log: self.clone(),
+            started: HashMap::new(),
+        }
+    }
+
+    /// Returns the pipeline recording scraped items and flushing the log at shutdown.
+    pub fn pipeline<I>(&self) -> EventLogPipeline<I> {
+        EventLogPipeline {
+            log: self.clone(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Returns the spider with the errors from its `parse` recorded.
+    pub fn wrap<S: Spider>(&self, spider: S) -> EventLogged<S> {
+        EventLogged {
+            spider,
+            log: self.clone(),
+        }
+    }
+}
+
+impl std::fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventLog")
+            .field("flush_interval", &self.flush_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Records requests and responses in an [`EventLog`], see [`EventLog::middleware`].
+#[derive(Debug)]
+pub struct EventLogMiddleware {
+    log: EventLog,
+    started: HashMap<Url, Instant>,
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for EventLogMiddleware {
+    fn name(&self) -> &str {
+        "EventLogMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        self.log.record(CrawlEvent::Request {
+            url: request.url.to_string(),
+            method: request.method.to_string(),
+        });
+        self.started.insert(request.url.clone(), Instant::now());
+        Ok(MiddlewareAction::Continue(request))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        let request_url = response.original_request_url();
+        let latency_ms = self
+            .started
+            .remove(&request_url)
+            .map(|started| started.elapsed().as_millis() as u64);
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        self.log.record(CrawlEvent::Response {
+            url: request_url.to_string(),
+            status: response.status.as_u16(),
+            latency_ms,
+            bytes: response.body.len(),
+        });
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
+
+/// Records scraped items in an [`EventLog`] and passes them on, see
+/// [`EventLog::pipeline`].
+pub struct EventLogPipeline<I> {
+    log: EventLog,
+    _item: PhantomData<fn(I)>,
+}
+
+#[async_trait]
+impl<I: ScrapedItem> Pipeline<I> for EventLogPipeline<I> {
+    fn name(&self) -> &str {
+        "EventLogPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        self.log.record(CrawlEvent::Item {
+            item: item.to_json_value(),
+        });
+        Ok(Some(item))
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        self.log
+            .flush()
+            .map_err(|e| PipelineError::IoError(e.to_string()))
+    }
+}
+
+/// A spider whose `parse` errors are recorded in an [`EventLog`], see
+/// [`EventLog::wrap`].
+pub struct EventLogged<S> {
+    spider: S,
+    log: EventLog,
+}
+
+impl<S> EventLogged<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for EventLogged<S: Spider> {
+        async fn parse(&self, response, state) {
+            let url = response.url.to_string();
+            let output = self.spider.parse(response, state).await;
+            if let Err(e) = &output {
+                self.log.record(CrawlEvent::Error {
+                    url,
+                    error: e.to_string(),
+                });
+            }
+            output
+        }
+    }
+}
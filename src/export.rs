@@ -0,0 +1,255 @@
+//! Writing items to several formats from one pipeline.
+//!
+//! One pipeline per output format works, but each keeps its own buffer and is closed on
+//! its own. [`MultiFormatExporter`] serializes every item once, buffers it once, and
+//! writes the buffer to all of its formats together, so the files always hold the same
+//! items and are flushed together when the crawl closes:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_pipeline(
+//!         MultiFormatExporter::new()
+//!             .json("items.json")
+//!             .csv("items.csv")
+//!             .jsonl("items.jsonl"),
+//!     )
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The files are created, replacing existing ones, when the first items are written.
+//! JSON output is a single array, completed when the pipeline is closed. CSV columns are
+//! the fields of the first item; nested values are written as JSON, and fields missing
+//! from an item are left empty.
+
+use log::debug;
+use serde_json::Value;
+use spider_core::async_trait;
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How many items are buffered before they are written, by default.
+const DEFAULT_BUFFER_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Jsonl,
+    Csv,
+}
+
+struct Output {
+    format: Format,
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    /// Whether a JSON item was written, so the next one needs a separator.
+    items_written: bool,
+    /// The CSV columns, taken from the first item.
+    columns: Vec<String>,
+}
+
+impl Output {
+    fn writer(&mut self) -> Result<&mut BufWriter<File>, PipelineError> {
+        if self.writer.is_none() {
+            let mut writer = BufWriter::new(File::create(&self.path)?);
+            if self.format == Format::Json {
+                writer.write_all(b"[")?;
+            }
+            self.writer = Some(writer);
+        }
+        Ok(self.writer.as_mut().expect("writer was just created"))
+    }
+
+    fn write(&mut self, items: &[Value]) -> Result<(), PipelineError> {
+        if self.format == Format::Csv
+            && self.columns.is_empty()
+            && let Some(Value::Object(first)) = items.first()
+        {
+            self.columns = first.keys().cloned().collect();
+            let header = csv_record(self.columns.iter().map(String::as_str));
+            self.writer()?.write_all(header.as_bytes())?;
+        }
+        for item in items {
+            match self.format {
+                Format::Json => {
+                    let separator: &[u8] = if self.items_written { b",\n" } else { b"\n" };
+                    let writer = self.writer()?;
+                    writer.write_all(separator)?;
+                    serde_json::to_writer(&mut *writer, item)
+                        .map_err(|e| PipelineError::SerializationError(e.to_string()))?;
+                    self.items_written = true;
+                }
+                Format::Jsonl => {
+                    let writer = self.writer()?;
+                    serde_json::to_writer(&mut *writer, item)
+                        .map_err(|e| PipelineError::SerializationError(e.to_string()))?;
+                    writer.write_all(b"\n")?;
+                }
+                Format::Csv => {
+                    let cells: Vec<String> = self
+                        .columns
+                        .iter()
+                        .map(|column| csv_cell(item.get(column)))
+                        .collect();
+                    let record = csv_record(cells.iter().map(String::as_str));
+                    self.writer()?.write_all(record.as_bytes())?;
+                }
+            }
+        }
+        self.writer()?.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), PipelineError> {
+        let (format, items_written) = (self.format, self.items_written);
+        let writer = self.writer()?;
+        if format == Format::Json {
+            writer.write_all(if items_written { b"\n]\n" } else { b"]\n" })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn csv_record<'a>(cells: impl Iterator<Item = &'a str>) -> String {
+    let mut record = cells
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    record.push('\n');
+    record
+}
+
+struct ExportState {
+    outputs: Vec<Output>,
+    buffer: Vec<Value>,
+    closed: bool,
+}
+
+impl ExportState {
+    fn flush(&mut self) -> Result<(), PipelineError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let items = std::mem::take(&mut self.buffer);
+        for output in &mut self.outputs {
+            output.write(&items)?;
+        }
+        Ok(())
+    }
+}
+
+/// A pipeline writing every item to several files and formats, see the
+/// [module docs](self).
+pub struct MultiFormatExporter<I> {
+    state: Mutex<ExportState>,
+    buffer_size: usize,
+    _item: PhantomData<fn(I)>,
+}
+
+impl<I> MultiFormatExporter<I> {
+    /// Creates an exporter without outputs.
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(ExportState {
+                outputs: Vec::new(),
+                buffer: Vec::new(),
+                closed: false,
+            }),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            _item: PhantomData,
+        }
+    }
+
+    /// Writes the items as a JSON array to `path`.
+    pub fn json(self, path: impl AsRef<Path>) -> Self {
+        self.output(Format::Json, path)
+    }
+
+    /// Writes the items as JSON lines to `path`.
+    pub fn jsonl(self, path: impl AsRef<Path>) -> Self {
+        self.output(Format::Jsonl, path)
+    }
+
+    /// Writes the items as CSV to `path`.
+    pub fn csv(self, path: impl AsRef<Path>) -> Self {
+        self.output(Format::Csv, path)
+    }
+
+    /// Writes the buffered items to every output once `size` items are buffered.
+    /// Defaults to 100.
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size.max(1);
+        self
+    }
+
+    fn output(self, format: Format, path: impl AsRef<Path>) -> Self {
+        self.state
+            .lock()
+            .expect("exporter poisoned")
+            .outputs
+            .push(Output {
+                format,
+                path: path.as_ref().to_path_buf(),
+                writer: None,
+                items_written: false,
+                columns: Vec::new(),
+            });
+        self
+    }
+}
+
+impl<I> Default for MultiFormatExporter<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<I: ScrapedItem> Pipeline<I> for MultiFormatExporter<I> {
+    fn name(&self) -> &str {
+        "MultiFormatExporter"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        let mut state = self.state.lock().expect("exporter poisoned");
+        state.buffer.push(item.to_json_value());
+        if state.buffer.len() >= self.buffer_size {
+            state.flush()?;
+        }
+        Ok(Some(item))
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        let mut state = self.state.lock().expect("exporter poisoned");
+        if state.closed {
+            return Ok(());
+        }
+        state.closed = true;
+        state.flush()?;
+        for output in &mut state.outputs {
+            output.finish()?;
+            debug!("Exported items to {}", output.path.display());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+//! Declarative extraction of items from HTML.
+//!
+//! [`ItemSelectors`] maps item fields to [`FieldRule`]s (a CSS selector plus what to read
+//! from the matched element). The extracted values are assembled into a JSON object and
+//! deserialized into the item type, so any `#[scraped_item]` struct can be filled without
+//! writing selection code by hand:
+//!
+//! ```rust,ignore
+//! let selectors = ItemSelectors::new()
+//!     .field("text", FieldRule::text(".text").trim().required())
+//!     .field("author", FieldRule::text(".author"));
+//!
+//! for quote in response.extract_items::<QuoteItem>(".quote", &selectors)? {
+//!     output.add_item(quote);
+//! }
+//! ```
+//!
+//! The item type must implement `serde::de::DeserializeOwned`. For types that do not,
+//! [`extract_records`] returns the extracted [`Fields`] to build the items from:
+//!
+//! ```rust,ignore
+//! for quote in response.extract_records(".quote", &selectors)? {
+//!     output.add_item(QuoteItem {
+//!         text: quote.text("text"),
+//!         author: quote.text("author"),
+//!     });
+//! }
+//! ```
+
+use scraper::{ElementRef, Html, Selector};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use spider_util::{error::SpiderError, utils::ToSelector};
+
+/// What a [`FieldRule`] reads from the elements it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldSource {
+    /// The concatenated text of the element and its descendants.
+    Text,
+    /// The value of the named attribute.
+    Attr(String),
+    /// The inner HTML of the element.
+    InnerHtml,
+}
+
+/// How to extract a single item field.
+#[derive(Debug, Clone)]
+pub struct FieldRule {
+    selector: String,
+    source: FieldSource,
+    trim: bool,
+    required: bool,
+    all: bool,
+    default: Option<String>,
+    transform: Option<fn(String) -> String>,
+}
+
+impl FieldRule {
+    fn new(selector: &str, source: FieldSource) -> Self {
+        Self {
+            selector: selector.to_string(),
+            source,
+            trim: false,
+            required: false,
+            all: false,
+            default: None,
+            transform: None,
+        }
+    }
+
+    /// Reads the text of the first element matching `selector`.
+    ///
+    /// An empty selector refers to the element the item is extracted from.
+    pub fn text(selector: &str) -> Self {
+        Self::new(selector, FieldSource::Text)
+    }
+
+    /// Reads attribute `attr` of the first element matching `selector`.
+    pub fn attr(selector: &str, attr: &str) -> Self {
+        Self::new(selector, FieldSource::Attr(attr.to_string()))
+    }
+
+    /// Reads the inner HTML of the first element matching `selector`.
+    pub fn inner_html(selector: &str) -> Self {
+        Self::new(selector, FieldSource::InnerHtml)
+    }
+
+    /// Marks the field as required: items for which it matches nothing, or only empty
+    /// values, are skipped.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Uses `value` when nothing matches, instead of an empty string.
+    pub fn default_value(mut self, value: &str) -> Self {
+        self.default = Some(value.to_string());
+        self
+    }
+
+    /// Collects the values of all matching elements into an array.
+    pub fn all(mut self) -> Self {
+        self.all = true;
+        self
+    }
+
+    /// Trims surrounding whitespace from every extracted value. Values are kept as they
+    /// are by default.
+    pub fn trim(mut self) -> Self {
+        self.trim = true;
+        self
+    }
+
+    /// Applies `transform` to every extracted value, after trimming.
+    pub fn map(mut self, transform: fn(String) -> String) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    fn values(&self, element: ElementRef<'_>, selector: Option<&Selector>) -> Vec<String> {
+        let matches: Box<dyn Iterator<Item = ElementRef<'_>>> = match selector {
+            Some(selector) => Box::new(element.select(selector)),
+            None => Box::new(std::iter::once(element)),
+        };
+
+        let mut values = Vec::new();
+        for matched in matches {
+            let value = match &self.source {
+                FieldSource::Text => Some(matched.text().collect::<String>()),
+                FieldSource::Attr(attr) => matched.value().attr(attr).map(str::to_string),
+                FieldSource::InnerHtml => Some(matched.inner_html()),
+            };
+            let Some(mut value) = value else {
+                continue;
+            };
+            if self.trim {
+                value = value.trim().to_string();
+            }
+            if let Some(transform) = self.transform {
+                value = transform(value);
+            }
+            values.push(value);
+            if !self.all {
+                break;
+            }
+        }
+        values
+    }
+}
+
+/// A set of field rules describing how to build one item type.
+#[derive(Debug, Clone, Default)]
+pub struct ItemSelectors {
+    fields: Vec<(String, FieldRule)>,
+}
+
+impl ItemSelectors {
+    /// Creates an empty set of rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the rule for the item field `name`.
+    pub fn field(mut self, name: &str, rule: FieldRule) -> Self {
+        self.fields.push((name.to_string(), rule));
+        self
+    }
+
+    fn compile(&self) -> Result<Vec<Option<Selector>>, SpiderError> {
+        self.fields
+            .iter()
+            .map(|(_, rule)| {
+                if rule.selector.trim().is_empty() {
+                    Ok(None)
+                } else {
+                    rule.selector.to_selector().map(Some)
+                }
+            })
+            .collect()
+    }
+
+    fn fields(&self, element: ElementRef<'_>, selectors: &[Option<Selector>]) -> Option<Fields> {
+        let mut object = Map::new();
+        for ((name, rule), selector) in self.fields.iter().zip(selectors) {
+            let mut values = rule.values(element, selector.as_ref());
+            if rule.required && values.iter().all(String::is_empty) {
+                log::debug!("Skipping item: required field `{}` has no value", name);
+                return None;
+            }
+            if values.is_empty() {
+                values.extend(rule.default.clone());
+            }
+
+            let value = if rule.all {
+                Value::Array(values.into_iter().map(Value::String).collect())
+            } else {
+                Value::String(values.into_iter().next().unwrap_or_default())
+            };
+            object.insert(name.clone(), value);
+        }
+        Some(Fields(object))
+    }
+
+    fn build<T: DeserializeOwned>(
+        &self,
+        element: ElementRef<'_>,
+        selectors: &[Option<Selector>],
+    ) -> Option<T> {
+        match self.fields(element, selectors)?.into_item() {
+            Ok(item) => Some(item),
+            Err(err) => {
+                log::warn!("Skipping item: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// The field values extracted for one item, see [`extract_records`].
+///
+/// Single-value fields hold strings and [`FieldRule::all`] fields hold arrays of strings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fields(Map<String, Value>);
+
+impl Fields {
+    /// Returns the value of field `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    /// Returns the value of the single-value field `name`, or an empty string if there
+    /// is no such field.
+    pub fn text(&self, name: &str) -> String {
+        self.get(name)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Returns the values of the [`FieldRule::all`] field `name`.
+    pub fn all(&self, name: &str) -> Vec<String> {
+        self.get(name)
+            .and_then(Value::as_array)
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Deserializes the fields into `T`, as [`extract_items`] does.
+    pub fn into_item<T: DeserializeOwned>(self) -> Result<T, SpiderError> {
+        serde_json::from_value(Value::Object(self.0)).map_err(|e| {
+            SpiderError::JsonError(format!("extracted fields do not match the item: {}", e))
+        })
+    }
+
+    /// Returns the fields as a JSON object.
+    pub fn into_map(self) -> Map<String, Value> {
+        self.0
+    }
+}
+
+/// Builds one item from `element` using `selectors`.
+///
+/// Returns `Ok(None)` when a required field has no value or the extracted fields cannot
+/// be deserialized into `T`; invalid selectors are reported as errors.
+pub fn extract_item<T: DeserializeOwned>(
+    element: ElementRef<'_>,
+    selectors: &ItemSelectors,
+) -> Result<Option<T>, SpiderError> {
+    let compiled = selectors.compile()?;
+    Ok(selectors.build(element, &compiled))
+}
+
+/// Builds one item per element matching `root`, skipping elements that do not yield a
+/// complete item.
+pub fn extract_items<T: DeserializeOwned>(
+    html: &Html,
+    root: &str,
+    selectors: &ItemSelectors,
+) -> Result<Vec<T>, SpiderError> {
+    let root = root.to_selector()?;
+    let compiled = selectors.compile()?;
+    Ok(html
+        .select(&root)
+        .filter_map(|element| selectors.build(element, &compiled))
+        .collect())
+}
+
+/// Extracts the fields of one item per element matching `root`, skipping elements for
+/// which a required field has no value.
+///
+/// This is [`extract_items`] without the deserialization step, for item types that do
+/// not implement `DeserializeOwned`.
+pub fn extract_records(
+    html: &Html,
+    root: &str,
+    selectors: &ItemSelectors,
+) -> Result<Vec<Fields>, SpiderError> {
+    let root = root.to_selector()?;
+    let compiled = selectors.compile()?;
+    Ok(html
+        .select(&root)
+        .filter_map(|element| selectors.fields(element, &compiled))
+        .collect())
+}
+
+/// Returns `true` when no element matching `selector` has any text.
+///
+/// Pages whose content is loaded by JavaScript often ship a shell that parses fine but
+/// contains none of the expected elements; this tells such pages apart from pages that
+/// genuinely have no results, so the caller can retry them elsewhere instead of silently
+/// scraping nothing.
+pub fn looks_empty(html: &Html, selector: &str) -> Result<bool, SpiderError> {
+    let selector = selector.to_selector()?;
+    Ok(!html
+        .select(&selector)
+        .any(|element| element.text().any(|text| !text.trim().is_empty())))
+}
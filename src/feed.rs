@@ -0,0 +1,301 @@
+//! RSS and Atom feed parsing.
+//!
+//! [`parse_feed`] reads RSS 2.0, RSS 1.0 (RDF) and Atom documents into a common
+//! [`Feed`], so a spider can follow the articles a site publishes:
+//!
+//! ```rust,ignore
+//! let feed = response.feed()?;
+//! for request in feed.requests() {
+//!     output.add_request(request);
+//! }
+//! ```
+//!
+//! Besides the XML entities, text may use the HTML entities feeds often contain, such as
+//! `&nbsp;` and `&mdash;`. Text with an unknown entity is kept as written.
+
+use quick_xml::Reader;
+use quick_xml::escape::resolve_html5_entity;
+use quick_xml::events::{BytesStart, Event};
+use spider_util::request::Request;
+use std::collections::HashSet;
+use std::fmt;
+use url::Url;
+
+/// An article or post listed in a feed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedEntry {
+    /// The entry title.
+    pub title: Option<String>,
+    /// The absolute URL of the entry, resolved against the feed URL.
+    pub link: Option<Url>,
+    /// The raw publication date: `pubDate` in RSS (RFC 822), `published` or `updated`
+    /// in Atom (RFC 3339).
+    pub published: Option<String>,
+    /// The entry summary, falling back to its full content. May contain HTML.
+    pub summary: Option<String>,
+}
+
+/// The contents of an RSS or Atom feed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Feed {
+    /// The title of the feed itself.
+    pub title: Option<String>,
+    /// The entries of the feed, in document order.
+    pub entries: Vec<FeedEntry>,
+}
+
+impl Feed {
+    /// Builds a request for the link of every entry, skipping entries without one and
+    /// duplicate links.
+    pub fn requests(&self) -> Vec<Request> {
+        let mut seen = HashSet::new();
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.link.clone())
+            .filter(|link| seen.insert(link.clone()))
+            .map(Request::new)
+            .collect()
+    }
+}
+
+/// Errors that can occur while parsing a feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedError {
+    /// The body is not well-formed XML.
+    Xml(String),
+    /// The document is neither an RSS nor an Atom feed.
+    NotFeed,
+}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedError::Xml(reason) => write!(f, "malformed feed XML: {}", reason),
+            FeedError::NotFeed => write!(f, "document is not an RSS or Atom feed"),
+        }
+    }
+}
+
+impl std::error::Error for FeedError {}
+
+/// The element whose text is being collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    FeedTitle,
+    Title,
+    Link,
+    Published,
+    Updated,
+    Summary,
+    Content,
+}
+
+#[derive(Default)]
+struct PartialEntry {
+    title: Option<String>,
+    link: Option<String>,
+    published: Option<String>,
+    updated: Option<String>,
+    summary: Option<String>,
+    content: Option<String>,
+}
+
+impl PartialEntry {
+    fn set(&mut self, field: Field, value: String) {
+        let target = match field {
+            Field::FeedTitle => return,
+            Field::Title => &mut self.title,
+            Field::Link => &mut self.link,
+            Field::Published => &mut self.published,
+            Field::Updated => &mut self.updated,
+            Field::Summary => &mut self.summary,
+            Field::Content => &mut self.content,
+        };
+        if target.is_none() && !value.is_empty() {
+            *target = Some(value);
+        }
+    }
+
+    fn finish(self, base: &Url) -> FeedEntry {
+        let link = self.link.and_then(|link| match base.join(&link) {
+            Ok(url) => Some(url),
+            Err(err) => {
+                log::debug!("Ignoring feed entry link {:?}: {}", link, err);
+                None
+            }
+        });
+        FeedEntry {
+            title: self.title,
+            link,
+            published: self.published.or(self.updated),
+            summary: self.summary.or(self.content),
+        }
+    }
+}
+
+/// Parses an RSS or Atom feed.
+///
+/// Relative entry links, common in Atom feeds, are resolved against `base`, the URL the
+/// feed was fetched from. Entry fields that are missing from the document are `None`.
+pub fn parse_feed(bytes: &[u8], base: &Url) -> Result<Feed, FeedError> {
+    let mut reader = Reader::from_reader(bytes);
+    reader.config_mut().trim_text(true);
+
+    let mut entry_name: Option<&'static [u8]> = None;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut feed = Feed::default();
+    let mut entry: Option<(usize, PartialEntry)> = None;
+    let mut capture: Option<(usize, Field, String)> = None;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|err| FeedError::Xml(err.to_string()))?;
+        match event {
+            Event::Start(start) => {
+                let name = start.local_name().as_ref().to_vec();
+                if entry_name.is_none() {
+                    entry_name = Some(root_entry_name(&name)?);
+                }
+                path.push(name);
+                let depth = path.len();
+
+                if capture.is_some() {
+                    continue;
+                }
+                match &mut entry {
+                    Some((entry_depth, partial)) if depth == *entry_depth + 1 => {
+                        if let Some(href) = atom_link(&start) {
+                            partial.set(Field::Link, href);
+                        } else if let Some(field) = entry_field(&path[depth - 1]) {
+                            capture = Some((depth, field, String::new()));
+                        }
+                    }
+                    Some(_) => {}
+                    None if entry_name == Some(path[depth - 1].as_slice()) => {
+                        entry = Some((depth, PartialEntry::default()));
+                    }
+                    None => {
+                        let parent = depth.checked_sub(2).map(|index| path[index].as_slice());
+                        if path[depth - 1] == b"title"
+                            && matches!(parent, Some(b"channel" | b"feed"))
+                        {
+                            capture = Some((depth, Field::FeedTitle, String::new()));
+                        }
+                    }
+                }
+            }
+            Event::Empty(empty) => {
+                if entry_name.is_none() {
+                    root_entry_name(empty.local_name().as_ref())?;
+                    return Ok(feed);
+                }
+                if let Some((entry_depth, partial)) = &mut entry
+                    && path.len() == *entry_depth
+                    && capture.is_none()
+                    && let Some(href) = atom_link(&empty)
+                {
+                    partial.set(Field::Link, href);
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, _, buffer)) = &mut capture {
+                    // Feeds often carry HTML entities such as `&nbsp;` that XML does not
+                    // define. Resolve them, and keep the raw text if one is unknown.
+                    match text.unescape_with(resolve_html5_entity) {
+                        Ok(value) => push_text(buffer, &value),
+                        Err(_) => push_text(buffer, &String::from_utf8_lossy(&text)),
+                    }
+                }
+            }
+            Event::CData(data) => {
+                if let Some((_, _, buffer)) = &mut capture {
+                    push_text(buffer, &String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(_) => {
+                let depth = path.len();
+                if let Some((capture_depth, field, buffer)) = capture.take() {
+                    if capture_depth != depth {
+                        capture = Some((capture_depth, field, buffer));
+                    } else if field == Field::FeedTitle {
+                        feed.title = Some(buffer).filter(|title| !title.is_empty());
+                    } else if let Some((_, partial)) = &mut entry {
+                        partial.set(field, buffer);
+                    }
+                }
+                if let Some((entry_depth, _)) = &entry
+                    && *entry_depth == depth
+                    && let Some((_, partial)) = entry.take()
+                {
+                    feed.entries.push(partial.finish(base));
+                }
+                path.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if entry_name.is_none() {
+        return Err(FeedError::NotFeed);
+    }
+    Ok(feed)
+}
+
+/// Returns the name of the entry elements of a feed with the given root element.
+fn root_entry_name(root: &[u8]) -> Result<&'static [u8], FeedError> {
+    match root {
+        b"rss" | b"RDF" => Ok(b"item"),
+        b"feed" => Ok(b"entry"),
+        _ => Err(FeedError::NotFeed),
+    }
+}
+
+fn entry_field(name: &[u8]) -> Option<Field> {
+    match name {
+        b"title" => Some(Field::Title),
+        b"link" => Some(Field::Link),
+        b"pubDate" | b"published" | b"date" => Some(Field::Published),
+        b"updated" => Some(Field::Updated),
+        b"description" | b"summary" => Some(Field::Summary),
+        b"content" | b"encoded" => Some(Field::Content),
+        _ => None,
+    }
+}
+
+/// Returns the `href` of an Atom `<link>` pointing at the entry itself.
+fn atom_link(element: &BytesStart<'_>) -> Option<String> {
+    if element.local_name().as_ref() != b"link" {
+        return None;
+    }
+    let attr = |name: &[u8]| {
+        element
+            .try_get_attribute(name)
+            .ok()
+            .flatten()
+            .map(
+                |attr| match attr.unescape_value_with(resolve_html5_entity) {
+                    Ok(value) => value.into_owned(),
+                    Err(_) => String::from_utf8_lossy(&attr.value).into_owned(),
+                },
+            )
+            .map(|value| value.trim().to_string())
+    };
+    let href = attr(b"href")?;
+    match attr(b"rel").as_deref() {
+        None | Some("alternate") => Some(href),
+        Some(_) => None,
+    }
+}
+
+fn push_text(buffer: &mut String, text: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    if !buffer.is_empty() {
+        buffer.push(' ');
+    }
+    buffer.push_str(text);
+}
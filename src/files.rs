@@ -0,0 +1,314 @@
+//! Downloading the files items refer to.
+//!
+//! Items often carry the URLs of images, PDFs or other attachments that should be stored
+//! next to the scraped data. [`FilesPipeline`] downloads the URLs in configured item
+//! fields through a [`Downloader`], stores each file under a path derived from its URL,
+//! and writes what it stored back into the item, like Scrapy's `FilesPipeline`:
+//!
+//! ```rust,ignore
+//! #[scraped_item]
+//! pub struct Product {
+//!     pub name: String,
+//!     pub image_urls: Vec<String>,
+//!     #[serde(default)]
+//!     pub images: Vec<StoredFile>,
+//! }
+//!
+//! let files = FilesPipeline::new(HttpDownloader::new()?, "downloads")
+//!     .field("image_urls", "images")
+//!     .add_middleware(UserAgentMiddleware::new(...))
+//!     .concurrency(8);
+//! let crawler = CrawlerBuilder::new(ProductSpider)
+//!     .add_pipeline(files)
+//!     .add_pipeline(JsonlWriterPipeline::new("products.jsonl")?)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! A URL field holds a string or an array of strings, resolved as absolute URLs. Each
+//! file is stored as `full/<hash>.<extension>` under the directory, where the hash is the
+//! fingerprint of a `GET` request for the URL and the extension is taken from the URL
+//! path. Since the path is known before downloading, a file already on disk is not
+//! downloaded again, and a URL referenced by several items is downloaded once. The result
+//! field receives a [`StoredFile`] for every file that was stored, in the order of the
+//! URLs; URLs that are invalid or fail to download are logged and left out, and the item
+//! is passed on either way. The item type must be deserializable, which
+//! `#[scraped_item]` provides.
+//!
+//! Pipelines do not go through the crawl's middlewares, so the downloads only pass the
+//! middlewares added with [`FilesPipeline::add_middleware`]: request middlewares in the
+//! order they were added, response middlewares in reverse, as in the engine. Responses
+//! other than `2xx` count as failures.
+
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spider_core::{Downloader, async_trait, tokio};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem, request::Request, response::Response};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OnceCell, Semaphore};
+use url::Url;
+
+/// Files downloaded at once by default.
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Longest extension kept from a URL path.
+const MAX_EXTENSION_LEN: usize = 8;
+
+/// A file stored by a [`FilesPipeline`], written to the item's result field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredFile {
+    /// The URL the file was downloaded from.
+    pub url: String,
+    /// Where the file is stored, relative to the pipeline's directory.
+    pub path: String,
+}
+
+/// What the request middlewares left to do.
+enum Fetch {
+    Download(Request),
+    Answered(Response),
+}
+
+/// Downloads the files items refer to, see the [module docs](self).
+pub struct FilesPipeline<I, D: Downloader> {
+    downloader: D,
+    dir: PathBuf,
+    fields: Vec<(String, String)>,
+    middlewares: tokio::sync::Mutex<Vec<Box<dyn Middleware<D::Client>>>>,
+    permits: Semaphore,
+    /// Files stored or being stored, by URL, so every URL is downloaded once.
+    files: Mutex<HashMap<String, Arc<OnceCell<Option<String>>>>>,
+    _item: PhantomData<fn(I)>,
+}
+
+impl<I, D: Downloader> FilesPipeline<I, D> {
+    /// Creates a pipeline storing files under `dir` with `downloader`, without URL fields.
+    pub fn new(downloader: D, dir: impl AsRef<Path>) -> Self {
+        Self {
+            downloader,
+            dir: dir.as_ref().to_path_buf(),
+            fields: Vec::new(),
+            middlewares: tokio::sync::Mutex::new(Vec::new()),
+            permits: Semaphore::new(DEFAULT_CONCURRENCY),
+            files: Mutex::new(HashMap::new()),
+            _item: PhantomData,
+        }
+    }
+
+    /// Downloads the URLs in item field `urls` and writes the stored files to field
+    /// `results`.
+    pub fn field(mut self, urls: &str, results: &str) -> Self {
+        self.fields.push((urls.to_string(), results.to_string()));
+        self
+    }
+
+    /// Runs the downloads through `middleware`, like `CrawlerBuilder::add_middleware`.
+    pub fn add_middleware(self, middleware: impl Middleware<D::Client>) -> Self {
+        self.middlewares
+            .try_lock()
+            .expect("middlewares are not in use before the crawl")
+            .push(Box::new(middleware));
+        self
+    }
+
+    /// Sets how many files may be downloaded at once. Defaults to 4.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.permits = Semaphore::new(limit.max(1));
+        self
+    }
+
+    /// Returns the directory files are stored under.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Stores the file at `url` unless it is already stored, and returns its relative
+    /// path, or `None` if it could not be stored.
+    async fn store(&self, url: Url) -> Option<String> {
+        let cell = self
+            .files
+            .lock()
+            .expect("files pipeline poisoned")
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+        cell.get_or_init(|| async {
+            let path = stored_path(&url);
+            let full_path = self.dir.join(&path);
+            if tokio::fs::try_exists(&full_path).await.unwrap_or(false) {
+                debug!("{} is already stored at {}", url, full_path.display());
+                return Some(path);
+            }
+            match self.download(url.clone(), &full_path).await {
+                Ok(()) => Some(path),
+                Err(err) => {
+                    warn!("Cannot store {}: {}", url, err);
+                    None
+                }
+            }
+        })
+        .await
+        .clone()
+    }
+
+    async fn download(&self, url: Url, full_path: &Path) -> Result<(), PipelineError> {
+        let _permit = self.permits.acquire().await.map_err(other_error)?;
+        let Some(request) = self.process_request(Request::new(url)).await? else {
+            return Err(PipelineError::Other("dropped by a middleware".into()));
+        };
+        let response = match request {
+            Fetch::Download(request) => self
+                .downloader
+                .download(request)
+                .await
+                .map_err(other_error)?,
+            Fetch::Answered(response) => response,
+        };
+        let Some(response) = self.process_response(response).await? else {
+            return Err(PipelineError::Other("dropped by a middleware".into()));
+        };
+        if !response.status.is_success() {
+            return Err(PipelineError::Other(format!("status {}", response.status)));
+        }
+
+        if let Some(parent) = full_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Write under a temporary name so a partial file is never taken as stored.
+        let partial = full_path.with_extension("part");
+        tokio::fs::write(&partial, &response.body).await?;
+        tokio::fs::rename(&partial, full_path).await?;
+        debug!("Stored {} at {}", response.url, full_path.display());
+        Ok(())
+    }
+
+    /// Runs the request middlewares. Returns the request to download, or a response a
+    /// middleware answered with, or `None` if a middleware dropped the request.
+    async fn process_request(&self, request: Request) -> Result<Option<Fetch>, PipelineError> {
+        let mut middlewares = self.middlewares.lock().await;
+        let client = self.downloader.client();
+        let mut request = request;
+        for middleware in middlewares.iter_mut() {
+            match middleware
+                .process_request(client, request)
+                .await
+                .map_err(other_error)?
+            {
+                MiddlewareAction::Continue(next) => request = next,
+                MiddlewareAction::ReturnResponse(response) => {
+                    return Ok(Some(Fetch::Answered(response)));
+                }
+                MiddlewareAction::Retry(..) | MiddlewareAction::Drop => return Ok(None),
+            }
+        }
+        Ok(Some(Fetch::Download(request)))
+    }
+
+    /// Runs the response middlewares, returning `None` if one of them did not pass the
+    /// response on.
+    async fn process_response(
+        &self,
+        response: Response,
+    ) -> Result<Option<Response>, PipelineError> {
+        let mut middlewares = self.middlewares.lock().await;
+        let mut response = response;
+        for middleware in middlewares.iter_mut().rev() {
+            match middleware
+                .process_response(response)
+                .await
+                .map_err(other_error)?
+            {
+                MiddlewareAction::Continue(next) => response = next,
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(response))
+    }
+}
+
+/// Returns the path a file from `url` is stored at, relative to the pipeline directory.
+fn stored_path(url: &Url) -> String {
+    let hash = Request::new(url.clone()).fingerprint();
+    let extension = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .filter(|extension| {
+            !extension.is_empty()
+                && extension.len() <= MAX_EXTENSION_LEN
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    match extension {
+        Some(extension) => format!("full/{}.{}", hash, extension),
+        None => format!("full/{}", hash),
+    }
+}
+
+/// Returns the URLs in a field holding a string or an array of strings.
+fn field_urls(value: &Value) -> Vec<&str> {
+    match value {
+        Value::String(url) => vec![url.as_str()],
+        Value::Array(urls) => urls.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn other_error(err: impl std::fmt::Display) -> PipelineError {
+    PipelineError::Other(err.to_string())
+}
+
+#[async_trait]
+impl<I, D> Pipeline<I> for FilesPipeline<I, D>
+where
+    I: ScrapedItem + DeserializeOwned,
+    D: Downloader,
+{
+    fn name(&self) -> &str {
+        "FilesPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        if self.fields.is_empty() {
+            return Ok(Some(item));
+        }
+        let mut value = item.to_json_value();
+        for (urls_field, results_field) in &self.fields {
+            let urls: Vec<String> = value
+                .get(urls_field)
+                .map(field_urls)
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            let mut stored = Vec::new();
+            for href in urls {
+                let url = match Url::parse(href.trim()) {
+                    Ok(url) => url,
+                    Err(err) => {
+                        warn!("Skipping invalid file URL {:?}: {}", href, err);
+                        continue;
+                    }
+                };
+                if let Some(path) = self.store(url).await {
+                    stored.push(StoredFile { url: href, path });
+                }
+            }
+            let stored = serde_json::to_value(stored)
+                .map_err(|e| PipelineError::SerializationError(e.to_string()))?;
+            if let Some(fields) = value.as_object_mut() {
+                fields.insert(results_field.clone(), stored);
+            }
+        }
+        let item = serde_json::from_value(value)
+            .map_err(|e| PipelineError::SerializationError(e.to_string()))?;
+        Ok(Some(item))
+    }
+}
@@ -0,0 +1,140 @@
+//! Item-level post-processing before items reach the pipelines.
+//!
+//! Some changes to an item cannot be expressed per field: computing a derived field from
+//! others, formatting a date, normalizing units. Implementing [`FinalizeItem`] puts them
+//! in one place, and [`FinalizeExt::finalize_items`] applies them to every item the
+//! spider scrapes, once, before any pipeline sees it, so all output formats get the same
+//! items:
+//!
+//! ```rust,ignore
+//! impl FinalizeItem for Product {
+//!     fn finalize(mut self) -> Self {
+//!         self.total = self.price * self.quantity as f64;
+//!         self
+//!     }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(ProductsSpider.finalize_items())
+//!     .add_pipeline(JsonlPipeline::new("products.jsonl")?)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Cleaning that depends on the spider, its configuration or its state belongs to the
+//! spider instead. A [`ProcessItemSpider`] implements
+//! [`process_item`](ProcessItemSpider::process_item), which sees every item `parse`
+//! adds and may drop it, and [`ProcessItemSpider::processed`] applies it before the
+//! pipelines:
+//!
+//! ```rust,ignore
+//! impl ProcessItemSpider for BooksSpider {
+//!     fn process_item(&self, mut book: Book, _state: &Self::State) -> Option<Book> {
+//!         book.title = book.title.trim().to_string();
+//!         book.url = self.base_url.join(&book.url).ok()?.to_string();
+//!         (!book.title.is_empty()).then_some(book)
+//!     }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(BooksSpider::new().processed()).build().await?;
+//! ```
+
+use spider_core::Spider;
+use spider_util::item::{ParseOutput, ScrapedItem};
+
+/// An item with a final transform applied before it is exported.
+pub trait FinalizeItem: ScrapedItem + Sized {
+    /// Returns the item as it should be exported. The default leaves it unchanged.
+    fn finalize(self) -> Self {
+        self
+    }
+}
+
+/// A spider whose items are finalized before they reach the pipelines, see
+/// [`FinalizeExt::finalize_items`].
+pub struct Finalized<S> {
+    spider: S,
+}
+
+impl<S> Finalized<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Finalized<S: Spider>
+    where S::Item: FinalizeItem
+    {
+        async fn parse(&self, response, state) {
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(items.into_iter().map(FinalizeItem::finalize));
+            output.add_requests(requests);
+            Ok(output)
+        }
+    }
+}
+
+/// Adds [`finalize_items`](FinalizeExt::finalize_items) to spiders whose items implement
+/// [`FinalizeItem`].
+pub trait FinalizeExt: Spider + Sized
+where
+    Self::Item: FinalizeItem,
+{
+    /// Applies [`FinalizeItem::finalize`] to every item the spider scrapes.
+    fn finalize_items(self) -> Finalized<Self> {
+        Finalized { spider: self }
+    }
+}
+
+impl<S> FinalizeExt for S
+where
+    S: Spider,
+    S::Item: FinalizeItem,
+{
+}
+
+/// A spider that cleans the items it scrapes itself, see the [module docs](self).
+pub trait ProcessItemSpider: Spider + Sized {
+    /// Returns `item` as it should reach the pipelines, or `None` to drop it. The
+    /// default passes every item unchanged.
+    fn process_item(&self, item: Self::Item, _state: &Self::State) -> Option<Self::Item> {
+        Some(item)
+    }
+
+    /// Wraps the spider so [`process_item`](Self::process_item) is applied to every item
+    /// it scrapes.
+    fn processed(self) -> Processed<Self> {
+        Processed { spider: self }
+    }
+}
+
+/// A spider whose items pass its [`ProcessItemSpider::process_item`] before the
+/// pipelines, see [`ProcessItemSpider::processed`].
+pub struct Processed<S> {
+    spider: S,
+}
+
+impl<S> Processed<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Processed<S: ProcessItemSpider> {
+        async fn parse(&self, response, state) {
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(
+                items
+                    .into_iter()
+                    .filter_map(|item| self.spider.process_item(item, state)),
+            );
+            output.add_requests(requests);
+            Ok(output)
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Reading HTML forms and building their submissions.
+//!
+//! [`Form`] collects the values a browser would submit for a `<form>`: hidden inputs
+//! (such as CSRF tokens), pre-filled text fields, checked checkboxes and radio buttons,
+//! selected `<select>` options and `<textarea>` contents. Values can then be overridden
+//! and turned into a request.
+
+use crate::request::RequestExt;
+use scraper::{ElementRef, Html};
+use spider_util::{error::SpiderError, request::Request, utils::ToSelector};
+use url::{Url, form_urlencoded};
+
+/// A form extracted from an HTML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Form {
+    /// The URL the form submits to, resolved against the page URL.
+    pub action: Url,
+    /// The submission method, upper-cased (`GET` or `POST`).
+    pub method: String,
+    /// The default values of the form's successful controls, in document order.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Form {
+    /// Builds a form from a `<form>` element found on the page at `base_url`.
+    ///
+    /// A missing or empty `action` submits back to `base_url`, and a missing or unknown
+    /// `method` means `GET`.
+    pub fn from_element(form: ElementRef<'_>, base_url: &Url) -> Result<Self, url::ParseError> {
+        let action = match form.value().attr("action").map(str::trim) {
+            Some(action) if !action.is_empty() => base_url.join(action)?,
+            _ => base_url.clone(),
+        };
+
+        let method = match form.value().attr("method") {
+            Some(method) if method.trim().eq_ignore_ascii_case("post") => "POST",
+            _ => "GET",
+        }
+        .to_string();
+
+        let mut fields = Vec::new();
+        for control in form.descendants().filter_map(ElementRef::wrap) {
+            collect_control(control, &mut fields);
+        }
+
+        Ok(Self {
+            action,
+            method,
+            fields,
+        })
+    }
+
+    /// Returns the form's values with `overrides` applied.
+    ///
+    /// An override replaces every default value of the field with the same name, keeping
+    /// the position of the first one; overrides for fields the form does not contain are
+    /// appended.
+    pub fn form_data(&self, overrides: &[(&str, &str)]) -> Vec<(String, String)> {
+        let mut data: Vec<(String, String)> = Vec::with_capacity(self.fields.len());
+        for (name, value) in &self.fields {
+            match overrides.iter().find(|(key, _)| key == name) {
+                Some(_) if data.iter().any(|(existing, _)| existing == name) => {}
+                Some((_, replacement)) => data.push((name.clone(), replacement.to_string())),
+                None => data.push((name.clone(), value.clone())),
+            }
+        }
+        for (name, value) in overrides {
+            if !self.fields.iter().any(|(existing, _)| existing == name) {
+                data.push((name.to_string(), value.to_string()));
+            }
+        }
+        data
+    }
+
+    /// Returns the form's values, with `overrides` applied, encoded as
+    /// `application/x-www-form-urlencoded`.
+    pub fn encoded(&self, overrides: &[(&str, &str)]) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.form_data(overrides))
+            .finish()
+    }
+
+    /// Builds the request submitting this form with `overrides` applied.
+    ///
+    /// `GET` forms are submitted by replacing the query string of the action URL with the
+    /// form data. `POST` forms send it as an `application/x-www-form-urlencoded` body,
+    /// which keeps the field order and repeated names of the form and survives retries,
+    /// see [`RequestExt::form`].
+    pub fn submit(&self, overrides: &[(&str, &str)]) -> Request {
+        if self.method == "POST" {
+            return Request::new(self.action.clone()).form(&self.form_data(overrides));
+        }
+
+        let mut url = self.action.clone();
+        url.set_query(None);
+        let data = self.form_data(overrides);
+        if !data.is_empty() {
+            url.query_pairs_mut().extend_pairs(data);
+        }
+        Request::new(url)
+    }
+}
+
+/// Finds the first `<form>` matching `selector` in the document and extracts it.
+pub fn extract_form(
+    html: &Html,
+    base_url: &Url,
+    selector: &str,
+) -> Result<Option<Form>, SpiderError> {
+    let selector = selector.to_selector()?;
+    match html
+        .select(&selector)
+        .find(|element| element.value().name() == "form")
+    {
+        Some(form) => Ok(Some(Form::from_element(form, base_url)?)),
+        None => Ok(None),
+    }
+}
+
+/// Appends the name/value pairs a form control contributes to a submission.
+fn collect_control(control: ElementRef<'_>, fields: &mut Vec<(String, String)>) {
+    let element = control.value();
+    let Some(name) = element.attr("name").filter(|name| !name.is_empty()) else {
+        return;
+    };
+    if element.attr("disabled").is_some() {
+        return;
+    }
+
+    match element.name() {
+        "input" => {
+            let kind = element.attr("type").unwrap_or("text").to_ascii_lowercase();
+            match kind.as_str() {
+                // Buttons only contribute when clicked, and files cannot be pre-filled.
+                "submit" | "image" | "button" | "reset" | "file" => {}
+                "checkbox" | "radio" => {
+                    if element.attr("checked").is_some() {
+                        let value = element.attr("value").unwrap_or("on");
+                        fields.push((name.to_string(), value.to_string()));
+                    }
+                }
+                _ => {
+                    let value = element.attr("value").unwrap_or_default();
+                    fields.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+        "textarea" => {
+            fields.push((name.to_string(), control.text().collect()));
+        }
+        "select" => {
+            let options: Vec<ElementRef<'_>> = control
+                .descendants()
+                .filter_map(ElementRef::wrap)
+                .filter(|option| {
+                    option.value().name() == "option" && option.value().attr("disabled").is_none()
+                })
+                .collect();
+            let selected: Vec<ElementRef<'_>> = options
+                .iter()
+                .copied()
+                .filter(|option| option.value().attr("selected").is_some())
+                .collect();
+
+            if element.attr("multiple").is_some() {
+                for option in selected {
+                    fields.push((name.to_string(), option_value(option)));
+                }
+            } else if let Some(option) = selected.last().or(options.first()) {
+                // Browsers keep the last `selected` option of a single select,
+                // and fall back to the first option when none is marked.
+                fields.push((name.to_string(), option_value(*option)));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn option_value(option: ElementRef<'_>) -> String {
+    match option.value().attr("value") {
+        Some(value) => value.to_string(),
+        None => option
+            .text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
@@ -0,0 +1,163 @@
+//! Health reports for long-running crawls.
+//!
+//! A crawler service needs to know when a crawl is stuck, so an orchestrator can restart
+//! it. [`HealthMonitor`] reads the crawl statistics and reports a [`CrawlHealth`]: how
+//! long the crawl has run, an estimate of its frontier and the memory the frontier
+//! holds, and whether it is still making progress:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider).build().await?;
+//! let monitor = HealthMonitor::new(crawler.get_stats()).stall_after(Duration::from_secs(120));
+//!
+//! tokio::spawn(async move {
+//!     loop {
+//!         tokio::time::sleep(Duration::from_secs(30)).await;
+//!         let health = monitor.check();
+//!         if !health.progressing {
+//!             warn!("crawl stalled for {:?}", health.idle_for);
+//!         }
+//!     }
+//! });
+//! crawler.start_crawl().await?;
+//! ```
+//!
+//! A crawl counts as progressing while requests are sent, responses received or items
+//! scraped. Progress is noticed when [`HealthMonitor::check`] runs, so check more often
+//! than the [`stall_after`](HealthMonitor::stall_after) window. Placeholder requests a
+//! wrapper parks in the engine's queue are not requests of the crawl; pass their counts to
+//! [`HealthMonitor::excluding`] to leave them out of the frontier.
+
+use crate::stats::SyntheticTraffic;
+use spider_core::stats::StatCollector;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a crawl may go without progress before it counts as stalled, by default.
+const DEFAULT_STALL_AFTER: Duration = Duration::from_secs(60);
+
+/// The size assumed for one queued request by default, URL and headers included.
+const DEFAULT_REQUEST_BYTES: usize = 1024;
+
+/// A point-in-time health report, see [`HealthMonitor::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrawlHealth {
+    /// How long the crawl has run.
+    pub elapsed: Duration,
+    /// Requests enqueued but neither sent nor dropped yet, an estimate of the frontier.
+    pub queued_requests: usize,
+    /// The memory the frontier is estimated to hold, from
+    /// [`HealthMonitor::request_bytes`].
+    pub estimated_frontier_bytes: usize,
+    /// Requests sent so far.
+    pub requests_sent: usize,
+    /// Items scraped so far.
+    pub items_scraped: usize,
+    /// How long it has been since the crawl last made progress.
+    pub idle_for: Duration,
+    /// Whether the crawl made progress within the stall window.
+    pub progressing: bool,
+}
+
+#[derive(Debug)]
+struct Progress {
+    counter: usize,
+    at: Instant,
+}
+
+/// Derives [`CrawlHealth`] reports from a crawl's statistics.
+#[derive(Debug)]
+pub struct HealthMonitor {
+    stats: Arc<StatCollector>,
+    stall_after: Duration,
+    request_bytes: usize,
+    excluded: Vec<SyntheticTraffic>,
+    progress: Mutex<Progress>,
+}
+
+impl HealthMonitor {
+    /// Monitors the crawl `stats` belong to, usually from `Crawler::get_stats`.
+    pub fn new(stats: Arc<StatCollector>) -> Self {
+        let counter = progress_counter(&stats);
+        Self {
+            stats,
+            stall_after: DEFAULT_STALL_AFTER,
+            request_bytes: DEFAULT_REQUEST_BYTES,
+            excluded: Vec::new(),
+            progress: Mutex::new(Progress {
+                counter,
+                at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Reports the crawl as stalled once it has made no progress for `window`. Defaults
+    /// to one minute.
+    pub fn stall_after(mut self, window: Duration) -> Self {
+        self.stall_after = window;
+        self
+    }
+
+    /// Sets the average size of a queued request used for the frontier memory estimate.
+    /// Defaults to 1 KiB.
+    pub fn request_bytes(mut self, bytes: usize) -> Self {
+        self.request_bytes = bytes;
+        self
+    }
+
+    /// Leaves the placeholder requests counted by `traffic` out of the reports, see
+    /// [Synthetic traffic](crate::stats#synthetic-traffic). May be called once per wrapper.
+    pub fn excluding(mut self, traffic: &SyntheticTraffic) -> Self {
+        self.excluded.push(traffic.clone());
+        self
+    }
+
+    /// Returns the crawl's current health.
+    pub fn check(&self) -> CrawlHealth {
+        let stats = &self.stats;
+        let counter = progress_counter(stats);
+        let idle_for = {
+            let mut progress = self.progress.lock().expect("health monitor poisoned");
+            if counter != progress.counter {
+                progress.counter = counter;
+                progress.at = Instant::now();
+            }
+            progress.at.elapsed()
+        };
+
+        let queued_requests = queued_requests(stats, &self.excluded);
+        CrawlHealth {
+            elapsed: stats.start_time.elapsed(),
+            queued_requests,
+            estimated_frontier_bytes: queued_requests.saturating_mul(self.request_bytes),
+            requests_sent: stats.requests_sent.load(Ordering::Relaxed),
+            items_scraped: stats.items_scraped.load(Ordering::Relaxed),
+            idle_for,
+            progressing: idle_for < self.stall_after,
+        }
+    }
+}
+
+/// Estimates the requests enqueued but neither sent nor dropped yet, leaving out the
+/// placeholders counted by `excluded`.
+pub(crate) fn queued_requests(stats: &StatCollector, excluded: &[SyntheticTraffic]) -> usize {
+    let released: usize = excluded.iter().map(SyntheticTraffic::dropped).sum();
+    stats
+        .requests_enqueued
+        .load(Ordering::Relaxed)
+        .saturating_sub(released)
+        .saturating_sub(stats.requests_sent.load(Ordering::Relaxed))
+        .saturating_sub(
+            stats
+                .requests_dropped
+                .load(Ordering::Relaxed)
+                .saturating_sub(released),
+        )
+}
+
+/// Sums the counters that grow while a crawl makes progress.
+fn progress_counter(stats: &StatCollector) -> usize {
+    stats.requests_sent.load(Ordering::Relaxed)
+        + stats.responses_received.load(Ordering::Relaxed)
+        + stats.items_scraped.load(Ordering::Relaxed)
+}
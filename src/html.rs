@@ -0,0 +1,198 @@
+//! Refusing to parse binary responses as HTML.
+//!
+//! `Response::to_html` parses whatever the body holds. A misrouted image or PDF that
+//! happens to be valid UTF-8 turns into a document of junk text, and selectors quietly
+//! extract nonsense from it. [`ResponseExt::html`] checks the response with a
+//! [`TextHeuristic`] first and returns [`NotHtml`] for binary content, so the spider can
+//! branch on it:
+//!
+//! ```rust,ignore
+//! let html = match response.html() {
+//!     Ok(html) => html,
+//!     Err(not_html) => {
+//!         warn!("skipping {}: {}", response.url, not_html);
+//!         return Ok(ParseOutput::new());
+//!     }
+//! };
+//! ```
+//!
+//! A response is binary when its `Content-Type` names an image, audio, video, font or
+//! archive type, when its body starts with the signature of a common binary format, or
+//! when the start of its body holds NUL bytes or too many control characters. Each check
+//! can be tuned with [`ResponseExt::html_with`]. Bodies that pass are decoded with
+//! [`ResponseExt::encoding`], as [`ResponseExt::decoded_html`] does with the same check,
+//! see [`crate::encoding`].
+//!
+//! [`ResponseExt::html`]: crate::response::ResponseExt::html
+//! [`ResponseExt::html_with`]: crate::response::ResponseExt::html_with
+//! [`ResponseExt::encoding`]: crate::response::ResponseExt::encoding
+//! [`ResponseExt::decoded_html`]: crate::response::ResponseExt::decoded_html
+
+use spider_util::{error::SpiderError, response::Response};
+use std::fmt;
+
+/// How many leading bytes are inspected by default.
+const DEFAULT_SNIFF_LEN: usize = 1024;
+
+/// The share of control characters above which a body is binary, by default.
+const DEFAULT_MAX_CONTROL_RATIO: f64 = 0.1;
+
+/// Media types that are never HTML, besides the `image`, `audio`, `video` and `font`
+/// types.
+const BINARY_MEDIA_TYPES: &[&str] = &[
+    "application/octet-stream",
+    "application/pdf",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-tar",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/wasm",
+];
+
+/// Leading bytes of common binary formats.
+const BINARY_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xFF\xD8\xFF", "JPEG image"),
+    (b"GIF87a", "GIF image"),
+    (b"GIF89a", "GIF image"),
+    (b"%PDF-", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"\x1F\x8B", "gzip data"),
+    (b"\x00asm", "WebAssembly module"),
+];
+
+/// Why a response was not parsed as HTML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotHtml {
+    /// The `Content-Type` header names a binary type.
+    ContentType(String),
+    /// The body starts with the signature of a binary format.
+    Signature(&'static str),
+    /// The body holds NUL bytes or too many control characters.
+    Binary,
+}
+
+impl fmt::Display for NotHtml {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotHtml::ContentType(media_type) => {
+                write!(f, "response is not HTML: content type is {}", media_type)
+            }
+            NotHtml::Signature(format) => write!(f, "response is not HTML: body is a {}", format),
+            NotHtml::Binary => write!(f, "response is not HTML: body is binary"),
+        }
+    }
+}
+
+impl std::error::Error for NotHtml {}
+
+impl From<NotHtml> for SpiderError {
+    fn from(error: NotHtml) -> Self {
+        SpiderError::HtmlParseError(error.to_string())
+    }
+}
+
+/// Decides whether a response holds text, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct TextHeuristic {
+    check_content_type: bool,
+    sniff_len: usize,
+    max_control_ratio: f64,
+}
+
+impl Default for TextHeuristic {
+    fn default() -> Self {
+        Self {
+            check_content_type: true,
+            sniff_len: DEFAULT_SNIFF_LEN,
+            max_control_ratio: DEFAULT_MAX_CONTROL_RATIO,
+        }
+    }
+}
+
+impl TextHeuristic {
+    /// Creates the default heuristic.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether a binary `Content-Type` rejects the response. Defaults to `true`;
+    /// turn it off for servers that label every page `application/octet-stream`.
+    pub fn check_content_type(mut self, check: bool) -> Self {
+        self.check_content_type = check;
+        self
+    }
+
+    /// Sets how many leading bytes of the body are inspected. Defaults to 1024; `0`
+    /// skips the body checks.
+    pub fn sniff_len(mut self, len: usize) -> Self {
+        self.sniff_len = len;
+        self
+    }
+
+    /// Sets the share of control characters, other than whitespace, above which the
+    /// body is binary. Defaults to 0.1.
+    pub fn max_control_ratio(mut self, ratio: f64) -> Self {
+        self.max_control_ratio = ratio;
+        self
+    }
+
+    /// Checks `response`, returning why it is not text.
+    pub fn check(&self, response: &Response) -> Result<(), NotHtml> {
+        if self.check_content_type
+            && let Some(media_type) = response
+                .headers
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| {
+                    value
+                        .split(';')
+                        .next()
+                        .unwrap_or("")
+                        .trim()
+                        .to_ascii_lowercase()
+                })
+            && is_binary_media_type(&media_type)
+        {
+            return Err(NotHtml::ContentType(media_type));
+        }
+        self.check_body(&response.body)
+    }
+
+    /// Checks the start of `body`, returning why it is not text.
+    pub fn check_body(&self, body: &[u8]) -> Result<(), NotHtml> {
+        let head = &body[..body.len().min(self.sniff_len)];
+        if head.is_empty() {
+            return Ok(());
+        }
+        if let Some((_, format)) = BINARY_SIGNATURES
+            .iter()
+            .find(|(signature, _)| head.starts_with(signature))
+        {
+            return Err(NotHtml::Signature(format));
+        }
+        // UTF-16 text is full of NUL bytes; its byte order mark vouches for it.
+        if head.starts_with(b"\xFF\xFE") || head.starts_with(b"\xFE\xFF") {
+            return Ok(());
+        }
+        if head.contains(&0) {
+            return Err(NotHtml::Binary);
+        }
+        let control = head
+            .iter()
+            .filter(|&&byte| (byte < 0x20 && !b"\t\n\x0C\r".contains(&byte)) || byte == 0x7F)
+            .count();
+        if control as f64 > head.len() as f64 * self.max_control_ratio {
+            return Err(NotHtml::Binary);
+        }
+        Ok(())
+    }
+}
+
+fn is_binary_media_type(media_type: &str) -> bool {
+    let top_level = media_type.split('/').next().unwrap_or("");
+    matches!(top_level, "image" | "audio" | "video" | "font")
+        || BINARY_MEDIA_TYPES.contains(&media_type)
+}
@@ -0,0 +1,303 @@
+//! Helpers for JSON API responses.
+//!
+//! Paths into a JSON document are either JSON pointers (`/data/0/url`) or dotted paths
+//! (`data.0.url`). Where several values are wanted, a `*` segment matches every element
+//! of an array or every value of an object (`/data/*/url`). [`json_path`] evaluates
+//! JSONPath expressions for more involved queries.
+
+use serde_json::Value;
+use spider_util::{error::SpiderError, request::Request};
+use std::fmt;
+use url::Url;
+
+/// Why a response body could not be deserialized from JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotJson {
+    /// The body is not valid JSON.
+    Syntax(String),
+    /// The body is valid JSON but does not match the requested type.
+    Data(String),
+}
+
+impl fmt::Display for NotJson {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotJson::Syntax(reason) => write!(f, "response is not JSON: {}", reason),
+            NotJson::Data(reason) => {
+                write!(
+                    f,
+                    "response JSON does not match the expected type: {}",
+                    reason
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotJson {}
+
+impl From<serde_json::Error> for NotJson {
+    fn from(error: serde_json::Error) -> Self {
+        match error.classify() {
+            serde_json::error::Category::Data => NotJson::Data(error.to_string()),
+            _ => NotJson::Syntax(error.to_string()),
+        }
+    }
+}
+
+impl From<NotJson> for SpiderError {
+    fn from(error: NotJson) -> Self {
+        SpiderError::JsonError(error.to_string())
+    }
+}
+
+/// Errors that can occur while evaluating a JSONPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonPathError {
+    /// The expression is not valid or uses unsupported JSONPath features.
+    Syntax(String),
+    /// The response body is not valid JSON.
+    Json(String),
+}
+
+impl fmt::Display for JsonPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonPathError::Syntax(reason) => write!(f, "invalid JSONPath expression: {}", reason),
+            JsonPathError::Json(reason) => write!(f, "response body is not JSON: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for JsonPathError {}
+
+/// Looks up a single value by JSON pointer or dotted path.
+pub(crate) fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() || path.starts_with('/') {
+        return value.pointer(path);
+    }
+    path.split('.')
+        .try_fold(value, |current, key| match current {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => current.get(key),
+        })
+}
+
+/// Returns every value matched by `path`, expanding `*` segments, in document order.
+pub fn select_all<'a>(value: &'a Value, path: &str) -> Vec<&'a Value> {
+    let segments: Vec<String> = if path.is_empty() {
+        Vec::new()
+    } else if let Some(pointer) = path.strip_prefix('/') {
+        pointer
+            .split('/')
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect()
+    } else {
+        path.split('.').map(str::to_string).collect()
+    };
+
+    let mut matches = vec![value];
+    for segment in &segments {
+        matches = matches
+            .into_iter()
+            .flat_map(|current| -> Vec<&'a Value> {
+                match (current, segment.as_str()) {
+                    (Value::Array(items), "*") => items.iter().collect(),
+                    (Value::Object(fields), "*") => fields.values().collect(),
+                    (Value::Array(items), index) => index
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| items.get(index))
+                        .into_iter()
+                        .collect(),
+                    (Value::Object(fields), key) => fields.get(key).into_iter().collect(),
+                    _ => Vec::new(),
+                }
+            })
+            .collect();
+    }
+    matches
+}
+
+/// Evaluates a JSONPath expression and returns the matched values in document order.
+///
+/// Supported syntax: the root `$`, child members (`.name`, `['name']`), array indices
+/// including negative ones (`[0]`, `[-1]`), wildcards (`.*`, `[*]`), unions
+/// (`[0,2]`, `['a','b']`) and recursive descent (`..name`, `..*`).
+///
+/// ```
+/// use serde_json::json;
+/// use spider_lib::json::json_path;
+///
+/// let body = json!({"data": {"items": [{"name": "a"}, {"name": "b"}]}});
+/// let names = json_path(&body, "$.data.items[*].name").unwrap();
+/// assert_eq!(names, vec!["a", "b"]);
+/// ```
+pub fn json_path<'a>(value: &'a Value, expr: &str) -> Result<Vec<&'a Value>, JsonPathError> {
+    let segments = parse_json_path(expr)?;
+    let mut matches = vec![value];
+    for (recursive, selectors) in &segments {
+        let candidates: Vec<&'a Value> = if *recursive {
+            matches.into_iter().flat_map(descendants_or_self).collect()
+        } else {
+            matches
+        };
+        matches = candidates
+            .into_iter()
+            .flat_map(|candidate| {
+                selectors
+                    .iter()
+                    .flat_map(move |selector| select_children(candidate, selector))
+            })
+            .collect();
+    }
+    Ok(matches)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSelector {
+    Key(String),
+    Index(i64),
+    Wildcard,
+}
+
+/// A JSONPath segment: whether it is recursive (`..`) and the selectors it applies.
+type PathSegment = (bool, Vec<PathSelector>);
+
+fn parse_json_path(expr: &str) -> Result<Vec<PathSegment>, JsonPathError> {
+    let syntax = |reason: &str| JsonPathError::Syntax(format!("{} in {:?}", reason, expr));
+    let mut rest = expr
+        .trim()
+        .strip_prefix('$')
+        .ok_or_else(|| syntax("expression must start with `$`"))?;
+
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        let recursive = rest.starts_with("..");
+        if recursive {
+            rest = &rest[2..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            rest = after;
+        } else if !rest.starts_with('[') {
+            return Err(syntax("expected `.` or `[`"));
+        }
+
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = bracket_end(after).ok_or_else(|| syntax("unterminated `[`"))?;
+            let selectors = after[..end]
+                .split(',')
+                .map(|part| parse_bracket_selector(part.trim()))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| syntax("invalid bracket selector"))?;
+            segments.push((recursive, selectors));
+            rest = &after[end + 1..];
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            let selector = match &rest[..end] {
+                "" => return Err(syntax("expected a member name")),
+                "*" => PathSelector::Wildcard,
+                name => PathSelector::Key(name.to_string()),
+            };
+            segments.push((recursive, vec![selector]));
+            rest = &rest[end..];
+        }
+    }
+    Ok(segments)
+}
+
+/// Finds the `]` closing a bracket selector, skipping quoted names.
+fn bracket_end(input: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in input.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ']') => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_bracket_selector(part: &str) -> Option<PathSelector> {
+    if part == "*" {
+        return Some(PathSelector::Wildcard);
+    }
+    for quote in ['\'', '"'] {
+        if let Some(name) = part
+            .strip_prefix(quote)
+            .and_then(|part| part.strip_suffix(quote))
+        {
+            return Some(PathSelector::Key(name.to_string()));
+        }
+    }
+    part.parse().ok().map(PathSelector::Index)
+}
+
+fn select_children<'a>(value: &'a Value, selector: &PathSelector) -> Vec<&'a Value> {
+    match (selector, value) {
+        (PathSelector::Wildcard, Value::Array(items)) => items.iter().collect(),
+        (PathSelector::Wildcard, Value::Object(fields)) => fields.values().collect(),
+        (PathSelector::Key(key), Value::Object(fields)) => fields.get(key).into_iter().collect(),
+        (PathSelector::Index(index), Value::Array(items)) => {
+            let index = if *index < 0 {
+                items.len().checked_sub(index.unsigned_abs() as usize)
+            } else {
+                Some(*index as usize)
+            };
+            index
+                .and_then(|index| items.get(index))
+                .into_iter()
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns `value` and everything nested in it, in document order.
+fn descendants_or_self(value: &Value) -> Vec<&Value> {
+    let mut values = Vec::new();
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        values.push(value);
+        // Children are pushed last to first, so the first child is visited next.
+        match value {
+            Value::Array(items) => stack.extend(items.iter().rev()),
+            Value::Object(fields) => stack.extend(fields.values().rev()),
+            _ => {}
+        }
+    }
+    values
+}
+
+/// Builds requests for the URLs found at `paths` in `body`, resolved against `base`.
+///
+/// Values that are not strings, or strings that do not look like an `http(s)` URL or an
+/// absolute path, are skipped with a debug log. Each URL is returned once, in the order
+/// it is first found.
+pub fn json_links(body: &Value, base: &Url, paths: &[&str]) -> Vec<Request> {
+    let mut urls: Vec<Url> = Vec::new();
+    for path in paths {
+        for value in select_all(body, path) {
+            let Some(link) = value.as_str().filter(|link| looks_like_url(link)) else {
+                log::debug!("Skipping non-URL value at {}: {}", path, value);
+                continue;
+            };
+            match base.join(link) {
+                Ok(url) if !urls.contains(&url) => urls.push(url),
+                Ok(_) => {}
+                Err(err) => log::debug!("Skipping invalid URL {:?} at {}: {}", link, path, err),
+            }
+        }
+    }
+    urls.into_iter().map(Request::new).collect()
+}
+
+/// Returns `true` for absolute `http(s)` URLs and for paths or queries relative to the
+/// current URL (`/next`, `?page=2`).
+fn looks_like_url(value: &str) -> bool {
+    value.starts_with('/')
+        || value.starts_with('?')
+        || Url::parse(value).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
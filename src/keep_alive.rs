@@ -0,0 +1,244 @@
+//! Keeping a crawl alive until all of its work has settled.
+//!
+//! The engine ends a crawl once its request queue is empty and no download, parse or
+//! item is being processed. Between those stages there are short windows where work is
+//! in a channel and counted nowhere: a downloaded response waiting for a parse worker, or
+//! requests from a finished parse waiting to be enqueued. If the crawl is checked at that
+//! moment, it ends early and the pages the response would have led to are never crawled.
+//!
+//! [`KeepAlive`] closes these windows. Its middleware counts every response from the
+//! moment it leaves the middlewares until the wrapped spider has parsed it, and a
+//! placeholder "keep-alive" request circles through the engine's queue while there is
+//! such work or the crawl was active recently, so the queue is never empty while work
+//! is settling:
+//!
+//! ```rust,ignore
+//! let keep_alive = KeepAlive::new();
+//! let crawler = CrawlerBuilder::new(keep_alive.wrap(MySpider))
+//!     .keep_alive(&keep_alive)
+//!     .add_middleware(RetryMiddleware::new())
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//! ```
+//!
+//! The keep-alive request is never downloaded. It is released once nothing was
+//! downloaded or parsed for [`KeepAlive::quiet_period`], and the next parse sends a new
+//! one. A crawl therefore ends about one quiet period after its last parse. The engine
+//! counts every trip of the keep-alive request as a retried request, and its release as
+//! a dropped request. [`KeepAlive::traffic`] counts both, so the readers of the
+//! statistics can leave them out, see [Synthetic traffic](crate::stats#synthetic-traffic).
+
+use crate::stats::SyntheticTraffic;
+use log::trace;
+use spider_core::{Spider, async_trait};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Meta key marking the keep-alive request of a [`KeepAlive`].
+pub const KEEP_ALIVE_KEY: &str = "keep_alive";
+
+/// How long a crawl must be inactive before the keep-alive request is released, by
+/// default.
+const DEFAULT_QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// How long the keep-alive request waits in the engine's queue between checks.
+const PARK_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+struct KeepAliveState {
+    quiet_period: Duration,
+    /// Responses that left the middlewares and have not been parsed yet.
+    pending: AtomicUsize,
+    /// Whether a keep-alive request is in the engine's queue.
+    armed: AtomicBool,
+    next_slot: AtomicU64,
+    last_activity: Mutex<Instant>,
+    traffic: SyntheticTraffic,
+}
+
+/// Tracks the work of a crawl so it does not end while work is settling, see the
+/// [module docs](self).
+///
+/// Clones share the same state, so the wrapped spider and the middleware agree on it.
+#[derive(Debug, Clone)]
+pub struct KeepAlive {
+    state: Arc<KeepAliveState>,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::with_quiet_period(DEFAULT_QUIET_PERIOD)
+    }
+}
+
+impl KeepAlive {
+    /// Creates a tracker releasing the crawl after half a second without activity.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a tracker releasing the crawl after `quiet_period` without activity.
+    pub fn with_quiet_period(quiet_period: Duration) -> Self {
+        Self {
+            state: Arc::new(KeepAliveState {
+                quiet_period,
+                pending: AtomicUsize::new(0),
+                armed: AtomicBool::new(false),
+                next_slot: AtomicU64::new(0),
+                last_activity: Mutex::new(Instant::now()),
+                traffic: SyntheticTraffic::new(),
+            }),
+        }
+    }
+
+    /// Returns the spider with its `parse` calls tracked.
+    pub fn wrap<S: Spider>(&self, spider: S) -> KeptAlive<S> {
+        KeptAlive {
+            spider,
+            keep_alive: self.clone(),
+        }
+    }
+
+    /// Returns the middleware counting responses and circling the keep-alive request.
+    /// Add it to the crawler first.
+    pub fn middleware(&self) -> KeepAliveMiddleware {
+        KeepAliveMiddleware {
+            keep_alive: self.clone(),
+        }
+    }
+
+    /// Returns how long the crawl must be inactive before it may end.
+    pub fn quiet_period(&self) -> Duration {
+        self.state.quiet_period
+    }
+
+    /// Returns how many responses left the middlewares and are not parsed yet.
+    pub fn pending(&self) -> usize {
+        self.state.pending.load(Ordering::SeqCst)
+    }
+
+    /// Returns the trips and releases of the keep-alive request the engine counted as
+    /// retried and dropped requests.
+    pub fn traffic(&self) -> &SyntheticTraffic {
+        &self.state.traffic
+    }
+
+    fn touch(&self) {
+        *self
+            .state
+            .last_activity
+            .lock()
+            .expect("keep-alive state poisoned") = Instant::now();
+    }
+
+    fn is_settled(&self) -> bool {
+        self.pending() == 0
+            && self
+                .state
+                .last_activity
+                .lock()
+                .expect("keep-alive state poisoned")
+                .elapsed()
+                >= self.state.quiet_period
+    }
+
+    /// Returns a new keep-alive request if none is in the engine's queue. Each gets its
+    /// own URL fragment, so the engine's duplicate filter never takes it for a visited
+    /// page.
+    fn arm(&self, like: &Url) -> Option<Request> {
+        if self.state.armed.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        let slot = self.state.next_slot.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut url = like.clone();
+        url.set_fragment(Some(&format!("keep-alive-{}", slot)));
+        Some(Request::new(url).with_meta(KEEP_ALIVE_KEY, true.into()))
+    }
+}
+
+/// Counts responses for a [`KeepAlive`] and keeps its keep-alive request circling.
+#[derive(Debug, Clone)]
+pub struct KeepAliveMiddleware {
+    keep_alive: KeepAlive,
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for KeepAliveMiddleware {
+    fn name(&self) -> &str {
+        "KeepAliveMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if !request.meta.contains_key(KEEP_ALIVE_KEY) {
+            self.keep_alive.touch();
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        if self.keep_alive.is_settled() {
+            trace!("Crawl has settled, releasing the keep-alive request");
+            self.keep_alive.state.armed.store(false, Ordering::SeqCst);
+            self.keep_alive.state.traffic.record_drop();
+            return Ok(MiddlewareAction::Drop);
+        }
+        self.keep_alive.state.traffic.record_retry();
+        Ok(MiddlewareAction::Retry(Box::new(request), PARK_DELAY))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        self.keep_alive.state.pending.fetch_add(1, Ordering::SeqCst);
+        self.keep_alive.touch();
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
+
+/// A spider whose parses are tracked by a [`KeepAlive`], see [`KeepAlive::wrap`].
+pub struct KeptAlive<S> {
+    spider: S,
+    keep_alive: KeepAlive,
+}
+
+impl<S> KeptAlive<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for KeptAlive<S: Spider> {
+        fn start_requests(&self) {
+            let mut requests = self.spider.start_requests()?;
+            if let Some(first) = requests.first()
+                && let Some(slot) = self.keep_alive.arm(&first.url)
+            {
+                requests.push(slot);
+            }
+            Ok(requests)
+        }
+
+        async fn parse(&self, response, state) {
+            let url = response.url.clone();
+            let output = self.spider.parse(response, state).await;
+            let pending = &self.keep_alive.state.pending;
+            let _ = pending.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            self.keep_alive.touch();
+
+            let mut output = output?;
+            if let Some(slot) = self.keep_alive.arm(&url) {
+                output.add_request(slot);
+            }
+            Ok(output)
+        }
+    }
+}
@@ -69,5 +69,68 @@
 //! and receives a separate state parameter (`state: &Self::State`). This enables more efficient
 //! concurrent crawling by eliminating the need for mutex locks on the spider itself.
 
+#[macro_use]
+mod wrapper;
+
+pub mod builder;
+pub mod callback;
+pub mod channel;
+pub mod close_guard;
+pub mod context;
+pub mod crawl;
+pub mod dead_letter;
+pub mod debug;
+pub mod dns;
+pub mod downloader;
+pub mod encoding;
+pub mod event_log;
+pub mod export;
+pub mod extract;
+#[cfg(feature = "feed")]
+pub mod feed;
+pub mod files;
+pub mod finalize;
+pub mod form;
+pub mod health;
+pub mod html;
+pub mod json;
+pub mod keep_alive;
+pub mod lifecycle;
+pub mod links;
+#[cfg(feature = "metrics-prometheus")]
+pub mod metrics;
+pub mod middleware;
+#[cfg(feature = "pipeline-mongo")]
+pub mod mongo_writer;
+pub mod pagination;
+#[cfg(feature = "pipeline-parquet")]
+pub mod parquet_export;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod pending;
+pub mod pipeline_context;
+#[cfg(feature = "pipeline-postgres")]
+pub mod postgres_writer;
 pub mod prelude;
+pub mod request;
+pub mod response;
+pub mod routing;
+pub mod rules;
+pub mod sample;
+pub mod scheduler;
+pub mod scope;
+pub mod seed;
+pub mod select;
+#[cfg(feature = "sitemap")]
+pub mod sitemap;
+pub mod stats;
+pub mod stream;
+pub mod table;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timing;
+pub mod utils;
+pub mod validate;
+pub mod xpath;
+
 pub use prelude::*;
@@ -0,0 +1,111 @@
+//! Spider hooks run when a crawl opens and closes.
+//!
+//! Setup and teardown that belong to the spider, such as opening a connection, writing a
+//! header row or printing a summary from the spider's state, go in a
+//! [`LifecycleSpider`]. [`LifecycleSpider::with_lifecycle`] wraps the spider, and
+//! [`CrawlControl::run_with_lifecycle`](crate::crawl::CrawlControl::run_with_lifecycle)
+//! calls [`opened`](LifecycleSpider::opened) before the crawl starts and
+//! [`closed`](LifecycleSpider::closed) once it has ended, with the [`CloseReason`]:
+//!
+//! ```rust,ignore
+//! #[async_trait]
+//! impl LifecycleSpider for BooksSpider {
+//!     async fn closed(&self, state: &Self::State, reason: &CloseReason) {
+//!         println!("{} books, {}", state.get_book_count(), reason);
+//!     }
+//! }
+//!
+//! let spider = BooksSpider::new().with_lifecycle();
+//! let control = CrawlControl::new();
+//! let crawler = CrawlerBuilder::new(spider.clone())
+//!     .add_middleware(control.middleware())
+//!     .build()
+//!     .await?;
+//! let summary = control.run_with_lifecycle(crawler, &spider).await;
+//! ```
+//!
+//! The engine creates the spider state itself and only hands it to `parse`, so the
+//! wrapper keeps the state instead: it passes its own `S::State` to the spider's `parse`
+//! and to the hooks, and gives the engine a `()` state. Read the state during or after
+//! the crawl with [`Lifecycle::state`]. Clones of the wrapper share the spider and its
+//! state.
+//!
+//! When `opened` fails, the crawl does not start and `closed` is called with
+//! [`CloseReason::Error`].
+
+use crate::crawl::CloseReason;
+use spider_core::{Spider, async_trait};
+use spider_util::error::SpiderError;
+use std::sync::Arc;
+
+/// A spider with setup and teardown hooks, see the [module docs](self).
+#[async_trait]
+pub trait LifecycleSpider: Spider + Sized {
+    /// Runs before the first request is sent. An error keeps the crawl from starting.
+    /// The default does nothing.
+    async fn opened(&self, _state: &Self::State) -> Result<(), SpiderError> {
+        Ok(())
+    }
+
+    /// Runs once the crawl has ended and the pipelines are closed, with the reason it
+    /// ended. The default does nothing.
+    async fn closed(&self, _state: &Self::State, _reason: &CloseReason) {}
+
+    /// Wraps the spider so
+    /// [`CrawlControl::run_with_lifecycle`](crate::crawl::CrawlControl::run_with_lifecycle)
+    /// can call its hooks.
+    fn with_lifecycle(self) -> Lifecycle<Self> {
+        Lifecycle {
+            inner: Arc::new(Inner {
+                spider: self,
+                state: Self::State::default(),
+            }),
+        }
+    }
+}
+
+struct Inner<S: Spider> {
+    spider: S,
+    state: S::State,
+}
+
+/// A spider whose hooks are called by
+/// [`CrawlControl::run_with_lifecycle`](crate::crawl::CrawlControl::run_with_lifecycle),
+/// see [`LifecycleSpider::with_lifecycle`].
+pub struct Lifecycle<S: Spider> {
+    inner: Arc<Inner<S>>,
+}
+
+impl<S: Spider> Clone for Lifecycle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: Spider> Lifecycle<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.inner.spider
+    }
+
+    /// Returns the spider state, shared by `parse` and the hooks.
+    pub fn state(&self) -> &S::State {
+        &self.inner.state
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Lifecycle<S: LifecycleSpider> {
+        type State = ();
+
+        fn start_requests(&self) {
+            self.spider().start_requests()
+        }
+
+        async fn parse(&self, response, _state) {
+            self.spider().parse(response, self.state()).await
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Following links from HTML pages.
+
+use crate::utils::canonicalize_url;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use spider_util::{error::SpiderError, request::Request, response::Response, utils::ToSelector};
+use std::collections::HashSet;
+use url::Url;
+
+/// Elements whose `href` a [`LinkExtractor`] follows.
+const LINK_ELEMENTS: &str = "a[href], area[href]";
+
+/// A link that can be followed: an `href` string or a link element.
+pub trait FollowTarget {
+    /// Returns the URL reference to follow, if there is one.
+    fn href(&self) -> Option<&str>;
+}
+
+impl FollowTarget for str {
+    fn href(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl FollowTarget for String {
+    fn href(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+/// Elements are followed through their `href` attribute, or `src` for frames, images and
+/// scripts.
+impl FollowTarget for ElementRef<'_> {
+    fn href(&self) -> Option<&str> {
+        self.value()
+            .attr("href")
+            .or_else(|| self.value().attr("src"))
+    }
+}
+
+/// Builds a request for `target`, resolved against `base`, with `base` as its `Referer`.
+///
+/// Invalid references are reported as URL parse errors, and elements without an `href`
+/// or `src` as a general error saying so.
+pub fn follow<T: FollowTarget + ?Sized>(base: &Url, target: &T) -> Result<Request, SpiderError> {
+    let href = target.href().ok_or_else(|| {
+        SpiderError::GeneralError("cannot follow an element without href or src".into())
+    })?;
+    referred(base, base.join(href.trim())?)
+}
+
+/// Builds a request for `url` found on `base`, with `base` as its `Referer`.
+///
+/// Like browsers, the fragment and credentials of `base` are left out, and no `Referer`
+/// is sent from an `https` page to an `http` URL.
+fn referred(base: &Url, url: Url) -> Result<Request, SpiderError> {
+    let request = Request::new(url);
+    if base.scheme() == "https" && request.url.scheme() == "http" {
+        return Ok(request);
+    }
+    let mut referer = base.clone();
+    referer.set_fragment(None);
+    let _ = referer.set_username("");
+    let _ = referer.set_password(None);
+    request.with_header("Referer", referer.as_str())
+}
+
+/// Builds requests for attribute `attr` of every element matching `selector`, resolved
+/// against `base`, with `base` as their `Referer`.
+///
+/// Elements without the attribute, references that cannot be resolved and URLs that are
+/// not `http(s)` (`mailto:`, `javascript:`, ...) are skipped with a debug log. Each URL is
+/// returned once, in document order.
+pub fn follow_links(
+    html: &Html,
+    base: &Url,
+    selector: &str,
+    attr: &str,
+) -> Result<Vec<Request>, SpiderError> {
+    let selector = selector.to_selector()?;
+    let mut urls: Vec<Url> = Vec::new();
+    for element in html.select(&selector) {
+        let Some(href) = element.value().attr(attr) else {
+            continue;
+        };
+        match base.join(href.trim()) {
+            Ok(url) if !matches!(url.scheme(), "http" | "https") => {
+                log::debug!("Skipping non-HTTP link {}", url);
+            }
+            Ok(url) if !urls.contains(&url) => urls.push(url),
+            Ok(_) => {}
+            Err(err) => log::debug!("Skipping invalid link {:?}: {}", href, err),
+        }
+    }
+    urls.into_iter().map(|url| referred(base, url)).collect()
+}
+
+/// Declarative rules for the links a spider follows.
+///
+/// Links are taken from `<a>` and `<area>` elements, optionally only from the regions
+/// matching [`restrict_css`](Self::restrict_css): links inside a region, or the region
+/// itself when it is a link. A link is kept when it is `http(s)`, matches at least one
+/// `allow` pattern (or there are none) and matches no `deny` pattern. Patterns are
+/// matched against the absolute, canonicalized URL.
+///
+/// Build the extractor once, for example when constructing the spider, and reuse it for
+/// every page:
+///
+/// ```rust,ignore
+/// let links = LinkExtractor::new()
+///     .allow(r"/catalogue/[^/]+/index\.html$")?
+///     .deny(r"/category/")?
+///     .restrict_css("article.product_pod");
+///
+/// for request in links.extract(&response)? {
+///     output.add_request(request);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LinkExtractor {
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+    restrict_css: Vec<String>,
+    canonicalize: bool,
+}
+
+impl Default for LinkExtractor {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            restrict_css: Vec::new(),
+            canonicalize: true,
+        }
+    }
+}
+
+impl LinkExtractor {
+    /// Creates an extractor that follows every `http(s)` link on the page.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only follows URLs matching `pattern` (or any other `allow` pattern).
+    pub fn allow(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.allow.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Never follows URLs matching `pattern`, even when they are allowed.
+    pub fn deny(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.deny.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Only takes links matching `selector` or inside elements matching it. May be given
+    /// several times.
+    pub fn restrict_css(mut self, selector: &str) -> Self {
+        self.restrict_css.push(selector.to_string());
+        self
+    }
+
+    /// Whether URLs are canonicalized with [`canonicalize_url`] before matching and
+    /// deduplication, which is the default.
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Builds requests for the matching links of `response`, each URL once.
+    ///
+    /// Invalid `restrict_css` selectors are reported as errors.
+    pub fn extract(&self, response: &Response) -> Result<Vec<Request>, SpiderError> {
+        self.extract_from(&response.to_html()?, &response.url)
+    }
+
+    /// Like [`extract`](Self::extract), for an already parsed page whose URL is `base`.
+    pub fn extract_from(&self, html: &Html, base: &Url) -> Result<Vec<Request>, SpiderError> {
+        let links = LINK_ELEMENTS.to_selector()?;
+        let regions = self
+            .restrict_css
+            .iter()
+            .map(|selector| selector.to_selector())
+            .collect::<Result<Vec<Selector>, _>>()?;
+
+        let elements: Vec<ElementRef<'_>> = if regions.is_empty() {
+            html.select(&links).collect()
+        } else {
+            let mut seen = HashSet::new();
+            regions
+                .iter()
+                .flat_map(|region| html.select(region))
+                // A region that is itself a link, such as `a.next`, counts too.
+                .flat_map(|region| {
+                    std::iter::once(region)
+                        .filter(|region| links.matches(region))
+                        .chain(region.select(&links))
+                })
+                .filter(|element| seen.insert(element.id()))
+                .collect()
+        };
+
+        let mut seen = HashSet::new();
+        let mut requests = Vec::new();
+        for element in elements {
+            let Some(url) = self.resolve(base, element) else {
+                continue;
+            };
+            if self.matches(&url) && seen.insert(url.clone()) {
+                requests.push(Request::new(url));
+            }
+        }
+        Ok(requests)
+    }
+
+    fn resolve(&self, base: &Url, element: ElementRef<'_>) -> Option<Url> {
+        let href = element.value().attr("href")?;
+        match base.join(href.trim()) {
+            Ok(url) if !matches!(url.scheme(), "http" | "https") => {
+                log::debug!("Skipping non-HTTP link {}", url);
+                None
+            }
+            Ok(url) if self.canonicalize => Some(canonicalize_url(&url)),
+            Ok(url) => Some(url),
+            Err(err) => {
+                log::debug!("Skipping invalid link {:?}: {}", href, err);
+                None
+            }
+        }
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let url = url.as_str();
+        (self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.is_match(url)))
+            && !self.deny.iter().any(|pattern| pattern.is_match(url))
+    }
+}
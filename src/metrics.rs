@@ -0,0 +1,324 @@
+//! Prometheus metrics for long-running crawls.
+//!
+//! [`PrometheusExporter`] reads the crawl statistics and renders them in the Prometheus
+//! text format. [`PrometheusExporter::serve`] publishes them on an HTTP endpoint for the
+//! duration of the crawl, read live from the statistics on every scrape:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider).build().await?;
+//! let metrics = PrometheusExporter::new(crawler.get_stats())
+//!     .serve("0.0.0.0:9898")
+//!     .await?;
+//! info!("metrics at http://{}/metrics", metrics.local_addr());
+//! crawler.start_crawl().await?;
+//! ```
+//!
+//! The endpoint answers `GET` and `HEAD` requests for its path, `/metrics` by default, and
+//! stops when the returned [`MetricsServer`] is dropped. It is served by hyper's HTTP/1
+//! server on the crawl's tokio runtime; the `metrics-prometheus` feature adds hyper's
+//! server side, so crawls without it do not build one.
+//!
+//! The engine's counters are exported as counters, named after the [`StatCollector`]
+//! fields with a `spider_` prefix. The engine does not expose its queue or the requests
+//! being downloaded, so the two gauges are derived from the counters:
+//! `spider_requests_queued` is the frontier estimate of
+//! [`CrawlHealth`](crate::health::CrawlHealth), and `spider_requests_in_flight` counts the
+//! requests sent that have neither been answered nor failed yet. Placeholder requests a
+//! wrapper parks in the engine's queue, and placeholder responses for failed downloads,
+//! are left out of the counters and gauges once their counts are passed to
+//! [`PrometheusExporter::excluding`].
+
+use crate::downloader::FAILED_DOWNLOAD_STATUS;
+use crate::health::queued_requests;
+use crate::stats::SyntheticTraffic;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{CONTENT_TYPE, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{debug, warn};
+use spider_core::stats::StatCollector;
+use spider_core::tokio::{
+    self,
+    net::{TcpListener, ToSocketAddrs},
+    task::{JoinHandle, JoinSet},
+};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+/// The path metrics are served on by default.
+const DEFAULT_PATH: &str = "/metrics";
+
+/// The largest request head read from a scraper.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// The content type of the Prometheus text format.
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Renders and serves a crawl's statistics as Prometheus metrics, see the
+/// [module docs](self).
+#[derive(Debug, Clone)]
+pub struct PrometheusExporter {
+    stats: Arc<StatCollector>,
+    path: String,
+    excluded: Vec<SyntheticTraffic>,
+}
+
+impl PrometheusExporter {
+    /// Exports the crawl `stats` belong to, usually from `Crawler::get_stats`.
+    pub fn new(stats: Arc<StatCollector>) -> Self {
+        Self {
+            stats,
+            path: DEFAULT_PATH.to_string(),
+            excluded: Vec::new(),
+        }
+    }
+
+    /// Sets the path metrics are served on. Defaults to `/metrics`.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Leaves the placeholder requests counted by `traffic` out of the metrics, see
+    /// [Synthetic traffic](crate::stats#synthetic-traffic). May be called once per wrapper.
+    pub fn excluding(mut self, traffic: &SyntheticTraffic) -> Self {
+        self.excluded.push(traffic.clone());
+        self
+    }
+
+    /// Renders the current statistics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let stats = &self.stats;
+        let counters = [
+            (
+                "requests_enqueued",
+                "Requests added to the queue.",
+                &stats.requests_enqueued,
+            ),
+            (
+                "requests_sent",
+                "Requests downloaded.",
+                &stats.requests_sent,
+            ),
+            (
+                "requests_succeeded",
+                "Requests that received a response.",
+                &stats.requests_succeeded,
+            ),
+            (
+                "requests_failed",
+                "Requests that failed to download.",
+                &stats.requests_failed,
+            ),
+            (
+                "requests_retried",
+                "Requests scheduled again by a middleware.",
+                &stats.requests_retried,
+            ),
+            (
+                "requests_dropped",
+                "Requests dropped by a middleware.",
+                &stats.requests_dropped,
+            ),
+            (
+                "responses_received",
+                "Responses received.",
+                &stats.responses_received,
+            ),
+            (
+                "responses_from_cache",
+                "Responses answered from a cache.",
+                &stats.responses_from_cache,
+            ),
+            (
+                "bytes_downloaded",
+                "Response body bytes downloaded.",
+                &stats.total_bytes_downloaded,
+            ),
+            (
+                "items_scraped",
+                "Items returned by the spider.",
+                &stats.items_scraped,
+            ),
+            (
+                "items_processed",
+                "Items that went through the pipelines.",
+                &stats.items_processed,
+            ),
+            (
+                "items_dropped",
+                "Items dropped by a pipeline.",
+                &stats.items_dropped_by_pipeline,
+            ),
+        ];
+
+        let retried: usize = self.excluded.iter().map(SyntheticTraffic::retried).sum();
+        let released: usize = self.excluded.iter().map(SyntheticTraffic::dropped).sum();
+        let failed: usize = self.excluded.iter().map(SyntheticTraffic::failed).sum();
+        let mut out = String::new();
+        for (name, help, counter) in counters {
+            let value = counter.load(Ordering::Relaxed);
+            let value = match name {
+                "requests_enqueued" | "requests_dropped" => value.saturating_sub(released),
+                "requests_retried" => value.saturating_sub(retried),
+                "requests_succeeded" | "responses_received" => value.saturating_sub(failed),
+                "requests_failed" => value + failed,
+                _ => value,
+            };
+            metric(&mut out, &format!("{}_total", name), "counter", help, value);
+        }
+
+        let mut statuses: Vec<(u16, usize)> = stats
+            .response_status_counts
+            .iter()
+            .map(|entry| match *entry.key() {
+                FAILED_DOWNLOAD_STATUS => {
+                    (FAILED_DOWNLOAD_STATUS, entry.value().saturating_sub(failed))
+                }
+                status => (status, *entry.value()),
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        statuses.sort_unstable();
+        header(
+            &mut out,
+            "responses_by_status_total",
+            "counter",
+            "Responses received, by status code.",
+        );
+        for (status, count) in statuses {
+            let _ = writeln!(
+                out,
+                "spider_responses_by_status_total{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+
+        let answered = stats
+            .responses_received
+            .load(Ordering::Relaxed)
+            .saturating_sub(stats.responses_from_cache.load(Ordering::Relaxed));
+        let in_flight = stats
+            .requests_sent
+            .load(Ordering::Relaxed)
+            .saturating_sub(answered)
+            .saturating_sub(stats.requests_failed.load(Ordering::Relaxed));
+        metric(
+            &mut out,
+            "requests_queued",
+            "gauge",
+            "Requests enqueued but neither sent nor dropped yet, estimated.",
+            queued_requests(stats, &self.excluded),
+        );
+        metric(
+            &mut out,
+            "requests_in_flight",
+            "gauge",
+            "Requests being downloaded.",
+            in_flight,
+        );
+        header(
+            &mut out,
+            "uptime_seconds",
+            "gauge",
+            "How long the crawl has run.",
+        );
+        let _ = writeln!(
+            out,
+            "spider_uptime_seconds {}",
+            stats.start_time.elapsed().as_secs_f64()
+        );
+        out
+    }
+
+    /// Serves the metrics over HTTP on `addr` until the returned server is dropped.
+    pub async fn serve(self, addr: impl ToSocketAddrs) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        debug!("Serving Prometheus metrics on http://{}{}", addr, self.path);
+        let exporter = Arc::new(self);
+        let task = tokio::spawn(async move {
+            // Owned by the task, so stopping the server closes its connections too.
+            let mut connections = JoinSet::new();
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        warn!("Cannot accept a metrics connection: {}", err);
+                        continue;
+                    }
+                };
+                let exporter = exporter.clone();
+                let service = service_fn(move |request| {
+                    let response = exporter.answer(&request);
+                    async move { Ok::<_, Infallible>(response) }
+                });
+                connections.spawn(async move {
+                    let connection = http1::Builder::new()
+                        .max_buf_size(MAX_REQUEST_HEAD)
+                        .serve_connection(TokioIo::new(stream), service);
+                    if let Err(err) = connection.await {
+                        debug!("Metrics connection failed: {}", err);
+                    }
+                });
+                while connections.try_join_next().is_some() {}
+            }
+        });
+        Ok(MetricsServer { addr, task })
+    }
+
+    /// Answers one HTTP request. Hyper leaves the body out of `HEAD` responses.
+    fn answer(&self, request: &Request<Incoming>) -> Response<Full<Bytes>> {
+        let (status, body) = match *request.method() {
+            _ if request.uri().path() != self.path => (StatusCode::NOT_FOUND, String::new()),
+            Method::GET | Method::HEAD => (StatusCode::OK, self.render()),
+            _ => (StatusCode::METHOD_NOT_ALLOWED, String::new()),
+        };
+        let mut response = Response::new(Full::new(Bytes::from(body)));
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT));
+        response
+    }
+}
+
+/// A running metrics endpoint, see [`PrometheusExporter::serve`]. The endpoint stops when
+/// this is dropped.
+#[derive(Debug)]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Returns the address the endpoint listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Writes the `HELP` and `TYPE` lines of metric `spider_<name>`.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP spider_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE spider_{} {}", name, kind);
+}
+
+/// Writes metric `spider_<name>` with a single unlabelled sample.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: usize) {
+    header(out, name, kind, help);
+    let _ = writeln!(out, "spider_{} {}", name, value);
+}
@@ -0,0 +1,58 @@
+//! Middlewares provided by the facade crate.
+//!
+//! They implement the public [`Middleware`](spider_middleware::middleware::Middleware)
+//! trait and are added to a crawl with `CrawlerBuilder::add_middleware`, like the ones
+//! from `spider-middleware`. Per-request settings are read from the request meta, see
+//! [`RequestExt`](crate::request::RequestExt).
+//!
+//! # Order
+//!
+//! A middleware has two phases. `process_request` runs on each request before it is
+//! downloaded, through the middlewares in the order they were added. `process_response`
+//! runs on each downloaded response before it is handed to `Spider::parse`, through the
+//! middlewares in reverse order, so the first middleware added sees the request first
+//! and the response last:
+//!
+//! ```text
+//! request -> A -> B -> C -> download
+//! parse   <- A <- B <- C <- response
+//! ```
+//!
+//! In either phase a middleware returns `MiddlewareAction::Continue` with the request
+//! or response, changed or not, to pass it on, or `MiddlewareAction::Drop` to stop it,
+//! which counts in the crawl's `requests_dropped` statistic. The middlewares after the
+//! one that dropped it never see it, and a dropped response is never parsed. A response
+//! can also be turned into a retry with `MiddlewareAction::Retry`, as
+//! [`retry::RetryPolicyMiddleware`] does. `MiddlewareAction::ReturnResponse` answers a
+//! request without downloading it in the request phase; in the response phase it drops
+//! the response.
+//!
+//! To rewrite or drop responses without writing a middleware, add a
+//! [`response_hook::ResponseHookMiddleware`] with a closure.
+
+pub mod auth;
+pub mod autothrottle;
+pub mod content_filter;
+pub mod control;
+#[cfg(feature = "middleware-cookies")]
+pub mod cookie_jar;
+pub mod dead_letter;
+pub mod depth;
+pub mod dupe_filter;
+pub mod headers;
+pub mod https_upgrade;
+pub mod jitter;
+pub mod offsite;
+pub mod path_prefix;
+pub mod politeness;
+pub mod proxy_pool;
+pub mod ramp;
+pub mod response_hook;
+pub mod retry;
+#[cfg(feature = "middleware-robots")]
+pub mod robots_cache;
+pub mod soft_404;
+pub mod url_length;
+pub mod validation;
+#[cfg(feature = "middleware-warc")]
+pub mod warc;
@@ -0,0 +1,188 @@
+//! Middleware authenticating requests per domain.
+//!
+//! Internal sites often sit behind HTTP basic auth and APIs behind bearer tokens. An
+//! [`AuthMiddleware`] sets the `Authorization` header of the requests to each domain it
+//! has [`Credentials`] for, and of no others. Secrets can be read from environment
+//! variables, so they stay out of the code:
+//!
+//! ```rust,ignore
+//! let auth = AuthMiddleware::new()
+//!     .domain("wiki.corp.example", Credentials::basic_from_env("WIKI_USER", "WIKI_PASSWORD")?)?
+//!     .domain("api.example.com", Credentials::bearer_from_env("API_TOKEN")?)?;
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(auth)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Domains match like the allowed domains of
+//! [`OffsiteMiddleware`](crate::middleware::offsite::OffsiteMiddleware): a domain matches
+//! itself and its subdomains, `*.example.com` only the subdomains. When several domains
+//! match a host, the credentials added first are used. A request that already has an
+//! `Authorization` header keeps it, unless it carries the credentials of a domain that
+//! does not match its host, which are removed.
+//!
+//! Credentials do not follow redirects to another host:
+//! [`HttpDownloader`](crate::downloader::HttpDownloader) drops the `Authorization` header
+//! of a redirect to another host or port, and so does reqwest for the engine's
+//! downloader. The header is marked as sensitive, and [`Credentials`] print without
+//! their secret, so neither shows up in logs.
+
+use crate::utils::{host_matches_domain, normalize_domain_pattern};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::{debug, trace};
+use reqwest::header::{AUTHORIZATION, HeaderValue};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request};
+use std::fmt;
+
+/// The credentials of an [`AuthMiddleware`] domain.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// HTTP basic auth with a username and a password.
+    Basic {
+        /// The username.
+        username: String,
+        /// The password.
+        password: String,
+    },
+    /// A bearer token.
+    Bearer(String),
+}
+
+impl Credentials {
+    /// Creates basic auth credentials.
+    pub fn basic(username: &str, password: &str) -> Self {
+        Self::Basic {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    /// Creates bearer token credentials.
+    pub fn bearer(token: &str) -> Self {
+        Self::Bearer(token.to_string())
+    }
+
+    /// Creates basic auth credentials from the environment variables `username_var`
+    /// and `password_var`.
+    pub fn basic_from_env(username_var: &str, password_var: &str) -> Result<Self, SpiderError> {
+        Ok(Self::basic(&env(username_var)?, &env(password_var)?))
+    }
+
+    /// Creates bearer token credentials from the environment variable `token_var`.
+    pub fn bearer_from_env(token_var: &str) -> Result<Self, SpiderError> {
+        Ok(Self::bearer(&env(token_var)?))
+    }
+
+    /// Returns the value of the `Authorization` header, marked as sensitive.
+    ///
+    /// Fails with a `ConfigurationError` if the credentials contain characters not
+    /// allowed in a header. The error does not include the secret.
+    pub fn header_value(&self) -> Result<HeaderValue, SpiderError> {
+        let value = match self {
+            Self::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{username}:{password}"))
+                )
+            }
+            Self::Bearer(token) => format!("Bearer {token}"),
+        };
+        let mut value = HeaderValue::from_str(&value).map_err(|_| {
+            SpiderError::ConfigurationError(
+                "credentials contain characters not allowed in a header".to_string(),
+            )
+        })?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Self::Bearer(_) => f.write_str("Bearer(..)"),
+        }
+    }
+}
+
+fn env(var: &str) -> Result<String, SpiderError> {
+    std::env::var(var).map_err(|e| {
+        SpiderError::ConfigurationError(format!("cannot read credentials from ${var}: {e}"))
+    })
+}
+
+/// Sets the `Authorization` header of requests by their domain, see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct AuthMiddleware {
+    domains: Vec<(String, HeaderValue)>,
+}
+
+impl AuthMiddleware {
+    /// Creates a middleware without credentials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authenticates requests to `domain` and its subdomains, or only to the
+    /// subdomains for `*.example.com`, with `credentials`.
+    ///
+    /// Fails like [`Credentials::header_value`] if the credentials cannot be sent in a
+    /// header.
+    pub fn domain(mut self, domain: &str, credentials: Credentials) -> Result<Self, SpiderError> {
+        self.domains.push((
+            normalize_domain_pattern(domain),
+            credentials.header_value()?,
+        ));
+        Ok(self)
+    }
+
+    /// Returns the `Authorization` header for requests to `host`, if it has credentials.
+    pub fn header_for(&self, host: &str) -> Option<&HeaderValue> {
+        self.domains
+            .iter()
+            .find(|(pattern, _)| host_matches_domain(host, pattern))
+            .map(|(_, value)| value)
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for AuthMiddleware {
+    fn name(&self) -> &str {
+        "AuthMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        mut request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        let host = request.url.host_str().unwrap_or_default();
+        let own = self.header_for(host);
+        if let Some(current) = request.headers.get(AUTHORIZATION)
+            && Some(current) != own
+            && self.domains.iter().any(|(_, value)| value == current)
+        {
+            debug!(
+                "Removing credentials for another domain from {}",
+                request.url
+            );
+            request.headers.remove(AUTHORIZATION);
+        }
+        if let Some(value) = own
+            && !request.headers.contains_key(AUTHORIZATION)
+        {
+            trace!("Authenticating {}", request.url);
+            request.headers.insert(AUTHORIZATION, value.clone());
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
@@ -0,0 +1,254 @@
+//! Middleware adapting the delay between requests to each site's latency.
+//!
+//! A fixed delay is either too slow for a fast site or too fast for a struggling one.
+//! [`AutoThrottleMiddleware`] keeps a delay per host and adjusts it from the latency of
+//! the responses, like Scrapy's AutoThrottle:
+//!
+//! ```rust,ignore
+//! let throttle = AutoThrottleMiddleware::new()
+//!     .target_concurrency(2.0)?
+//!     .delay_range(Duration::from_millis(100), Duration::from_secs(30))?;
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .autothrottle(&throttle)
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//!
+//! for (host, delay) in throttle.delays() {
+//!     println!("{}: {:?}", host, delay);
+//! }
+//! ```
+//!
+//! Requests to a host are started at least its current delay apart. After each response
+//! the delay moves halfway towards `latency / target_concurrency`, the delay that keeps
+//! `target_concurrency` requests in flight to the host on average. Error responses never
+//! lower the delay, and `429 Too Many Requests` and `503 Service Unavailable` double it,
+//! to at least the start delay. The delay stays within the
+//! [`delay_range`](AutoThrottleMiddleware::delay_range).
+//!
+//! Latency is taken from [`ResponseExt::timings`] when the downloader records them, see
+//! [`crate::timing`]. Otherwise it is measured from the moment the middleware lets the
+//! request through, so add the middleware last to keep other middlewares out of the
+//! measurement. Requests marked with [`RequestExt::impolite`] are neither delayed nor
+//! measured.
+//!
+//! The engine's `StatCollector` has no room for custom values, so the computed delays
+//! are read from the middleware with [`AutoThrottleMiddleware::delay`] and
+//! [`AutoThrottleMiddleware::delays`]. Clones share them.
+
+use crate::request::RequestExt;
+use crate::response::ResponseExt;
+use log::{debug, trace};
+use spider_core::{async_trait, tokio};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use url::Url;
+
+/// Meta key holding when the middleware let a request through, in milliseconds since
+/// the Unix epoch.
+pub const THROTTLE_SENT_KEY: &str = "autothrottle_sent_ms";
+
+/// Delay before the first response from a host by default.
+const DEFAULT_START_DELAY: Duration = Duration::from_secs(1);
+
+/// Smallest delay by default.
+const DEFAULT_MIN_DELAY: Duration = Duration::ZERO;
+
+/// Largest delay by default.
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Average requests in flight to each host by default.
+const DEFAULT_TARGET_CONCURRENCY: f64 = 1.0;
+
+/// Adapts the delay between requests to each host, see the [module docs](self).
+///
+/// Clones share the delays, so keep a clone to read them during or after the crawl.
+#[derive(Debug, Clone)]
+pub struct AutoThrottleMiddleware {
+    start_delay: Duration,
+    min_delay: Duration,
+    max_delay: Duration,
+    target_concurrency: f64,
+    slots: Arc<Mutex<HashMap<String, Slot>>>,
+}
+
+#[derive(Debug)]
+struct Slot {
+    delay: Duration,
+    next_allowed_at: Option<Instant>,
+}
+
+impl Default for AutoThrottleMiddleware {
+    fn default() -> Self {
+        Self {
+            start_delay: DEFAULT_START_DELAY,
+            min_delay: DEFAULT_MIN_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            target_concurrency: DEFAULT_TARGET_CONCURRENCY,
+            slots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl AutoThrottleMiddleware {
+    /// Creates a middleware starting every host at a one second delay, adapting it
+    /// between zero and sixty seconds to keep one request in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the delay before the first response from a host. Defaults to one second.
+    pub fn start_delay(mut self, delay: Duration) -> Self {
+        self.start_delay = delay;
+        self
+    }
+
+    /// Sets the smallest and largest delay. Defaults to zero and sixty seconds.
+    ///
+    /// Fails with a `ConfigurationError` if `min` is larger than `max`.
+    pub fn delay_range(mut self, min: Duration, max: Duration) -> Result<Self, SpiderError> {
+        if min > max {
+            return Err(SpiderError::ConfigurationError(format!(
+                "minimum delay {min:?} exceeds maximum {max:?}"
+            )));
+        }
+        self.min_delay = min;
+        self.max_delay = max;
+        Ok(self)
+    }
+
+    /// Sets how many requests should be in flight to each host on average. Defaults
+    /// to 1.
+    ///
+    /// Fails with a `ConfigurationError` if `concurrency` is not a positive, finite number.
+    pub fn target_concurrency(mut self, concurrency: f64) -> Result<Self, SpiderError> {
+        if !(concurrency.is_finite() && concurrency > 0.0) {
+            return Err(SpiderError::ConfigurationError(format!(
+                "target concurrency must be positive, got {concurrency}"
+            )));
+        }
+        self.target_concurrency = concurrency;
+        Ok(self)
+    }
+
+    /// Returns the current delay for `host`, if a request was sent to it.
+    pub fn delay(&self, host: &str) -> Option<Duration> {
+        self.slots()
+            .get(&host.to_ascii_lowercase())
+            .map(|slot| slot.delay)
+    }
+
+    /// Returns the current delay of every host a request was sent to.
+    pub fn delays(&self) -> BTreeMap<String, Duration> {
+        self.slots()
+            .iter()
+            .map(|(host, slot)| (host.clone(), slot.delay))
+            .collect()
+    }
+
+    fn slots(&self) -> std::sync::MutexGuard<'_, HashMap<String, Slot>> {
+        self.slots.lock().expect("autothrottle state poisoned")
+    }
+
+    fn clamp(&self, delay: Duration) -> Duration {
+        delay.clamp(self.min_delay, self.max_delay)
+    }
+
+    /// Returns the delay after a response with `status` that took `latency`.
+    fn adjust(&self, delay: Duration, status: u16, latency: Duration) -> Duration {
+        if status == 429 || status == 503 {
+            return self.clamp(delay.saturating_mul(2).max(self.start_delay));
+        }
+        let target = latency.div_f64(self.target_concurrency);
+        let mut adjusted = (delay + target) / 2;
+        if status >= 400 {
+            adjusted = adjusted.max(delay);
+        }
+        self.clamp(adjusted)
+    }
+}
+
+fn host_key(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_ascii_lowercase()
+}
+
+fn unix_ms() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        * 1000.0
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for AutoThrottleMiddleware {
+    fn name(&self) -> &str {
+        "AutoThrottleMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.is_impolite() {
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        let now = Instant::now();
+        let start_delay = self.clamp(self.start_delay);
+        let send_at = {
+            let mut slots = self.slots();
+            let slot = slots.entry(host_key(&request.url)).or_insert(Slot {
+                delay: start_delay,
+                next_allowed_at: None,
+            });
+            let send_at = slot.next_allowed_at.map_or(now, |at| at.max(now));
+            slot.next_allowed_at = Some(send_at + slot.delay);
+            send_at
+        };
+        if send_at > now {
+            trace!("Throttling {} by {:?}", request.url, send_at - now);
+            tokio::time::sleep_until(send_at).await;
+        }
+        request
+            .meta
+            .insert(THROTTLE_SENT_KEY.into(), unix_ms().into());
+        Ok(MiddlewareAction::Continue(request))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        let latency = response
+            .timings()
+            .map(|timings| timings.total())
+            .or_else(|| {
+                let sent_ms = response.meta.get(THROTTLE_SENT_KEY)?.as_f64()?;
+                Duration::try_from_secs_f64((unix_ms() - sent_ms).max(0.0) / 1000.0).ok()
+            });
+        let Some(latency) = latency else {
+            return Ok(MiddlewareAction::Continue(response));
+        };
+        let host = host_key(&response.request_url);
+        let status = response.status.as_u16();
+        let mut slots = self.slots();
+        if let Some(slot) = slots.get_mut(&host) {
+            let delay = self.adjust(slot.delay, status, latency);
+            debug!(
+                "{} answered {} in {:?}, delay {:?} -> {:?}",
+                host, status, latency, slot.delay, delay
+            );
+            slot.delay = delay;
+        }
+        drop(slots);
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
@@ -0,0 +1,201 @@
+//! Middleware skipping binary assets an HTML spider cannot parse.
+//!
+//! On broad crawls, links to PDFs, images and archives waste bandwidth.
+//! [`ContentFilterMiddleware`] drops them in two places:
+//!
+//! - Before download, requests whose URL path ends in a denied extension are dropped.
+//!   The default list covers common images, audio, video, office documents and
+//!   archives; replace it with [`ContentFilterMiddleware::deny_extensions`].
+//! - After download, responses whose `Content-Type` is not in the list set with
+//!   [`ContentFilterMiddleware::allow_content_types`] are dropped before they reach the
+//!   spider. Responses without a `Content-Type` are kept.
+//!
+//! ```rust,ignore
+//! let filter = ContentFilterMiddleware::new().allow_content_types(&["text/html"]);
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .content_filter(&filter)
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//! println!("skipped {} by extension", filter.skipped_by_extension());
+//! ```
+//!
+//! Skipped requests are counted in the crawl's `requests_dropped` statistic, and by
+//! [`ContentFilterMiddleware::skipped_by_extension`] and
+//! [`ContentFilterMiddleware::skipped_by_content_type`].
+//!
+//! Middlewares only see a response once its body has been read, so the content type
+//! check saves parsing but not bandwidth. An
+//! [`HttpDownloader`](crate::downloader::HttpDownloader) built with
+//! [`allowed_content_types`](crate::downloader::HttpDownloaderBuilder::allowed_content_types)
+//! checks the headers before reading the body and skips it, marking the response with
+//! [`BODY_SKIPPED_KEY`](crate::stream::BODY_SKIPPED_KEY).
+
+use crate::response::ResponseExt;
+use log::debug;
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+/// URL extensions dropped by [`ContentFilterMiddleware::new`].
+pub const DEFAULT_DENIED_EXTENSIONS: &[&str] = &[
+    "3gp", "7z", "aac", "ai", "aiff", "apk", "avi", "avif", "bin", "bmp", "bz2", "cdr", "dmg",
+    "doc", "docx", "eps", "exe", "flac", "flv", "gif", "gz", "ico", "iso", "jar", "jpeg", "jpg",
+    "m4a", "m4v", "mid", "mkv", "mov", "mp3", "mp4", "mpeg", "mpg", "odp", "ods", "odt", "ogg",
+    "pdf", "png", "ppt", "pptx", "psd", "rar", "svg", "swf", "tar", "tgz", "tif", "tiff", "wav",
+    "webm", "webp", "wma", "wmv", "xls", "xlsx", "xz", "zip",
+];
+
+#[derive(Debug, Default)]
+struct Skipped {
+    by_extension: AtomicUsize,
+    by_content_type: AtomicUsize,
+}
+
+/// Drops requests and responses for binary assets, see the [module docs](self).
+///
+/// Clones share the skip counters, so keep a clone to read them after the crawl.
+#[derive(Debug, Clone)]
+pub struct ContentFilterMiddleware {
+    denied_extensions: Vec<String>,
+    allowed_content_types: Vec<String>,
+    skipped: Arc<Skipped>,
+}
+
+impl Default for ContentFilterMiddleware {
+    fn default() -> Self {
+        Self {
+            denied_extensions: DEFAULT_DENIED_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            allowed_content_types: Vec::new(),
+            skipped: Arc::default(),
+        }
+    }
+}
+
+impl ContentFilterMiddleware {
+    /// Creates a middleware denying [`DEFAULT_DENIED_EXTENSIONS`] and allowing every
+    /// content type.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the denied URL extensions, compared case-insensitively and given without
+    /// the dot.
+    pub fn deny_extensions(mut self, extensions: &[&str]) -> Self {
+        self.denied_extensions = extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Keeps only responses whose media type is in `content_types`, such as
+    /// `text/html`, or matches an entry like `text/*`. An empty list allows every
+    /// content type, the default.
+    pub fn allow_content_types(mut self, content_types: &[&str]) -> Self {
+        self.allowed_content_types = content_types
+            .iter()
+            .map(|content_type| content_type.trim().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Returns the number of requests dropped for their URL extension so far.
+    pub fn skipped_by_extension(&self) -> usize {
+        self.skipped.by_extension.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of responses dropped for their content type so far.
+    pub fn skipped_by_content_type(&self) -> usize {
+        self.skipped.by_content_type.load(Ordering::Relaxed)
+    }
+
+    fn denies(&self, url: &Url) -> bool {
+        url_extension(url).is_some_and(|extension| self.denied_extensions.contains(&extension))
+    }
+}
+
+/// Returns the lowercased extension of the last segment of the URL path.
+fn url_extension(url: &Url) -> Option<String> {
+    let name = url.path_segments()?.next_back()?;
+    let (_, extension) = name.rsplit_once('.')?;
+    (!extension.is_empty()).then(|| extension.to_ascii_lowercase())
+}
+
+/// Returns whether the `Content-Type` in `headers` is allowed by `allowed`, a list of
+/// lowercase media types or `type/*` patterns. An empty list, or a missing or
+/// unreadable header, allows the response.
+pub(crate) fn content_type_allowed(allowed: &[String], headers: &HeaderMap) -> bool {
+    if allowed.is_empty() {
+        return true;
+    }
+    let Some(media_type) = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_ascii_lowercase()
+        })
+        .filter(|media_type| !media_type.is_empty())
+    else {
+        return true;
+    };
+    allowed
+        .iter()
+        .any(|pattern| match pattern.strip_suffix("/*") {
+            Some(kind) => media_type
+                .split_once('/')
+                .is_some_and(|(media_kind, _)| media_kind == kind),
+            None => *pattern == media_type,
+        })
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for ContentFilterMiddleware {
+    fn name(&self) -> &str {
+        "ContentFilterMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if self.denies(&request.url) {
+            self.skipped.by_extension.fetch_add(1, Ordering::Relaxed);
+            debug!("Skipping request for a denied extension: {}", request.url);
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        if !content_type_allowed(&self.allowed_content_types, &response.headers) {
+            self.skipped.by_content_type.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Skipping response with content type {:?}: {}",
+                response.headers.get(CONTENT_TYPE),
+                response.url
+            );
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
@@ -0,0 +1,59 @@
+//! Middleware enforcing a [`CrawlControl`].
+
+use crate::crawl::CrawlControl;
+use crate::response::ResponseExt;
+use log::{debug, trace};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+
+/// Drops requests once the crawl has been asked to stop, holds them while it is paused,
+/// and counts downloaded bytes against the control's byte budget, see [`CrawlControl`].
+#[derive(Debug, Clone)]
+pub struct ControlMiddleware {
+    control: CrawlControl,
+}
+
+impl ControlMiddleware {
+    /// Creates a middleware enforcing `control`; usually obtained from
+    /// [`CrawlControl::middleware`].
+    pub fn new(control: CrawlControl) -> Self {
+        control.middleware_created();
+        Self { control }
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for ControlMiddleware {
+    fn name(&self) -> &str {
+        "ControlMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if self.control.is_paused() {
+            trace!("Crawl is paused, holding {}", request.url);
+            self.control.wait_while_paused().await;
+        }
+        if self.control.is_stopping() {
+            debug!("Crawl is stopping, dropping {}", request.url);
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        self.control
+            .record_bytes(response.wire_bytes(), response.body.len());
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
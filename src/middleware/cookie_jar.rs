@@ -0,0 +1,146 @@
+//! Cookie jars kept on disk between crawl runs.
+//!
+//! `CookieMiddleware` keeps its cookies in memory, so a logged-in session is lost when the
+//! process exits. [`CookieMiddlewareExt::with_persistence`] wraps it in a
+//! [`PersistentCookieMiddleware`] that loads the jar from a file when it is built and
+//! writes it back when the crawl ends:
+//!
+//! ```rust,ignore
+//! let cookies = CookieMiddleware::new().with_persistence("cookies.jsonl")?;
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(cookies)
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//! ```
+//!
+//! The jar is stored in the JSON lines format of `CookieMiddleware::from_json`. Session
+//! cookies are saved too, so a session survives a restart or a checkpoint resume, and
+//! expired cookies are left out when the jar is loaded. When the file does not exist yet,
+//! the middleware starts with the cookies it was created with; otherwise the saved jar
+//! replaces them.
+//!
+//! Middlewares have no close hook, so the jar is saved when the engine drops the
+//! middleware at the end of the crawl, including a graceful shutdown. Call
+//! [`PersistentCookieMiddleware::save`] to write it at other times, e.g. after logging in.
+
+use crate::response::ResponseExt;
+use cookie_store::CookieStore;
+use log::{debug, warn};
+use spider_core::{async_trait, tokio};
+use spider_middleware::cookies::CookieMiddleware;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Persistence for `CookieMiddleware`, see the [module docs](self).
+pub trait CookieMiddlewareExt {
+    /// Loads the cookie jar from `path` if the file exists, and saves it there when the
+    /// crawl ends.
+    fn with_persistence(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<PersistentCookieMiddleware, SpiderError>;
+}
+
+impl CookieMiddlewareExt for CookieMiddleware {
+    fn with_persistence(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<PersistentCookieMiddleware, SpiderError> {
+        let path = path.as_ref().to_path_buf();
+        let inner = match File::open(&path) {
+            Ok(file) => {
+                let store = CookieStore::load_json(BufReader::new(file))
+                    .map_err(|e| SpiderError::GeneralError(e.to_string()))?;
+                debug!("Loaded cookie jar from {}", path.display());
+                CookieMiddleware::with_store(store)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(PersistentCookieMiddleware { inner, path })
+    }
+}
+
+/// A `CookieMiddleware` whose jar is kept on disk, see the [module docs](self).
+pub struct PersistentCookieMiddleware {
+    inner: CookieMiddleware,
+    path: PathBuf,
+}
+
+impl PersistentCookieMiddleware {
+    /// Returns the cookie store, shared with the wrapped middleware.
+    pub fn store(&self) -> &Arc<RwLock<CookieStore>> {
+        &self.inner.store
+    }
+
+    /// Returns the file the jar is kept in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the jar to its file now.
+    pub async fn save(&self) -> Result<(), SpiderError> {
+        let store = self.inner.store.read().await;
+        save_store(&store, &self.path)
+    }
+}
+
+/// Writes the unexpired cookies of `store`, session cookies included, to `path`.
+fn save_store(store: &CookieStore, path: &Path) -> Result<(), SpiderError> {
+    // Write under a temporary name so an interrupted save keeps the previous jar.
+    let partial = path.with_extension("part");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    for cookie in store.iter_unexpired() {
+        let line =
+            serde_json::to_string(cookie).map_err(|e| SpiderError::GeneralError(e.to_string()))?;
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(&partial, path)?;
+    debug!("Saved cookie jar to {}", path.display());
+    Ok(())
+}
+
+impl Drop for PersistentCookieMiddleware {
+    fn drop(&mut self) {
+        let result = match self.inner.store.try_read() {
+            Ok(store) => save_store(&store, &self.path),
+            Err(_) => Err(SpiderError::GeneralError("cookie store is locked".into())),
+        };
+        if let Err(e) = result {
+            warn!("Cannot save cookie jar to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for PersistentCookieMiddleware {
+    fn name(&self) -> &str {
+        "PersistentCookieMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        self.inner.process_request(client, request).await
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        Middleware::<C>::process_response(&mut self.inner, response).await
+    }
+}
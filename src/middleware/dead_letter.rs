@@ -0,0 +1,69 @@
+//! Middleware recording error responses in a dead-letter file.
+//!
+//! [`DeadLetterMiddleware`] records every response with an error status that reaches it
+//! in a [`DeadLetterSink`] and passes it on. Add it before
+//! [`RetryPolicyMiddleware`](crate::middleware::retry::RetryPolicyMiddleware), so it only
+//! sees the responses the retry middleware does not retry; record exhausted retries with
+//! [`RetryPolicyMiddleware::dead_letter`]. The placeholder response of a failed download,
+//! see [`ResponseExt::is_failed_download`], is recorded with its transport error whatever
+//! the statuses recorded. See [`crate::dead_letter`] for re-crawling the recorded requests.
+//!
+//! [`RetryPolicyMiddleware::dead_letter`]:
+//!     crate::middleware::retry::RetryPolicyMiddleware::dead_letter
+
+use crate::dead_letter::DeadLetterSink;
+use crate::response::ResponseExt;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, response::Response};
+
+/// Records responses with an error status in a dead-letter file.
+#[derive(Debug, Clone)]
+pub struct DeadLetterMiddleware {
+    sink: DeadLetterSink,
+    statuses: Option<Vec<u16>>,
+}
+
+impl DeadLetterMiddleware {
+    /// Creates a middleware recording responses with a status of 400 or above in `sink`.
+    pub fn new(sink: DeadLetterSink) -> Self {
+        Self {
+            sink,
+            statuses: None,
+        }
+    }
+
+    /// Records only responses with one of `statuses`.
+    pub fn statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.statuses = Some(statuses);
+        self
+    }
+
+    fn is_failure(&self, status: u16) -> bool {
+        match &self.statuses {
+            Some(statuses) => statuses.contains(&status),
+            None => status >= 400,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for DeadLetterMiddleware {
+    fn name(&self) -> &str {
+        "DeadLetterMiddleware"
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            let reason = response.failure().unwrap_or_default();
+            self.sink.record(&response.replay_request(), &reason);
+        } else if self.is_failure(response.status.as_u16()) {
+            let reason = format!("status: {}", response.status);
+            self.sink.record(&response.replay_request(), &reason);
+        }
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
@@ -0,0 +1,139 @@
+//! Middleware limiting how deep a crawl follows links.
+//!
+//! [`DepthMiddleware`] drops requests more than a maximum number of links away from the
+//! start requests. The depth of a request is counted from the page it was found on, so
+//! the spider is wrapped to record it:
+//!
+//! ```rust,ignore
+//! let depth = DepthMiddleware::new(3);
+//! let crawler = CrawlerBuilder::new(depth.wrap(MySpider))
+//!     .max_depth(&depth)
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//!
+//! for (depth, requests) in depth.depth_counts() {
+//!     println!("depth {}: {} requests", depth, requests);
+//! }
+//! ```
+//!
+//! Every request the wrapped spider returns is marked with its depth under [`DEPTH_KEY`],
+//! one more than the page it was found on. Requests without the mark, such as start
+//! requests, are at depth `0`. Dropped requests are counted in the crawl's
+//! `requests_dropped` statistic and by [`DepthMiddleware::dropped`]; the requests let
+//! through are counted per depth by [`DepthMiddleware::depth_counts`].
+
+use log::debug;
+use spider_core::{Spider, async_trait};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, item::ParseOutput, request::Request};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Meta key holding how many links a request is away from the start requests.
+pub const DEPTH_KEY: &str = "depth";
+
+/// Drops requests deeper than a maximum depth, see the [module docs](self).
+///
+/// Clones share the counters, so keep a clone to read them after the crawl.
+#[derive(Debug, Clone)]
+pub struct DepthMiddleware {
+    max_depth: u32,
+    counts: Arc<Mutex<BTreeMap<u32, usize>>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl DepthMiddleware {
+    /// Creates a middleware allowing requests up to `max_depth` links from the start
+    /// requests. `0` allows only the start requests.
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            counts: Arc::new(Mutex::new(BTreeMap::new())),
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the maximum depth.
+    pub fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    /// Returns the spider with the depth of its requests recorded.
+    pub fn wrap<S: Spider>(&self, spider: S) -> DepthTracked<S> {
+        DepthTracked { spider }
+    }
+
+    /// Returns how many requests were let through at each depth so far.
+    pub fn depth_counts(&self) -> BTreeMap<u32, usize> {
+        self.counts.lock().expect("depth counts poisoned").clone()
+    }
+
+    /// Returns the number of requests dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn recorded_depth(meta: Option<&serde_json::Value>) -> u32 {
+    meta.and_then(serde_json::Value::as_u64)
+        .map_or(0, |depth| u32::try_from(depth).unwrap_or(u32::MAX))
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for DepthMiddleware {
+    fn name(&self) -> &str {
+        "DepthMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        let depth = recorded_depth(request.meta.get(DEPTH_KEY).as_deref());
+        if depth > self.max_depth {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Dropping request {} at depth {} (max {})",
+                request.url, depth, self.max_depth
+            );
+            return Ok(MiddlewareAction::Drop);
+        }
+        *self
+            .counts
+            .lock()
+            .expect("depth counts poisoned")
+            .entry(depth)
+            .or_default() += 1;
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
+
+/// A spider whose requests are marked with their depth, see [`DepthMiddleware::wrap`].
+pub struct DepthTracked<S> {
+    spider: S,
+}
+
+impl<S> DepthTracked<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for DepthTracked<S: Spider> {
+        async fn parse(&self, response, state) {
+            let depth = recorded_depth(response.meta.get(DEPTH_KEY).as_deref()).saturating_add(1);
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(items);
+            output.add_requests(requests.into_iter().inspect(|request| {
+                request.meta.insert(DEPTH_KEY.into(), depth.into());
+            }));
+            Ok(output)
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Middleware deciding which requests the engine's duplicate filter remembers.
+//!
+//! The engine skips any request whose URL it has already downloaded. A rule set with
+//! [`DupeFilterMiddleware::should_dedup`] exempts requests from this, for instance to
+//! re-fetch live pages every time they are linked:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .dupe_filter(
+//!         DupeFilterMiddleware::new()
+//!             .should_dedup(|request| !request.url.path().contains("/live/")),
+//!     )
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Returning `false` means the request is never filtered as a duplicate: its download is
+//! not recorded as visited, so the same URL is fetched again every time it is requested.
+//! Single requests are exempted with [`RequestExt::dont_filter`], which the middleware
+//! honours whatever the rule, so it must be in the crawler for the flag to take effect:
+//!
+//! ```rust,ignore
+//! let poll = Request::new(status_url).dont_filter();
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .dupe_filter(DupeFilterMiddleware::new())
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! To do this, the middleware sets the URL fragment of the response's `request_url` to
+//! `#no-dedup`, which the engine then records instead of the real URL. The engine's
+//! duplicate filter cannot be told to skip a URL, so the mark is the only way past it.
+//! Add the middleware first, with
+//! [`CrawlerBuilderExt::dupe_filter`](crate::builder::CrawlerBuilderExt::dupe_filter),
+//! to keep the mark from the other middlewares, and wrap the spider with
+//! [`DupeFilterMiddleware::wrap`] to take it off before `parse`, so the marked URL does not
+//! end up in items or in the requests built from it:
+//!
+//! ```rust,ignore
+//! let filter = DupeFilterMiddleware::new();
+//! let crawler = CrawlerBuilder::new(filter.wrap(MySpider))
+//!     .dupe_filter(filter)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Without the wrapper the spider sees the marked URL, and
+//! [`ResponseExt::original_request_url`](crate::response::ResponseExt::original_request_url)
+//! returns it without the fragment. Either way, a fragment the exempted request had is
+//! lost.
+//!
+//! Pages reachable under many URLs usually declare one of them with
+//! `<link rel="canonical">`. With [`DupeFilterMiddleware::dedup_on_canonical`] the
+//! middleware remembers the canonical URL of every successful HTML response and drops
+//! later responses declaring one it has seen, so their items are scraped once. Requests
+//! for a canonical URL already seen are dropped before they are downloaded:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .dupe_filter(DupeFilterMiddleware::new().dedup_on_canonical(true))
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The canonical is found by [`canonical_url_in_head`], which scans the `<link>` tags at
+//! the top of the page without parsing it, and only HTML responses are scanned. Pages
+//! without a canonical are remembered by their own URL. Items can carry the canonical
+//! URL as well, see [`ResponseExt::canonical_url`](crate::response::ResponseExt::canonical_url).
+//!
+//! The engine compares exact URLs, so `?a=1&b=2` and `?b=2&a=1` are downloaded twice.
+//! With [`DupeFilterMiddleware::dedup_set`] the middleware adds a second filter in front
+//! of the engine's: it remembers every request it lets through by the fingerprint of its
+//! [`canonicalize_url_with`] form, and drops later requests with the same fingerprint.
+//! The [`DedupSet`] holds the fingerprints exactly, or in a Bloom filter sized for an
+//! expected number of requests, and both drop the same query parameters,
+//! [`TRACKING_PARAMS`] unless set with [`DedupSet::strip_params`]:
+//!
+//! ```rust,ignore
+//! let set = DedupSet::bloom(50_000_000, 0.001)?.strip_params(&["utm_*", "sessionid"]);
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .dupe_filter(DupeFilterMiddleware::new().dedup_set(set))
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The rules combine on one middleware, e.g.
+//! `DupeFilterMiddleware::new().dedup_on_canonical(true).dedup_set(set)`. Each
+//! middleware added keeps its own seen sets, so configure a single one.
+//!
+//! The engine's own set of visited URLs is not pluggable, and it keeps every URL
+//! downloaded whatever the [`DedupSet`]. A Bloom filter therefore saves no memory over
+//! the engine's set, it only bounds what the canonical filter adds on top of it: a
+//! fixed, small amount (about 1.8 bytes per expected request at a rate of 1 in 1,000).
+//! A [`Scheduling`](crate::scheduler::Scheduling) keeps a third set of its own.
+//!
+//! A Bloom filter can report a request as seen when it was not, and that request is
+//! then never downloaded. The chance grows past the rate chosen once more requests than
+//! expected are added. [`DedupSet::exact`], the default, never drops a new request but
+//! keeps every fingerprint. Retries and requests exempted from duplicate filtering are
+//! neither checked nor remembered.
+
+use crate::request::RequestExt;
+use crate::response::ResponseExt;
+use crate::utils::{TRACKING_PARAMS, canonical_url_in_head, canonicalize_url_with};
+use log::{debug, trace};
+use spider_core::{Spider, async_trait};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{
+    bloom_filter::BloomFilter, error::SpiderError, request::Request, response::Response,
+};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Meta key marking a request exempted from duplicate filtering, see
+/// [`RequestExt::dont_filter`].
+pub const NO_DEDUP_KEY: &str = "no_dedup";
+
+/// URL fragment recorded as visited in place of an exempted request's URL.
+pub const NO_DEDUP_FRAGMENT: &str = "no-dedup";
+
+type DedupRule = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+
+/// How a [`DupeFilterMiddleware`] or a [`Scheduling`](crate::scheduler::Scheduling)
+/// remembers the requests it let through, see the [module docs](self).
+pub struct DedupSet {
+    fingerprints: Mutex<Fingerprints>,
+    strip_params: Vec<String>,
+}
+
+enum Fingerprints {
+    Exact(HashSet<String>),
+    Bloom(BloomFilter),
+}
+
+impl Default for DedupSet {
+    fn default() -> Self {
+        Self::exact()
+    }
+}
+
+impl DedupSet {
+    /// Keeps every fingerprint, so no request is dropped unless it was seen.
+    pub fn exact() -> Self {
+        Self::with(Fingerprints::Exact(HashSet::new()))
+    }
+
+    /// Keeps the fingerprints in a Bloom filter sized for `expected_requests`, which
+    /// takes a request for one already seen with probability `false_positive_rate`.
+    ///
+    /// Fails with a `ConfigurationError` if `false_positive_rate` is not strictly
+    /// between 0 and 1.
+    pub fn bloom(expected_requests: u64, false_positive_rate: f64) -> Result<Self, SpiderError> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(SpiderError::ConfigurationError(format!(
+                "false positive rate must be between 0 and 1, got {false_positive_rate}"
+            )));
+        }
+        let items = expected_requests.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-items * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+        let hash_functions = ((bits / items) * ln2).round().max(1.0);
+        Ok(Self::with(Fingerprints::Bloom(BloomFilter::new(
+            bits as u64,
+            hash_functions as usize,
+        ))))
+    }
+
+    fn with(fingerprints: Fingerprints) -> Self {
+        Self {
+            fingerprints: Mutex::new(fingerprints),
+            strip_params: TRACKING_PARAMS
+                .iter()
+                .map(|param| param.to_string())
+                .collect(),
+        }
+    }
+
+    /// Sets the query parameters dropped from URLs before fingerprinting, as
+    /// [`canonicalize_url_with`] takes them. Defaults to [`TRACKING_PARAMS`].
+    pub fn strip_params(mut self, params: &[&str]) -> Self {
+        self.strip_params = params.iter().map(|param| param.to_string()).collect();
+        self
+    }
+
+    /// Returns the fingerprint `request` is remembered by: the fingerprint of the
+    /// request with its URL in [`canonicalize_url_with`] form.
+    pub fn fingerprint(&self, request: &Request) -> String {
+        let strip_params: Vec<&str> = self.strip_params.iter().map(String::as_str).collect();
+        let mut canonical = request.clone();
+        canonical.url = canonicalize_url_with(&request.url, &strip_params);
+        canonical.fingerprint()
+    }
+
+    /// Records `request`, returning `false` if a request with the same fingerprint was
+    /// already recorded.
+    pub fn insert(&self, request: &Request) -> bool {
+        let fingerprint = self.fingerprint(request);
+        match &mut *self.fingerprints.lock().expect("dedup set poisoned") {
+            Fingerprints::Exact(set) => set.insert(fingerprint),
+            Fingerprints::Bloom(filter) => {
+                if filter.might_contain(&fingerprint) {
+                    return false;
+                }
+                filter.add(&fingerprint);
+                true
+            }
+        }
+    }
+
+    /// Returns whether `request` may go on: `false` if it is a duplicate. Retries and
+    /// requests exempted with [`RequestExt::dont_filter`] are neither checked nor
+    /// recorded.
+    pub(crate) fn admits(&self, request: &Request) -> bool {
+        if !request.filters_duplicates() || request.get_retry_attempts() > 0 {
+            return true;
+        }
+        self.insert(request)
+    }
+}
+
+#[derive(Debug, Default)]
+struct CanonicalDedup {
+    same_origin: bool,
+    seen: Mutex<HashSet<Url>>,
+}
+
+impl CanonicalDedup {
+    fn seen(&self, url: &Url) -> bool {
+        self.seen
+            .lock()
+            .expect("canonical set poisoned")
+            .contains(&without_fragment(url))
+    }
+
+    /// Records `url`, returning `false` if it was already recorded.
+    fn insert(&self, url: &Url) -> bool {
+        self.seen
+            .lock()
+            .expect("canonical set poisoned")
+            .insert(without_fragment(url))
+    }
+}
+
+fn without_fragment(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url
+}
+
+/// Applies a re-fetch policy to the engine's duplicate filter.
+///
+/// Clones share the canonical URLs seen.
+#[derive(Clone)]
+pub struct DupeFilterMiddleware {
+    should_dedup: DedupRule,
+    canonical: Option<Arc<CanonicalDedup>>,
+    seen: Option<Arc<DedupSet>>,
+}
+
+impl Default for DupeFilterMiddleware {
+    fn default() -> Self {
+        Self {
+            should_dedup: Arc::new(|_| true),
+            canonical: None,
+            seen: None,
+        }
+    }
+}
+
+impl DupeFilterMiddleware {
+    /// Creates a middleware that deduplicates every request, like the engine alone.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the rule deciding whether a request is deduplicated. Requests for which
+    /// `rule` returns `false` are never filtered as duplicates.
+    pub fn should_dedup(mut self, rule: impl Fn(&Request) -> bool + Send + Sync + 'static) -> Self {
+        self.should_dedup = Arc::new(rule);
+        self
+    }
+
+    /// Deduplicates pages on the URL they declare with `<link rel="canonical">`, see
+    /// the [module docs](self). With `same_origin` set, canonicals on another origin than
+    /// the page are ignored.
+    pub fn dedup_on_canonical(mut self, same_origin: bool) -> Self {
+        self.canonical = Some(Arc::new(CanonicalDedup {
+            same_origin,
+            ..CanonicalDedup::default()
+        }));
+        self
+    }
+
+    /// Drops requests whose canonical URL was already requested, remembering them in
+    /// `set`, see the [module docs](self).
+    pub fn dedup_set(mut self, set: DedupSet) -> Self {
+        self.seen = Some(Arc::new(set));
+        self
+    }
+
+    /// Returns the spider with the `#no-dedup` mark taken off the `request_url` of its
+    /// responses before they are parsed, see the [module docs](self).
+    pub fn wrap<S: Spider>(&self, spider: S) -> Unmarked<S> {
+        Unmarked { spider }
+    }
+
+    /// Returns the URL `response` is deduplicated on, or `None` if it is not checked.
+    fn canonical_key(&self, response: &Response) -> Option<Url> {
+        let canonical = self.canonical.as_ref()?;
+        if !response.status.is_success() {
+            return None;
+        }
+        let is_html = response
+            .headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_none_or(|value| value.to_ascii_lowercase().contains("html"));
+        if !is_html {
+            return None;
+        }
+        Some(
+            canonical_url_in_head(&response.body, &response.url, canonical.same_origin)
+                .unwrap_or_else(|| response.url.clone()),
+        )
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for DupeFilterMiddleware {
+    fn name(&self) -> &str {
+        "DupeFilterMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.filters_duplicates() && (self.should_dedup)(&request) {
+            if let Some(canonical) = &self.canonical
+                && canonical.seen(&request.url)
+            {
+                debug!(
+                    "Dropping request for a canonical URL already seen: {}",
+                    request.url
+                );
+                return Ok(MiddlewareAction::Drop);
+            }
+            if let Some(seen) = &self.seen
+                && !seen.admits(&request)
+            {
+                debug!("Dropping request already seen: {}", request.url);
+                return Ok(MiddlewareAction::Drop);
+            }
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        trace!("Exempting {} from duplicate filtering", request.url);
+        Ok(MiddlewareAction::Continue(
+            request.with_meta(NO_DEDUP_KEY, true.into()),
+        ))
+    }
+
+    async fn process_response(
+        &mut self,
+        mut response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response
+            .meta
+            .get(NO_DEDUP_KEY)
+            .is_some_and(|exempt| exempt.as_bool() == Some(true))
+        {
+            response.request_url.set_fragment(Some(NO_DEDUP_FRAGMENT));
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        if let (Some(canonical), Some(key)) = (&self.canonical, self.canonical_key(&response))
+            && !canonical.insert(&key)
+        {
+            debug!(
+                "Dropping {}, its canonical URL {} was already seen",
+                response.url, key
+            );
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
+
+/// A spider whose responses have the `#no-dedup` mark of a [`DupeFilterMiddleware`] taken
+/// off before parsing, see [`DupeFilterMiddleware::wrap`].
+pub struct Unmarked<S> {
+    spider: S,
+}
+
+impl<S> Unmarked<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Unmarked<S: Spider> {
+        async fn parse(&self, response, state) {
+            let mut response = response;
+            response.request_url = response.original_request_url();
+            self.spider.parse(response, state).await
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Middleware setting request headers per domain.
+//!
+//! `UserAgentMiddleware` only sets `User-Agent`. [`HeadersMiddleware`] adds any headers,
+//! such as `Accept-Language`, `Referer` or custom `X-` headers, so each site gets the
+//! header profile it expects:
+//!
+//! ```rust,ignore
+//! let headers = HeadersMiddleware::new()
+//!     .default_headers(&[("Accept-Language", "en-US,en;q=0.9")])
+//!     .domain("example.de", &[("Accept-Language", "de-DE,de;q=0.9")])
+//!     .domain("*.api.example.com", &[("X-Client", "catalog-sync")])
+//!     .rotate(
+//!         "shop.example",
+//!         &[
+//!             &[("Accept-Language", "en-GB"), ("DNT", "1")],
+//!             &[("Accept-Language", "en-US")],
+//!         ],
+//!     );
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(headers)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Domains match like the allowed domains of
+//! [`OffsiteMiddleware`](crate::middleware::offsite::OffsiteMiddleware): a domain matches
+//! itself and its subdomains, `*.example.com` only the subdomains. The default headers
+//! apply to every request, then the headers of each matching domain in the order they
+//! were added, each replacing the values of the same header before it. A domain added
+//! with [`rotate`](HeadersMiddleware::rotate) takes its header sets in turn, one per
+//! request.
+//!
+//! Headers already set on the [`Request`] take precedence: the middleware only adds the
+//! headers a request does not have.
+
+use crate::utils::{host_matches_domain, normalize_domain_pattern};
+use log::trace;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The header sets of one domain pattern.
+#[derive(Debug)]
+struct DomainHeaders {
+    pattern: String,
+    sets: Vec<HeaderMap>,
+    next: AtomicUsize,
+}
+
+impl DomainHeaders {
+    /// Returns the next header set, in turn.
+    fn next_set(&self) -> &HeaderMap {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.sets.len();
+        &self.sets[index]
+    }
+}
+
+/// Adds headers to requests by their domain, see the [module docs](self).
+///
+/// Clones share the rotation of the header sets.
+#[derive(Debug, Clone, Default)]
+pub struct HeadersMiddleware {
+    defaults: HeaderMap,
+    domains: Vec<Arc<DomainHeaders>>,
+}
+
+impl HeadersMiddleware {
+    /// Creates a middleware adding no headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `headers` to every request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header name or value is invalid.
+    pub fn default_headers(mut self, headers: &[(&str, &str)]) -> Self {
+        merge(&mut self.defaults, &header_map(headers));
+        self
+    }
+
+    /// Adds `headers` to requests to `domain` and its subdomains, or only to the
+    /// subdomains for `*.example.com`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header name or value is invalid.
+    pub fn domain(self, domain: &str, headers: &[(&str, &str)]) -> Self {
+        self.rotate(domain, &[headers])
+    }
+
+    /// Adds one of `sets` to each request to `domain`, taking the sets in turn.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sets` is empty, or if a header name or value is invalid.
+    pub fn rotate(mut self, domain: &str, sets: &[&[(&str, &str)]]) -> Self {
+        assert!(!sets.is_empty(), "no header sets for {domain}");
+        self.domains.push(Arc::new(DomainHeaders {
+            pattern: normalize_domain_pattern(domain),
+            sets: sets.iter().map(|set| header_map(set)).collect(),
+            next: AtomicUsize::new(0),
+        }));
+        self
+    }
+
+    /// Returns the headers for the next request to `host`, taking the next set of each
+    /// matching domain.
+    pub fn headers_for(&self, host: &str) -> HeaderMap {
+        let mut headers = self.defaults.clone();
+        for domain in &self.domains {
+            if host_matches_domain(host, &domain.pattern) {
+                merge(&mut headers, domain.next_set());
+            }
+        }
+        headers
+    }
+}
+
+fn header_map(headers: &[(&str, &str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        map.append(
+            HeaderName::from_bytes(name.as_bytes()).expect("invalid header name"),
+            HeaderValue::from_str(value).expect("invalid header value"),
+        );
+    }
+    map
+}
+
+/// Replaces the values in `headers` of every header in `other`.
+fn merge(headers: &mut HeaderMap, other: &HeaderMap) {
+    for name in other.keys() {
+        headers.remove(name);
+        for value in other.get_all(name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for HeadersMiddleware {
+    fn name(&self) -> &str {
+        "HeadersMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        mut request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        let Some(host) = request.url.host_str() else {
+            return Ok(MiddlewareAction::Continue(request));
+        };
+        let headers = self.headers_for(host);
+        for name in headers.keys() {
+            if request.headers.contains_key(name) {
+                continue;
+            }
+            trace!("Setting {} on {}", name, request.url);
+            for value in headers.get_all(name) {
+                request.headers.append(name.clone(), value.clone());
+            }
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
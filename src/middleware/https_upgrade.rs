@@ -0,0 +1,143 @@
+//! Middleware upgrading `http://` requests to HTTPS.
+//!
+//! Many sites still link to `http://` pages while serving them over HTTPS as well.
+//! [`HttpsUpgradeMiddleware`] rewrites those requests to `https://` before they are sent,
+//! and drops an upgraded request when its HTTPS twin has already been sent, so the crawl
+//! does not fetch insecure duplicates. URLs with an explicit port other than 80 are left
+//! alone, since the HTTPS port cannot be guessed.
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .upgrade_to_https(true)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Falling back to HTTP is opt-in with [`HttpsUpgradeMiddleware::fallback_to_http`]. The
+//! first time a host is upgraded, the middleware then fetches `/robots.txt` from it over
+//! HTTPS, once. If that request fails, requests to the host keep using HTTP for the rest
+//! of the crawl.
+
+use crate::middleware::dupe_filter::NO_DEDUP_KEY;
+use log::{debug, info};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, http_client::HttpClient, request::Request};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use url::Url;
+
+/// Meta key holding the original `http://` URL of an upgraded request.
+pub const UPGRADED_FROM_KEY: &str = "upgraded_from";
+
+/// How long the HTTPS probe of a host may take when falling back is enabled.
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Rewrites `http://` requests to `https://`.
+#[derive(Debug, Clone)]
+pub struct HttpsUpgradeMiddleware {
+    fallback_to_http: bool,
+    probe_timeout: Duration,
+    /// Whether each probed host answered over HTTPS.
+    https_hosts: HashMap<String, bool>,
+    /// Fingerprints of the requests passed on, to drop upgraded duplicates.
+    sent: HashSet<String>,
+}
+
+impl Default for HttpsUpgradeMiddleware {
+    fn default() -> Self {
+        Self {
+            fallback_to_http: false,
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
+            https_hosts: HashMap::new(),
+            sent: HashSet::new(),
+        }
+    }
+}
+
+impl HttpsUpgradeMiddleware {
+    /// Creates a middleware upgrading every `http://` request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps using HTTP for hosts that fail an HTTPS probe. Disabled by default.
+    pub fn fallback_to_http(mut self, fallback: bool) -> Self {
+        self.fallback_to_http = fallback;
+        self
+    }
+
+    /// Sets how long the HTTPS probe of a host may take. Defaults to 10 seconds.
+    pub fn probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Returns whether requests to `url`'s host may be upgraded, probing the host once
+    /// when falling back is enabled.
+    async fn supports_https<C: HttpClient>(&mut self, client: &C, url: &Url) -> bool {
+        if !self.fallback_to_http {
+            return true;
+        }
+        let host = url.host_str().unwrap_or_default().to_string();
+        if let Some(&supported) = self.https_hosts.get(&host) {
+            return supported;
+        }
+
+        let probe = format!("https://{host}/robots.txt");
+        let supported = match client.get_text(&probe, self.probe_timeout).await {
+            Ok(_) => true,
+            Err(e) => {
+                info!("{} does not answer over HTTPS, keeping HTTP: {}", host, e);
+                false
+            }
+        };
+        self.https_hosts.insert(host, supported);
+        supported
+    }
+}
+
+/// Returns the `https://` form of an `http://` URL on the default port.
+fn upgraded(url: &Url) -> Option<Url> {
+    if url.scheme() != "http" || url.port().is_some_and(|port| port != 80) {
+        return None;
+    }
+    let mut upgraded = url.clone();
+    upgraded.set_scheme("https").ok()?;
+    upgraded.set_port(None).ok()?;
+    Some(upgraded)
+}
+
+#[async_trait]
+impl<C: HttpClient> Middleware<C> for HttpsUpgradeMiddleware {
+    fn name(&self) -> &str {
+        "HttpsUpgradeMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        client: &C,
+        mut request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        let mut was_upgraded = false;
+        if let Some(https_url) = upgraded(&request.url)
+            && self.supports_https(client, &request.url).await
+        {
+            let original = std::mem::replace(&mut request.url, https_url);
+            request = request.with_meta(UPGRADED_FROM_KEY, original.as_str().into());
+            was_upgraded = true;
+        }
+
+        let first_send = self.sent.insert(request.fingerprint());
+        let exempt = request.get_retry_attempts() > 0
+            || request
+                .meta
+                .get(NO_DEDUP_KEY)
+                .is_some_and(|exempt| exempt.as_bool() == Some(true));
+        if was_upgraded && !first_send && !exempt {
+            debug!("Dropping {}: already requested over HTTPS", request.url);
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
@@ -0,0 +1,182 @@
+//! Randomized delays for `RateLimitMiddleware`.
+//!
+//! A crawler that sends requests exactly one second apart is easy to spot in a server's
+//! logs. [`RateLimitJitterExt::with_jitter`] builds a `RateLimitMiddleware` whose delay
+//! between two requests to a domain is drawn anew each time, uniformly within `spread`
+//! of the base delay:
+//!
+//! ```rust,ignore
+//! // Each delay is between 0.5s and 1.5s.
+//! let rate_limit = RateLimitMiddleware::with_jitter(Duration::from_secs(1), 0.5);
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .rate_limit(rate_limit)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Added with [`rate_limit`](crate::builder::CrawlerBuilderExt::rate_limit), the
+//! jittered delays are skipped for requests exempted from politeness, see
+//! [`crate::middleware::politeness`].
+//!
+//! The delays come from a [`JitterLimiter`], which can also be given to
+//! `RateLimitMiddleware::builder` to choose another scope. Limiters seeded with
+//! [`JitterLimiter::with_seed`] or built by
+//! [`RateLimitJitterExt::with_seeded_jitter`] draw the same delays on every run, which
+//! keeps tests reproducible; every domain then gets the same sequence of delays.
+
+use rand::SeedableRng;
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use spider_core::{async_trait, tokio};
+use spider_middleware::rate_limit::{RateLimitMiddleware, RateLimiter};
+use spider_util::response::Response;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{Instant, sleep};
+
+struct JitterState {
+    rng: StdRng,
+    delay: Duration,
+    next_allowed_at: Instant,
+}
+
+/// A rate limiter waiting a random delay around a base delay between requests.
+///
+/// See the [module docs](self).
+pub struct JitterLimiter {
+    base: Duration,
+    spread: f64,
+    state: Mutex<JitterState>,
+}
+
+impl JitterLimiter {
+    /// Creates a limiter whose delays are drawn uniformly from
+    /// `base * (1 - spread)..=base * (1 + spread)`, e.g. `0.5` for ±50%.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spread` is not between 0 and 1.
+    pub fn new(base: Duration, spread: f64) -> Self {
+        Self::with_rng(base, spread, StdRng::from_entropy())
+    }
+
+    /// Like [`new`](Self::new), but draws the delays from an RNG seeded with `seed`, so
+    /// limiters with the same seed wait the same delays.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spread` is not between 0 and 1.
+    pub fn with_seed(base: Duration, spread: f64, seed: u64) -> Self {
+        Self::with_rng(base, spread, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(base: Duration, spread: f64, mut rng: StdRng) -> Self {
+        check_spread(spread);
+        let delay = draw(&mut rng, base, spread);
+        Self {
+            base,
+            spread,
+            state: Mutex::new(JitterState {
+                rng,
+                delay,
+                next_allowed_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns the base delay the random delays are drawn around.
+    pub fn base(&self) -> Duration {
+        self.base
+    }
+
+    /// Returns the spread of the delays, as a fraction of the base delay.
+    pub fn spread(&self) -> f64 {
+        self.spread
+    }
+}
+
+fn check_spread(spread: f64) {
+    assert!(
+        (0.0..=1.0).contains(&spread),
+        "jitter spread must be between 0 and 1"
+    );
+}
+
+/// Draws a delay uniformly from `base * (1 ± spread)`.
+fn draw(rng: &mut StdRng, base: Duration, spread: f64) -> Duration {
+    if base.is_zero() || spread == 0.0 {
+        return base;
+    }
+    let low = base.mul_f64(1.0 - spread);
+    let high = base.mul_f64(1.0 + spread);
+    Uniform::new_inclusive(low, high).sample(rng)
+}
+
+#[async_trait]
+impl RateLimiter for JitterLimiter {
+    async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().await;
+            let now = Instant::now();
+            let delay = state.delay;
+            state.delay = draw(&mut state.rng, self.base, self.spread);
+            if now < state.next_allowed_at {
+                let wait = state.next_allowed_at - now;
+                state.next_allowed_at += delay;
+                wait
+            } else {
+                state.next_allowed_at = now + delay;
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// The delays do not depend on the responses.
+    async fn adjust(&self, _response: &Response) {}
+
+    /// Returns the delay drawn for the next request.
+    async fn current_delay(&self) -> Duration {
+        self.state.lock().await.delay
+    }
+}
+
+/// Jittered construction of `RateLimitMiddleware`, see the [module docs](self).
+pub trait RateLimitJitterExt {
+    /// Builds a per-domain rate limit waiting a random delay within `spread` of `base`
+    /// between requests, e.g. `with_jitter(Duration::from_secs(1), 0.5)` for 0.5s to
+    /// 1.5s.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spread` is not between 0 and 1.
+    fn with_jitter(base: Duration, spread: f64) -> RateLimitMiddleware;
+
+    /// Like [`with_jitter`](Self::with_jitter), but seeds the delays of every domain with
+    /// `seed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spread` is not between 0 and 1.
+    fn with_seeded_jitter(base: Duration, spread: f64, seed: u64) -> RateLimitMiddleware;
+}
+
+impl RateLimitJitterExt for RateLimitMiddleware {
+    fn with_jitter(base: Duration, spread: f64) -> RateLimitMiddleware {
+        // Fail here rather than when the first request creates a limiter.
+        check_spread(spread);
+        RateLimitMiddleware::builder()
+            .limiter_factory(move || Arc::new(JitterLimiter::new(base, spread)))
+            .build()
+    }
+
+    fn with_seeded_jitter(base: Duration, spread: f64, seed: u64) -> RateLimitMiddleware {
+        check_spread(spread);
+        RateLimitMiddleware::builder()
+            .limiter_factory(move || Arc::new(JitterLimiter::with_seed(base, spread, seed)))
+            .build()
+    }
+}
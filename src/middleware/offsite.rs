@@ -0,0 +1,211 @@
+//! Middleware restricting a crawl to its allowed domains.
+//!
+//! [`OffsiteMiddleware`] drops requests to hosts outside the allowed domains. A domain
+//! allows the host itself and its subdomains, and a wildcard `*.example.com` only the
+//! subdomains:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .allowed_domains(&["example.com", "*.cdn.example.org"])
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! [`OffsiteMiddleware::for_spider`] allows the sites of the spider's start requests
+//! instead: every host sharing a registrable domain with one of them, so a crawl
+//! starting at `www.example.com` may also visit `shop.example.com`. IP addresses only
+//! allow themselves.
+//!
+//! For link-graph crawls the site is crawled fully, but outbound links should still be
+//! fetched once to capture their targets, without crawling the external sites any
+//! further. [`OffsiteMode::ExternalDepth`] allows requests up to that many hops away
+//! from the allowed domains. The hops are counted from the page a request was found on,
+//! so the spider is wrapped to record them:
+//!
+//! ```rust,ignore
+//! let offsite = OffsiteMiddleware::new(&["example.com"]).mode(OffsiteMode::ExternalDepth(1));
+//! let crawler = CrawlerBuilder::new(offsite.wrap(MySpider))
+//!     .add_middleware(offsite)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Every request the wrapped spider returns is marked with its hops under
+//! [`OFFSITE_HOPS_KEY`]: `0` on an allowed domain, otherwise one more than the page it
+//! was found on. A link from an external page back to an allowed domain is on the site
+//! again. Requests without the mark, such as start requests, count as one hop when they
+//! are off-site. Dropped requests are counted in the crawl's `requests_dropped`
+//! statistic and by [`OffsiteMiddleware::dropped`].
+
+use crate::utils::{host_matches_domain, normalize_domain_pattern, same_registrable_domain};
+use log::debug;
+use spider_core::{Spider, async_trait};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, item::ParseOutput, request::Request};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+/// Meta key holding how many hops a request is away from the allowed domains.
+pub const OFFSITE_HOPS_KEY: &str = "offsite_hops";
+
+/// How an [`OffsiteMiddleware`] treats requests outside the allowed domains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OffsiteMode {
+    /// Drops every off-site request.
+    #[default]
+    Drop,
+    /// Allows off-site requests up to the given number of hops from the allowed domains.
+    /// `ExternalDepth(1)` fetches the pages the site links to, but none of their links.
+    ExternalDepth(u32),
+}
+
+/// Drops requests outside the allowed domains, see the [module docs](self).
+///
+/// Clones share the drop counter, so keep a clone to read it after the crawl.
+#[derive(Debug, Clone)]
+pub struct OffsiteMiddleware {
+    domains: Arc<Vec<String>>,
+    sites: Arc<Vec<Url>>,
+    mode: OffsiteMode,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl OffsiteMiddleware {
+    /// Creates a middleware allowing `domains` and their subdomains. A domain starting
+    /// with `*.` allows only its subdomains.
+    pub fn new(domains: &[&str]) -> Self {
+        Self {
+            domains: Arc::new(
+                domains
+                    .iter()
+                    .map(|domain| normalize_domain_pattern(domain))
+                    .collect(),
+            ),
+            sites: Arc::new(Vec::new()),
+            mode: OffsiteMode::Drop,
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Creates a middleware allowing the sites of `spider`'s start requests, see the
+    /// [module docs](self).
+    pub fn for_spider<S: Spider>(spider: &S) -> Result<Self, SpiderError> {
+        let mut offsite = Self::new(&[]);
+        offsite.sites = Arc::new(
+            spider
+                .start_requests()?
+                .into_iter()
+                .map(|request| request.url)
+                .collect(),
+        );
+        Ok(offsite)
+    }
+
+    /// Sets how off-site requests are treated. Defaults to [`OffsiteMode::Drop`].
+    pub fn mode(mut self, mode: OffsiteMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns whether `url` is on an allowed domain.
+    pub fn is_onsite(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        self.domains
+            .iter()
+            .any(|domain| host_matches_domain(host, domain))
+            || self
+                .sites
+                .iter()
+                .any(|site| same_registrable_domain(site, url))
+    }
+
+    /// Returns the spider with the hops of its requests recorded.
+    pub fn wrap<S: Spider>(&self, spider: S) -> OffsiteTracked<S> {
+        OffsiteTracked {
+            spider,
+            offsite: self.clone(),
+        }
+    }
+
+    /// Returns the number of requests dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn hops(&self, url: &Url, parent_hops: u64) -> u64 {
+        if self.is_onsite(url) {
+            0
+        } else {
+            parent_hops + 1
+        }
+    }
+}
+
+fn recorded_hops(meta: Option<&serde_json::Value>) -> Option<u64> {
+    meta.and_then(serde_json::Value::as_u64)
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for OffsiteMiddleware {
+    fn name(&self) -> &str {
+        "OffsiteMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if self.is_onsite(&request.url) {
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        let max_hops = match self.mode {
+            OffsiteMode::Drop => 0,
+            OffsiteMode::ExternalDepth(depth) => u64::from(depth),
+        };
+        let hops = recorded_hops(request.meta.get(OFFSITE_HOPS_KEY).as_deref()).unwrap_or(1);
+        if hops > max_hops {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Dropping off-site request {} ({} hops away)",
+                request.url, hops
+            );
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
+
+/// A spider whose requests are marked with their hops from the allowed domains, see
+/// [`OffsiteMiddleware::wrap`].
+pub struct OffsiteTracked<S> {
+    spider: S,
+    offsite: OffsiteMiddleware,
+}
+
+impl<S> OffsiteTracked<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for OffsiteTracked<S: Spider> {
+        async fn parse(&self, response, state) {
+            let parent_hops = recorded_hops(response.meta.get(OFFSITE_HOPS_KEY).as_deref())
+                .unwrap_or_else(|| self.offsite.hops(&response.url, 0));
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(items);
+            output.add_requests(requests.into_iter().inspect(|request| {
+                let hops = self.offsite.hops(&request.url, parent_hops);
+                request.meta.insert(OFFSITE_HOPS_KEY.into(), hops.into());
+            }));
+            Ok(output)
+        }
+    }
+}
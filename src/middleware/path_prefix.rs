@@ -0,0 +1,128 @@
+//! Middleware restricting a crawl to URL path prefixes.
+//!
+//! The most common crawl scope is "everything under `example.com/docs/`".
+//! [`PathPrefixMiddleware`] drops requests whose path does not start with one of the
+//! configured prefixes, without writing regexes for it:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .restrict_to_path_prefix("/docs/")
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Prefixes can also be given per host, so each allowed domain of a crawl has its own:
+//!
+//! ```rust,ignore
+//! let prefixes = PathPrefixMiddleware::new()
+//!     .host_prefix("docs.rs", "/tokio/")
+//!     .host_prefix("example.com", "/docs/")
+//!     .host_prefix("example.com", "/blog/");
+//! ```
+//!
+//! A host prefix applies to the host and its subdomains. Requests to hosts without any
+//! applicable prefix pass, which leaves them to the offsite filtering. Dropped requests
+//! are counted in the crawl's `requests_dropped` statistic and by
+//! [`PathPrefixMiddleware::dropped`].
+
+use log::debug;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+#[derive(Debug, Clone)]
+struct PrefixRule {
+    /// The host the prefix applies to, or `None` for every host.
+    host: Option<String>,
+    prefix: String,
+}
+
+impl PrefixRule {
+    fn applies_to(&self, url: &Url) -> bool {
+        let Some(host) = &self.host else {
+            return true;
+        };
+        let Some(url_host) = url.host_str() else {
+            return false;
+        };
+        url_host.eq_ignore_ascii_case(host)
+            || url_host.len() > host.len()
+                && url_host[url_host.len() - host.len()..].eq_ignore_ascii_case(host)
+                && url_host.as_bytes()[url_host.len() - host.len() - 1] == b'.'
+    }
+}
+
+/// Drops requests whose URL path does not start with a configured prefix.
+///
+/// Clones share the drop counter, so keep a clone to read it after the crawl.
+#[derive(Debug, Clone, Default)]
+pub struct PathPrefixMiddleware {
+    rules: Vec<PrefixRule>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl PathPrefixMiddleware {
+    /// Creates a middleware without prefixes, which lets every request pass.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows paths starting with `prefix` on every host.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.rules.push(PrefixRule {
+            host: None,
+            prefix: prefix.to_string(),
+        });
+        self
+    }
+
+    /// Allows paths starting with `prefix` on `host` and its subdomains.
+    pub fn host_prefix(mut self, host: &str, prefix: &str) -> Self {
+        self.rules.push(PrefixRule {
+            host: Some(host.to_ascii_lowercase()),
+            prefix: prefix.to_string(),
+        });
+        self
+    }
+
+    /// Returns whether a request for `url` passes.
+    pub fn allows(&self, url: &Url) -> bool {
+        let mut applicable = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(url))
+            .peekable();
+        applicable.peek().is_none() || applicable.any(|rule| url.path().starts_with(&rule.prefix))
+    }
+
+    /// Returns the number of requests dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for PathPrefixMiddleware {
+    fn name(&self) -> &str {
+        "PathPrefixMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if !self.allows(&request.url) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Dropping request outside the path prefixes: {}",
+                request.url
+            );
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
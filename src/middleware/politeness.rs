@@ -0,0 +1,146 @@
+//! Switching off a crawl's politeness for benchmarks.
+//!
+//! Benchmarking parse throughput against a local fixture server is skewed by pacing,
+//! jitter, crawl-delays and robots.txt checks. [`NoPolitenessMiddleware`] marks every
+//! request with [`RequestExt::impolite`], and every politeness middleware lets marked
+//! requests through untouched: the facade's own (the ramps, `AutoThrottle`,
+//! `RobotsCache`) read the mark themselves, and those from `spider-middleware`
+//! (`RateLimitMiddleware`, including jittered ones, and `RobotsTxtMiddleware`) read it
+//! through the [`Polite`] gate. Add them with [`CrawlerBuilderExt::rate_limit`] or wrap
+//! them in [`Polite`], and a single [`CrawlerBuilderExt::no_politeness`] switches them
+//! all off:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .no_politeness()
+//!     .rate_limit(RateLimitMiddleware::with_jitter(Duration::from_secs(1), 0.5))
+//!     .add_middleware(Polite::new(robots_txt))
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Requests pass middlewares in the order they were added, so the mark must be set
+//! first: call `no_politeness` before adding any other middleware.
+//!
+//! # Only against your own servers
+//!
+//! Without politeness a crawl sends requests as fast as the engine's concurrency allows
+//! and ignores robots.txt. Pointed at a third-party site, that is abusive and can get
+//! your address blocked or break the site. Use it only against servers you run, such as
+//! a local fixture server in CI. A warning is logged when the middleware is created.
+//!
+//! [`CrawlerBuilderExt::rate_limit`]: crate::builder::CrawlerBuilderExt::rate_limit
+//! [`CrawlerBuilderExt::no_politeness`]: crate::builder::CrawlerBuilderExt::no_politeness
+
+use crate::request::{IMPOLITE_KEY, RequestExt};
+use log::warn;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+
+/// Exempts every request from the facade's politeness, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct NoPolitenessMiddleware {
+    _private: (),
+}
+
+impl NoPolitenessMiddleware {
+    /// Creates the middleware, logging a warning that politeness is off.
+    pub fn new() -> Self {
+        warn!(
+            "Politeness is disabled: requests are not paced and robots.txt is ignored. \
+             Never crawl third-party sites with this setting."
+        );
+        Self { _private: () }
+    }
+}
+
+impl Default for NoPolitenessMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for NoPolitenessMiddleware {
+    fn name(&self) -> &str {
+        "NoPolitenessMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        Ok(MiddlewareAction::Continue(request.impolite()))
+    }
+}
+
+/// Skips `M` for requests marked with [`RequestExt::impolite`], see the
+/// [module docs](self).
+///
+/// Wrap the middlewares that pace requests or check robots.txt in it; marked requests
+/// and their responses and errors bypass the inner middleware.
+#[derive(Debug, Clone)]
+pub struct Polite<M> {
+    inner: M,
+}
+
+impl<M> Polite<M> {
+    /// Wraps `inner`.
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the wrapped middleware.
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<C, M> Middleware<C> for Polite<M>
+where
+    C: Send + Sync,
+    M: Middleware<C> + Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn process_request(
+        &mut self,
+        client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.is_impolite() {
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        self.inner.process_request(client, request).await
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        let impolite = response
+            .meta
+            .get(IMPOLITE_KEY)
+            .is_some_and(|value| value.as_bool() == Some(true));
+        if impolite {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        self.inner.process_response(response).await
+    }
+
+    async fn handle_error(
+        &mut self,
+        request: &Request,
+        error: &SpiderError,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.is_impolite() {
+            return Err(error.clone());
+        }
+        self.inner.handle_error(request, error).await
+    }
+}
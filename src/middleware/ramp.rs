@@ -0,0 +1,335 @@
+//! Middlewares ramping a crawl up over a warmup period.
+//!
+//! Hitting a site at full concurrency from the first request can trip its protections.
+//! [`ConcurrencyRampMiddleware`] caps the requests in flight, starting at `start` and
+//! raising the cap linearly to `target` over the warmup period:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .max_concurrent_downloads(16)
+//!     .concurrency_ramp(2, 16, Duration::from_secs(60))?
+//!     .autothrottle(&throttle)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The warmup starts with the first request. The engine's own semaphore cannot be
+//! resized from outside, so the ramp keeps one in front of it and adds permits as the
+//! warmup goes on. A request holds a permit from the moment the middleware lets it
+//! through until its response or download error comes back through it. The cap is the
+//! ceiling other middlewares work within: [`AutoThrottleMiddleware`] can still space
+//! requests further apart, but never has more than the cap in flight. Keep `target` at
+//! or below the engine's `max_concurrent_downloads`, which still applies.
+//!
+//! A response dropped by another middleware before it reaches the ramp never returns its
+//! permit, so permits held longer than the [`lease`](ConcurrencyRampMiddleware::lease)
+//! are taken back. Set it to at least the downloader's timeout.
+//!
+//! [`RampUpMiddleware`] ramps the rate requests are started at instead, from `start` to
+//! `target` requests per second:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .rate_ramp(1.0, 20.0, Duration::from_secs(60))?
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! After the warmup, requests keep being paced at `target`, so the rate ramp is the
+//! ceiling that rate limiting middlewares such as `RateLimitMiddleware` work within.
+//!
+//! Invalid settings, such as a zero `start`, are rejected with a `ConfigurationError`.
+//! Requests marked with [`RequestExt::impolite`] are neither counted nor paced.
+//!
+//! [`AutoThrottleMiddleware`]: crate::middleware::autothrottle::AutoThrottleMiddleware
+
+use crate::request::RequestExt;
+use log::{debug, trace};
+use spider_core::{async_trait, tokio};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request, response::Response};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// Meta key holding the permit a request took from a [`ConcurrencyRampMiddleware`].
+pub const RAMP_PERMIT_KEY: &str = "concurrency_ramp_permit";
+
+/// How long a request may hold a permit before it is taken back, by default.
+const DEFAULT_LEASE: Duration = Duration::from_secs(60);
+
+/// How often a request waiting for a permit checks whether the cap was raised.
+const RAISE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Paces requests at a rate rising from `start` to `target` requests per second.
+#[derive(Debug, Clone)]
+pub struct RampUpMiddleware {
+    start: f64,
+    target: f64,
+    over: Duration,
+    started_at: Option<Instant>,
+    next_allowed_at: Option<Instant>,
+}
+
+impl RampUpMiddleware {
+    /// Creates a middleware raising the rate from `start` to `target` requests per
+    /// second over `over`.
+    ///
+    /// Fails with a `ConfigurationError` if `start` or `target` is not a positive, finite
+    /// number.
+    pub fn new(start: f64, target: f64, over: Duration) -> Result<Self, SpiderError> {
+        for (name, rate) in [("start", start), ("target", target)] {
+            if !(rate.is_finite() && rate > 0.0) {
+                return Err(SpiderError::ConfigurationError(format!(
+                    "ramp {name} rate must be positive, got {rate}"
+                )));
+            }
+        }
+        Ok(Self {
+            start,
+            target,
+            over,
+            started_at: None,
+            next_allowed_at: None,
+        })
+    }
+
+    /// Returns the rate, in requests per second, `elapsed` after the first request.
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        let progress = if self.over.is_zero() {
+            1.0
+        } else {
+            (elapsed.as_secs_f64() / self.over.as_secs_f64()).min(1.0)
+        };
+        self.start + (self.target - self.start) * progress
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for RampUpMiddleware {
+    fn name(&self) -> &str {
+        "RampUpMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.is_impolite() {
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        let now = Instant::now();
+        let started_at = *self.started_at.get_or_insert(now);
+        if let Some(next_allowed_at) = self.next_allowed_at
+            && next_allowed_at > now
+        {
+            trace!(
+                "Ramping up, delaying {} by {:?}",
+                request.url,
+                next_allowed_at - now
+            );
+            tokio::time::sleep_until(next_allowed_at).await;
+        }
+
+        let sent_at = Instant::now();
+        let rate = self.rate_at(sent_at - started_at);
+        self.next_allowed_at = Some(sent_at + Duration::from_secs_f64(1.0 / rate));
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
+
+/// Caps the requests in flight at a limit rising from `start` to `target`, see the
+/// [module docs](self).
+///
+/// Clones share the cap and the permits.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyRampMiddleware {
+    start: usize,
+    target: usize,
+    over: Duration,
+    lease: Duration,
+    state: Arc<RampState>,
+}
+
+#[derive(Debug)]
+struct RampState {
+    semaphore: Arc<Semaphore>,
+    /// Permits added to the semaphore so far, the current cap.
+    granted: Mutex<usize>,
+    started_at: OnceLock<Instant>,
+    held: Mutex<HashMap<u64, (Instant, OwnedSemaphorePermit)>>,
+    next_permit: AtomicU64,
+}
+
+impl ConcurrencyRampMiddleware {
+    /// Creates a middleware raising the cap on requests in flight from `start` to
+    /// `target` over `over`.
+    ///
+    /// Fails with a `ConfigurationError` if `start` is zero or above `target`.
+    pub fn new(start: usize, target: usize, over: Duration) -> Result<Self, SpiderError> {
+        if start == 0 || start > target {
+            return Err(SpiderError::ConfigurationError(format!(
+                "concurrency ramp must start between 1 and its target {target}, got {start}"
+            )));
+        }
+        Ok(Self {
+            start,
+            target,
+            over,
+            lease: DEFAULT_LEASE,
+            state: Arc::new(RampState {
+                semaphore: Arc::new(Semaphore::new(start)),
+                granted: Mutex::new(start),
+                started_at: OnceLock::new(),
+                held: Mutex::new(HashMap::new()),
+                next_permit: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// Sets how long a request may hold a permit before it is taken back. Defaults to 60
+    /// seconds.
+    pub fn lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Returns the cap on requests in flight `elapsed` after the first request.
+    pub fn limit_at(&self, elapsed: Duration) -> usize {
+        let progress = if self.over.is_zero() {
+            1.0
+        } else {
+            (elapsed.as_secs_f64() / self.over.as_secs_f64()).min(1.0)
+        };
+        self.start + ((self.target - self.start) as f64 * progress) as usize
+    }
+
+    /// Returns the current cap on requests in flight.
+    pub fn limit(&self) -> usize {
+        *self.state.granted.lock().expect("ramp poisoned")
+    }
+
+    /// Returns the number of requests holding a permit.
+    pub fn in_flight(&self) -> usize {
+        self.state.held.lock().expect("ramp poisoned").len()
+    }
+
+    /// Adds the permits the warmup has reached since the last call.
+    fn raise(&self) {
+        let Some(started_at) = self.state.started_at.get() else {
+            return;
+        };
+        let limit = self.limit_at(started_at.elapsed());
+        let mut granted = self.state.granted.lock().expect("ramp poisoned");
+        if limit > *granted {
+            trace!("Raising the concurrency cap to {}", limit);
+            self.state.semaphore.add_permits(limit - *granted);
+            *granted = limit;
+        }
+    }
+
+    /// Takes back the permits held longer than the lease.
+    fn expire(&self) {
+        let mut held = self.state.held.lock().expect("ramp poisoned");
+        let before = held.len();
+        held.retain(|_, (since, _)| since.elapsed() < self.lease);
+        if held.len() < before {
+            debug!(
+                "Took back {} concurrency permits held longer than {:?}",
+                before - held.len(),
+                self.lease
+            );
+        }
+    }
+
+    /// Returns the permit recorded under [`RAMP_PERMIT_KEY`], if it is still held.
+    fn release(&self, permit: Option<u64>) {
+        if let Some(id) = permit {
+            self.state.held.lock().expect("ramp poisoned").remove(&id);
+        }
+    }
+
+    /// Waits until a request may be sent, raising the cap as the warmup goes on.
+    async fn acquire(&self) -> OwnedSemaphorePermit {
+        loop {
+            self.raise();
+            let wait = if self.limit() < self.target {
+                RAISE_INTERVAL
+            } else {
+                self.lease
+            };
+            let acquire = self.state.semaphore.clone().acquire_owned();
+            match tokio::time::timeout(wait, acquire).await {
+                Ok(permit) => return permit.expect("ramp semaphore closed"),
+                Err(_) => self.expire(),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for ConcurrencyRampMiddleware {
+    fn name(&self) -> &str {
+        "ConcurrencyRampMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.is_impolite() {
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        // A retried request gives back the permit of its earlier attempt.
+        self.release(
+            request
+                .meta
+                .get(RAMP_PERMIT_KEY)
+                .and_then(|value| value.as_u64()),
+        );
+        self.state.started_at.get_or_init(Instant::now);
+
+        let permit = self.acquire().await;
+        let id = self.state.next_permit.fetch_add(1, Ordering::Relaxed);
+        self.state
+            .held
+            .lock()
+            .expect("ramp poisoned")
+            .insert(id, (Instant::now(), permit));
+        Ok(MiddlewareAction::Continue(
+            request.with_meta(RAMP_PERMIT_KEY, id.into()),
+        ))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        self.release(
+            response
+                .meta
+                .get(RAMP_PERMIT_KEY)
+                .and_then(|value| value.as_u64()),
+        );
+        Ok(MiddlewareAction::Continue(response))
+    }
+
+    async fn handle_error(
+        &mut self,
+        request: &Request,
+        error: &SpiderError,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        self.release(
+            request
+                .meta
+                .get(RAMP_PERMIT_KEY)
+                .and_then(|value| value.as_u64()),
+        );
+        Err(error.clone())
+    }
+}
@@ -0,0 +1,97 @@
+//! Middleware rewriting or dropping responses before they are parsed.
+//!
+//! Every middleware sees the downloaded responses in its `process_response` phase,
+//! before they reach the spider, see [`crate::middleware`] for the order. A
+//! [`ResponseHookMiddleware`] runs a closure there, to clean up pages or drop the ones
+//! not worth parsing without writing a middleware. The closure gets the response and
+//! returns it, changed or not, or `None` to drop it:
+//!
+//! ```rust,ignore
+//! let hook = ResponseHookMiddleware::new(|mut response: Response| {
+//!     if response.status.is_success() && response.body.starts_with(b"<h1>Not found") {
+//!         return None;
+//!     }
+//!     let base = format!("<base href=\"{}\">", response.url);
+//!     let mut body = base.into_bytes();
+//!     body.extend_from_slice(&response.body);
+//!     response.body = body.into();
+//!     Some(response)
+//! });
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(hook.clone())
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Dropped responses are counted in the crawl's `requests_dropped` statistic and by
+//! [`ResponseHookMiddleware::dropped`], and logged at debug level.
+
+use crate::response::ResponseExt;
+use log::debug;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, response::Response};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+type Hook = dyn Fn(Response) -> Option<Response> + Send + Sync;
+
+/// Runs a closure on every response before it is parsed, see the
+/// [module docs](self).
+///
+/// Clones share the closure and the drop counter, so keep a clone to read it after the
+/// crawl.
+#[derive(Clone)]
+pub struct ResponseHookMiddleware {
+    hook: Arc<Hook>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl ResponseHookMiddleware {
+    /// Creates a middleware passing each response through `hook`, which returns the
+    /// response to parse or `None` to drop it.
+    pub fn new(hook: impl Fn(Response) -> Option<Response> + Send + Sync + 'static) -> Self {
+        Self {
+            hook: Arc::new(hook),
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the number of responses dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Debug for ResponseHookMiddleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseHookMiddleware")
+            .field("dropped", &self.dropped())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for ResponseHookMiddleware {
+    fn name(&self) -> &str {
+        "ResponseHookMiddleware"
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        let url = response.url.clone();
+        match (self.hook)(response) {
+            Some(response) => Ok(MiddlewareAction::Continue(response)),
+            None => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                debug!("Dropping the response from {} before parsing", url);
+                Ok(MiddlewareAction::Drop)
+            }
+        }
+    }
+}
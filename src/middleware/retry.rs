@@ -0,0 +1,381 @@
+//! Per-request retry limits and retry policies on top of `RetryMiddleware`.
+//!
+//! [`RetryPolicyMiddleware`] wraps `spider-middleware`'s
+//! [`RetryMiddleware`](spider_middleware::retry::RetryMiddleware), which still decides
+//! which statuses are retried and builds the retried request. The wrapper adds what the
+//! upstream middleware cannot know about. A request's own limit, set with
+//! [`RequestExt::max_retries`], takes precedence over the wrapped `max_retries`:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(RetryPolicyMiddleware::new(RetryMiddleware::new().max_retries(3)))
+//!     .build()
+//!     .await?;
+//!
+//! // In `parse`: this request is retried up to 10 times, whatever the global limit.
+//! output.add_request(Request::new(url).max_retries(10));
+//! ```
+//!
+//! Which responses and errors are retried is configurable. The defaults are
+//! [`DEFAULT_RETRY_STATUS`] and [`DEFAULT_RETRY_ERRORS`]; a middleware built with
+//! [`RetryPolicyMiddleware::new`] keeps the statuses of the one it wraps. A crawl rotating
+//! proxies can also retry `403`, and no status is retried unless it is listed:
+//!
+//! ```rust,ignore
+//! let retry = RetryPolicyMiddleware::default()
+//!     .retry_on_status(&[403, 429, 502, 503])
+//!     .retry_on_errors(&[RetryErrorKind::Timeout, RetryErrorKind::ConnectionReset]);
+//! ```
+//!
+//! The delay between attempts is the wrapped middleware's exponential backoff unless a
+//! [`BackoffStrategy`] is set: fixed, exponential, or exponential with random jitter, so
+//! clients failing together do not retry together. A `Retry-After` header on a 429 or
+//! 503 response, in seconds or as an HTTP date, replaces the computed delay. Either way
+//! the delay is at most `max_delay`, and the engine waits it out before re-enqueueing the
+//! request:
+//!
+//! ```rust,ignore
+//! let retry = RetryPolicyMiddleware::default()
+//!     .with_backoff(BackoffStrategy::ExponentialJitter(Duration::from_millis(500)))
+//!     .max_delay(Duration::from_secs(60));
+//! ```
+//!
+//! A retried request keeps the method and body it was built with, see
+//! [`crate::request`]. Placeholder responses for failed downloads, see
+//! [`crate::callback`], are retried like transport errors, when their error is of one of
+//! the retried kinds. Once the retries of a request with an errback are exhausted, its
+//! response is passed on marked as failed, so the errback is called, instead of being
+//! dropped.
+
+use crate::callback::{FAILURE_ERROR_KEY, FAILURE_KEY};
+use crate::dead_letter::DeadLetterSink;
+use crate::downloader::is_reset;
+use crate::request::{RequestExt, replayed};
+use crate::response::ResponseExt;
+use log::{debug, info, warn};
+use rand::Rng;
+use reqwest::StatusCode;
+use reqwest::header::RETRY_AFTER;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_middleware::retry::RetryMiddleware;
+use spider_util::{
+    error::{ReqwestErrorDetails, SpiderError},
+    request::Request,
+    response::Response,
+};
+use std::time::{Duration, SystemTime};
+
+/// The status codes [`RetryPolicyMiddleware::default`] retries: server errors, timeouts
+/// and rate limiting.
+pub const DEFAULT_RETRY_STATUS: &[u16] = &[500, 502, 503, 504, 408, 429];
+
+/// The transport errors retried by default.
+pub const DEFAULT_RETRY_ERRORS: &[RetryErrorKind] =
+    &[RetryErrorKind::Connect, RetryErrorKind::Timeout];
+
+/// A kind of transport error that can be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryErrorKind {
+    /// The connection could not be established.
+    Connect,
+    /// The request timed out.
+    Timeout,
+    /// The server reset the connection. Only recognized in errors from
+    /// [`HttpDownloader`](crate::downloader::HttpDownloader), see
+    /// [`is_connection_reset`](crate::downloader::is_connection_reset).
+    ConnectionReset,
+}
+
+impl RetryErrorKind {
+    /// Returns whether `details` describe an error of this kind.
+    pub fn matches(&self, details: &ReqwestErrorDetails) -> bool {
+        match self {
+            RetryErrorKind::Connect => details.is_connect,
+            RetryErrorKind::Timeout => details.is_timeout,
+            RetryErrorKind::ConnectionReset => is_reset(details),
+        }
+    }
+}
+
+/// How the delay before a retry grows with the attempts already made.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackoffStrategy {
+    /// Waits the same delay before every retry.
+    Fixed(Duration),
+    /// Waits the base delay before the first retry and doubles it for each one after.
+    Exponential(Duration),
+    /// Waits a random delay between zero and the [`Exponential`](Self::Exponential)
+    /// delay.
+    ExponentialJitter(Duration),
+}
+
+impl BackoffStrategy {
+    /// Returns the delay before a retry once `attempts` retries have been made, without
+    /// a maximum.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        match *self {
+            BackoffStrategy::Fixed(delay) => delay,
+            BackoffStrategy::Exponential(base) => {
+                saturating_secs(exponential_secs(base.as_secs_f64(), attempts))
+            }
+            BackoffStrategy::ExponentialJitter(base) => saturating_secs(
+                exponential_secs(base.as_secs_f64(), attempts) * rand::thread_rng().r#gen::<f64>(),
+            ),
+        }
+    }
+}
+
+fn exponential_secs(base: f64, attempts: u32) -> f64 {
+    base * 2.0f64.powi(attempts.min(64) as i32)
+}
+
+/// Converts `secs` to a duration, saturating at `Duration::MAX` where the conversion
+/// would overflow, so a long run of retries cannot panic.
+fn saturating_secs(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+/// Wraps a [`RetryMiddleware`] to honour per-request retry limits and the policies
+/// described in the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct RetryPolicyMiddleware {
+    /// The wrapped middleware, whose retry statuses, backoff and limits apply.
+    pub inner: RetryMiddleware,
+    /// Transport errors that should trigger a retry.
+    pub retry_errors: Vec<RetryErrorKind>,
+    /// Replaces the wrapped middleware's backoff, if set.
+    pub backoff: Option<BackoffStrategy>,
+    /// Receives requests whose retries are exhausted.
+    pub dead_letter: Option<DeadLetterSink>,
+}
+
+impl Default for RetryPolicyMiddleware {
+    fn default() -> Self {
+        Self::new(RetryMiddleware::default()).retry_on_status(DEFAULT_RETRY_STATUS)
+    }
+}
+
+impl From<RetryMiddleware> for RetryPolicyMiddleware {
+    fn from(inner: RetryMiddleware) -> Self {
+        Self::new(inner)
+    }
+}
+
+impl RetryPolicyMiddleware {
+    /// Wraps `inner`, whose `max_retries` applies to requests without their own limit.
+    pub fn new(inner: RetryMiddleware) -> Self {
+        Self {
+            inner,
+            retry_errors: DEFAULT_RETRY_ERRORS.to_vec(),
+            backoff: None,
+            dead_letter: None,
+        }
+    }
+
+    /// Retries responses with one of `codes`, and no other status. Defaults to
+    /// [`DEFAULT_RETRY_STATUS`], or to the statuses of the middleware passed to
+    /// [`new`](Self::new).
+    pub fn retry_on_status(mut self, codes: &[u16]) -> Self {
+        self.inner.retry_http_codes = codes.to_vec();
+        self
+    }
+
+    /// Retries transport errors of one of `kinds`, and no other error. Defaults to
+    /// [`DEFAULT_RETRY_ERRORS`].
+    pub fn retry_on_errors(mut self, kinds: &[RetryErrorKind]) -> Self {
+        self.retry_errors = kinds.to_vec();
+        self
+    }
+
+    /// Replaces the wrapped middleware's backoff with `backoff`.
+    pub fn with_backoff(mut self, backoff: BackoffStrategy) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Sets the maximum delay between retries, also for delays from `Retry-After`.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.inner.max_delay = max_delay;
+        self
+    }
+
+    /// Records requests in `sink` once their retries are exhausted, see
+    /// [`crate::dead_letter`].
+    pub fn dead_letter(mut self, sink: DeadLetterSink) -> Self {
+        self.dead_letter = Some(sink);
+        self
+    }
+
+    /// Returns the retry limit for `request`: its own limit if set, the wrapped
+    /// middleware's otherwise.
+    pub fn retry_limit(&self, request: &Request) -> u32 {
+        request
+            .max_retries_override()
+            .unwrap_or(self.inner.max_retries)
+    }
+
+    /// Returns the wrapped middleware with the retry limit of `request`.
+    fn limited(&self, request: &Request) -> RetryMiddleware {
+        RetryMiddleware {
+            max_retries: self.retry_limit(request),
+            ..self.inner.clone()
+        }
+    }
+
+    /// Returns the delay before retrying a request that has had `attempts` retries:
+    /// `retry_after` if the server sent one, the backoff strategy's delay if one is
+    /// set, and `upstream` otherwise, capped at `max_delay`.
+    fn delay(&self, attempts: u32, upstream: Duration, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .or_else(|| self.backoff.map(|backoff| backoff.delay(attempts)))
+            .unwrap_or(upstream)
+            .min(self.inner.max_delay)
+    }
+
+    /// Returns whether `details` describe a transport error of a kind that is retried.
+    fn retries_error(&self, details: &ReqwestErrorDetails) -> bool {
+        self.retry_errors.iter().any(|kind| kind.matches(details))
+    }
+
+    /// Records that `request` will not be retried again.
+    fn give_up(&self, request: &Request, reason: &str) {
+        warn!(
+            "Max retries ({}) reached for {} ({}). Dropping request.",
+            self.retry_limit(request),
+            request.url,
+            reason
+        );
+        if let Some(sink) = &self.dead_letter {
+            sink.record(request, reason);
+        }
+    }
+
+    /// Retries a request that failed in transport, which the wrapped middleware only
+    /// does for some kinds of errors.
+    fn retry_failed(&self, mut request: Request, reason: &str) -> Option<(Box<Request>, Duration)> {
+        let attempts = request.get_retry_attempts();
+        if attempts >= self.retry_limit(&request) {
+            self.give_up(&request, reason);
+            return None;
+        }
+        request.increment_retry_attempts();
+        let backoff = exponential_secs(self.inner.backoff_factor, attempts);
+        let delay = self.delay(attempts, saturating_secs(backoff), None);
+        info!(
+            "Retrying {} ({}, attempt {}/{}) after {:?}",
+            request.url,
+            reason,
+            attempts + 1,
+            self.retry_limit(&request),
+            delay
+        );
+        Some((Box::new(request), delay))
+    }
+}
+
+/// Returns the delay a 429 or 503 response asks for in its `Retry-After` header, either
+/// a number of seconds or an HTTP date. A date in the past means no delay.
+fn retry_after(response: &Response) -> Option<Duration> {
+    if !matches!(
+        response.status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+    let value = response.headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            date.duration_since(SystemTime::now()).unwrap_or_default()
+        }
+    };
+    debug!("{} asks to retry after {:?}", response.url, delay);
+    Some(delay)
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for RetryPolicyMiddleware {
+    fn name(&self) -> &str {
+        "RetryPolicyMiddleware"
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if let Some(reason) = response.failure() {
+            let retryable = response
+                .failure_error()
+                .is_some_and(|details| self.retries_error(&details));
+            let request = response.replay_request();
+            request.meta.remove(FAILURE_KEY);
+            request.meta.remove(FAILURE_ERROR_KEY);
+            let has_errback = request.errback_name().is_some();
+            let retried = if retryable {
+                self.retry_failed(request, &reason)
+            } else {
+                debug!("Not retrying {} ({})", response.url, reason);
+                None
+            };
+            return Ok(match retried {
+                Some((request, delay)) => MiddlewareAction::Retry(request, delay),
+                None if has_errback => {
+                    response.meta.insert(FAILURE_KEY.into(), reason.into());
+                    MiddlewareAction::Continue(response)
+                }
+                None => MiddlewareAction::Drop,
+            });
+        }
+
+        if !self
+            .inner
+            .retry_http_codes
+            .contains(&response.status.as_u16())
+        {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+
+        let reason = format!("status: {}", response.status);
+        let request = response.replay_request();
+        let attempts = request.get_retry_attempts();
+        if attempts >= self.retry_limit(&request) {
+            self.give_up(&request, &reason);
+            if request.errback_name().is_none() {
+                return Ok(MiddlewareAction::Drop);
+            }
+            response.meta.insert(FAILURE_KEY.into(), reason.into());
+            return Ok(MiddlewareAction::Continue(response));
+        }
+
+        let retry_after = retry_after(&response);
+        let mut limited = self.limited(&request);
+        Ok(
+            match Middleware::<C>::process_response(&mut limited, response).await? {
+                MiddlewareAction::Retry(request, delay) => MiddlewareAction::Retry(
+                    Box::new(replayed(*request)),
+                    self.delay(attempts, delay, retry_after),
+                ),
+                action => action,
+            },
+        )
+    }
+
+    async fn handle_error(
+        &mut self,
+        request: &Request,
+        error: &SpiderError,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        match error {
+            SpiderError::ReqwestError(details) if self.retries_error(details) => {
+                let reason = format!("error: {}", details.message);
+                Ok(match self.retry_failed(request.clone(), &reason) {
+                    Some((request, delay)) => MiddlewareAction::Retry(request, delay),
+                    None => MiddlewareAction::Drop,
+                })
+            }
+            _ => Err(error.clone()),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Middleware obeying `robots.txt` with a tunable, persistent cache.
+//!
+//! [`RobotsCacheMiddleware`] checks every request against its origin's `robots.txt` like
+//! the engine's `RobotsTxtMiddleware`, and adds what polite broad crawls need on top:
+//!
+//! - Failed fetches, network errors and `5xx` answers, are handled by a
+//!   [`RobotsFailurePolicy`] and cached for a shorter TTL, so an unreachable host is not
+//!   asked again for every request.
+//! - [`RobotsCacheMiddleware::preload`] fetches the files of known origins before the
+//!   crawl, so the first request to each is not held up by a cold fetch.
+//! - [`RobotsCacheMiddleware::save`] and [`RobotsCacheMiddleware::load`] keep the cache
+//!   on disk between runs.
+//! - [`RobotsCacheMiddleware::hits`] and [`RobotsCacheMiddleware::misses`] count lookups.
+//!
+//! ```rust,ignore
+//! let robots = RobotsCacheMiddleware::new()
+//!     .ttl(Duration::from_secs(6 * 60 * 60))
+//!     .load("robots-cache.json")?;
+//! robots.preload(&reqwest::Client::new(), &start_urls).await;
+//!
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(robots.clone())
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//! robots.save("robots-cache.json")?;
+//! ```
+//!
+//! A `4xx` answer means the site has no `robots.txt` and everything is allowed. Requests
+//! are matched with their `User-Agent` header, or as `*` without one. A disallowed
+//! request fails with [`SpiderError::BlockedByRobotsTxt`]. Requests marked with
+//! [`RequestExt::impolite`] are not checked.
+
+use crate::request::RequestExt;
+use log::{debug, warn};
+use robotstxt::DefaultMatcher;
+use serde::{Deserialize, Serialize};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, http_client::HttpClient, request::Request};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// How long a fetched `robots.txt` is used by default.
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a failed fetch is remembered by default.
+const DEFAULT_FAILURE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Timeout for fetching one `robots.txt` by default.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do with requests to an origin whose `robots.txt` could not be fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RobotsFailurePolicy {
+    /// Allow every request, like a missing `robots.txt`.
+    #[default]
+    Allow,
+    /// Block every request until the fetch is retried.
+    Deny,
+}
+
+/// A cached `robots.txt`, as saved to disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// The file's content, or `None` if fetching it failed.
+    body: Option<Arc<String>>,
+    /// Seconds since the Unix epoch when the file was fetched.
+    fetched_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct RobotsState {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Checks requests against `robots.txt`, see the [module docs](self).
+///
+/// Clones share the cache and the counters.
+#[derive(Debug, Clone)]
+pub struct RobotsCacheMiddleware {
+    ttl: Duration,
+    failure_ttl: Duration,
+    on_failure: RobotsFailurePolicy,
+    request_timeout: Duration,
+    state: Arc<RobotsState>,
+}
+
+impl Default for RobotsCacheMiddleware {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_TTL,
+            failure_ttl: DEFAULT_FAILURE_TTL,
+            on_failure: RobotsFailurePolicy::default(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            state: Arc::default(),
+        }
+    }
+}
+
+impl RobotsCacheMiddleware {
+    /// Creates a middleware with an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses a fetched `robots.txt` for `ttl` before fetching it again. Defaults to one
+    /// day.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Retries a failed fetch after `ttl`. Defaults to five minutes.
+    pub fn failure_ttl(mut self, ttl: Duration) -> Self {
+        self.failure_ttl = ttl;
+        self
+    }
+
+    /// Sets what happens to requests while an origin's `robots.txt` cannot be fetched.
+    /// Defaults to [`RobotsFailurePolicy::Allow`].
+    pub fn on_failure(mut self, policy: RobotsFailurePolicy) -> Self {
+        self.on_failure = policy;
+        self
+    }
+
+    /// Sets the timeout for fetching one `robots.txt`. Defaults to five seconds.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Fills the cache from the file at `path` written by [`save`](Self::save). A missing
+    /// file leaves the cache empty. Entries older than their TTL are fetched again when
+    /// first needed.
+    pub fn load(self, path: impl AsRef<Path>) -> Result<Self, SpiderError> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(self),
+            Err(e) => return Err(e.into()),
+        };
+        let entries: HashMap<String, CacheEntry> = serde_json::from_slice(&data)
+            .map_err(|e| SpiderError::JsonError(format!("robots.txt cache: {}", e)))?;
+        self.state
+            .entries
+            .lock()
+            .expect("robots cache poisoned")
+            .extend(entries);
+        Ok(self)
+    }
+
+    /// Writes the cache to `path`, see [`load`](Self::load).
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SpiderError> {
+        let data = {
+            let entries = self.state.entries.lock().expect("robots cache poisoned");
+            serde_json::to_vec(&*entries).map_err(|e| SpiderError::JsonError(e.to_string()))?
+        };
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Fetches the `robots.txt` of the origin of every URL in `urls` that is not cached
+    /// yet, one after another.
+    pub async fn preload<C: HttpClient>(&self, client: &C, urls: &[Url]) {
+        for url in urls {
+            let Some(origin) = origin_of(url) else {
+                continue;
+            };
+            if self.cached(&origin).is_none() {
+                let entry = self.fetch(client, &origin).await;
+                self.insert(origin, entry);
+            }
+        }
+    }
+
+    /// Returns how many requests were checked against a cached `robots.txt`.
+    pub fn hits(&self) -> u64 {
+        self.state.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many requests needed their origin's `robots.txt` fetched.
+    pub fn misses(&self) -> u64 {
+        self.state.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the fresh cache entry for `origin`, if there is one.
+    fn cached(&self, origin: &str) -> Option<CacheEntry> {
+        let entries = self.state.entries.lock().expect("robots cache poisoned");
+        let entry = entries.get(origin)?;
+        let ttl = if entry.body.is_some() {
+            self.ttl
+        } else {
+            self.failure_ttl
+        };
+        (now_secs().saturating_sub(entry.fetched_at) < ttl.as_secs()).then(|| entry.clone())
+    }
+
+    fn insert(&self, origin: String, entry: CacheEntry) {
+        self.state
+            .entries
+            .lock()
+            .expect("robots cache poisoned")
+            .insert(origin, entry);
+    }
+
+    async fn fetch<C: HttpClient>(&self, client: &C, origin: &str) -> CacheEntry {
+        let robots_url = format!("{}/robots.txt", origin);
+        debug!("Fetching robots.txt from {}", robots_url);
+        let body = match client.get_text(&robots_url, self.request_timeout).await {
+            Ok((status, body)) if status.is_success() => {
+                Some(Arc::new(String::from_utf8_lossy(&body).into_owned()))
+            }
+            Ok((status, _)) if status.is_client_error() => {
+                debug!(
+                    "robots.txt {} returned {}, allowing all",
+                    robots_url, status
+                );
+                Some(Arc::new(String::new()))
+            }
+            Ok((status, _)) => {
+                warn!("robots.txt {} returned {}", robots_url, status);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to fetch robots.txt {}: {}", robots_url, e);
+                None
+            }
+        };
+        CacheEntry {
+            body,
+            fetched_at: now_secs(),
+        }
+    }
+}
+
+fn origin_of(url: &Url) -> Option<String> {
+    let origin = url.origin();
+    origin.is_tuple().then(|| origin.unicode_serialization())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[async_trait]
+impl<C: HttpClient> Middleware<C> for RobotsCacheMiddleware {
+    fn name(&self) -> &str {
+        "RobotsCacheMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.is_impolite() {
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        let Some(origin) = origin_of(&request.url) else {
+            return Ok(MiddlewareAction::Continue(request));
+        };
+        let entry = match self.cached(&origin) {
+            Some(entry) => {
+                self.state.hits.fetch_add(1, Ordering::Relaxed);
+                entry
+            }
+            None => {
+                self.state.misses.fetch_add(1, Ordering::Relaxed);
+                let entry = self.fetch(client, &origin).await;
+                self.insert(origin, entry.clone());
+                entry
+            }
+        };
+
+        let allowed = match &entry.body {
+            Some(body) => {
+                let user_agent = request
+                    .headers
+                    .get(reqwest::header::USER_AGENT)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("*");
+                DefaultMatcher::default().one_agent_allowed_by_robots(
+                    body,
+                    user_agent,
+                    request.url.as_str(),
+                )
+            }
+            None => self.on_failure == RobotsFailurePolicy::Allow,
+        };
+        if !allowed {
+            debug!("Blocked by robots.txt: {}", request.url);
+            return Err(SpiderError::BlockedByRobotsTxt);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
@@ -0,0 +1,191 @@
+//! Middleware dropping soft 404s, error pages served with a success status.
+//!
+//! Many sites answer a missing page with `200 OK` and a "not found" page, which the
+//! spider would scrape like any other. A [`Soft404Middleware`] checks the body of every
+//! `2xx` response in the response phase and drops the ones a [`Soft404Matcher`] matches,
+//! before they reach `parse`. The text of a "not found" page differs from site to site,
+//! so matchers can be set per domain:
+//!
+//! ```rust,ignore
+//! let soft_404s = Soft404Middleware::new()
+//!     .matcher(Soft404Matcher::contains("Page not found"))
+//!     .domain("shop.example", Soft404Matcher::contains("no longer available").within("h1")?)
+//!     .domain("example.de", Soft404Matcher::regex(r"(?i)seite nicht gefunden")?);
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(soft_404s.clone())
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Domains match like the allowed domains of
+//! [`OffsiteMiddleware`](crate::middleware::offsite::OffsiteMiddleware): a domain matches
+//! itself and its subdomains, `*.example.com` only the subdomains. A response is dropped
+//! when any matcher added with [`matcher`](Soft404Middleware::matcher), or with
+//! [`domain`](Soft404Middleware::domain) for a domain its host matches, finds its
+//! pattern. A matcher scoped with [`within`](Soft404Matcher::within) looks at the text of
+//! the elements matching a CSS selector only, otherwise at the whole decoded body.
+//!
+//! Dropped responses are counted in the crawl's `requests_dropped` statistic and, per
+//! host, by [`Soft404Middleware::dropped_by_domain`], which
+//! [`StatsSnapshot::with_soft_404s`](crate::stats::StatsSnapshot::with_soft_404s) adds to
+//! the crawl statistics. They are logged at debug level.
+
+use crate::response::ResponseExt;
+use crate::utils::{host_matches_domain, normalize_domain_pattern};
+use log::debug;
+use regex::Regex;
+use scraper::Selector;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, response::Response, utils::ToSelector};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Contains(String),
+    Regex(Regex),
+}
+
+/// What marks a page as a soft 404, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Soft404Matcher {
+    pattern: Pattern,
+    within: Option<Selector>,
+}
+
+impl Soft404Matcher {
+    /// Matches pages containing `text`.
+    pub fn contains(text: &str) -> Self {
+        Self {
+            pattern: Pattern::Contains(text.to_string()),
+            within: None,
+        }
+    }
+
+    /// Matches pages in which the regular expression `pattern` finds a match.
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Pattern::Regex(Regex::new(pattern)?),
+            within: None,
+        })
+    }
+
+    /// Looks for the pattern in the text of the elements matching the CSS `selector`
+    /// only, such as `h1` or `title`.
+    pub fn within(mut self, selector: &str) -> Result<Self, SpiderError> {
+        self.within = Some(selector.to_selector()?);
+        Ok(self)
+    }
+
+    /// Returns whether `response` is a page this matcher marks as a soft 404.
+    pub fn matches(&self, response: &Response) -> bool {
+        let text = match &self.within {
+            Some(selector) => {
+                let Ok(html) = response.decoded_html(None) else {
+                    return false;
+                };
+                let texts: Vec<String> = html
+                    .select(selector)
+                    .map(|element| element.text().collect())
+                    .collect();
+                texts.join(" ")
+            }
+            None => response.decoded_text(None),
+        };
+        match &self.pattern {
+            Pattern::Contains(needle) => text.contains(needle.as_str()),
+            Pattern::Regex(regex) => regex.is_match(&text),
+        }
+    }
+}
+
+/// Drops `2xx` responses that are error pages, see the [module docs](self).
+///
+/// Clones share the drop counts, so keep a clone to read them after the crawl.
+#[derive(Debug, Clone, Default)]
+pub struct Soft404Middleware {
+    matchers: Vec<Soft404Matcher>,
+    domains: Vec<(String, Soft404Matcher)>,
+    dropped: Arc<Mutex<BTreeMap<String, usize>>>,
+}
+
+impl Soft404Middleware {
+    /// Creates a middleware without matchers, which drops nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops the responses of every domain that `matcher` matches.
+    pub fn matcher(mut self, matcher: Soft404Matcher) -> Self {
+        self.matchers.push(matcher);
+        self
+    }
+
+    /// Drops the responses from `domain` and its subdomains, or only from the
+    /// subdomains for `*.example.com`, that `matcher` matches.
+    pub fn domain(mut self, domain: &str, matcher: Soft404Matcher) -> Self {
+        self.domains
+            .push((normalize_domain_pattern(domain), matcher));
+        self
+    }
+
+    /// Returns whether `response` is a soft 404: a `2xx` response matched by a matcher
+    /// for its host.
+    pub fn is_soft_404(&self, response: &Response) -> bool {
+        if !response.status.is_success() {
+            return false;
+        }
+        let host = response.url.host_str().unwrap_or_default();
+        self.matchers
+            .iter()
+            .chain(
+                self.domains
+                    .iter()
+                    .filter(|(pattern, _)| host_matches_domain(host, pattern))
+                    .map(|(_, matcher)| matcher),
+            )
+            .any(|matcher| matcher.matches(response))
+    }
+
+    /// Returns the number of responses dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped_by_domain().values().sum()
+    }
+
+    /// Returns the number of responses dropped so far per host.
+    pub fn dropped_by_domain(&self) -> BTreeMap<String, usize> {
+        self.dropped
+            .lock()
+            .expect("soft 404 counts poisoned")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for Soft404Middleware {
+    fn name(&self) -> &str {
+        "Soft404Middleware"
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        if !self.is_soft_404(&response) {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        debug!("Dropping soft 404 {}", response.url);
+        let host = response.url.host_str().unwrap_or_default().to_string();
+        *self
+            .dropped
+            .lock()
+            .expect("soft 404 counts poisoned")
+            .entry(host)
+            .or_default() += 1;
+        Ok(MiddlewareAction::Drop)
+    }
+}
@@ -0,0 +1,90 @@
+//! Middleware dropping requests with over-long URLs.
+//!
+//! Broken or adversarial pages sometimes produce URLs of many kilobytes, for instance by
+//! appending to a relative link on every visit. [`UrlLengthMiddleware`] drops requests
+//! whose URL is longer than a limit before they are sent. Dropped requests are counted in
+//! the crawl's `requests_dropped` statistic and by [`UrlLengthMiddleware::dropped`], and
+//! logged at debug level.
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .max_url_length(4096)
+//!     .build()
+//!     .await?;
+//! ```
+
+use log::debug;
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, request::Request};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// URL length limit used by [`UrlLengthMiddleware::new`].
+pub const DEFAULT_MAX_URL_LENGTH: usize = 2048;
+
+/// Drops requests whose URL is longer than a limit.
+///
+/// Clones share the drop counter, so keep a clone to read it after the crawl.
+#[derive(Debug, Clone)]
+pub struct UrlLengthMiddleware {
+    max_length: usize,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Default for UrlLengthMiddleware {
+    fn default() -> Self {
+        Self::with_max_length(DEFAULT_MAX_URL_LENGTH)
+    }
+}
+
+impl UrlLengthMiddleware {
+    /// Creates a middleware allowing URLs of up to 2048 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a middleware allowing URLs of up to `max_length` bytes.
+    pub fn with_max_length(max_length: usize) -> Self {
+        Self {
+            max_length,
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the URL length limit in bytes.
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Returns the number of requests dropped so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for UrlLengthMiddleware {
+    fn name(&self) -> &str {
+        "UrlLengthMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        let length = request.url.as_str().len();
+        if length > self.max_length {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Dropping request with a {}-byte URL (limit {}): {:.200}...",
+                length,
+                self.max_length,
+                request.url.as_str()
+            );
+            return Ok(MiddlewareAction::Drop);
+        }
+        Ok(MiddlewareAction::Continue(request))
+    }
+}
@@ -0,0 +1,95 @@
+//! Middleware checking responses against their request's validators.
+//!
+//! See [`crate::validate`] for the validators and how failures are handled.
+
+use crate::request::RequestExt;
+use crate::response::ResponseExt;
+use crate::validate::VALIDATION_ERROR_KEY;
+use log::{info, warn};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, response::Response};
+use std::time::Duration;
+
+/// Retries responses failing their request's validators, then marks them.
+#[derive(Debug, Clone)]
+pub struct ValidationMiddleware {
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl Default for ValidationMiddleware {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ValidationMiddleware {
+    /// Creates a middleware retrying a failing response twice, one second apart.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how often a request is retried without a per-request limit, see
+    /// [`RequestExt::max_retries`]. `0` marks failing responses right away.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before a failing request is retried.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for ValidationMiddleware {
+    fn name(&self) -> &str {
+        "ValidationMiddleware"
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        let mut request = response.replay_request();
+        let Some(failure) = request
+            .validators()
+            .iter()
+            .find_map(|validator| validator.check(&response).err())
+        else {
+            return Ok(MiddlewareAction::Continue(response));
+        };
+
+        let attempts = request.get_retry_attempts();
+        let limit = request.max_retries_override().unwrap_or(self.max_retries);
+        if attempts < limit {
+            info!(
+                "Response from {} failed validation ({}), retrying (attempt {}/{})",
+                response.url,
+                failure,
+                attempts + 1,
+                limit
+            );
+            request.increment_retry_attempts();
+            return Ok(MiddlewareAction::Retry(Box::new(request), self.retry_delay));
+        }
+
+        warn!(
+            "Response from {} failed validation: {}",
+            response.url, failure
+        );
+        response
+            .meta
+            .insert(VALIDATION_ERROR_KEY.into(), failure.into());
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
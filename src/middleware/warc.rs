@@ -0,0 +1,259 @@
+//! Middleware archiving requests and responses to a WARC file.
+//!
+//! [`WarcWriterMiddleware`] writes a WARC/1.1 `request` record for every request it
+//! passes on and a `response` record for every response it sees, linked through
+//! `WARC-Concurrent-To`, after a leading `warcinfo` record. The resulting file can be
+//! replayed or indexed by standard WARC tools.
+//!
+//! This module is only available with the `middleware-warc` feature, which pulls in the
+//! `warc` crate.
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_middleware(RetryMiddleware::new())
+//!     .add_middleware(WarcWriterMiddleware::new("crawl.warc")?)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Add it after the other middlewares, so request records hold the request as it is
+//! finally sent and response records hold the response as it arrived. Headers added by
+//! the HTTP client itself, such as `Accept-Encoding`, are not visible to middlewares and
+//! are not recorded. Response bodies are stored decoded, so `Content-Encoding` and
+//! `Transfer-Encoding` are dropped from the recorded headers and `Content-Length`
+//! matches the stored body.
+
+use crate::response::ResponseExt;
+use crate::stream::BODY_TRUNCATED_KEY;
+use log::warn;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH, HeaderMap, TRANSFER_ENCODING};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{
+    error::SpiderError,
+    request::{Body, Request},
+    response::Response,
+};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use url::Url;
+use warc::{BufferedBody, Record, RecordType, TruncatedType, WarcHeader, WarcWriter};
+
+/// Request meta key holding the record ID of the request's WARC record, so the response
+/// record can refer to it.
+pub const WARC_REQUEST_ID_KEY: &str = "warc_request_id";
+
+const WARC_VERSION: &str = "1.1";
+
+/// Writes every request and response of a crawl to a WARC file.
+pub struct WarcWriterMiddleware {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl WarcWriterMiddleware {
+    /// Creates the WARC file at `path`, replacing any existing file.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, SpiderError> {
+        let file = File::create(path)?;
+        Self::from_writer(BufWriter::new(file))
+    }
+
+    /// Writes the WARC records to `writer`.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> Result<Self, SpiderError> {
+        let middleware = Self {
+            writer: Mutex::new(Box::new(writer)),
+        };
+        let mut info = Record::<BufferedBody>::with_body(format!(
+            "software: spider-lib/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        ));
+        info.set_warc_version(WARC_VERSION);
+        info.set_warc_type(RecordType::WarcInfo);
+        set_header(
+            &mut info,
+            WarcHeader::ContentType,
+            "application/warc-fields",
+        );
+        middleware.write(&info)?;
+        Ok(middleware)
+    }
+
+    /// Writes `record` and flushes it, so an interrupted crawl leaves complete records.
+    fn write(&self, record: &Record<BufferedBody>) -> io::Result<()> {
+        let mut writer = self.writer.lock().expect("WARC writer poisoned");
+        WarcWriter::new(WriteAll(&mut *writer)).write(record)?;
+        writer.flush()
+    }
+}
+
+/// `WarcWriter` uses `write` rather than `write_all`; this adapter makes every write
+/// complete.
+struct WriteAll<W>(W);
+
+impl<W: Write> Write for WriteAll<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for WarcWriterMiddleware {
+    fn name(&self) -> &str {
+        "WarcWriterMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        let mut record = Record::<BufferedBody>::with_body(request_block(&request));
+        record.set_warc_version(WARC_VERSION);
+        record.set_warc_type(RecordType::Request);
+        set_header(&mut record, WarcHeader::TargetURI, request.url.as_str());
+        set_header(
+            &mut record,
+            WarcHeader::ContentType,
+            "application/http;msgtype=request",
+        );
+
+        if let Err(e) = self.write(&record) {
+            warn!(
+                "Failed to write WARC request record for {}: {}",
+                request.url, e
+            );
+            return Ok(MiddlewareAction::Continue(request));
+        }
+        Ok(MiddlewareAction::Continue(
+            request.with_meta(WARC_REQUEST_ID_KEY, record.warc_id().into()),
+        ))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.is_failed_download() {
+            return Ok(MiddlewareAction::Continue(response));
+        }
+        let mut record = Record::<BufferedBody>::with_body(response_block(&response));
+        record.set_warc_version(WARC_VERSION);
+        record.set_warc_type(RecordType::Response);
+        set_header(&mut record, WarcHeader::TargetURI, response.url.as_str());
+        set_header(
+            &mut record,
+            WarcHeader::ContentType,
+            "application/http;msgtype=response",
+        );
+        if let Some(request_id) = response
+            .meta
+            .get(WARC_REQUEST_ID_KEY)
+            .and_then(|id| id.as_str().map(str::to_owned))
+        {
+            set_header(&mut record, WarcHeader::ConcurrentTo, request_id);
+        }
+        if response
+            .meta
+            .get(BODY_TRUNCATED_KEY)
+            .is_some_and(|truncated| truncated.as_bool() == Some(true))
+        {
+            record.set_truncated_type(TruncatedType::Length);
+        }
+
+        if let Err(e) = self.write(&record) {
+            warn!(
+                "Failed to write WARC response record for {}: {}",
+                response.url, e
+            );
+        }
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
+
+fn set_header(record: &mut Record<BufferedBody>, header: WarcHeader, value: impl Into<String>) {
+    record
+        .set_header(header, value)
+        .expect("header has no well-formedness check");
+}
+
+/// Formats `request` as an HTTP/1.1 request message.
+fn request_block(request: &Request) -> Vec<u8> {
+    let mut target = request.url.path().to_string();
+    if let Some(query) = request.url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let body: Vec<u8> = match &request.body {
+        Some(Body::Json(value)) => serde_json::to_vec(value).unwrap_or_default(),
+        Some(Body::Form(fields)) => {
+            let pairs: Vec<(String, String)> = fields
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect();
+            url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(pairs)
+                .finish()
+                .into_bytes()
+        }
+        Some(Body::Bytes(bytes)) => bytes.to_vec(),
+        None => Vec::new(),
+    };
+
+    let mut block = format!("{} {} HTTP/1.1\r\n", request.method, target).into_bytes();
+    if !request.headers.contains_key("host") {
+        block.extend_from_slice(format!("host: {}\r\n", host_header(&request.url)).as_bytes());
+    }
+    write_headers(&mut block, &request.headers);
+    if !body.is_empty() && !request.headers.contains_key(CONTENT_LENGTH) {
+        block.extend_from_slice(format!("content-length: {}\r\n", body.len()).as_bytes());
+    }
+    block.extend_from_slice(b"\r\n");
+    block.extend_from_slice(&body);
+    block
+}
+
+/// Formats `response` as an HTTP/1.1 response message with its decoded body.
+fn response_block(response: &Response) -> Vec<u8> {
+    let mut headers = response.headers.clone();
+    headers.remove(CONTENT_ENCODING);
+    headers.remove(TRANSFER_ENCODING);
+    headers.insert(CONTENT_LENGTH, response.body.len().into());
+
+    let status = response.status;
+    let mut block = Vec::with_capacity(response.body.len() + 512);
+    block.extend_from_slice(
+        format!(
+            "HTTP/1.1 {} {}\r\n",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("")
+        )
+        .as_bytes(),
+    );
+    write_headers(&mut block, &headers);
+    block.extend_from_slice(b"\r\n");
+    block.extend_from_slice(&response.body);
+    block
+}
+
+fn write_headers(block: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+}
+
+fn host_header(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    }
+}
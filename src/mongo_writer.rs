@@ -0,0 +1,206 @@
+//! Writing items to a MongoDB collection.
+//!
+//! Document-shaped items fit a document store without a schema. [`MongoWriterPipeline`]
+//! buffers the scraped items and writes each one as a BSON document, in batches,
+//! optionally replacing the document that already has the same key:
+//!
+//! ```rust,ignore
+//! let writer = MongoWriterPipeline::new("mongodb://db:27017", "scrapes", "products")
+//!     .upsert_key("url")
+//!     .batch_size(500)
+//!     .write_concern(WriteConcern::majority());
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_pipeline(writer)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Without an upsert key a batch is sent as one unordered `insertMany`. With one, every
+//! item whose key field is set, and not null, replaces the document with the same key,
+//! or is inserted when there is none; items without the key field are inserted.
+//!
+//! Pipelines have no open hook, so the client is connected when the first batch is
+//! written, and that one client is reused for the rest of the crawl. Use
+//! [`MongoWriterPipeline::from_client`] to share a client configured elsewhere. The
+//! remaining items are written when the pipeline is closed.
+//!
+//! Requires the `pipeline-mongo` feature.
+
+use log::debug;
+use mongodb::bson::{self, Bson, Document, doc};
+use mongodb::options::CollectionOptions;
+use mongodb::{Client, Collection};
+use serde_json::Value;
+use spider_core::{async_trait, tokio};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use tokio::sync::OnceCell;
+
+pub use mongodb::options::WriteConcern;
+
+/// Items per write by default.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// Where the client comes from.
+enum Connection {
+    Uri(String),
+    Client(Client),
+}
+
+/// A pipeline writing items to a MongoDB collection, see the [module docs](self).
+pub struct MongoWriterPipeline<I> {
+    connection: Connection,
+    database: String,
+    collection_name: String,
+    upsert_key: Option<String>,
+    batch_size: usize,
+    write_concern: Option<WriteConcern>,
+    buffer: Mutex<Vec<Value>>,
+    /// The collection, opened when the first batch is written.
+    collection: OnceCell<Collection<Document>>,
+    _item: PhantomData<fn(I)>,
+}
+
+impl<I> MongoWriterPipeline<I> {
+    /// Creates a pipeline writing to `collection` in `database` on the deployment at
+    /// `uri`, a `mongodb://` or `mongodb+srv://` connection string.
+    pub fn new(uri: &str, database: &str, collection: &str) -> Self {
+        Self::with_connection(Connection::Uri(uri.to_string()), database, collection)
+    }
+
+    /// Creates a pipeline writing to `collection` in `database` through `client`.
+    pub fn from_client(client: Client, database: &str, collection: &str) -> Self {
+        Self::with_connection(Connection::Client(client), database, collection)
+    }
+
+    fn with_connection(connection: Connection, database: &str, collection: &str) -> Self {
+        Self {
+            connection,
+            database: database.to_string(),
+            collection_name: collection.to_string(),
+            upsert_key: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            write_concern: None,
+            buffer: Mutex::new(Vec::new()),
+            collection: OnceCell::new(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Replaces the document whose `field` equals the item's, instead of inserting a
+    /// second one.
+    pub fn upsert_key(mut self, field: &str) -> Self {
+        self.upsert_key = Some(field.to_string());
+        self
+    }
+
+    /// Sets how many items are written at once. Defaults to 100.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Sets the write concern of the writes. Defaults to the deployment's.
+    pub fn write_concern(mut self, concern: WriteConcern) -> Self {
+        self.write_concern = Some(concern);
+        self
+    }
+
+    fn buffer(&self) -> std::sync::MutexGuard<'_, Vec<Value>> {
+        self.buffer.lock().expect("mongo writer poisoned")
+    }
+
+    /// Connects on first use and returns the target collection.
+    async fn collection(&self) -> Result<&Collection<Document>, PipelineError> {
+        self.collection
+            .get_or_try_init(|| async {
+                let client = match &self.connection {
+                    Connection::Uri(uri) => {
+                        debug!("Connecting to MongoDB for {}", self.collection_name);
+                        Client::with_uri_str(uri).await.map_err(database_error)?
+                    }
+                    Connection::Client(client) => client.clone(),
+                };
+                let options = CollectionOptions::builder()
+                    .write_concern(self.write_concern.clone())
+                    .build();
+                Ok(client
+                    .database(&self.database)
+                    .collection_with_options(&self.collection_name, options))
+            })
+            .await
+    }
+
+    /// Writes `items`, upserting those that have the upsert key.
+    async fn write(&self, items: Vec<Value>) -> Result<(), PipelineError> {
+        let collection = self.collection().await?;
+        let count = items.len();
+        let mut inserts = Vec::new();
+        for item in items {
+            let document = bson::to_document(&item).map_err(database_error)?;
+            let key = self
+                .upsert_key
+                .as_ref()
+                .and_then(|field| match document.get(field)? {
+                    Bson::Null => None,
+                    value => Some((field, value.clone())),
+                });
+            match key {
+                Some((field, value)) => {
+                    collection
+                        .replace_one(doc! { field.as_str(): value }, document)
+                        .upsert(true)
+                        .await
+                        .map_err(database_error)?;
+                }
+                None => inserts.push(document),
+            }
+        }
+        if !inserts.is_empty() {
+            collection
+                .insert_many(inserts)
+                .ordered(false)
+                .await
+                .map_err(database_error)?;
+        }
+        debug!("Wrote {} items to {}", count, self.collection_name);
+        Ok(())
+    }
+}
+
+fn database_error(err: impl std::fmt::Display) -> PipelineError {
+    PipelineError::DatabaseError(err.to_string())
+}
+
+#[async_trait]
+impl<I: ScrapedItem> Pipeline<I> for MongoWriterPipeline<I> {
+    fn name(&self) -> &str {
+        "MongoWriterPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        let batch = {
+            let mut buffer = self.buffer();
+            buffer.push(item.to_json_value());
+            if buffer.len() < self.batch_size {
+                None
+            } else {
+                Some(std::mem::take(&mut *buffer))
+            }
+        };
+        if let Some(batch) = batch {
+            self.write(batch).await?;
+        }
+        Ok(Some(item))
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        let batch = std::mem::take(&mut *self.buffer());
+        if !batch.is_empty() {
+            self.write(batch).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+//! Declarative pagination for APIs.
+//!
+//! Instead of hand-rolling "next page" logic in every `parse`, describe how the API
+//! paginates with a [`Pagination`] strategy and let [`Paginate::paginate`] enqueue the
+//! next request:
+//!
+//! ```rust,ignore
+//! let mut output = ParseOutput::new();
+//! output.paginate(&response, &Pagination::Cursor { field: "/meta/next_cursor", param: "cursor" });
+//! ```
+//!
+//! APIs that link their pages from the `Link` header use
+//! `output.paginate(&response, &Pagination::LinkHeader)`. Paths into the JSON body are
+//! either JSON pointers (`/meta/next_cursor`) or dotted paths (`meta.next_cursor`).
+
+use crate::json::lookup;
+use crate::response::ResponseExt;
+use reqwest::header::{HeaderMap, LINK};
+use serde_json::Value;
+use spider_util::{item::ParseOutput, request::Request, response::Response};
+use url::Url;
+
+/// How an API exposes its next page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pagination {
+    /// Increments a page-number query parameter (`?page=1`, `?page=2`, ...).
+    ///
+    /// A request without the parameter counts as page 1. Pagination stops once the array
+    /// at `results` is missing or empty.
+    PageNumber {
+        param: &'static str,
+        results: &'static str,
+    },
+    /// Advances an offset query parameter by `step` (`?offset=0`, `?offset=20`, ...).
+    ///
+    /// A request without the parameter counts as offset 0. Pagination stops once the array
+    /// at `results` is missing or empty.
+    Offset {
+        param: &'static str,
+        step: u64,
+        results: &'static str,
+    },
+    /// Follows a cursor read from the JSON body at `field`.
+    ///
+    /// Cursors that are absolute `http(s)` URLs are followed as they are; other cursors,
+    /// including ones that start with `/`, are sent in the `param` query parameter.
+    /// Pagination stops when the cursor is missing, `null`, `false` or empty.
+    Cursor {
+        field: &'static str,
+        param: &'static str,
+    },
+    /// Follows the `rel="next"` link of the `Link` response header (RFC 8288), as used by
+    /// the GitHub API. The body does not need to be JSON.
+    ///
+    /// Pagination stops when there is no `next` link.
+    LinkHeader,
+}
+
+impl Pagination {
+    /// Computes the URL of the page following `response`.
+    ///
+    /// Returns `None` when there is no next page, when the next page number or offset
+    /// would overflow, and when the strategy points back at the current page, which would
+    /// loop forever. Bodies that are not valid JSON end the JSON-based strategies with a
+    /// warning.
+    pub fn next_url(&self, response: &Response) -> Option<Url> {
+        let current = &response.url;
+        let next = if let Pagination::LinkHeader = self {
+            next_link(&response.headers).and_then(|link| current.join(&link).ok())?
+        } else {
+            let body = match response.json_value() {
+                Ok(body) => body,
+                Err(err) => {
+                    log::warn!("Cannot paginate {}: {}", current, err);
+                    return None;
+                }
+            };
+            self.next_from_body(current, &body)?
+        };
+
+        (next != *current).then_some(next)
+    }
+
+    fn next_from_body(&self, current: &Url, body: &Value) -> Option<Url> {
+        match self {
+            Pagination::PageNumber { param, results } => {
+                if !has_results(body, results) {
+                    return None;
+                }
+                let page = query_number(current, param).unwrap_or(1).checked_add(1)?;
+                Some(with_query_param(current, param, &page.to_string()))
+            }
+            Pagination::Offset {
+                param,
+                step,
+                results,
+            } => {
+                if !has_results(body, results) {
+                    return None;
+                }
+                let offset = query_number(current, param)
+                    .unwrap_or(0)
+                    .checked_add(*step)?;
+                Some(with_query_param(current, param, &offset.to_string()))
+            }
+            Pagination::Cursor { field, param } => match lookup(body, field)? {
+                Value::String(cursor) if cursor.is_empty() => None,
+                Value::String(cursor) => match Url::parse(cursor) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https") => Some(url),
+                    _ => Some(with_query_param(current, param, cursor)),
+                },
+                Value::Number(cursor) => {
+                    Some(with_query_param(current, param, &cursor.to_string()))
+                }
+                _ => None,
+            },
+            Pagination::LinkHeader => None,
+        }
+    }
+}
+
+/// Adds pagination support to [`ParseOutput`].
+pub trait Paginate {
+    /// Enqueues the page following `response` according to `pagination`, see
+    /// [`Pagination::next_url`].
+    ///
+    /// Returns `true` if a request for the next page was added.
+    fn paginate(&mut self, response: &Response, pagination: &Pagination) -> bool;
+}
+
+impl<I> Paginate for ParseOutput<I> {
+    fn paginate(&mut self, response: &Response, pagination: &Pagination) -> bool {
+        match pagination.next_url(response) {
+            Some(next) => {
+                self.add_request(Request::new(next));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn has_results(body: &Value, path: &str) -> bool {
+    match lookup(body, path) {
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::Object(fields)) => !fields.is_empty(),
+        _ => false,
+    }
+}
+
+fn query_number(url: &Url, param: &str) -> Option<u64> {
+    url.query_pairs()
+        .find(|(key, _)| key == param)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Returns `url` with the query parameter `param` set to `value`, keeping other parameters.
+fn with_query_param(url: &Url, param: &str, value: &str) -> Url {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    match pairs.iter_mut().find(|(key, _)| key == param) {
+        Some(pair) => pair.1 = value.to_string(),
+        None => pairs.push((param.to_string(), value.to_string())),
+    }
+
+    let mut next = url.clone();
+    next.query_pairs_mut().clear().extend_pairs(pairs);
+    next
+}
+
+/// Returns the target of the first `rel="next"` link in the `Link` headers.
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| {
+            parse_link_header(value)
+                .into_iter()
+                .find(|(_, rels)| rels.iter().any(|rel| rel.eq_ignore_ascii_case("next")))
+                .map(|(target, _)| target)
+        })
+}
+
+/// Splits a `Link` header value into link targets and their `rel` values.
+fn parse_link_header(value: &str) -> Vec<(String, Vec<String>)> {
+    let mut links = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let target = rest[start + 1..start + end].trim().to_string();
+        rest = &rest[start + end + 1..];
+
+        // Parameters run up to the next comma outside a quoted string.
+        let mut in_quotes = false;
+        let params_end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                }
+                c == ',' && !in_quotes
+            })
+            .map_or(rest.len(), |(index, _)| index);
+        let rels = rest[..params_end]
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("rel"))
+            .flat_map(|(_, rel)| {
+                rel.trim()
+                    .trim_matches('"')
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        links.push((target, rels));
+        rest = &rest[params_end..];
+    }
+    links
+}
@@ -0,0 +1,81 @@
+//! Text extraction from PDF documents.
+//!
+//! This module is only available with the `pdf` feature, which pulls in the
+//! `pdf-extract` crate.
+
+use pdf_extract::OutputError;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+/// Readers accept the `%PDF-` header anywhere within the first kilobyte of a file.
+const HEADER_SEARCH_LIMIT: usize = 1024;
+
+/// The text content of a PDF document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PdfText {
+    /// The extracted text of each page, in page order.
+    pub pages: Vec<String>,
+}
+
+impl PdfText {
+    /// Returns the text of the whole document, with pages separated by newlines.
+    pub fn text(&self) -> String {
+        self.pages.join("\n")
+    }
+}
+
+/// Errors that can occur while extracting text from a PDF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PdfError {
+    /// The body does not start with a PDF header.
+    NotPdf,
+    /// The document is encrypted and cannot be opened without a password.
+    Encrypted,
+    /// The document could not be parsed.
+    Malformed(String),
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfError::NotPdf => write!(f, "response body is not a PDF document"),
+            PdfError::Encrypted => write!(f, "PDF document is encrypted"),
+            PdfError::Malformed(reason) => write!(f, "malformed PDF document: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for PdfError {}
+
+/// Extracts the text of a PDF document, page by page.
+///
+/// Encrypted documents that open with an empty password are decrypted transparently;
+/// any other encrypted document yields [`PdfError::Encrypted`]. Malformed documents
+/// return [`PdfError::Malformed`] instead of panicking.
+pub fn extract_pdf_text(bytes: &[u8]) -> Result<PdfText, PdfError> {
+    let header = &bytes[..bytes.len().min(HEADER_SEARCH_LIMIT)];
+    if !header
+        .windows(PDF_MAGIC.len())
+        .any(|window| window == PDF_MAGIC)
+    {
+        return Err(PdfError::NotPdf);
+    }
+
+    // The underlying parser panics on some malformed inputs, so contain those as errors.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        pdf_extract::extract_text_from_mem_by_pages(bytes)
+    }));
+
+    match result {
+        Ok(Ok(pages)) => Ok(PdfText { pages }),
+        Ok(Err(OutputError::PdfError(pdf_extract::Error::Decryption(_)))) => {
+            Err(PdfError::Encrypted)
+        }
+        Ok(Err(err)) => Err(PdfError::Malformed(err.to_string())),
+        Err(_) => Err(PdfError::Malformed(
+            "the PDF parser panicked while reading the document".to_string(),
+        )),
+    }
+}
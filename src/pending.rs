@@ -0,0 +1,156 @@
+//! Bounding the responses waiting to be parsed.
+//!
+//! When downloads outpace the parse workers, downloaded responses queue up with their
+//! whole bodies in memory. [`PendingParses`] caps how many responses may be downloaded but
+//! not yet parsed. Once the cap is reached, the next downloaded response waits in the
+//! middleware chain until a parse finishes, which holds back further downloads too:
+//!
+//! ```rust,ignore
+//! let pending = PendingParses::new(64);
+//! let crawler = CrawlerBuilder::new(pending.wrap(MySpider))
+//!     .max_pending_parses(&pending)
+//!     .add_middleware(RetryMiddleware::new())
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! A response is counted from the moment it leaves the middlewares until the spider's
+//! `parse` returns. `max_pending_parses` adds [`PendingParses::middleware`]; call it
+//! before adding other middlewares. Response middlewares run in reverse order, so it
+//! then sees each response last, after any middleware that might drop it.
+//!
+//! Unlike the engine's `channel_capacity`, which counts queued messages, this bounds the
+//! responses themselves, wherever they wait. [`PendingParses::pending`] and
+//! [`PendingParses::peak`] report how many are waiting.
+
+use log::trace;
+use spider_core::{Spider, async_trait, tokio};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, response::Response};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Semaphore;
+
+#[derive(Debug)]
+struct PendingState {
+    limit: usize,
+    permits: Semaphore,
+    peak: AtomicUsize,
+}
+
+/// A cap on the responses downloaded but not yet parsed.
+///
+/// Clones share the same count, so the wrapped spider and the middleware agree on it.
+#[derive(Debug, Clone)]
+pub struct PendingParses {
+    state: Arc<PendingState>,
+}
+
+impl PendingParses {
+    /// Allows at most `limit` responses to wait for parsing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero.
+    pub fn new(limit: usize) -> Self {
+        assert!(limit > 0, "max pending parses must be at least 1");
+        Self {
+            state: Arc::new(PendingState {
+                limit,
+                permits: Semaphore::new(limit),
+                peak: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the spider with its `parse` calls counted against this cap.
+    pub fn wrap<S: Spider>(&self, spider: S) -> BoundedParses<S> {
+        BoundedParses {
+            spider,
+            pending: self.clone(),
+        }
+    }
+
+    /// Returns the middleware that holds back responses at the cap. Add it to the
+    /// crawler first.
+    pub fn middleware(&self) -> PendingParsesMiddleware {
+        PendingParsesMiddleware {
+            pending: self.clone(),
+        }
+    }
+
+    /// Returns the maximum number of responses waiting for parsing.
+    pub fn limit(&self) -> usize {
+        self.state.limit
+    }
+
+    /// Returns how many responses are waiting for parsing or being parsed.
+    pub fn pending(&self) -> usize {
+        self.state.limit - self.state.permits.available_permits()
+    }
+
+    /// Returns the highest number of responses that waited for parsing at once.
+    pub fn peak(&self) -> usize {
+        self.state.peak.load(Ordering::Relaxed)
+    }
+
+    async fn acquire(&self) {
+        let permit = self
+            .state
+            .permits
+            .acquire()
+            .await
+            .expect("pending parse semaphore is never closed");
+        permit.forget();
+        self.state.peak.fetch_max(self.pending(), Ordering::Relaxed);
+    }
+
+    fn release(&self) {
+        self.state.permits.add_permits(1);
+    }
+}
+
+/// Waits for room under the [`PendingParses`] cap before passing a response on.
+#[derive(Debug, Clone)]
+pub struct PendingParsesMiddleware {
+    pending: PendingParses,
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for PendingParsesMiddleware {
+    fn name(&self) -> &str {
+        "PendingParsesMiddleware"
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if self.pending.pending() >= self.pending.limit() {
+            trace!(
+                "{} responses waiting for parsing, holding back {}",
+                self.pending.pending(),
+                response.url
+            );
+        }
+        self.pending.acquire().await;
+        Ok(MiddlewareAction::Continue(response))
+    }
+}
+
+/// A spider whose responses are counted by a [`PendingParses`] cap until parsed, see
+/// [`PendingParses::wrap`].
+pub struct BoundedParses<S> {
+    spider: S,
+    pending: PendingParses,
+}
+
+impl_spider_wrapper! {
+    impl Spider for BoundedParses<S: Spider> {
+        async fn parse(&self, response, state) {
+            let output = self.spider.parse(response, state).await;
+            self.pending.release();
+            output
+        }
+    }
+}
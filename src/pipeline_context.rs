@@ -0,0 +1,194 @@
+//! Crawl statistics, a run id and configuration for pipelines.
+//!
+//! `Pipeline::process_item` only sees the item. A [`ContextPipeline`] also receives a
+//! [`PipelineContext`] carrying the id of the run, the run's configuration and the
+//! crawl's `StatCollector`, and [`ContextPipeline::with_context`] turns it into a
+//! regular [`Pipeline`] for the builder:
+//!
+//! ```rust,ignore
+//! struct TagRun;
+//!
+//! #[async_trait]
+//! impl ContextPipeline<Product> for TagRun {
+//!     fn name(&self) -> &str {
+//!         "TagRun"
+//!     }
+//!
+//!     async fn process_item(&self, mut item: Product, ctx: &PipelineContext)
+//!         -> Result<Option<Product>, PipelineError> {
+//!         item.run_id = ctx.run_id().to_string();
+//!         Ok(Some(item))
+//!     }
+//! }
+//!
+//! let context = PipelineContext::new().with_config("export", json!("s3://bucket/books"));
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_pipeline(TagRun.with_context(context.clone()))
+//!     .build()
+//!     .await?;
+//! context.attach(&crawler);
+//! ```
+//!
+//! The engine creates the statistics when the crawler is built, after the pipelines are
+//! added, so [`PipelineContext::attach`] hands them to the context afterwards; until
+//! then [`PipelineContext::stats`] returns `None`. Clones of a context share the
+//! statistics, so one context can serve several pipelines. Existing pipelines keep
+//! implementing [`Pipeline`] directly.
+
+use serde_json::{Map, Value};
+use spider_core::{Crawler, Spider, async_trait, stats::StatCollector};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+use std::marker::PhantomData;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a [`ContextPipeline`] knows about the crawl it runs in, see the
+/// [module docs](self).
+#[derive(Debug, Clone)]
+pub struct PipelineContext {
+    run_id: Arc<str>,
+    config: Arc<Map<String, Value>>,
+    stats: Arc<OnceLock<Arc<StatCollector>>>,
+}
+
+impl Default for PipelineContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineContext {
+    /// Creates a context with a run id made of the start time and the process id, and
+    /// no configuration.
+    pub fn new() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        Self {
+            run_id: format!("{:x}-{:x}", millis, std::process::id()).into(),
+            config: Arc::new(Map::new()),
+            stats: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Replaces the generated run id with `run_id`.
+    pub fn with_run_id(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = run_id.into().into();
+        self
+    }
+
+    /// Sets the configuration value `key` to `value`.
+    pub fn with_config(mut self, key: impl Into<String>, value: Value) -> Self {
+        Arc::make_mut(&mut self.config).insert(key.into(), value);
+        self
+    }
+
+    /// Returns the id of the run.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Returns the configuration value `key`, if it was set.
+    pub fn config(&self, key: &str) -> Option<&Value> {
+        self.config.get(key)
+    }
+
+    /// Returns the whole configuration.
+    pub fn config_map(&self) -> &Map<String, Value> {
+        &self.config
+    }
+
+    /// Hands the statistics of `crawler` to this context and its clones. Only the first
+    /// call has an effect.
+    pub fn attach<S, C>(&self, crawler: &Crawler<S, C>)
+    where
+        S: Spider + 'static,
+        S::Item: ScrapedItem,
+        C: Send + Sync + Clone + 'static,
+    {
+        let _ = self.stats.set(crawler.get_stats());
+    }
+
+    /// Returns the statistics of the crawl, once [`attach`](Self::attach) was called.
+    pub fn stats(&self) -> Option<&Arc<StatCollector>> {
+        self.stats.get()
+    }
+}
+
+/// A pipeline that receives a [`PipelineContext`] with every item.
+///
+/// This mirrors [`Pipeline`], with the context passed to `process_item` and `close`.
+#[async_trait]
+pub trait ContextPipeline<I: ScrapedItem>: Send + Sync + 'static {
+    /// Returns the name of the pipeline.
+    fn name(&self) -> &str;
+
+    /// Processes a single item, returning `None` to drop it.
+    async fn process_item(
+        &self,
+        item: I,
+        ctx: &PipelineContext,
+    ) -> Result<Option<I>, PipelineError>;
+
+    /// Called when the crawl is closing.
+    async fn close(&self, _ctx: &PipelineContext) -> Result<(), PipelineError> {
+        Ok(())
+    }
+
+    /// Pairs the pipeline with `context`, giving a [`Pipeline`] for
+    /// `CrawlerBuilder::add_pipeline`.
+    fn with_context(self, context: PipelineContext) -> WithPipelineContext<Self, I>
+    where
+        Self: Sized,
+    {
+        WithPipelineContext {
+            pipeline: self,
+            context,
+            _item: PhantomData,
+        }
+    }
+}
+
+/// A [`ContextPipeline`] together with its context, see
+/// [`ContextPipeline::with_context`].
+pub struct WithPipelineContext<P, I> {
+    pipeline: P,
+    context: PipelineContext,
+    _item: PhantomData<fn(I)>,
+}
+
+impl<P, I> WithPipelineContext<P, I>
+where
+    P: ContextPipeline<I>,
+    I: ScrapedItem,
+{
+    /// Returns the wrapped pipeline.
+    pub fn pipeline(&self) -> &P {
+        &self.pipeline
+    }
+
+    /// Returns the context handed to the pipeline.
+    pub fn context(&self) -> &PipelineContext {
+        &self.context
+    }
+}
+
+#[async_trait]
+impl<P, I> Pipeline<I> for WithPipelineContext<P, I>
+where
+    P: ContextPipeline<I>,
+    I: ScrapedItem,
+{
+    fn name(&self) -> &str {
+        self.pipeline.name()
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        self.pipeline.process_item(item, &self.context).await
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        self.pipeline.close(&self.context).await
+    }
+}
@@ -0,0 +1,306 @@
+//! Writing items to a PostgreSQL table.
+//!
+//! Several crawler instances can share one database. [`PostgresWriterPipeline`] buffers
+//! the scraped items and inserts them in batches through a connection pool, optionally
+//! updating the rows that already exist:
+//!
+//! ```rust,ignore
+//! let writer = PostgresWriterPipeline::new("postgres://crawler@db/scrapes", "products")?
+//!     .conflict_columns(&["url"])
+//!     .batch_size(500);
+//! let crawler = CrawlerBuilder::new(MySpider)
+//!     .add_pipeline(writer)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The columns are the fields of the first item. When the table does not exist it is
+//! created with a column per field, typed from the first non-null value of the first
+//! batch: `TEXT` for strings, `BIGINT` for integers, `DOUBLE PRECISION` for other
+//! numbers, `BOOLEAN` for booleans and `JSONB` for objects, arrays and fields that are
+//! always `null`. With conflict columns the table gets a unique constraint on them, and
+//! a row whose key already exists is updated with the new values (`ON CONFLICT ... DO
+//! UPDATE`); within a batch the last item with a key wins. Fields missing from an item
+//! are inserted as `NULL`, and fields the first item did not have are not written.
+//!
+//! Each batch is sent as a single `INSERT` on a connection from the pool, so batches
+//! flushed by concurrent item tasks do not wait for each other. The remaining items are
+//! written when the pipeline is closed.
+//!
+//! Requires the `pipeline-postgres` feature.
+
+use deadpool_postgres::{Config, Pool, Runtime};
+use log::debug;
+use postgres_native_tls::MakeTlsConnector;
+use serde_json::Value;
+use spider_core::{async_trait, tokio};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use tokio::sync::OnceCell;
+
+/// Items per `INSERT` by default.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// Connections in the pool created by [`PostgresWriterPipeline::new`].
+const DEFAULT_MAX_CONNECTIONS: usize = 8;
+
+/// A column of the target table.
+#[derive(Debug, Clone)]
+struct Column {
+    name: String,
+    sql_type: &'static str,
+}
+
+/// A pipeline writing items to a PostgreSQL table, see the [module docs](self).
+pub struct PostgresWriterPipeline<I> {
+    pool: Pool,
+    table: String,
+    conflict_columns: Vec<String>,
+    batch_size: usize,
+    buffer: Mutex<Vec<Value>>,
+    /// The columns, set up with the table when the first batch is written.
+    columns: OnceCell<Vec<Column>>,
+    _item: PhantomData<fn(I)>,
+}
+
+impl<I> PostgresWriterPipeline<I> {
+    /// Creates a pipeline writing to `table` in the database at `url`, through a pool of
+    /// up to eight connections opened as they are needed.
+    ///
+    /// `url` is a `postgres://` URL or a `key=value` connection string. TLS is used as
+    /// its `sslmode` asks.
+    pub fn new(url: &str, table: &str) -> Result<Self, PipelineError> {
+        let tls = native_tls::TlsConnector::new().map_err(database_error)?;
+        let mut config = Config::new();
+        config.url = Some(url.to_string());
+        config.pool = Some(deadpool_postgres::PoolConfig::new(DEFAULT_MAX_CONNECTIONS));
+        let pool = config
+            .create_pool(Some(Runtime::Tokio1), MakeTlsConnector::new(tls))
+            .map_err(database_error)?;
+        Ok(Self::from_pool(pool, table))
+    }
+
+    /// Creates a pipeline writing to `table` through `pool`.
+    pub fn from_pool(pool: Pool, table: &str) -> Self {
+        Self {
+            pool,
+            table: table.to_string(),
+            conflict_columns: Vec::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            buffer: Mutex::new(Vec::new()),
+            columns: OnceCell::new(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Updates the existing row when an item has the same values in `columns`, instead
+    /// of failing on the duplicate.
+    pub fn conflict_columns(mut self, columns: &[&str]) -> Self {
+        self.conflict_columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Sets how many items are inserted at once. Defaults to 100.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Returns the connection pool.
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    fn buffer(&self) -> std::sync::MutexGuard<'_, Vec<Value>> {
+        self.buffer.lock().expect("postgres writer poisoned")
+    }
+
+    /// Creates the table if needed and returns its columns, taken from `items`.
+    async fn columns(&self, items: &[Value]) -> Result<&[Column], PipelineError> {
+        let columns = self
+            .columns
+            .get_or_try_init(|| async {
+                let columns = infer_columns(items);
+                self.create_table(&columns).await?;
+                Ok::<_, PipelineError>(columns)
+            })
+            .await?;
+        Ok(columns)
+    }
+
+    async fn create_table(&self, columns: &[Column]) -> Result<(), PipelineError> {
+        let mut definitions: Vec<String> = columns
+            .iter()
+            .map(|column| format!("{} {}", quote(&column.name), column.sql_type))
+            .collect();
+        if !self.conflict_columns.is_empty() {
+            definitions.push(format!("UNIQUE ({})", quote_all(&self.conflict_columns)));
+        }
+        let statement = format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            quote(&self.table),
+            definitions.join(", ")
+        );
+        self.execute(&statement, None).await
+    }
+
+    async fn execute(&self, statement: &str, rows: Option<Value>) -> Result<(), PipelineError> {
+        let client = self.pool.get().await.map_err(database_error)?;
+        match rows {
+            Some(rows) => client.execute(statement, &[&rows]).await,
+            None => client.execute(statement, &[]).await,
+        }
+        .map_err(database_error)?;
+        Ok(())
+    }
+
+    /// Inserts `items` with one statement, expanding them from a JSON array on the server.
+    async fn insert(&self, items: Vec<Value>) -> Result<(), PipelineError> {
+        let columns = self.columns(&items).await?;
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let rows = self.dedupe(items);
+        let names: Vec<String> = columns.iter().map(|column| column.name.clone()).collect();
+        let record_type = columns
+            .iter()
+            .map(|column| format!("{} {}", quote(&column.name), column.sql_type))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut statement = format!(
+            "INSERT INTO {table} ({columns}) SELECT {columns} FROM \
+             jsonb_to_recordset($1::jsonb) AS item({record_type})",
+            table = quote(&self.table),
+            columns = quote_all(&names),
+        );
+        if !self.conflict_columns.is_empty() {
+            let updates: Vec<String> = names
+                .iter()
+                .filter(|name| !self.conflict_columns.contains(name))
+                .map(|name| format!("{0} = EXCLUDED.{0}", quote(name)))
+                .collect();
+            statement.push_str(&format!(
+                " ON CONFLICT ({})",
+                quote_all(&self.conflict_columns)
+            ));
+            if updates.is_empty() {
+                statement.push_str(" DO NOTHING");
+            } else {
+                statement.push_str(&format!(" DO UPDATE SET {}", updates.join(", ")));
+            }
+        }
+
+        let count = rows.len();
+        self.execute(&statement, Some(Value::Array(rows))).await?;
+        debug!("Wrote {} items to {}", count, self.table);
+        Ok(())
+    }
+
+    /// Keeps the last item for every conflict key, since one `INSERT` cannot update the
+    /// same row twice.
+    fn dedupe(&self, items: Vec<Value>) -> Vec<Value> {
+        if self.conflict_columns.is_empty() {
+            return items;
+        }
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut rows: Vec<Value> = Vec::with_capacity(items.len());
+        for item in items {
+            let key: Vec<&Value> = self
+                .conflict_columns
+                .iter()
+                .map(|column| item.get(column).unwrap_or(&Value::Null))
+                .collect();
+            let key = Value::from(key.into_iter().cloned().collect::<Vec<_>>()).to_string();
+            match positions.get(&key) {
+                Some(&index) => rows[index] = item,
+                None => {
+                    positions.insert(key, rows.len());
+                    rows.push(item);
+                }
+            }
+        }
+        rows
+    }
+}
+
+/// Returns the columns for the fields of the first item, typed from the first non-null
+/// value in `items`.
+fn infer_columns(items: &[Value]) -> Vec<Column> {
+    let Some(Value::Object(first)) = items.first() else {
+        return Vec::new();
+    };
+    first
+        .keys()
+        .map(|name| {
+            let sample = items
+                .iter()
+                .filter_map(|item| item.get(name))
+                .find(|value| !value.is_null());
+            Column {
+                name: name.clone(),
+                sql_type: sql_type(sample),
+            }
+        })
+        .collect()
+}
+
+fn sql_type(value: Option<&Value>) -> &'static str {
+    match value {
+        Some(Value::String(_)) => "TEXT",
+        Some(Value::Number(number)) if number.is_i64() || number.is_u64() => "BIGINT",
+        Some(Value::Number(_)) => "DOUBLE PRECISION",
+        Some(Value::Bool(_)) => "BOOLEAN",
+        _ => "JSONB",
+    }
+}
+
+/// Quotes an SQL identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn quote_all(identifiers: &[String]) -> String {
+    identifiers
+        .iter()
+        .map(|identifier| quote(identifier))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn database_error(err: impl std::fmt::Display) -> PipelineError {
+    PipelineError::DatabaseError(err.to_string())
+}
+
+#[async_trait]
+impl<I: ScrapedItem> Pipeline<I> for PostgresWriterPipeline<I> {
+    fn name(&self) -> &str {
+        "PostgresWriterPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        let batch = {
+            let mut buffer = self.buffer();
+            buffer.push(item.to_json_value());
+            if buffer.len() < self.batch_size {
+                None
+            } else {
+                Some(std::mem::take(&mut *buffer))
+            }
+        };
+        if let Some(batch) = batch {
+            self.insert(batch).await?;
+        }
+        Ok(Some(item))
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        let batch = std::mem::take(&mut *self.buffer());
+        if !batch.is_empty() {
+            self.insert(batch).await?;
+        }
+        Ok(())
+    }
+}
@@ -27,9 +27,7 @@ pub use spider_core::{
 };
 
 // Re-export ParseOutput and ScrapedItem from spider_util
-pub use spider_util::{
-    item::{ParseOutput, ScrapedItem},
-};
+pub use spider_util::item::{ParseOutput, ScrapedItem};
 
 // Re-export Pipeline from spider_pipeline
 pub use spider_pipeline::pipeline::Pipeline;
@@ -44,6 +42,103 @@ pub use spider_util::{
     utils::{ToSelector, create_dir, is_same_site, normalize_origin, validate_output_dir},
 };
 
+pub use crate::{
+    builder::CrawlerBuilderExt,
+    callback::{CALLBACK_KEY, CallbackSpider, Callbacks, Routed},
+    channel::ChannelPipeline,
+    close_guard::{CloseGuard, PipelineExt},
+    context::{ContextSpider, WithContext},
+    crawl::{CloseReason, ControlPipeline, CrawlControl, CrawlSummary},
+    dead_letter::{DeadLetterSink, RetryFailedExt, read_dead_letters},
+    debug::{DebugFetch, DebugReport},
+    dns::{DnsResolver, DnsStats},
+    downloader::{
+        BufferPool, ConnectionStats, HttpDownloader, HttpDownloaderBuilder, RedirectPolicy,
+    },
+    encoding::{charset_from_content_type, charset_from_meta, decode_body, detect_encoding},
+    event_log::{CrawlEvent, EventLog},
+    export::MultiFormatExporter,
+    extract::{
+        FieldRule, FieldSource, Fields, ItemSelectors, extract_item, extract_items,
+        extract_records, looks_empty,
+    },
+    files::{FilesPipeline, StoredFile},
+    finalize::{FinalizeExt, FinalizeItem, Finalized, ProcessItemSpider, Processed},
+    form::{Form, extract_form},
+    health::{CrawlHealth, HealthMonitor},
+    html::{NotHtml, TextHeuristic},
+    json::{JsonPathError, NotJson, json_links, json_path, select_all},
+    keep_alive::{KeepAlive, KeepAliveMiddleware, KeptAlive},
+    lifecycle::{Lifecycle, LifecycleSpider},
+    links::{FollowTarget, LinkExtractor, follow, follow_links},
+    middleware::{
+        auth::{AuthMiddleware, Credentials},
+        autothrottle::AutoThrottleMiddleware,
+        content_filter::ContentFilterMiddleware,
+        control::ControlMiddleware,
+        dead_letter::DeadLetterMiddleware,
+        depth::{DepthMiddleware, DepthTracked},
+        dupe_filter::{DedupSet, DupeFilterMiddleware, Unmarked},
+        headers::HeadersMiddleware,
+        https_upgrade::HttpsUpgradeMiddleware,
+        jitter::{JitterLimiter, RateLimitJitterExt},
+        offsite::{OffsiteMiddleware, OffsiteMode, OffsiteTracked},
+        path_prefix::PathPrefixMiddleware,
+        politeness::{NoPolitenessMiddleware, Polite},
+        proxy_pool::{ProxyHealth, ProxyPoolMiddleware, ProxySelection},
+        ramp::{ConcurrencyRampMiddleware, RampUpMiddleware},
+        response_hook::ResponseHookMiddleware,
+        retry::{
+            BackoffStrategy, DEFAULT_RETRY_ERRORS, DEFAULT_RETRY_STATUS, RetryErrorKind,
+            RetryPolicyMiddleware,
+        },
+        soft_404::{Soft404Matcher, Soft404Middleware},
+        url_length::UrlLengthMiddleware,
+        validation::ValidationMiddleware,
+    },
+    pagination::{Paginate, Pagination},
+    pending::{BoundedParses, PendingParses, PendingParsesMiddleware},
+    pipeline_context::{ContextPipeline, PipelineContext, WithPipelineContext},
+    request::RequestExt,
+    response::ResponseExt,
+    routing::RoutingPipeline,
+    rules::{Rule, RuleSpider, Ruled},
+    sample::{Sampled, Sampling},
+    scheduler::{
+        DefaultScheduler, DiskScheduler, FairScheduler, PriorityScheduler, RequestScheduler,
+        Scheduled, Scheduling, SchedulingMiddleware,
+    },
+    scope::{Scoped, ScopedSpider, UrlScope},
+    seed::{AsyncStartSpider, Seeded},
+    select::SelectExt,
+    stats::{ByteStats, StatCollectorExt, StatsSnapshot, SyntheticTraffic},
+    stream::{LimitAction, StreamResponse},
+    table::{Table, extract_tables},
+    timing::{RequestTimings, TimingStats},
+    utils::{
+        TRACKING_PARAMS, canonical_url, canonical_url_in_head, canonicalize_url,
+        canonicalize_url_with, content_disposition_filename, download_filename, registrable_domain,
+        same_registrable_domain,
+    },
+    validate::ResponseValidator,
+    xpath::{XPath, XPathDocument, XPathError, XPathMatch, XPathNode, xpath},
+};
+
+#[cfg(feature = "pdf")]
+pub use crate::pdf::{PdfError, PdfText, extract_pdf_text};
+
+#[cfg(feature = "feed")]
+pub use crate::feed::{Feed, FeedEntry, FeedError, parse_feed};
+
+#[cfg(feature = "sitemap")]
+pub use crate::sitemap::{
+    SITEMAP_KEY, Sitemap, SitemapEntry, SitemapError, default_sitemap_url, parse_sitemap,
+    sitemaps_from_robots,
+};
+
+#[cfg(feature = "metrics-prometheus")]
+pub use crate::metrics::{MetricsServer, PrometheusExporter};
+
 pub use spider_middleware::{
     rate_limit::RateLimitMiddleware, referer::RefererMiddleware, retry::RetryMiddleware,
 };
@@ -60,9 +155,16 @@ pub use spider_middleware::user_agent::UserAgentMiddleware;
 #[cfg(feature = "middleware-robots")]
 pub use spider_middleware::robots_txt::RobotsTxtMiddleware;
 
+#[cfg(feature = "middleware-cookies")]
+pub use crate::middleware::cookie_jar::{CookieMiddlewareExt, PersistentCookieMiddleware};
 #[cfg(feature = "middleware-cookies")]
 pub use spider_middleware::cookies::CookieMiddleware;
 
+#[cfg(feature = "middleware-robots")]
+pub use crate::middleware::robots_cache::{RobotsCacheMiddleware, RobotsFailurePolicy};
+#[cfg(feature = "middleware-warc")]
+pub use crate::middleware::warc::WarcWriterMiddleware;
+
 pub use spider_pipeline::{
     console_writer::ConsoleWriterPipeline, deduplication::DeduplicationPipeline,
 };
@@ -79,9 +181,17 @@ pub use spider_pipeline::jsonl_writer::JsonlWriterPipeline;
 #[cfg(feature = "pipeline-sqlite")]
 pub use spider_pipeline::sqlite_writer::SqliteWriterPipeline;
 
+#[cfg(feature = "pipeline-mongo")]
+pub use crate::mongo_writer::{MongoWriterPipeline, WriteConcern};
+
+#[cfg(feature = "pipeline-postgres")]
+pub use crate::postgres_writer::PostgresWriterPipeline;
+
 #[cfg(feature = "pipeline-stream-json")]
 pub use spider_pipeline::stream_json_writer::StreamJsonWriterPipeline;
 
+#[cfg(feature = "pipeline-parquet")]
+pub use crate::parquet_export::ParquetExporterPipeline;
 
 #[cfg(feature = "checkpoint")]
 pub use spider_core::checkpoint::{Checkpoint, SchedulerCheckpoint};
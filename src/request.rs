@@ -0,0 +1,409 @@
+//! Per-request settings stored in [`Request`] meta.
+//!
+//! The facade's middlewares look for these keys in the request meta, so a setting made
+//! on one request overrides the middleware's global configuration for that request
+//! only. Meta travels with the request through retries and onto its response.
+//!
+//! ```rust,ignore
+//! // The seed is critical: retry it harder. Thumbnails are optional: never retry.
+//! let seed = Request::new(seed_url).max_retries(10);
+//! let thumbnail = Request::new(thumbnail_url).max_retries(0);
+//! ```
+//!
+//! Requests with a body are built with [`RequestExt::post`], [`RequestExt::form`] and
+//! [`RequestExt::json`], which also record the method, content type and body in the meta.
+//! A request rebuilt from its response with `request_from_response` only keeps the URL
+//! and meta; [`ResponseExt::replay_request`] restores the rest, so a retried `POST` is
+//! sent again as it was the first time:
+//!
+//! ```rust,ignore
+//! let login = Request::new(login_url).form(&[("user", "me"), ("password", "secret")]);
+//! let search = Request::post(api_url, Body::Json(json!({"query": "books"})));
+//! ```
+//!
+//! [`ResponseExt::replay_request`]: crate::response::ResponseExt::replay_request
+
+use crate::callback::{CALLBACK_KEY, ERRBACK_KEY};
+use crate::middleware::dupe_filter::NO_DEDUP_KEY;
+#[cfg(feature = "sitemap")]
+use crate::sitemap::SITEMAP_KEY;
+use crate::validate::ResponseValidator;
+use bytes::Bytes;
+use encoding_rs::Encoding;
+use reqwest::Method;
+use reqwest::header::{CONTENT_TYPE, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spider_util::request::{Body, Request};
+use std::time::Duration;
+use url::Url;
+
+/// Meta key holding the per-request retry limit, see [`RequestExt::max_retries`].
+pub const MAX_RETRIES_KEY: &str = "max_retries";
+
+/// Meta key holding the request's response validators, see [`RequestExt::validate`].
+pub const VALIDATORS_KEY: &str = "validators";
+
+/// Meta key exempting a request from the facade's politeness, see
+/// [`RequestExt::impolite`].
+pub const IMPOLITE_KEY: &str = "impolite";
+
+/// Meta key holding the method, content type and body of a request built with
+/// [`RequestExt::post`], [`RequestExt::form`] or [`RequestExt::json`].
+pub const REPLAY_KEY: &str = "replay";
+
+/// What a request rebuilt from its response needs to be sent again as it was.
+#[derive(Serialize, Deserialize)]
+struct Replay {
+    method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    body: Body,
+}
+
+/// Records the method, content type and body of `request` under [`REPLAY_KEY`].
+fn remember_body(request: Request) -> Request {
+    let Some(body) = request.body.clone() else {
+        return request;
+    };
+    let replay = Replay {
+        method: request.method.to_string(),
+        content_type: request
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        body,
+    };
+    let value = serde_json::to_value(replay).unwrap_or(Value::Null);
+    request.with_meta(REPLAY_KEY, value)
+}
+
+/// Sets `body` with `content_type`, making a `GET` request a `POST`.
+fn with_typed_body(mut request: Request, body: Body, content_type: &'static str) -> Request {
+    let method = if request.method == Method::GET {
+        Method::POST
+    } else {
+        request.method.clone()
+    };
+    request
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    remember_body(request.with_body(body).with_method(method))
+}
+
+/// Restores the method, content type and body recorded under [`REPLAY_KEY`] on a request
+/// rebuilt from its response.
+pub(crate) fn replayed(mut request: Request) -> Request {
+    let Some(replay) = request
+        .meta
+        .get(REPLAY_KEY)
+        .and_then(|value| serde_json::from_value::<Replay>(value.clone()).ok())
+    else {
+        return request;
+    };
+    if let Ok(method) = Method::from_bytes(replay.method.as_bytes()) {
+        request.method = method;
+    }
+    if let Some(value) = replay
+        .content_type
+        .and_then(|value| HeaderValue::from_str(&value).ok())
+    {
+        request.headers.insert(CONTENT_TYPE, value);
+    }
+    request.body = Some(replay.body);
+    request
+}
+
+/// Meta key keeping [`HttpDownloader`](crate::downloader::HttpDownloader) from following
+/// redirects for a request, see [`RequestExt::no_redirects`].
+pub const NO_REDIRECTS_KEY: &str = "no_redirects";
+
+/// Meta key holding the request's own timeout in milliseconds, see
+/// [`RequestExt::timeout`].
+pub const TIMEOUT_KEY: &str = "timeout_ms";
+
+/// Meta key holding the name of the encoding forced on the response, see
+/// [`RequestExt::force_encoding`].
+pub const ENCODING_KEY: &str = "encoding";
+
+/// Meta key holding the request's scheduling priority, see [`RequestExt::priority`].
+pub const PRIORITY_KEY: &str = "priority";
+
+/// Meta key holding the session pinned to one proxy, see [`RequestExt::proxy_session`].
+pub const PROXY_SESSION_KEY: &str = "proxy_session";
+
+/// Extension methods for [`Request`].
+pub trait RequestExt: Sized {
+    /// Creates a `POST` request to `url` sending `body`. The body survives retries, see
+    /// the [module docs](self).
+    fn post(url: Url, body: Body) -> Self;
+
+    /// Sends `fields` as an `application/x-www-form-urlencoded` body, in their order and
+    /// with repeated names kept. A `GET` request becomes a `POST`; other methods are kept.
+    fn form<K: AsRef<str>, V: AsRef<str>>(self, fields: &[(K, V)]) -> Self;
+
+    /// Sends `value` as an `application/json` body. A `GET` request becomes a `POST`;
+    /// other methods are kept.
+    fn json(self, value: Value) -> Self;
+
+    /// Overrides the number of times this request is retried by
+    /// [`RetryPolicyMiddleware`](crate::middleware::retry::RetryPolicyMiddleware).
+    ///
+    /// The per-request value takes precedence over the middleware's `max_retries`; `0`
+    /// disables retries for this request.
+    fn max_retries(self, max_retries: u32) -> Self;
+
+    /// Returns the per-request retry limit set with [`max_retries`](Self::max_retries).
+    fn max_retries_override(&self) -> Option<u32>;
+
+    /// Adds `validator` to the checks the response to this request must pass, see
+    /// [`crate::validate`].
+    fn validate(self, validator: ResponseValidator) -> Self;
+
+    /// Returns the validators added with [`validate`](Self::validate).
+    fn validators(&self) -> Vec<ResponseValidator>;
+
+    /// Exempts this request from politeness: it is not paced by the
+    /// [ramps](crate::middleware::ramp), `AutoThrottle` or
+    /// middlewares wrapped in [`Polite`](crate::middleware::politeness::Polite), nor
+    /// checked by `RobotsCacheMiddleware`. Only for requests to servers you run, see
+    /// [`NoPolitenessMiddleware`](crate::middleware::politeness::NoPolitenessMiddleware).
+    fn impolite(self) -> Self;
+
+    /// Returns whether the request was exempted with [`impolite`](Self::impolite).
+    fn is_impolite(&self) -> bool;
+
+    /// Exempts this request from duplicate filtering, so its URL is fetched again every
+    /// time it is requested, see
+    /// [`DupeFilterMiddleware`](crate::middleware::dupe_filter::DupeFilterMiddleware).
+    ///
+    /// The middleware keeps the engine from recording the download as visited, so it
+    /// must be added to the crawler, for instance with `DupeFilterMiddleware::new()`. A
+    /// URL the engine already recorded from a request without the flag stays filtered.
+    fn dont_filter(self) -> Self;
+
+    /// Returns whether the request is subject to duplicate filtering, that is whether it
+    /// was not marked with [`dont_filter`](Self::dont_filter).
+    fn filters_duplicates(&self) -> bool;
+
+    /// Hands a `3xx` answer to this request back as the response instead of following
+    /// it, whatever the [`RedirectPolicy`](crate::downloader::RedirectPolicy) of the
+    /// [`HttpDownloader`](crate::downloader::HttpDownloader).
+    fn no_redirects(self) -> Self;
+
+    /// Returns whether redirects are followed for this request, that is whether it was
+    /// not marked with [`no_redirects`](Self::no_redirects).
+    fn follows_redirects(&self) -> bool;
+
+    /// Gives this request `timeout` to complete, from sending it to reading the last body
+    /// byte, in place of the whole-request timeout of the
+    /// [`HttpDownloader`](crate::downloader::HttpDownloader).
+    fn timeout(self, timeout: Duration) -> Self;
+
+    /// Returns the per-request timeout set with [`timeout`](Self::timeout).
+    fn timeout_override(&self) -> Option<Duration>;
+
+    /// Decodes the response to this request with `encoding`, whatever its headers and
+    /// body declare, see [`crate::encoding`].
+    fn force_encoding(self, encoding: &'static Encoding) -> Self;
+
+    /// Returns the encoding set with [`force_encoding`](Self::force_encoding).
+    fn forced_encoding(&self) -> Option<&'static Encoding>;
+
+    /// Has the response to this request parsed by the callback registered under `name`
+    /// instead of `parse`, see [`crate::callback`].
+    fn callback(self, name: &str) -> Self;
+
+    /// Returns the callback name set with [`callback`](Self::callback).
+    fn callback_name(&self) -> Option<String>;
+
+    /// Hands this request to the errback registered under `name` if it fails for good,
+    /// see [`crate::callback`].
+    fn errback(self, name: &str) -> Self;
+
+    /// Returns the errback name set with [`errback`](Self::errback).
+    fn errback_name(&self) -> Option<String>;
+
+    /// Creates a request for the sitemap at `url`, marked so that its response can be
+    /// told apart with [`ResponseExt::is_sitemap`](crate::response::ResponseExt::is_sitemap),
+    /// see [`crate::sitemap`].
+    #[cfg(feature = "sitemap")]
+    fn sitemap(url: Url) -> Self;
+
+    /// Returns whether the request was created with [`sitemap`](Self::sitemap).
+    #[cfg(feature = "sitemap")]
+    fn is_sitemap(&self) -> bool;
+
+    /// Sets the priority of this request. Requests default to `0`.
+    ///
+    /// The engine's own queue is first-in, first-out and ignores it. A crawl downloads
+    /// higher priorities first when its requests go through a
+    /// [`PriorityScheduler`](crate::scheduler::PriorityScheduler) or a
+    /// [`DiskScheduler`](crate::scheduler::DiskScheduler), set with
+    /// [`CrawlerBuilderExt::scheduler`](crate::builder::CrawlerBuilderExt::scheduler).
+    fn priority(self, priority: i32) -> Self;
+
+    /// Returns the priority set with [`priority`](Self::priority), or `0`.
+    fn priority_value(&self) -> i32;
+
+    /// Sends this request through the same proxy as the other requests of `session`,
+    /// see [`ProxyPoolMiddleware`](crate::middleware::proxy_pool::ProxyPoolMiddleware).
+    fn proxy_session(self, session: &str) -> Self;
+
+    /// Returns the session set with [`proxy_session`](Self::proxy_session).
+    fn proxy_session_name(&self) -> Option<String>;
+}
+
+impl RequestExt for Request {
+    fn post(url: Url, body: Body) -> Self {
+        remember_body(Request::new(url).with_body(body))
+    }
+
+    fn form<K: AsRef<str>, V: AsRef<str>>(self, fields: &[(K, V)]) -> Self {
+        let encoded = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(fields)
+            .finish();
+        with_typed_body(
+            self,
+            Body::Bytes(Bytes::from(encoded)),
+            "application/x-www-form-urlencoded",
+        )
+    }
+
+    fn json(self, value: Value) -> Self {
+        with_typed_body(self, Body::Json(value), "application/json")
+    }
+
+    fn max_retries(self, max_retries: u32) -> Self {
+        self.with_meta(MAX_RETRIES_KEY, max_retries.into())
+    }
+
+    fn max_retries_override(&self) -> Option<u32> {
+        self.meta
+            .get(MAX_RETRIES_KEY)
+            .and_then(|value| value.as_u64())
+            .map(|value| u32::try_from(value).unwrap_or(u32::MAX))
+    }
+
+    fn validate(self, validator: ResponseValidator) -> Self {
+        let mut validators = self.validators();
+        validators.push(validator);
+        let value = serde_json::to_value(validators).unwrap_or(Value::Null);
+        self.with_meta(VALIDATORS_KEY, value)
+    }
+
+    fn validators(&self) -> Vec<ResponseValidator> {
+        self.meta
+            .get(VALIDATORS_KEY)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    fn impolite(self) -> Self {
+        self.with_meta(IMPOLITE_KEY, true.into())
+    }
+
+    fn is_impolite(&self) -> bool {
+        self.meta
+            .get(IMPOLITE_KEY)
+            .is_some_and(|value| value.as_bool() == Some(true))
+    }
+
+    fn dont_filter(self) -> Self {
+        self.with_meta(NO_DEDUP_KEY, true.into())
+    }
+
+    fn filters_duplicates(&self) -> bool {
+        self.meta
+            .get(NO_DEDUP_KEY)
+            .is_none_or(|value| value.as_bool() != Some(true))
+    }
+
+    fn no_redirects(self) -> Self {
+        self.with_meta(NO_REDIRECTS_KEY, true.into())
+    }
+
+    fn follows_redirects(&self) -> bool {
+        self.meta
+            .get(NO_REDIRECTS_KEY)
+            .is_none_or(|value| value.as_bool() != Some(true))
+    }
+
+    fn timeout(self, timeout: Duration) -> Self {
+        let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        self.with_meta(TIMEOUT_KEY, millis.into())
+    }
+
+    fn timeout_override(&self) -> Option<Duration> {
+        self.meta
+            .get(TIMEOUT_KEY)
+            .and_then(|value| value.as_u64())
+            .map(Duration::from_millis)
+    }
+
+    fn force_encoding(self, encoding: &'static Encoding) -> Self {
+        self.with_meta(ENCODING_KEY, encoding.name().into())
+    }
+
+    fn forced_encoding(&self) -> Option<&'static Encoding> {
+        self.meta
+            .get(ENCODING_KEY)
+            .and_then(|value| Encoding::for_label(value.as_str()?.as_bytes()))
+    }
+
+    fn callback(self, name: &str) -> Self {
+        self.with_meta(CALLBACK_KEY, name.into())
+    }
+
+    fn callback_name(&self) -> Option<String> {
+        self.meta
+            .get(CALLBACK_KEY)
+            .and_then(|value| value.as_str().map(str::to_string))
+    }
+
+    fn errback(self, name: &str) -> Self {
+        self.with_meta(ERRBACK_KEY, name.into())
+    }
+
+    fn errback_name(&self) -> Option<String> {
+        self.meta
+            .get(ERRBACK_KEY)
+            .and_then(|value| value.as_str().map(str::to_string))
+    }
+
+    #[cfg(feature = "sitemap")]
+    fn sitemap(url: Url) -> Self {
+        Request::new(url).with_meta(SITEMAP_KEY, true.into())
+    }
+
+    #[cfg(feature = "sitemap")]
+    fn is_sitemap(&self) -> bool {
+        self.meta
+            .get(SITEMAP_KEY)
+            .is_some_and(|value| value.as_bool() == Some(true))
+    }
+
+    fn priority(self, priority: i32) -> Self {
+        self.with_meta(PRIORITY_KEY, priority.into())
+    }
+
+    fn priority_value(&self) -> i32 {
+        self.meta
+            .get(PRIORITY_KEY)
+            .and_then(|value| value.as_i64())
+            .map_or(0, |value| {
+                value.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+            })
+    }
+
+    fn proxy_session(self, session: &str) -> Self {
+        self.with_meta(PROXY_SESSION_KEY, session.into())
+    }
+
+    fn proxy_session_name(&self) -> Option<String> {
+        self.meta
+            .get(PROXY_SESSION_KEY)
+            .and_then(|value| value.as_str().map(str::to_string))
+    }
+}
@@ -0,0 +1,515 @@
+//! Convenience extensions for [`Response`].
+//!
+//! These helpers are built on top of the response URL, status, headers, raw body and
+//! [`Response::to_html`], so they work with any response produced by the crawler.
+//! Bring [`ResponseExt`] into scope (it is part of the prelude) to call them as methods
+//! on a response.
+
+use crate::callback::{FAILURE_ERROR_KEY, FAILURE_KEY};
+use crate::downloader::{FAILED_DOWNLOAD_STATUS, REDIRECT_CHAIN_KEY, REDIRECT_HOPS_KEY};
+use crate::encoding::{charset_from_content_type, decode_body, detect_encoding};
+use crate::extract::{
+    Fields, ItemSelectors, extract_item, extract_items, extract_records, looks_empty,
+};
+#[cfg(feature = "feed")]
+use crate::feed::{Feed, FeedError, parse_feed};
+use crate::form::{Form, extract_form};
+use crate::html::{NotHtml, TextHeuristic};
+use crate::json::{JsonPathError, NotJson, json_links, json_path};
+use crate::links::{FollowTarget, follow, follow_links};
+use crate::middleware::dupe_filter::{NO_DEDUP_FRAGMENT, NO_DEDUP_KEY};
+#[cfg(feature = "pdf")]
+use crate::pdf::{PdfError, PdfText, extract_pdf_text};
+use crate::request::ENCODING_KEY;
+use crate::request::replayed;
+use crate::select::SelectExt;
+#[cfg(feature = "sitemap")]
+use crate::sitemap::{SITEMAP_KEY, Sitemap, SitemapError, parse_sitemap};
+use crate::stream::WIRE_BYTES_KEY;
+use crate::table::{Table, extract_tables};
+use crate::timing::{RequestTimings, TIMINGS_KEY};
+use crate::utils::canonical_url;
+use crate::validate::VALIDATION_ERROR_KEY;
+use crate::xpath::{XPath, XPathError, XPathMatch};
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use scraper::Html;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use spider_util::{
+    error::{ReqwestErrorDetails, SpiderError},
+    request::Request,
+    response::Response,
+};
+use url::Url;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Extension methods for [`Response`].
+pub trait ResponseExt {
+    /// Returns the HTTP status code, such as `200` or `404`.
+    fn status(&self) -> u16;
+
+    /// Returns the value of header `name`, matched case-insensitively.
+    ///
+    /// Values that are not visible ASCII are returned as `None`; read them from
+    /// [`headers`](ResponseExt::headers) as bytes.
+    fn header(&self, name: &str) -> Option<&str>;
+
+    /// Returns every response header.
+    fn headers(&self) -> &HeaderMap;
+
+    /// Parses the response as HTML and extracts every `<table>` in document order.
+    ///
+    /// See [`Table`] for how headers, `colspan`/`rowspan` and nested tables are handled.
+    fn tables(&self) -> Result<Vec<Table>, SpiderError>;
+
+    /// Extracts the first `<form>` matching `selector`, with its action resolved against
+    /// the response URL.
+    ///
+    /// Returns `Ok(None)` when no form matches.
+    fn form(&self, selector: &str) -> Result<Option<Form>, SpiderError>;
+
+    /// Extracts the text of a PDF response body, page by page.
+    ///
+    /// Encrypted or malformed documents yield a [`PdfError`] rather than a panic.
+    #[cfg(feature = "pdf")]
+    fn pdf_text(&self) -> Result<PdfText, PdfError>;
+
+    /// Returns the timing breakdown of the download, when the downloader recorded one,
+    /// see [`crate::timing`].
+    fn timings(&self) -> Option<RequestTimings>;
+
+    /// Builds one item from the whole document using `selectors`.
+    ///
+    /// Returns `Ok(None)` when a required field is missing; see [`ItemSelectors`].
+    fn extract_item<T: DeserializeOwned>(
+        &self,
+        selectors: &ItemSelectors,
+    ) -> Result<Option<T>, SpiderError>;
+
+    /// Builds one item per element matching `root` using `selectors`.
+    fn extract_items<T: DeserializeOwned>(
+        &self,
+        root: &str,
+        selectors: &ItemSelectors,
+    ) -> Result<Vec<T>, SpiderError>;
+
+    /// Extracts the fields of one item per element matching `root`, for item types that
+    /// do not implement `DeserializeOwned`; see [`extract_records`].
+    fn extract_records(
+        &self,
+        root: &str,
+        selectors: &ItemSelectors,
+    ) -> Result<Vec<Fields>, SpiderError>;
+
+    /// Returns `true` when the page has no text inside elements matching `selector`.
+    ///
+    /// Useful to detect pages whose content is loaded by JavaScript, see
+    /// [`looks_empty`](crate::extract::looks_empty).
+    fn looks_empty(&self, selector: &str) -> Result<bool, SpiderError>;
+
+    /// Returns the absolute URLs that redirected before the response arrived from
+    /// `url`, in order, when the downloader recorded them, see
+    /// [`HttpDownloader`](crate::downloader::HttpDownloader). Empty without redirects.
+    fn redirect_chain(&self) -> Vec<Url>;
+
+    /// Deserializes the JSON response body into `T`.
+    ///
+    /// The body is decoded with the encoding of its byte order mark or of the `charset`
+    /// of the `Content-Type` header, and as UTF-8 otherwise. `Response` has an inherent
+    /// `json` that only reads UTF-8, so call this one as `ResponseExt::json(&response)`.
+    fn json<T: DeserializeOwned>(&self) -> Result<T, NotJson>;
+
+    /// Parses the JSON response body into a [`serde_json::Value`], decoded like
+    /// [`json`](Self::json).
+    fn json_value(&self) -> Result<Value, NotJson>;
+
+    /// Parses the response body as JSON and builds requests for the URLs found at
+    /// `paths`, resolved against the response URL.
+    ///
+    /// Paths may use `*` to match every array element (`/data/*/url`). Bodies that are not
+    /// valid JSON yield no requests and a warning.
+    fn json_links(&self, paths: &[&str]) -> Vec<Request>;
+
+    /// Returns the same-origin canonical URL declared by the page, resolved against the
+    /// response URL.
+    ///
+    /// Use [`canonical_url`](crate::utils::canonical_url) to accept canonicals on other
+    /// origins.
+    fn canonical_url(&self) -> Option<Url>;
+
+    /// Returns why the response failed its request's validators once its retries were
+    /// exhausted, see [`crate::validate`].
+    fn validation_error(&self) -> Option<String>;
+
+    /// Parses the body as HTML, unless the default [`TextHeuristic`] finds it is binary,
+    /// see [`crate::html`].
+    fn html(&self) -> Result<Html, NotHtml>;
+
+    /// Parses the body as HTML unless `heuristic` finds it is binary, decoding it with the
+    /// [`encoding`](Self::encoding) of the response.
+    fn html_with(&self, heuristic: &TextHeuristic) -> Result<Html, NotHtml>;
+
+    /// Returns why the request of this placeholder response failed for good, see
+    /// [`crate::callback`].
+    fn failure(&self) -> Option<String>;
+
+    /// Returns the transport error of this placeholder response, if its download failed
+    /// in transport rather than, say, while decoding the body.
+    fn failure_error(&self) -> Option<ReqwestErrorDetails>;
+
+    /// Returns whether this is the placeholder response
+    /// [`HttpDownloader`](crate::downloader::HttpDownloader) answers a failed download of a
+    /// request with an errback with, see [`crate::callback`]. Middlewares looking at what
+    /// a server sent pass it on untouched.
+    fn is_failed_download(&self) -> bool;
+
+    /// Returns the number of body bytes received on the wire: the compressed size of a
+    /// body [`HttpDownloader`](crate::downloader::HttpDownloader) decoded, and the body
+    /// length for a response from another downloader, which does not decode bodies.
+    fn wire_bytes(&self) -> usize;
+
+    /// Rebuilds the request of this response to send it again. Unlike
+    /// `request_from_response`, it keeps the method, content type and body of a request
+    /// built with [`RequestExt::post`](crate::request::RequestExt::post) and friends.
+    fn replay_request(&self) -> Request;
+
+    /// Returns the URL of the request, without the `#no-dedup` fragment that
+    /// [`DupeFilterMiddleware`](crate::middleware::dupe_filter::DupeFilterMiddleware) puts
+    /// on `request_url` for requests exempted from duplicate filtering.
+    fn original_request_url(&self) -> Url;
+
+    /// Evaluates an XPath expression on the page and returns the matched nodes, with
+    /// their text and attributes.
+    ///
+    /// To run several expressions or CSS selectors on the same parsed page, keep an
+    /// [`XPathDocument`](crate::xpath::XPathDocument) of [`Response::to_html`] instead.
+    fn xpath(&self, expr: &str) -> Result<Vec<XPathMatch>, XPathError>;
+
+    /// Parses the response as a sitemap or sitemap index, decompressing gzipped bodies.
+    ///
+    /// Use [`Sitemap::requests`] to follow the entries.
+    #[cfg(feature = "sitemap")]
+    fn sitemap(&self) -> Result<Sitemap, SitemapError>;
+
+    /// Returns whether the response answers a request created with
+    /// [`RequestExt::sitemap`](crate::request::RequestExt::sitemap).
+    #[cfg(feature = "sitemap")]
+    fn is_sitemap(&self) -> bool;
+
+    /// Builds a request for `target` (an `href` string or a link element), resolved against
+    /// the response URL and with it as the `Referer`, see [`follow`](crate::links::follow).
+    ///
+    /// Invalid references yield an error instead of being dropped.
+    fn follow<T: FollowTarget + ?Sized>(&self, target: &T) -> Result<Request, SpiderError>;
+
+    /// Builds requests for attribute `attr` of every element matching `selector`,
+    /// resolved against the response URL and with it as the `Referer`.
+    ///
+    /// Unresolvable and non-`http(s)` links are skipped; see
+    /// [`follow_links`](crate::links::follow_links).
+    fn follow_all(&self, selector: &str, attr: &str) -> Result<Vec<Request>, SpiderError>;
+
+    /// Returns the redirects followed before the response, as the status of each `3xx`
+    /// answer and the URL that sent it, in order. Empty without redirects.
+    fn redirect_hops(&self) -> Vec<(u16, Url)>;
+
+    /// Evaluates a JSONPath expression such as `$.data.items[*].name` on the JSON
+    /// response body and returns the matched values.
+    ///
+    /// See [`json_path`](crate::json::json_path) for the supported syntax.
+    fn json_path(&self, expr: &str) -> Result<Vec<Value>, JsonPathError>;
+
+    /// Parses the response as an RSS or Atom feed, resolving entry links against the
+    /// response URL.
+    ///
+    /// Use [`Feed::requests`] to follow the entries.
+    #[cfg(feature = "feed")]
+    fn feed(&self) -> Result<Feed, FeedError>;
+
+    /// Returns the character encoding of the body: the one forced on the request with
+    /// [`RequestExt::force_encoding`], or the one detected from its byte order mark, the
+    /// `charset` of its `Content-Type` header, its `<meta charset>` declaration or its
+    /// content, see [`detect_encoding`].
+    fn encoding(&self) -> &'static Encoding;
+
+    /// Decodes the body to text, using `encoding` when given and the
+    /// [`encoding`](Self::encoding) of the response otherwise.
+    fn decoded_text(&self, encoding: Option<&'static Encoding>) -> String;
+
+    /// Like [`html`](Self::html), but decodes the body like
+    /// [`decoded_text`](Self::decoded_text), so `encoding` overrides the detected one,
+    /// e.g. for a page that lies about its encoding.
+    fn decoded_html(&self, encoding: Option<&'static Encoding>) -> Result<Html, NotHtml>;
+
+    /// Returns the trimmed text of the first element matching `selector`.
+    ///
+    /// Each call parses the page; for several lookups, or to get at the element itself
+    /// with `css_first`, use the [`SelectExt`] methods on the result of
+    /// [`Response::to_html`].
+    fn css_text(&self, selector: &str) -> Result<Option<String>, SpiderError>;
+
+    /// Returns attribute `attr` of the first element matching `selector` that has it.
+    fn css_attr(&self, selector: &str, attr: &str) -> Result<Option<String>, SpiderError>;
+}
+
+impl ResponseExt for Response {
+    fn status(&self) -> u16 {
+        self.status.as_u16()
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    fn tables(&self) -> Result<Vec<Table>, SpiderError> {
+        Ok(extract_tables(&self.to_html()?))
+    }
+
+    fn form(&self, selector: &str) -> Result<Option<Form>, SpiderError> {
+        extract_form(&self.to_html()?, &self.url, selector)
+    }
+
+    #[cfg(feature = "pdf")]
+    fn pdf_text(&self) -> Result<PdfText, PdfError> {
+        extract_pdf_text(&self.body)
+    }
+
+    fn timings(&self) -> Option<RequestTimings> {
+        self.meta
+            .get(TIMINGS_KEY)
+            .and_then(|value| RequestTimings::from_value(&value))
+    }
+
+    fn extract_item<T: DeserializeOwned>(
+        &self,
+        selectors: &ItemSelectors,
+    ) -> Result<Option<T>, SpiderError> {
+        extract_item(self.to_html()?.root_element(), selectors)
+    }
+
+    fn extract_items<T: DeserializeOwned>(
+        &self,
+        root: &str,
+        selectors: &ItemSelectors,
+    ) -> Result<Vec<T>, SpiderError> {
+        extract_items(&self.to_html()?, root, selectors)
+    }
+
+    fn extract_records(
+        &self,
+        root: &str,
+        selectors: &ItemSelectors,
+    ) -> Result<Vec<Fields>, SpiderError> {
+        extract_records(&self.to_html()?, root, selectors)
+    }
+
+    fn looks_empty(&self, selector: &str) -> Result<bool, SpiderError> {
+        looks_empty(&self.to_html()?, selector)
+    }
+
+    fn redirect_chain(&self) -> Vec<Url> {
+        let Some(chain) = self.meta.get(REDIRECT_CHAIN_KEY) else {
+            return Vec::new();
+        };
+        chain
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|url| Url::parse(url.as_str()?).ok())
+            .collect()
+    }
+
+    fn json<T: DeserializeOwned>(&self) -> Result<T, NotJson> {
+        let body: &[u8] = &self.body;
+        let encoding = Encoding::for_bom(body)
+            .map(|(encoding, _)| encoding)
+            .or_else(|| {
+                self.header(CONTENT_TYPE.as_str())
+                    .and_then(charset_from_content_type)
+            })
+            .unwrap_or(UTF_8);
+        if encoding == UTF_8 {
+            return Ok(serde_json::from_slice(
+                body.strip_prefix(UTF8_BOM).unwrap_or(body),
+            )?);
+        }
+        let text = decode_body(body, None, Some(encoding));
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn json_value(&self) -> Result<Value, NotJson> {
+        ResponseExt::json(self)
+    }
+
+    fn json_links(&self, paths: &[&str]) -> Vec<Request> {
+        match self.json_value() {
+            Ok(body) => json_links(&body, &self.url, paths),
+            Err(err) => {
+                log::warn!("Cannot extract links from {}: {}", self.url, err);
+                Vec::new()
+            }
+        }
+    }
+
+    fn canonical_url(&self) -> Option<Url> {
+        canonical_url(&self.to_html().ok()?, &self.url, true)
+    }
+
+    fn validation_error(&self) -> Option<String> {
+        self.meta
+            .get(VALIDATION_ERROR_KEY)
+            .and_then(|value| value.as_str().map(str::to_string))
+    }
+
+    fn html(&self) -> Result<Html, NotHtml> {
+        self.html_with(&TextHeuristic::default())
+    }
+
+    fn html_with(&self, heuristic: &TextHeuristic) -> Result<Html, NotHtml> {
+        heuristic.check(self)?;
+        Ok(Html::parse_document(&self.decoded_text(None)))
+    }
+
+    fn failure(&self) -> Option<String> {
+        self.meta
+            .get(FAILURE_KEY)
+            .and_then(|value| value.as_str().map(str::to_string))
+    }
+
+    fn failure_error(&self) -> Option<ReqwestErrorDetails> {
+        let error = self.meta.get(FAILURE_ERROR_KEY)?;
+        let error: &Value = &error;
+        Some(ReqwestErrorDetails {
+            message: error.get("message")?.as_str()?.to_string(),
+            is_connect: error.get("is_connect")?.as_bool()?,
+            is_timeout: error.get("is_timeout")?.as_bool()?,
+        })
+    }
+
+    fn is_failed_download(&self) -> bool {
+        self.status.as_u16() == FAILED_DOWNLOAD_STATUS && self.meta.contains_key(FAILURE_KEY)
+    }
+
+    fn wire_bytes(&self) -> usize {
+        self.meta
+            .get(WIRE_BYTES_KEY)
+            .and_then(|value| value.as_u64())
+            .map_or(self.body.len(), |bytes| bytes as usize)
+    }
+
+    fn replay_request(&self) -> Request {
+        let mut request = self.request_from_response();
+        request.url = self.original_request_url();
+        replayed(request)
+    }
+
+    fn original_request_url(&self) -> Url {
+        let mut url = self.request_url.clone();
+        let exempt = self
+            .meta
+            .get(NO_DEDUP_KEY)
+            .is_some_and(|exempt| exempt.as_bool() == Some(true));
+        if exempt && url.fragment() == Some(NO_DEDUP_FRAGMENT) {
+            url.set_fragment(None);
+        }
+        url
+    }
+
+    fn xpath(&self, expr: &str) -> Result<Vec<XPathMatch>, XPathError> {
+        let xpath = XPath::parse(expr)?;
+        let html = self
+            .to_html()
+            .map_err(|err| XPathError::Html(err.to_string()))?;
+        Ok(xpath
+            .select(&html)
+            .into_iter()
+            .map(XPathMatch::from)
+            .collect())
+    }
+
+    #[cfg(feature = "sitemap")]
+    fn sitemap(&self) -> Result<Sitemap, SitemapError> {
+        parse_sitemap(&self.body)
+    }
+
+    #[cfg(feature = "sitemap")]
+    fn is_sitemap(&self) -> bool {
+        self.meta
+            .get(SITEMAP_KEY)
+            .is_some_and(|value| value.as_bool() == Some(true))
+    }
+
+    fn follow<T: FollowTarget + ?Sized>(&self, target: &T) -> Result<Request, SpiderError> {
+        follow(&self.url, target)
+    }
+
+    fn follow_all(&self, selector: &str, attr: &str) -> Result<Vec<Request>, SpiderError> {
+        follow_links(&self.to_html()?, &self.url, selector, attr)
+    }
+
+    fn redirect_hops(&self) -> Vec<(u16, Url)> {
+        let Some(hops) = self.meta.get(REDIRECT_HOPS_KEY) else {
+            return Vec::new();
+        };
+        hops.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hop| {
+                let status = u16::try_from(hop.get("status")?.as_u64()?).ok()?;
+                Some((status, Url::parse(hop.get("url")?.as_str()?).ok()?))
+            })
+            .collect()
+    }
+
+    fn json_path(&self, expr: &str) -> Result<Vec<Value>, JsonPathError> {
+        let body =
+            self.json_value()
+                .map_err(|(NotJson::Syntax(reason) | NotJson::Data(reason))| {
+                    JsonPathError::Json(reason)
+                })?;
+        Ok(json_path(&body, expr)?.into_iter().cloned().collect())
+    }
+
+    #[cfg(feature = "feed")]
+    fn feed(&self) -> Result<Feed, FeedError> {
+        parse_feed(&self.body, &self.url)
+    }
+
+    fn encoding(&self) -> &'static Encoding {
+        forced_encoding(self)
+            .unwrap_or_else(|| detect_encoding(&self.body, self.header(CONTENT_TYPE.as_str())))
+    }
+
+    fn decoded_text(&self, encoding: Option<&'static Encoding>) -> String {
+        let encoding = encoding.unwrap_or_else(|| self.encoding());
+        decode_body(&self.body, None, Some(encoding)).into_owned()
+    }
+
+    fn decoded_html(&self, encoding: Option<&'static Encoding>) -> Result<Html, NotHtml> {
+        TextHeuristic::default().check(self)?;
+        Ok(Html::parse_document(&self.decoded_text(encoding)))
+    }
+
+    fn css_text(&self, selector: &str) -> Result<Option<String>, SpiderError> {
+        self.to_html()?.css_text(selector)
+    }
+
+    fn css_attr(&self, selector: &str, attr: &str) -> Result<Option<String>, SpiderError> {
+        self.to_html()?.css_attr(selector, attr)
+    }
+}
+
+/// Returns the encoding forced on the request of `response` with
+/// [`RequestExt::force_encoding`](crate::request::RequestExt::force_encoding).
+fn forced_encoding(response: &Response) -> Option<&'static Encoding> {
+    response
+        .meta
+        .get(ENCODING_KEY)
+        .and_then(|value| Encoding::for_label(value.as_str()?.as_bytes()))
+}
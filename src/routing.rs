@@ -0,0 +1,152 @@
+//! Routing items to pipelines by a tag.
+//!
+//! Every pipeline of a crawl sees every item. When one spider produces items for
+//! several sinks, say products for one database and reviews for another, the items can
+//! carry a tag and a [`RoutingPipeline`] hands each item only to the pipelines
+//! registered for its tag:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(ShopSpider)
+//!     .add_pipeline(
+//!         RoutingPipeline::by_field("kind")
+//!             .route("product", products_db)
+//!             .route("review", JsonlWriterPipeline::new("reviews.jsonl")?)
+//!             .fallback(JsonlWriterPipeline::new("other.jsonl")?),
+//!     )
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The tag is read from an item field with [`RoutingPipeline::by_field`], or computed by
+//! a function with [`RoutingPipeline::new`]. Items are created in `parse`, so a tag kept
+//! in the request meta is copied into the item there. Pipelines registered for the same
+//! tag process an item in registration order, and an item one of them drops stops there.
+//!
+//! An item without a tag, or whose tag has no route, goes to the
+//! [`fallback`](RoutingPipeline::fallback) pipelines. Without fallback pipelines it is
+//! passed on unchanged. Either way the router returns what its last pipeline returned,
+//! so pipelines added after the router still see the item.
+
+use serde_json::Value;
+use spider_core::async_trait;
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{error::PipelineError, item::ScrapedItem};
+
+type TagFn<I> = Box<dyn Fn(&I) -> Option<String> + Send + Sync>;
+
+/// A pipeline handing each item to the pipelines registered for its tag, see the
+/// [module docs](self).
+pub struct RoutingPipeline<I> {
+    tag: TagFn<I>,
+    routes: Vec<(String, Box<dyn Pipeline<I>>)>,
+    fallback: Vec<Box<dyn Pipeline<I>>>,
+}
+
+impl<I: ScrapedItem> RoutingPipeline<I> {
+    /// Creates a router tagging items with `tag`.
+    pub fn new(tag: impl Fn(&I) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self {
+            tag: Box::new(tag),
+            routes: Vec::new(),
+            fallback: Vec::new(),
+        }
+    }
+
+    /// Creates a router tagging items with their string field `field`. Items where the
+    /// field is missing or not a string have no tag.
+    pub fn by_field(field: &str) -> Self {
+        let field = field.to_string();
+        Self::new(move |item: &I| {
+            item.to_json_value()
+                .get(&field)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+    }
+
+    /// Hands items tagged `tag` to `pipeline`.
+    pub fn route(mut self, tag: &str, pipeline: impl Pipeline<I>) -> Self {
+        self.routes.push((tag.to_string(), Box::new(pipeline)));
+        self
+    }
+
+    /// Hands items without a matching route to `pipeline`.
+    pub fn fallback(mut self, pipeline: impl Pipeline<I>) -> Self {
+        self.fallback.push(Box::new(pipeline));
+        self
+    }
+
+    fn pipelines(&self) -> impl Iterator<Item = &dyn Pipeline<I>> {
+        self.routes
+            .iter()
+            .map(|(_, pipeline)| pipeline.as_ref())
+            .chain(self.fallback.iter().map(|pipeline| pipeline.as_ref()))
+    }
+}
+
+#[async_trait]
+impl<I: ScrapedItem> Pipeline<I> for RoutingPipeline<I> {
+    fn name(&self) -> &str {
+        "RoutingPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        let tag = (self.tag)(&item);
+        let mut matched: Vec<&dyn Pipeline<I>> = self
+            .routes
+            .iter()
+            .filter(|(route, _)| tag.as_deref() == Some(route.as_str()))
+            .map(|(_, pipeline)| pipeline.as_ref())
+            .collect();
+        if matched.is_empty() {
+            matched = self
+                .fallback
+                .iter()
+                .map(|pipeline| pipeline.as_ref())
+                .collect();
+        }
+
+        let mut item = item;
+        for pipeline in matched {
+            match pipeline.process_item(item).await? {
+                Some(next) => item = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(item))
+    }
+
+    async fn close(&self) -> Result<(), PipelineError> {
+        for pipeline in self.pipelines() {
+            pipeline.close().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the states of the routed pipelines as an array, in registration order
+    /// with the fallback pipelines last.
+    async fn get_state(&self) -> Result<Option<Value>, PipelineError> {
+        let mut states = Vec::new();
+        for pipeline in self.pipelines() {
+            states.push(pipeline.get_state().await?.unwrap_or(Value::Null));
+        }
+        if states.iter().all(Value::is_null) {
+            return Ok(None);
+        }
+        Ok(Some(Value::Array(states)))
+    }
+
+    async fn restore_state(&self, state: Value) -> Result<(), PipelineError> {
+        let Value::Array(states) = state else {
+            return Err(PipelineError::Other(
+                "routing pipeline state is not an array".into(),
+            ));
+        };
+        for (pipeline, state) in self.pipelines().zip(states) {
+            if !state.is_null() {
+                pipeline.restore_state(state).await?;
+            }
+        }
+        Ok(())
+    }
+}
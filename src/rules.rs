@@ -0,0 +1,173 @@
+//! Rule-driven spiders, following links by pattern.
+//!
+//! Broad crawls mostly follow links and hand each page to the callback for its kind. A
+//! [`RuleSpider`] declares that as a list of [`Rule`]s, each a [`LinkExtractor`] with the
+//! [named callback](crate::callback) parsing the pages it matches, and whether to
+//! follow the links of those pages in turn:
+//!
+//! ```rust,ignore
+//! impl RuleSpider for BooksSpider {
+//!     fn rules() -> Vec<Rule> {
+//!         vec![
+//!             Rule::new(LinkExtractor::new().allow(r"/page-\d+\.html$").unwrap()),
+//!             Rule::new(LinkExtractor::new().allow(r"/catalogue/[^/]+/index\.html$").unwrap())
+//!                 .callback("parse_book"),
+//!         ]
+//!     }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(BooksSpider.with_rules()).build().await?;
+//! ```
+//!
+//! The links of the start pages, and of every page matched by a rule that follows, are
+//! run through the rules in order. The first rule whose extractor matches a link
+//! claims it, so a link matched by several rules is requested once, for the earliest.
+//! The request names the rule's callback, and the response is parsed like any other
+//! response of a [`CallbackSpider`], followed by the rules' requests. A rule without
+//! a callback leaves its pages to [`Spider::parse`].
+//!
+//! The index of the rule that matched a request is kept in its meta under
+//! [`RULE_KEY`]. Pages that are not HTML, failed, or failed their validators, see
+//! [`crate::validate`], have no links followed.
+
+use crate::callback::{CallbackSpider, Callbacks};
+use crate::links::LinkExtractor;
+use crate::request::RequestExt;
+use crate::response::ResponseExt;
+use log::{debug, warn};
+use spider_core::Spider;
+use spider_util::{request::Request, response::Response};
+use std::collections::HashSet;
+
+/// Meta key holding the index of the rule that matched a request.
+pub const RULE_KEY: &str = "rule";
+
+/// A link pattern of a [`RuleSpider`], see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Rule {
+    extractor: LinkExtractor,
+    callback: Option<String>,
+    follow: Option<bool>,
+}
+
+impl Rule {
+    /// Creates a rule for the links `extractor` matches.
+    pub fn new(extractor: LinkExtractor) -> Self {
+        Self {
+            extractor,
+            callback: None,
+            follow: None,
+        }
+    }
+
+    /// Parses the matched pages with the callback registered under `name`.
+    pub fn callback(mut self, name: &str) -> Self {
+        self.callback = Some(name.to_string());
+        self
+    }
+
+    /// Sets whether the links of the matched pages are run through the rules. Defaults
+    /// to `true` for a rule without a callback and to `false` for one with a callback.
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = Some(follow);
+        self
+    }
+
+    /// Returns whether the links of the matched pages are followed.
+    pub fn follows(&self) -> bool {
+        self.follow.unwrap_or(self.callback.is_none())
+    }
+
+    fn request(&self, index: usize, request: Request) -> Request {
+        let request = request.with_meta(RULE_KEY, index.into());
+        match &self.callback {
+            Some(name) => request.callback(name),
+            None => request,
+        }
+    }
+}
+
+/// A [`CallbackSpider`] whose links are followed by [`Rule`]s.
+pub trait RuleSpider: CallbackSpider {
+    /// Returns the spider's rules, in the order they are tried.
+    fn rules() -> Vec<Rule>;
+
+    /// Wraps the spider so each response is parsed by its callback and its links are
+    /// followed by the rules.
+    fn with_rules(self) -> Ruled<Self> {
+        Ruled {
+            callbacks: Self::callbacks(),
+            rules: Self::rules(),
+            spider: self,
+        }
+    }
+}
+
+/// A [`RuleSpider`] following links by its rules, see [`RuleSpider::with_rules`].
+pub struct Ruled<S: Spider> {
+    spider: S,
+    callbacks: Callbacks<S>,
+    rules: Vec<Rule>,
+}
+
+impl<S: Spider> Ruled<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+
+    /// Returns the requests for the links of `response`, each claimed by the first rule
+    /// matching it, or none when the rule that matched the response does not follow.
+    fn follow(&self, response: &Response) -> Vec<Request> {
+        if response.failure().is_some() || response.validation_error().is_some() {
+            return Vec::new();
+        }
+        let rule = response
+            .meta
+            .get(RULE_KEY)
+            .and_then(|index| index.as_u64())
+            .and_then(|index| self.rules.get(index as usize));
+        if rule.is_some_and(|rule| !rule.follows()) {
+            return Vec::new();
+        }
+        let html = match response.to_html() {
+            Ok(html) => html,
+            Err(err) => {
+                debug!("Not following links of {}: {}", response.url, err);
+                return Vec::new();
+            }
+        };
+
+        let mut seen = HashSet::new();
+        let mut requests = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let links = match rule.extractor.extract_from(&html, &response.url) {
+                Ok(links) => links,
+                Err(err) => {
+                    warn!("Rule {} failed on {}: {}", index, response.url, err);
+                    continue;
+                }
+            };
+            for request in links {
+                if seen.insert(request.url.clone()) {
+                    requests.push(rule.request(index, request));
+                }
+            }
+        }
+        requests
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Ruled<S: Spider> {
+        async fn parse(&self, response, state) {
+            let requests = self.follow(&response);
+            let mut output = self
+                .callbacks
+                .dispatch(&self.spider, response, state)
+                .await?;
+            output.add_requests(requests);
+            Ok(output)
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Crawling a deterministic sample of the discovered pages.
+//!
+//! For a quick survey of a site's structure, [`Sampling`] follows only a fraction of the
+//! links a spider discovers. Whether a URL is followed depends only on a hash of the URL
+//! and a seed, so repeated runs crawl the same sample, and a larger rate crawls a
+//! superset of a smaller one:
+//!
+//! ```rust,ignore
+//! let sampling = Sampling::new(0.1);
+//! let crawler = CrawlerBuilder::new(sampling.wrap(MySpider)).build().await?;
+//! crawler.start_crawl().await?;
+//!
+//! println!("skipped {} discovered requests", sampling.sampled_out());
+//! ```
+//!
+//! Start requests are always crawled; the sample applies to the requests returned from
+//! `parse`. The engine's builder cannot tell the two apart, so sampling wraps the spider
+//! rather than being a builder option, and the skipped requests are counted by
+//! [`Sampling::sampled_out`] instead of the engine's statistics.
+
+use log::{debug, info};
+use spider_core::Spider;
+use spider_util::item::ParseOutput;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+#[derive(Debug)]
+struct SamplingState {
+    rate: f64,
+    seed: u64,
+    sampled_out: AtomicUsize,
+}
+
+/// A deterministic sample of discovered requests.
+///
+/// Clones share the counter, so keep one to read it after the crawl.
+#[derive(Debug, Clone)]
+pub struct Sampling {
+    state: Arc<SamplingState>,
+}
+
+impl Sampling {
+    /// Follows about `rate` of the discovered requests, e.g. `0.1` for 10%.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not between 0 and 1.
+    pub fn new(rate: f64) -> Self {
+        Self::with_seed(rate, 0)
+    }
+
+    /// Like [`new`](Self::new), but picks the sample with `seed`. Runs with the same seed
+    /// crawl the same URLs; another seed picks a different sample of the same size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not between 0 and 1.
+    pub fn with_seed(rate: f64, seed: u64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&rate),
+            "sample rate must be between 0 and 1"
+        );
+        Self {
+            state: Arc::new(SamplingState {
+                rate,
+                seed,
+                sampled_out: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the fraction of discovered requests that are followed.
+    pub fn rate(&self) -> f64 {
+        self.state.rate
+    }
+
+    /// Returns whether `url` is in the sample.
+    pub fn includes(&self, url: &Url) -> bool {
+        if self.state.rate >= 1.0 {
+            return true;
+        }
+        let threshold = (self.state.rate * u64::MAX as f64) as u64;
+        url_hash(self.state.seed, url.as_str()) < threshold
+    }
+
+    /// Returns the number of discovered requests left out of the sample so far.
+    pub fn sampled_out(&self) -> usize {
+        self.state.sampled_out.load(Ordering::Relaxed)
+    }
+
+    /// Returns the spider with its discovered requests sampled.
+    pub fn wrap<S: Spider>(&self, spider: S) -> Sampled<S> {
+        info!(
+            "Sampling {:.1}% of discovered requests",
+            self.state.rate * 100.0
+        );
+        Sampled {
+            spider,
+            sampling: self.clone(),
+        }
+    }
+}
+
+/// Hashes `seed` followed by `text` with 64-bit FNV-1a, whose result, unlike the
+/// standard library's hasher, is stable across Rust versions. FNV-1a mixes similar
+/// URLs poorly into the high bits, so the result goes through the MurmurHash3 finalizer.
+fn url_hash(seed: u64, text: &str) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = seed
+        .to_le_bytes()
+        .iter()
+        .chain(text.as_bytes())
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+        });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// A spider whose discovered requests are sampled, see [`Sampling::wrap`].
+pub struct Sampled<S> {
+    spider: S,
+    sampling: Sampling,
+}
+
+impl<S> Sampled<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Sampled<S: Spider> {
+        async fn parse(&self, response, state) {
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(items);
+            output.add_requests(requests.into_iter().filter(|request| {
+                let included = self.sampling.includes(&request.url);
+                if !included {
+                    self.sampling
+                        .state
+                        .sampled_out
+                        .fetch_add(1, Ordering::Relaxed);
+                    debug!("Sampling out request {}", request.url);
+                }
+                included
+            }));
+            Ok(output)
+        }
+    }
+}
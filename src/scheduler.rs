@@ -0,0 +1,423 @@
+//! Pluggable request scheduling.
+//!
+//! A [`RequestScheduler`] holds the requests waiting to be downloaded and decides which one
+//! goes next. This trait is the extension point for custom orders: implement it, then
+//! hand the scheduler to a crawl with a [`Scheduling`], which routes the requests of
+//! the spider's parses through it, see [`scheduling`]:
+//!
+//! ```rust,ignore
+//! let scheduling = Scheduling::new(DefaultScheduler::new());
+//! let crawler = CrawlerBuilder::new(scheduling.wrap(MySpider))
+//!     .scheduler(&scheduling)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The trait is named apart from the engine's own queue,
+//! `spider_core::scheduler::Scheduler`, which the [prelude](crate::prelude) still exports
+//! as `Scheduler`.
+//!
+//! A scheduler can also be driven without the engine, by a loop around an
+//! [`HttpDownloader`](crate::downloader::HttpDownloader):
+//!
+//! ```rust,ignore
+//! let scheduler = FairScheduler::new().max_per_domain(2);
+//! scheduler.enqueue(Request::new(start_url)).await;
+//!
+//! let downloader = HttpDownloader::new()?;
+//! while let Some(request) = scheduler.dequeue().await {
+//!     let url = request.url.clone();
+//!     let downloaded = downloader.download(request).await;
+//!     scheduler.complete(&url).await;
+//!     for next in follow_links(downloaded?)? {
+//!         scheduler.enqueue(next).await;
+//!     }
+//! }
+//! ```
+//!
+//! # Contract
+//!
+//! - Schedulers are shared between download tasks, so every method takes `&self` and
+//!   implementations must be `Send + Sync`.
+//! - Every method but [`len`](RequestScheduler::len) is async, so that implementations
+//!   backed by a disk or a remote store can await I/O. They must not block the executor.
+//! - `dequeue` returning `None` while requests are queued means none of them may run
+//!   yet, for instance because their host is at its concurrency limit. Ask again once
+//!   a dispatched request has completed.
+//! - [`complete`](RequestScheduler::complete) must be called once for every dequeued
+//!   request when it has finished, with a response or with an error. Limits built on it
+//!   hold the request's place until then.
+//! - [`snapshot`](RequestScheduler::snapshot) and [`restore`](RequestScheduler::restore)
+//!   persist the queued requests, e.g. to a checkpoint. [`Request`] is serializable.
+//!
+//! Frontiers that outgrow memory can be kept on disk with a [`DiskScheduler`].
+
+pub mod disk;
+pub mod scheduling;
+
+pub use disk::DiskScheduler;
+pub use scheduling::{Scheduled, Scheduling, SchedulingMiddleware};
+
+use crate::request::RequestExt;
+use spider_core::async_trait;
+use spider_util::request::Request;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Requests to one host allowed in flight at once by default in a [`FairScheduler`].
+const DEFAULT_MAX_PER_DOMAIN: usize = 2;
+
+/// Requests allowed in flight at once by default in a [`PriorityScheduler`] or a
+/// [`DiskScheduler`].
+const DEFAULT_MAX_IN_FLIGHT: usize = 8;
+
+/// How long a dispatched request counts against its host by default if no response
+/// arrives.
+const DEFAULT_IN_FLIGHT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Decides the order in which requests are downloaded.
+#[async_trait]
+pub trait RequestScheduler: Send + Sync {
+    /// Adds a request to the queue.
+    async fn enqueue(&self, request: Request);
+
+    /// Removes and returns the next request to download, or `None` if no queued request
+    /// may run right now.
+    async fn dequeue(&self) -> Option<Request>;
+
+    /// Returns the number of queued requests.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if no requests are queued.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Called with the request URL when a dispatched request has finished, whether it
+    /// got a response or failed.
+    async fn complete(&self, _request_url: &Url) {}
+
+    /// Returns a copy of the queued requests, in dequeue order where that is defined.
+    async fn snapshot(&self) -> Vec<Request>;
+
+    /// Adds previously snapshotted requests back to the queue.
+    async fn restore(&self, requests: Vec<Request>);
+}
+
+/// A first-in, first-out [`RequestScheduler`], matching the engine's own order.
+#[derive(Debug, Default)]
+pub struct DefaultScheduler {
+    queue: Mutex<VecDeque<Request>>,
+}
+
+impl DefaultScheduler {
+    /// Creates an empty scheduler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn queue(&self) -> std::sync::MutexGuard<'_, VecDeque<Request>> {
+        self.queue.lock().expect("scheduler queue poisoned")
+    }
+}
+
+#[async_trait]
+impl RequestScheduler for DefaultScheduler {
+    async fn enqueue(&self, request: Request) {
+        self.queue().push_back(request);
+    }
+
+    async fn dequeue(&self) -> Option<Request> {
+        self.queue().pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.queue().len()
+    }
+
+    async fn snapshot(&self) -> Vec<Request> {
+        self.queue().iter().cloned().collect()
+    }
+
+    async fn restore(&self, requests: Vec<Request>) {
+        self.queue().extend(requests);
+    }
+}
+
+/// A [`RequestScheduler`] that round-robins across hosts.
+///
+/// Each host gets its own queue, and `dequeue` takes one request from each host in turn,
+/// so a site with thousands of links cannot starve the others. A host is skipped while it
+/// has [`max_per_domain`](Self::max_per_domain) requests in flight. An in-flight
+/// request that is never [completed](RequestScheduler::complete) stops counting against its
+/// host after [`in_flight_timeout`](Self::in_flight_timeout).
+///
+/// Plugged into a crawl, it keeps a broad crawl from working through one site at a
+/// time:
+///
+/// ```rust,ignore
+/// let scheduling = Scheduling::new(FairScheduler::new().max_per_domain(2));
+/// let crawler = CrawlerBuilder::new(scheduling.wrap(MySpider))
+///     .scheduler(&scheduling)
+///     .build()
+///     .await?;
+/// ```
+#[derive(Debug)]
+pub struct FairScheduler {
+    state: Mutex<FairState>,
+    max_per_domain: usize,
+    in_flight_timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+struct FairState {
+    queues: HashMap<String, VecDeque<Request>>,
+    /// Hosts with queued requests, in the order they get their next turn.
+    rotation: VecDeque<String>,
+    in_flight: HashMap<String, VecDeque<Instant>>,
+    len: usize,
+}
+
+impl Default for FairScheduler {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(FairState::default()),
+            max_per_domain: DEFAULT_MAX_PER_DOMAIN,
+            in_flight_timeout: DEFAULT_IN_FLIGHT_TIMEOUT,
+        }
+    }
+}
+
+impl FairScheduler {
+    /// Creates an empty scheduler allowing two requests in flight per host.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many requests to one host may be in flight at once. Defaults to 2.
+    pub fn max_per_domain(mut self, limit: usize) -> Self {
+        self.max_per_domain = limit.max(1);
+        self
+    }
+
+    /// Sets how long a dispatched request counts against its host if no response is
+    /// reported. Defaults to 60 seconds.
+    pub fn in_flight_timeout(mut self, timeout: Duration) -> Self {
+        self.in_flight_timeout = timeout;
+        self
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, FairState> {
+        self.state.lock().expect("scheduler state poisoned")
+    }
+
+    fn push(state: &mut FairState, request: Request) {
+        let host = host_key(&request.url);
+        let queue = state.queues.entry(host.clone()).or_default();
+        if queue.is_empty() {
+            state.rotation.push_back(host);
+        }
+        queue.push_back(request);
+        state.len += 1;
+    }
+}
+
+#[async_trait]
+impl RequestScheduler for FairScheduler {
+    async fn enqueue(&self, request: Request) {
+        Self::push(&mut self.state(), request);
+    }
+
+    async fn dequeue(&self) -> Option<Request> {
+        let mut state = self.state();
+        let now = Instant::now();
+        for turn in 0..state.rotation.len() {
+            let host = state.rotation[turn].clone();
+            let in_flight = state.in_flight.entry(host.clone()).or_default();
+            while in_flight
+                .front()
+                .is_some_and(|sent| now.duration_since(*sent) >= self.in_flight_timeout)
+            {
+                in_flight.pop_front();
+            }
+            if in_flight.len() >= self.max_per_domain {
+                continue;
+            }
+            in_flight.push_back(now);
+
+            let queue = state
+                .queues
+                .get_mut(&host)
+                .expect("rotated host has a queue");
+            let request = queue.pop_front().expect("rotated host has requests");
+            let drained = queue.is_empty();
+            state.rotation.remove(turn);
+            if drained {
+                state.queues.remove(&host);
+            } else {
+                state.rotation.push_back(host);
+            }
+            state.len -= 1;
+            return Some(request);
+        }
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.state().len
+    }
+
+    async fn complete(&self, request_url: &Url) {
+        let mut state = self.state();
+        let host = host_key(request_url);
+        if let Some(in_flight) = state.in_flight.get_mut(&host) {
+            in_flight.pop_front();
+            if in_flight.is_empty() {
+                state.in_flight.remove(&host);
+            }
+        }
+    }
+
+    async fn snapshot(&self) -> Vec<Request> {
+        let state = self.state();
+        state
+            .rotation
+            .iter()
+            .flat_map(|host| state.queues[host].iter().cloned())
+            .collect()
+    }
+
+    async fn restore(&self, requests: Vec<Request>) {
+        let mut state = self.state();
+        for request in requests {
+            Self::push(&mut state, request);
+        }
+    }
+}
+
+/// A [`RequestScheduler`] that downloads requests with a higher
+/// [`priority`](RequestExt::priority) first.
+///
+/// Requests of equal priority keep the order they were enqueued in. At most
+/// [`max_in_flight`](Self::max_in_flight) requests are dequeued and not yet completed at
+/// once. Like in a [`FairScheduler`], an in-flight request stops counting after
+/// [`in_flight_timeout`](Self::in_flight_timeout).
+///
+/// In a crawl, requests added with `ParseOutput::add_request` keep their priority:
+///
+/// ```rust,ignore
+/// let scheduling = Scheduling::new(PriorityScheduler::new().max_in_flight(4));
+/// let crawler = CrawlerBuilder::new(scheduling.wrap(MySpider))
+///     .scheduler(&scheduling)
+///     .build()
+///     .await?;
+///
+/// // In `parse`: detail pages before the next listing page.
+/// output.add_request(Request::new(next_page_url));
+/// output.add_request(Request::new(detail_url).priority(10));
+/// ```
+#[derive(Debug)]
+pub struct PriorityScheduler {
+    state: Mutex<PriorityState>,
+    max_in_flight: usize,
+    in_flight_timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+struct PriorityState {
+    /// Queued requests keyed by descending priority, then insertion order.
+    queue: BTreeMap<(Reverse<i32>, u64), Request>,
+    next_seq: u64,
+    in_flight: VecDeque<Instant>,
+}
+
+impl Default for PriorityScheduler {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(PriorityState::default()),
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            in_flight_timeout: DEFAULT_IN_FLIGHT_TIMEOUT,
+        }
+    }
+}
+
+impl PriorityScheduler {
+    /// Creates an empty scheduler allowing eight requests in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many requests may be in flight at once. Defaults to 8.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = limit.max(1);
+        self
+    }
+
+    /// Sets how long a dispatched request counts against the limit if no response is
+    /// reported. Defaults to 60 seconds.
+    pub fn in_flight_timeout(mut self, timeout: Duration) -> Self {
+        self.in_flight_timeout = timeout;
+        self
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, PriorityState> {
+        self.state.lock().expect("scheduler state poisoned")
+    }
+
+    fn push(state: &mut PriorityState, request: Request) {
+        let key = (Reverse(request.priority_value()), state.next_seq);
+        state.next_seq += 1;
+        state.queue.insert(key, request);
+    }
+}
+
+#[async_trait]
+impl RequestScheduler for PriorityScheduler {
+    async fn enqueue(&self, request: Request) {
+        Self::push(&mut self.state(), request);
+    }
+
+    async fn dequeue(&self) -> Option<Request> {
+        let mut state = self.state();
+        let now = Instant::now();
+        while state
+            .in_flight
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= self.in_flight_timeout)
+        {
+            state.in_flight.pop_front();
+        }
+        if state.in_flight.len() >= self.max_in_flight {
+            return None;
+        }
+        let (_, request) = state.queue.pop_first()?;
+        state.in_flight.push_back(now);
+        Some(request)
+    }
+
+    fn len(&self) -> usize {
+        self.state().queue.len()
+    }
+
+    async fn complete(&self, _request_url: &Url) {
+        self.state().in_flight.pop_front();
+    }
+
+    async fn snapshot(&self) -> Vec<Request> {
+        self.state().queue.values().cloned().collect()
+    }
+
+    async fn restore(&self, requests: Vec<Request>) {
+        let mut state = self.state();
+        for request in requests {
+            Self::push(&mut state, request);
+        }
+    }
+}
+
+fn host_key(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_ascii_lowercase()
+}
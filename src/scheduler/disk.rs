@@ -0,0 +1,681 @@
+//! A [`RequestScheduler`] keeping its queue on disk.
+//!
+//! Crawls that queue millions of requests outgrow memory. A [`DiskScheduler`] writes
+//! every queued request to a journal in a directory and keeps only the position of each
+//! entry in memory, together with a bounded buffer of the highest-priority requests. It
+//! plugs into a crawl like any scheduler:
+//!
+//! ```rust,ignore
+//! let scheduler = DiskScheduler::open("crawl-queue").await?.max_in_flight(16);
+//! let scheduling = Scheduling::new(scheduler);
+//! let crawler = CrawlerBuilder::new(scheduling.wrap(MySpider))
+//!     .scheduler(&scheduling)
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! Requests are downloaded by [`priority`](crate::request::RequestExt::priority), then
+//! in the order they were queued, like in a [`PriorityScheduler`](super::PriorityScheduler).
+//!
+//! # Duplicates
+//!
+//! The scheduler remembers a 64-bit hash of the fingerprint of every request it
+//! queued, and drops requests it has seen before, also across restarts. Retries and
+//! requests exempted with [`dont_filter`](crate::request::RequestExt::dont_filter) are
+//! neither checked nor remembered. The hashes are kept in memory, 8 bytes per request
+//! plus the set's overhead.
+//!
+//! # Durability
+//!
+//! A request is appended to `queue.jsonl`, together with its hash, before `enqueue`
+//! returns, and its sequence number is appended to `done.log` once it is
+//! [completed](RequestScheduler::complete). Opening a directory again queues every request
+//! that was not completed and remembers every hash in the journal, so a crawl that
+//! dies, even in the middle of a write, resumes without losing requests, and the
+//! duplicate hashes always match the queue: a request is in both or in neither.
+//! Requests that were in flight are queued again and may be downloaded twice. A partly
+//! written last line is cut off on open.
+//!
+//! Opening reads the journal but leaves it as it is. Once more than half of it is
+//! finished, it is rewritten without the finished entries in the background, after
+//! the hashes of the dropped entries are saved to `seen.log`.
+//!
+//! The queue is the checkpoint: the engine's own checkpoint does not cover it, and the
+//! wrapped spider sends a slot for every request left in the queue with its start
+//! requests. Reading and writing the files happens on Tokio's blocking threads, so a
+//! slow disk never stalls the executor.
+
+use super::{DEFAULT_IN_FLIGHT_TIMEOUT, DEFAULT_MAX_IN_FLIGHT, RequestScheduler};
+use crate::request::RequestExt;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use spider_core::{async_trait, tokio};
+use spider_util::request::Request;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+const QUEUE_FILE: &str = "queue.jsonl";
+const DONE_FILE: &str = "done.log";
+const SEEN_FILE: &str = "seen.log";
+
+/// Requests kept in memory by default.
+const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+/// Finished entries below which the journal is never compacted while crawling.
+const MIN_COMPACTION: usize = 10_000;
+
+/// Dequeue order: higher priorities first, then insertion order.
+type Key = (Reverse<i32>, u64);
+
+#[derive(Debug)]
+struct Entry {
+    seq: u64,
+    priority: i32,
+    /// The hash the request is remembered by, unless it is exempt from duplicate
+    /// filtering or already saved in the seen log.
+    fingerprint: Option<u64>,
+    request: Request,
+}
+
+/// A dequeued request that was not completed yet.
+#[derive(Debug)]
+struct Dispatched {
+    entry: Entry,
+    sent: Instant,
+    /// Whether the request still counts against the in-flight limit.
+    counted: bool,
+}
+
+/// A journal line. `Request`'s own serialization leaves out the meta and cannot read
+/// back a request without a body, so both are handled here.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    seq: u64,
+    priority: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<u64>,
+    request: Value,
+    #[serde(default)]
+    meta: serde_json::Map<String, Value>,
+}
+
+/// A [`RequestScheduler`] keeping its queue in a directory, see the [module docs](self).
+#[derive(Debug)]
+pub struct DiskScheduler {
+    dir: PathBuf,
+    shared: Arc<Shared>,
+    buffer_size: usize,
+    max_in_flight: usize,
+    in_flight_timeout: Duration,
+}
+
+/// What the blocking threads working on the queue share with the scheduler.
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<DiskState>,
+    /// The number of queued requests, readable without waiting for the state.
+    len: AtomicUsize,
+    compacting: AtomicBool,
+}
+
+impl Shared {
+    fn state(&self) -> std::sync::MutexGuard<'_, DiskState> {
+        self.state.lock().expect("scheduler state poisoned")
+    }
+}
+
+#[derive(Debug)]
+struct DiskState {
+    dir: PathBuf,
+    journal: File,
+    reader: File,
+    journal_len: u64,
+    done: File,
+    /// Journal offset of every queued request.
+    index: BTreeMap<Key, u64>,
+    /// The first queued requests, read ahead from the journal.
+    buffer: BTreeMap<Key, Request>,
+    /// Dispatched requests by URL, kept until they are completed so compaction does not
+    /// drop them from the journal.
+    in_flight: HashMap<String, VecDeque<Dispatched>>,
+    in_flight_count: usize,
+    /// Hashes of every request queued so far, see [`fingerprint_hash`].
+    seen: HashSet<u64>,
+    next_seq: u64,
+    finished: usize,
+}
+
+impl DiskScheduler {
+    /// Opens the queue in `dir`, creating the directory if needed, and queues the
+    /// requests left unfinished by an earlier crawl.
+    pub async fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let opened = dir.clone();
+        let state = tokio::task::spawn_blocking(move || DiskState::open(opened))
+            .await
+            .expect("disk scheduler task panicked")?;
+        let len = state.index.len();
+        Ok(Self {
+            dir,
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                len: AtomicUsize::new(len),
+                compacting: AtomicBool::new(false),
+            }),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            in_flight_timeout: DEFAULT_IN_FLIGHT_TIMEOUT,
+        })
+    }
+
+    /// Sets how many of the first queued requests are kept in memory. Defaults to 1024.
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size.max(1);
+        let mut state = self.shared.state();
+        while state.buffer.len() > self.buffer_size {
+            state.buffer.pop_last();
+        }
+        drop(state);
+        self
+    }
+
+    /// Sets how many requests may be in flight at once. Defaults to 8.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = limit.max(1);
+        self
+    }
+
+    /// Sets how long a dispatched request counts against the limit if no response is
+    /// reported. Defaults to 60 seconds.
+    pub fn in_flight_timeout(mut self, timeout: Duration) -> Self {
+        self.in_flight_timeout = timeout;
+        self
+    }
+
+    /// Returns the directory holding the queue.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Runs `f` with the state on a blocking thread, then updates the queue length.
+    async fn with_state<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DiskState) -> T + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut state = shared.state();
+            let result = f(&mut state);
+            shared.len.store(state.index.len(), Ordering::Relaxed);
+            result
+        })
+        .await
+        .expect("disk scheduler task panicked")
+    }
+
+    /// Rewrites the journal on a blocking thread, unless that is already happening.
+    fn compact_in_background(&self) {
+        if self.shared.compacting.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let shared = self.shared.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut state = shared.state();
+            if let Err(err) = state.compact() {
+                warn!("Cannot compact the queue in {:?}: {}", state.dir, err);
+            }
+            shared.compacting.store(false, Ordering::Release);
+        });
+    }
+}
+
+impl DiskState {
+    /// Opens the files in `dir` and indexes the unfinished entries of the journal.
+    fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let journal_path = dir.join(QUEUE_FILE);
+        let done_path = dir.join(DONE_FILE);
+
+        let (finished, done_len) = read_numbers(&done_path)?;
+        let (mut seen, _) = read_numbers(&dir.join(SEEN_FILE))?;
+        let scan = scan_journal(&journal_path, &finished, &mut seen)?;
+
+        // Cut off partly written last lines, so new lines start on their own.
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)?;
+        journal.set_len(scan.len)?;
+        let done = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&done_path)?;
+        done.set_len(done_len)?;
+
+        let mut state = Self {
+            reader: File::open(&journal_path)?,
+            dir,
+            journal,
+            journal_len: scan.len,
+            done,
+            index: scan.index,
+            buffer: BTreeMap::new(),
+            in_flight: HashMap::new(),
+            in_flight_count: 0,
+            seen,
+            next_seq: scan.next_seq,
+            finished: scan.finished,
+        };
+        state.refill(DEFAULT_BUFFER_SIZE);
+        Ok(state)
+    }
+
+    /// Appends `request` to the journal and queues it, unless it was queued before. A
+    /// request that cannot be written is not queued, since it could not survive a
+    /// restart anyway.
+    fn push(&mut self, request: Request, buffer_size: usize) {
+        let fingerprint = (request.filters_duplicates() && request.get_retry_attempts() == 0)
+            .then(|| fingerprint_hash(&request));
+        if fingerprint.is_some_and(|hash| self.seen.contains(&hash)) {
+            debug!("Not queueing a request already seen: {}", request.url);
+            return;
+        }
+        let entry = Entry {
+            seq: self.next_seq,
+            priority: request.priority_value(),
+            fingerprint,
+            request,
+        };
+        let line = match encode(&entry) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Cannot queue {}: {}", entry.request.url, err);
+                return;
+            }
+        };
+        if let Err(err) = self.journal.write_all(&line) {
+            error!("Cannot write {} to the queue: {}", entry.request.url, err);
+            return;
+        }
+        self.next_seq += 1;
+        if let Some(hash) = fingerprint {
+            self.seen.insert(hash);
+        }
+
+        let key = key(&entry);
+        self.index.insert(key, self.journal_len);
+        self.journal_len += line.len() as u64;
+        let ranks_in_buffer = self.buffer.len() < buffer_size
+            || self
+                .buffer
+                .last_key_value()
+                .is_some_and(|(last, _)| key < *last);
+        if ranks_in_buffer {
+            self.buffer.insert(key, entry.request);
+            if self.buffer.len() > buffer_size {
+                self.buffer.pop_last();
+            }
+        }
+    }
+
+    /// Reads the request at `offset` from the journal.
+    fn load(&mut self, offset: u64) -> io::Result<Request> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut line = String::new();
+        BufReader::new(&mut self.reader).read_line(&mut line)?;
+        Ok(decode(&line)?.request)
+    }
+
+    /// Reads ahead the first queued requests once the buffer has run dry.
+    fn refill(&mut self, buffer_size: usize) {
+        if !self.buffer.is_empty() {
+            return;
+        }
+        let next: Vec<(Key, u64)> = self
+            .index
+            .iter()
+            .take(buffer_size)
+            .map(|(key, offset)| (*key, *offset))
+            .collect();
+        for (key, offset) in next {
+            match self.load(offset) {
+                Ok(request) => {
+                    self.buffer.insert(key, request);
+                }
+                Err(err) => {
+                    warn!("Cannot read ahead queued request {}: {}", key.1, err);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns whether finished entries make up most of the journal.
+    fn needs_compaction(&self) -> bool {
+        self.finished >= MIN_COMPACTION && self.finished >= self.index.len()
+    }
+
+    /// Rewrites the journal without finished entries once they make up most of it.
+    ///
+    /// The seen log is saved first, so the hashes of the dropped entries survive a crash
+    /// at any point.
+    fn compact(&mut self) -> io::Result<()> {
+        if !self.needs_compaction() {
+            return Ok(());
+        }
+        let seen_path = self.dir.join(SEEN_FILE);
+        let seen_temp = self.dir.join(format!("{}.tmp", SEEN_FILE));
+        {
+            let mut temp = BufWriter::new(File::create(&seen_temp)?);
+            for hash in &self.seen {
+                writeln!(temp, "{}", hash)?;
+            }
+            temp.into_inner()?.sync_all()?;
+        }
+        fs::rename(&seen_temp, &seen_path)?;
+
+        let journal_path = self.dir.join(QUEUE_FILE);
+        let temp_path = self.dir.join(format!("{}.tmp", QUEUE_FILE));
+        let queued: Vec<(Key, u64)> = self.index.iter().map(|(k, o)| (*k, *o)).collect();
+        let mut index = BTreeMap::new();
+        let mut offset = 0;
+        {
+            let mut temp = File::create(&temp_path)?;
+            for dispatched in self.in_flight.values().flatten() {
+                let line = encode(&dispatched.entry)?;
+                temp.write_all(&line)?;
+                offset += line.len() as u64;
+            }
+            for ((priority, seq), old_offset) in queued {
+                let request = match self.buffer.get(&(priority, seq)) {
+                    Some(request) => request.clone(),
+                    None => self.load(old_offset)?,
+                };
+                let line = encode(&Entry {
+                    seq,
+                    priority: priority.0,
+                    fingerprint: None,
+                    request,
+                })?;
+                temp.write_all(&line)?;
+                index.insert((priority, seq), offset);
+                offset += line.len() as u64;
+            }
+            temp.sync_all()?;
+        }
+        fs::rename(&temp_path, &journal_path)?;
+        self.journal = OpenOptions::new().append(true).open(&journal_path)?;
+        self.reader = File::open(&journal_path)?;
+        self.journal_len = offset;
+        self.index = index;
+        self.done = File::create(self.dir.join(DONE_FILE))?;
+        self.finished = 0;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RequestScheduler for DiskScheduler {
+    async fn enqueue(&self, request: Request) {
+        let buffer_size = self.buffer_size;
+        self.with_state(move |state| state.push(request, buffer_size))
+            .await;
+    }
+
+    async fn dequeue(&self) -> Option<Request> {
+        let (buffer_size, max_in_flight, timeout) =
+            (self.buffer_size, self.max_in_flight, self.in_flight_timeout);
+        self.with_state(move |state| {
+            let now = Instant::now();
+            let mut expired = 0;
+            for dispatched in state.in_flight.values_mut().flatten() {
+                if dispatched.counted && now.duration_since(dispatched.sent) >= timeout {
+                    dispatched.counted = false;
+                    expired += 1;
+                }
+            }
+            state.in_flight_count -= expired;
+            if state.in_flight_count >= max_in_flight {
+                return None;
+            }
+
+            loop {
+                let (key, offset) = state.index.pop_first()?;
+                let request = match state.buffer.remove(&key) {
+                    Some(request) => request,
+                    None => match state.load(offset) {
+                        Ok(request) => request,
+                        Err(err) => {
+                            error!("Skipping unreadable queued request {}: {}", key.1, err);
+                            continue;
+                        }
+                    },
+                };
+                state.refill(buffer_size);
+                // Compaction saves the hash in the seen log before it rewrites the entry.
+                let entry = Entry {
+                    seq: key.1,
+                    priority: key.0.0,
+                    fingerprint: None,
+                    request: request.clone(),
+                };
+                state
+                    .in_flight
+                    .entry(request.url.to_string())
+                    .or_default()
+                    .push_back(Dispatched {
+                        entry,
+                        sent: now,
+                        counted: true,
+                    });
+                state.in_flight_count += 1;
+                return Some(request);
+            }
+        })
+        .await
+    }
+
+    fn len(&self) -> usize {
+        self.shared.len.load(Ordering::Relaxed)
+    }
+
+    /// Marks the oldest in-flight request for `request_url` as finished, so it is not
+    /// queued again when the directory is reopened. Compaction, when it is due, runs
+    /// in the background.
+    async fn complete(&self, request_url: &Url) {
+        let url = request_url.to_string();
+        let compact = self
+            .with_state(move |state| {
+                let Some(dispatched) = state.in_flight.get_mut(&url) else {
+                    return false;
+                };
+                let Some(finished) = dispatched.pop_front() else {
+                    return false;
+                };
+                if dispatched.is_empty() {
+                    state.in_flight.remove(&url);
+                }
+                if finished.counted {
+                    state.in_flight_count -= 1;
+                }
+                if let Err(err) = writeln!(state.done, "{}", finished.entry.seq) {
+                    warn!("Cannot record {} as finished: {}", url, err);
+                    return false;
+                }
+                state.finished += 1;
+                state.needs_compaction()
+            })
+            .await;
+        if compact {
+            self.compact_in_background();
+        }
+    }
+
+    async fn snapshot(&self) -> Vec<Request> {
+        self.with_state(|state| {
+            let queued: Vec<(Key, u64)> = state.index.iter().map(|(k, o)| (*k, *o)).collect();
+            queued
+                .into_iter()
+                .filter_map(|(key, offset)| match state.buffer.get(&key) {
+                    Some(request) => Some(request.clone()),
+                    None => state.load(offset).ok(),
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn restore(&self, requests: Vec<Request>) {
+        let buffer_size = self.buffer_size;
+        self.with_state(move |state| {
+            for request in requests {
+                state.push(request, buffer_size);
+            }
+        })
+        .await;
+    }
+}
+
+fn key(entry: &Entry) -> Key {
+    (Reverse(entry.priority), entry.seq)
+}
+
+fn encode(entry: &Entry) -> io::Result<Vec<u8>> {
+    let mut request = serde_json::to_value(&entry.request)?;
+    if let Some(fields) = request.as_object_mut()
+        && fields.get("body").is_some_and(Value::is_null)
+    {
+        fields.remove("body");
+    }
+    let meta = entry
+        .request
+        .meta
+        .iter()
+        .map(|pair| (pair.key().to_string(), pair.value().clone()))
+        .collect();
+    let record = Record {
+        seq: entry.seq,
+        priority: entry.priority,
+        fingerprint: entry.fingerprint,
+        request,
+        meta,
+    };
+    let mut line = serde_json::to_vec(&record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn decode(line: &str) -> serde_json::Result<Entry> {
+    let record: Record = serde_json::from_str(line)?;
+    let request: Request = serde_json::from_value(record.request)?;
+    for (key, value) in record.meta {
+        request.meta.insert(key.into(), value);
+    }
+    Ok(Entry {
+        seq: record.seq,
+        priority: record.priority,
+        fingerprint: record.fingerprint,
+        request,
+    })
+}
+
+/// Hashes the fingerprint of `request` with 64-bit FNV-1a, which, unlike the standard
+/// library's hasher, stays the same across builds.
+fn fingerprint_hash(request: &Request) -> u64 {
+    request
+        .fingerprint()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Reads a file of one number per line, returning the numbers and the length of its
+/// complete lines. A missing file is empty.
+fn read_numbers(path: &Path) -> io::Result<(HashSet<u64>, u64)> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err),
+    };
+    let mut numbers = HashSet::new();
+    let mut len = 0;
+    for line in contents.split_inclusive('\n') {
+        if !line.ends_with('\n') {
+            warn!("Discarding a partly written line at the end of {:?}", path);
+            break;
+        }
+        len += line.len() as u64;
+        if let Ok(number) = line.trim().parse() {
+            numbers.insert(number);
+        }
+    }
+    Ok((numbers, len))
+}
+
+/// What [`scan_journal`] found in the journal.
+struct JournalScan {
+    /// Journal offset of every unfinished entry.
+    index: BTreeMap<Key, u64>,
+    /// The length of the complete lines.
+    len: u64,
+    next_seq: u64,
+    finished: usize,
+}
+
+/// Reads the journal, indexing the entries not in `finished` and adding every hash to
+/// `seen`.
+fn scan_journal(
+    path: &Path,
+    finished: &HashSet<u64>,
+    seen: &mut HashSet<u64>,
+) -> io::Result<JournalScan> {
+    let mut scan = JournalScan {
+        index: BTreeMap::new(),
+        len: 0,
+        next_seq: 0,
+        finished: 0,
+    };
+    let journal = match File::open(path) {
+        Ok(journal) => journal,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(scan),
+        Err(err) => return Err(err),
+    };
+
+    let mut reader = BufReader::new(journal);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        if !line.ends_with('\n') {
+            warn!("Discarding a partly written request at the end of the queue");
+            break;
+        }
+        let offset = scan.len;
+        scan.len += line.len() as u64;
+        match decode(&line) {
+            Ok(entry) => {
+                scan.next_seq = scan.next_seq.max(entry.seq + 1);
+                seen.extend(entry.fingerprint);
+                if finished.contains(&entry.seq) {
+                    scan.finished += 1;
+                } else {
+                    scan.index.insert(key(&entry), offset);
+                }
+            }
+            Err(err) => warn!("Skipping an unreadable queued request: {}", err),
+        }
+    }
+    Ok(scan)
+}
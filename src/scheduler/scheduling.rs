@@ -0,0 +1,289 @@
+//! Driving a crawl's requests through a custom [`RequestScheduler`].
+//!
+//! The engine keeps its own FIFO queue. [`Scheduling`] puts a [`RequestScheduler`] in front of
+//! it: the wrapped spider hands every request its parse returns to the scheduler, and
+//! sends a placeholder "slot" request to the engine in its place. When the engine
+//! dispatches a slot, the middleware sends the request the scheduler dequeues instead.
+//! If the scheduler holds its requests back, for instance because their host is at its
+//! limit, the slot is parked in the engine's queue with a short retry delay and asks
+//! again when it comes back. There is one slot for every request the scheduler holds, so
+//! held requests keep the crawl alive until they are sent.
+//!
+//! ```rust,ignore
+//! let scheduling = Scheduling::new(FairScheduler::new().max_per_domain(2));
+//! let crawler = CrawlerBuilder::new(scheduling.wrap(MySpider))
+//!     .scheduler(&scheduling)
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//! ```
+//!
+//! Start requests go to the engine directly, followed by a slot for every request
+//! already in the scheduler, e.g. in a reopened [`DiskScheduler`](super::DiskScheduler).
+//! A dispatched request is [completed](RequestScheduler::complete) when its response or
+//! download error reaches the middleware.
+//!
+//! Slots carry unique URL fragments, so the engine's duplicate filter never sees the
+//! scheduled requests. The middleware therefore keeps a [`DedupSet`] of its own and
+//! drops requests already scheduled or started before they reach the scheduler. Turn it
+//! off with [`Scheduling::without_dedup`] for a scheduler that drops duplicates itself.
+//!
+//! The engine counts every park as a retried request, and a slot that finds the
+//! scheduler empty is dropped and counted as a dropped request. [`Scheduling::traffic`]
+//! counts both, so the readers of the statistics can leave them out, see
+//! [Synthetic traffic](crate::stats#synthetic-traffic):
+//!
+//! ```rust,ignore
+//! let stats = crawler.get_stats();
+//! crawler.start_crawl().await?;
+//! let snapshot = stats.snapshot().excluding(scheduling.traffic());
+//! ```
+
+use super::RequestScheduler;
+use crate::middleware::dupe_filter::DedupSet;
+use crate::response::ResponseExt;
+use crate::stats::SyntheticTraffic;
+use log::{debug, trace, warn};
+use spider_core::{Spider, async_trait};
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_util::{error::SpiderError, item::ParseOutput, request::Request, response::Response};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use url::Url;
+
+/// Meta key marking a placeholder request parked by a [`SchedulingMiddleware`].
+pub const SCHEDULER_SLOT_KEY: &str = "scheduler_slot";
+
+/// Meta key marking a request sent by a [`SchedulingMiddleware`] in place of a slot.
+pub const SCHEDULED_KEY: &str = "scheduled";
+
+/// How long a slot waits in the engine's queue before asking the scheduler again, by
+/// default.
+const DEFAULT_PARK_DELAY: Duration = Duration::from_millis(50);
+
+/// Routes the requests of a crawl through a [`RequestScheduler`], see the [module docs](self).
+///
+/// Clones share the same scheduler, so the wrapped spider and the middleware agree on
+/// it.
+#[derive(Clone)]
+pub struct Scheduling {
+    scheduler: Arc<dyn RequestScheduler>,
+    seen: Option<Arc<DedupSet>>,
+    park_delay: Duration,
+    next_slot: Arc<AtomicU64>,
+    traffic: SyntheticTraffic,
+}
+
+impl Scheduling {
+    /// Routes requests through `scheduler`.
+    pub fn new(scheduler: impl RequestScheduler + 'static) -> Self {
+        Self::from_arc(Arc::new(scheduler))
+    }
+
+    /// Routes requests through a scheduler that is also used elsewhere, e.g. to
+    /// snapshot it.
+    pub fn from_arc(scheduler: Arc<dyn RequestScheduler>) -> Self {
+        Self {
+            scheduler,
+            seen: Some(Arc::new(DedupSet::exact())),
+            park_delay: DEFAULT_PARK_DELAY,
+            next_slot: Arc::new(AtomicU64::new(0)),
+            traffic: SyntheticTraffic::new(),
+        }
+    }
+
+    /// Remembers scheduled requests in `set` instead of an exact [`DedupSet`].
+    pub fn dedup_set(mut self, set: DedupSet) -> Self {
+        self.seen = Some(Arc::new(set));
+        self
+    }
+
+    /// Hands every request to the scheduler, duplicates included.
+    pub fn without_dedup(mut self) -> Self {
+        self.seen = None;
+        self
+    }
+
+    /// Sets how long a held-back request waits before the scheduler is asked again.
+    /// Defaults to 50 milliseconds.
+    pub fn park_delay(mut self, delay: Duration) -> Self {
+        self.park_delay = delay;
+        self
+    }
+
+    /// Returns the scheduler requests are routed through.
+    pub fn scheduler(&self) -> &Arc<dyn RequestScheduler> {
+        &self.scheduler
+    }
+
+    /// Returns the parks and dropped slots the engine counted as retried and dropped
+    /// requests.
+    pub fn traffic(&self) -> &SyntheticTraffic {
+        &self.traffic
+    }
+
+    /// Returns the spider with the requests of its parses routed through the scheduler.
+    pub fn wrap<S: Spider>(&self, spider: S) -> Scheduled<S> {
+        Scheduled {
+            spider,
+            scheduling: self.clone(),
+        }
+    }
+
+    /// Returns the middleware sending scheduled requests in place of their slots. Add
+    /// it to the crawler first.
+    pub fn middleware(&self) -> SchedulingMiddleware {
+        SchedulingMiddleware {
+            scheduling: self.clone(),
+        }
+    }
+
+    /// Returns `false` if `request` was already scheduled or started.
+    fn admits(&self, request: &Request) -> bool {
+        self.seen.as_ref().is_none_or(|seen| seen.admits(request))
+    }
+
+    /// Queues `request` and returns the slot standing for it in the engine's queue.
+    async fn schedule(&self, request: Request) -> Option<Request> {
+        if !self.admits(&request) {
+            debug!("Not scheduling a request already seen: {}", request.url);
+            return None;
+        }
+        let slot = self.slot(&request.url);
+        self.scheduler.enqueue(request).await;
+        Some(slot)
+    }
+
+    /// Returns a new slot. Each slot gets its own URL fragment, so the engine's
+    /// duplicate filter never takes it for a visited page.
+    fn slot(&self, like: &Url) -> Request {
+        let slot = self.next_slot.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut url = like.clone();
+        url.set_fragment(Some(&format!("scheduler-slot-{}", slot)));
+        Request::new(url).with_meta(SCHEDULER_SLOT_KEY, true.into())
+    }
+
+    async fn complete(&self, request_url: &Url) {
+        self.scheduler.complete(request_url).await;
+    }
+}
+
+/// Sends the requests of a [`Scheduling`] in place of their slots.
+#[derive(Clone)]
+pub struct SchedulingMiddleware {
+    scheduling: Scheduling,
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for SchedulingMiddleware {
+    fn name(&self) -> &str {
+        "SchedulingMiddleware"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if !request.meta.contains_key(SCHEDULER_SLOT_KEY) {
+            if !request.meta.contains_key(SCHEDULED_KEY) {
+                // A start request: remember it, so links back to it are not scheduled.
+                self.scheduling.admits(&request);
+            }
+            return Ok(MiddlewareAction::Continue(request));
+        }
+
+        let scheduler = &self.scheduling.scheduler;
+        if let Some(next) = scheduler.dequeue().await {
+            trace!("Scheduler released {}", next.url);
+            return Ok(MiddlewareAction::Continue(
+                next.with_meta(SCHEDULED_KEY, true.into()),
+            ));
+        }
+        if scheduler.is_empty() {
+            trace!("Scheduler is empty, dropping slot {}", request.url);
+            self.scheduling.traffic.record_drop();
+            return Ok(MiddlewareAction::Drop);
+        }
+        trace!(
+            "Scheduler is holding {} requests, parking a slot",
+            scheduler.len()
+        );
+        self.scheduling.traffic.record_retry();
+        Ok(MiddlewareAction::Retry(
+            Box::new(request),
+            self.scheduling.park_delay,
+        ))
+    }
+
+    async fn process_response(
+        &mut self,
+        response: Response,
+    ) -> Result<MiddlewareAction<Response>, SpiderError> {
+        if response.meta.contains_key(SCHEDULED_KEY) {
+            self.scheduling
+                .complete(&response.original_request_url())
+                .await;
+        }
+        Ok(MiddlewareAction::Continue(response))
+    }
+
+    async fn handle_error(
+        &mut self,
+        request: &Request,
+        error: &SpiderError,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        if request.meta.contains_key(SCHEDULED_KEY) {
+            self.scheduling.complete(&request.url).await;
+        }
+        Err(error.clone())
+    }
+}
+
+/// A spider whose parsed requests go through a [`Scheduling`], see
+/// [`Scheduling::wrap`].
+pub struct Scheduled<S> {
+    spider: S,
+    scheduling: Scheduling,
+}
+
+impl<S> Scheduled<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Scheduled<S: Spider> {
+        fn start_requests(&self) {
+            let mut requests = self.spider.start_requests()?;
+            let queued = self.scheduling.scheduler.len();
+            match requests.first() {
+                Some(first) => {
+                    let slots: Vec<Request> =
+                        (0..queued).map(|_| self.scheduling.slot(&first.url)).collect();
+                    requests.extend(slots);
+                }
+                None if queued > 0 => {
+                    warn!("No start request to resume the {} scheduled requests with", queued);
+                }
+                None => {}
+            }
+            Ok(requests)
+        }
+
+        async fn parse(&self, response, state) {
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(items);
+            for request in requests {
+                if let Some(slot) = self.scheduling.schedule(request).await {
+                    output.add_request(slot);
+                }
+            }
+            Ok(output)
+        }
+    }
+}
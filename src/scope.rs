@@ -0,0 +1,125 @@
+//! Per-spider URL scope as allowed and denied regexes.
+//!
+//! Offsite filtering works on domains and is configured once per crawl. A [`ScopedSpider`]
+//! declares its own scope next to its parsing code, as regexes over the full URL, so
+//! path-level rules travel with the spider, also when several spiders run in one process:
+//!
+//! ```rust,ignore
+//! impl ScopedSpider for BooksSpider {
+//!     fn allowed_url_patterns(&self) -> Vec<&'static str> {
+//!         vec![r"^https://books\.toscrape\.com/catalogue/"]
+//!     }
+//!
+//!     fn denied_url_patterns(&self) -> Vec<&'static str> {
+//!         vec![r"/category/", r"\?sort="]
+//!     }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(BooksSpider.scoped()?).build().await?;
+//! ```
+//!
+//! [`ScopedSpider::scoped`] compiles the patterns once and fails on an invalid one, before
+//! the crawl starts. Every request the spider returns from `parse` is then checked before
+//! it is scheduled: a URL matching a denied pattern is dropped, even when it also matches
+//! an allowed one, and with allowed patterns declared, a URL must match one of them.
+//! Start requests are not filtered.
+
+use log::debug;
+use regex::RegexSet;
+use spider_core::Spider;
+use spider_util::{error::SpiderError, item::ParseOutput};
+use url::Url;
+
+/// A spider that declares which URLs it may request.
+pub trait ScopedSpider: Spider + Sized {
+    /// Returns regexes of which requested URLs must match at least one. No patterns, the
+    /// default, allow every URL.
+    fn allowed_url_patterns(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Returns regexes of URLs that are never requested. They take precedence over the
+    /// allowed patterns.
+    fn denied_url_patterns(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Compiles the spider's patterns and wraps it so requests outside its scope are
+    /// dropped. Fails with a configuration error naming the first invalid pattern.
+    fn scoped(self) -> Result<Scoped<Self>, SpiderError> {
+        let scope = UrlScope::new(&self.allowed_url_patterns(), &self.denied_url_patterns())?;
+        Ok(Scoped {
+            spider: self,
+            scope,
+        })
+    }
+}
+
+/// Compiled allowed and denied URL patterns.
+#[derive(Debug, Clone)]
+pub struct UrlScope {
+    allowed: RegexSet,
+    denied: RegexSet,
+}
+
+impl UrlScope {
+    /// Compiles `allowed` and `denied`, see [`ScopedSpider`] for how they apply.
+    pub fn new(allowed: &[&str], denied: &[&str]) -> Result<Self, SpiderError> {
+        Ok(Self {
+            allowed: compile(allowed, "allowed")?,
+            denied: compile(denied, "denied")?,
+        })
+    }
+
+    /// Returns whether `url` is in scope.
+    pub fn allows(&self, url: &Url) -> bool {
+        let url = url.as_str();
+        !self.denied.is_match(url) && (self.allowed.is_empty() || self.allowed.is_match(url))
+    }
+}
+
+fn compile(patterns: &[&str], kind: &str) -> Result<RegexSet, SpiderError> {
+    for pattern in patterns {
+        regex::Regex::new(pattern).map_err(|e| {
+            SpiderError::ConfigurationError(format!("invalid {kind} URL pattern {pattern:?}: {e}"))
+        })?;
+    }
+    RegexSet::new(patterns).map_err(|e| SpiderError::ConfigurationError(e.to_string()))
+}
+
+/// A [`ScopedSpider`] whose out-of-scope requests are dropped, see
+/// [`ScopedSpider::scoped`].
+pub struct Scoped<S> {
+    spider: S,
+    scope: UrlScope,
+}
+
+impl<S> Scoped<S> {
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+
+    /// Returns the compiled scope.
+    pub fn scope(&self) -> &UrlScope {
+        &self.scope
+    }
+}
+
+impl_spider_wrapper! {
+    impl Spider for Scoped<S: Spider> {
+        async fn parse(&self, response, state) {
+            let (items, requests) = self.spider.parse(response, state).await?.into_parts();
+            let mut output = ParseOutput::new();
+            output.add_items(items);
+            output.add_requests(requests.into_iter().filter(|request| {
+                let allowed = self.scope.allows(&request.url);
+                if !allowed {
+                    debug!("Dropping out-of-scope request {}", request.url);
+                }
+                allowed
+            }));
+            Ok(output)
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Start requests built asynchronously.
+//!
+//! `Spider::start_requests` is synchronous, so seeds that come from a database, an API or
+//! a file read with async I/O cannot be produced there. An [`AsyncStartSpider`] builds
+//! its start requests in an async method instead, and [`AsyncStartSpider::seeded`] wraps
+//! it so the engine gets the result as its start requests:
+//!
+//! ```rust,ignore
+//! #[async_trait]
+//! impl AsyncStartSpider for ProductsSpider {
+//!     async fn start_requests_async(&self) -> Result<Vec<Request>, SpiderError> {
+//!         let ids = self.db.pending_product_ids().await?;
+//!         ids.into_iter()
+//!             .map(|id| {
+//!                 let url = Url::parse(&format!("https://api.example.com/products/{id}"))?;
+//!                 Request::new(url).with_header("Authorization", &self.token)
+//!             })
+//!             .collect()
+//!     }
+//! }
+//!
+//! let crawler = CrawlerBuilder::new(ProductsSpider::new(db).seeded())
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The seeds are built when the crawl starts, the first time the engine asks for the
+//! start requests, so building the crawler stays cheap and a crawler that is never run
+//! never queries the source. The engine asks from a synchronous method, so on a
+//! multi-threaded runtime the build runs in place, blocking that worker thread; on a
+//! current-thread runtime it runs on a thread of its own, with a runtime of its own, so
+//! it must not rely on resources that only the crawl's runtime drives. An error building
+//! the seeds is logged by the engine and the crawl starts without requests.
+//!
+//! Requests that are already built can be passed to [`Seeded::new`]. Either way the
+//! spider's own `start_requests` and `start_urls` are not used.
+
+use spider_core::{Spider, async_trait, tokio};
+use spider_util::{error::SpiderError, request::Request};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Builds the start requests of a [`Seeded`] spider.
+type Seed<S> =
+    for<'a> fn(
+        &'a S,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Request>, SpiderError>> + Send + 'a>>;
+
+/// A spider whose start requests are built by an async method, see the
+/// [module docs](self).
+#[async_trait]
+pub trait AsyncStartSpider: Spider + Sized {
+    /// Builds the requests the crawl starts from.
+    async fn start_requests_async(&self) -> Result<Vec<Request>, SpiderError>;
+
+    /// Returns the spider starting from the requests of
+    /// [`start_requests_async`](Self::start_requests_async), built when the crawl
+    /// starts.
+    fn seeded(self) -> Seeded<Self> {
+        Seeded {
+            spider: self,
+            seed: Some(|spider| spider.start_requests_async()),
+            requests: OnceLock::new(),
+        }
+    }
+}
+
+/// A spider starting from requests built asynchronously, see
+/// [`AsyncStartSpider::seeded`].
+pub struct Seeded<S> {
+    spider: S,
+    seed: Option<Seed<S>>,
+    requests: OnceLock<Vec<Request>>,
+}
+
+impl<S: Spider> Seeded<S> {
+    /// Starts `spider` from `requests` instead of its own start requests.
+    pub fn new(spider: S, requests: Vec<Request>) -> Self {
+        Self {
+            spider,
+            seed: None,
+            requests: OnceLock::from(requests),
+        }
+    }
+
+    /// Returns the wrapped spider.
+    pub fn spider(&self) -> &S {
+        &self.spider
+    }
+
+    /// Returns the start requests, once they are built.
+    pub fn requests(&self) -> Option<&[Request]> {
+        self.requests.get().map(Vec::as_slice)
+    }
+
+    /// Returns the start requests, building them on the first call.
+    fn seed_requests(&self) -> Result<Vec<Request>, SpiderError> {
+        if let Some(requests) = self.requests.get() {
+            return Ok(requests.clone());
+        }
+        let Some(seed) = self.seed else {
+            return Ok(Vec::new());
+        };
+        let requests = block_on(seed(&self.spider))?;
+        Ok(self.requests.get_or_init(|| requests).clone())
+    }
+}
+
+/// Runs `future` to completion from synchronous code, see the [module docs](self).
+fn block_on<T: Send>(
+    future: impl Future<Output = Result<T, SpiderError>> + Send,
+) -> Result<T, SpiderError> {
+    if let Ok(handle) = Handle::try_current()
+        && handle.runtime_flavor() == RuntimeFlavor::MultiThread
+    {
+        return tokio::task::block_in_place(|| handle.block_on(future));
+    }
+    std::thread::scope(|scope| {
+        let seeding = scope.spawn(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|error| {
+                    SpiderError::GeneralError(format!("Cannot start a seeding runtime: {error}"))
+                })?
+                .block_on(future)
+        });
+        seeding
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+impl_spider_wrapper! {
+    impl Spider for Seeded<S: Spider> {
+        fn start_requests(&self) {
+            self.seed_requests()
+        }
+
+        async fn parse(&self, response, state) {
+            self.spider.parse(response, state).await
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Shorthands for the most common CSS selections.
+//!
+//! [`SelectExt`] is implemented for whole pages and for selected elements, so lookups can
+//! drill down without building selectors and iterators by hand:
+//!
+//! ```rust,ignore
+//! for book in html.select(&"article.product_pod".to_selector()?) {
+//!     let title = book.css_attr("h3 a", "title")?.unwrap_or_default();
+//!     let price = book.css_text(".price_color")?.unwrap_or_default();
+//! }
+//! ```
+
+use scraper::{ElementRef, Html};
+use spider_util::{error::SpiderError, utils::ToSelector};
+
+/// CSS lookups returning the first match.
+///
+/// Implemented for `&'a Html` and `ElementRef<'a>`, so the elements found borrow the page
+/// rather than the value they were looked up from, and can be kept or drilled into
+/// further. Invalid selectors are reported as errors; a selector that matches nothing
+/// yields `Ok(None)`.
+pub trait SelectExt<'a>: Copy {
+    /// Returns the first element matching `selector`.
+    fn css_first(self, selector: &str) -> Result<Option<ElementRef<'a>>, SpiderError>;
+
+    /// Returns the trimmed text of the first element matching `selector`.
+    fn css_text(self, selector: &str) -> Result<Option<String>, SpiderError> {
+        Ok(self
+            .css_first(selector)?
+            .map(|element| element.text().collect::<String>().trim().to_string()))
+    }
+
+    /// Returns attribute `attr` of the first element matching `selector` that has it.
+    fn css_attr(self, selector: &str, attr: &str) -> Result<Option<String>, SpiderError>;
+}
+
+impl<'a> SelectExt<'a> for &'a Html {
+    fn css_first(self, selector: &str) -> Result<Option<ElementRef<'a>>, SpiderError> {
+        Ok(self.select(&selector.to_selector()?).next())
+    }
+
+    fn css_attr(self, selector: &str, attr: &str) -> Result<Option<String>, SpiderError> {
+        Ok(self
+            .select(&selector.to_selector()?)
+            .find_map(|element| element.value().attr(attr))
+            .map(str::to_string))
+    }
+}
+
+impl<'a> SelectExt<'a> for ElementRef<'a> {
+    fn css_first(self, selector: &str) -> Result<Option<ElementRef<'a>>, SpiderError> {
+        Ok(self.select(&selector.to_selector()?).next())
+    }
+
+    fn css_attr(self, selector: &str, attr: &str) -> Result<Option<String>, SpiderError> {
+        Ok(self
+            .select(&selector.to_selector()?)
+            .find_map(|element| element.value().attr(attr))
+            .map(str::to_string))
+    }
+}
@@ -0,0 +1,256 @@
+//! Sitemap discovery and parsing.
+//!
+//! [`parse_sitemap`] reads both `<urlset>` sitemaps and `<sitemapindex>` files, gzipped
+//! or not, so a spider can seed its crawl from a site's sitemaps. Requests built with
+//! [`Request::sitemap`](crate::request::RequestExt::sitemap) are marked under
+//! [`SITEMAP_KEY`], so `parse` can tell sitemaps from pages, and [`Sitemap::requests`]
+//! marks the nested sitemaps of an index the same way:
+//!
+//! ```rust,ignore
+//! fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+//!     Ok(vec![Request::sitemap(Url::parse("https://example.com/sitemap.xml.gz")?)])
+//! }
+//!
+//! async fn parse(&self, response: Response, _state: &Self::State) -> ... {
+//!     let mut output = ParseOutput::new();
+//!     if response.is_sitemap() {
+//!         output.add_requests(response.sitemap()?.requests());
+//!         return Ok(output);
+//!     }
+//!     // A page listed in a sitemap.
+//! }
+//! ```
+
+use crate::request::RequestExt;
+use flate2::read::MultiGzDecoder;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use spider_util::{error::SpiderError, request::Request};
+use std::fmt;
+use std::io::Read;
+use url::Url;
+
+/// Meta key marking a request for a sitemap, see
+/// [`RequestExt::sitemap`](crate::request::RequestExt::sitemap).
+pub const SITEMAP_KEY: &str = "sitemap";
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Largest uncompressed sitemap accepted by the sitemap protocol (50 MiB).
+const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+/// A URL listed in a sitemap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapEntry {
+    /// The absolute URL from the `<loc>` element.
+    pub url: Url,
+    /// The raw `<lastmod>` value, usually a W3C datetime such as `2024-05-01`.
+    pub lastmod: Option<String>,
+}
+
+/// The contents of a sitemap file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sitemap {
+    /// A `<urlset>`: the pages of the site.
+    UrlSet(Vec<SitemapEntry>),
+    /// A `<sitemapindex>`: further sitemaps to fetch.
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    /// Returns the entries of the sitemap, whichever kind it is.
+    pub fn entries(&self) -> &[SitemapEntry] {
+        match self {
+            Sitemap::UrlSet(entries) | Sitemap::Index(entries) => entries,
+        }
+    }
+
+    /// Builds a request for every entry: sitemap requests for the entries of an index,
+    /// plain requests for the pages of a `<urlset>`.
+    pub fn requests(&self) -> Vec<Request> {
+        match self {
+            Sitemap::UrlSet(entries) => entries
+                .iter()
+                .map(|entry| Request::new(entry.url.clone()))
+                .collect(),
+            Sitemap::Index(entries) => entries
+                .iter()
+                .map(|entry| Request::sitemap(entry.url.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Errors that can occur while parsing a sitemap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SitemapError {
+    /// The body is gzip-compressed but could not be decompressed.
+    Gzip(String),
+    /// The body is not well-formed XML.
+    Xml(String),
+    /// The document is neither a `<urlset>` nor a `<sitemapindex>`.
+    NotSitemap,
+    /// The uncompressed sitemap exceeds the 50 MiB limit of the sitemap protocol.
+    TooLarge,
+}
+
+impl fmt::Display for SitemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SitemapError::Gzip(reason) => write!(f, "cannot decompress sitemap: {}", reason),
+            SitemapError::Xml(reason) => write!(f, "malformed sitemap XML: {}", reason),
+            SitemapError::NotSitemap => write!(f, "document is not a sitemap"),
+            SitemapError::TooLarge => write!(f, "sitemap exceeds 50 MiB uncompressed"),
+        }
+    }
+}
+
+impl std::error::Error for SitemapError {}
+
+impl From<SitemapError> for SpiderError {
+    fn from(error: SitemapError) -> Self {
+        SpiderError::GeneralError(error.to_string())
+    }
+}
+
+/// Parses a sitemap or sitemap index.
+///
+/// Gzipped bodies (`.xml.gz` files, or responses that were not decoded by the HTTP
+/// client) are detected from their magic bytes and decompressed transparently. Entries
+/// whose `<loc>` is not an absolute URL are skipped with a debug log.
+pub fn parse_sitemap(bytes: &[u8]) -> Result<Sitemap, SitemapError> {
+    let decompressed;
+    let xml = if bytes.starts_with(GZIP_MAGIC) {
+        decompressed = gunzip(bytes)?;
+        decompressed.as_slice()
+    } else if bytes.len() as u64 > MAX_SITEMAP_SIZE {
+        return Err(SitemapError::TooLarge);
+    } else {
+        bytes
+    };
+
+    let mut reader = Reader::from_reader(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut index = None;
+    let mut entries = Vec::new();
+    let mut current: Option<(Option<String>, Option<String>)> = None;
+    let mut field: Option<&'static str> = None;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|err| SitemapError::Xml(err.to_string()))?;
+        match event {
+            Event::Start(start) => match (index, start.local_name().as_ref()) {
+                (None, b"urlset") => index = Some(false),
+                (None, b"sitemapindex") => index = Some(true),
+                (None, _) => return Err(SitemapError::NotSitemap),
+                (Some(false), b"url") | (Some(true), b"sitemap") => {
+                    current = Some((None, None));
+                }
+                (Some(_), b"loc") if current.is_some() => field = Some("loc"),
+                (Some(_), b"lastmod") if current.is_some() => field = Some("lastmod"),
+                _ => field = None,
+            },
+            Event::Text(text) => {
+                let value = text
+                    .unescape()
+                    .map_err(|err| SitemapError::Xml(err.to_string()))?;
+                set_field(&mut current, field, &value);
+            }
+            Event::CData(data) => {
+                set_field(&mut current, field, &String::from_utf8_lossy(&data));
+            }
+            Event::End(end) => match end.local_name().as_ref() {
+                b"url" | b"sitemap" => {
+                    if let Some(entry) = current.take().and_then(to_entry) {
+                        entries.push(entry);
+                    }
+                }
+                _ => field = None,
+            },
+            Event::Empty(empty) if index.is_none() => {
+                return match empty.local_name().as_ref() {
+                    b"urlset" => Ok(Sitemap::UrlSet(Vec::new())),
+                    b"sitemapindex" => Ok(Sitemap::Index(Vec::new())),
+                    _ => Err(SitemapError::NotSitemap),
+                };
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    match index {
+        Some(false) => Ok(Sitemap::UrlSet(entries)),
+        Some(true) => Ok(Sitemap::Index(entries)),
+        None => Err(SitemapError::NotSitemap),
+    }
+}
+
+/// Returns the sitemap URLs declared with `Sitemap:` lines in a `robots.txt` body.
+///
+/// Relative URLs are resolved against `base`, the URL of the `robots.txt` file.
+pub fn sitemaps_from_robots(robots_txt: &str, base: &Url) -> Vec<Url> {
+    robots_txt
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if !key.trim().eq_ignore_ascii_case("sitemap") {
+                return None;
+            }
+            let value = value.split('#').next()?.trim();
+            base.join(value).ok()
+        })
+        .collect()
+}
+
+/// Returns the conventional sitemap location of a site, `/sitemap.xml` at its root.
+pub fn default_sitemap_url(url: &Url) -> Option<Url> {
+    url.join("/sitemap.xml").ok()
+}
+
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, SitemapError> {
+    let mut xml = Vec::new();
+    MultiGzDecoder::new(bytes)
+        .take(MAX_SITEMAP_SIZE + 1)
+        .read_to_end(&mut xml)
+        .map_err(|err| SitemapError::Gzip(err.to_string()))?;
+    if xml.len() as u64 > MAX_SITEMAP_SIZE {
+        return Err(SitemapError::TooLarge);
+    }
+    Ok(xml)
+}
+
+fn set_field(
+    current: &mut Option<(Option<String>, Option<String>)>,
+    field: Option<&'static str>,
+    value: &str,
+) {
+    let Some((loc, lastmod)) = current else {
+        return;
+    };
+    let target = match field {
+        Some("loc") => loc,
+        Some("lastmod") => lastmod,
+        _ => return,
+    };
+    target
+        .get_or_insert_with(String::new)
+        .push_str(value.trim());
+}
+
+fn to_entry((loc, lastmod): (Option<String>, Option<String>)) -> Option<SitemapEntry> {
+    let loc = loc?;
+    match Url::parse(&loc) {
+        Ok(url) => Some(SitemapEntry {
+            url,
+            lastmod: lastmod.filter(|lastmod| !lastmod.is_empty()),
+        }),
+        Err(err) => {
+            log::debug!("Skipping sitemap entry {:?}: {}", loc, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! Machine-readable snapshots of the crawl statistics.
+//!
+//! `StatCollector` prints a table through `Display`, which is for people.
+//! [`StatCollectorExt::to_json`] turns the statistics into a JSON document a CI job or a
+//! dashboard can read:
+//!
+//! ```rust,ignore
+//! let crawler = CrawlerBuilder::new(MySpider).build().await?;
+//! let stats = crawler.get_stats();
+//! crawler.start_crawl().await?;
+//! std::fs::write("stats.json", stats.to_json().to_string())?;
+//! ```
+//!
+//! The document looks like this:
+//!
+//! ```json
+//! {
+//!   "elapsed_secs": 12.5,
+//!   "requests_per_second": 8.0,
+//!   "requests": {"enqueued": 101, "sent": 100, "succeeded": 97, "failed": 3,
+//!                "retried": 4, "dropped": 1},
+//!   "responses": {"received": 97, "from_cache": 0, "by_status": {"200": 95, "404": 2}},
+//!   "items": {"scraped": 90, "processed": 90, "dropped_by_pipeline": 0},
+//!   "total_bytes_downloaded": 1843200,
+//!   "requests_by_domain": {"example.com": 100}
+//! }
+//! ```
+//!
+//! `requests_by_domain` counts the distinct URLs downloaded from each host, taken from
+//! the engine's request timings. `requests_per_second` is the number of requests sent
+//! divided by the elapsed time.
+//!
+//! The engine updates its counters without a lock, so a snapshot taken while the crawl
+//! runs reads them until two reads in a row agree, and no counter moved in between.
+//!
+//! To write the snapshot to a file when a crawl finishes, run it with a
+//! [`CrawlControl`](crate::crawl::CrawlControl) configured with
+//! [`stats_file`](crate::crawl::CrawlControl::stats_file).
+//!
+//! # Bandwidth
+//!
+//! [`ByteStats`] counts the bytes downloaded and the smallest, largest and average
+//! response body, to estimate what a crawl costs and spot unexpectedly large pages. A
+//! [`CrawlControl`](crate::crawl::CrawlControl) counts every response its middleware
+//! sees, and an [`HttpDownloader`](crate::downloader::HttpDownloader) counts body bytes
+//! as they arrive, so a streamed body is counted even when it is cut short:
+//!
+//! ```rust,ignore
+//! let summary = control.run(crawler).await;
+//! // downloaded 1.76 MiB in 97 responses
+//! // (smallest 312 B, average 18.60 KiB, largest 1.20 MiB)
+//! println!("{}", control.byte_stats());
+//! ```
+//!
+//! The stats file of a control includes them under `response_sizes`.
+//!
+//! # Proxies
+//!
+//! A [`ProxyPoolMiddleware`](crate::middleware::proxy_pool::ProxyPoolMiddleware) reports
+//! the requests, failures and ejections of each proxy, which
+//! [`StatsSnapshot::with_proxy_health`] adds under `proxies`:
+//!
+//! ```rust,ignore
+//! let snapshot = stats.snapshot().with_proxy_health(proxies.health());
+//! ```
+//!
+//! # Soft 404s
+//!
+//! A [`Soft404Middleware`](crate::middleware::soft_404::Soft404Middleware) counts the
+//! soft 404s it dropped per host, apart from the other dropped requests, which
+//! [`StatsSnapshot::with_soft_404s`] adds under `soft_404s`:
+//!
+//! ```rust,ignore
+//! let snapshot = stats.snapshot().with_soft_404s(soft_404s.dropped_by_domain());
+//! ```
+//!
+//! # Synthetic traffic
+//!
+//! Some wrappers keep the engine busy with placeholder requests of their own: a
+//! [`Scheduling`](crate::scheduler::Scheduling) parks slots in the engine's queue while
+//! its scheduler holds requests back, and a [`KeepAlive`](crate::keep_alive::KeepAlive)
+//! circles a keep-alive request while work is settling. The engine counts their trips
+//! like real ones, as retried and dropped requests. Likewise,
+//! [`HttpDownloader`](crate::downloader::HttpDownloader) answers a failed download of a
+//! request with an errback with a placeholder response, which the engine counts as a
+//! succeeded request. Each of them counts its placeholders in a [`SyntheticTraffic`], and
+//! the readers of the statistics take them back out:
+//!
+//! ```rust,ignore
+//! let snapshot = stats.snapshot().excluding(scheduling.traffic());
+//! let monitor = HealthMonitor::new(stats.clone()).excluding(scheduling.traffic());
+//! let control = CrawlControl::new().excluding(scheduling.traffic());
+//! ```
+
+use crate::downloader::FAILED_DOWNLOAD_STATUS;
+use crate::middleware::proxy_pool::ProxyHealth;
+use serde::{Deserialize, Serialize};
+use spider_core::stats::StatCollector;
+use spider_util::error::SpiderError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use url::Url;
+
+/// How many times a snapshot reads the counters while they keep changing.
+const MAX_SNAPSHOT_READS: usize = 8;
+
+/// Request counters of a [`StatsSnapshot`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    /// Requests added to the queue.
+    pub enqueued: usize,
+    /// Requests handed to the downloader.
+    pub sent: usize,
+    /// Requests downloaded successfully.
+    pub succeeded: usize,
+    /// Requests whose download failed.
+    pub failed: usize,
+    /// Requests sent back to the queue by a middleware.
+    pub retried: usize,
+    /// Requests dropped by a middleware.
+    pub dropped: usize,
+}
+
+/// Response counters of a [`StatsSnapshot`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseCounts {
+    /// Responses received.
+    pub received: usize,
+    /// Responses served from a cache.
+    pub from_cache: usize,
+    /// Responses received per HTTP status.
+    pub by_status: BTreeMap<u16, usize>,
+}
+
+/// Item counters of a [`StatsSnapshot`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemCounts {
+    /// Items returned by the spider.
+    pub scraped: usize,
+    /// Items that went through the pipelines.
+    pub processed: usize,
+    /// Items dropped by a pipeline.
+    pub dropped_by_pipeline: usize,
+}
+
+/// A consistent copy of a crawl's statistics, see the [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    /// Seconds since the crawler was built.
+    pub elapsed_secs: f64,
+    /// Requests sent per second over the whole crawl.
+    pub requests_per_second: f64,
+    /// Request counters.
+    pub requests: RequestCounts,
+    /// Response counters.
+    pub responses: ResponseCounts,
+    /// Item counters.
+    pub items: ItemCounts,
+    /// Response body bytes downloaded.
+    pub total_bytes_downloaded: usize,
+    /// Distinct URLs downloaded per host.
+    pub requests_by_domain: BTreeMap<String, usize>,
+    /// Bytes downloaded and response sizes, when added with
+    /// [`with_byte_stats`](Self::with_byte_stats).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_sizes: Option<ResponseSizes>,
+    /// The health of each proxy, when added with
+    /// [`with_proxy_health`](Self::with_proxy_health).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxies: Option<Vec<ProxyHealth>>,
+    /// Soft 404s dropped per host, when added with
+    /// [`with_soft_404s`](Self::with_soft_404s).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub soft_404s: Option<BTreeMap<String, usize>>,
+}
+
+impl StatsSnapshot {
+    /// Adds the byte counts of `bytes` to the snapshot.
+    pub fn with_byte_stats(mut self, bytes: &ByteStats) -> Self {
+        self.response_sizes = Some(bytes.sizes());
+        self
+    }
+
+    /// Adds the health of a proxy pool, read from
+    /// [`ProxyPoolMiddleware::health`](crate::middleware::proxy_pool::ProxyPoolMiddleware::health),
+    /// to the snapshot.
+    pub fn with_proxy_health(mut self, proxies: Vec<ProxyHealth>) -> Self {
+        self.proxies = Some(proxies);
+        self
+    }
+
+    /// Adds the soft 404s dropped per host, as counted by a
+    /// [`Soft404Middleware`](crate::middleware::soft_404::Soft404Middleware), to the
+    /// snapshot.
+    pub fn with_soft_404s(mut self, dropped: BTreeMap<String, usize>) -> Self {
+        self.soft_404s = Some(dropped);
+        self
+    }
+
+    /// Takes the placeholder requests counted by `traffic` out of the counters, and counts
+    /// the placeholder responses as failed requests, see
+    /// [Synthetic traffic](self#synthetic-traffic).
+    pub fn excluding(mut self, traffic: &SyntheticTraffic) -> Self {
+        let requests = &mut self.requests;
+        requests.enqueued = requests.enqueued.saturating_sub(traffic.dropped());
+        requests.retried = requests.retried.saturating_sub(traffic.retried());
+        requests.dropped = requests.dropped.saturating_sub(traffic.dropped());
+        requests.succeeded = requests.succeeded.saturating_sub(traffic.failed());
+        requests.failed += traffic.failed();
+
+        let responses = &mut self.responses;
+        responses.received = responses.received.saturating_sub(traffic.failed());
+        if let Some(count) = responses.by_status.get_mut(&FAILED_DOWNLOAD_STATUS) {
+            *count = count.saturating_sub(traffic.failed());
+            if *count == 0 {
+                responses.by_status.remove(&FAILED_DOWNLOAD_STATUS);
+            }
+        }
+        self
+    }
+
+    /// Returns the snapshot as a JSON document.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("stats snapshot serializes")
+    }
+
+    /// Writes the snapshot to `path` as pretty-printed JSON.
+    pub fn write_json(&self, path: impl AsRef<Path>) -> Result<(), SpiderError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Snapshots of `StatCollector`, see the [module docs](self).
+pub trait StatCollectorExt {
+    /// Reads the statistics into a consistent [`StatsSnapshot`].
+    fn snapshot(&self) -> StatsSnapshot;
+
+    /// Returns the statistics as a JSON document.
+    fn to_json(&self) -> serde_json::Value {
+        self.snapshot().to_json()
+    }
+}
+
+impl StatCollectorExt for StatCollector {
+    fn snapshot(&self) -> StatsSnapshot {
+        let mut snapshot = read_counters(self);
+        for _ in 1..MAX_SNAPSHOT_READS {
+            let again = read_counters(self);
+            if again == snapshot {
+                break;
+            }
+            snapshot = again;
+        }
+
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        snapshot.elapsed_secs = elapsed;
+        snapshot.requests_per_second = if elapsed > 0.0 {
+            snapshot.requests.sent as f64 / elapsed
+        } else {
+            0.0
+        };
+        snapshot
+    }
+}
+
+/// Reads every counter of `stats` once, leaving the rates at zero.
+fn read_counters(stats: &StatCollector) -> StatsSnapshot {
+    let load = |counter: &std::sync::atomic::AtomicUsize| counter.load(Ordering::SeqCst);
+    let mut requests_by_domain = BTreeMap::new();
+    for entry in stats.request_times.iter() {
+        if let Some(host) = Url::parse(entry.key())
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        {
+            *requests_by_domain.entry(host).or_insert(0) += 1;
+        }
+    }
+    StatsSnapshot {
+        elapsed_secs: 0.0,
+        requests_per_second: 0.0,
+        requests: RequestCounts {
+            enqueued: load(&stats.requests_enqueued),
+            sent: load(&stats.requests_sent),
+            succeeded: load(&stats.requests_succeeded),
+            failed: load(&stats.requests_failed),
+            retried: load(&stats.requests_retried),
+            dropped: load(&stats.requests_dropped),
+        },
+        responses: ResponseCounts {
+            received: load(&stats.responses_received),
+            from_cache: load(&stats.responses_from_cache),
+            by_status: stats
+                .response_status_counts
+                .iter()
+                .map(|entry| (*entry.key(), *entry.value()))
+                .collect(),
+        },
+        items: ItemCounts {
+            scraped: load(&stats.items_scraped),
+            processed: load(&stats.items_processed),
+            dropped_by_pipeline: load(&stats.items_dropped_by_pipeline),
+        },
+        total_bytes_downloaded: load(&stats.total_bytes_downloaded),
+        requests_by_domain,
+        response_sizes: None,
+        proxies: None,
+        soft_404s: None,
+    }
+}
+
+/// Counts the placeholder requests a wrapper sent through the engine, see
+/// [Synthetic traffic](self#synthetic-traffic).
+///
+/// A placeholder parked in the engine's queue counts as retried. One released without
+/// standing for a real request counts as dropped, and its enqueue is taken back out with
+/// it. A placeholder response standing for a failed download counts as failed. Clones
+/// share the counts.
+#[derive(Debug, Clone, Default)]
+pub struct SyntheticTraffic {
+    counters: Arc<SyntheticCounters>,
+}
+
+#[derive(Debug, Default)]
+struct SyntheticCounters {
+    retried: AtomicUsize,
+    dropped: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl SyntheticTraffic {
+    /// Creates empty counts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how many times a placeholder was parked, each counted by the engine as a
+    /// retried request.
+    pub fn retried(&self) -> usize {
+        self.counters.retried.load(Ordering::SeqCst)
+    }
+
+    /// Returns how many placeholders were released, each counted by the engine as a
+    /// dropped request.
+    pub fn dropped(&self) -> usize {
+        self.counters.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Returns how many placeholder responses stood for a failed download, each counted
+    /// by the engine as a succeeded request and a received response.
+    pub fn failed(&self) -> usize {
+        self.counters.failed.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.counters.retried.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn record_drop(&self) {
+        self.counters.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn record_failure(&self) {
+        self.counters.failed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Bytes downloaded and response body sizes, read from [`ByteStats::sizes`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseSizes {
+    /// Body bytes downloaded, counted as they arrived.
+    pub bytes_downloaded: u64,
+    /// Responses whose body was read to the end or to a limit.
+    pub responses: u64,
+    /// The size of the smallest response body.
+    pub min_bytes: Option<u64>,
+    /// The size of the largest response body.
+    pub max_bytes: Option<u64>,
+    /// The average size of a response body.
+    pub avg_bytes: Option<f64>,
+}
+
+#[derive(Debug)]
+struct ByteCounters {
+    downloaded: AtomicU64,
+    responses: AtomicU64,
+    response_bytes: AtomicU64,
+    smallest: AtomicU64,
+    largest: AtomicU64,
+}
+
+/// Counts the bytes downloaded and the sizes of response bodies, see the
+/// [module docs](self#bandwidth).
+///
+/// Clones share the counts.
+#[derive(Debug, Clone)]
+pub struct ByteStats {
+    counters: Arc<ByteCounters>,
+}
+
+impl Default for ByteStats {
+    fn default() -> Self {
+        Self {
+            counters: Arc::new(ByteCounters {
+                downloaded: AtomicU64::new(0),
+                responses: AtomicU64::new(0),
+                response_bytes: AtomicU64::new(0),
+                smallest: AtomicU64::new(u64::MAX),
+                largest: AtomicU64::new(0),
+            }),
+        }
+    }
+}
+
+impl ByteStats {
+    /// Creates empty counts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `bytes` of body as downloaded, without finishing a response.
+    pub fn add_bytes(&self, bytes: usize) {
+        self.counters
+            .downloaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records a finished response body of `size` bytes, whose bytes were already
+    /// counted with [`add_bytes`](Self::add_bytes).
+    pub fn record_response(&self, size: usize) {
+        let size = size as u64;
+        let counters = &self.counters;
+        counters.responses.fetch_add(1, Ordering::Relaxed);
+        counters.response_bytes.fetch_add(size, Ordering::Relaxed);
+        counters.smallest.fetch_min(size, Ordering::Relaxed);
+        counters.largest.fetch_max(size, Ordering::Relaxed);
+    }
+
+    /// Counts a whole response body of `size` bytes, downloaded in one go.
+    pub fn record_body(&self, size: usize) {
+        self.add_bytes(size);
+        self.record_response(size);
+    }
+
+    /// Returns the body bytes downloaded so far.
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.counters.downloaded.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of responses recorded so far.
+    pub fn responses(&self) -> u64 {
+        self.counters.responses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the size of the smallest response body, if any was recorded.
+    pub fn min_response_bytes(&self) -> Option<u64> {
+        (self.responses() > 0).then(|| self.counters.smallest.load(Ordering::Relaxed))
+    }
+
+    /// Returns the size of the largest response body, if any was recorded.
+    pub fn max_response_bytes(&self) -> Option<u64> {
+        (self.responses() > 0).then(|| self.counters.largest.load(Ordering::Relaxed))
+    }
+
+    /// Returns the average size of a response body, if any was recorded.
+    pub fn avg_response_bytes(&self) -> Option<f64> {
+        let responses = self.responses();
+        (responses > 0)
+            .then(|| self.counters.response_bytes.load(Ordering::Relaxed) as f64 / responses as f64)
+    }
+
+    /// Returns the counts as a serializable [`ResponseSizes`].
+    pub fn sizes(&self) -> ResponseSizes {
+        ResponseSizes {
+            bytes_downloaded: self.bytes_downloaded(),
+            responses: self.responses(),
+            min_bytes: self.min_response_bytes(),
+            max_bytes: self.max_response_bytes(),
+            avg_bytes: self.avg_response_bytes(),
+        }
+    }
+}
+
+impl fmt::Display for ByteStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "downloaded {} in {} responses",
+            format_bytes(self.bytes_downloaded() as f64),
+            self.responses()
+        )?;
+        if let (Some(min), Some(avg), Some(max)) = (
+            self.min_response_bytes(),
+            self.avg_response_bytes(),
+            self.max_response_bytes(),
+        ) {
+            write!(
+                f,
+                " (smallest {}, average {}, largest {})",
+                format_bytes(min as f64),
+                format_bytes(avg),
+                format_bytes(max as f64)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `18.60 KiB`.
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024.0 {
+        return format!("{} B", bytes.round());
+    }
+    let mut value = bytes / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{:.2} {}", value, unit)
+}
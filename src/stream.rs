@@ -0,0 +1,492 @@
+//! Streaming response bodies with byte and time limits.
+//!
+//! [`HttpDownloader::stream`](crate::downloader::HttpDownloader::stream) returns a
+//! [`StreamResponse`] as soon as the response headers arrive. Its body is read chunk by
+//! chunk, and the limits set with [`StreamResponse::with_byte_limit`] and
+//! [`StreamResponse::with_time_limit`] stop an endless or very slow body:
+//!
+//! ```rust,ignore
+//! let links = downloader
+//!     .stream(Request::new(url))
+//!     .await?
+//!     .with_byte_limit(10 * 1024 * 1024)
+//!     .with_time_limit(Duration::from_secs(30))
+//!     .on_limit(LimitAction::Truncate)
+//!     .into_links(&LinkExtractor::new())
+//!     .await?;
+//! ```
+//!
+//! With [`LimitAction::Truncate`] the body read so far is kept and the response meta
+//! gets [`BODY_TRUNCATED_KEY`] set to `true`; with [`LimitAction::Fail`] reading the body
+//! returns an error.
+//!
+//! A body sent with a `gzip`, `deflate` or `br` `Content-Encoding` is decoded as it is
+//! read, unless decompression is turned off with the downloader's
+//! [`decompress`](crate::downloader::HttpDownloaderBuilder::decompress). The chunks, the
+//! limits and [`StreamResponse::bytes_read`] are all in decoded bytes, so a byte limit
+//! also stops a body that inflates out of proportion. The bytes downloaded, counted in
+//! the downloader's [`ByteStats`] and under [`WIRE_BYTES_KEY`] in the response meta, are
+//! the compressed bytes as they came off the wire.
+//! The `Content-Encoding` and `Content-Length` headers are removed from a decoded
+//! response, as they describe the encoded body. A body that cannot be decoded fails with
+//! an error naming its encoding.
+
+use crate::downloader::{BufferPool, transport_error};
+use crate::links::LinkExtractor;
+use crate::response::ResponseExt;
+use crate::stats::ByteStats;
+use crate::timing::{PendingTimings, TIMINGS_KEY};
+use bytes::Bytes;
+use dashmap::DashMap;
+use log::debug;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH, HeaderMap};
+use serde_json::Value;
+use spider_core::tokio;
+use spider_core::tokio::sync::OwnedSemaphorePermit;
+use spider_util::{error::SpiderError, request::Request, response::Response};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Response meta key set to `true` when a body was cut short by a limit.
+pub const BODY_TRUNCATED_KEY: &str = "body_truncated";
+
+/// Response meta key set to `true` when a downloader did not read a body because of its
+/// content type, see the downloader's
+/// [`allowed_content_types`](crate::downloader::HttpDownloaderBuilder::allowed_content_types).
+pub const BODY_SKIPPED_KEY: &str = "body_skipped";
+
+/// Response meta key holding the number of body bytes received on the wire, before
+/// decoding, see [`ResponseExt::wire_bytes`].
+pub const WIRE_BYTES_KEY: &str = "wire_bytes";
+
+/// What happens when a body exceeds a [`StreamResponse`] limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitAction {
+    /// Keep the part of the body read so far.
+    #[default]
+    Truncate,
+    /// Fail with an error.
+    Fail,
+}
+
+/// Decodes a body sent with a `Content-Encoding`, chunk by chunk.
+enum Decoder {
+    Gzip(Box<flate2::write::GzDecoder<Vec<u8>>>),
+    /// A zlib stream, and whether it has ended. The zlib writer does not tell a stream
+    /// cut short from a finished one, so it is inflated by hand.
+    Deflate(Box<flate2::Decompress>, bool),
+    Brotli(Box<brotli_decompressor::DecompressorWriter<Vec<u8>>>),
+}
+
+impl Decoder {
+    /// Returns a decoder for `encoding`, or `None` for `identity` and the encodings it
+    /// does not know, whose bodies are kept as they are.
+    fn for_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip(Box::new(flate2::write::GzDecoder::new(
+                Vec::new(),
+            )))),
+            "deflate" => Some(Self::Deflate(
+                Box::new(flate2::Decompress::new(true)),
+                false,
+            )),
+            "br" => Some(Self::Brotli(Box::new(
+                brotli_decompressor::DecompressorWriter::new(Vec::new(), 4096),
+            ))),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip(_) => "gzip",
+            Self::Deflate(..) => "deflate",
+            Self::Brotli(_) => "br",
+        }
+    }
+
+    /// Decodes `chunk`, returning the bytes decoded so far.
+    fn decode(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let output = match self {
+            Self::Gzip(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.get_mut()
+            }
+            Self::Deflate(decompress, ended) => return inflate(decompress, ended, chunk),
+            Self::Brotli(decoder) => {
+                decoder.write_all(chunk)?;
+                decoder.get_mut()
+            }
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+
+    /// Checks that the body ended where its encoding does, returning the last bytes.
+    fn finish(&mut self) -> io::Result<Bytes> {
+        let output = match self {
+            Self::Gzip(decoder) => {
+                decoder.try_finish()?;
+                decoder.get_mut()
+            }
+            Self::Deflate(_, true) => return Ok(Bytes::new()),
+            Self::Deflate(_, false) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "deflate stream ended early",
+                ));
+            }
+            Self::Brotli(decoder) => {
+                decoder.close()?;
+                decoder.get_mut()
+            }
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+}
+
+/// Inflates `input` with `decompress`, setting `ended` at the end of the stream.
+fn inflate(
+    decompress: &mut flate2::Decompress,
+    ended: &mut bool,
+    mut input: &[u8],
+) -> io::Result<Bytes> {
+    let mut output = Vec::with_capacity(input.len() * 4);
+    while !input.is_empty() {
+        if *ended {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data after the end of the deflate stream",
+            ));
+        }
+        if output.len() == output.capacity() {
+            output.reserve(output.capacity().max(4096));
+        }
+        let read = decompress.total_in();
+        let status = decompress
+            .decompress_vec(input, &mut output, flate2::FlushDecompress::None)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        input = &input[(decompress.total_in() - read) as usize..];
+        *ended = status == flate2::Status::StreamEnd;
+    }
+    Ok(Bytes::from(output))
+}
+
+/// An HTTP response whose body has not been read yet.
+pub struct StreamResponse {
+    /// The final URL of the response after any redirects.
+    pub url: Url,
+    /// The HTTP status code of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The URL of the request that led to this response.
+    pub request_url: Url,
+    /// Metadata carried over from the request.
+    pub meta: DashMap<Cow<'static, str>, Value>,
+    inner: reqwest::Response,
+    byte_limit: Option<usize>,
+    time_limit: Option<Duration>,
+    on_limit: LimitAction,
+    started: Instant,
+    read: usize,
+    /// The body bytes received so far, before decoding.
+    wire: usize,
+    truncated: bool,
+    timings: Option<PendingTimings>,
+    byte_stats: Option<ByteStats>,
+    decoder: Option<Decoder>,
+    /// Whether the body is decoded, so its bytes were counted before decoding.
+    decoding: bool,
+    /// Held until the body is read or dropped, see
+    /// `HttpDownloaderBuilder::max_concurrent_per_domain`.
+    domain_permit: Option<OwnedSemaphorePermit>,
+}
+
+impl StreamResponse {
+    pub(crate) fn new(inner: reqwest::Response, request: Request) -> Self {
+        Self {
+            url: inner.url().clone(),
+            status: inner.status(),
+            headers: inner.headers().clone(),
+            request_url: request.url,
+            meta: request.meta,
+            inner,
+            byte_limit: None,
+            time_limit: None,
+            on_limit: LimitAction::default(),
+            started: Instant::now(),
+            read: 0,
+            wire: 0,
+            truncated: false,
+            timings: None,
+            byte_stats: None,
+            decoder: None,
+            decoding: false,
+            domain_permit: None,
+        }
+    }
+
+    /// Decodes the body as its `Content-Encoding` says, and removes the headers that
+    /// describe the encoded body. A body with an encoding that is not known is kept as
+    /// it is.
+    pub(crate) fn with_decompression(mut self) -> Self {
+        let Some(encoding) = self
+            .headers
+            .get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return self;
+        };
+        match Decoder::for_encoding(encoding) {
+            Some(decoder) => {
+                self.decoder = Some(decoder);
+                self.decoding = true;
+                self.headers.remove(CONTENT_ENCODING);
+                self.headers.remove(CONTENT_LENGTH);
+            }
+            None => debug!(
+                "Not decoding the {:?} body of {}, its encoding is not supported",
+                encoding, self.url
+            ),
+        }
+        self
+    }
+
+    /// Completes `timings` once the body has been read, see [`crate::timing`].
+    pub(crate) fn with_timings(mut self, timings: PendingTimings) -> Self {
+        self.timings = Some(timings);
+        self
+    }
+
+    /// Holds `permit` for the origin until the body has been read or the response is
+    /// dropped.
+    pub(crate) fn with_domain_permit(mut self, permit: OwnedSemaphorePermit) -> Self {
+        self.domain_permit = Some(permit);
+        self
+    }
+
+    /// Counts the body bytes in `stats` as they are read, and the body size once it has
+    /// been read into a response.
+    pub(crate) fn with_byte_stats(mut self, stats: ByteStats) -> Self {
+        self.byte_stats = Some(stats);
+        self
+    }
+
+    /// Stops reading the body after `bytes` bytes.
+    pub fn with_byte_limit(mut self, bytes: usize) -> Self {
+        self.byte_limit = Some(bytes);
+        self
+    }
+
+    /// Stops reading the body once `limit` has passed since the response headers
+    /// arrived.
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// Chooses between a partial body and an error when a limit is hit. Defaults to
+    /// [`LimitAction::Truncate`].
+    pub fn on_limit(mut self, action: LimitAction) -> Self {
+        self.on_limit = action;
+        self
+    }
+
+    /// Returns the number of body bytes read so far.
+    pub fn bytes_read(&self) -> usize {
+        self.read
+    }
+
+    /// Returns `true` if a limit cut the body short.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Reads the next chunk of the body, decoded, or returns `None` at its end or once a
+    /// limit truncated it.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, SpiderError> {
+        if self.truncated {
+            return Ok(None);
+        }
+
+        let mut chunk = loop {
+            let next = match self.time_limit {
+                Some(limit) => {
+                    let remaining = limit.saturating_sub(self.started.elapsed());
+                    match tokio::time::timeout(remaining, self.inner.chunk()).await {
+                        Ok(next) => next.map_err(transport_error)?,
+                        Err(_) => {
+                            return self.limit_hit(format!("body took longer than {:?}", limit));
+                        }
+                    }
+                }
+                None => self.inner.chunk().await.map_err(transport_error)?,
+            };
+            let Some(decoder) = &mut self.decoder else {
+                match next {
+                    Some(chunk) => break chunk,
+                    None => return Ok(None),
+                }
+            };
+            // Compressed bytes are counted as they arrive, before they are decoded.
+            let decoded = match &next {
+                Some(chunk) => {
+                    self.wire += chunk.len();
+                    if let Some(stats) = &self.byte_stats {
+                        stats.add_bytes(chunk.len());
+                    }
+                    decoder.decode(chunk)
+                }
+                None => decoder.finish(),
+            };
+            let decoded = decoded.map_err(|e| {
+                SpiderError::GeneralError(format!(
+                    "Failed to decode the {} body of {}: {}",
+                    decoder.name(),
+                    self.url,
+                    e
+                ))
+            })?;
+            if next.is_none() {
+                self.decoder = None;
+                if decoded.is_empty() {
+                    return Ok(None);
+                }
+            }
+            if !decoded.is_empty() {
+                break decoded;
+            }
+        };
+
+        if let Some(limit) = self.byte_limit
+            && self.read + chunk.len() > limit
+        {
+            let keep = limit - self.read;
+            if self.on_limit == LimitAction::Truncate {
+                chunk.truncate(keep);
+                self.read = limit;
+                self.truncated = true;
+                self.count_decoded(chunk.len());
+                return Ok((!chunk.is_empty()).then_some(chunk));
+            }
+            return self.limit_hit(format!("body is larger than {} bytes", limit));
+        }
+        self.read += chunk.len();
+        self.count_decoded(chunk.len());
+        Ok(Some(chunk))
+    }
+
+    /// Counts `bytes` of a body read without decoding as downloaded.
+    fn count_decoded(&mut self, bytes: usize) {
+        if !self.decoding {
+            self.count_bytes(bytes);
+        }
+    }
+
+    fn count_bytes(&mut self, bytes: usize) {
+        self.wire += bytes;
+        if let Some(stats) = &self.byte_stats {
+            stats.add_bytes(bytes);
+        }
+    }
+
+    fn limit_hit(&mut self, reason: String) -> Result<Option<Bytes>, SpiderError> {
+        match self.on_limit {
+            LimitAction::Truncate => {
+                self.truncated = true;
+                Ok(None)
+            }
+            LimitAction::Fail => Err(SpiderError::GeneralError(format!(
+                "Stopped reading {}: {}",
+                self.url, reason
+            ))),
+        }
+    }
+
+    /// Reads the rest of the body, within the limits, into a [`Response`].
+    pub async fn into_response(self) -> Result<Response, SpiderError> {
+        self.collect(None).await
+    }
+
+    /// Reads the rest of the body, within the limits, and extracts the links on the page
+    /// with `extractor`. A truncated page yields the links found in the part read, and a
+    /// binary body none.
+    pub async fn into_links(self, extractor: &LinkExtractor) -> Result<Vec<Request>, SpiderError> {
+        let response = self.into_response().await?;
+        let Ok(html) = response.decoded_html(None) else {
+            return Ok(Vec::new());
+        };
+        extractor.extract_from(&html, &response.url)
+    }
+
+    pub(crate) async fn collect(
+        mut self,
+        pool: Option<&Arc<BufferPool>>,
+    ) -> Result<Response, SpiderError> {
+        let body = match pool {
+            Some(pool) => {
+                let mut buffer = pool.take();
+                loop {
+                    match self.chunk().await {
+                        Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                        Ok(None) => break,
+                        Err(error) => {
+                            pool.give_back(buffer);
+                            return Err(error);
+                        }
+                    }
+                }
+                pool.into_body(buffer)
+            }
+            None => {
+                let mut body = Vec::new();
+                while let Some(chunk) = self.chunk().await? {
+                    body.extend_from_slice(&chunk);
+                }
+                Bytes::from(body)
+            }
+        };
+
+        if self.truncated {
+            self.meta
+                .insert(Cow::Borrowed(BODY_TRUNCATED_KEY), Value::Bool(true));
+        }
+        if let Some(stats) = &self.byte_stats {
+            stats.record_response(body.len());
+        }
+        Ok(self.finish(body))
+    }
+
+    /// Returns the response with an empty body, without reading the body, marked with
+    /// [`BODY_SKIPPED_KEY`].
+    pub(crate) fn skip_body(self) -> Response {
+        self.meta
+            .insert(Cow::Borrowed(BODY_SKIPPED_KEY), Value::Bool(true));
+        self.finish(Bytes::new())
+    }
+
+    fn finish(mut self, body: Bytes) -> Response {
+        // The body is read, so the origin may take its next request.
+        drop(self.domain_permit.take());
+        if let Some(timings) = self.timings.take() {
+            let timings = timings.finish(self.started.elapsed());
+            self.meta
+                .insert(Cow::Borrowed(TIMINGS_KEY), timings.to_value());
+        }
+        self.meta
+            .insert(Cow::Borrowed(WIRE_BYTES_KEY), Value::from(self.wire));
+        Response {
+            url: self.url,
+            status: self.status,
+            headers: self.headers,
+            body,
+            request_url: self.request_url,
+            meta: self.meta,
+            cached: false,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Extraction of HTML `<table>` elements into structured rows.
+//!
+//! Tables are flattened into a rectangular grid: cells spanning several columns
+//! (`colspan`) or rows (`rowspan`) are repeated in every position they cover, so each
+//! row has one entry per column. Nested tables are not merged into their parent's
+//! cells; they are returned as tables of their own.
+
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashMap;
+
+/// Upper bound for `colspan`/`rowspan` values, guarding against absurd attributes.
+const MAX_SPAN: usize = 1000;
+
+/// A table extracted from an HTML document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Table {
+    /// The text of the table's `<caption>`, if any.
+    pub caption: Option<String>,
+    /// Column headers, taken from `<thead>` rows or a leading row made only of `<th>` cells.
+    ///
+    /// When several header rows are present, the labels of each column are joined with a space.
+    pub headers: Vec<String>,
+    /// The data rows, each padded to the width of the table.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Builds a table from a `<table>` element.
+    pub fn from_element(table: ElementRef<'_>) -> Self {
+        let caption = child_elements(table)
+            .find(|child| child.value().name() == "caption")
+            .map(cell_text);
+
+        let mut header_rows = Vec::new();
+        let mut body_sections: Vec<Vec<ElementRef<'_>>> = Vec::new();
+        // Index of the section collecting `<tr>` elements that sit directly in the table.
+        let mut bare_section = None;
+        for child in child_elements(table) {
+            match child.value().name() {
+                "thead" => header_rows.extend(rows_of(child)),
+                "tbody" | "tfoot" => {
+                    body_sections.push(rows_of(child).collect());
+                    bare_section = None;
+                }
+                "tr" => {
+                    let index = *bare_section.get_or_insert_with(|| {
+                        body_sections.push(Vec::new());
+                        body_sections.len() - 1
+                    });
+                    body_sections[index].push(child);
+                }
+                _ => {}
+            }
+        }
+
+        let header_count = if header_rows.is_empty() {
+            // Without a `<thead>`, a first row made only of `<th>` cells is the header.
+            let leading = body_sections
+                .iter()
+                .find_map(|section| section.first())
+                .is_some_and(|row| {
+                    let mut cells = cells_of(*row).peekable();
+                    cells.peek().is_some() && cells.all(|cell| cell.value().name() == "th")
+                });
+            usize::from(leading)
+        } else {
+            header_rows.len()
+        };
+
+        let mut sections = vec![header_rows];
+        sections.extend(body_sections);
+        let grid = expand_grid(&sections);
+        let (header_grid, rows) = grid.split_at(header_count.min(grid.len()));
+
+        let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+        let headers = if header_grid.is_empty() {
+            Vec::new()
+        } else {
+            (0..width)
+                .map(|column| {
+                    let mut parts: Vec<&str> = Vec::new();
+                    for label in header_grid.iter().filter_map(|row| row.get(column)) {
+                        if !label.is_empty() && parts.last() != Some(&label.as_str()) {
+                            parts.push(label);
+                        }
+                    }
+                    parts.join(" ")
+                })
+                .collect()
+        };
+
+        Self {
+            caption,
+            headers,
+            rows: rows.to_vec(),
+        }
+    }
+
+    /// Returns the data rows as maps keyed by column header.
+    ///
+    /// Columns without a header (or tables without any header row) are keyed by their
+    /// zero-based column index.
+    pub fn records(&self) -> Vec<HashMap<String, String>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .map(|(column, value)| {
+                        let key = match self.headers.get(column) {
+                            Some(header) if !header.is_empty() => header.clone(),
+                            _ => column.to_string(),
+                        };
+                        (key, value.clone())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Interprets a two-column "label / value" table as a map from label to value.
+    ///
+    /// This suits detail tables such as product specifications, where every row holds a
+    /// `<th>` label followed by a `<td>` value. Rows are padded to the width of the table,
+    /// so a label without a value maps to an empty string; a table narrower than two
+    /// columns yields an empty map.
+    pub fn key_values(&self) -> HashMap<String, String> {
+        self.rows
+            .iter()
+            .filter(|row| row.len() >= 2)
+            .map(|row| (row[0].clone(), row[1].clone()))
+            .collect()
+    }
+}
+
+/// Extracts every `<table>` in the document, in document order.
+///
+/// Nested tables are returned as separate entries following their parent table.
+pub fn extract_tables(html: &Html) -> Vec<Table> {
+    html.root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| element.value().name() == "table")
+        .map(Table::from_element)
+        .collect()
+}
+
+/// Expands row groups of cells into a grid, applying `colspan` and `rowspan`.
+///
+/// A `rowspan` never reaches past the end of its row group, and `rowspan="0"` spans to
+/// the end of it.
+fn expand_grid(sections: &[Vec<ElementRef<'_>>]) -> Vec<Vec<String>> {
+    let mut grid = Vec::with_capacity(sections.iter().map(Vec::len).sum());
+
+    for section in sections {
+        // For each column, the text of a cell spanning down into it and the rows it still covers.
+        let mut carried: Vec<Option<(String, usize)>> = Vec::new();
+
+        for (index, row) in section.iter().enumerate() {
+            let mut values: Vec<String> = Vec::new();
+            let mut column = 0;
+
+            for cell in cells_of(*row) {
+                column = fill_carried(&mut carried, &mut values, column);
+
+                let text = cell_text(cell);
+                let colspan = span_attr(cell, "colspan");
+                let rowspan = match span_attr(cell, "rowspan") {
+                    0 => section.len() - index,
+                    rowspan => rowspan,
+                };
+                for offset in 0..colspan {
+                    set_value(&mut values, column + offset, text.clone());
+                    if rowspan > 1 {
+                        if carried.len() <= column + offset {
+                            carried.resize(column + offset + 1, None);
+                        }
+                        carried[column + offset] = Some((text.clone(), rowspan - 1));
+                    }
+                }
+                column += colspan;
+            }
+
+            // Cells spanning down from earlier rows may still cover trailing columns.
+            while column < carried.len() {
+                column = fill_carried(&mut carried, &mut values, column);
+                column += 1;
+            }
+            grid.push(values);
+        }
+    }
+
+    let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+    for row in &mut grid {
+        row.resize(width, String::new());
+    }
+    grid
+}
+
+/// Fills consecutive columns starting at `column` that are covered by a `rowspan` from
+/// above, returning the first column that is free.
+fn fill_carried(
+    carried: &mut [Option<(String, usize)>],
+    values: &mut Vec<String>,
+    mut column: usize,
+) -> usize {
+    while let Some(slot) = carried.get_mut(column) {
+        let Some((text, remaining)) = slot else {
+            break;
+        };
+        set_value(values, column, text.clone());
+        *remaining -= 1;
+        if *remaining == 0 {
+            *slot = None;
+        }
+        column += 1;
+    }
+    column
+}
+
+fn set_value(values: &mut Vec<String>, column: usize, text: String) {
+    if values.len() <= column {
+        values.resize(column + 1, String::new());
+    }
+    values[column] = text;
+}
+
+/// Reads a span attribute. Missing or invalid values count as 1, and `rowspan="0"` is
+/// returned as 0; a `colspan` of 0 also counts as 1.
+fn span_attr(cell: ElementRef<'_>, name: &str) -> usize {
+    match cell
+        .value()
+        .attr(name)
+        .and_then(|value| value.trim().parse::<usize>().ok())
+    {
+        Some(0) if name == "rowspan" => 0,
+        Some(span) => span.clamp(1, MAX_SPAN),
+        None => 1,
+    }
+}
+
+fn child_elements<'a>(element: ElementRef<'a>) -> impl Iterator<Item = ElementRef<'a>> {
+    element.children().filter_map(ElementRef::wrap)
+}
+
+fn rows_of(section: ElementRef<'_>) -> impl Iterator<Item = ElementRef<'_>> {
+    child_elements(section).filter(|child| child.value().name() == "tr")
+}
+
+fn cells_of(row: ElementRef<'_>) -> impl Iterator<Item = ElementRef<'_>> {
+    child_elements(row).filter(|child| matches!(child.value().name(), "td" | "th"))
+}
+
+/// Collects the whitespace-normalized text of a cell, skipping any nested tables.
+fn cell_text(cell: ElementRef<'_>) -> String {
+    let mut text = String::new();
+    collect_text(cell, &mut text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn collect_text(element: ElementRef<'_>, out: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => out.push_str(text),
+            Node::Element(inner) if inner.name() == "table" => {}
+            Node::Element(inner) => {
+                if inner.name() == "br" {
+                    out.push(' ');
+                } else if let Some(inner) = ElementRef::wrap(child) {
+                    collect_text(inner, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Utilities for testing spiders end to end, in memory.
+//!
+//! Enabled by the `testing` feature, usually only for tests:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! spider-lib = { version = "1.3.0-beta", features = ["testing"] }
+//! ```
+//!
+//! [`MockResponses`] answers requests with canned responses instead of downloading them,
+//! and [`CollectorPipeline`] keeps every scraped item, so a test can run a whole crawl
+//! without a network or output files and assert on the items themselves:
+//!
+//! ```rust,ignore
+//! use spider_lib::testing::{CollectorPipeline, MockResponses};
+//!
+//! let collector = CollectorPipeline::new();
+//! let crawler = CrawlerBuilder::new(QuotesSpider)
+//!     .add_middleware(
+//!         MockResponses::new()
+//!             .html("https://quotes.toscrape.com/", include_str!("fixtures/quotes.html")),
+//!     )
+//!     .add_pipeline(collector.clone())
+//!     .build()
+//!     .await?;
+//! crawler.start_crawl().await?;
+//!
+//! assert_eq!(collector.items()[0].author, "Albert Einstein");
+//! ```
+//!
+//! The engine's builder always downloads with its own `reqwest` downloader, so the mock
+//! is a middleware that answers every request before it would be downloaded. Add it
+//! after the other middlewares, so they still see each request.
+
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use spider_core::async_trait;
+use spider_middleware::middleware::{Middleware, MiddlewareAction};
+use spider_pipeline::pipeline::Pipeline;
+use spider_util::{
+    error::{PipelineError, SpiderError},
+    item::ScrapedItem,
+    request::Request,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// A pipeline that stores every item it receives, for assertions after a crawl.
+///
+/// Clones share the same storage, so keep a clone before handing one to the builder.
+/// Items are passed on unchanged to the pipelines after it.
+pub struct CollectorPipeline<I> {
+    items: Arc<Mutex<Vec<I>>>,
+}
+
+impl<I> CollectorPipeline<I> {
+    /// Creates an empty collector.
+    pub fn new() -> Self {
+        Self {
+            items: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns the shared storage the items are collected in.
+    pub fn storage(&self) -> Arc<Mutex<Vec<I>>> {
+        self.items.clone()
+    }
+
+    /// Returns the number of items collected so far.
+    pub fn len(&self) -> usize {
+        self.items.lock().expect("collector poisoned").len()
+    }
+
+    /// Returns `true` if no item has been collected.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<I: Clone> CollectorPipeline<I> {
+    /// Returns the items collected so far, in the order they reached the pipeline.
+    pub fn items(&self) -> Vec<I> {
+        self.items.lock().expect("collector poisoned").clone()
+    }
+}
+
+impl<I> Default for CollectorPipeline<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> Clone for CollectorPipeline<I> {
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<I: ScrapedItem + Clone> Pipeline<I> for CollectorPipeline<I> {
+    fn name(&self) -> &str {
+        "CollectorPipeline"
+    }
+
+    async fn process_item(&self, item: I) -> Result<Option<I>, PipelineError> {
+        self.items
+            .lock()
+            .expect("collector poisoned")
+            .push(item.clone());
+        Ok(Some(item))
+    }
+}
+
+/// A canned response served by [`MockResponses`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl MockResponse {
+    /// Creates an empty response with `status`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` is not a valid HTTP status code.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("invalid mock status"),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a `200 OK` HTML response.
+    pub fn html(body: &str) -> Self {
+        Self::new(200)
+            .header(CONTENT_TYPE.as_str(), "text/html; charset=utf-8")
+            .body(body)
+    }
+
+    /// Adds a response header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` is not a valid header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.append(
+            HeaderName::from_bytes(name.as_bytes()).expect("invalid mock header name"),
+            HeaderValue::from_str(value).expect("invalid mock header value"),
+        );
+        self
+    }
+
+    /// Sets the response body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Returns this response as the answer to `request`, carrying its meta, e.g. to
+    /// hand it to a middleware or a parse method directly.
+    pub fn into_response(self, request: Request) -> Response {
+        Response {
+            url: request.url.clone(),
+            status: self.status,
+            headers: self.headers,
+            body: self.body.into(),
+            request_url: request.url,
+            meta: request.meta,
+            cached: false,
+        }
+    }
+}
+
+/// A middleware serving canned responses by URL instead of downloading requests.
+///
+/// URLs without a response get an empty `404 Not Found`. Every request URL is recorded,
+/// see [`MockResponses::requested`].
+#[derive(Debug, Clone, Default)]
+pub struct MockResponses {
+    responses: HashMap<Url, MockResponse>,
+    requested: Arc<Mutex<Vec<Url>>>,
+}
+
+impl MockResponses {
+    /// Creates a mock without any responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `response` for `url`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `url` is not an absolute URL.
+    pub fn route(mut self, url: &str, response: MockResponse) -> Self {
+        let url = Url::parse(url).expect("invalid mock URL");
+        self.responses.insert(url, response);
+        self
+    }
+
+    /// Serves `body` as a `200 OK` HTML page for `url`.
+    pub fn html(self, url: &str, body: &str) -> Self {
+        self.route(url, MockResponse::html(body))
+    }
+
+    /// Returns the URLs requested so far, in order. Clones share the record.
+    pub fn requested(&self) -> Vec<Url> {
+        self.requested.lock().expect("mock poisoned").clone()
+    }
+}
+
+#[async_trait]
+impl<C: Send + Sync> Middleware<C> for MockResponses {
+    fn name(&self) -> &str {
+        "MockResponses"
+    }
+
+    async fn process_request(
+        &mut self,
+        _client: &C,
+        request: Request,
+    ) -> Result<MiddlewareAction<Request>, SpiderError> {
+        self.requested
+            .lock()
+            .expect("mock poisoned")
+            .push(request.url.clone());
+        let mock = self
+            .responses
+            .get(&request.url)
+            .cloned()
+            .unwrap_or_else(|| MockResponse::new(404));
+        Ok(MiddlewareAction::ReturnResponse(
+            mock.into_response(request),
+        ))
+    }
+}
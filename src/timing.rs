@@ -0,0 +1,293 @@
+//! Per-request timing breakdown for [`HttpDownloader`](crate::downloader::HttpDownloader).
+//!
+//! With [`HttpDownloaderBuilder::record_timings`] enabled, every response carries
+//! [`RequestTimings`] in its meta under [`TIMINGS_KEY`], read back with
+//! [`ResponseExt::timings`](crate::response::ResponseExt::timings), and the downloader
+//! aggregates them into [`TimingStats`] histograms:
+//!
+//! ```rust,ignore
+//! let downloader = HttpDownloader::builder().record_timings(true).build()?;
+//! let response = downloader.download(Request::new(url)).await?;
+//! if let Some(timings) = response.timings() {
+//!     println!("dns {:?}, connect {:?}, ttfb {:?}", timings.dns, timings.connect, timings.ttfb);
+//! }
+//! println!("mean TTFB: {:?}", downloader.timing_stats().unwrap().ttfb.mean());
+//! ```
+//!
+//! Accuracy limitations:
+//!
+//! - `dns` and `connect` are only measured when the request opened a new connection;
+//!   requests on a reused keep-alive or HTTP/2 connection report `None` for both.
+//! - `connect` covers the TCP connect and the TLS handshake together; `reqwest` does not
+//!   expose the boundary between them.
+//! - Hosts given as IP addresses skip DNS, so `dns` is `None` for them.
+//! - `ttfb` runs from sending the request to receiving the response headers, so it
+//!   includes `dns` and `connect`, plus time spent waiting for a pooled connection.
+//! - When several requests race for a new connection, the one that opened it may not be
+//!   the one that uses it, and the connection's timings are attributed to the former.
+//!
+//! [`HttpDownloaderBuilder::record_timings`]:
+//!     crate::downloader::HttpDownloaderBuilder::record_timings
+
+use serde_json::{Value, json};
+use spider_core::tokio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Response meta key holding the [`RequestTimings`] of a download.
+pub const TIMINGS_KEY: &str = "timings";
+
+/// Upper bounds of the histogram buckets, in milliseconds. A last bucket holds the rest.
+const BUCKET_BOUNDS_MS: [u64; 9] = [10, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// How long the phases of one download took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RequestTimings {
+    /// DNS lookup, when a new connection was opened to a named host.
+    pub dns: Option<Duration>,
+    /// TCP connect and TLS handshake, when a new connection was opened.
+    pub connect: Option<Duration>,
+    /// From sending the request to receiving the response headers.
+    pub ttfb: Duration,
+    /// Reading the response body.
+    pub download: Duration,
+}
+
+impl RequestTimings {
+    /// Returns the time from sending the request to reading the last body byte.
+    pub fn total(&self) -> Duration {
+        self.ttfb + self.download
+    }
+
+    /// Encodes the timings for the response meta, in milliseconds.
+    pub fn to_value(&self) -> Value {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        json!({
+            "dns_ms": self.dns.map(ms),
+            "connect_ms": self.connect.map(ms),
+            "ttfb_ms": ms(self.ttfb),
+            "download_ms": ms(self.download),
+        })
+    }
+
+    /// Decodes timings written by [`to_value`](Self::to_value).
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let duration = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_f64)
+                .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok())
+        };
+        Some(Self {
+            dns: duration("dns_ms"),
+            connect: duration("connect_ms"),
+            ttfb: duration("ttfb_ms")?,
+            download: duration("download_ms")?,
+        })
+    }
+}
+
+/// Count, mean, maximum and histogram of one phase's durations.
+#[derive(Debug, Default)]
+pub struct PhaseStats {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKET_BOUNDS_MS.len() + 1],
+}
+
+impl PhaseStats {
+    fn record(&self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| micros <= bound * 1000)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the mean duration, or `None` if none was recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0)
+            .then(|| Duration::from_micros(self.total_micros.load(Ordering::Relaxed) / count))
+    }
+
+    /// Returns the longest recorded duration.
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.max_micros.load(Ordering::Relaxed))
+    }
+
+    /// Returns the histogram as `(upper bound, count)` pairs, from 10 ms to 10 s. The
+    /// last bucket, with an upper bound of `Duration::MAX`, counts longer durations.
+    pub fn histogram(&self) -> Vec<(Duration, u64)> {
+        BUCKET_BOUNDS_MS
+            .iter()
+            .map(|&bound| Duration::from_millis(bound))
+            .chain([Duration::MAX])
+            .zip(&self.buckets)
+            .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// Timing histograms of all downloads made by one downloader.
+#[derive(Debug, Default)]
+pub struct TimingStats {
+    /// DNS lookups.
+    pub dns: PhaseStats,
+    /// TCP connects with their TLS handshakes.
+    pub connect: PhaseStats,
+    /// Times to first byte.
+    pub ttfb: PhaseStats,
+    /// Body downloads.
+    pub download: PhaseStats,
+}
+
+impl TimingStats {
+    /// Adds the timings of one download.
+    pub fn record(&self, timings: &RequestTimings) {
+        if let Some(dns) = timings.dns {
+            self.dns.record(dns);
+        }
+        if let Some(connect) = timings.connect {
+            self.connect.record(connect);
+        }
+        self.ttfb.record(timings.ttfb);
+        self.download.record(timings.download);
+    }
+}
+
+/// Connection timings collected while a request is being sent.
+#[derive(Debug, Default)]
+pub(crate) struct ConnectTimings {
+    pub(crate) dns: Option<Duration>,
+    /// The whole connection setup, including DNS.
+    pub(crate) connect: Option<Duration>,
+}
+
+pub(crate) type ConnectSlot = Arc<Mutex<ConnectTimings>>;
+
+tokio::task_local! {
+    /// Where the resolver and connector of the request being sent record their timings.
+    pub(crate) static CONNECT_SLOT: ConnectSlot;
+}
+
+/// Timings of a download whose body is still being read.
+pub(crate) struct PendingTimings {
+    pub(crate) ttfb: Duration,
+    pub(crate) slot: ConnectSlot,
+    pub(crate) stats: Arc<TimingStats>,
+}
+
+impl PendingTimings {
+    /// Completes the timings once the body took `download` to read, and records them.
+    pub(crate) fn finish(self, download: Duration) -> RequestTimings {
+        let connect = self.slot.lock().expect("timing slot poisoned");
+        let timings = RequestTimings {
+            dns: connect.dns,
+            connect: connect
+                .connect
+                .map(|total| total.saturating_sub(connect.dns.unwrap_or_default())),
+            ttfb: self.ttfb,
+            download,
+        };
+        self.stats.record(&timings);
+        timings
+    }
+}
+
+/// Returns the slot of the request being sent, if timings are recorded for it.
+pub(crate) fn current_slot() -> Option<ConnectSlot> {
+    CONNECT_SLOT.try_with(Arc::clone).ok()
+}
+
+pub(crate) mod instrument {
+    //! The `reqwest` resolver and connector layer that fill [`super::ConnectTimings`].
+
+    use super::current_slot;
+    use crate::dns::DnsResolver;
+    use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+    use spider_core::tokio;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    /// Resolves names with the [`DnsResolver`] if one is set, with the system resolver
+    /// like `reqwest`'s default otherwise, and times it.
+    pub(crate) struct TimedResolver(pub(crate) Option<DnsResolver>);
+
+    impl Resolve for TimedResolver {
+        fn resolve(&self, name: Name) -> Resolving {
+            let slot = current_slot();
+            let dns = self.0.clone();
+            Box::pin(async move {
+                let started = Instant::now();
+                let addrs: Vec<_> = match dns {
+                    Some(dns) => dns.resolve_host(name.as_str()).await?,
+                    None => tokio::net::lookup_host((name.as_str(), 0)).await?.collect(),
+                };
+                if let Some(slot) = slot {
+                    slot.lock().expect("timing slot poisoned").dns = Some(started.elapsed());
+                }
+                Ok(Box::new(addrs.into_iter()) as Addrs)
+            })
+        }
+    }
+
+    /// Times connection setups made by the wrapped connector.
+    #[derive(Clone)]
+    pub(crate) struct TimedConnectLayer;
+
+    impl<S> Layer<S> for TimedConnectLayer {
+        type Service = TimedConnect<S>;
+
+        fn layer(&self, inner: S) -> TimedConnect<S> {
+            TimedConnect { inner }
+        }
+    }
+
+    #[derive(Clone)]
+    pub(crate) struct TimedConnect<S> {
+        inner: S,
+    }
+
+    impl<S, R> Service<R> for TimedConnect<S>
+    where
+        S: Service<R>,
+        S::Future: Send + 'static,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: R) -> Self::Future {
+            let slot = current_slot();
+            let started = Instant::now();
+            let connecting = self.inner.call(request);
+            Box::pin(async move {
+                let connection = connecting.await;
+                if let (Ok(_), Some(slot)) = (&connection, slot) {
+                    slot.lock().expect("timing slot poisoned").connect = Some(started.elapsed());
+                }
+                connection
+            })
+        }
+    }
+}
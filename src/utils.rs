@@ -0,0 +1,383 @@
+//! Utility functions that complement the ones of `spider_util::utils`.
+//!
+//! The functions of `spider_util::utils` are re-exported here, so this module is the one
+//! place to find URL and filesystem helpers, such as the [`canonicalize_url`] form that
+//! [`DedupSet`](crate::middleware::dupe_filter::DedupSet) fingerprints requests by.
+
+pub use spider_util::utils::*;
+
+use percent_encoding::percent_decode_str;
+use scraper::{Html, Selector};
+use url::{Host, Url};
+
+/// Returns the registrable domain (the public suffix plus one label) of a URL's host.
+///
+/// The computation is backed by the public suffix list, so multi-level suffixes are
+/// handled correctly: `https://a.b.example.co.uk/` yields `example.co.uk` rather than
+/// `co.uk`. Returns `None` for IP addresses, hosts that are themselves a public suffix,
+/// and URLs without a host.
+///
+/// # Example
+///
+/// ```
+/// use spider_lib::registrable_domain;
+/// use url::Url;
+///
+/// let url = Url::parse("https://a.b.example.co.uk/path").unwrap();
+/// assert_eq!(registrable_domain(&url).as_deref(), Some("example.co.uk"));
+/// ```
+pub fn registrable_domain(url: &Url) -> Option<String> {
+    let domain = match url.host()? {
+        Host::Domain(domain) => domain,
+        Host::Ipv4(_) | Host::Ipv6(_) => return None,
+    };
+
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    psl::domain_str(&domain).map(str::to_owned)
+}
+
+/// Returns whether two URLs share a registrable domain.
+///
+/// Domain-scoped middlewares compare hosts with this rather than with
+/// `spider_util::utils::is_same_site`, which treats every host without a registrable
+/// domain as one site. Here IP addresses and bare public suffixes only match the very
+/// same host, so `10.0.0.1` and `10.0.0.2` are different sites.
+///
+/// # Example
+///
+/// ```
+/// use spider_lib::same_registrable_domain;
+/// use url::Url;
+///
+/// let a = Url::parse("https://shop.example.co.uk/").unwrap();
+/// let b = Url::parse("https://blog.example.co.uk/").unwrap();
+/// assert!(same_registrable_domain(&a, &b));
+/// ```
+pub fn same_registrable_domain(a: &Url, b: &Url) -> bool {
+    match (registrable_domain(a), registrable_domain(b)) {
+        (Some(a), Some(b)) => a == b,
+        (None, None) => a.host().is_some() && a.host() == b.host(),
+        _ => false,
+    }
+}
+
+/// Lowercases a domain pattern and trims its leading dots, keeping a `*.` wildcard.
+pub(crate) fn normalize_domain_pattern(pattern: &str) -> String {
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(parent) => format!("*.{}", parent.trim_start_matches('.')),
+        None => pattern.trim_start_matches('.').to_string(),
+    }
+}
+
+/// Returns whether `host` matches a pattern normalized by [`normalize_domain_pattern`]:
+/// a domain matches itself and its subdomains, `*.example.com` only the subdomains.
+pub(crate) fn host_matches_domain(host: &str, pattern: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let is_subdomain = |domain: &str| {
+        host.strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.'))
+    };
+    match pattern.strip_prefix("*.") {
+        Some(parent) => is_subdomain(parent),
+        None => host == pattern || is_subdomain(pattern),
+    }
+}
+
+/// File name used by [`download_filename`] when neither the header nor the URL provide one.
+const DEFAULT_DOWNLOAD_FILENAME: &str = "download";
+
+/// Longest file name, in bytes, accepted by common file systems.
+const MAX_FILENAME_LEN: usize = 255;
+
+/// Extracts the file name from a `Content-Disposition` header value.
+///
+/// Both the plain `filename` parameter and the RFC 5987 encoded `filename*` parameter
+/// (`UTF-8` and `ISO-8859-1` charsets) are understood; `filename*` takes precedence when
+/// present. The result is sanitized with the same rules as [`download_filename`], so it is
+/// always a bare file name that is safe to join onto a download directory.
+///
+/// # Example
+///
+/// ```
+/// use spider_lib::content_disposition_filename;
+///
+/// let header = "attachment; filename=\"rates.txt\"; filename*=UTF-8''%E2%82%AC%20rates.txt";
+/// assert_eq!(content_disposition_filename(header).as_deref(), Some("€ rates.txt"));
+/// ```
+pub fn content_disposition_filename(header: &str) -> Option<String> {
+    let mut filename = None;
+    let mut extended = None;
+
+    // The first segment is the disposition type (`attachment`, `inline`, ...).
+    for param in split_params(header).into_iter().skip(1) {
+        let Some((name, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = unquote(value.trim());
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename*" if extended.is_none() => extended = decode_ext_value(&value),
+            "filename" if filename.is_none() => filename = Some(value),
+            _ => {}
+        }
+    }
+
+    extended
+        .and_then(|name| sanitize_filename(&name))
+        .or_else(|| filename.and_then(|name| sanitize_filename(&name)))
+}
+
+/// Chooses a local file name for a downloaded resource.
+///
+/// The name comes from the `Content-Disposition` header when it carries one, otherwise
+/// from the last non-empty segment of the URL path, and finally falls back to
+/// `"download"`. Directory components, control characters and characters reserved on
+/// common file systems are stripped, so the result cannot escape the directory it is
+/// saved into.
+pub fn download_filename(content_disposition: Option<&str>, url: &Url) -> String {
+    content_disposition
+        .and_then(content_disposition_filename)
+        .or_else(|| {
+            let segment = url.path_segments()?.rev().find(|s| !s.is_empty())?;
+            sanitize_filename(&percent_decode_str(segment).decode_utf8_lossy())
+        })
+        .unwrap_or_else(|| DEFAULT_DOWNLOAD_FILENAME.to_string())
+}
+
+/// Returns the canonical URL a page declares with `<link rel="canonical">`.
+///
+/// The `href` is resolved against `base` (the page URL). Only `http(s)` URLs are
+/// accepted, and with `same_origin` set a canonical on a different origin than `base` is
+/// ignored, since a page cannot usually vouch for another site's content.
+pub fn canonical_url(html: &Html, base: &Url, same_origin: bool) -> Option<Url> {
+    let selector = Selector::parse("link[rel][href]").ok()?;
+    let href = html
+        .select(&selector)
+        .find(|link| link.value().attr("rel").is_some_and(is_canonical_rel))?
+        .value()
+        .attr("href")?;
+    resolve_canonical(href, base, same_origin)
+}
+
+/// How much of a document [`canonical_url_in_head`] scans.
+const CANONICAL_PRESCAN_LIMIT: usize = 16 * 1024;
+
+/// Returns the canonical URL declared in the `<head>` of the HTML `body`, like
+/// [`canonical_url`] but without parsing the document.
+///
+/// Only the `<link>` tags before `</head>` within the first 16 KiB are scanned, which is
+/// where pages declare their canonical, and the body is read as UTF-8.
+pub fn canonical_url_in_head(body: &[u8], base: &Url, same_origin: bool) -> Option<Url> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(CANONICAL_PRESCAN_LIMIT)]);
+    // ASCII lowercasing keeps the byte offsets, so they index `head` as well.
+    let lower = head.to_ascii_lowercase();
+    let end = lower.find("</head").unwrap_or(lower.len());
+    for (start, _) in lower[..end].match_indices("<link") {
+        let tag = &head[start + "<link".len()..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        if !tag.starts_with(|c: char| c.is_ascii_whitespace() || c == '/') {
+            continue;
+        }
+        if tag_attribute(tag, "rel").is_some_and(is_canonical_rel)
+            && let Some(href) = tag_attribute(tag, "href")
+        {
+            return resolve_canonical(&href.replace("&amp;", "&"), base, same_origin);
+        }
+    }
+    None
+}
+
+fn is_canonical_rel(rel: &str) -> bool {
+    rel.split_ascii_whitespace()
+        .any(|token| token.eq_ignore_ascii_case("canonical"))
+}
+
+/// Returns the value of the attribute `name` in `attributes`, the inside of a start tag
+/// after its name.
+fn tag_attribute<'a>(mut attributes: &'a str, name: &str) -> Option<&'a str> {
+    loop {
+        attributes = attributes.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if attributes.is_empty() {
+            return None;
+        }
+        let name_end = attributes
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(attributes.len());
+        let (attribute, rest) = attributes.split_at(name_end);
+        let rest = rest.trim_start();
+        let (value, rest) = match rest.strip_prefix('=').map(str::trim_start) {
+            Some(quoted) if quoted.starts_with(['"', '\'']) => {
+                let quote = quoted.as_bytes()[0] as char;
+                let quoted = &quoted[1..];
+                let end = quoted.find(quote).unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            Some(unquoted) => unquoted.split_at(
+                unquoted
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(unquoted.len()),
+            ),
+            None => ("", rest),
+        };
+        if attribute.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        attributes = rest;
+    }
+}
+
+/// Resolves the `href` of a canonical link, see [`canonical_url`].
+fn resolve_canonical(href: &str, base: &Url, same_origin: bool) -> Option<Url> {
+    let href = href.trim();
+    if href.is_empty() {
+        return None;
+    }
+
+    let canonical = base.join(href).ok()?;
+    if !matches!(canonical.scheme(), "http" | "https") {
+        return None;
+    }
+    if same_origin && canonical.origin() != base.origin() {
+        return None;
+    }
+    Some(canonical)
+}
+
+/// Query parameters that only track the visitor and never change the page, dropped by
+/// [`canonicalize_url`]. A trailing `*` matches any parameter with that prefix.
+pub const TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "_ga", "_gl",
+];
+
+/// Returns a canonical form of `url` for deduplication, dropping [`TRACKING_PARAMS`].
+///
+/// See [`canonicalize_url_with`] for the normalization applied.
+pub fn canonicalize_url(url: &Url) -> Url {
+    canonicalize_url_with(url, TRACKING_PARAMS)
+}
+
+/// Returns a canonical form of `url`, dropping the query parameters in `strip_params`.
+///
+/// Parameter names in `strip_params` are matched case-insensitively, and a trailing `*`
+/// matches any parameter with that prefix. The remaining query parameters are sorted by
+/// name and value and re-encoded, and the fragment is removed. Hosts are lowercased and
+/// default ports removed when the URL is parsed, so URLs that differ only in those
+/// respects also compare equal.
+pub fn canonicalize_url_with(url: &Url, strip_params: &[&str]) -> Url {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| {
+            !strip_params
+                .iter()
+                .any(|pattern| param_matches(pattern, name))
+        })
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    pairs.sort();
+
+    let mut canonical = url.clone();
+    canonical.set_fragment(None);
+    if pairs.is_empty() {
+        canonical.set_query(None);
+    } else {
+        canonical.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    canonical
+}
+
+fn param_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => name.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Splits a header value on `;`, ignoring separators inside quoted strings.
+fn split_params(header: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in header.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                params.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&header[start..]);
+    params
+}
+
+/// Removes surrounding quotes from a quoted-string and resolves backslash escapes.
+fn unquote(value: &str) -> String {
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    else {
+        return value.to_string();
+    };
+
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                out.push(escaped);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Decodes an RFC 5987 `charset'language'value` extended parameter.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?.to_ascii_lowercase();
+    let _language = parts.next()?;
+    let bytes: Vec<u8> = percent_decode_str(parts.next()?).collect();
+
+    match charset.as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" => Some(bytes.into_iter().map(char::from).collect()),
+        _ => None,
+    }
+}
+
+/// Reduces a suggested file name to a safe bare name, or `None` if nothing usable remains.
+fn sanitize_filename(name: &str) -> Option<String> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let cleaned: String = base
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Leading dots would create hidden files (or `..`); trailing dots are invalid on Windows.
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    if cleaned.is_empty() {
+        return None;
+    }
+
+    let mut end = cleaned.len().min(MAX_FILENAME_LEN);
+    while !cleaned.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(cleaned[..end].to_string())
+}
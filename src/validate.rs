@@ -0,0 +1,99 @@
+//! Checking that a response is what its request expected.
+//!
+//! Some failures only show in the body: an API endpoint answering a block page with
+//! `200 OK`, or a login wall served in place of a listing. A request can carry
+//! [`ResponseValidator`]s, set with [`RequestExt::validate`], and the
+//! [`ValidationMiddleware`] checks its response against them before it is parsed:
+//!
+//! ```rust,ignore
+//! let request = Request::new(api_url)
+//!     .validate(ResponseValidator::Json)
+//!     .validate(ResponseValidator::NotContains("captcha".into()));
+//!
+//! let crawler = CrawlerBuilder::new(MySpider.routed())
+//!     .add_middleware(RetryPolicyMiddleware::default())
+//!     .add_middleware(ValidationMiddleware::new())
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! A response failing a validator is retried like a retryable status: the retry counts
+//! against the same per-request attempts and limit as
+//! [`RetryPolicyMiddleware`](crate::middleware::retry::RetryPolicyMiddleware)'s retries.
+//! Once the retries are exhausted the response is passed on, marked with the failure under
+//! [`VALIDATION_ERROR_KEY`]. A [`Routed`](crate::callback::Routed) spider hands a marked
+//! response to its [`errback`](crate::callback::Callbacks::errback) instead of the
+//! callback; other spiders can check [`ResponseExt::validation_error`] in `parse`.
+//!
+//! [`RequestExt::validate`]: crate::request::RequestExt::validate
+//! [`ValidationMiddleware`]: crate::middleware::validation::ValidationMiddleware
+//! [`ResponseExt::validation_error`]: crate::response::ResponseExt::validation_error
+
+use serde::{Deserialize, Serialize};
+use spider_util::response::Response;
+
+/// Meta key holding the failure of a response that failed validation after its retries.
+pub const VALIDATION_ERROR_KEY: &str = "validation_error";
+
+/// An expectation on the response to a request, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ResponseValidator {
+    /// The body is valid JSON.
+    Json,
+    /// The `Content-Type` media type is the given one, compared case-insensitively and
+    /// without parameters, e.g. `application/json`.
+    ContentType(String),
+    /// The body contains the given text.
+    Contains(String),
+    /// The body does not contain the given text.
+    NotContains(String),
+}
+
+impl ResponseValidator {
+    /// Checks `response`, returning why it fails.
+    pub fn check(&self, response: &Response) -> Result<(), String> {
+        match self {
+            ResponseValidator::Json => serde_json::from_slice::<serde_json::Value>(&response.body)
+                .map(|_| ())
+                .map_err(|e| format!("body is not JSON: {}", e)),
+            ResponseValidator::ContentType(expected) => {
+                let actual = response
+                    .headers
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("");
+                let media_type = actual.split(';').next().unwrap_or("").trim();
+                if media_type.eq_ignore_ascii_case(expected) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "content type is `{}`, expected `{}`",
+                        media_type, expected
+                    ))
+                }
+            }
+            ResponseValidator::Contains(text) => {
+                if contains(&response.body, text) {
+                    Ok(())
+                } else {
+                    Err(format!("body does not contain `{}`", text))
+                }
+            }
+            ResponseValidator::NotContains(text) => {
+                if contains(&response.body, text) {
+                    Err(format!("body contains `{}`", text))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn contains(body: &[u8], text: &str) -> bool {
+    text.is_empty()
+        || body
+            .windows(text.len())
+            .any(|window| window == text.as_bytes())
+}
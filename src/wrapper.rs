@@ -0,0 +1,77 @@
+//! The `Spider` implementation shared by the facade's spider wrappers.
+//!
+//! Wrappers such as `Scoped` or `Sampled` hold the spider they wrap in a `spider` field
+//! and only change what happens around its `parse`. `impl_spider_wrapper!` writes the
+//! rest: the wrapper keeps the spider's item and state types, so wrappers compose and
+//! the innermost spider still gets its state, and `start_requests` forwards to the
+//! wrapped spider unless the wrapper gives its own.
+
+/// Implements `Spider` for a wrapper around the spider in its `spider` field.
+///
+/// ```rust,ignore
+/// impl_spider_wrapper! {
+///     impl Spider for Scoped<S: Spider> {
+///         async fn parse(&self, response, state) {
+///             let output = self.spider.parse(response, state).await?;
+///             // ...
+///         }
+///     }
+/// }
+/// ```
+///
+/// `type State = ...;` replaces the wrapped spider's state type and
+/// `fn start_requests(&self) { ... }` its start requests; both go before `parse`.
+macro_rules! impl_spider_wrapper {
+    (
+        impl Spider for $wrapper:ident<$S:ident: $bound:path>
+        $(where $($where_ty:ty: $where_bound:path),+)?
+        {
+            $(type State = $state:ty;)?
+            $(fn start_requests(&$start_self:ident) $start_requests:block)?
+            async fn parse(&$parse_self:ident, $response:ident, $parse_state:ident) $parse:block
+        }
+    ) => {
+        #[::spider_core::async_trait]
+        impl<$S: $bound> ::spider_core::Spider for $wrapper<$S>
+        $(where $($where_ty: $where_bound),+)?
+        {
+            type Item = $S::Item;
+            type State = impl_spider_wrapper!(@state $S $($state)?);
+
+            impl_spider_wrapper!(@start_requests $($start_self $start_requests)?);
+
+            async fn parse(
+                &$parse_self,
+                $response: ::spider_util::response::Response,
+                $parse_state: &Self::State,
+            ) -> ::std::result::Result<
+                ::spider_util::item::ParseOutput<Self::Item>,
+                ::spider_util::error::SpiderError,
+            > $parse
+        }
+    };
+    (@state $S:ident) => {
+        $S::State
+    };
+    (@state $S:ident $state:ty) => {
+        $state
+    };
+    (@start_requests) => {
+        fn start_requests(
+            &self,
+        ) -> ::std::result::Result<
+            ::std::vec::Vec<::spider_util::request::Request>,
+            ::spider_util::error::SpiderError,
+        > {
+            self.spider.start_requests()
+        }
+    };
+    (@start_requests $start_self:ident $start_requests:block) => {
+        fn start_requests(
+            &$start_self,
+        ) -> ::std::result::Result<
+            ::std::vec::Vec<::spider_util::request::Request>,
+            ::spider_util::error::SpiderError,
+        > $start_requests
+    };
+}
@@ -0,0 +1,250 @@
+//! XPath selection over parsed HTML.
+//!
+//! Some elements are much easier to reach with XPath than with CSS, for example text
+//! nodes or `following-sibling` relations. [`XPath`] evaluates expressions directly on a
+//! [`Html`] document parsed by `scraper`, so CSS selectors and XPath can be used on the
+//! same document without parsing it twice. An [`XPathDocument`] keeps the parsed page
+//! together with the document order XPath results are sorted by, so it is worth keeping
+//! when many expressions run on one page:
+//!
+//! ```rust,ignore
+//! let page = XPathDocument::from(response.to_html()?);
+//! for quote in page.xpath("//div[@class='quote']")? {
+//!     let quote = quote.as_element().unwrap();
+//!     let text = page.xpath_from(quote, "./span[1]")?;
+//!     let tags = quote.select(&tag_selector).count();
+//! }
+//! ```
+//!
+//! For a quick lookup, [`ResponseExt::xpath`](crate::response::ResponseExt::xpath)
+//! returns owned [`XPathMatch`]es that keep their text and attributes.
+//!
+//! The supported subset of XPath 1.0 covers location paths with the `child`,
+//! `descendant`, `descendant-or-self`, `self`, `parent`, `ancestor`, `ancestor-or-self`,
+//! `following-sibling`, `preceding-sibling` and `attribute` axes (and their `//`, `.`,
+//! `..` and `@` abbreviations), the `*`, `text()` and `node()` node tests, unions with
+//! `|`, and predicates using positions, comparisons, `and`/`or` and the functions
+//! `last`, `position`, `count`, `contains`, `starts-with`, `normalize-space`, `string`,
+//! `string-length`, `concat`, `not`, `true` and `false`.
+
+mod eval;
+mod parser;
+
+use eval::DocumentOrder;
+use scraper::{ElementRef, Html};
+use std::cell::OnceCell;
+use std::fmt;
+
+/// Errors that can occur while evaluating an XPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XPathError {
+    /// The expression is not valid or uses unsupported XPath features.
+    Syntax(String),
+    /// The response body could not be parsed as HTML.
+    Html(String),
+}
+
+impl fmt::Display for XPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XPathError::Syntax(reason) => write!(f, "invalid XPath expression: {}", reason),
+            XPathError::Html(reason) => write!(f, "cannot parse response as HTML: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for XPathError {}
+
+/// A node selected by an XPath expression.
+#[derive(Debug, Clone, Copy)]
+pub enum XPathNode<'a> {
+    /// An element.
+    Element(ElementRef<'a>),
+    /// A text node.
+    Text(&'a str),
+    /// An attribute of an element.
+    Attribute { name: &'a str, value: &'a str },
+}
+
+impl<'a> XPathNode<'a> {
+    /// Returns the text of the node: the concatenated text of an element, the content of
+    /// a text node, or the value of an attribute.
+    pub fn text(&self) -> String {
+        match self {
+            XPathNode::Element(element) => element.text().collect(),
+            XPathNode::Text(text) => text.to_string(),
+            XPathNode::Attribute { value, .. } => value.to_string(),
+        }
+    }
+
+    /// Returns the value of attribute `name` if the node is an element.
+    pub fn attr(&self, name: &str) -> Option<&'a str> {
+        match self {
+            XPathNode::Element(element) => element.value().attr(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the node as an element, so that CSS selectors can be applied to it.
+    pub fn as_element(&self) -> Option<ElementRef<'a>> {
+        match self {
+            XPathNode::Element(element) => Some(*element),
+            _ => None,
+        }
+    }
+}
+
+/// A node selected by [`ResponseExt::xpath`](crate::response::ResponseExt::xpath), copied
+/// out of the document so it outlives the parsed page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPathMatch {
+    text: String,
+    name: Option<String>,
+    attrs: Vec<(String, String)>,
+}
+
+impl XPathMatch {
+    /// Returns the text of the node, like [`XPathNode::text`].
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the element's tag name or the attribute's name, `None` for text nodes.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the value of attribute `name` if the node is an element.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl From<XPathNode<'_>> for XPathMatch {
+    fn from(node: XPathNode<'_>) -> Self {
+        let (name, attrs) = match node {
+            XPathNode::Element(element) => (
+                Some(element.value().name().to_string()),
+                element
+                    .value()
+                    .attrs()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            ),
+            XPathNode::Text(_) => (None, Vec::new()),
+            XPathNode::Attribute { name, .. } => (Some(name.to_string()), Vec::new()),
+        };
+        Self {
+            text: node.text(),
+            name,
+            attrs,
+        }
+    }
+}
+
+/// A compiled XPath expression.
+#[derive(Debug, Clone)]
+pub struct XPath {
+    paths: Vec<parser::Path>,
+}
+
+impl XPath {
+    /// Compiles `expr`, which must select nodes (a location path or a union of paths).
+    pub fn parse(expr: &str) -> Result<Self, XPathError> {
+        Ok(Self {
+            paths: parser::parse(expr)?,
+        })
+    }
+
+    /// Evaluates the expression against the whole document.
+    ///
+    /// The document order is computed on every call; use an [`XPathDocument`] to
+    /// evaluate many expressions on the same page.
+    pub fn select<'a>(&self, html: &'a Html) -> Vec<XPathNode<'a>> {
+        let root = html.tree.root();
+        eval::evaluate(&self.paths, root, root, &eval::document_order(root))
+    }
+
+    /// Evaluates the expression with `element` as the context node.
+    ///
+    /// Relative paths start at `element`; absolute paths still start at the document root.
+    pub fn select_from<'a>(&self, element: ElementRef<'a>) -> Vec<XPathNode<'a>> {
+        let root = element.ancestors().last().unwrap_or(*element);
+        eval::evaluate(&self.paths, root, *element, &eval::document_order(root))
+    }
+}
+
+/// A parsed page for repeated XPath and CSS selection.
+///
+/// The document order is computed once, on the first evaluation, and reused by every
+/// later one, including evaluations relative to an element of the page.
+#[derive(Debug)]
+pub struct XPathDocument {
+    html: Html,
+    order: OnceCell<DocumentOrder>,
+}
+
+impl XPathDocument {
+    /// Parses `document` as HTML.
+    pub fn parse(document: &str) -> Self {
+        Self::from(Html::parse_document(document))
+    }
+
+    /// Returns the parsed page, for CSS selectors.
+    pub fn html(&self) -> &Html {
+        &self.html
+    }
+
+    /// Returns the parsed page.
+    pub fn into_html(self) -> Html {
+        self.html
+    }
+
+    /// Evaluates `xpath` against the whole page.
+    pub fn select(&self, xpath: &XPath) -> Vec<XPathNode<'_>> {
+        let root = self.html.tree.root();
+        eval::evaluate(&xpath.paths, root, root, self.order())
+    }
+
+    /// Evaluates `xpath` with `element`, which must belong to this page, as the context
+    /// node.
+    pub fn select_from<'a>(&'a self, element: ElementRef<'a>, xpath: &XPath) -> Vec<XPathNode<'a>> {
+        eval::evaluate(&xpath.paths, self.html.tree.root(), *element, self.order())
+    }
+
+    /// Compiles and evaluates `expr` against the whole page.
+    pub fn xpath(&self, expr: &str) -> Result<Vec<XPathNode<'_>>, XPathError> {
+        Ok(self.select(&XPath::parse(expr)?))
+    }
+
+    /// Compiles and evaluates `expr` with `element` as the context node.
+    pub fn xpath_from<'a>(
+        &'a self,
+        element: ElementRef<'a>,
+        expr: &str,
+    ) -> Result<Vec<XPathNode<'a>>, XPathError> {
+        Ok(self.select_from(element, &XPath::parse(expr)?))
+    }
+
+    fn order(&self) -> &DocumentOrder {
+        self.order
+            .get_or_init(|| eval::document_order(self.html.tree.root()))
+    }
+}
+
+impl From<Html> for XPathDocument {
+    fn from(html: Html) -> Self {
+        Self {
+            html,
+            order: OnceCell::new(),
+        }
+    }
+}
+
+/// Evaluates `expr` against the whole document.
+pub fn xpath<'a>(html: &'a Html, expr: &str) -> Result<Vec<XPathNode<'a>>, XPathError> {
+    Ok(XPath::parse(expr)?.select(html))
+}
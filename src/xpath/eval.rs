@@ -0,0 +1,354 @@
+//! Evaluation of parsed XPath expressions on a `scraper` document.
+
+use super::XPathNode;
+use super::parser::{Axis, CmpOp, Expr, Function, NodeTest, Path, Step};
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Node};
+use std::collections::HashMap;
+
+/// Positions of the nodes of a document in document order.
+pub(super) type DocumentOrder = HashMap<NodeId, usize>;
+
+/// Numbers the nodes under `root` in document order.
+pub(super) fn document_order(root: NodeRef<'_, Node>) -> DocumentOrder {
+    root.descendants()
+        .enumerate()
+        .map(|(index, node)| (node.id(), index))
+        .collect()
+}
+
+/// Evaluates `paths` from `context` and returns the matches in document order.
+pub(super) fn evaluate<'a>(
+    paths: &[Path],
+    root: NodeRef<'a, Node>,
+    context: NodeRef<'a, Node>,
+    order: &DocumentOrder,
+) -> Vec<XPathNode<'a>> {
+    let evaluator = Evaluator { root, order };
+    let mut items = Vec::new();
+    for path in paths {
+        items.extend(evaluator.path(path, Item::Node(context)));
+    }
+    evaluator
+        .sorted(items)
+        .into_iter()
+        .filter_map(Item::to_node)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Item<'a> {
+    Node(NodeRef<'a, Node>),
+    Attr {
+        owner: NodeRef<'a, Node>,
+        index: usize,
+        name: &'a str,
+        value: &'a str,
+    },
+}
+
+impl<'a> Item<'a> {
+    fn to_node(self) -> Option<XPathNode<'a>> {
+        match self {
+            Item::Attr { name, value, .. } => Some(XPathNode::Attribute { name, value }),
+            Item::Node(node) => match node.value() {
+                Node::Element(_) => ElementRef::wrap(node).map(XPathNode::Element),
+                Node::Text(text) => Some(XPathNode::Text(text)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value<'a> {
+    Nodes(Vec<Item<'a>>),
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+struct Evaluator<'a, 'o> {
+    root: NodeRef<'a, Node>,
+    order: &'o DocumentOrder,
+}
+
+impl<'a> Evaluator<'a, '_> {
+    /// Position of an item in document order; attributes follow their element.
+    fn key(&self, item: &Item<'a>) -> (usize, usize) {
+        match item {
+            Item::Node(node) => (self.order.get(&node.id()).copied().unwrap_or(0), 0),
+            Item::Attr { owner, index, .. } => {
+                (self.order.get(&owner.id()).copied().unwrap_or(0), index + 1)
+            }
+        }
+    }
+
+    fn sorted(&self, mut items: Vec<Item<'a>>) -> Vec<Item<'a>> {
+        items.sort_by_key(|item| self.key(item));
+        items.dedup_by_key(|item| self.key(item));
+        items
+    }
+
+    fn path(&self, path: &Path, context: Item<'a>) -> Vec<Item<'a>> {
+        let mut current = if path.absolute {
+            vec![Item::Node(self.root)]
+        } else {
+            vec![context]
+        };
+        for step in &path.steps {
+            let next = current
+                .into_iter()
+                .flat_map(|item| self.step(step, item))
+                .collect();
+            current = self.sorted(next);
+        }
+        current
+    }
+
+    fn step(&self, step: &Step, context: Item<'a>) -> Vec<Item<'a>> {
+        let mut items: Vec<Item<'a>> = axis(step.axis, context)
+            .into_iter()
+            .filter(|item| node_test(step.axis, &step.test, item))
+            .collect();
+
+        for predicate in &step.predicates {
+            let size = items.len();
+            items = items
+                .into_iter()
+                .enumerate()
+                .filter(
+                    |(index, item)| match self.eval(predicate, *item, index + 1, size) {
+                        Value::Num(position) => position == (index + 1) as f64,
+                        value => self.boolean(&value),
+                    },
+                )
+                .map(|(_, item)| item)
+                .collect();
+        }
+        items
+    }
+
+    fn eval(&self, expr: &Expr, item: Item<'a>, position: usize, size: usize) -> Value<'a> {
+        match expr {
+            Expr::Or(left, right) => Value::Bool(
+                self.boolean(&self.eval(left, item, position, size))
+                    || self.boolean(&self.eval(right, item, position, size)),
+            ),
+            Expr::And(left, right) => Value::Bool(
+                self.boolean(&self.eval(left, item, position, size))
+                    && self.boolean(&self.eval(right, item, position, size)),
+            ),
+            Expr::Compare(op, left, right) => Value::Bool(self.compare(
+                *op,
+                self.eval(left, item, position, size),
+                self.eval(right, item, position, size),
+            )),
+            Expr::Literal(literal) => Value::Str(literal.clone()),
+            Expr::Number(number) => Value::Num(*number),
+            Expr::Path(path) => Value::Nodes(self.path(path, item)),
+            Expr::Call(function, args) => {
+                let arg = |index: usize| -> Value<'a> {
+                    match args.get(index) {
+                        Some(expr) => self.eval(expr, item, position, size),
+                        None => Value::Nodes(vec![item]),
+                    }
+                };
+                match function {
+                    Function::Last => Value::Num(size as f64),
+                    Function::Position => Value::Num(position as f64),
+                    Function::Count => match arg(0) {
+                        Value::Nodes(nodes) => Value::Num(nodes.len() as f64),
+                        _ => Value::Num(f64::NAN),
+                    },
+                    Function::Contains => {
+                        Value::Bool(self.string(&arg(0)).contains(self.string(&arg(1)).as_str()))
+                    }
+                    Function::StartsWith => Value::Bool(
+                        self.string(&arg(0))
+                            .starts_with(self.string(&arg(1)).as_str()),
+                    ),
+                    Function::NormalizeSpace => Value::Str(
+                        self.string(&arg(0))
+                            .split_whitespace()
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    Function::String => Value::Str(self.string(&arg(0))),
+                    Function::StringLength => {
+                        Value::Num(self.string(&arg(0)).chars().count() as f64)
+                    }
+                    Function::Concat => {
+                        Value::Str((0..args.len()).map(|i| self.string(&arg(i))).collect())
+                    }
+                    Function::Not => Value::Bool(!self.boolean(&arg(0))),
+                    Function::True => Value::Bool(true),
+                    Function::False => Value::Bool(false),
+                }
+            }
+        }
+    }
+
+    fn string_value(&self, item: &Item<'a>) -> String {
+        match item {
+            Item::Attr { value, .. } => value.to_string(),
+            Item::Node(node) => match node.value() {
+                Node::Text(text) => text.to_string(),
+                Node::Comment(comment) => comment.to_string(),
+                _ => node
+                    .descendants()
+                    .filter_map(|descendant| descendant.value().as_text())
+                    .map(|text| &**text)
+                    .collect(),
+            },
+        }
+    }
+
+    fn string(&self, value: &Value<'a>) -> String {
+        match value {
+            Value::Nodes(nodes) => nodes
+                .first()
+                .map(|item| self.string_value(item))
+                .unwrap_or_default(),
+            Value::Str(string) => string.clone(),
+            Value::Num(number) if number.is_finite() && number.fract() == 0.0 => {
+                format!("{}", *number as i64)
+            }
+            Value::Num(number) => number.to_string(),
+            Value::Bool(boolean) => boolean.to_string(),
+        }
+    }
+
+    fn number(&self, value: &Value<'a>) -> f64 {
+        match value {
+            Value::Num(number) => *number,
+            Value::Bool(boolean) => f64::from(u8::from(*boolean)),
+            _ => self.string(value).trim().parse().unwrap_or(f64::NAN),
+        }
+    }
+
+    fn boolean(&self, value: &Value<'a>) -> bool {
+        match value {
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::Str(string) => !string.is_empty(),
+            Value::Num(number) => *number != 0.0 && !number.is_nan(),
+            Value::Bool(boolean) => *boolean,
+        }
+    }
+
+    /// Compares two values; comparisons involving node sets are true if they hold for
+    /// any node in the set.
+    fn compare(&self, op: CmpOp, left: Value<'a>, right: Value<'a>) -> bool {
+        match (left, right) {
+            (Value::Nodes(left), Value::Nodes(right)) => left.iter().any(|a| {
+                let a = Value::Str(self.string_value(a));
+                right
+                    .iter()
+                    .any(|b| self.compare_atomic(op, &a, &Value::Str(self.string_value(b))))
+            }),
+            (Value::Nodes(nodes), Value::Bool(boolean)) => {
+                self.compare_atomic(op, &Value::Bool(!nodes.is_empty()), &Value::Bool(boolean))
+            }
+            (Value::Bool(boolean), Value::Nodes(nodes)) => {
+                self.compare_atomic(op, &Value::Bool(boolean), &Value::Bool(!nodes.is_empty()))
+            }
+            (Value::Nodes(nodes), other) => nodes
+                .iter()
+                .any(|node| self.compare_atomic(op, &Value::Str(self.string_value(node)), &other)),
+            (other, Value::Nodes(nodes)) => nodes
+                .iter()
+                .any(|node| self.compare_atomic(op, &other, &Value::Str(self.string_value(node)))),
+            (left, right) => self.compare_atomic(op, &left, &right),
+        }
+    }
+
+    fn compare_atomic(&self, op: CmpOp, left: &Value<'a>, right: &Value<'a>) -> bool {
+        let equal = || match (left, right) {
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => self.boolean(left) == self.boolean(right),
+            (Value::Num(_), _) | (_, Value::Num(_)) => self.number(left) == self.number(right),
+            _ => self.string(left) == self.string(right),
+        };
+        let (left, right) = (self.number(left), self.number(right));
+        match op {
+            CmpOp::Eq => equal(),
+            CmpOp::Ne => !equal(),
+            CmpOp::Lt => left < right,
+            CmpOp::Le => left <= right,
+            CmpOp::Gt => left > right,
+            CmpOp::Ge => left >= right,
+        }
+    }
+}
+
+/// Returns the items on `axis` from `context`, nearest first.
+fn axis<'a>(axis: Axis, context: Item<'a>) -> Vec<Item<'a>> {
+    let node = match context {
+        Item::Node(node) => node,
+        Item::Attr { owner, .. } => {
+            return match axis {
+                Axis::SelfNode => vec![context],
+                Axis::Parent => vec![Item::Node(owner)],
+                Axis::Ancestor => std::iter::once(owner)
+                    .chain(owner.ancestors())
+                    .map(Item::Node)
+                    .collect(),
+                Axis::AncestorOrSelf => std::iter::once(context)
+                    .chain(
+                        std::iter::once(owner)
+                            .chain(owner.ancestors())
+                            .map(Item::Node),
+                    )
+                    .collect(),
+                _ => Vec::new(),
+            };
+        }
+    };
+
+    match axis {
+        Axis::Child => node.children().map(Item::Node).collect(),
+        Axis::Descendant => node.descendants().skip(1).map(Item::Node).collect(),
+        Axis::DescendantOrSelf => node.descendants().map(Item::Node).collect(),
+        Axis::SelfNode => vec![context],
+        Axis::Parent => node.parent().map(Item::Node).into_iter().collect(),
+        Axis::Ancestor => node.ancestors().map(Item::Node).collect(),
+        Axis::AncestorOrSelf => std::iter::once(node)
+            .chain(node.ancestors())
+            .map(Item::Node)
+            .collect(),
+        Axis::FollowingSibling => node.next_siblings().map(Item::Node).collect(),
+        Axis::PrecedingSibling => node.prev_siblings().map(Item::Node).collect(),
+        Axis::Attribute => match node.value().as_element() {
+            Some(element) => element
+                .attrs()
+                .enumerate()
+                .map(|(index, (name, value))| Item::Attr {
+                    owner: node,
+                    index,
+                    name,
+                    value,
+                })
+                .collect(),
+            None => Vec::new(),
+        },
+    }
+}
+
+fn node_test(axis: Axis, test: &NodeTest, item: &Item<'_>) -> bool {
+    match (test, item) {
+        (NodeTest::Node, _) => true,
+        (NodeTest::Text, Item::Node(node)) => node.value().is_text(),
+        (NodeTest::Text, Item::Attr { .. }) => false,
+        (NodeTest::Any, Item::Attr { .. }) => axis == Axis::Attribute,
+        (NodeTest::Any, Item::Node(node)) => axis != Axis::Attribute && node.value().is_element(),
+        (NodeTest::Name(expected), Item::Attr { name, .. }) => {
+            axis == Axis::Attribute && name.eq_ignore_ascii_case(expected)
+        }
+        (NodeTest::Name(expected), Item::Node(node)) => {
+            axis != Axis::Attribute
+                && node
+                    .value()
+                    .as_element()
+                    .is_some_and(|element| element.name().eq_ignore_ascii_case(expected))
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! Tokenizer and parser for the supported XPath subset.
+
+use super::XPathError;
+
+/// Parses `expr`, a location path or a union of paths.
+pub(super) fn parse(expr: &str) -> Result<Vec<Path>, XPathError> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+    };
+    let mut paths = vec![parser.path()?];
+    while parser.eat(&Token::Pipe) {
+        paths.push(parser.path()?);
+    }
+    if let Some((token, offset)) = parser.tokens.get(parser.pos) {
+        return Err(XPathError::Syntax(format!(
+            "unexpected {:?} at offset {}",
+            token, offset
+        )));
+    }
+    Ok(paths)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    Dot,
+    DotDot,
+    At,
+    Star,
+    Pipe,
+    Comma,
+    ColonColon,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Op(CmpOp),
+    Literal(String),
+    Number(f64),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(Token, usize)>, XPathError> {
+    let chars: Vec<(usize, char)> = expr.char_indices().collect();
+    let peek = |i: usize| chars.get(i).map(|(_, c)| *c);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while let Some(&(offset, c)) = chars.get(i) {
+        let (token, len) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '/' if peek(i + 1) == Some('/') => (Token::DoubleSlash, 2),
+            '/' => (Token::Slash, 1),
+            '.' if peek(i + 1) == Some('.') => (Token::DotDot, 2),
+            '.' if !peek(i + 1).is_some_and(|c| c.is_ascii_digit()) => (Token::Dot, 1),
+            '@' => (Token::At, 1),
+            '*' => (Token::Star, 1),
+            '|' => (Token::Pipe, 1),
+            ',' => (Token::Comma, 1),
+            '[' => (Token::LBracket, 1),
+            ']' => (Token::RBracket, 1),
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            ':' if peek(i + 1) == Some(':') => (Token::ColonColon, 2),
+            '=' => (Token::Op(CmpOp::Eq), 1),
+            '!' if peek(i + 1) == Some('=') => (Token::Op(CmpOp::Ne), 2),
+            '<' if peek(i + 1) == Some('=') => (Token::Op(CmpOp::Le), 2),
+            '<' => (Token::Op(CmpOp::Lt), 1),
+            '>' if peek(i + 1) == Some('=') => (Token::Op(CmpOp::Ge), 2),
+            '>' => (Token::Op(CmpOp::Gt), 1),
+            '"' | '\'' => {
+                let end = (i + 1..chars.len())
+                    .find(|&j| chars[j].1 == c)
+                    .ok_or_else(|| {
+                        XPathError::Syntax(format!("unterminated literal at offset {}", offset))
+                    })?;
+                let literal = chars[i + 1..end].iter().map(|(_, c)| c).collect();
+                (Token::Literal(literal), end - i + 1)
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_ascii_digit() || *c == '.')
+                    .count();
+                let text: String = chars[i..i + len].iter().map(|(_, c)| c).collect();
+                let number = text.parse().map_err(|_| {
+                    XPathError::Syntax(format!("invalid number {:?} at offset {}", text, offset))
+                })?;
+                (Token::Number(number), len)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '-')
+                    .count();
+                let name = chars[i..i + len].iter().map(|(_, c)| c).collect();
+                (Token::Name(name), len)
+            }
+            c => {
+                return Err(XPathError::Syntax(format!(
+                    "unexpected character {:?} at offset {}",
+                    c, offset
+                )));
+            }
+        };
+        tokens.push((token, offset));
+        i += len;
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Axis {
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    SelfNode,
+    Parent,
+    Ancestor,
+    AncestorOrSelf,
+    FollowingSibling,
+    PrecedingSibling,
+    Attribute,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum NodeTest {
+    Name(String),
+    Any,
+    Text,
+    Node,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Step {
+    pub(super) axis: Axis,
+    pub(super) test: NodeTest,
+    pub(super) predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Path {
+    pub(super) absolute: bool,
+    pub(super) steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Function {
+    Last,
+    Position,
+    Count,
+    Contains,
+    StartsWith,
+    NormalizeSpace,
+    String,
+    StringLength,
+    Concat,
+    Not,
+    True,
+    False,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(CmpOp, Box<Expr>, Box<Expr>),
+    Literal(String),
+    Number(f64),
+    Call(Function, Vec<Expr>),
+    Path(Path),
+}
+
+struct Parser<'t> {
+    tokens: &'t [(Token, usize)],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + ahead).map(|(token, _)| token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(n)) if n == name) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, expected: &str) -> XPathError {
+        match self.tokens.get(self.pos) {
+            Some((token, offset)) => XPathError::Syntax(format!(
+                "expected {} at offset {}, found {:?}",
+                expected, offset, token
+            )),
+            None => XPathError::Syntax(format!("expected {} at end of expression", expected)),
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<(), XPathError> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Dot | Token::DotDot | Token::At | Token::Star | Token::Name(_))
+        )
+    }
+
+    fn path(&mut self) -> Result<Path, XPathError> {
+        let mut path = Path {
+            absolute: false,
+            steps: Vec::new(),
+        };
+
+        if self.eat(&Token::Slash) {
+            path.absolute = true;
+            if !self.starts_step() {
+                return Ok(path);
+            }
+        } else if self.eat(&Token::DoubleSlash) {
+            path.absolute = true;
+            path.steps.push(descendant_or_self());
+        }
+        path.steps.push(self.step()?);
+
+        loop {
+            if self.eat(&Token::Slash) {
+                path.steps.push(self.step()?);
+            } else if self.eat(&Token::DoubleSlash) {
+                path.steps.push(descendant_or_self());
+                path.steps.push(self.step()?);
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<Step, XPathError> {
+        if self.eat(&Token::Dot) {
+            return Ok(node_step(Axis::SelfNode));
+        }
+        if self.eat(&Token::DotDot) {
+            return Ok(node_step(Axis::Parent));
+        }
+
+        let axis = if self.eat(&Token::At) {
+            Axis::Attribute
+        } else if let (Some(Token::Name(name)), Some(Token::ColonColon)) =
+            (self.peek(), self.peek_at(1))
+        {
+            let axis = match name.as_str() {
+                "child" => Axis::Child,
+                "descendant" => Axis::Descendant,
+                "descendant-or-self" => Axis::DescendantOrSelf,
+                "self" => Axis::SelfNode,
+                "parent" => Axis::Parent,
+                "ancestor" => Axis::Ancestor,
+                "ancestor-or-self" => Axis::AncestorOrSelf,
+                "following-sibling" => Axis::FollowingSibling,
+                "preceding-sibling" => Axis::PrecedingSibling,
+                "attribute" => Axis::Attribute,
+                _ => return Err(XPathError::Syntax(format!("unsupported axis {:?}", name))),
+            };
+            self.pos += 2;
+            axis
+        } else {
+            Axis::Child
+        };
+
+        let test = match self.peek().cloned() {
+            Some(Token::Star) => {
+                self.pos += 1;
+                NodeTest::Any
+            }
+            Some(Token::Name(name)) if self.peek_at(1) == Some(&Token::LParen) => {
+                let test = match name.as_str() {
+                    "text" => NodeTest::Text,
+                    "node" => NodeTest::Node,
+                    _ => return Err(self.error("a node test")),
+                };
+                self.pos += 2;
+                self.expect(Token::RParen, "`)`")?;
+                test
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                NodeTest::Name(name)
+            }
+            _ => return Err(self.error("a node test")),
+        };
+
+        let mut predicates = Vec::new();
+        while self.eat(&Token::LBracket) {
+            predicates.push(self.or_expr()?);
+            self.expect(Token::RBracket, "`]`")?;
+        }
+
+        Ok(Step {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.and_expr()?;
+        while self.eat_name("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.compare_expr()?;
+        while self.eat_name("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.compare_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn compare_expr(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.primary()?;
+        while let Some(Token::Op(op)) = self.peek().cloned() {
+            self.pos += 1;
+            expr = Expr::Compare(op, Box::new(expr), Box::new(self.primary()?));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, XPathError> {
+        match self.peek().cloned() {
+            Some(Token::Literal(literal)) => {
+                self.pos += 1;
+                Ok(Expr::Literal(literal))
+            }
+            Some(Token::Number(number)) => {
+                self.pos += 1;
+                Ok(Expr::Number(number))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.or_expr()?;
+                self.expect(Token::RParen, "`)`")?;
+                Ok(expr)
+            }
+            Some(Token::Name(name))
+                if self.peek_at(1) == Some(&Token::LParen) && name != "text" && name != "node" =>
+            {
+                self.call(&name)
+            }
+            Some(Token::Slash | Token::DoubleSlash) => Ok(Expr::Path(self.path()?)),
+            _ if self.starts_step() => Ok(Expr::Path(self.path()?)),
+            _ => Err(self.error("an expression")),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr, XPathError> {
+        let (function, min, max) = match name {
+            "last" => (Function::Last, 0, 0),
+            "position" => (Function::Position, 0, 0),
+            "count" => (Function::Count, 1, 1),
+            "contains" => (Function::Contains, 2, 2),
+            "starts-with" => (Function::StartsWith, 2, 2),
+            "normalize-space" => (Function::NormalizeSpace, 0, 1),
+            "string" => (Function::String, 0, 1),
+            "string-length" => (Function::StringLength, 0, 1),
+            "concat" => (Function::Concat, 2, usize::MAX),
+            "not" => (Function::Not, 1, 1),
+            "true" => (Function::True, 0, 0),
+            "false" => (Function::False, 0, 0),
+            _ => {
+                return Err(XPathError::Syntax(format!(
+                    "unsupported function {}()",
+                    name
+                )));
+            }
+        };
+        self.pos += 2;
+
+        let mut args = Vec::new();
+        if !self.eat(&Token::RParen) {
+            loop {
+                args.push(self.or_expr()?);
+                if self.eat(&Token::RParen) {
+                    break;
+                }
+                self.expect(Token::Comma, "`,` or `)`")?;
+            }
+        }
+
+        if args.len() < min || args.len() > max {
+            return Err(XPathError::Syntax(format!(
+                "wrong number of arguments for {}()",
+                name
+            )));
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+fn node_step(axis: Axis) -> Step {
+    Step {
+        axis,
+        test: NodeTest::Node,
+        predicates: Vec::new(),
+    }
+}
+
+fn descendant_or_self() -> Step {
+    node_step(Axis::DescendantOrSelf)
+}
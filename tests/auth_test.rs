@@ -0,0 +1,91 @@
+use reqwest::header::{AUTHORIZATION, HeaderValue};
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the `Authorization` header `middleware` sends to `url`.
+    async fn authorization(
+        middleware: &mut AuthMiddleware,
+        url: &str,
+        current: Option<&str>,
+    ) -> Option<String> {
+        let mut request = Request::new(Url::parse(url).unwrap());
+        if let Some(current) = current {
+            request
+                .headers
+                .insert(AUTHORIZATION, HeaderValue::from_str(current).unwrap());
+        }
+        let action = Middleware::<()>::process_request(middleware, &(), request)
+            .await
+            .unwrap();
+        let MiddlewareAction::Continue(request) = action else {
+            panic!("the request was dropped");
+        };
+        request
+            .headers
+            .get(AUTHORIZATION)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_credentials_are_sent_to_their_domain_only() {
+        let mut middleware = AuthMiddleware::new()
+            .domain("wiki.corp.example", Credentials::basic("alice", "s3cret"))
+            .unwrap()
+            .domain("*.api.example", Credentials::bearer("tok"))
+            .unwrap();
+        let basic = "Basic YWxpY2U6czNjcmV0";
+        let other = "Bearer other";
+
+        for (url, current, expected) in [
+            ("https://wiki.corp.example/", None, Some(basic)),
+            ("https://docs.wiki.corp.example/", None, Some(basic)),
+            ("https://v1.api.example/items", None, Some("Bearer tok")),
+            ("https://api.example/items", None, None),
+            ("https://example.org/", None, None),
+            // Our credentials are removed from other hosts, others are kept.
+            ("https://example.org/", Some(basic), None),
+            ("https://example.org/", Some(other), Some(other)),
+            ("https://v1.api.example/", Some(other), Some(other)),
+        ] {
+            let sent = authorization(&mut middleware, url, current).await;
+            assert_eq!(sent.as_deref(), expected, "{url}");
+        }
+        let header = middleware.header_for("wiki.corp.example").unwrap();
+        assert!(header.is_sensitive());
+    }
+
+    #[test]
+    fn test_credentials_not_allowed_in_a_header_are_an_error() {
+        let credentials = Credentials::bearer("line\nbreak");
+        let error = credentials.header_value().unwrap_err();
+        assert!(matches!(error, SpiderError::ConfigurationError(_)));
+        assert!(!error.to_string().contains("break"));
+
+        let error = AuthMiddleware::new()
+            .domain("api.example", credentials)
+            .unwrap_err();
+        assert!(matches!(error, SpiderError::ConfigurationError(_)));
+    }
+
+    #[test]
+    fn test_credentials_from_env_are_redacted() {
+        // SAFETY: no other test reads or writes these variables.
+        unsafe {
+            std::env::set_var("SPIDER_AUTH_TEST_USER", "bob");
+            std::env::set_var("SPIDER_AUTH_TEST_PASSWORD", "hunter2");
+        }
+        let credentials =
+            Credentials::basic_from_env("SPIDER_AUTH_TEST_USER", "SPIDER_AUTH_TEST_PASSWORD")
+                .unwrap();
+        assert_eq!(credentials, Credentials::basic("bob", "hunter2"));
+        assert!(!format!("{credentials:?}").contains("hunter2"));
+
+        let missing = Credentials::bearer_from_env("SPIDER_AUTH_TEST_MISSING").unwrap_err();
+        assert!(matches!(missing, SpiderError::ConfigurationError(_)));
+        assert!(!format!("{:?}", Credentials::bearer("tok")).contains("tok"));
+    }
+}
@@ -0,0 +1,138 @@
+use spider_lib::middleware::autothrottle::THROTTLE_SENT_KEY;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str) -> Request {
+        Request::new(Url::parse(&format!("https://example.com{path}")).unwrap())
+    }
+
+    /// A response to `path` whose request was let through `latency` ago.
+    fn response(path: &str, status: u16, latency: Duration) -> Response {
+        let url = Url::parse(&format!("https://example.com{path}")).unwrap();
+        let sent_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .saturating_sub(latency)
+            .as_secs_f64()
+            * 1000.0;
+        let response = MockResponse::new(status).into_response(Request::new(url));
+        response
+            .meta
+            .insert(THROTTLE_SENT_KEY.into(), sent_ms.into());
+        response
+    }
+
+    async fn send(throttle: &mut AutoThrottleMiddleware, request: Request) {
+        let action = Middleware::<()>::process_request(throttle, &(), request)
+            .await
+            .unwrap();
+        assert!(matches!(action, MiddlewareAction::Continue(_)));
+    }
+
+    async fn answer(throttle: &mut AutoThrottleMiddleware, response: Response) {
+        let action = Middleware::<()>::process_response(throttle, response)
+            .await
+            .unwrap();
+        assert!(matches!(action, MiddlewareAction::Continue(_)));
+    }
+
+    #[tokio::test]
+    async fn test_delay_follows_latency_within_the_range() {
+        let mut throttle = AutoThrottleMiddleware::new()
+            .start_delay(Duration::from_millis(400))
+            .delay_range(Duration::from_millis(50), Duration::from_secs(1))
+            .unwrap()
+            .target_concurrency(2.0)
+            .unwrap();
+        let reader = throttle.clone();
+        send(&mut throttle, request("/")).await;
+        assert_eq!(
+            reader.delay("example.com"),
+            Some(Duration::from_millis(400))
+        );
+
+        // Halfway from 400ms towards 200ms / 2.
+        answer(
+            &mut throttle,
+            response("/", 200, Duration::from_millis(200)),
+        )
+        .await;
+        let delay = reader.delay("EXAMPLE.com").unwrap();
+        assert!(
+            delay >= Duration::from_millis(240) && delay < Duration::from_millis(270),
+            "delay {delay:?}"
+        );
+
+        for _ in 0..20 {
+            answer(&mut throttle, response("/", 200, Duration::ZERO)).await;
+        }
+        assert_eq!(reader.delay("example.com"), Some(Duration::from_millis(50)));
+        assert_eq!(
+            reader.delays().into_iter().collect::<Vec<_>>(),
+            [("example.com".to_string(), Duration::from_millis(50))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_overload_backs_off_and_errors_never_speed_up() {
+        let mut throttle = AutoThrottleMiddleware::new()
+            .start_delay(Duration::from_millis(100))
+            .delay_range(Duration::ZERO, Duration::from_millis(300))
+            .unwrap();
+        let reader = throttle.clone();
+        send(&mut throttle, request("/")).await;
+
+        answer(&mut throttle, response("/", 503, Duration::ZERO)).await;
+        assert_eq!(
+            reader.delay("example.com"),
+            Some(Duration::from_millis(200))
+        );
+        answer(&mut throttle, response("/", 404, Duration::ZERO)).await;
+        assert_eq!(
+            reader.delay("example.com"),
+            Some(Duration::from_millis(200))
+        );
+        answer(&mut throttle, response("/", 429, Duration::ZERO)).await;
+        assert_eq!(
+            reader.delay("example.com"),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(reader.delay("other.example"), None);
+    }
+
+    #[tokio::test]
+    async fn test_requests_to_a_host_are_spaced_by_its_delay() {
+        let mut throttle = AutoThrottleMiddleware::new().start_delay(Duration::from_millis(100));
+        let started = Instant::now();
+        send(&mut throttle, request("/a")).await;
+        send(&mut throttle, request("/b")).await;
+        send(&mut throttle, request("/c")).await;
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        let other = Instant::now();
+        send(
+            &mut throttle,
+            Request::new(Url::parse("https://other.example/").unwrap()),
+        )
+        .await;
+        send(&mut throttle, request("/d").impolite()).await;
+        assert!(other.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_invalid_settings_are_configuration_errors() {
+        let inverted = AutoThrottleMiddleware::new()
+            .delay_range(Duration::from_secs(2), Duration::from_secs(1));
+        assert!(matches!(inverted, Err(SpiderError::ConfigurationError(_))));
+        for concurrency in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let throttle = AutoThrottleMiddleware::new().target_concurrency(concurrency);
+            assert!(matches!(throttle, Err(SpiderError::ConfigurationError(_))));
+        }
+    }
+}
@@ -6,7 +6,6 @@ use dashmap::DashMap;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio;
 
     #[scraped_item]
     pub struct TestItem {
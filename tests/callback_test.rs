@@ -0,0 +1,436 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::callback::FAILURE_KEY;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Parsed {
+        pub by: String,
+    }
+
+    pub struct BooksSpider;
+
+    impl BooksSpider {
+        async fn parse_book(
+            &self,
+            _response: Response,
+            _state: &(),
+        ) -> Result<ParseOutput<Parsed>, SpiderError> {
+            Ok(parsed_by("parse_book"))
+        }
+
+        async fn parse_default(
+            &self,
+            _response: Response,
+            _state: &(),
+        ) -> Result<ParseOutput<Parsed>, SpiderError> {
+            Ok(parsed_by("parse_default"))
+        }
+    }
+
+    fn parsed_by(by: &str) -> ParseOutput<Parsed> {
+        let mut output = ParseOutput::new();
+        output.add_item(Parsed { by: by.to_string() });
+        output
+    }
+
+    #[async_trait]
+    impl Spider for BooksSpider {
+        type Item = Parsed;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://books.example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            _response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            Ok(parsed_by("parse"))
+        }
+    }
+
+    impl CallbackSpider for BooksSpider {
+        fn callbacks() -> Callbacks<Self> {
+            Callbacks::<Self>::new()
+                .add("parse_book", |spider, response, state| {
+                    Box::pin(spider.parse_book(response, state))
+                })
+                .add("parse_default", |spider, response, state| {
+                    Box::pin(spider.parse_default(response, state))
+                })
+        }
+    }
+
+    fn response(callback: Option<&str>) -> Response {
+        response_with_body(callback, "<html></html>")
+    }
+
+    fn response_with_body(callback: Option<&str>, body: &'static str) -> Response {
+        let request = Request::new(Url::parse("https://books.example.com/").unwrap());
+        let response = MockResponse::new(200).body(body).into_response(request);
+        if let Some(callback) = callback {
+            response.meta.insert(CALLBACK_KEY.into(), callback.into());
+        }
+        response
+    }
+
+    async fn parsed_with<S: Spider<Item = Parsed, State = ()>>(
+        spider: &S,
+        callback: Option<&str>,
+    ) -> String {
+        let (items, _) = spider
+            .parse(response(callback), &())
+            .await
+            .unwrap()
+            .into_parts();
+        items[0].by.clone()
+    }
+
+    #[tokio::test]
+    async fn test_named_callbacks_are_dispatched() {
+        let spider = BooksSpider.routed();
+        assert_eq!(parsed_with(&spider, None).await, "parse");
+        assert_eq!(parsed_with(&spider, Some("parse_book")).await, "parse_book");
+    }
+
+    #[tokio::test]
+    async fn test_request_callback_reaches_its_response() {
+        let request = Request::new(Url::parse("https://books.example.com/book/1").unwrap())
+            .callback("parse_book");
+        assert_eq!(request.callback_name().as_deref(), Some("parse_book"));
+        assert_eq!(Request::new(request.url.clone()).callback_name(), None);
+
+        let response = response(None);
+        for entry in request.meta.iter() {
+            response
+                .meta
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        let (items, _) = BooksSpider
+            .routed()
+            .parse(response, &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "parse_book");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_callback_falls_back_to_parse() {
+        let spider = BooksSpider.routed();
+        assert_eq!(parsed_with(&spider, Some("parse_renamed")).await, "parse");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_callback_uses_configured_fallback() {
+        let callbacks = BooksSpider::callbacks().fallback("parse_default");
+        assert!(callbacks.contains("parse_book"));
+
+        let spider = BooksSpider;
+        let (items, _) = callbacks
+            .dispatch(&spider, response(Some("parse_renamed")), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "parse_default");
+
+        let (items, _) = callbacks
+            .dispatch(&spider, response(Some("parse_book")), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "parse_book");
+    }
+
+    #[tokio::test]
+    async fn test_routed_spider_builds_a_crawler() {
+        assert!(
+            CrawlerBuilder::new(BooksSpider.routed())
+                .build()
+                .await
+                .is_ok()
+        );
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct ApiPage {
+        pub title: String,
+    }
+
+    pub struct ApiSpider;
+
+    impl ApiSpider {
+        async fn parse_api(
+            &self,
+            page: ApiPage,
+            _response: Response,
+            _state: &(),
+        ) -> Result<ParseOutput<Parsed>, SpiderError> {
+            Ok(parsed_by(&page.title))
+        }
+
+        async fn parse_error(
+            &self,
+            error: SpiderError,
+            _response: Response,
+            _state: &(),
+        ) -> Result<ParseOutput<Parsed>, SpiderError> {
+            assert!(matches!(error, SpiderError::JsonError(_)));
+            Ok(parsed_by("errback"))
+        }
+    }
+
+    #[async_trait]
+    impl Spider for ApiSpider {
+        type Item = Parsed;
+        type State = ();
+
+        async fn parse(
+            &self,
+            _response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            Ok(parsed_by("parse"))
+        }
+    }
+
+    fn api_callbacks() -> Callbacks<ApiSpider> {
+        Callbacks::<ApiSpider>::new().add_json(
+            "parse_api",
+            |spider, page: ApiPage, response, state| {
+                Box::pin(spider.parse_api(page, response, state))
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_json_callback_receives_deserialized_body() {
+        let (items, _) = api_callbacks()
+            .dispatch(
+                &ApiSpider,
+                response_with_body(Some("parse_api"), r#"{"title": "Dune"}"#),
+                &(),
+            )
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "Dune");
+    }
+
+    #[tokio::test]
+    async fn test_json_callback_decodes_the_declared_charset() {
+        let request = Request::new(Url::parse("https://books.example.com/").unwrap());
+        // "Les Misérables" in latin-1, where the é is the single byte 0xE9.
+        let response = MockResponse::new(200)
+            .header("content-type", "application/json; charset=iso-8859-1")
+            .body(b"{\"title\": \"Les Mis\xe9rables\"}".to_vec())
+            .into_response(request);
+        response
+            .meta
+            .insert(CALLBACK_KEY.into(), "parse_api".into());
+
+        let (items, _) = api_callbacks()
+            .dispatch(&ApiSpider, response, &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "Les Misérables");
+    }
+
+    #[tokio::test]
+    async fn test_adapter_failure_skips_the_callback() {
+        let result = api_callbacks()
+            .dispatch(
+                &ApiSpider,
+                response_with_body(Some("parse_api"), "<html>"),
+                &(),
+            )
+            .await;
+        assert!(matches!(result, Err(SpiderError::JsonError(_))));
+
+        let (items, _) = api_callbacks()
+            .errback(|spider, error, response, state| {
+                Box::pin(spider.parse_error(error, response, state))
+            })
+            .dispatch(
+                &ApiSpider,
+                response_with_body(Some("parse_api"), "<html>"),
+                &(),
+            )
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "errback");
+    }
+
+    #[tokio::test]
+    async fn test_typed_callback_with_custom_adapter() {
+        let callbacks = Callbacks::<ApiSpider>::new().add_typed(
+            "parse_length",
+            |response| Ok(response.body.len()),
+            |_spider, length: usize, _response, _state| {
+                Box::pin(async move { Ok(parsed_by(&length.to_string())) })
+            },
+        );
+        let (items, _) = callbacks
+            .dispatch(
+                &ApiSpider,
+                response_with_body(Some("parse_length"), "12345"),
+                &(),
+            )
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "5");
+    }
+
+    /// Requests `/broken`, falling back to `/mirror` through its errback.
+    pub struct MirrorSpider {
+        start: Url,
+        errors: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MirrorSpider {
+        async fn try_mirror(
+            &self,
+            error: SpiderError,
+            request: Request,
+            _state: &(),
+        ) -> Result<ParseOutput<Parsed>, SpiderError> {
+            self.errors.lock().unwrap().push(error.to_string());
+            assert!(!request.meta.contains_key(FAILURE_KEY));
+            let mut output = ParseOutput::new();
+            output.add_request(Request::new(request.url.join("/mirror")?));
+            Ok(output)
+        }
+    }
+
+    #[async_trait]
+    impl Spider for MirrorSpider {
+        type Item = Parsed;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![
+                Request::new(self.start.clone())
+                    .errback("try_mirror")
+                    .max_retries(1),
+            ])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            Ok(parsed_by(response.url.path()))
+        }
+    }
+
+    impl CallbackSpider for MirrorSpider {
+        fn callbacks() -> Callbacks<Self> {
+            Callbacks::<Self>::new().add_errback("try_mirror", |spider, error, request, state| {
+                Box::pin(spider.try_mirror(error, request, state))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_errback_recovers_a_request_with_exhausted_retries() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/broken" => TestResponse::status(503),
+            _ => TestResponse::html("<p>mirror</p>"),
+        })
+        .await;
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let collector = spider_lib::testing::CollectorPipeline::new();
+        let spider = MirrorSpider {
+            start: server.url("/broken"),
+            errors: errors.clone(),
+        };
+        let crawler = CrawlerBuilder::new(spider.routed())
+            .add_middleware(RetryPolicyMiddleware::from(
+                RetryMiddleware::new().backoff_factor(0.0),
+            ))
+            .add_pipeline(collector.clone())
+            .build()
+            .await
+            .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let errors = errors.lock().unwrap().clone();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("503"), "{}", errors[0]);
+        let items = collector.items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].by, "/mirror");
+    }
+
+    #[tokio::test]
+    async fn test_failed_download_reaches_its_errback_and_counts_as_failed() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let spider = MirrorSpider {
+            start: Url::parse(&format!("http://{addr}/gone")).unwrap(),
+            errors: errors.clone(),
+        };
+        let downloader = HttpDownloader::new().unwrap();
+        let failed_downloads = downloader.traffic().clone();
+        let content_filter = ContentFilterMiddleware::new().allow_content_types(&["text/html"]);
+        let crawler = CrawlerBuilder::new(spider.routed())
+            .downloader(downloader)
+            .add_middleware(content_filter.clone())
+            .build()
+            .await
+            .unwrap();
+        let stats = crawler.get_stats();
+        crawler.start_crawl().await.unwrap();
+
+        // The placeholder got past the content filter to the errback, whose mirror is
+        // down too.
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert_eq!(content_filter.skipped_by_content_type(), 0);
+        assert_eq!(failed_downloads.failed(), 1);
+        let snapshot = stats.snapshot().excluding(&failed_downloads);
+        assert_eq!(snapshot.requests.succeeded, 0);
+        assert_eq!(snapshot.requests.failed, 2);
+        assert_eq!(snapshot.responses.received, 0);
+        assert!(snapshot.responses.by_status.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failure_without_a_registered_errback() {
+        let failed = response(None);
+        failed
+            .meta
+            .insert(FAILURE_KEY.into(), "error: connection refused".into());
+        failed.meta.insert("errback".into(), "renamed".into());
+        let error = BooksSpider.routed().parse(failed, &()).await.unwrap_err();
+        assert!(error.to_string().contains("connection refused"));
+
+        let failed = response(None);
+        failed.meta.insert(FAILURE_KEY.into(), "status: 503".into());
+        let (items, _) = Callbacks::<BooksSpider>::new()
+            .errback(|_spider, _error, _response, _state| {
+                Box::pin(async { Ok(parsed_by("errback")) })
+            })
+            .dispatch(&BooksSpider, failed, &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].by, "errback");
+    }
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    fn item(path: &str) -> PageItem {
+        PageItem {
+            url: format!("https://example.com{path}"),
+        }
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                for page in 1..=3 {
+                    let url = response.url.join(&format!("/page/{page}"))?;
+                    output.add_request(Request::new(url));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crawl_items_reach_a_slow_consumer() {
+        let server =
+            TestServer::start(|_| TestResponse::html("<html><body>page</body></html>")).await;
+        let (pipeline, mut items) = ChannelPipeline::new(1);
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_pipeline(pipeline)
+        .build()
+        .await
+        .unwrap();
+
+        let consumer = tokio::spawn(async move {
+            let mut urls = Vec::new();
+            while let Some(item) = items.recv().await {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                urls.push(item.url);
+            }
+            urls
+        });
+        crawler.start_crawl().await.unwrap();
+
+        let mut urls = consumer.await.unwrap();
+        urls.sort();
+        assert_eq!(
+            urls,
+            ["/", "/page/1", "/page/2", "/page/3"].map(|path| server.url(path).to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_holds_items_back() {
+        let (pipeline, mut items) = ChannelPipeline::new(1);
+        assert!(pipeline.process_item(item("/a")).await.unwrap().is_some());
+
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(50), pipeline.process_item(item("/b")))
+                .await;
+        assert!(blocked.is_err());
+
+        assert_eq!(items.recv().await.unwrap().url, "https://example.com/a");
+        assert!(pipeline.process_item(item("/c")).await.unwrap().is_some());
+        pipeline.close().await.unwrap();
+        assert_eq!(items.recv().await.unwrap().url, "https://example.com/c");
+        assert!(items.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_lines() {
+        let (pipeline, mut lines) = ChannelPipeline::ndjson(4);
+        pipeline.process_item(item("/a")).await.unwrap();
+        pipeline.close().await.unwrap();
+
+        assert_eq!(
+            lines.recv().await.as_deref(),
+            Some(r#"{"url":"https://example.com/a"}"#)
+        );
+        assert!(lines.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_items_pass_on_without_a_receiver() {
+        let (pipeline, items) = ChannelPipeline::new(1);
+        drop(items);
+        for path in ["/a", "/b"] {
+            let passed = pipeline.process_item(item(path)).await.unwrap();
+            assert_eq!(passed.unwrap().url, format!("https://example.com{path}"));
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponses;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub page: usize,
+    }
+
+    /// Buffers items in memory and writes them to `output` only when closed.
+    pub struct BufferedPipeline {
+        buffer: Mutex<Vec<usize>>,
+        output: Arc<Mutex<Vec<usize>>>,
+        closes: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Pipeline<PageItem> for BufferedPipeline {
+        fn name(&self) -> &str {
+            "BufferedPipeline"
+        }
+
+        async fn process_item(&self, item: PageItem) -> Result<Option<PageItem>, PipelineError> {
+            self.buffer.lock().unwrap().push(item.page);
+            Ok(Some(item))
+        }
+
+        async fn close(&self) -> Result<(), PipelineError> {
+            self.closes.fetch_add(1, Ordering::SeqCst);
+            let buffered = std::mem::take(&mut *self.buffer.lock().unwrap());
+            self.output.lock().unwrap().extend(buffered);
+            Ok(())
+        }
+    }
+
+    /// Follows pages forever, slowly.
+    pub struct EndlessSpider {
+        pages: usize,
+    }
+
+    #[async_trait]
+    impl Spider for EndlessSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://example.com/0"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let page: usize = response.url.path()[1..].parse().unwrap();
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem { page });
+            if page + 1 < self.pages {
+                let next = Url::parse(&format!("https://example.com/{}", page + 1)).unwrap();
+                output.add_request(Request::new(next));
+            }
+            Ok(output)
+        }
+    }
+
+    fn site(pages: usize) -> MockResponses {
+        (0..pages).fold(MockResponses::new(), |mock, page| {
+            mock.html(&format!("https://example.com/{page}"), "<p>page</p>")
+        })
+    }
+
+    fn buffered() -> (BufferedPipeline, Arc<Mutex<Vec<usize>>>, Arc<AtomicUsize>) {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let closes = Arc::new(AtomicUsize::new(0));
+        let pipeline = BufferedPipeline {
+            buffer: Mutex::new(Vec::new()),
+            output: output.clone(),
+            closes: closes.clone(),
+        };
+        (pipeline, output, closes)
+    }
+
+    #[test]
+    fn test_killed_crawl_flushes_buffered_items() {
+        let (pipeline, output, closes) = buffered();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let crawler = CrawlerBuilder::new(EndlessSpider { pages: 10_000 })
+                .add_middleware(site(100))
+                .add_pipeline(pipeline.close_on_drop())
+                .build()
+                .await
+                .unwrap();
+            let killed =
+                tokio::time::timeout(Duration::from_millis(500), crawler.start_crawl()).await;
+            assert!(killed.is_err());
+        });
+        assert!(output.lock().unwrap().is_empty());
+
+        // Shutting the runtime down drops the crawl's tasks and with them the pipeline.
+        drop(runtime);
+
+        let mut pages = output.lock().unwrap().clone();
+        assert!(!pages.is_empty());
+        pages.sort();
+        assert_eq!(pages, (0..pages.len()).collect::<Vec<_>>());
+        assert_eq!(closes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_finished_crawl_is_closed_once() {
+        let (pipeline, output, closes) = buffered();
+        let crawler = CrawlerBuilder::new(EndlessSpider { pages: 3 })
+            .add_middleware(site(3))
+            .add_pipeline(pipeline.close_on_drop())
+            .build()
+            .await
+            .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(output.lock().unwrap().len(), 3);
+        assert_eq!(closes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_drop_on_a_runtime_worker_closes_the_pipeline() {
+        let (pipeline, output, closes) = buffered();
+        let guard = pipeline.close_on_drop();
+        guard.process_item(PageItem { page: 7 }).await.unwrap();
+
+        // Dropped on a worker, so the close goes through `block_in_place`.
+        tokio::spawn(async move { drop(guard) }).await.unwrap();
+
+        assert_eq!(*output.lock().unwrap(), vec![7]);
+        assert_eq!(closes.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use spider_lib::stream::BODY_SKIPPED_KEY;
+use std::sync::atomic::Ordering;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    pub struct AssetsSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for AssetsSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                for path in ["/report.PDF", "/logo", "/about"] {
+                    output.add_request(Request::new(response.url.join(path)?));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    async fn server() -> TestServer {
+        TestServer::start(|request| match request.path.as_str() {
+            "/logo" => TestResponse::new(200, "image/png", vec![0x89, b'P', b'N', b'G']),
+            "/report.PDF" => TestResponse::new(200, "application/pdf", b"%PDF-1.4".to_vec()),
+            _ => TestResponse::html("<html><body>page</body></html>"),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_binary_assets_are_skipped() {
+        let server = server().await;
+        let filter = ContentFilterMiddleware::new().allow_content_types(&["text/html"]);
+        let crawler = CrawlerBuilder::new(AssetsSpider {
+            start: server.url("/"),
+        })
+        .content_filter(&filter)
+        .build()
+        .await
+        .unwrap();
+        let stats = crawler.get_stats();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(filter.skipped_by_extension(), 1);
+        assert_eq!(filter.skipped_by_content_type(), 1);
+        assert_eq!(stats.requests_dropped.load(Ordering::SeqCst), 2);
+        assert_eq!(stats.items_scraped.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_filters_are_configurable() {
+        let mut filter = ContentFilterMiddleware::new()
+            .deny_extensions(&[".JSON"])
+            .allow_content_types(&["text/*"]);
+        let request = |path: &str| {
+            Request::new(
+                Url::parse("https://example.com")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            )
+        };
+
+        let pdf = Middleware::<()>::process_request(&mut filter, &(), request("/a.pdf"))
+            .await
+            .unwrap();
+        assert!(matches!(pdf, MiddlewareAction::Continue(_)));
+        let json =
+            Middleware::<()>::process_request(&mut filter, &(), request("/data.json?page=2"))
+                .await
+                .unwrap();
+        assert!(matches!(json, MiddlewareAction::Drop));
+
+        let server = server().await;
+        let downloader = HttpDownloader::new().unwrap();
+        for (path, kept) in [("/about", true), ("/logo", false)] {
+            let response = downloader
+                .download(Request::new(server.url(path)))
+                .await
+                .unwrap();
+            let action = Middleware::<()>::process_response(&mut filter, response)
+                .await
+                .unwrap();
+            assert_eq!(
+                matches!(action, MiddlewareAction::Continue(_)),
+                kept,
+                "{path}"
+            );
+        }
+        assert_eq!(filter.skipped_by_extension(), 1);
+        assert_eq!(filter.skipped_by_content_type(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_downloader_skips_bodies_after_headers() {
+        let server = server().await;
+        let downloader = HttpDownloader::builder()
+            .allowed_content_types(&["text/html"])
+            .build()
+            .unwrap();
+
+        let image = downloader
+            .download(Request::new(server.url("/logo")))
+            .await
+            .unwrap();
+        assert_eq!(image.status(), 200);
+        assert!(image.body.is_empty());
+        assert_eq!(
+            image.meta.get(BODY_SKIPPED_KEY).map(|value| value.clone()),
+            Some(true.into())
+        );
+
+        let page = downloader
+            .download(Request::new(server.url("/about")))
+            .await
+            .unwrap();
+        assert!(!page.body.is_empty());
+        assert!(page.meta.get(BODY_SKIPPED_KEY).is_none());
+    }
+}
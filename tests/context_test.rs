@@ -0,0 +1,77 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Product {
+        pub category: String,
+        pub url: String,
+    }
+
+    pub struct Config {
+        category: String,
+    }
+
+    pub struct ProductsSpider;
+
+    #[async_trait]
+    impl ContextSpider for ProductsSpider {
+        type Item = Product;
+        type State = ();
+        type Context = Config;
+
+        fn start_requests(&self, ctx: &Config) -> Result<Vec<Request>, SpiderError> {
+            let url = format!("https://shop.example.com/{}", ctx.category);
+            Ok(vec![Request::new(Url::parse(&url)?)])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &(),
+            ctx: &Config,
+        ) -> Result<ParseOutput<Product>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(Product {
+                category: ctx.category.clone(),
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    fn spider() -> WithContext<ProductsSpider> {
+        ProductsSpider.with_context(Config {
+            category: "books".into(),
+        })
+    }
+
+    #[test]
+    fn test_context_reaches_start_requests() {
+        let spider = spider();
+        let requests = Spider::start_requests(&spider).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url.as_str(), "https://shop.example.com/books");
+        assert_eq!(spider.context().category, "books");
+    }
+
+    #[tokio::test]
+    async fn test_context_reaches_parse() {
+        let url = Url::parse("https://shop.example.com/books").unwrap();
+        let response = MockResponse::html("<html></html>").into_response(Request::new(url));
+
+        let output = Spider::parse(&spider(), response, &()).await.unwrap();
+        let (items, _) = output.into_parts();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].category, "books");
+    }
+
+    #[tokio::test]
+    async fn test_context_spider_builds_a_crawler() {
+        assert!(CrawlerBuilder::new(spider()).build().await.is_ok());
+    }
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "middleware-cookies")]
+
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::path::PathBuf;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jar_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spider-cookie-jar-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn login_response(set_cookies: &[&'static str]) -> Response {
+        let url = Url::parse("https://example.com/login").unwrap();
+        set_cookies
+            .iter()
+            .fold(MockResponse::new(200), |response, cookie| {
+                response.header("set-cookie", cookie)
+            })
+            .into_response(Request::new(url))
+    }
+
+    async fn cookie_header(middleware: &mut PersistentCookieMiddleware) -> Option<String> {
+        let request = Request::new(Url::parse("https://example.com/account").unwrap());
+        match Middleware::<()>::process_request(middleware, &(), request)
+            .await
+            .unwrap()
+        {
+            MiddlewareAction::Continue(request) => request
+                .headers
+                .get("cookie")
+                .map(|value| value.to_str().unwrap().to_string()),
+            _ => panic!("the request should continue"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_survives_restart() {
+        let path = jar_path("session.jsonl");
+        let mut cookies = CookieMiddleware::new().with_persistence(&path).unwrap();
+        assert_eq!(cookies.path(), path.as_path());
+        assert_eq!(cookie_header(&mut cookies).await, None);
+
+        Middleware::<()>::process_response(
+            &mut cookies,
+            login_response(&["session=abc; Path=/", "theme=dark; Max-Age=3600"]),
+        )
+        .await
+        .unwrap();
+        assert!(!path.exists());
+        drop(cookies);
+        assert!(path.exists());
+
+        let mut restarted = CookieMiddleware::new().with_persistence(&path).unwrap();
+        let header = cookie_header(&mut restarted).await.unwrap();
+        assert!(header.contains("session=abc"), "{header}");
+        assert!(header.contains("theme=dark"), "{header}");
+    }
+
+    #[tokio::test]
+    async fn test_expired_cookies_are_pruned_on_load() {
+        let path = jar_path("expiring.jsonl");
+        let mut cookies = CookieMiddleware::new().with_persistence(&path).unwrap();
+        Middleware::<()>::process_response(
+            &mut cookies,
+            login_response(&["short=1; Max-Age=1", "long=2; Max-Age=3600"]),
+        )
+        .await
+        .unwrap();
+        cookies.save().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let mut restarted = CookieMiddleware::new().with_persistence(&path).unwrap();
+        assert_eq!(
+            cookie_header(&mut restarted).await.as_deref(),
+            Some("long=2")
+        );
+        drop(cookies);
+    }
+
+    #[test]
+    fn test_unreadable_jar_is_an_error() {
+        let path = jar_path("broken.jsonl");
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(CookieMiddleware::new().with_persistence(&path).is_err());
+    }
+}
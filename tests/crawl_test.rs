@@ -0,0 +1,275 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use spider_lib::testing::CollectorPipeline;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                for page in 1..=3 {
+                    let url = response.url.join(&format!("/page/{page}"))?;
+                    output.add_request(Request::new(url));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    async fn server() -> TestServer {
+        TestServer::start(|_| TestResponse::html("<html><body>page</body></html>")).await
+    }
+
+    #[tokio::test]
+    async fn test_finished_crawl_reports_stats() {
+        let server = server().await;
+        let control = CrawlControl::new();
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::Finished));
+        assert_eq!(summary.failed_urls_count, 0);
+        assert_eq!(summary.stats.responses_received.load(Ordering::SeqCst), 4);
+        assert_eq!(summary.stats.items_scraped.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_stopped_crawl_reports_stopped() {
+        let server = server().await;
+        let control = CrawlControl::new();
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+
+        control.stop();
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::Stopped));
+        assert_eq!(summary.stats.responses_received.load(Ordering::SeqCst), 0);
+        assert_eq!(summary.stats.requests_dropped.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_refuses_a_crawler_without_the_middleware() {
+        let server = server().await;
+        let control = CrawlControl::new();
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(
+            summary.reason,
+            CloseReason::Error(SpiderError::ConfigurationError(_))
+        ));
+        assert_eq!(summary.stats.requests_sent.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_byte_budget_closes_crawl() {
+        let server = server().await;
+        let control = CrawlControl::new().max_bytes(40);
+        assert_eq!(control.remaining_bytes(), Some(40));
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::MaxBytes));
+        assert_eq!(summary.bytes_remaining, Some(0));
+        assert!(summary.stats.total_bytes_downloaded.load(Ordering::SeqCst) > 40);
+    }
+
+    #[tokio::test]
+    async fn test_no_byte_budget_by_default() {
+        let server = server().await;
+        let control = CrawlControl::new();
+        assert_eq!(control.remaining_bytes(), None);
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::Finished));
+        assert_eq!(summary.bytes_remaining, None);
+    }
+
+    #[tokio::test]
+    async fn test_paused_crawl_waits_for_resume() {
+        let server = server().await;
+        let control = CrawlControl::new();
+        control.pause();
+        assert!(control.is_paused());
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+        let stats = crawler.get_stats();
+
+        let handle = control.clone();
+        let resumer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            let sent_while_paused = stats.responses_received.load(Ordering::SeqCst);
+            handle.resume();
+            sent_while_paused
+        });
+
+        let summary = control.run(crawler).await;
+        assert_eq!(resumer.await.unwrap(), 0);
+        assert!(!control.is_paused());
+        assert!(matches!(summary.reason, CloseReason::Finished));
+        assert_eq!(summary.stats.items_scraped.load(Ordering::SeqCst), 4);
+        assert_eq!(summary.stats.requests_retried.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_item_limit_closes_crawl() {
+        let server = server().await;
+        let control = CrawlControl::new().max_items(2);
+        let collector = CollectorPipeline::new();
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .crawl_control(&control)
+        .add_pipeline(collector.clone())
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::ItemLimitReached));
+        assert_eq!(collector.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_item_limit_closes_crawl_without_the_pipeline() {
+        let server = server().await;
+        let control = CrawlControl::new().max_items(1);
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::ItemLimitReached));
+    }
+
+    #[tokio::test]
+    async fn test_item_limit_holds_under_concurrent_items() {
+        let control = CrawlControl::new().max_items(10);
+        let pipeline = std::sync::Arc::new(control.pipeline::<PageItem>());
+        let tasks: Vec<_> = (0..50)
+            .map(|n| {
+                let pipeline = pipeline.clone();
+                tokio::spawn(async move {
+                    let item = PageItem {
+                        url: format!("https://example.com/{n}"),
+                    };
+                    pipeline.process_item(item).await.unwrap().is_some()
+                })
+            })
+            .collect();
+        let mut passed = 0;
+        for task in tasks {
+            passed += usize::from(task.await.unwrap());
+        }
+        assert_eq!(passed, 10);
+        assert!(matches!(
+            control.close_reason(),
+            Some(CloseReason::ItemLimitReached)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_time_limit_closes_crawl() {
+        let server = server().await;
+        let control = CrawlControl::new().max_duration(Duration::from_millis(200));
+        control.pause();
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .crawl_control(&control)
+        .add_pipeline(CollectorPipeline::new())
+        .build()
+        .await
+        .unwrap();
+
+        let summary = control.run(crawler).await;
+        assert!(matches!(summary.reason, CloseReason::TimeLimitReached));
+        assert!(summary.duration >= Duration::from_millis(200));
+        assert_eq!(summary.stats.responses_received.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_first_close_reason_wins() {
+        let control = CrawlControl::new();
+        assert!(!control.is_stopping());
+
+        control.close(CloseReason::Shutdown);
+        control.stop();
+        assert!(control.is_stopping());
+        assert!(matches!(
+            control.close_reason(),
+            Some(CloseReason::Shutdown)
+        ));
+        assert_eq!(CloseReason::Shutdown.to_string(), "shutdown");
+    }
+}
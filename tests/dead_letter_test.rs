@@ -0,0 +1,175 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use reqwest::Method;
+use spider_lib::prelude::*;
+use spider_util::request::Body;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dead_letter_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "spider-dead-letter-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_dead_letters_round_trip() {
+        let path = dead_letter_path("round-trip");
+        let sink = DeadLetterSink::new(&path).unwrap();
+
+        let mut request = Request::new(Url::parse("https://api.example.com/items").unwrap())
+            .with_json(serde_json::json!({"page": 2}))
+            .with_header("x-token", "abc")
+            .unwrap()
+            .with_meta("category", "books".into());
+        request.increment_retry_attempts();
+        sink.record(&request, "status: 503");
+        sink.record(
+            &Request::new(Url::parse("https://example.com/gone").unwrap()),
+            "status: 404",
+        );
+
+        let requests = read_dead_letters(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(requests.len(), 2);
+
+        let restored = &requests[0];
+        assert_eq!(restored.url, request.url);
+        assert_eq!(restored.method, Method::POST);
+        assert_eq!(restored.headers.get("x-token").unwrap(), "abc");
+        assert!(matches!(&restored.body, Some(Body::Json(body)) if body["page"] == 2));
+        assert_eq!(
+            restored.meta.get("category").map(|value| value.clone()),
+            Some("books".into())
+        );
+        assert_eq!(restored.get_retry_attempts(), 0);
+        assert_eq!(requests[1].url.path(), "/gone");
+    }
+
+    #[test]
+    fn test_invalid_line_is_an_error() {
+        let path = dead_letter_path("invalid");
+        std::fs::write(&path, "\n{\"not\": \"a dead letter\"}\n").unwrap();
+        let result = read_dead_letters(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            matches!(result, Err(SpiderError::JsonError(message)) if message.contains("line 2"))
+        );
+    }
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub path: String,
+    }
+
+    pub struct PathsSpider {
+        start: Url,
+        paths: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Spider for PathsSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            ["/ok", "/gone", "/flaky"]
+                .into_iter()
+                .map(|path| Ok(Request::new(self.start.join(path)?)))
+                .collect()
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let path = response.url.path().to_string();
+            self.paths.lock().unwrap().push(path.clone());
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem { path });
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_recorded_and_recrawled() {
+        let recovered = Arc::new(AtomicBool::new(false));
+        let server_recovered = recovered.clone();
+        let server = TestServer::start(move |request| {
+            if server_recovered.load(Ordering::SeqCst) {
+                return TestResponse::html("<p>ok</p>");
+            }
+            match request.path.as_str() {
+                "/gone" => TestResponse::status(404),
+                "/flaky" => TestResponse::status(503),
+                _ => TestResponse::html("<p>ok</p>"),
+            }
+        })
+        .await;
+        let path = dead_letter_path("crawl");
+
+        let sink = DeadLetterSink::new(&path).unwrap();
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let crawler = CrawlerBuilder::new(PathsSpider {
+            start: server.url("/"),
+            paths: paths.clone(),
+        })
+        .add_middleware(DeadLetterMiddleware::new(sink.clone()))
+        .add_middleware(
+            RetryPolicyMiddleware::new(RetryMiddleware::new().max_retries(1).backoff_factor(0.01))
+                .dead_letter(sink),
+        )
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let mut failed: Vec<String> = read_dead_letters(&path)
+            .unwrap()
+            .into_iter()
+            .map(|request| request.url.path().to_string())
+            .collect();
+        failed.sort();
+        assert_eq!(failed, ["/flaky", "/gone"]);
+
+        recovered.store(true, Ordering::SeqCst);
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let spider = PathsSpider {
+            start: server.url("/"),
+            paths: paths.clone(),
+        }
+        .retry_failed_from(&path)
+        .unwrap()
+        .max_retries(5);
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            spider
+                .requests()
+                .iter()
+                .all(|request| request.max_retries_override() == Some(5))
+        );
+
+        let crawler = CrawlerBuilder::new(spider).build().await.unwrap();
+        tokio::time::timeout(Duration::from_secs(30), crawler.start_crawl())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut recrawled = paths.lock().unwrap().clone();
+        recrawled.sort();
+        assert_eq!(recrawled, ["/flaky", "/gone"]);
+    }
+}
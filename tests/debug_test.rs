@@ -0,0 +1,160 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::debug::{Decision, Stage};
+use spider_lib::prelude::*;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct TitleItem {
+        pub title: String,
+    }
+
+    pub struct TitleSpider;
+
+    #[async_trait]
+    impl Spider for TitleSpider {
+        type Item = TitleItem;
+        type State = ();
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let html = response.to_html()?;
+            let mut output = ParseOutput::new();
+            let title = html
+                .select(&"title".to_selector()?)
+                .next()
+                .map(|title| title.text().collect::<String>())
+                .unwrap_or_default();
+            output.add_item(TitleItem { title });
+            for link in html.select(&"a".to_selector()?) {
+                if let Some(href) = link.value().attr("href") {
+                    output.add_request(Request::new(response.url.join(href)?));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    /// Retries every response once.
+    struct RetryOnce;
+
+    #[async_trait]
+    impl<C: Send + Sync> Middleware<C> for RetryOnce {
+        fn name(&self) -> &str {
+            "RetryOnce"
+        }
+
+        async fn process_response(
+            &mut self,
+            response: Response,
+        ) -> Result<MiddlewareAction<Response>, SpiderError> {
+            let mut request = response.request_from_response();
+            if request.get_retry_attempts() > 0 {
+                return Ok(MiddlewareAction::Continue(response));
+            }
+            request.increment_retry_attempts();
+            Ok(MiddlewareAction::Retry(
+                Box::new(request),
+                Duration::from_secs(60),
+            ))
+        }
+    }
+
+    async fn server() -> TestServer {
+        TestServer::start(|_| TestResponse::html(r#"<title>Hello</title><a href="/next">next</a>"#))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_debug_fetch_traces_middlewares_and_parses() {
+        let server = server().await;
+        let mut debug = DebugFetch::new(TitleSpider, HttpDownloader::new().unwrap())
+            .add_middleware(RetryOnce)
+            .add_middleware(UrlLengthMiddleware::with_max_length(200));
+
+        let report = debug.fetch(server.url("/page")).await;
+        assert!(report.error.is_none(), "{:?}", report.error);
+        assert_eq!(report.retries, 1);
+        assert!(report.download_time.is_some());
+        assert!(report.elapsed < Duration::from_secs(60));
+
+        let trace: Vec<_> = report
+            .trace
+            .iter()
+            .map(|step| {
+                (
+                    step.attempt,
+                    step.stage,
+                    step.middleware.as_str(),
+                    step.decision.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            trace,
+            vec![
+                (0, Stage::Request, "RetryOnce", Decision::Continue),
+                (0, Stage::Request, "UrlLengthMiddleware", Decision::Continue),
+                (
+                    0,
+                    Stage::Response,
+                    "UrlLengthMiddleware",
+                    Decision::Continue
+                ),
+                (
+                    0,
+                    Stage::Response,
+                    "RetryOnce",
+                    Decision::Retry(Duration::from_secs(60))
+                ),
+                (1, Stage::Request, "RetryOnce", Decision::Continue),
+                (1, Stage::Request, "UrlLengthMiddleware", Decision::Continue),
+                (
+                    1,
+                    Stage::Response,
+                    "UrlLengthMiddleware",
+                    Decision::Continue
+                ),
+                (1, Stage::Response, "RetryOnce", Decision::Continue),
+            ]
+        );
+
+        assert_eq!(report.response.as_ref().unwrap().status.as_u16(), 200);
+        let (items, requests) = report.output.unwrap().into_parts();
+        assert_eq!(items[0].title, "Hello");
+        assert_eq!(requests[0].url, server.url("/next"));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_not_downloaded() {
+        let server = server().await;
+        let mut debug = DebugFetch::new(TitleSpider, HttpDownloader::new().unwrap())
+            .add_middleware(UrlLengthMiddleware::with_max_length(10));
+
+        let report = debug.fetch(server.url("/page")).await;
+        assert!(report.dropped());
+        assert!(report.download_time.is_none());
+        assert!(report.response.is_none());
+        assert!(report.output.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_the_limit() {
+        let server = server().await;
+        let mut debug = DebugFetch::new(TitleSpider, HttpDownloader::new().unwrap())
+            .add_middleware(RetryOnce)
+            .max_retries(0);
+
+        let report = debug.fetch(server.url("/page")).await;
+        assert_eq!(report.retries, 0);
+        assert!(report.error.unwrap().to_string().contains("gave up"));
+    }
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use common::{TestRequest, TestResponse, TestServer};
+use spider_lib::middleware::depth::DEPTH_KEY;
+use spider_lib::prelude::*;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    /// Follows every link and records the paths it parsed.
+    pub struct ChainSpider {
+        start: Url,
+        parsed: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Spider for ChainSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            self.parsed
+                .lock()
+                .unwrap()
+                .push(response.url.path().to_string());
+            let mut output = ParseOutput::new();
+            let html = response.to_html()?;
+            for link in html.select(&"a".to_selector()?) {
+                if let Some(href) = link.value().attr("href") {
+                    output.add_request(Request::new(response.url.join(href)?));
+                }
+            }
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    /// Every page links to two pages one level below it.
+    fn tree(request: &TestRequest) -> TestResponse {
+        TestResponse::html(&format!(
+            r#"<a href="{path}/a">a</a><a href="{path}/b">b</a>"#,
+            path = request.path
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_requests_past_the_max_depth_are_dropped() {
+        let server = TestServer::start(tree).await;
+        let parsed = Arc::new(Mutex::new(Vec::new()));
+        let depth = DepthMiddleware::new(2);
+        let crawler = CrawlerBuilder::new(depth.wrap(ChainSpider {
+            start: server.url("/r"),
+            parsed: parsed.clone(),
+        }))
+        .max_depth(&depth)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let mut parsed = parsed.lock().unwrap().clone();
+        parsed.sort();
+        assert_eq!(
+            parsed,
+            ["/r", "/r/a", "/r/a/a", "/r/a/b", "/r/b", "/r/b/a", "/r/b/b"]
+        );
+        assert_eq!(
+            depth.depth_counts().into_iter().collect::<Vec<_>>(),
+            [(0, 1), (1, 2), (2, 4)]
+        );
+        assert_eq!(depth.dropped(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_unmarked_requests_are_at_depth_zero() {
+        let mut depth = DepthMiddleware::new(0);
+        let url = Url::parse("https://example.com/").unwrap();
+
+        let action = Middleware::<()>::process_request(&mut depth, &(), Request::new(url.clone()))
+            .await
+            .unwrap();
+        assert!(matches!(action, MiddlewareAction::Continue(_)));
+
+        let marked = Request::new(url).with_meta(DEPTH_KEY, 1.into());
+        let action = Middleware::<()>::process_request(&mut depth, &(), marked)
+            .await
+            .unwrap();
+        assert!(matches!(action, MiddlewareAction::Drop));
+        assert_eq!(depth.dropped(), 1);
+    }
+}
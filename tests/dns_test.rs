@@ -0,0 +1,133 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A resolver answering every name with `addr` after a short delay, recording the
+    /// names it was asked for and the most lookups it saw running at once.
+    struct MockResolver {
+        looked_up: Arc<Mutex<Vec<String>>>,
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl MockResolver {
+        fn new() -> Self {
+            Self {
+                looked_up: Arc::new(Mutex::new(Vec::new())),
+                running: Arc::new(AtomicUsize::new(0)),
+                peak: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn resolver(&self, addr: SocketAddr) -> DnsResolver {
+            let looked_up = self.looked_up.clone();
+            let running = self.running.clone();
+            let peak = self.peak.clone();
+            DnsResolver::with_lookup(move |host| {
+                let looked_up = looked_up.clone();
+                let running = running.clone();
+                let peak = peak.clone();
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    looked_up.lock().unwrap().push(host);
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(vec![addr])
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookups_are_limited_and_cached() {
+        let mock = MockResolver::new();
+        let dns = mock
+            .resolver("127.0.0.1:0".parse().unwrap())
+            .max_concurrent(4);
+
+        // Every host is asked for twice, concurrently and in different cases.
+        let lookups: Vec<_> = (0..50)
+            .flat_map(|i| [format!("host{i}.test"), format!("HOST{i}.test")])
+            .map(|host| {
+                let dns = dns.clone();
+                tokio::spawn(async move { dns.resolve_host(&host).await.unwrap() })
+            })
+            .collect();
+        for lookup in lookups {
+            assert_eq!(lookup.await.unwrap().len(), 1);
+        }
+
+        let looked_up = mock.looked_up.lock().unwrap().clone();
+        assert_eq!(looked_up.len(), 50);
+        assert_eq!(looked_up.iter().collect::<HashSet<_>>().len(), 50);
+        assert!(mock.peak.load(Ordering::SeqCst) <= 4);
+        assert_eq!(dns.stats().misses(), 50);
+        assert_eq!(dns.stats().hits(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_failed_lookups_are_repeated() {
+        let failures = Arc::new(AtomicUsize::new(0));
+        let lookup_failures = failures.clone();
+        let dns = DnsResolver::with_lookup(move |_host| {
+            let failures = lookup_failures.clone();
+            async move {
+                if failures.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(std::io::Error::other("resolver timed out"));
+                }
+                Ok(vec!["127.0.0.1:0".parse().unwrap()])
+            }
+        })
+        .cache_ttl(Duration::from_millis(50));
+
+        assert!(dns.resolve_host("example.test").await.is_err());
+        assert!(dns.resolve_host("example.test").await.is_ok());
+        assert!(dns.resolve_host("example.test").await.is_ok());
+        assert_eq!((dns.stats().misses(), dns.stats().hits()), (2, 1));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(dns.resolve_host("example.test").await.is_ok());
+        assert_eq!(dns.stats().misses(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_downloader_resolves_many_hosts_through_the_resolver() {
+        let server = TestServer::start(|request| {
+            let host = request.headers.get("host").cloned().unwrap_or_default();
+            TestResponse::html(&format!("<p>{host}</p>"))
+        })
+        .await;
+        let mock = MockResolver::new();
+        let downloader = HttpDownloader::builder()
+            .dns_resolver(mock.resolver(server.addr).max_concurrent(3))
+            .build()
+            .unwrap();
+
+        for i in 0..20 {
+            for _ in 0..2 {
+                let url =
+                    Url::parse(&format!("http://site{i}.test:{}/", server.addr.port())).unwrap();
+                let response = downloader.download(Request::new(url)).await.unwrap();
+                assert_eq!(response.status.as_u16(), 200);
+                assert!(String::from_utf8_lossy(&response.body).contains(&format!("site{i}.test")));
+            }
+        }
+
+        assert_eq!(mock.looked_up.lock().unwrap().len(), 20);
+        assert!(mock.peak.load(Ordering::SeqCst) <= 3);
+        let stats = downloader.dns_stats().unwrap();
+        assert_eq!(stats.misses(), 20);
+    }
+}
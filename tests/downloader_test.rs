@@ -0,0 +1,563 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::downloader::{FAILED_DOWNLOAD_STATUS, is_connection_reset, is_timeout};
+use spider_lib::prelude::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves `body` as a chunked `text/html` response to every connection.
+    async fn serve(body: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let mut response = String::from(
+                        "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\
+                         transfer-encoding: chunked\r\nconnection: close\r\n\r\n",
+                    );
+                    for chunk in body.as_bytes().chunks(7) {
+                        let chunk = std::str::from_utf8(chunk).unwrap();
+                        response.push_str(&format!("{:x}\r\n{chunk}\r\n", chunk.len()));
+                    }
+                    response.push_str("0\r\n\r\n");
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        addr
+    }
+
+    fn url_of(addr: SocketAddr, path: &str) -> Url {
+        Url::parse(&format!("http://{addr}{path}")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_download_reads_the_whole_body() {
+        let addr = serve("<html><title>Hello</title></html>").await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let request = Request::new(url_of(addr, "/page")).with_meta("page", 3.into());
+        let response = downloader.download(request).await.unwrap();
+
+        assert_eq!(response.status.as_u16(), 200);
+        assert_eq!(&response.body[..], b"<html><title>Hello</title></html>");
+        assert_eq!(response.request_url, url_of(addr, "/page"));
+        assert_eq!(response.meta.get("page").map(|v| v.clone()), Some(3.into()));
+    }
+
+    #[tokio::test]
+    async fn test_body_buffer_pool_reuses_buffers() {
+        let addr = serve("<p>pooled body that spans several chunks</p>").await;
+        let downloader = HttpDownloader::builder()
+            .body_buffer_pool(true)
+            .body_buffer_pool_cap(4)
+            .build()
+            .unwrap();
+
+        let pool = downloader.buffer_pool().unwrap();
+        for page in 0..10 {
+            let url = url_of(addr, &format!("/{page}"));
+            let response = downloader.download(Request::new(url)).await.unwrap();
+            assert_eq!(
+                &response.body[..],
+                b"<p>pooled body that spans several chunks</p>"
+            );
+            // The body is the pooled buffer, which goes back once the response is dropped.
+            assert_eq!(pool.idle(), 0);
+        }
+
+        assert_eq!(pool.allocated(), 1);
+        assert_eq!(pool.reused(), 9);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_kept_bodies_stay_out_of_the_pool() {
+        let addr = serve("<p>kept</p>").await;
+        let downloader = HttpDownloader::builder()
+            .body_buffer_pool(true)
+            .build()
+            .unwrap();
+        let pool = downloader.buffer_pool().unwrap();
+
+        let first = downloader
+            .download(Request::new(url_of(addr, "/first")))
+            .await
+            .unwrap();
+        let kept = first.body.clone();
+        drop(first);
+        assert_eq!(pool.idle(), 0);
+
+        let second = downloader
+            .download(Request::new(url_of(addr, "/second")))
+            .await
+            .unwrap();
+        assert_eq!(pool.allocated(), 2);
+        assert_eq!(&kept[..], b"<p>kept</p>");
+        assert_eq!(&second.body[..], b"<p>kept</p>");
+
+        drop((kept, second));
+        assert_eq!(pool.idle(), 2);
+    }
+
+    #[test]
+    fn test_buffer_pool_cap() {
+        let pool = BufferPool::new(1);
+        let (a, b) = (pool.take(), pool.take());
+        pool.give_back(a);
+        pool.give_back(b);
+        assert_eq!(pool.idle(), 1);
+        assert_eq!(pool.allocated(), 2);
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(pool.reused(), 1);
+    }
+
+    /// Resets the first `resets` connections after reading their request, then answers
+    /// with `ok`.
+    async fn serve_after_resets(resets: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let reset = accepted.fetch_add(1, Ordering::SeqCst) < resets;
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    if reset {
+                        // A zero linger makes dropping the socket send a reset. It does
+                        // not block, which is what the deprecation warns about.
+                        #[allow(deprecated)]
+                        socket.set_linger(Some(Duration::ZERO)).unwrap();
+                        return;
+                    }
+                    let _ = socket
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                        )
+                        .await;
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_connection_resets_are_retried_in_place() {
+        let addr = serve_after_resets(2).await;
+        let downloader = HttpDownloader::builder()
+            .connection_retries(2)
+            .build()
+            .unwrap();
+
+        let response = downloader
+            .download(Request::new(url_of(addr, "/")))
+            .await
+            .unwrap();
+        assert_eq!(&response.body[..], b"ok");
+        assert_eq!(downloader.connection_retries_used(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_connection_retries_are_off_by_default_and_bounded() {
+        let addr = serve_after_resets(1).await;
+        let downloader = HttpDownloader::new().unwrap();
+        let error = downloader
+            .download(Request::new(url_of(addr, "/")))
+            .await
+            .unwrap_err();
+        assert!(is_connection_reset(&error), "{error}");
+        assert!(!is_timeout(&error));
+        assert_eq!(downloader.connection_retries_used(), 0);
+
+        // Nothing listens on a port whose listener was dropped, so every connect fails.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let downloader = HttpDownloader::builder()
+            .connection_retries(3)
+            .build()
+            .unwrap();
+        assert!(
+            downloader
+                .download(Request::new(url_of(closed, "/")))
+                .await
+                .is_err()
+        );
+        assert_eq!(downloader.connection_retries_used(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_reset_post_is_not_retried() {
+        let addr = serve_after_resets(1).await;
+        let downloader = HttpDownloader::builder()
+            .connection_retries(2)
+            .build()
+            .unwrap();
+
+        let mut request = Request::new(url_of(addr, "/submit"));
+        request.method = reqwest::Method::POST;
+        assert!(downloader.download(request).await.is_err());
+        assert_eq!(downloader.connection_retries_used(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_same_host_requests_reuse_a_connection() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/old" => TestResponse::status(302).header("location", "/new"),
+            _ => TestResponse::html("<p>page</p>"),
+        })
+        .await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        for page in 0..4 {
+            let request = Request::new(server.url(&format!("/page/{page}")));
+            downloader.download(request).await.unwrap();
+        }
+        let response = downloader
+            .download(Request::new(server.url("/old")))
+            .await
+            .unwrap();
+        assert_eq!(response.url.path(), "/new");
+
+        let stats = downloader.connection_stats();
+        assert_eq!(stats.requests(), 6);
+        assert_eq!(stats.connections_created(), 1);
+        assert_eq!(stats.connections_reused(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_pool_without_idle_connections_opens_one_per_request() {
+        let server = TestServer::start(|_| TestResponse::html("<p>page</p>")).await;
+        let downloader = HttpDownloader::builder()
+            .pool_max_idle_per_host(0)
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            downloader
+                .download(Request::new(server.url("/")))
+                .await
+                .unwrap();
+        }
+        let stats = downloader.connection_stats();
+        assert_eq!(stats.connections_created(), 3);
+        assert_eq!(stats.connections_reused(), 0);
+    }
+
+    /// Starts a server that records the most requests it saw at once.
+    async fn counting_server(peak: Arc<AtomicUsize>) -> TestServer {
+        let active = Arc::new(AtomicUsize::new(0));
+        TestServer::start(move |_| {
+            let current = active.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(current, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(50));
+            active.fetch_sub(1, Ordering::SeqCst);
+            TestResponse::html("<p>page</p>")
+        })
+        .await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_max_concurrent_per_domain_caps_each_domain() {
+        let (peak_a, peak_b) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let a = counting_server(peak_a.clone()).await;
+        let b = counting_server(peak_b.clone()).await;
+        // Both servers listen on 127.0.0.1, so one of them is reached by another name.
+        let b_url = |path: &str| {
+            let mut url = b.url(path);
+            url.set_host(Some("localhost")).unwrap();
+            url
+        };
+        let downloader = Arc::new(
+            HttpDownloader::builder()
+                .max_concurrent_per_domain(2)
+                .build()
+                .unwrap(),
+        );
+
+        let started = std::time::Instant::now();
+        let downloads: Vec<_> = (0..6)
+            .flat_map(|page| [a.url(&format!("/{page}")), b_url(&format!("/{page}"))])
+            .map(|url| {
+                let downloader = downloader.clone();
+                tokio::spawn(async move { downloader.download(Request::new(url)).await })
+            })
+            .collect();
+        for download in downloads {
+            assert_eq!(download.await.unwrap().unwrap().status.as_u16(), 200);
+        }
+
+        assert_eq!(peak_a.load(Ordering::SeqCst), 2);
+        assert_eq!(peak_b.load(Ordering::SeqCst), 2);
+        // Both domains ran side by side: three rounds of 50ms each, not six.
+        assert!(started.elapsed() < Duration::from_millis(280));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_max_concurrent_per_domain_is_shared_across_ports() {
+        let (peak_a, peak_b) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let a = counting_server(peak_a.clone()).await;
+        let b = counting_server(peak_b.clone()).await;
+        let downloader = Arc::new(
+            HttpDownloader::builder()
+                .max_concurrent_per_domain(1)
+                .build()
+                .unwrap(),
+        );
+
+        let started = std::time::Instant::now();
+        let downloads: Vec<_> = (0..2)
+            .flat_map(|page| [a.url(&format!("/{page}")), b.url(&format!("/{page}"))])
+            .map(|url| {
+                let downloader = downloader.clone();
+                tokio::spawn(async move { downloader.download(Request::new(url)).await })
+            })
+            .collect();
+        for download in downloads {
+            assert_eq!(download.await.unwrap().unwrap().status.as_u16(), 200);
+        }
+
+        // One request at a time to 127.0.0.1, whichever port: four rounds of 50ms.
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_failed_download_with_an_errback_gives_a_placeholder() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let downloader = HttpDownloader::new().unwrap();
+
+        let request = Request::new(url_of(addr, "/gone"));
+        assert!(downloader.download(request).await.is_err());
+
+        let request = Request::new(url_of(addr, "/gone")).errback("try_mirror");
+        let response = downloader.download(request).await.unwrap();
+        assert_eq!(response.status.as_u16(), FAILED_DOWNLOAD_STATUS);
+        assert!(response.body.is_empty());
+        assert!(response.failure().unwrap().starts_with("error: "));
+        assert!(response.failure_error().unwrap().is_connect);
+        assert_eq!(
+            response.request_from_response().errback_name().as_deref(),
+            Some("try_mirror")
+        );
+    }
+
+    /// Answers every connection with headers and part of the body, then stalls.
+    async fn serve_stalling() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let head = "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\
+                                content-length: 10\r\n\r\n<p>";
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_per_request_timeout_overrides_the_downloader() {
+        let addr = serve_stalling().await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let request = Request::new(url_of(addr, "/slow")).timeout(Duration::from_millis(200));
+        assert_eq!(request.timeout_override(), Some(Duration::from_millis(200)));
+        let started = std::time::Instant::now();
+        let error = downloader.download(request).await.unwrap_err();
+        assert!(is_timeout(&error), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(Request::new(url_of(addr, "/")).timeout_override(), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_fails_a_stalled_body() {
+        let addr = serve_stalling().await;
+        let downloader = HttpDownloader::builder()
+            .connect_timeout(Duration::from_secs(1))
+            .read_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let error = downloader
+            .download(Request::new(url_of(addr, "/slow")))
+            .await
+            .unwrap_err();
+        assert!(is_timeout(&error), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let addr = serve("<html>fast</html>").await;
+        let response = downloader
+            .download(Request::new(url_of(addr, "/")))
+            .await
+            .unwrap();
+        assert_eq!(&response.body[..], b"<html>fast</html>");
+    }
+
+    const PAGE: &[u8] = b"<html><body><p>Decoded</p></body></html>";
+
+    /// Returns `PAGE` in the `Content-Encoding` named `encoding`.
+    fn encoded_page(encoding: &str) -> Vec<u8> {
+        use flate2::Compression;
+        use std::io::Write;
+        match encoding {
+            "gzip" => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(PAGE).unwrap();
+                encoder.finish().unwrap()
+            }
+            "deflate" => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(PAGE).unwrap();
+                encoder.finish().unwrap()
+            }
+            // An uncompressed meta-block holding the page, then an empty last one.
+            "br" => {
+                let header = ((PAGE.len() as u32 - 1) << 4) | (1 << 20);
+                let mut body = header.to_le_bytes()[..3].to_vec();
+                body.extend_from_slice(PAGE);
+                body.push(0x03);
+                body
+            }
+            _ => b"not compressed at all".to_vec(),
+        }
+    }
+
+    /// Serves `PAGE` in the encoding named by the request path, and records the
+    /// `Accept-Encoding` of the requests.
+    async fn serve_encoded(accepted: Arc<std::sync::Mutex<Vec<String>>>) -> TestServer {
+        TestServer::start(move |request| {
+            if let Some(accept) = request.headers.get("accept-encoding") {
+                accepted.lock().unwrap().push(accept.clone());
+            }
+            let encoding = request.path.trim_start_matches('/');
+            TestResponse::new(200, "text/html", encoded_page(encoding))
+                .header("content-encoding", encoding)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_compressed_bodies_are_decoded() {
+        let accepted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = serve_encoded(accepted.clone()).await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let mut wire_bytes = 0;
+        for encoding in ["gzip", "deflate", "br"] {
+            let response = downloader
+                .download(Request::new(server.url(&format!("/{encoding}"))))
+                .await
+                .unwrap();
+            assert_eq!(&response.body[..], PAGE, "{encoding}");
+            assert!(!response.headers.contains_key("content-encoding"));
+            assert_eq!(response.css_text("p").unwrap().as_deref(), Some("Decoded"));
+            assert_eq!(response.wire_bytes(), encoded_page(encoding).len());
+            wire_bytes += encoded_page(encoding).len() as u64;
+        }
+        assert_eq!(downloader.byte_stats().bytes_downloaded(), wire_bytes);
+        assert_eq!(
+            downloader.byte_stats().max_response_bytes(),
+            Some(PAGE.len() as u64)
+        );
+        assert!(
+            accepted
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|accept| accept == "gzip, deflate, br")
+        );
+
+        let mut stream = downloader
+            .stream(Request::new(server.url("/gzip")))
+            .await
+            .unwrap();
+        let mut body = Vec::new();
+        while let Some(chunk) = stream.chunk().await.unwrap() {
+            body.extend_from_slice(&chunk);
+        }
+        assert_eq!(body, PAGE);
+        assert_eq!(stream.bytes_read(), PAGE.len());
+    }
+
+    #[tokio::test]
+    async fn test_decompression_can_be_turned_off() {
+        let accepted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = serve_encoded(accepted.clone()).await;
+        let downloader = HttpDownloader::builder().decompress(false).build().unwrap();
+
+        let response = downloader
+            .download(Request::new(server.url("/gzip")))
+            .await
+            .unwrap();
+        assert_eq!(&response.body[..], &encoded_page("gzip")[..]);
+        assert_eq!(response.headers["content-encoding"], "gzip");
+        assert!(accepted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_compression_is_an_error() {
+        let downloader = HttpDownloader::new().unwrap();
+
+        let mut cases = vec![("gzip", b"<html>not gzip</html>".to_vec())];
+        for encoding in ["gzip", "deflate", "br"] {
+            let mut cut_short = encoded_page(encoding);
+            cut_short.truncate(cut_short.len() - 2);
+            cases.push((encoding, cut_short));
+        }
+        for (encoding, body) in cases {
+            let server = TestServer::start(move |_| {
+                TestResponse::new(200, "text/html", body.clone())
+                    .header("content-encoding", encoding)
+            })
+            .await;
+            let error = downloader
+                .download(Request::new(server.url("/")))
+                .await
+                .unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains(&format!("Failed to decode the {encoding} body")),
+                "{error}"
+            );
+        }
+    }
+}
@@ -0,0 +1,371 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use reqwest::StatusCode;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends `url` through the middleware and returns the request the engine records as
+    /// visited.
+    async fn visited_request(middleware: &mut DupeFilterMiddleware, url: &str) -> Request {
+        visited(middleware, Request::new(Url::parse(url).unwrap())).await
+    }
+
+    async fn visited(middleware: &mut DupeFilterMiddleware, request: Request) -> Request {
+        let MiddlewareAction::Continue(request) =
+            Middleware::<()>::process_request(middleware, &(), request)
+                .await
+                .unwrap()
+        else {
+            panic!("the dupe filter must pass requests on");
+        };
+        let response = MockResponse::new(200).body("page").into_response(request);
+        let MiddlewareAction::Continue(response) =
+            Middleware::<()>::process_response(middleware, response)
+                .await
+                .unwrap()
+        else {
+            panic!("the dupe filter must pass responses on");
+        };
+        response.request_from_response()
+    }
+
+    fn fingerprint(url: &str) -> String {
+        Request::new(Url::parse(url).unwrap()).fingerprint()
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_deduplicated_by_default() {
+        let mut middleware = DupeFilterMiddleware::new();
+        let url = "https://example.com/live/scores";
+
+        let visited = visited_request(&mut middleware, url).await;
+        assert_eq!(visited.fingerprint(), fingerprint(url));
+    }
+
+    #[tokio::test]
+    async fn test_rule_exempts_matching_requests() {
+        let mut middleware = DupeFilterMiddleware::new()
+            .should_dedup(|request| !request.url.path().contains("/live/"));
+
+        let live = "https://example.com/live/scores";
+        let visited = visited_request(&mut middleware, live).await;
+        assert_ne!(visited.fingerprint(), fingerprint(live));
+
+        let article = "https://example.com/articles/1";
+        let visited = visited_request(&mut middleware, article).await;
+        assert_eq!(visited.fingerprint(), fingerprint(article));
+    }
+
+    #[tokio::test]
+    async fn test_dont_filter_exempts_a_request() {
+        let mut middleware = DupeFilterMiddleware::new().dedup_on_canonical(true);
+        let url = "https://example.com/status";
+
+        let request = Request::new(Url::parse(url).unwrap()).dont_filter();
+        assert!(!request.filters_duplicates());
+        let recorded = visited(&mut middleware, request).await;
+        assert_ne!(recorded.fingerprint(), fingerprint(url));
+
+        let recorded = visited_request(&mut middleware, url).await;
+        assert_eq!(recorded.fingerprint(), fingerprint(url));
+        let request = Request::new(Url::parse(url).unwrap()).dont_filter();
+        let recorded = visited(&mut middleware, request).await;
+        assert_ne!(recorded.fingerprint(), fingerprint(url));
+    }
+
+    #[tokio::test]
+    async fn test_original_request_url_drops_the_mark() {
+        let mut middleware = DupeFilterMiddleware::new();
+        let url = Url::parse("https://example.com/status").unwrap();
+        let response = MockResponse::new(200)
+            .body("page")
+            .into_response(Request::new(url.clone()).dont_filter());
+        let MiddlewareAction::Continue(response) =
+            Middleware::<()>::process_response(&mut middleware, response)
+                .await
+                .unwrap()
+        else {
+            panic!("the dupe filter must pass responses on");
+        };
+
+        assert_eq!(response.request_url.fragment(), Some("no-dedup"));
+        assert_eq!(response.original_request_url(), url);
+        assert_eq!(response.replay_request().url, url);
+
+        let spider = middleware.wrap(RequestUrlSpider);
+        let (items, _) = spider.parse(response, &()).await.unwrap().into_parts();
+        assert_eq!(items[0].request_url, url.as_str());
+    }
+
+    #[scraped_item]
+    pub struct RequestUrlItem {
+        pub request_url: String,
+    }
+
+    /// Scrapes the `request_url` of every response.
+    pub struct RequestUrlSpider;
+
+    #[async_trait]
+    impl Spider for RequestUrlSpider {
+        type Item = RequestUrlItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(Vec::new())
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(RequestUrlItem {
+                request_url: response.request_url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    async fn passes(middleware: &mut DupeFilterMiddleware, request: Request) -> bool {
+        matches!(
+            Middleware::<()>::process_request(middleware, &(), request)
+                .await
+                .unwrap(),
+            MiddlewareAction::Continue(_)
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dedup_set_drops_canonical_duplicates() {
+        for set in [DedupSet::exact(), DedupSet::bloom(1_000, 0.001).unwrap()] {
+            let mut middleware = DupeFilterMiddleware::new().dedup_set(set);
+            let request = |url: &str| Request::new(Url::parse(url).unwrap());
+
+            assert!(passes(&mut middleware, request("https://Example.com/?a=1&b=2")).await);
+            assert!(
+                !passes(
+                    &mut middleware,
+                    request("https://example.com:443/?b=2&utm_source=x&a=1#top")
+                )
+                .await
+            );
+            assert!(passes(&mut middleware, request("https://example.com/?a=1&b=3")).await);
+
+            let mut retry = request("https://example.com/?a=1&b=2");
+            retry.increment_retry_attempts();
+            assert!(passes(&mut middleware, retry).await);
+            let poll = request("https://example.com/?a=1&b=2").dont_filter();
+            assert!(passes(&mut middleware, poll).await);
+        }
+    }
+
+    #[test]
+    fn test_dedup_set_strip_params() {
+        let request = |url: &str| Request::new(Url::parse(url).unwrap());
+        let tracked = request("https://example.com/?id=1&utm_source=x&session=2");
+
+        let default = DedupSet::exact();
+        assert_ne!(
+            default.fingerprint(&tracked),
+            default.fingerprint(&request("https://example.com/?id=1"))
+        );
+        assert_eq!(
+            default.fingerprint(&tracked),
+            default.fingerprint(&request("https://example.com/?session=2&id=1"))
+        );
+
+        let custom = DedupSet::bloom(100, 0.01)
+            .unwrap()
+            .strip_params(&["session", "utm_*"]);
+        assert!(custom.insert(&request("https://example.com/?id=1")));
+        assert!(!custom.insert(&tracked));
+    }
+
+    #[test]
+    fn test_bloom_rejects_an_invalid_rate() {
+        for rate in [0.0, 1.0, -0.5, f64::NAN] {
+            assert!(matches!(
+                DedupSet::bloom(100, rate),
+                Err(SpiderError::ConfigurationError(_))
+            ));
+        }
+    }
+
+    fn html_response(url: &str, canonical: Option<&str>) -> Response {
+        let url = Url::parse(url).unwrap();
+        let link = canonical
+            .map(|href| format!(r#"<link rel="canonical" href="{}">"#, href))
+            .unwrap_or_default();
+        MockResponse::new(200)
+            .header("content-type", "text/html")
+            .body(format!(
+                "<html><head>{}</head><body>page</body></html>",
+                link
+            ))
+            .into_response(Request::new(url))
+    }
+
+    async fn passes_response(middleware: &mut DupeFilterMiddleware, response: Response) -> bool {
+        match Middleware::<()>::process_response(middleware, response)
+            .await
+            .unwrap()
+        {
+            MiddlewareAction::Continue(_) => true,
+            MiddlewareAction::Drop => false,
+            _ => panic!("unexpected action"),
+        }
+    }
+
+    async fn passes_request(middleware: &mut DupeFilterMiddleware, url: &str) -> bool {
+        let request = Request::new(Url::parse(url).unwrap());
+        match Middleware::<()>::process_request(middleware, &(), request)
+            .await
+            .unwrap()
+        {
+            MiddlewareAction::Continue(_) => true,
+            MiddlewareAction::Drop => false,
+            _ => panic!("unexpected action"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pages_are_deduplicated_on_canonical() {
+        let mut middleware = DupeFilterMiddleware::new().dedup_on_canonical(true);
+
+        let first = html_response("https://example.com/shoes?utm_source=a", Some("/shoes"));
+        assert!(passes_response(&mut middleware, first).await);
+        let variant = html_response("https://example.com/shoes?sort=price", Some("/shoes"));
+        assert!(!passes_response(&mut middleware, variant).await);
+
+        assert!(!passes_request(&mut middleware, "https://example.com/shoes").await);
+        assert!(passes_request(&mut middleware, "https://example.com/hats").await);
+
+        let plain = html_response("https://example.com/hats", None);
+        assert!(passes_response(&mut middleware, plain).await);
+        let declaring = html_response("https://example.com/hats?page=1", Some("/hats"));
+        assert!(!passes_response(&mut middleware, declaring).await);
+    }
+
+    #[tokio::test]
+    async fn test_canonical_origin_check() {
+        let mut strict = DupeFilterMiddleware::new().dedup_on_canonical(true);
+        let mut lenient = DupeFilterMiddleware::new().dedup_on_canonical(false);
+
+        for url in [
+            "https://mirror.example.net/a",
+            "https://mirror.example.net/b",
+        ] {
+            let response = html_response(url, Some("https://example.com/a"));
+            assert!(passes_response(&mut strict, response).await);
+        }
+        let first = html_response(
+            "https://mirror.example.net/a",
+            Some("https://example.com/a"),
+        );
+        assert!(passes_response(&mut lenient, first).await);
+        let second = html_response(
+            "https://mirror.example.net/b",
+            Some("https://example.com/a"),
+        );
+        assert!(!passes_response(&mut lenient, second).await);
+    }
+
+    #[tokio::test]
+    async fn test_failed_responses_are_not_remembered() {
+        let mut middleware = DupeFilterMiddleware::new().dedup_on_canonical(true);
+        let mut unavailable = html_response("https://example.com/a", None);
+        unavailable.status = StatusCode::SERVICE_UNAVAILABLE;
+        assert!(passes_response(&mut middleware, unavailable).await);
+        assert!(
+            passes_response(
+                &mut middleware,
+                html_response("https://example.com/a", None)
+            )
+            .await
+        );
+    }
+
+    #[scraped_item]
+    pub struct HitItem {
+        pub hits: usize,
+    }
+
+    /// Requests `/live` and `/static`, then requests each page again from itself until
+    /// it has been served three times. The pause gives the engine time to record the
+    /// visit, which it does in the background.
+    pub struct RefetchSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for RefetchSpider {
+        type Item = HitItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![
+                Request::new(self.start.join("/live")?),
+                Request::new(self.start.join("/static")?),
+            ])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            let hits: usize = String::from_utf8_lossy(&response.body).parse().unwrap();
+            if hits < 3 {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                let mut again = response.url.clone();
+                again.set_fragment(None);
+                output.add_request(Request::new(again));
+            }
+            output.add_item(HitItem { hits });
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exempted_pages_are_fetched_again() {
+        let live_hits = Arc::new(AtomicUsize::new(0));
+        let static_hits = Arc::new(AtomicUsize::new(0));
+        let (live, stat) = (live_hits.clone(), static_hits.clone());
+        let server = TestServer::start(move |request| {
+            let counter = if request.path == "/live" {
+                &live
+            } else {
+                &stat
+            };
+            let hits = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            TestResponse::new(200, "text/plain", hits.to_string().into_bytes())
+        })
+        .await;
+
+        // The wrapper takes the mark off before `parse`, after the engine recorded it.
+        let filter = DupeFilterMiddleware::new()
+            .should_dedup(|request| !request.url.path().starts_with("/live"));
+        let crawler = CrawlerBuilder::new(filter.wrap(RefetchSpider {
+            start: server.url("/"),
+        }))
+        .dupe_filter(filter)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(live_hits.load(Ordering::SeqCst), 3);
+        assert_eq!(static_hits.load(Ordering::SeqCst), 1);
+    }
+}
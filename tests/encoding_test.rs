@@ -0,0 +1,104 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{EUC_KR, SHIFT_JIS, UTF_8, UTF_16LE, WINDOWS_1251, WINDOWS_1252};
+
+    #[test]
+    fn test_detect_encoding_precedence() {
+        let meta = b"<html><head><META Charset='windows-1251'></head></html>";
+        assert_eq!(detect_encoding(meta, None), WINDOWS_1251);
+        assert_eq!(
+            detect_encoding(meta, Some("text/html; charset=\"Shift_JIS\"")),
+            SHIFT_JIS
+        );
+        assert_eq!(
+            detect_encoding(b"\xFF\xFEh\0i\0", Some("text/html; charset=shift_jis")),
+            UTF_16LE
+        );
+
+        let http_equiv = br#"<meta http-equiv="Content-Type" content="text/html; charset=euc-kr">"#;
+        assert_eq!(charset_from_meta(http_equiv), Some(EUC_KR));
+        assert_eq!(charset_from_meta(b"<meta charset=utf-16>"), Some(UTF_8));
+        assert_eq!(charset_from_meta(b"<meta name=charset>"), None);
+        assert_eq!(charset_from_content_type("text/html"), None);
+        assert_eq!(charset_from_content_type("text/html; charset=bogus"), None);
+
+        assert_eq!(detect_encoding("plain ünïcode".as_bytes(), None), UTF_8);
+        assert_eq!(detect_encoding(b"caf\xE9", None), WINDOWS_1252);
+    }
+
+    #[test]
+    fn test_decode_body_with_detected_and_forced_encodings() {
+        let (cyrillic, _, _) = WINDOWS_1251.encode("<meta charset=\"cp1251\"><p>Привет</p>");
+        assert!(decode_body(&cyrillic, None, None).contains("Привет"));
+
+        let (japanese, _, _) = SHIFT_JIS.encode("<p>こんにちは</p>");
+        assert!(
+            decode_body(&japanese, Some("text/html; charset=Shift_JIS"), None)
+                .contains("こんにちは")
+        );
+        assert!(decode_body(&japanese, None, Some(SHIFT_JIS)).contains("こんにちは"));
+        assert!(!decode_body(&japanese, None, Some(WINDOWS_1252)).contains("こんにちは"));
+    }
+
+    #[test]
+    fn test_undeclared_legacy_bodies_are_guessed() {
+        let (japanese, _, _) = SHIFT_JIS.encode(concat!(
+            "<p>こんにちは、世界。",
+            "日本語のページです。今日はいい天気ですね。</p>"
+        ));
+        assert_eq!(detect_encoding(&japanese, None), SHIFT_JIS);
+
+        let (cyrillic, _, _) = WINDOWS_1251.encode(concat!(
+            "<p>Привет, мир! Это страница на русском языке, ",
+            "и она не в UTF-8.</p>"
+        ));
+        assert_eq!(detect_encoding(&cyrillic, None), WINDOWS_1251);
+        assert!(decode_body(&cyrillic, None, None).contains("Привет, мир!"));
+    }
+
+    fn response(body: &[u8], content_type: Option<&'static str>, request: Request) -> Response {
+        let mut response = MockResponse::new(200).body(body);
+        if let Some(content_type) = content_type {
+            response = response.header("content-type", content_type);
+        }
+        response.into_response(request)
+    }
+
+    #[test]
+    fn test_response_uses_its_content_type_and_forced_encoding() {
+        let url = Url::parse("https://example.jp/").unwrap();
+        let (japanese, _, _) = SHIFT_JIS.encode("<p>東京</p>");
+
+        let declared = response(
+            &japanese,
+            Some("text/html; charset=Shift_JIS"),
+            Request::new(url.clone()),
+        );
+        assert_eq!(declared.encoding(), SHIFT_JIS);
+        assert_eq!(declared.decoded_text(None), "<p>東京</p>");
+        let html = declared.decoded_html(None).unwrap();
+        assert_eq!(html.css_text("p").unwrap().as_deref(), Some("東京"));
+
+        let mislabeled = response(
+            &japanese,
+            Some("text/html; charset=iso-8859-1"),
+            Request::new(url.clone()),
+        );
+        assert_ne!(mislabeled.decoded_text(None), "<p>東京</p>");
+
+        let request = Request::new(url).force_encoding(SHIFT_JIS);
+        assert_eq!(request.forced_encoding(), Some(SHIFT_JIS));
+        let forced = response(&japanese, Some("text/html; charset=iso-8859-1"), request);
+        assert_eq!(forced.encoding(), SHIFT_JIS);
+        assert_eq!(forced.decoded_text(None), "<p>東京</p>");
+        assert_eq!(
+            forced.decoded_text(Some(UTF_8)),
+            String::from_utf8_lossy(&japanese)
+        );
+    }
+}
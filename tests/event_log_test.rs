@@ -0,0 +1,148 @@
+use spider_lib::event_log::EventRecord;
+use spider_lib::prelude::*;
+use spider_lib::testing::{CollectorPipeline, MockResponses};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "spider-event-log-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn read_events(path: &PathBuf) -> Vec<EventRecord> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub title: String,
+    }
+
+    pub struct PagesSpider;
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            if response.url.path() == "/broken" {
+                return Err(SpiderError::GeneralError("no title".to_string()));
+            }
+            let mut output = ParseOutput::new();
+            let title = response
+                .to_html()?
+                .select(&"title".to_selector()?)
+                .next()
+                .map(|title| title.text().collect::<String>())
+                .unwrap_or_default();
+            output.add_item(PageItem { title });
+            output.add_request(Request::new(response.url.join("/broken")?));
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crawl_events_are_written_as_jsonl() {
+        let path = event_log_path("crawl");
+        let events = EventLog::create(&path).unwrap();
+        let collector = CollectorPipeline::new();
+        let crawler = CrawlerBuilder::new(events.wrap(PagesSpider))
+            .event_log(&events)
+            .add_pipeline(collector.clone())
+            .add_middleware(
+                MockResponses::new()
+                    .html("https://example.com/", "<title>Home</title>")
+                    .html("https://example.com/broken", "<p>No title</p>"),
+            )
+            .build()
+            .await
+            .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let records = read_events(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(collector.len(), 1);
+        assert!(records.iter().all(|record| record.timestamp_ms > 0));
+
+        let events: Vec<CrawlEvent> = records.into_iter().map(|record| record.event).collect();
+        let requests: Vec<&CrawlEvent> = events
+            .iter()
+            .filter(|event| matches!(event, CrawlEvent::Request { .. }))
+            .collect();
+        assert_eq!(requests.len(), 2);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            CrawlEvent::Response { url, status: 200, latency_ms: Some(_), .. }
+                if url == "https://example.com/broken"
+        )));
+        assert!(events.contains(&CrawlEvent::Item {
+            item: serde_json::json!({"title": "Home"}),
+        }));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            CrawlEvent::Error { url, error } if url == "https://example.com/broken"
+                && error.contains("no title")
+        )));
+    }
+
+    #[test]
+    fn test_events_are_buffered_until_the_flush_interval() {
+        let path = event_log_path("buffered");
+        let events = EventLog::create(&path)
+            .unwrap()
+            .flush_interval(Duration::from_secs(3600));
+        events.record(CrawlEvent::Request {
+            url: "https://example.com/".to_string(),
+            method: "GET".to_string(),
+        });
+        assert!(read_events(&path).is_empty());
+
+        events.flush().unwrap();
+        let records = read_events(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            records[0].event,
+            CrawlEvent::Request {
+                url: "https://example.com/".to_string(),
+                method: "GET".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_zero_interval_flushes_every_event() {
+        let path = event_log_path("unbuffered");
+        let events = EventLog::create(&path)
+            .unwrap()
+            .flush_interval(Duration::ZERO);
+        events.record(CrawlEvent::Error {
+            url: "https://example.com/".to_string(),
+            error: "boom".to_string(),
+        });
+        let records = read_events(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 1);
+    }
+}
@@ -0,0 +1,97 @@
+use spider_lib::prelude::*;
+use std::path::PathBuf;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Book {
+        pub title: String,
+        pub price: f64,
+        pub tags: Vec<String>,
+    }
+
+    fn export_path(name: &str, extension: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "spider-export-{}-{}.{}",
+            name,
+            std::process::id(),
+            extension
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn books() -> Vec<Book> {
+        vec![
+            Book {
+                title: "Dune".into(),
+                price: 9.5,
+                tags: vec!["sf".into()],
+            },
+            Book {
+                title: "Cooking, \"simply\"".into(),
+                price: 12.0,
+                tags: vec![],
+            },
+            Book {
+                title: "Emma".into(),
+                price: 4.25,
+                tags: vec!["classic".into(), "novel".into()],
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_items_are_written_to_every_format() {
+        let json = export_path("all", "json");
+        let jsonl = export_path("all", "jsonl");
+        let csv = export_path("all", "csv");
+        let exporter = MultiFormatExporter::new()
+            .json(&json)
+            .jsonl(&jsonl)
+            .csv(&csv)
+            .buffer_size(2);
+
+        for book in books() {
+            assert!(exporter.process_item(book).await.unwrap().is_some());
+        }
+        exporter.close().await.unwrap();
+        exporter.close().await.unwrap();
+
+        let array: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(array.len(), 3);
+        assert_eq!(array[2]["title"], "Emma");
+
+        let lines = std::fs::read_to_string(&jsonl).unwrap();
+        let lines: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, array);
+
+        assert_eq!(
+            std::fs::read_to_string(&csv).unwrap(),
+            "price,tags,title\n\
+             9.5,\"[\"\"sf\"\"]\",Dune\n\
+             12.0,[],\"Cooking, \"\"simply\"\"\"\n\
+             4.25,\"[\"\"classic\"\",\"\"novel\"\"]\",Emma\n"
+        );
+        for path in [json, jsonl, csv] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_export_is_valid_json() {
+        let json = export_path("empty", "json");
+        let exporter = MultiFormatExporter::<Book>::new().json(&json);
+        exporter.close().await.unwrap();
+        let array: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert!(array.is_empty());
+        let _ = std::fs::remove_file(json);
+    }
+}
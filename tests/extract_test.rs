@@ -0,0 +1,174 @@
+use scraper::Html;
+use serde::Deserialize;
+use spider_lib::prelude::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Quote {
+        text: String,
+        author: String,
+        tags: Vec<String>,
+    }
+
+    const QUOTES: &str = r#"
+        <div class="quote" data-id="1">
+            <span class="text">  “Be yourself.”  </span>
+            <small class="author">Oscar Wilde</small>
+            <a class="tag" href="/tag/life">life</a><a class="tag" href="/tag/humor">humor</a>
+        </div>
+        <div class="quote" data-id="2">
+            <small class="author">Nobody</small>
+        </div>
+        <div class="quote" data-id="3">
+            <span class="text">Simplicity.</span>
+        </div>
+        <div class="quote" data-id="4">
+            <span class="text">   </span>
+            <small class="author">Blank</small>
+        </div>
+    "#;
+
+    fn quote_selectors() -> ItemSelectors {
+        ItemSelectors::new()
+            .field("text", FieldRule::text(".text").trim().required())
+            .field(
+                "author",
+                FieldRule::text(".author").default_value("Anonymous"),
+            )
+            .field("tags", FieldRule::text(".tag").all())
+    }
+
+    #[test]
+    fn test_extract_items_applies_required_and_default() {
+        let html = Html::parse_document(QUOTES);
+        let quotes: Vec<Quote> = extract_items(&html, ".quote", &quote_selectors()).unwrap();
+
+        assert_eq!(
+            quotes,
+            vec![
+                Quote {
+                    text: "“Be yourself.”".to_string(),
+                    author: "Oscar Wilde".to_string(),
+                    tags: vec!["life".to_string(), "humor".to_string()],
+                },
+                Quote {
+                    text: "Simplicity.".to_string(),
+                    author: "Anonymous".to_string(),
+                    tags: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attributes_transforms_and_root_element() {
+        #[derive(Debug, Deserialize)]
+        struct Link {
+            id: String,
+            href: String,
+            label: String,
+        }
+
+        let html = Html::parse_document(QUOTES);
+        let selectors = ItemSelectors::new()
+            .field("id", FieldRule::attr("", "data-id"))
+            .field("href", FieldRule::attr(".tag", "href").required())
+            .field(
+                "label",
+                FieldRule::text(".tag").map(|tag| tag.to_uppercase()),
+            );
+
+        let links: Vec<Link> = extract_items(&html, ".quote", &selectors).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].id, "1");
+        assert_eq!(links[0].href, "/tag/life");
+        assert_eq!(links[0].label, "LIFE");
+
+        let raw: Option<Quote> = extract_item(
+            html.root_element(),
+            &ItemSelectors::new()
+                .field("text", FieldRule::text(".text"))
+                .field("author", FieldRule::text(".author"))
+                .field("tags", FieldRule::text(".tag").all()),
+        )
+        .unwrap();
+        assert_eq!(raw.unwrap().text, "  “Be yourself.”  ");
+    }
+
+    #[test]
+    fn test_records_keep_missing_and_untrimmed_values() {
+        let html = Html::parse_document(QUOTES);
+        let selectors = ItemSelectors::new()
+            .field("text", FieldRule::text(".text"))
+            .field("author", FieldRule::text(".author"))
+            .field("tags", FieldRule::text(".tag").all());
+
+        let records = extract_records(&html, ".quote", &selectors).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].text("text"), "  “Be yourself.”  ");
+        assert_eq!(records[0].all("tags"), vec!["life", "humor"]);
+        assert_eq!(records[1].text("text"), "");
+        assert_eq!(records[1].text("author"), "Nobody");
+        assert_eq!(records[3].text("text"), "   ");
+        assert_eq!(records[2].text("missing"), "");
+
+        let quote: Quote = records[0].clone().into_item().unwrap();
+        assert_eq!(quote.author, "Oscar Wilde");
+        assert!(records[1].clone().into_item::<Vec<String>>().is_err());
+    }
+
+    #[test]
+    fn test_required_rejects_empty_values() {
+        let html = Html::parse_document(r#"<div class="quote"><span class="text"></span></div>"#);
+        let selectors = ItemSelectors::new().field("text", FieldRule::text(".text").required());
+        assert!(
+            extract_records(&html, ".quote", &selectors)
+                .unwrap()
+                .is_empty()
+        );
+
+        let html =
+            Html::parse_document(r#"<div class="quote"><a class="text" href="">x</a></div>"#);
+        let selectors =
+            ItemSelectors::new().field("href", FieldRule::attr(".text", "href").required());
+        assert!(
+            extract_records(&html, ".quote", &selectors)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_mismatched_items_are_skipped_and_bad_selectors_fail() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Count {
+            count: u32,
+        }
+
+        let html = Html::parse_document(QUOTES);
+        let selectors = ItemSelectors::new().field("count", FieldRule::text(".author"));
+        let counts: Vec<Count> = extract_items(&html, ".quote", &selectors).unwrap();
+        assert!(counts.is_empty());
+
+        let invalid = ItemSelectors::new().field("text", FieldRule::text("[[["));
+        assert!(extract_items::<Quote>(&html, ".quote", &invalid).is_err());
+    }
+
+    #[test]
+    fn test_looks_empty_detects_missing_content() {
+        let shell = Html::parse_document(
+            r#"<html><body><div id="app"><div class="quote">  </div></div>
+            <script src="/bundle.js"></script></body></html>"#,
+        );
+        assert!(looks_empty(&shell, ".quote").unwrap());
+        assert!(looks_empty(&shell, ".missing").unwrap());
+
+        let page = Html::parse_document(QUOTES);
+        assert!(!looks_empty(&page, ".quote .text").unwrap());
+        assert!(looks_empty(&page, "[[[").is_err());
+    }
+}
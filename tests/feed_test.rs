@@ -0,0 +1,160 @@
+#![cfg(feature = "feed")]
+
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rss_feed() {
+        let base = Url::parse("https://news.example.com/rss.xml").unwrap();
+        let rss = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+              <channel>
+                <title>Example News</title>
+                <link>https://news.example.com/</link>
+                <item>
+                  <title>First &amp; foremost</title>
+                  <link>https://news.example.com/articles/1</link>
+                  <pubDate>Tue, 01 Oct 2024 08:00:00 GMT</pubDate>
+                  <description><![CDATA[<p>Short summary</p>]]></description>
+                </item>
+                <item>
+                  <title>Relative</title>
+                  <link>/articles/2</link>
+                  <content:encoded>Full text</content:encoded>
+                </item>
+                <item>
+                  <title>No link</title>
+                </item>
+              </channel>
+            </rss>"#;
+
+        let feed = parse_feed(rss, &base).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example News"));
+        assert_eq!(feed.entries.len(), 3);
+
+        let first = &feed.entries[0];
+        assert_eq!(first.title.as_deref(), Some("First & foremost"));
+        assert_eq!(
+            first.link.as_ref().map(Url::as_str),
+            Some("https://news.example.com/articles/1")
+        );
+        assert_eq!(
+            first.published.as_deref(),
+            Some("Tue, 01 Oct 2024 08:00:00 GMT")
+        );
+        assert_eq!(first.summary.as_deref(), Some("<p>Short summary</p>"));
+
+        assert_eq!(feed.entries[1].summary.as_deref(), Some("Full text"));
+        assert_eq!(feed.entries[2].link, None);
+
+        let urls: Vec<String> = feed
+            .requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://news.example.com/articles/1",
+                "https://news.example.com/articles/2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_atom_feed() {
+        let base = Url::parse("https://blog.example.com/feed.atom").unwrap();
+        let atom = br#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <title>Example Blog</title>
+              <link rel="self" href="https://blog.example.com/feed.atom"/>
+              <entry>
+                <title type="html">Hello</title>
+                <link rel="alternate" href="/posts/hello"/>
+                <link rel="enclosure" href="/media/hello.mp3"/>
+                <author><name>Jane</name></author>
+                <updated>2024-10-02T09:00:00Z</updated>
+                <published>2024-10-01T09:00:00Z</published>
+                <summary>Hi there</summary>
+              </entry>
+              <entry>
+                <title>Updated only</title>
+                <link href="https://blog.example.com/posts/2"/>
+                <updated>2024-10-03T09:00:00Z</updated>
+                <content type="xhtml"><div>Body <b>text</b></div></content>
+              </entry>
+            </feed>"#;
+
+        let feed = parse_feed(atom, &base).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example Blog"));
+        assert_eq!(
+            feed.entries,
+            vec![
+                FeedEntry {
+                    title: Some("Hello".to_string()),
+                    link: Some(Url::parse("https://blog.example.com/posts/hello").unwrap()),
+                    published: Some("2024-10-01T09:00:00Z".to_string()),
+                    summary: Some("Hi there".to_string()),
+                },
+                FeedEntry {
+                    title: Some("Updated only".to_string()),
+                    link: Some(Url::parse("https://blog.example.com/posts/2").unwrap()),
+                    published: Some("2024-10-03T09:00:00Z".to_string()),
+                    summary: Some("Body text".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_feed_rejects_other_documents() {
+        let base = Url::parse("https://example.com/").unwrap();
+        assert_eq!(
+            parse_feed(b"<html><body></body></html>", &base),
+            Err(FeedError::NotFeed)
+        );
+        assert_eq!(parse_feed(b"", &base), Err(FeedError::NotFeed));
+        assert!(matches!(
+            parse_feed(b"<rss><channel><item></channel></rss>", &base),
+            Err(FeedError::Xml(_))
+        ));
+        assert_eq!(parse_feed(b"<rss/>", &base), Ok(Feed::default()));
+    }
+
+    #[test]
+    fn test_parse_feed_with_html_entities() {
+        let base = Url::parse("https://blog.example.com/feed").unwrap();
+        let rss = br#"<rss version="2.0"><channel>
+              <title>Notes&nbsp;&mdash; daily</title>
+              <item>
+                <title>Caf&eacute; &amp; more&hellip;</title>
+                <link>/posts/1?a=1&amp;b=2</link>
+                <description>Unknown &bogus; entity</description>
+              </item>
+            </channel></rss>"#;
+
+        let feed = parse_feed(rss, &base).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Notes\u{a0}\u{2014} daily"));
+        let entry = &feed.entries[0];
+        assert_eq!(entry.title.as_deref(), Some("Caf\u{e9} & more\u{2026}"));
+        assert_eq!(
+            entry.link.as_ref().map(Url::as_str),
+            Some("https://blog.example.com/posts/1?a=1&b=2")
+        );
+        assert_eq!(entry.summary.as_deref(), Some("Unknown &bogus; entity"));
+
+        let atom = br#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <entry><title>A&nbsp;B</title><link href="/a?x=1&amp;y=&copy;"/></entry>
+            </feed>"#;
+        let feed = parse_feed(atom, &base).unwrap();
+        assert_eq!(feed.entries[0].title.as_deref(), Some("A\u{a0}B"));
+        assert_eq!(
+            feed.entries[0].link.as_ref().map(Url::as_str),
+            Some("https://blog.example.com/a?x=1&y=%C2%A9")
+        );
+    }
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::{TestRequest, TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Product {
+        pub name: String,
+        pub image_urls: Vec<String>,
+        #[serde(default)]
+        pub images: Vec<StoredFile>,
+    }
+
+    /// Marks every request it passes with a header, to show the downloads use it.
+    struct TagMiddleware;
+
+    #[async_trait]
+    impl<C: Send + Sync> Middleware<C> for TagMiddleware {
+        fn name(&self) -> &str {
+            "TagMiddleware"
+        }
+
+        async fn process_request(
+            &mut self,
+            _client: &C,
+            request: Request,
+        ) -> Result<MiddlewareAction<Request>, SpiderError> {
+            Ok(MiddlewareAction::Continue(
+                request.with_header("X-Files", "yes")?,
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_files_are_stored_once_and_written_back() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let server = TestServer::start(move |request: &TestRequest| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(
+                request.headers.get("x-files").map(String::as_str),
+                Some("yes")
+            );
+            match request.path.as_str() {
+                "/img/lamp.PNG" => TestResponse::new(200, "image/png", b"lamp".to_vec()),
+                "/img/desk" => TestResponse::new(200, "image/jpeg", b"desk".to_vec()),
+                _ => TestResponse::status(404),
+            }
+        })
+        .await;
+        let dir = std::env::temp_dir().join("spider_files_pipeline");
+        let _ = std::fs::remove_dir_all(&dir);
+        let files = FilesPipeline::new(HttpDownloader::new().unwrap(), &dir)
+            .field("image_urls", "images")
+            .add_middleware(TagMiddleware)
+            .concurrency(2);
+
+        let lamp = server.url("/img/lamp.PNG").to_string();
+        let desk = server.url("/img/desk").to_string();
+        let missing = server.url("/img/missing.png").to_string();
+        let first = files
+            .process_item(Product {
+                name: "Lamp".into(),
+                image_urls: vec![lamp.clone(), missing, "not a url".into(), desk.clone()],
+                images: Vec::new(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.images.len(), 2);
+        assert_eq!(first.images[0].url, lamp);
+        assert!(first.images[0].path.starts_with("full/"));
+        assert!(first.images[0].path.ends_with(".png"));
+        assert_eq!(first.images[1].url, desk);
+        assert!(!first.images[1].path.contains('.'));
+        assert_eq!(
+            std::fs::read(dir.join(&first.images[0].path)).unwrap(),
+            b"lamp"
+        );
+        assert_eq!(
+            std::fs::read(dir.join(&first.images[1].path)).unwrap(),
+            b"desk"
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // Seen in this crawl: not downloaded again.
+        let second = files
+            .process_item(Product {
+                name: "Lamp set".into(),
+                image_urls: vec![lamp.clone()],
+                images: Vec::new(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.images, first.images[..1]);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // Already on disk: a new pipeline does not download it either.
+        let restarted = FilesPipeline::new(HttpDownloader::new().unwrap(), &dir)
+            .field("image_urls", "images")
+            .add_middleware(TagMiddleware);
+        let third = restarted
+            .process_item(Product {
+                name: "Desk".into(),
+                image_urls: vec![desk],
+                images: Vec::new(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(third.images, first.images[1..]);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,167 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Product {
+        pub price: f64,
+        pub quantity: u32,
+        pub total: f64,
+        pub date: String,
+    }
+
+    impl FinalizeItem for Product {
+        fn finalize(mut self) -> Self {
+            self.total = self.price * f64::from(self.quantity);
+            self.date = self.date.replace('/', "-");
+            self
+        }
+    }
+
+    #[scraped_item]
+    pub struct Plain {
+        pub name: String,
+    }
+
+    impl FinalizeItem for Plain {}
+
+    pub struct ProductsSpider;
+
+    #[async_trait]
+    impl Spider for ProductsSpider {
+        type Item = Product;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://shop.example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(Product {
+                price: 2.5,
+                quantity: 4,
+                total: 0.0,
+                date: "2024/05/01".into(),
+            });
+            output.add_request(Request::new(response.url.join("/page/2")?));
+            Ok(output)
+        }
+    }
+
+    /// Scrapes one product in stock and one sold out, and keeps only the products with
+    /// at least `min_quantity` in stock.
+    pub struct StockSpider {
+        min_quantity: u32,
+    }
+
+    #[async_trait]
+    impl Spider for StockSpider {
+        type Item = Product;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://shop.example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            for quantity in [3, 0] {
+                output.add_item(Product {
+                    price: 1.0,
+                    quantity,
+                    total: 0.0,
+                    date: " 2024-05-01 ".into(),
+                });
+            }
+            output.add_request(Request::new(response.url.join("/page/2")?));
+            Ok(output)
+        }
+    }
+
+    impl ProcessItemSpider for StockSpider {
+        fn process_item(&self, mut item: Product, _state: &()) -> Option<Product> {
+            item.date = item.date.trim().to_string();
+            (item.quantity >= self.min_quantity).then_some(item)
+        }
+    }
+
+    impl ProcessItemSpider for ProductsSpider {}
+
+    fn response() -> Response {
+        let request = Request::new(Url::parse("https://shop.example.com/").unwrap());
+        MockResponse::new(200)
+            .body("<html></html>")
+            .into_response(request)
+    }
+
+    #[tokio::test]
+    async fn test_items_are_finalized_and_requests_kept() {
+        let spider = ProductsSpider.finalize_items();
+        assert_eq!(Spider::start_requests(&spider).unwrap().len(), 1);
+
+        let (items, requests) = Spider::parse(&spider, response(), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].total, 10.0);
+        assert_eq!(items[0].date, "2024-05-01");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url.path(), "/page/2");
+    }
+
+    #[test]
+    fn test_default_finalize_is_a_no_op() {
+        let item = Plain {
+            name: "unchanged".into(),
+        };
+        assert_eq!(item.finalize().name, "unchanged");
+    }
+
+    #[tokio::test]
+    async fn test_finalized_spider_builds_a_crawler() {
+        assert!(
+            CrawlerBuilder::new(ProductsSpider.finalize_items())
+                .build()
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_processed_items_are_cleaned_and_dropped_by_the_spider() {
+        let spider = StockSpider { min_quantity: 1 }.processed();
+        let (items, requests) = Spider::parse(&spider, response(), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].quantity, 3);
+        assert_eq!(items[0].date, "2024-05-01");
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_default_process_item_passes_items() {
+        let spider = ProductsSpider.processed();
+        let (items, _) = Spider::parse(&spider, response(), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].total, 0.0);
+    }
+}
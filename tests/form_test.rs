@@ -0,0 +1,132 @@
+use reqwest::Method;
+use scraper::Html;
+use spider_lib::prelude::*;
+use spider_util::request::Body;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIN_PAGE: &str = r#"
+        <form id="search" action="/search?old=1"><input name="q" value="rust"></form>
+        <form id="login" action="session" method="post">
+            <input type="hidden" name="csrf_token" value="abc123">
+            <input name="username">
+            <input type="password" name="password" value="">
+            <input type="checkbox" name="remember" checked>
+            <input type="checkbox" name="newsletter" value="yes">
+            <input type="radio" name="plan" value="free">
+            <input type="radio" name="plan" value="pro" checked>
+            <select name="country">
+                <option value="id">Indonesia</option>
+                <option value="jp" selected>Japan</option>
+            </select>
+            <select name="lang"><option>English</option><option>Bahasa</option></select>
+            <select name="tags" multiple>
+                <option value="a" selected>A</option>
+                <option value="b">B</option>
+                <option value="c" selected>C</option>
+            </select>
+            <textarea name="note">hello</textarea>
+            <input name="disabled_field" value="x" disabled>
+            <input type="submit" name="go" value="Sign in">
+        </form>
+    "#;
+
+    fn page_url() -> Url {
+        Url::parse("https://example.com/account/login").unwrap()
+    }
+
+    #[test]
+    fn test_form_defaults() {
+        let html = Html::parse_document(LOGIN_PAGE);
+        let form = extract_form(&html, &page_url(), "form#login")
+            .unwrap()
+            .expect("login form");
+
+        assert_eq!(form.action.as_str(), "https://example.com/account/session");
+        assert_eq!(form.method, "POST");
+
+        let fields: Vec<(&str, &str)> = form
+            .fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("csrf_token", "abc123"),
+                ("username", ""),
+                ("password", ""),
+                ("remember", "on"),
+                ("plan", "pro"),
+                ("country", "jp"),
+                ("lang", "English"),
+                ("tags", "a"),
+                ("tags", "c"),
+                ("note", "hello"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_form_overrides_and_encoding() {
+        let html = Html::parse_document(LOGIN_PAGE);
+        let form = extract_form(&html, &page_url(), "#login").unwrap().unwrap();
+
+        let data = form.form_data(&[("username", "alice"), ("tags", "b"), ("extra", "1")]);
+        assert!(data.contains(&("username".to_string(), "alice".to_string())));
+        assert!(data.contains(&("csrf_token".to_string(), "abc123".to_string())));
+        assert_eq!(data.iter().filter(|(name, _)| name == "tags").count(), 1);
+        assert_eq!(data.last(), Some(&("extra".to_string(), "1".to_string())));
+
+        assert!(
+            form.encoded(&[("username", "a b&c")])
+                .contains("username=a+b%26c")
+        );
+    }
+
+    #[test]
+    fn test_post_form_submission() {
+        let html = Html::parse_document(LOGIN_PAGE);
+        let form = extract_form(&html, &page_url(), "#login").unwrap().unwrap();
+        assert_eq!(form.method, "POST");
+
+        let request = form.submit(&[("username", "alice")]);
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url, form.action);
+        assert_eq!(
+            request.headers.get("content-type").unwrap(),
+            "application/x-www-form-urlencoded"
+        );
+        let Some(Body::Bytes(body)) = &request.body else {
+            panic!("POST submissions carry an encoded body");
+        };
+        assert_eq!(
+            std::str::from_utf8(body).unwrap(),
+            form.encoded(&[("username", "alice")])
+        );
+    }
+
+    #[test]
+    fn test_get_form_submission() {
+        let html = Html::parse_document(LOGIN_PAGE);
+        let form = extract_form(&html, &page_url(), "#search")
+            .unwrap()
+            .unwrap();
+        assert_eq!(form.method, "GET");
+
+        let request = form.submit(&[("q", "web scraping")]);
+        assert_eq!(
+            request.url.as_str(),
+            "https://example.com/search?q=web+scraping"
+        );
+
+        assert!(
+            extract_form(&html, &page_url(), "#missing")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use reqwest::header::{ACCEPT_LANGUAGE, HeaderValue};
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn send(headers: &mut HeadersMiddleware, request: Request) -> Request {
+        match Middleware::<()>::process_request(headers, &(), request)
+            .await
+            .unwrap()
+        {
+            MiddlewareAction::Continue(request) => request,
+            _ => panic!("request was not continued"),
+        }
+    }
+
+    fn request(url: &str) -> Request {
+        Request::new(Url::parse(url).unwrap())
+    }
+
+    fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+        request
+            .headers
+            .get(name)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_domain_headers_override_defaults() {
+        let mut headers = HeadersMiddleware::new()
+            .default_headers(&[("Accept-Language", "en-US"), ("X-Crawler", "spider")])
+            .domain("example.de", &[("Accept-Language", "de-DE")])
+            .domain("*.api.example.com", &[("X-Client", "sync")]);
+
+        let german = send(&mut headers, request("https://shop.example.de/")).await;
+        assert_eq!(header(&german, "accept-language"), Some("de-DE"));
+        assert_eq!(header(&german, "x-crawler"), Some("spider"));
+
+        let api = send(&mut headers, request("https://v2.api.example.com/items")).await;
+        assert_eq!(header(&api, "x-client"), Some("sync"));
+        assert_eq!(header(&api, "accept-language"), Some("en-US"));
+
+        let apex = send(&mut headers, request("https://api.example.com/")).await;
+        assert_eq!(header(&apex, "x-client"), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_headers_take_precedence() {
+        let mut headers = HeadersMiddleware::new()
+            .default_headers(&[("Accept-Language", "en-US"), ("Referer", "https://a.test/")]);
+        let mut own = request("https://example.com/");
+        own.headers
+            .insert(ACCEPT_LANGUAGE, HeaderValue::from_static("fr-FR"));
+
+        let sent = send(&mut headers, own).await;
+        assert_eq!(header(&sent, "accept-language"), Some("fr-FR"));
+        assert_eq!(header(&sent, "referer"), Some("https://a.test/"));
+    }
+
+    #[tokio::test]
+    async fn test_header_sets_rotate() {
+        let mut headers = HeadersMiddleware::new().rotate(
+            "shop.example",
+            &[
+                &[("Accept-Language", "en-GB"), ("DNT", "1")],
+                &[("Accept-Language", "en-US")],
+            ],
+        );
+        let shared = headers.clone();
+
+        let mut languages = Vec::new();
+        for _ in 0..3 {
+            let sent = send(&mut headers, request("https://shop.example/")).await;
+            languages.push(header(&sent, "accept-language").unwrap().to_string());
+        }
+        assert_eq!(languages, ["en-GB", "en-US", "en-GB"]);
+        assert_eq!(
+            shared
+                .headers_for("shop.example")
+                .get("accept-language")
+                .unwrap(),
+            "en-US"
+        );
+        assert!(shared.headers_for("other.example").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid header name")]
+    fn test_invalid_header_names_panic() {
+        HeadersMiddleware::new().default_headers(&[("Bad Header", "x")]);
+    }
+}
@@ -0,0 +1,49 @@
+use spider_lib::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontier_estimate() {
+        let stats = Arc::new(StatCollector::default());
+        stats.requests_enqueued.store(10, Ordering::SeqCst);
+        stats.requests_sent.store(3, Ordering::SeqCst);
+        stats.requests_dropped.store(2, Ordering::SeqCst);
+        stats.items_scraped.store(4, Ordering::SeqCst);
+
+        let health = HealthMonitor::new(stats).request_bytes(2000).check();
+        assert_eq!(health.queued_requests, 5);
+        assert_eq!(health.estimated_frontier_bytes, 10_000);
+        assert_eq!(health.requests_sent, 3);
+        assert_eq!(health.items_scraped, 4);
+        assert!(health.progressing);
+    }
+
+    #[test]
+    fn test_stalled_crawl_stops_progressing_until_counters_move() {
+        let stats = Arc::new(StatCollector::default());
+        let monitor = HealthMonitor::new(stats.clone()).stall_after(Duration::from_millis(20));
+        assert!(monitor.check().progressing);
+
+        std::thread::sleep(Duration::from_millis(40));
+        let health = monitor.check();
+        assert!(!health.progressing);
+        assert!(health.idle_for >= Duration::from_millis(40));
+
+        stats.responses_received.fetch_add(1, Ordering::SeqCst);
+        let health = monitor.check();
+        assert!(health.progressing);
+        assert!(health.idle_for < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_counters_never_underflow() {
+        let stats = Arc::new(StatCollector::default());
+        stats.requests_sent.store(3, Ordering::SeqCst);
+        assert_eq!(HealthMonitor::new(stats).check().queued_requests, 0);
+    }
+}
@@ -0,0 +1,102 @@
+use scraper::Html;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: Option<&'static str>, body: Vec<u8>) -> Response {
+        let request = Request::new(Url::parse("https://example.com/page").unwrap());
+        let mut response = MockResponse::new(200).body(body);
+        if let Some(content_type) = content_type {
+            response = response.header("content-type", content_type);
+        }
+        response.into_response(request)
+    }
+
+    fn text_of(html: &Html, selector: &str) -> String {
+        html.select(&selector.to_selector().unwrap())
+            .next()
+            .map(|element| element.text().collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_html_is_parsed() {
+        let page = response(
+            Some("text/html; charset=utf-8"),
+            b"<html><body><h1>Hello</h1></body></html>".to_vec(),
+        );
+        let html = page.html().unwrap();
+        assert_eq!(text_of(&html, "h1"), "Hello");
+    }
+
+    #[test]
+    fn test_binary_bytes_are_not_html() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(
+            response(Some("text/html"), png).html().unwrap_err(),
+            NotHtml::Signature("PNG image")
+        );
+
+        let blob: Vec<u8> = (0u8..=255).cycle().take(2048).collect();
+        assert_eq!(response(None, blob).html().unwrap_err(), NotHtml::Binary);
+
+        let error = response(Some("image/webp"), b"RIFF....WEBP".to_vec())
+            .html()
+            .unwrap_err();
+        assert_eq!(error, NotHtml::ContentType("image/webp".into()));
+        assert!(matches!(
+            SpiderError::from(error),
+            SpiderError::HtmlParseError(_)
+        ));
+    }
+
+    #[test]
+    fn test_heuristic_is_configurable() {
+        let mislabeled = response(
+            Some("application/octet-stream"),
+            b"<html><p>text</p></html>".to_vec(),
+        );
+        assert!(mislabeled.html().is_err());
+        let lenient = TextHeuristic::new().check_content_type(false);
+        assert!(mislabeled.html_with(&lenient).is_ok());
+
+        let noisy = response(None, b"<p>a\x01b\x02c</p>".to_vec());
+        assert!(noisy.html().is_err());
+        let tolerant = TextHeuristic::new().max_control_ratio(0.5);
+        assert!(noisy.html_with(&tolerant).is_ok());
+    }
+
+    #[test]
+    fn test_utf16_text_is_not_binary() {
+        let mut body = vec![0xFF, 0xFE];
+        for unit in "<p>hi</p>".encode_utf16() {
+            body.extend_from_slice(&unit.to_le_bytes());
+        }
+        let html = response(Some("text/html"), body).html().unwrap();
+        assert_eq!(text_of(&html, "p"), "hi");
+    }
+
+    #[test]
+    fn test_html_and_decoded_html_share_the_encoding_and_check() {
+        let latin1 = response(
+            Some("text/html; charset=iso-8859-1"),
+            b"<p>Les Mis\xe9rables</p>".to_vec(),
+        );
+        assert_eq!(text_of(&latin1.html().unwrap(), "p"), "Les Misérables");
+        assert_eq!(
+            text_of(&latin1.decoded_html(None).unwrap(), "p"),
+            "Les Misérables"
+        );
+
+        let pdf = response(Some("text/html"), b"%PDF-1.7\n".to_vec());
+        assert_eq!(
+            pdf.decoded_html(None).unwrap_err(),
+            NotHtml::Signature("PDF document")
+        );
+    }
+}
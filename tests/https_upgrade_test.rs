@@ -0,0 +1,123 @@
+use bytes::Bytes;
+use reqwest::StatusCode;
+use spider_lib::middleware::https_upgrade::UPGRADED_FROM_KEY;
+use spider_lib::prelude::*;
+use spider_util::http_client::HttpClient;
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client whose HTTPS probes succeed only for `secure.example`.
+    #[derive(Default)]
+    struct ProbeClient {
+        probes: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl HttpClient for ProbeClient {
+        async fn get_text(
+            &self,
+            url: &str,
+            _timeout: Duration,
+        ) -> Result<(StatusCode, Bytes), SpiderError> {
+            self.probes.lock().unwrap().push(url.to_string());
+            if url.starts_with("https://secure.example/") {
+                Ok((StatusCode::NOT_FOUND, Bytes::new()))
+            } else {
+                Err(SpiderError::GeneralError("connection refused".into()))
+            }
+        }
+    }
+
+    async fn process(
+        middleware: &mut HttpsUpgradeMiddleware,
+        client: &ProbeClient,
+        url: &str,
+    ) -> Option<Request> {
+        let request = Request::new(Url::parse(url).unwrap());
+        match middleware.process_request(client, request).await.unwrap() {
+            MiddlewareAction::Continue(request) => Some(request),
+            MiddlewareAction::Drop => None,
+            _ => panic!("unexpected middleware action"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_requests_are_upgraded() {
+        let client = ProbeClient::default();
+        let mut middleware = HttpsUpgradeMiddleware::new();
+
+        let request = process(&mut middleware, &client, "http://plain.example:80/a?b=1")
+            .await
+            .unwrap();
+        assert_eq!(request.url.as_str(), "https://plain.example/a?b=1");
+        assert_eq!(
+            request
+                .meta
+                .get(UPGRADED_FROM_KEY)
+                .map(|value| value.clone()),
+            Some("http://plain.example/a?b=1".into())
+        );
+
+        let request = process(&mut middleware, &client, "http://plain.example:8080/a")
+            .await
+            .unwrap();
+        assert_eq!(request.url.as_str(), "http://plain.example:8080/a");
+        assert!(client.probes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upgraded_duplicates_are_dropped() {
+        let client = ProbeClient::default();
+        let mut middleware = HttpsUpgradeMiddleware::new();
+
+        assert!(
+            process(&mut middleware, &client, "https://secure.example/page")
+                .await
+                .is_some()
+        );
+        assert!(
+            process(&mut middleware, &client, "http://secure.example/page")
+                .await
+                .is_none()
+        );
+        assert!(
+            process(&mut middleware, &client, "http://secure.example/other")
+                .await
+                .is_some()
+        );
+        assert!(
+            process(&mut middleware, &client, "http://secure.example/other")
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_keeps_http_for_hosts_without_https() {
+        let client = ProbeClient::default();
+        let mut middleware = HttpsUpgradeMiddleware::new().fallback_to_http(true);
+
+        let secure = process(&mut middleware, &client, "http://secure.example/a").await;
+        assert_eq!(secure.unwrap().url.scheme(), "https");
+
+        for path in ["a", "b"] {
+            let url = format!("http://plain.example/{path}");
+            let plain = process(&mut middleware, &client, &url).await.unwrap();
+            assert_eq!(plain.url.as_str(), url);
+            assert!(plain.meta.get(UPGRADED_FROM_KEY).is_none());
+        }
+
+        assert_eq!(
+            *client.probes.lock().unwrap(),
+            [
+                "https://secure.example/robots.txt",
+                "https://plain.example/robots.txt"
+            ]
+        );
+    }
+}
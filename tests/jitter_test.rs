@@ -0,0 +1,72 @@
+use spider_lib::prelude::*;
+use spider_middleware::rate_limit::RateLimiter;
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn drawn_delays(limiter: &JitterLimiter, count: usize) -> Vec<Duration> {
+        let mut delays = Vec::new();
+        for _ in 0..count {
+            delays.push(limiter.current_delay().await);
+            limiter.acquire().await;
+        }
+        delays
+    }
+
+    #[tokio::test]
+    async fn test_seeded_delays_are_reproducible() {
+        let base = Duration::from_millis(10);
+        let first = drawn_delays(&JitterLimiter::with_seed(base, 0.5, 7), 8).await;
+        let second = drawn_delays(&JitterLimiter::with_seed(base, 0.5, 7), 8).await;
+        let other = drawn_delays(&JitterLimiter::with_seed(base, 0.5, 8), 8).await;
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        for delay in &first {
+            assert!(
+                (Duration::from_millis(5)..=Duration::from_millis(15)).contains(delay),
+                "{delay:?}"
+            );
+        }
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[tokio::test]
+    async fn test_zero_spread_is_the_base_delay() {
+        let limiter = JitterLimiter::with_seed(Duration::from_millis(10), 0.0, 1);
+        assert_eq!(
+            drawn_delays(&limiter, 3).await,
+            vec![Duration::from_millis(10); 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_middleware_waits_jittered_delays() {
+        let mut rate_limit =
+            RateLimitMiddleware::with_seeded_jitter(Duration::from_millis(40), 0.5, 3);
+        let mut sent = Vec::new();
+        for page in 0..4 {
+            let url = Url::parse(&format!("https://example.com/{page}")).unwrap();
+            let action = Middleware::<()>::process_request(&mut rate_limit, &(), Request::new(url))
+                .await
+                .unwrap();
+            assert!(matches!(action, MiddlewareAction::Continue(_)));
+            sent.push(Instant::now());
+        }
+
+        for pair in sent.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap >= Duration::from_millis(19), "gap {gap:?}");
+            assert!(gap < Duration::from_millis(200), "gap {gap:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "jitter spread must be between 0 and 1")]
+    fn test_spread_is_validated() {
+        RateLimitMiddleware::with_jitter(Duration::from_secs(1), 1.5);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_all_expands_wildcards() {
+        let body = json!({
+            "data": [
+                {"name": "a", "tags": ["x", "y"]},
+                {"name": "b", "tags": []},
+                {"title": "c"}
+            ],
+            "meta": {"a/b": 1}
+        });
+
+        assert_eq!(select_all(&body, "/data/*/name"), vec!["a", "b"]);
+        assert_eq!(select_all(&body, "data.*.tags.*"), vec!["x", "y"]);
+        assert_eq!(select_all(&body, "/data/1/name"), vec!["b"]);
+        assert_eq!(select_all(&body, "/meta/a~1b"), vec![1]);
+        assert_eq!(select_all(&body, "/meta/*"), vec![1]);
+        assert!(select_all(&body, "/data/9/name").is_empty());
+        assert!(select_all(&body, "/data/*/name/*").is_empty());
+        assert_eq!(select_all(&body, "").len(), 1);
+    }
+
+    #[test]
+    fn test_json_links_resolves_and_skips_non_urls() {
+        let base = Url::parse("https://api.example.com/v1/items?page=1").unwrap();
+        let body = json!({
+            "data": [
+                {"url": "/v1/items/1"},
+                {"url": "https://cdn.example.com/items/2"},
+                {"url": "not a url"},
+                {"url": 42},
+                {"url": "mailto:team@example.com"},
+                {"url": "/v1/items/1"}
+            ],
+            "next": "?page=2",
+            "prev": null
+        });
+
+        let urls: Vec<String> = json_links(&body, &base, &["/data/*/url", "/next", "/prev"])
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://api.example.com/v1/items/1",
+                "https://cdn.example.com/items/2",
+                "https://api.example.com/v1/items?page=2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_path_descendants_of_large_documents() {
+        let items: Vec<Value> = (0..50_000).map(|id| json!({"id": id})).collect();
+        let body = json!({"items": items, "nested": [[{"id": -1}]]});
+
+        let ids = json_path(&body, "$..id").unwrap();
+        assert_eq!(ids.len(), 50_001);
+        assert_eq!(ids[0], 0);
+        assert_eq!(ids[49_999], 49_999);
+        assert_eq!(ids[50_000], -1);
+    }
+
+    fn response(content_type: &'static str, body: Vec<u8>) -> Response {
+        let request = Request::new(Url::parse("https://api.example.com/v1/items").unwrap());
+        MockResponse::new(200)
+            .header("content-type", content_type)
+            .body(body)
+            .into_response(request)
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        name: String,
+        price: u32,
+    }
+
+    #[test]
+    fn test_json_deserializes_the_body() {
+        let mut body = b"\xEF\xBB\xBF".to_vec();
+        body.extend_from_slice(br#"{"name": "lamp", "price": 12}"#);
+        let response = response("application/json", body);
+
+        let item: Item = ResponseExt::json(&response).unwrap();
+        assert_eq!(
+            item,
+            Item {
+                name: "lamp".into(),
+                price: 12
+            }
+        );
+        assert_eq!(response.json_value().unwrap()["price"], 12);
+    }
+
+    #[test]
+    fn test_json_respects_the_content_type_charset() {
+        // "café" in ISO-8859-1.
+        let body = b"{\"name\": \"caf\xE9\", \"price\": 3}".to_vec();
+        let latin1 = response("application/json; charset=iso-8859-1", body);
+        assert_eq!(latin1.json_value().unwrap()["name"], "café");
+
+        let mut body = vec![0xFF, 0xFE];
+        for unit in r#"["é"]"#.encode_utf16() {
+            body.extend_from_slice(&unit.to_le_bytes());
+        }
+        let utf16 = response("application/json", body);
+        assert_eq!(utf16.json_value().unwrap()[0], "é");
+    }
+
+    #[test]
+    fn test_json_errors_name_the_problem() {
+        let html = response("application/json", b"<html>oops</html>".to_vec());
+        let error = html.json_value().unwrap_err();
+        assert!(matches!(error, NotJson::Syntax(_)));
+        assert!(error.to_string().starts_with("response is not JSON"));
+        assert!(matches!(
+            SpiderError::from(error),
+            SpiderError::JsonError(_)
+        ));
+
+        let partial = response("application/json", br#"{"name": "lamp"}"#.to_vec());
+        assert!(matches!(
+            ResponseExt::json::<Item>(&partial),
+            Err(NotJson::Data(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_path_selects_members_indices_and_descendants() {
+        let body = json!({
+            "data": {
+                "items": [
+                    {"name": "a", "price": 1},
+                    {"name": "b", "price": 2},
+                    {"name": "c", "tags": {"name": "nested"}}
+                ],
+                "total": 3
+            },
+            "odd key": true
+        });
+
+        let path = |expr| json_path(&body, expr).unwrap();
+        assert_eq!(path("$.data.items[*].name"), vec!["a", "b", "c"]);
+        assert_eq!(path("$['data']['items'][-1].name"), vec!["c"]);
+        assert_eq!(path("$.data.items[0,2].name"), vec!["a", "c"]);
+        assert_eq!(path("$..price"), vec![1, 2]);
+        assert_eq!(path("$.data..name"), vec!["a", "b", "c", "nested"]);
+        assert_eq!(path("$[\"odd key\"]"), vec![true]);
+        assert_eq!(path("$.data.total"), vec![3]);
+        assert_eq!(path("$").len(), 1);
+        assert!(path("$.data.items[7].name").is_empty());
+        assert!(path("$.data.total.*").is_empty());
+
+        for invalid in ["data.items", "$.", "$.data[", "$.data[a]", "$data"] {
+            assert!(
+                matches!(json_path(&body, invalid), Err(JsonPathError::Syntax(_))),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::keep_alive::KEEP_ALIVE_KEY;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    /// Parses slowly, and only the slow pages lead further: `/` to `/1`, `/1` to `/2` and
+    /// so on up to `/depth`.
+    pub struct SlowChainSpider {
+        start: Url,
+        depth: usize,
+        parsed: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Spider for SlowChainSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            self.parsed
+                .lock()
+                .unwrap()
+                .push(response.url.path().to_string());
+
+            let mut output = ParseOutput::new();
+            let page: usize = response.url.path()[1..].parse().unwrap_or(0);
+            if page < self.depth {
+                output.add_request(Request::new(response.url.join(&format!("/{}", page + 1))?));
+            }
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_responses_yield_requests_before_shutdown() {
+        let server = TestServer::start(|_| TestResponse::html("<p>page</p>")).await;
+        let parsed = Arc::new(Mutex::new(Vec::new()));
+        let keep_alive = KeepAlive::with_quiet_period(Duration::from_millis(200));
+
+        let started = Instant::now();
+        let crawler = CrawlerBuilder::new(keep_alive.wrap(SlowChainSpider {
+            start: server.url("/"),
+            depth: 4,
+            parsed: parsed.clone(),
+        }))
+        .keep_alive(&keep_alive)
+        .build()
+        .await
+        .unwrap();
+        let stats = crawler.get_stats();
+        crawler.start_crawl().await.unwrap();
+
+        let mut parsed = parsed.lock().unwrap().clone();
+        parsed.sort();
+        assert_eq!(parsed, ["/", "/1", "/2", "/3", "/4"]);
+        assert_eq!(keep_alive.pending(), 0);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let snapshot = stats.snapshot().excluding(keep_alive.traffic());
+        assert_eq!(snapshot.requests.retried, 0);
+        assert_eq!(snapshot.requests.dropped, 0);
+    }
+
+    fn response(url: &Url) -> Response {
+        MockResponse::new(200)
+            .body("page")
+            .into_response(Request::new(url.clone()))
+    }
+
+    async fn slot_action(
+        middleware: &mut KeepAliveMiddleware,
+        url: &Url,
+    ) -> MiddlewareAction<Request> {
+        let mut slot = url.clone();
+        slot.set_fragment(Some("keep-alive-1"));
+        let request = Request::new(slot).with_meta(KEEP_ALIVE_KEY, true.into());
+        Middleware::<()>::process_request(middleware, &(), request)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_request_circles_until_settled() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let keep_alive = KeepAlive::with_quiet_period(Duration::from_millis(50));
+        let mut middleware = keep_alive.middleware();
+
+        let passed = Middleware::<()>::process_response(&mut middleware, response(&url))
+            .await
+            .unwrap();
+        assert!(matches!(passed, MiddlewareAction::Continue(_)));
+        assert_eq!(keep_alive.pending(), 1);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(matches!(
+            slot_action(&mut middleware, &url).await,
+            MiddlewareAction::Retry(..)
+        ));
+
+        let spider = keep_alive.wrap(SlowChainSpider {
+            start: url.clone(),
+            depth: 0,
+            parsed: Arc::default(),
+        });
+        spider.parse(response(&url), &()).await.unwrap();
+        assert_eq!(keep_alive.pending(), 0);
+        assert!(matches!(
+            slot_action(&mut middleware, &url).await,
+            MiddlewareAction::Retry(..)
+        ));
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(matches!(
+            slot_action(&mut middleware, &url).await,
+            MiddlewareAction::Drop
+        ));
+        assert_eq!(keep_alive.traffic().retried(), 2);
+        assert_eq!(keep_alive.traffic().dropped(), 1);
+    }
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    #[derive(Default)]
+    pub struct PagesState {
+        pages: AtomicUsize,
+        events: Mutex<Vec<String>>,
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+        fail_to_open: bool,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = PageItem;
+        type State = PagesState;
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            state.pages.fetch_add(1, Ordering::SeqCst);
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                output.add_request(Request::new(response.url.join("/next")?));
+            }
+            Ok(output)
+        }
+    }
+
+    #[async_trait]
+    impl LifecycleSpider for PagesSpider {
+        async fn opened(&self, state: &Self::State) -> Result<(), SpiderError> {
+            state.events.lock().unwrap().push("opened".into());
+            if self.fail_to_open {
+                return Err(SpiderError::GeneralError("no database".into()));
+            }
+            Ok(())
+        }
+
+        async fn closed(&self, state: &Self::State, reason: &CloseReason) {
+            let pages = state.pages.load(Ordering::SeqCst);
+            state
+                .events
+                .lock()
+                .unwrap()
+                .push(format!("closed: {reason} after {pages} pages"));
+        }
+    }
+
+    async fn run(
+        server: &TestServer,
+        fail_to_open: bool,
+    ) -> (Lifecycle<PagesSpider>, CrawlSummary) {
+        let spider = PagesSpider {
+            start: server.url("/"),
+            fail_to_open,
+        }
+        .with_lifecycle();
+        let control = CrawlControl::new();
+        let crawler = CrawlerBuilder::new(spider.clone())
+            .add_middleware(control.middleware())
+            .build()
+            .await
+            .unwrap();
+        let summary = control.run_with_lifecycle(crawler, &spider).await;
+        (spider, summary)
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_around_the_crawl() {
+        let server = TestServer::start(|_| TestResponse::html("<p>page</p>")).await;
+
+        let (spider, summary) = run(&server, false).await;
+        assert!(matches!(summary.reason, CloseReason::Finished));
+        assert_eq!(spider.state().pages.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *spider.state().events.lock().unwrap(),
+            vec!["opened", "closed: finished after 2 pages"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_open_skips_the_crawl() {
+        let server = TestServer::start(|_| TestResponse::html("<p>page</p>")).await;
+
+        let (spider, summary) = run(&server, true).await;
+        assert!(matches!(summary.reason, CloseReason::Error(_)));
+        assert_eq!(summary.stats.requests_sent.load(Ordering::SeqCst), 0);
+        let events = spider.state().events.lock().unwrap().clone();
+        assert_eq!(events.len(), 2);
+        assert!(events[1].starts_with("closed: error:"), "{events:?}");
+        assert!(events[1].ends_with("after 0 pages"), "{events:?}");
+    }
+}
@@ -0,0 +1,173 @@
+use scraper::Html;
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://books.example.com/catalogue/page-2.html").unwrap()
+    }
+
+    #[test]
+    fn test_follow_strings_and_elements() {
+        let base = base();
+        assert_eq!(
+            follow(&base, "page-3.html").unwrap().url.as_str(),
+            "https://books.example.com/catalogue/page-3.html"
+        );
+        assert_eq!(
+            follow(&base, &" /index.html ".to_string())
+                .unwrap()
+                .url
+                .as_str(),
+            "https://books.example.com/index.html"
+        );
+        assert!(follow(&base, "https://[invalid").is_err());
+
+        let html = Html::parse_document(concat!(
+            r#"<a id="next" href="../page-3.html">next</a>"#,
+            r#"<img src="//cdn.example.com/a.png"><a id="empty">x</a>"#,
+        ));
+        let link = |selector: &str| {
+            html.select(&selector.to_selector().unwrap())
+                .next()
+                .unwrap()
+        };
+        assert_eq!(
+            follow(&base, &link("#next")).unwrap().url.as_str(),
+            "https://books.example.com/page-3.html"
+        );
+        assert_eq!(
+            follow(&base, &link("img")).unwrap().url.as_str(),
+            "https://cdn.example.com/a.png"
+        );
+        let err = follow(&base, &link("#empty")).unwrap_err();
+        assert!(err.to_string().contains("without href or src"), "{err}");
+    }
+
+    #[test]
+    fn test_follow_sets_the_referer() {
+        let referer = |request: &Request| {
+            request
+                .headers
+                .get("Referer")
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+        let page = Url::parse("https://user:pw@books.example.com/catalogue/?p=2#top").unwrap();
+        assert_eq!(
+            referer(&follow(&page, "page-3.html").unwrap()).as_deref(),
+            Some("https://books.example.com/catalogue/?p=2")
+        );
+        assert_eq!(
+            referer(&follow(&page, "http://plain.example.com/").unwrap()),
+            None
+        );
+
+        let html = Html::parse_document(r#"<a href="a.html">A</a>"#);
+        let requests = follow_links(&html, &base(), "a", "href").unwrap();
+        assert_eq!(referer(&requests[0]).as_deref(), Some(base().as_str()));
+    }
+
+    #[test]
+    fn test_follow_links_skips_and_dedupes() {
+        let html = Html::parse_document(
+            r#"<ul>
+                <li><a href="a.html">A</a></li>
+                <li><a href="/catalogue/a.html">A again</a></li>
+                <li><a href="mailto:shop@example.com">Mail</a></li>
+                <li><a href="javascript:void(0)">JS</a></li>
+                <li><a href="http://[::1">Broken</a></li>
+                <li><a>No href</a></li>
+                <li><a href="https://other.example.org/b">B</a></li>
+            </ul>"#,
+        );
+
+        let urls: Vec<String> = follow_links(&html, &base(), "li a", "href")
+            .unwrap()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://books.example.com/catalogue/a.html",
+                "https://other.example.org/b"
+            ]
+        );
+        assert!(follow_links(&html, &base(), "li >>> a", "href").is_err());
+    }
+
+    #[test]
+    fn test_link_extractor_rules() {
+        let html = Html::parse_document(
+            r#"<nav><a href="/catalogue/category/travel/index.html">Travel</a></nav>
+            <article class="product">
+                <a href="book-1/index.html?utm_source=feed#top">Book 1</a>
+                <a href="book-1/index.html">Book 1 again</a>
+                <a href="/catalogue/category/poetry/index.html">Poetry</a>
+            </article>
+            <article class="product">
+                <map><area href="book-2/index.html"></map>
+                <a href="mailto:shop@example.com">Mail</a>
+            </article>
+            <footer><a href="/about.html">About</a></footer>"#,
+        );
+        let urls = |extractor: &LinkExtractor| -> Vec<String> {
+            extractor
+                .extract_from(&html, &base())
+                .unwrap()
+                .into_iter()
+                .map(|request| request.url.to_string())
+                .collect()
+        };
+
+        assert_eq!(urls(&LinkExtractor::new()).len(), 5);
+
+        let books = LinkExtractor::new()
+            .allow(r"/catalogue/")
+            .unwrap()
+            .deny(r"/category/")
+            .unwrap();
+        assert_eq!(
+            urls(&books),
+            vec![
+                "https://books.example.com/catalogue/book-1/index.html",
+                "https://books.example.com/catalogue/book-2/index.html",
+            ]
+        );
+
+        let products = LinkExtractor::new()
+            .restrict_css("article.product")
+            .canonicalize(false);
+        assert_eq!(
+            urls(&products),
+            vec![
+                "https://books.example.com/catalogue/book-1/index.html?utm_source=feed#top",
+                "https://books.example.com/catalogue/book-1/index.html",
+                "https://books.example.com/catalogue/category/poetry/index.html",
+                "https://books.example.com/catalogue/book-2/index.html",
+            ]
+        );
+
+        let nav_and_footer = LinkExtractor::new()
+            .restrict_css("nav a")
+            .restrict_css("footer, footer a");
+        assert_eq!(
+            urls(&nav_and_footer),
+            vec![
+                "https://books.example.com/catalogue/category/travel/index.html",
+                "https://books.example.com/about.html",
+            ]
+        );
+
+        assert!(LinkExtractor::new().allow("(unclosed").is_err());
+        assert!(
+            LinkExtractor::new()
+                .restrict_css("article >>> a")
+                .extract_from(&html, &base())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,134 @@
+#![cfg(feature = "metrics-prometheus")]
+
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                for page in 1..=2 {
+                    output.add_request(Request::new(response.url.join(&format!("/page/{page}"))?));
+                }
+                output.add_request(Request::new(response.url.join("/missing")?));
+            }
+            Ok(output)
+        }
+    }
+
+    /// Returns the value of the unlabelled sample of `name` in `text`.
+    fn sample(text: &str, name: &str) -> Option<f64> {
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+    }
+
+    #[tokio::test]
+    async fn test_serves_live_crawl_metrics() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/missing" => TestResponse::status(404),
+            _ => TestResponse::html("<html><body>page</body></html>"),
+        })
+        .await;
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .build()
+        .await
+        .unwrap();
+        let metrics = PrometheusExporter::new(crawler.get_stats())
+            .serve("127.0.0.1:0")
+            .await
+            .unwrap();
+        let endpoint = format!("http://{}/metrics", metrics.local_addr());
+
+        let before = reqwest::get(&endpoint).await.unwrap();
+        assert_eq!(before.status(), 200);
+        assert!(
+            before.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain; version=0.0.4")
+        );
+        let before = before.text().await.unwrap();
+        assert_eq!(sample(&before, "spider_requests_sent_total"), Some(0.0));
+        assert!(before.contains("# TYPE spider_requests_in_flight gauge"));
+
+        crawler.start_crawl().await.unwrap();
+
+        let after = reqwest::get(&endpoint).await.unwrap().text().await.unwrap();
+        assert_eq!(sample(&after, "spider_requests_sent_total"), Some(4.0));
+        assert_eq!(sample(&after, "spider_requests_succeeded_total"), Some(4.0));
+        assert_eq!(sample(&after, "spider_requests_failed_total"), Some(0.0));
+        assert_eq!(sample(&after, "spider_items_scraped_total"), Some(4.0));
+        assert_eq!(sample(&after, "spider_requests_queued"), Some(0.0));
+        assert_eq!(sample(&after, "spider_requests_in_flight"), Some(0.0));
+        assert!(after.contains("spider_responses_by_status_total{status=\"200\"} 3"));
+        assert!(after.contains("spider_responses_by_status_total{status=\"404\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn test_serves_only_the_configured_path() {
+        let stats = CrawlerBuilder::new(PagesSpider {
+            start: Url::parse("http://127.0.0.1:9/").unwrap(),
+        })
+        .build()
+        .await
+        .unwrap()
+        .get_stats();
+        let metrics = PrometheusExporter::new(stats)
+            .path("/stats")
+            .serve("127.0.0.1:0")
+            .await
+            .unwrap();
+        let base = format!("http://{}", metrics.local_addr());
+
+        let found = reqwest::get(format!("{base}/stats?format=text"))
+            .await
+            .unwrap();
+        assert_eq!(found.status(), 200);
+        let missing = reqwest::get(format!("{base}/metrics")).await.unwrap();
+        assert_eq!(missing.status(), 404);
+        let posted = reqwest::Client::new()
+            .post(format!("{base}/stats"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(posted.status(), 405);
+
+        drop(metrics);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(reqwest::get(format!("{base}/stats")).await.is_err());
+    }
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "pipeline-mongo")]
+
+//! Runs against the deployment in `SPIDER_TEST_MONGO_URL`, and is skipped without it.
+
+use mongodb::Client;
+use mongodb::bson::{Document, doc};
+use spider_lib::prelude::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Product {
+        pub url: Option<String>,
+        pub name: String,
+        pub stock: i64,
+    }
+
+    fn product(url: Option<&str>, name: &str, stock: i64) -> Product {
+        Product {
+            url: url.map(Into::into),
+            name: name.into(),
+            stock,
+        }
+    }
+
+    fn mongo_url() -> Option<String> {
+        let url = std::env::var("SPIDER_TEST_MONGO_URL").ok();
+        if url.is_none() {
+            eprintln!("SPIDER_TEST_MONGO_URL is not set, skipping");
+        }
+        url
+    }
+
+    #[tokio::test]
+    async fn test_items_are_upserted_in_batches() {
+        let Some(url) = mongo_url() else {
+            return;
+        };
+        let client = Client::with_uri_str(&url).await.unwrap();
+        let products = client
+            .database("spider_test")
+            .collection::<Document>("products");
+        products.drop().await.unwrap();
+
+        let writer = MongoWriterPipeline::new(&url, "spider_test", "products")
+            .upsert_key("url")
+            .batch_size(2)
+            .write_concern(WriteConcern::majority());
+        for item in [
+            product(Some("https://shop.example/a"), "Lamp", 3),
+            product(Some("https://shop.example/b"), "Desk", 1),
+            product(Some("https://shop.example/a"), "Lamp v2", 2),
+            product(None, "Unlisted", 4),
+            product(Some("https://shop.example/b"), "Desk v2", 5),
+        ] {
+            assert!(writer.process_item(item).await.unwrap().is_some());
+        }
+        writer.close().await.unwrap();
+
+        let mut names = Vec::new();
+        let mut cursor = products
+            .find(doc! {})
+            .sort(doc! { "name": 1 })
+            .await
+            .unwrap();
+        while cursor.advance().await.unwrap() {
+            let document = cursor.deserialize_current().unwrap();
+            names.push((
+                document.get_str("name").unwrap().to_string(),
+                document.get_i64("stock").unwrap(),
+            ));
+        }
+        assert_eq!(
+            names,
+            [
+                ("Desk v2".to_string(), 5),
+                ("Lamp v2".to_string(), 2),
+                ("Unlisted".to_string(), 4),
+            ]
+        );
+        products.drop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_items_are_written_on_close() {
+        let Some(url) = mongo_url() else {
+            return;
+        };
+        let client = Client::with_uri_str(&url).await.unwrap();
+        let writer = MongoWriterPipeline::from_client(client.clone(), "spider_test", "pending");
+        let pending = client
+            .database("spider_test")
+            .collection::<Document>("pending");
+        pending.drop().await.unwrap();
+
+        writer.process_item(product(None, "Lamp", 1)).await.unwrap();
+        assert_eq!(pending.count_documents(doc! {}).await.unwrap(), 0);
+        writer.close().await.unwrap();
+        assert_eq!(pending.count_documents(doc! {}).await.unwrap(), 1);
+        pending.drop().await.unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use common::{TestRequest, TestResponse, TestServer};
+use spider_lib::middleware::offsite::OFFSITE_HOPS_KEY;
+use spider_lib::prelude::*;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    /// Follows every link and records the pages it parsed as `host/path`.
+    pub struct LinkSpider {
+        start: Url,
+        parsed: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Spider for LinkSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            self.parsed.lock().unwrap().push(format!(
+                "{}{}",
+                response.url.host_str().unwrap_or(""),
+                response.url.path()
+            ));
+            let mut output = ParseOutput::new();
+            let html = response.to_html()?;
+            for link in html.select(&"a".to_selector()?) {
+                if let Some(href) = link.value().attr("href") {
+                    output.add_request(Request::new(response.url.join(href)?));
+                }
+            }
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    /// The seed on `127.0.0.1` links to a page of its own and to an external page on
+    /// `localhost`, which links onward and back to the seed's site.
+    fn site(request: &TestRequest) -> TestResponse {
+        let port = request
+            .headers
+            .get("host")
+            .and_then(|host| host.rsplit(':').next())
+            .unwrap_or("");
+        match request.path.as_str() {
+            "/" => TestResponse::html(&format!(
+                r#"<a href="/about">About</a>
+                   <a href="http://localhost:{port}/external">External</a>"#
+            )),
+            "/external" => TestResponse::html(&format!(
+                r#"<a href="/onward">Onward</a>
+                   <a href="http://127.0.0.1:{port}/back">Back</a>"#
+            )),
+            _ => TestResponse::html("<p>leaf</p>"),
+        }
+    }
+
+    async fn crawl(offsite: OffsiteMiddleware) -> Vec<String> {
+        let server = TestServer::start(site).await;
+        let parsed = Arc::new(Mutex::new(Vec::new()));
+        let crawler = CrawlerBuilder::new(offsite.wrap(LinkSpider {
+            start: server.url("/"),
+            parsed: parsed.clone(),
+        }))
+        .add_middleware(offsite)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let mut parsed = parsed.lock().unwrap().clone();
+        parsed.sort();
+        parsed
+    }
+
+    #[tokio::test]
+    async fn test_external_depth_follows_outbound_links_once() {
+        let offsite = OffsiteMiddleware::new(&["127.0.0.1"]).mode(OffsiteMode::ExternalDepth(1));
+        let counter = offsite.clone();
+
+        let parsed = crawl(offsite).await;
+        assert_eq!(
+            parsed,
+            vec![
+                "127.0.0.1/",
+                "127.0.0.1/about",
+                "127.0.0.1/back",
+                "localhost/external",
+            ]
+        );
+        assert_eq!(counter.dropped(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_drop_mode_stays_on_the_allowed_domains() {
+        let parsed = crawl(OffsiteMiddleware::new(&["127.0.0.1"])).await;
+        assert_eq!(parsed, vec!["127.0.0.1/", "127.0.0.1/about"]);
+    }
+
+    async fn passes(middleware: &mut OffsiteMiddleware, request: Request) -> bool {
+        matches!(
+            Middleware::<()>::process_request(middleware, &(), request)
+                .await
+                .unwrap(),
+            MiddlewareAction::Continue(_)
+        )
+    }
+
+    #[tokio::test]
+    async fn test_unmarked_requests_count_as_one_hop() {
+        let mut middleware =
+            OffsiteMiddleware::new(&["example.com"]).mode(OffsiteMode::ExternalDepth(1));
+        let url = Url::parse("https://other.org/").unwrap();
+
+        assert!(passes(&mut middleware, Request::new(url.clone())).await);
+        let marked = Request::new(url.clone()).with_meta(OFFSITE_HOPS_KEY, 2.into());
+        assert!(!passes(&mut middleware, marked).await);
+        let onsite = Request::new(Url::parse("https://www.example.com/").unwrap());
+        assert!(passes(&mut middleware, onsite).await);
+
+        let mut middleware = middleware.mode(OffsiteMode::Drop);
+        assert!(!passes(&mut middleware, Request::new(url)).await);
+    }
+
+    #[test]
+    fn test_subdomains_are_onsite() {
+        let offsite = OffsiteMiddleware::new(&["Example.com"]);
+        assert!(offsite.is_onsite(&Url::parse("https://example.com/").unwrap()));
+        assert!(offsite.is_onsite(&Url::parse("https://docs.example.com/").unwrap()));
+        assert!(!offsite.is_onsite(&Url::parse("https://notexample.com/").unwrap()));
+    }
+
+    #[test]
+    fn test_wildcards_allow_only_subdomains() {
+        let offsite = OffsiteMiddleware::new(&["*.cdn.example.org", "example.com"]);
+        assert!(offsite.is_onsite(&Url::parse("https://a.cdn.example.org/").unwrap()));
+        assert!(!offsite.is_onsite(&Url::parse("https://cdn.example.org/").unwrap()));
+        assert!(!offsite.is_onsite(&Url::parse("https://www.example.org/").unwrap()));
+        assert!(offsite.is_onsite(&Url::parse("https://example.com/").unwrap()));
+    }
+
+    #[test]
+    fn test_for_spider_allows_the_start_sites() {
+        let spider = LinkSpider {
+            start: Url::parse("https://www.example.co.uk/start").unwrap(),
+            parsed: Default::default(),
+        };
+        let offsite = OffsiteMiddleware::for_spider(&spider).unwrap();
+        assert!(offsite.is_onsite(&Url::parse("https://shop.example.co.uk/").unwrap()));
+        assert!(!offsite.is_onsite(&Url::parse("https://other.co.uk/").unwrap()));
+
+        let spider = LinkSpider {
+            start: Url::parse("http://127.0.0.1:8080/").unwrap(),
+            parsed: Default::default(),
+        };
+        let offsite = OffsiteMiddleware::for_spider(&spider).unwrap();
+        assert!(offsite.is_onsite(&Url::parse("http://127.0.0.1:9090/x").unwrap()));
+        assert!(!offsite.is_onsite(&Url::parse("http://127.0.0.2/").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_allowed_domains_through_the_builder() {
+        let server = TestServer::start(site).await;
+        let parsed = Arc::new(Mutex::new(Vec::new()));
+        let crawler = CrawlerBuilder::new(LinkSpider {
+            start: server.url("/"),
+            parsed: parsed.clone(),
+        })
+        .allowed_domains(&["127.0.0.1"])
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let mut parsed = parsed.lock().unwrap().clone();
+        parsed.sort();
+        assert_eq!(parsed, vec!["127.0.0.1/", "127.0.0.1/about"]);
+    }
+}
@@ -0,0 +1,175 @@
+use reqwest::header::{HeaderValue, LINK};
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(url: &str, body: &str) -> Response {
+        let request = Request::new(Url::parse(url).unwrap());
+        MockResponse::new(200).body(body).into_response(request)
+    }
+
+    fn next(pagination: &Pagination, url: &str, body: &str) -> Option<String> {
+        pagination
+            .next_url(&response(url, body))
+            .map(|url| url.to_string())
+    }
+
+    const PAGE: Pagination = Pagination::PageNumber {
+        param: "page",
+        results: "items",
+    };
+    const OFFSET: Pagination = Pagination::Offset {
+        param: "offset",
+        step: 20,
+        results: "/data/items",
+    };
+    const CURSOR: Pagination = Pagination::Cursor {
+        field: "meta.next",
+        param: "cursor",
+    };
+
+    #[test]
+    fn test_page_number() {
+        let body = r#"{"items": [1, 2]}"#;
+        assert_eq!(
+            next(&PAGE, "https://api.example.com/list?q=x", body).as_deref(),
+            Some("https://api.example.com/list?q=x&page=2")
+        );
+        assert_eq!(
+            next(&PAGE, "https://api.example.com/list?page=2&q=x", body).as_deref(),
+            Some("https://api.example.com/list?page=3&q=x")
+        );
+    }
+
+    #[test]
+    fn test_page_number_stops_without_results() {
+        let url = "https://api.example.com/list?page=4";
+        assert_eq!(next(&PAGE, url, r#"{"items": []}"#), None);
+        assert_eq!(next(&PAGE, url, r#"{"other": [1]}"#), None);
+        assert_eq!(next(&PAGE, url, "<html>not json</html>"), None);
+    }
+
+    #[test]
+    fn test_offset() {
+        let body = r#"{"data": {"items": [1]}}"#;
+        assert_eq!(
+            next(&OFFSET, "https://api.example.com/list", body).as_deref(),
+            Some("https://api.example.com/list?offset=20")
+        );
+        assert_eq!(
+            next(&OFFSET, "https://api.example.com/list?offset=40", body).as_deref(),
+            Some("https://api.example.com/list?offset=60")
+        );
+        assert_eq!(
+            next(
+                &OFFSET,
+                "https://api.example.com/list?offset=0",
+                r#"{"data": {"items": []}}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_counters_stop_on_overflow() {
+        let body = r#"{"items": [1], "data": {"items": [1]}}"#;
+        let max = format!("https://api.example.com/list?page={0}&offset={0}", u64::MAX);
+        assert_eq!(next(&PAGE, &max, body), None);
+        assert_eq!(next(&OFFSET, &max, body), None);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let url = "https://api.example.com/list?cursor=a";
+        assert_eq!(
+            next(&CURSOR, url, r#"{"meta": {"next": "b2"}}"#).as_deref(),
+            Some("https://api.example.com/list?cursor=b2")
+        );
+        assert_eq!(
+            next(&CURSOR, url, r#"{"meta": {"next": 42}}"#).as_deref(),
+            Some("https://api.example.com/list?cursor=42")
+        );
+        assert_eq!(
+            next(
+                &CURSOR,
+                url,
+                r#"{"meta": {"next": "https://cdn.example.com/list?c=b"}}"#
+            )
+            .as_deref(),
+            Some("https://cdn.example.com/list?c=b")
+        );
+    }
+
+    #[test]
+    fn test_cursor_starting_with_slash_is_a_value() {
+        let url = "https://api.example.com/list";
+        assert_eq!(
+            next(&CURSOR, url, r#"{"meta": {"next": "/abc+def"}}"#).as_deref(),
+            Some("https://api.example.com/list?cursor=%2Fabc%2Bdef")
+        );
+    }
+
+    #[test]
+    fn test_cursor_stops_when_missing_or_empty() {
+        let url = "https://api.example.com/list?cursor=a";
+        for body in [
+            r#"{"meta": {}}"#,
+            r#"{"meta": {"next": null}}"#,
+            r#"{"meta": {"next": false}}"#,
+            r#"{"meta": {"next": ""}}"#,
+        ] {
+            assert_eq!(next(&CURSOR, url, body), None, "{body}");
+        }
+    }
+
+    #[test]
+    fn test_same_url_does_not_loop() {
+        let url = "https://api.example.com/list?cursor=a";
+        assert_eq!(next(&CURSOR, url, r#"{"meta": {"next": "a"}}"#), None);
+        assert_eq!(
+            next(&CURSOR, url, &format!(r#"{{"meta": {{"next": "{url}"}}}}"#)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_link_header() {
+        let mut response = response("https://api.example.com/repos?page=1", "not json");
+        response.headers.insert(
+            LINK,
+            HeaderValue::from_static(concat!(
+                r#"<https://api.example.com/repos?page=1>; rel="first", "#,
+                r#"<https://api.example.com/repos?page=2&a=1,2>; rel="next last""#,
+            )),
+        );
+        assert_eq!(
+            Pagination::LinkHeader.next_url(&response).unwrap().as_str(),
+            "https://api.example.com/repos?page=2&a=1,2"
+        );
+
+        let mut output: ParseOutput<()> = ParseOutput::new();
+        assert!(output.paginate(&response, &Pagination::LinkHeader));
+    }
+
+    #[test]
+    fn test_link_header_stops_without_next() {
+        let mut response = response("https://api.example.com/repos?page=9", "[]");
+        assert_eq!(Pagination::LinkHeader.next_url(&response), None);
+
+        response.headers.insert(
+            LINK,
+            HeaderValue::from_static(r#"<https://api.example.com/repos?page=1>; rel="prev""#),
+        );
+        assert_eq!(Pagination::LinkHeader.next_url(&response), None);
+
+        response.headers.insert(
+            LINK,
+            HeaderValue::from_static(r#"</repos?page=9>; rel="next""#),
+        );
+        assert_eq!(Pagination::LinkHeader.next_url(&response), None);
+    }
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "pipeline-parquet")]
+
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use spider_lib::prelude::*;
+use std::fs::File;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Book {
+        pub title: String,
+        pub price: Option<f64>,
+        pub tags: Vec<String>,
+    }
+
+    fn book(title: &str, price: Option<f64>, tags: &[&str]) -> Book {
+        Book {
+            title: title.into(),
+            price,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_items_are_written_in_row_groups() {
+        let path = std::env::temp_dir().join("spider_parquet_books.parquet");
+        let pipeline = ParquetExporterPipeline::new(&path).row_group_size(2);
+        for item in [
+            book("Dune", Some(9.5), &["sf"]),
+            book("Emma", None, &[]),
+            book("Ulysses", Some(12.0), &["classic", "long"]),
+        ] {
+            assert!(pipeline.process_item(item).await.unwrap().is_some());
+        }
+        pipeline.close().await.unwrap();
+
+        let metadata = SerializedFileReader::new(File::open(&path).unwrap())
+            .unwrap()
+            .metadata()
+            .clone();
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(metadata.file_metadata().num_rows(), 3);
+
+        let batches: Vec<_> = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let schema = batches[0].schema();
+        let columns: Vec<&str> = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(columns, ["price", "tags", "title"]);
+        let nulls: usize = batches
+            .iter()
+            .map(|batch| batch.column_by_name("price").unwrap().null_count())
+            .sum();
+        assert_eq!(nulls, 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_closing_without_items_writes_nothing() {
+        let path = std::env::temp_dir().join("spider_parquet_empty.parquet");
+        let _ = std::fs::remove_file(&path);
+        let pipeline = ParquetExporterPipeline::<Book>::new(&path);
+        pipeline.close().await.unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,51 @@
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn process(middleware: &mut PathPrefixMiddleware, url: &str) -> bool {
+        let request = Request::new(Url::parse(url).unwrap());
+        matches!(
+            Middleware::<()>::process_request(middleware, &(), request)
+                .await
+                .unwrap(),
+            MiddlewareAction::Continue(_)
+        )
+    }
+
+    #[tokio::test]
+    async fn test_requests_outside_the_prefix_are_dropped() {
+        let mut middleware = PathPrefixMiddleware::new().prefix("/docs/");
+        let counter = middleware.clone();
+
+        assert!(process(&mut middleware, "https://example.com/docs/").await);
+        assert!(process(&mut middleware, "https://example.com/docs/guide/intro").await);
+        assert!(!process(&mut middleware, "https://example.com/blog/post").await);
+        assert!(!process(&mut middleware, "https://example.com/docs").await);
+        assert_eq!(counter.dropped(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_several_prefixes_per_host() {
+        let mut middleware = PathPrefixMiddleware::new()
+            .host_prefix("example.com", "/docs/")
+            .host_prefix("example.com", "/blog/")
+            .host_prefix("Docs.RS", "/tokio/");
+
+        assert!(process(&mut middleware, "https://example.com/blog/post").await);
+        assert!(process(&mut middleware, "https://www.example.com/docs/a").await);
+        assert!(!process(&mut middleware, "https://example.com/shop/").await);
+        assert!(process(&mut middleware, "https://docs.rs/tokio/latest").await);
+        assert!(!process(&mut middleware, "https://docs.rs/serde/latest").await);
+    }
+
+    #[test]
+    fn test_hosts_without_a_prefix_pass() {
+        let middleware = PathPrefixMiddleware::new().host_prefix("example.com", "/docs/");
+        assert!(middleware.allows(&Url::parse("https://other.org/anything").unwrap()));
+        assert!(middleware.allows(&Url::parse("https://notexample.com/shop/").unwrap()));
+        assert!(PathPrefixMiddleware::new().allows(&Url::parse("https://example.com/").unwrap()));
+    }
+}
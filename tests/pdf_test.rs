@@ -0,0 +1,40 @@
+#![cfg(feature = "pdf")]
+
+use spider_lib::prelude::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_text_per_page() {
+        let pdf = include_bytes!("fixtures/two_pages.pdf");
+        let text = extract_pdf_text(pdf).unwrap();
+        assert_eq!(text.pages.len(), 2);
+        assert!(text.pages[0].contains("Hello from page one"));
+        assert!(text.pages[1].contains("Second page text"));
+        assert!(text.text().contains("Hello from page one"));
+    }
+
+    #[test]
+    fn test_password_protected_pdf_is_encrypted_error() {
+        let pdf = include_bytes!("fixtures/encrypted.pdf");
+        assert_eq!(extract_pdf_text(pdf), Err(PdfError::Encrypted));
+    }
+
+    #[test]
+    fn test_non_pdf_body_is_rejected() {
+        let result = extract_pdf_text(b"<html><body>Not a PDF</body></html>");
+        assert_eq!(result, Err(PdfError::NotPdf));
+    }
+
+    #[test]
+    fn test_malformed_pdf_returns_error() {
+        let result = extract_pdf_text(b"%PDF-1.7\n1 0 obj << /Type /Catalog >>\ntruncated");
+        assert!(
+            matches!(result, Err(PdfError::Malformed(_))),
+            "expected a malformed PDF error, got {:?}",
+            result
+        );
+    }
+}
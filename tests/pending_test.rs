@@ -0,0 +1,89 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    pub struct SlowSpider {
+        start: Url,
+        pending: PendingParses,
+        seen_over_limit: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Spider for SlowSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            if self.pending.pending() > self.pending.limit() {
+                self.seen_over_limit.fetch_add(1, Ordering::SeqCst);
+            }
+            tokio::time::sleep(Duration::from_millis(30)).await;
+
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                for page in 1..=12 {
+                    output.add_request(Request::new(response.url.join(&format!("/{page}"))?));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_parses_stay_under_the_cap() {
+        let server = TestServer::start(|_| TestResponse::html("<p>page</p>")).await;
+        let pending = PendingParses::new(2);
+        let seen_over_limit = Arc::new(AtomicUsize::new(0));
+        let spider = SlowSpider {
+            start: server.url("/"),
+            pending: pending.clone(),
+            seen_over_limit: seen_over_limit.clone(),
+        };
+
+        let crawler = CrawlerBuilder::new(pending.wrap(spider))
+            .max_pending_parses(&pending)
+            .max_concurrent_downloads(8)
+            .build()
+            .await
+            .unwrap();
+        let stats = crawler.get_stats();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(stats.items_scraped.load(Ordering::SeqCst), 13);
+        assert_eq!(seen_over_limit.load(Ordering::SeqCst), 0);
+        assert!(pending.peak() <= 2);
+        assert!(pending.peak() >= 1);
+        assert_eq!(pending.pending(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn test_zero_limit_is_rejected() {
+        PendingParses::new(0);
+    }
+}
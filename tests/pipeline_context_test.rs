@@ -0,0 +1,132 @@
+use serde_json::json;
+use spider_lib::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Page {
+        pub n: usize,
+        pub run_id: String,
+        pub export: String,
+        pub seen_requests: usize,
+    }
+
+    /// Tags items with the run and keeps one in `every`, counting with the crawl stats.
+    pub struct TagRun {
+        every: usize,
+    }
+
+    #[async_trait]
+    impl ContextPipeline<Page> for TagRun {
+        fn name(&self) -> &str {
+            "TagRun"
+        }
+
+        async fn process_item(
+            &self,
+            mut item: Page,
+            ctx: &PipelineContext,
+        ) -> Result<Option<Page>, PipelineError> {
+            if !item.n.is_multiple_of(self.every) {
+                return Ok(None);
+            }
+            item.run_id = ctx.run_id().to_string();
+            item.export = ctx
+                .config("export")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string();
+            item.seen_requests = ctx
+                .stats()
+                .map_or(0, |stats| stats.requests_succeeded.load(Ordering::SeqCst));
+            Ok(Some(item))
+        }
+    }
+
+    fn page(n: usize) -> Page {
+        Page {
+            n,
+            run_id: String::new(),
+            export: String::new(),
+            seen_requests: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_reaches_process_item() {
+        let context = PipelineContext::new()
+            .with_run_id("run-7")
+            .with_config("export", json!("s3://bucket/books"));
+        let pipeline = TagRun { every: 1 }.with_context(context.clone());
+        assert!(context.stats().is_none());
+        assert_eq!(Pipeline::name(&pipeline), "TagRun");
+
+        let item = Pipeline::process_item(&pipeline, page(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.run_id, "run-7");
+        assert_eq!(item.export, "s3://bucket/books");
+        assert_eq!(item.seen_requests, 0);
+        assert!(!PipelineContext::new().run_id().is_empty());
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = Page;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            _response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            Ok(ParseOutput::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attached_context_sees_the_crawl_stats() {
+        let context = PipelineContext::new().with_run_id("nightly");
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: Url::parse("http://127.0.0.1:9/").unwrap(),
+        })
+        .add_pipeline(TagRun { every: 2 }.with_context(context.clone()))
+        .build()
+        .await
+        .unwrap();
+        context.attach(&crawler);
+
+        let stats = crawler.get_stats();
+        assert!(Arc::ptr_eq(context.stats().unwrap(), &stats));
+        stats.requests_succeeded.fetch_add(3, Ordering::SeqCst);
+
+        // A clone of the context, as held by a second pipeline, sees the same stats.
+        let pipeline = TagRun { every: 2 }.with_context(context.clone());
+        assert!(
+            Pipeline::process_item(&pipeline, page(1))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let item = Pipeline::process_item(&pipeline, page(2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.run_id, "nightly");
+        assert_eq!(item.seen_requests, 3);
+    }
+}
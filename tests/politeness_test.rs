@@ -0,0 +1,76 @@
+use spider_lib::prelude::*;
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_impolite_requests_skip_the_ramp() {
+        let mut marker = NoPolitenessMiddleware::new();
+        let mut ramp = RampUpMiddleware::new(1.0, 1.0, Duration::ZERO).unwrap();
+
+        let started = Instant::now();
+        for page in 0..5 {
+            let url = Url::parse(&format!("http://127.0.0.1:8080/{page}")).unwrap();
+            let MiddlewareAction::Continue(request) =
+                Middleware::<()>::process_request(&mut marker, &(), Request::new(url))
+                    .await
+                    .unwrap()
+            else {
+                panic!("the marker must pass requests on");
+            };
+            assert!(request.is_impolite());
+            let action = Middleware::<()>::process_request(&mut ramp, &(), request)
+                .await
+                .unwrap();
+            assert!(matches!(action, MiddlewareAction::Continue(_)));
+        }
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_impolite_requests_skip_a_polite_rate_limit() {
+        let mut rate_limit = Polite::new(RateLimitMiddleware::with_jitter(
+            Duration::from_secs(1),
+            0.5,
+        ));
+
+        let started = Instant::now();
+        for page in 0..5 {
+            let url = Url::parse(&format!("http://127.0.0.1:8080/{page}")).unwrap();
+            let request = Request::new(url).impolite();
+            let action = Middleware::<()>::process_request(&mut rate_limit, &(), request)
+                .await
+                .unwrap();
+            assert!(matches!(action, MiddlewareAction::Continue(_)));
+        }
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_polite_requests_pass_the_rate_limit() {
+        let mut rate_limit = Polite::new(RateLimitMiddleware::with_jitter(
+            Duration::from_millis(100),
+            0.0,
+        ));
+
+        let started = Instant::now();
+        for page in 0..3 {
+            let url = Url::parse(&format!("http://127.0.0.1:8080/{page}")).unwrap();
+            let action = Middleware::<()>::process_request(&mut rate_limit, &(), Request::new(url))
+                .await
+                .unwrap();
+            assert!(matches!(action, MiddlewareAction::Continue(_)));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn test_requests_are_polite_by_default() {
+        let request = Request::new(Url::parse("https://example.com/").unwrap());
+        assert!(!request.is_impolite());
+        assert!(request.impolite().is_impolite());
+    }
+}
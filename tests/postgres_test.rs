@@ -0,0 +1,111 @@
+#![cfg(feature = "pipeline-postgres")]
+
+//! Runs against the database in `SPIDER_TEST_POSTGRES_URL`, and is skipped without it.
+
+use spider_lib::prelude::*;
+use tokio_postgres::NoTls;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Product {
+        pub url: String,
+        pub name: String,
+        pub price: Option<f64>,
+        pub stock: i64,
+        pub tags: Vec<String>,
+    }
+
+    fn product(url: &str, name: &str, price: Option<f64>, stock: i64) -> Product {
+        Product {
+            url: url.into(),
+            name: name.into(),
+            price,
+            stock,
+            tags: vec!["new".into()],
+        }
+    }
+
+    fn database_url() -> Option<String> {
+        let url = std::env::var("SPIDER_TEST_POSTGRES_URL").ok();
+        if url.is_none() {
+            eprintln!("SPIDER_TEST_POSTGRES_URL is not set, skipping");
+        }
+        url
+    }
+
+    async fn client(url: &str) -> tokio_postgres::Client {
+        let (client, connection) = tokio_postgres::connect(url, NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client
+    }
+
+    #[tokio::test]
+    async fn test_items_are_upserted_in_batches() {
+        let Some(url) = database_url() else {
+            return;
+        };
+        let db = client(&url).await;
+        db.batch_execute("DROP TABLE IF EXISTS spider_products")
+            .await
+            .unwrap();
+
+        let writer = PostgresWriterPipeline::new(&url, "spider_products")
+            .unwrap()
+            .conflict_columns(&["url"])
+            .batch_size(2);
+        for item in [
+            product("https://shop.example/a", "Lamp", Some(9.5), 3),
+            product("https://shop.example/b", "Desk", None, 1),
+            product("https://shop.example/a", "Lamp v2", Some(8.0), 2),
+            product("https://shop.example/c", "Chair", Some(20.0), 0),
+            product("https://shop.example/c", "Chair v2", Some(19.0), 5),
+        ] {
+            assert!(writer.process_item(item).await.unwrap().is_some());
+        }
+        writer.close().await.unwrap();
+
+        let rows = db
+            .query(
+                "SELECT url, name, price, stock, tags FROM spider_products ORDER BY url",
+                &[],
+            )
+            .await
+            .unwrap();
+        let rows: Vec<(String, String, Option<f64>, i64, serde_json::Value)> = rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (
+                    "https://shop.example/a".to_string(),
+                    "Lamp v2".to_string(),
+                    Some(8.0),
+                    2,
+                    serde_json::json!(["new"])
+                ),
+                (
+                    "https://shop.example/b".to_string(),
+                    "Desk".to_string(),
+                    None,
+                    1,
+                    serde_json::json!(["new"])
+                ),
+                (
+                    "https://shop.example/c".to_string(),
+                    "Chair v2".to_string(),
+                    Some(19.0),
+                    5,
+                    serde_json::json!(["new"])
+                ),
+            ]
+        );
+        db.batch_execute("DROP TABLE spider_products")
+            .await
+            .unwrap();
+    }
+}
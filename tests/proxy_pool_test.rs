@@ -0,0 +1,227 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use spider_util::error::ReqwestErrorDetails;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROXIES: [&str; 3] = ["http://a.proxy:1", "http://b.proxy:1", "http://c.proxy:1"];
+
+    async fn send(pool: &mut ProxyPoolMiddleware, request: Request) -> Request {
+        match Middleware::<()>::process_request(pool, &(), request)
+            .await
+            .unwrap()
+        {
+            MiddlewareAction::Continue(request) => request,
+            _ => panic!("request was not continued"),
+        }
+    }
+
+    fn proxy(request: &Request) -> String {
+        request
+            .meta
+            .get("proxy")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn request() -> Request {
+        Request::new(Url::parse("https://example.com/").unwrap())
+    }
+
+    async fn respond(pool: &mut ProxyPoolMiddleware, request: Request, status: u16) {
+        let response = MockResponse::new(status).into_response(request);
+        Middleware::<()>::process_response(pool, response)
+            .await
+            .unwrap();
+    }
+
+    async fn sent_proxies(pool: &mut ProxyPoolMiddleware, count: usize) -> Vec<String> {
+        let mut proxies = Vec::new();
+        for _ in 0..count {
+            proxies.push(proxy(&send(pool, request()).await));
+        }
+        proxies
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_and_random_selection() {
+        let mut pool = ProxyPoolMiddleware::new(&PROXIES).unwrap();
+        assert_eq!(
+            sent_proxies(&mut pool, 4).await,
+            [PROXIES[0], PROXIES[1], PROXIES[2], PROXIES[0]]
+        );
+
+        let mut random = ProxyPoolMiddleware::new(&PROXIES)
+            .unwrap()
+            .selection(ProxySelection::Random);
+        for proxy in sent_proxies(&mut random, 20).await {
+            assert!(PROXIES.contains(&proxy.as_str()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_proxy_is_ejected_and_readmitted() {
+        let mut pool = ProxyPoolMiddleware::new(&PROXIES[..2])
+            .unwrap()
+            .max_failures(2)
+            .ejection_time(Duration::from_millis(100));
+        let shared = pool.clone();
+
+        for status in [407, 403] {
+            let mut request = send(&mut pool, request()).await;
+            while proxy(&request) != PROXIES[0] {
+                respond(&mut pool, request, 200).await;
+                request = send(&mut pool, self::request()).await;
+            }
+            respond(&mut pool, request, status).await;
+        }
+        let health = shared.health();
+        assert!(!health[0].available);
+        assert_eq!((health[0].failures, health[0].ejections), (2, 1));
+        assert!(health[1].available);
+        assert_eq!(sent_proxies(&mut pool, 3).await, [PROXIES[1]; 3]);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(shared.health()[0].available);
+        assert_eq!(shared.health()[0].consecutive_failures, 0);
+        assert!(
+            sent_proxies(&mut pool, 2)
+                .await
+                .contains(&PROXIES[0].to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_success_resets_failures() {
+        let mut pool = ProxyPoolMiddleware::new(&PROXIES[..1])
+            .unwrap()
+            .max_failures(2);
+        for status in [403, 200, 403] {
+            let request = send(&mut pool, request()).await;
+            respond(&mut pool, request, status).await;
+        }
+        let health = pool.health();
+        assert!(health[0].available);
+        assert_eq!((health[0].failures, health[0].consecutive_failures), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_count_as_failures() {
+        let mut pool = ProxyPoolMiddleware::new(&PROXIES[..1])
+            .unwrap()
+            .max_failures(1);
+        let request = send(&mut pool, request()).await;
+        let error = SpiderError::ReqwestError(ReqwestErrorDetails {
+            message: "connection refused".into(),
+            is_connect: true,
+            is_timeout: false,
+        });
+        assert!(
+            Middleware::<()>::handle_error(&mut pool, &request, &error)
+                .await
+                .is_err()
+        );
+
+        let health = pool.health();
+        assert!(!health[0].available);
+        // With every proxy ejected, the pool still sends through one.
+        assert_eq!(proxy(&send(&mut pool, self::request()).await), PROXIES[0]);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_stick_to_one_proxy() {
+        let mut pool = ProxyPoolMiddleware::new(&PROXIES).unwrap().max_failures(1);
+        let first = proxy(&send(&mut pool, request().proxy_session("alice")).await);
+        let other = proxy(&send(&mut pool, request().proxy_session("bob")).await);
+        assert_ne!(first, other);
+        for _ in 0..3 {
+            send(&mut pool, request()).await;
+            let again = send(&mut pool, request().proxy_session("alice")).await;
+            assert_eq!(proxy(&again), first);
+        }
+
+        let again = send(&mut pool, request().proxy_session("alice")).await;
+        respond(&mut pool, again, 407).await;
+        let moved = proxy(&send(&mut pool, request().proxy_session("alice")).await);
+        assert_ne!(moved, first);
+        assert_eq!(
+            request()
+                .proxy_session("alice")
+                .proxy_session_name()
+                .as_deref(),
+            Some("alice")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_in_stats_snapshot() {
+        let pool = ProxyPoolMiddleware::new(&PROXIES[..1]).unwrap();
+        let snapshot = StatsSnapshot::default().with_proxy_health(pool.health());
+        assert_eq!(snapshot.to_json()["proxies"][0]["proxy"], PROXIES[0]);
+        assert!(StatsSnapshot::default().to_json().get("proxies").is_none());
+    }
+
+    #[test]
+    fn test_empty_pool_is_a_configuration_error() {
+        assert!(matches!(
+            ProxyPoolMiddleware::new(&[]),
+            Err(SpiderError::ConfigurationError(_))
+        ));
+    }
+
+    /// Starts a server answering as a forward proxy named `name`, echoing the URL asked.
+    async fn forward_proxy(name: &'static str) -> TestServer {
+        TestServer::start(move |request| {
+            TestResponse::new(200, "text/plain", format!("{name} {}", request.path).into())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_http_downloader_connects_through_the_picked_proxy() {
+        let a = forward_proxy("a").await;
+        let b = forward_proxy("b").await;
+        let (a_url, b_url) = (a.url("").to_string(), b.url("").to_string());
+        let mut pool = ProxyPoolMiddleware::new(&[&a_url, &b_url]).unwrap();
+        let downloader = HttpDownloader::new().unwrap();
+
+        let mut bodies = Vec::new();
+        for _ in 0..3 {
+            let url = Url::parse("http://origin.invalid/page?id=1").unwrap();
+            let request = send(&mut pool, Request::new(url)).await;
+            let response = downloader.download(request).await.unwrap();
+            bodies.push(String::from_utf8(response.body.to_vec()).unwrap());
+        }
+        assert_eq!(
+            bodies,
+            [
+                "a http://origin.invalid/page?id=1",
+                "b http://origin.invalid/page?id=1",
+                "a http://origin.invalid/page?id=1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_downloader_rejects_an_invalid_proxy() {
+        let request = Request::new(Url::parse("http://origin.invalid/").unwrap());
+        request
+            .meta
+            .insert("proxy".into(), "not a proxy url".into());
+        let error = HttpDownloader::new()
+            .unwrap()
+            .download(request)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SpiderError::ConfigurationError(_)));
+    }
+}
@@ -0,0 +1,158 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_rises_linearly_to_target() {
+        let ramp = RampUpMiddleware::new(2.0, 10.0, Duration::from_secs(8)).unwrap();
+        assert_eq!(ramp.rate_at(Duration::ZERO), 2.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(4)), 6.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(8)), 10.0);
+        assert_eq!(ramp.rate_at(Duration::from_secs(60)), 10.0);
+
+        let instant = RampUpMiddleware::new(2.0, 10.0, Duration::ZERO).unwrap();
+        assert_eq!(instant.rate_at(Duration::ZERO), 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_requests_speed_up_over_the_warmup() {
+        let mut ramp = RampUpMiddleware::new(10.0, 100.0, Duration::from_millis(600)).unwrap();
+        let mut sent = Vec::new();
+        for page in 0..30 {
+            let url = Url::parse(&format!("https://example.com/{page}")).unwrap();
+            let action = Middleware::<()>::process_request(&mut ramp, &(), Request::new(url))
+                .await
+                .unwrap();
+            assert!(matches!(action, MiddlewareAction::Continue(_)));
+            sent.push(Instant::now());
+        }
+
+        let gaps: Vec<Duration> = sent.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(
+            gaps[0] >= Duration::from_millis(80),
+            "first gap {:?}",
+            gaps[0]
+        );
+        let last = *gaps.last().unwrap();
+        assert!(last < Duration::from_millis(30), "last gap {:?}", last);
+        assert!(gaps[0] > last * 3);
+    }
+
+    #[test]
+    fn test_invalid_rates_are_rejected() {
+        for (start, target) in [(0.0, 10.0), (1.0, f64::NAN), (-1.0, 1.0)] {
+            let error = RampUpMiddleware::new(start, target, Duration::from_secs(1)).unwrap_err();
+            assert!(matches!(error, SpiderError::ConfigurationError(_)));
+        }
+    }
+
+    #[test]
+    fn test_concurrency_limit_rises_linearly_to_target() {
+        let ramp = ConcurrencyRampMiddleware::new(2, 10, Duration::from_secs(8)).unwrap();
+        assert_eq!(ramp.limit_at(Duration::ZERO), 2);
+        assert_eq!(ramp.limit_at(Duration::from_secs(4)), 6);
+        assert_eq!(ramp.limit_at(Duration::from_secs(60)), 10);
+        assert_eq!(ramp.limit(), 2);
+
+        for (start, target) in [(0, 4), (5, 4)] {
+            let error =
+                ConcurrencyRampMiddleware::new(start, target, Duration::from_secs(1)).unwrap_err();
+            assert!(matches!(error, SpiderError::ConfigurationError(_)));
+        }
+    }
+
+    /// Sends `requests` through `ramp` as concurrent 40ms downloads and returns, for
+    /// every request, when it was let through and how many requests were in flight.
+    async fn simulate(ramp: &ConcurrencyRampMiddleware, requests: usize) -> Vec<(Duration, usize)> {
+        let started = Instant::now();
+        let mut downloads = Vec::new();
+        for page in 0..requests {
+            let mut ramp = ramp.clone();
+            downloads.push(tokio::spawn(async move {
+                let url = Url::parse(&format!("https://example.com/{page}")).unwrap();
+                let MiddlewareAction::Continue(request) =
+                    Middleware::<()>::process_request(&mut ramp, &(), Request::new(url))
+                        .await
+                        .unwrap()
+                else {
+                    panic!("the ramp must pass requests on");
+                };
+                let sent = (started.elapsed(), ramp.in_flight());
+                tokio::time::sleep(Duration::from_millis(40)).await;
+                let response = MockResponse::html("<p>ok</p>").into_response(request);
+                Middleware::<()>::process_response(&mut ramp, response)
+                    .await
+                    .unwrap();
+                sent
+            }));
+        }
+        let mut sent = Vec::new();
+        for download in downloads {
+            sent.push(download.await.unwrap());
+        }
+        sent
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_rises_over_the_warmup() {
+        let ramp = ConcurrencyRampMiddleware::new(1, 4, Duration::from_millis(300)).unwrap();
+        let sent = simulate(&ramp, 40).await;
+
+        let early = sent
+            .iter()
+            .filter(|(at, _)| *at < Duration::from_millis(60))
+            .map(|(_, in_flight)| *in_flight)
+            .max()
+            .unwrap();
+        let late = sent
+            .iter()
+            .filter(|(at, _)| *at > Duration::from_millis(350))
+            .map(|(_, in_flight)| *in_flight)
+            .max()
+            .unwrap();
+        assert!(early <= 1, "early concurrency {early}");
+        assert_eq!(late, 4);
+        assert!(sent.iter().all(|(_, in_flight)| *in_flight <= 4));
+        assert_eq!(ramp.limit(), 4);
+        assert_eq!(ramp.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_and_lost_requests_give_back_their_permit() {
+        let mut ramp = ConcurrencyRampMiddleware::new(1, 1, Duration::ZERO)
+            .unwrap()
+            .lease(Duration::from_millis(100));
+        let url = Url::parse("https://example.com/").unwrap();
+
+        let MiddlewareAction::Continue(failed) =
+            Middleware::<()>::process_request(&mut ramp, &(), Request::new(url.clone()))
+                .await
+                .unwrap()
+        else {
+            panic!("the ramp must pass requests on");
+        };
+        let error = SpiderError::GeneralError("connection reset".into());
+        assert!(
+            Middleware::<()>::handle_error(&mut ramp, &failed, &error)
+                .await
+                .is_err()
+        );
+        assert_eq!(ramp.in_flight(), 0);
+
+        // This response never comes back, so its permit is taken back after the lease.
+        Middleware::<()>::process_request(&mut ramp, &(), Request::new(url.clone()))
+            .await
+            .unwrap();
+        let started = Instant::now();
+        Middleware::<()>::process_request(&mut ramp, &(), Request::new(url))
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(ramp.in_flight(), 1);
+    }
+}
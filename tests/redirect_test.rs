@@ -0,0 +1,170 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(status: u16, location: &str) -> TestResponse {
+        TestResponse::status(status).header("location", location)
+    }
+
+    #[tokio::test]
+    async fn test_relative_redirects_resolve_against_the_redirecting_url() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/docs/start" => redirect(302, "next"),
+            "/docs/next" => redirect(301, "../final?page=1"),
+            "/final?page=1" => TestResponse::html("<p>done</p>"),
+            _ => TestResponse::status(404),
+        })
+        .await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let response = downloader
+            .download(Request::new(server.url("/docs/start")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status.as_u16(), 200);
+        assert_eq!(response.url, server.url("/final?page=1"));
+        assert_eq!(response.request_url, server.url("/docs/start"));
+        assert_eq!(
+            response.redirect_chain(),
+            vec![server.url("/docs/start"), server.url("/docs/next")]
+        );
+        assert_eq!(
+            response.redirect_hops(),
+            vec![
+                (302, server.url("/docs/start")),
+                (301, server.url("/docs/next"))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protocol_relative_redirect_keeps_the_scheme() {
+        let addr = Arc::new(Mutex::new(String::new()));
+        let host = addr.clone();
+        let server = TestServer::start(move |request| match request.path.as_str() {
+            "/old" => redirect(308, &format!("//{}/new", host.lock().unwrap())),
+            "/new" => TestResponse::html("<p>moved</p>"),
+            _ => TestResponse::status(404),
+        })
+        .await;
+        *addr.lock().unwrap() = server.addr.to_string();
+        let downloader = HttpDownloader::new().unwrap();
+
+        let response = downloader
+            .download(Request::new(server.url("/old")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.url, server.url("/new"));
+        assert_eq!(response.redirect_chain(), vec![server.url("/old")]);
+    }
+
+    #[tokio::test]
+    async fn test_see_other_continues_as_get() {
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let seen = methods.clone();
+        let server = TestServer::start(move |request| {
+            seen.lock().unwrap().push(request.method.clone());
+            match request.path.as_str() {
+                "/submit" => redirect(303, "/thanks"),
+                _ => TestResponse::html("<p>thanks</p>"),
+            }
+        })
+        .await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let mut request = Request::new(server.url("/submit"));
+        request.method = reqwest::Method::POST;
+        let response = downloader.download(request).await.unwrap();
+
+        assert_eq!(response.url, server.url("/thanks"));
+        assert_eq!(*methods.lock().unwrap(), vec!["POST", "GET"]);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_limits() {
+        let server = TestServer::start(|_| redirect(302, "/loop")).await;
+
+        let downloader = HttpDownloader::builder()
+            .redirect_policy(RedirectPolicy::new().max_redirects(3))
+            .build()
+            .unwrap();
+        let error = downloader
+            .download(Request::new(server.url("/start")))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("too many redirects"));
+
+        let downloader = HttpDownloader::builder()
+            .redirect_policy(RedirectPolicy::none())
+            .build()
+            .unwrap();
+        let response = downloader
+            .download(Request::new(server.url("/start")))
+            .await
+            .unwrap();
+        assert_eq!(response.status.as_u16(), 302);
+        assert!(response.redirect_chain().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_redirects_returns_the_redirect() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/old" => redirect(301, "/new"),
+            _ => TestResponse::html("<p>moved</p>"),
+        })
+        .await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let request = Request::new(server.url("/old")).no_redirects();
+        assert!(!request.follows_redirects());
+        let response = downloader.download(request).await.unwrap();
+        assert_eq!(response.status(), 301);
+        assert_eq!(response.header("location"), Some("/new"));
+        assert!(response.redirect_hops().is_empty());
+
+        let response = downloader
+            .download(Request::new(server.url("/old")))
+            .await
+            .unwrap();
+        assert_eq!(response.url, server.url("/new"));
+        assert_eq!(response.redirect_hops(), vec![(301, server.url("/old"))]);
+    }
+
+    #[test]
+    fn test_cross_scheme_redirect_targets() {
+        let secure = Url::parse("https://example.com/account").unwrap();
+        let plain = Url::parse("http://example.com/account").unwrap();
+
+        let policy = RedirectPolicy::new();
+        assert_eq!(
+            policy.target(&secure, "http://example.com/login").unwrap(),
+            Url::parse("http://example.com/login").unwrap()
+        );
+        assert_eq!(
+            policy.target(&plain, "https://example.com/login").unwrap(),
+            Url::parse("https://example.com/login").unwrap()
+        );
+        assert_eq!(
+            policy.target(&secure, "//cdn.example.com/a").unwrap(),
+            Url::parse("https://cdn.example.com/a").unwrap()
+        );
+        assert!(policy.target(&secure, "ftp://example.com/file").is_err());
+
+        let policy = RedirectPolicy::new().refuse_downgrade(true);
+        let error = policy
+            .target(&secure, "http://example.com/login")
+            .unwrap_err();
+        assert!(error.to_string().contains("insecure"));
+        assert!(policy.target(&plain, "https://example.com/login").is_ok());
+        assert!(policy.target(&plain, "http://example.com/login").is_ok());
+    }
+}
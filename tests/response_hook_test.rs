@@ -0,0 +1,131 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::sync::atomic::Ordering;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Page {
+        pub title: String,
+        pub base: String,
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = Page;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let html = response.to_html()?;
+            let mut output = ParseOutput::new();
+            output.add_item(Page {
+                title: html.css_text("h1")?.unwrap_or_default(),
+                base: html.css_attr("base", "href")?.unwrap_or_default(),
+            });
+            if response.url.path() == "/" {
+                for path in ["/found", "/missing"] {
+                    output.add_request(Request::new(response.url.join(path)?));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    /// Drops soft 404s and tells each page where it came from.
+    fn hook() -> ResponseHookMiddleware {
+        ResponseHookMiddleware::new(|mut response: Response| {
+            if String::from_utf8_lossy(&response.body).contains("Page not found") {
+                return None;
+            }
+            let mut body = format!("<base href=\"{}\">", response.url).into_bytes();
+            body.extend_from_slice(&response.body);
+            response.body = body.into();
+            Some(response)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_hook_rewrites_and_drops_responses_before_parse() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/missing" => TestResponse::html("<h1>Page not found</h1>"),
+            path => TestResponse::html(&format!("<h1>{path}</h1>")),
+        })
+        .await;
+        let hook = hook();
+        let (pipeline, mut items) = ChannelPipeline::new(10);
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(hook.clone())
+        .add_pipeline(pipeline)
+        .build()
+        .await
+        .unwrap();
+        let stats = crawler.get_stats();
+        crawler.start_crawl().await.unwrap();
+
+        let mut pages = Vec::new();
+        while let Some(page) = items.recv().await {
+            pages.push(page);
+        }
+        pages.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].title, "/");
+        assert_eq!(pages[1].title, "/found");
+        assert_eq!(pages[1].base, server.url("/found").to_string());
+        assert_eq!(hook.dropped(), 1);
+        assert_eq!(stats.requests_dropped.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hook_runs_in_the_response_phase() {
+        let mut hook = hook();
+        let url = Url::parse("https://example.com/page").unwrap();
+        let response = |body: &'static str| {
+            MockResponse::new(200)
+                .body(body)
+                .into_response(Request::new(url.clone()))
+        };
+
+        let request = Request::new(url.clone());
+        let action = Middleware::<()>::process_request(&mut hook, &(), request)
+            .await
+            .unwrap();
+        assert!(matches!(action, MiddlewareAction::Continue(_)));
+
+        let action = Middleware::<()>::process_response(&mut hook, response("<p>Hi</p>"))
+            .await
+            .unwrap();
+        let MiddlewareAction::Continue(kept) = action else {
+            panic!("the page was dropped");
+        };
+        assert_eq!(
+            &kept.body[..],
+            b"<base href=\"https://example.com/page\"><p>Hi</p>"
+        );
+
+        let action = Middleware::<()>::process_response(&mut hook, response("Page not found"))
+            .await
+            .unwrap();
+        assert!(matches!(action, MiddlewareAction::Drop));
+        assert_eq!(hook.dropped(), 1);
+    }
+}
@@ -0,0 +1,29 @@
+use reqwest::header::HeaderValue;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_and_headers() {
+        let url = Url::parse("https://example.com/old").unwrap();
+        let mut response = MockResponse::new(301)
+            .header("Content-Type", "text/html")
+            .header("location", "/new")
+            .into_response(Request::new(url));
+        response
+            .headers
+            .insert("x-raw", HeaderValue::from_bytes(b"caf\xE9").unwrap());
+
+        assert_eq!(response.status(), 301);
+        assert_eq!(response.header("content-type"), Some("text/html"));
+        assert_eq!(response.header("Location"), Some("/new"));
+        assert_eq!(response.header("x-missing"), None);
+        assert_eq!(response.header("x-raw"), None);
+        assert_eq!(response.header("not a header"), None);
+        assert_eq!(response.headers().len(), 3);
+    }
+}
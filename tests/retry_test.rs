@@ -0,0 +1,401 @@
+mod common;
+
+use common::{TestRequest, TestResponse, TestServer};
+use reqwest::Method;
+use reqwest::header::{CONTENT_TYPE, HeaderValue, RETRY_AFTER};
+use spider_lib::callback::{FAILURE_ERROR_KEY, FAILURE_KEY};
+use spider_lib::downloader::{CONNECTION_RESET_PREFIX, FAILED_DOWNLOAD_STATUS};
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use spider_util::error::ReqwestErrorDetails;
+use spider_util::request::Body;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response_for(request: Request, status: u16) -> Response {
+        MockResponse::new(status).into_response(request)
+    }
+
+    fn request() -> Request {
+        Request::new(Url::parse("https://example.com/flaky").unwrap())
+    }
+
+    /// Feeds `status` responses through the middleware until it stops retrying, returning
+    /// the number of retries it scheduled.
+    async fn retries_until_dropped(
+        middleware: &mut RetryPolicyMiddleware,
+        request: Request,
+    ) -> u32 {
+        let mut request = request;
+        let mut retries = 0;
+        loop {
+            let action = Middleware::<()>::process_response(middleware, response_for(request, 503))
+                .await
+                .unwrap();
+            match action {
+                MiddlewareAction::Retry(next, _) => {
+                    retries += 1;
+                    request = *next;
+                }
+                MiddlewareAction::Drop => return retries,
+                _ => panic!("unexpected action for a retryable status"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_request_limit_overrides_global() {
+        let mut middleware =
+            RetryPolicyMiddleware::new(RetryMiddleware::new().max_retries(2).backoff_factor(0.0));
+
+        assert_eq!(retries_until_dropped(&mut middleware, request()).await, 2);
+        assert_eq!(
+            retries_until_dropped(&mut middleware, request().max_retries(5)).await,
+            5
+        );
+        assert_eq!(
+            retries_until_dropped(&mut middleware, request().max_retries(0)).await,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_status_continues() {
+        let mut middleware = RetryPolicyMiddleware::default();
+        let action = Middleware::<()>::process_response(
+            &mut middleware,
+            response_for(request().max_retries(3), 404),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(action, MiddlewareAction::Continue(_)));
+    }
+
+    async fn retry_delay(middleware: &mut RetryPolicyMiddleware, response: Response) -> Duration {
+        match Middleware::<()>::process_response(middleware, response)
+            .await
+            .unwrap()
+        {
+            MiddlewareAction::Retry(_, delay) => delay,
+            _ => panic!("response was not retried"),
+        }
+    }
+
+    fn with_retry_after(mut response: Response, value: &str) -> Response {
+        response
+            .headers
+            .insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        response
+    }
+
+    #[test]
+    fn test_backoff_strategies() {
+        let base = Duration::from_millis(100);
+        assert_eq!(BackoffStrategy::Fixed(base).delay(5), base);
+        assert_eq!(
+            BackoffStrategy::Exponential(base).delay(3),
+            Duration::from_millis(800)
+        );
+        for attempts in 0..5 {
+            let delay = BackoffStrategy::ExponentialJitter(base).delay(attempts);
+            assert!(delay <= BackoffStrategy::Exponential(base).delay(attempts));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backoff_grows_up_to_max_delay() {
+        let mut middleware = RetryPolicyMiddleware::new(RetryMiddleware::new().max_retries(5))
+            .with_backoff(BackoffStrategy::Exponential(Duration::from_secs(1)))
+            .max_delay(Duration::from_secs(5));
+        let mut request = request();
+        let mut delays = Vec::new();
+        for _ in 0..4 {
+            let action =
+                Middleware::<()>::process_response(&mut middleware, response_for(request, 500))
+                    .await
+                    .unwrap();
+            let MiddlewareAction::Retry(next, delay) = action else {
+                panic!("response was not retried");
+            };
+            delays.push(delay.as_secs());
+            request = *next;
+        }
+        assert_eq!(delays, [1, 2, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_overrides_backoff() {
+        let mut middleware = RetryPolicyMiddleware::default()
+            .with_backoff(BackoffStrategy::Fixed(Duration::from_secs(1)))
+            .max_delay(Duration::from_secs(60));
+
+        let seconds = with_retry_after(response_for(request(), 429), "7");
+        assert_eq!(
+            retry_delay(&mut middleware, seconds).await,
+            Duration::from_secs(7)
+        );
+
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+        let dated = with_retry_after(response_for(request(), 503), &date);
+        let delay = retry_delay(&mut middleware, dated).await;
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+
+        let capped = with_retry_after(response_for(request(), 503), "3600");
+        assert_eq!(
+            retry_delay(&mut middleware, capped).await,
+            Duration::from_secs(60)
+        );
+
+        let past = with_retry_after(
+            response_for(request(), 503),
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+        );
+        assert_eq!(retry_delay(&mut middleware, past).await, Duration::ZERO);
+
+        let other_status = with_retry_after(response_for(request(), 500), "7");
+        assert_eq!(
+            retry_delay(&mut middleware, other_status).await,
+            Duration::from_secs(1)
+        );
+        let unparsable = with_retry_after(response_for(request(), 429), "soon");
+        assert_eq!(
+            retry_delay(&mut middleware, unparsable).await,
+            Duration::from_secs(1)
+        );
+    }
+
+    async fn is_retried(middleware: &mut RetryPolicyMiddleware, status: u16) -> bool {
+        let action =
+            Middleware::<()>::process_response(middleware, response_for(request(), status))
+                .await
+                .unwrap();
+        matches!(action, MiddlewareAction::Retry(..))
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_status() {
+        let mut defaults = RetryPolicyMiddleware::default();
+        assert_eq!(defaults.inner.retry_http_codes, DEFAULT_RETRY_STATUS);
+        assert!(is_retried(&mut defaults, 500).await);
+        assert!(!is_retried(&mut defaults, 403).await);
+
+        let mut custom = RetryPolicyMiddleware::default().retry_on_status(&[403, 429]);
+        assert!(is_retried(&mut custom, 403).await);
+        assert!(is_retried(&mut custom, 429).await);
+        assert!(!is_retried(&mut custom, 500).await);
+        assert!(!is_retried(&mut custom, 404).await);
+    }
+
+    fn transport_error(message: &str, is_connect: bool, is_timeout: bool) -> SpiderError {
+        SpiderError::ReqwestError(ReqwestErrorDetails {
+            message: message.to_string(),
+            is_connect,
+            is_timeout,
+        })
+    }
+
+    async fn is_error_retried(middleware: &mut RetryPolicyMiddleware, error: SpiderError) -> bool {
+        match Middleware::<()>::handle_error(middleware, &request(), &error).await {
+            Ok(MiddlewareAction::Retry(..)) => true,
+            Err(_) => false,
+            Ok(_) => panic!("unexpected action for a transport error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_errors() {
+        let timeout = || transport_error("operation timed out", false, true);
+        let refused = || transport_error("connection refused", true, false);
+        let reset = || {
+            let message = format!("{CONNECTION_RESET_PREFIX}error sending request");
+            transport_error(&message, false, false)
+        };
+        // Only the downloader's classification counts, not the wording of the message.
+        let worded = || transport_error("Connection reset by peer (os error 104)", false, false);
+
+        let mut defaults = RetryPolicyMiddleware::default();
+        assert_eq!(defaults.retry_errors, DEFAULT_RETRY_ERRORS);
+        assert!(is_error_retried(&mut defaults, timeout()).await);
+        assert!(is_error_retried(&mut defaults, refused()).await);
+        assert!(!is_error_retried(&mut defaults, reset()).await);
+
+        let mut custom = RetryPolicyMiddleware::default()
+            .retry_on_errors(&[RetryErrorKind::Timeout, RetryErrorKind::ConnectionReset]);
+        assert!(is_error_retried(&mut custom, timeout()).await);
+        assert!(is_error_retried(&mut custom, reset()).await);
+        assert!(!is_error_retried(&mut custom, worded()).await);
+        assert!(!is_error_retried(&mut custom, refused()).await);
+    }
+
+    /// Returns the placeholder response `HttpDownloader` gives a request with an errback
+    /// whose download failed in transport.
+    fn failed_download(is_connect: bool, is_timeout: bool) -> Response {
+        let response = response_for(request().errback("try_mirror"), FAILED_DOWNLOAD_STATUS);
+        response
+            .meta
+            .insert(FAILURE_KEY.into(), "error: download failed".into());
+        response.meta.insert(
+            FAILURE_ERROR_KEY.into(),
+            serde_json::json!({
+                "message": "download failed",
+                "is_connect": is_connect,
+                "is_timeout": is_timeout,
+            }),
+        );
+        response
+    }
+
+    #[tokio::test]
+    async fn test_failed_downloads_are_retried_by_error_kind() {
+        let mut middleware =
+            RetryPolicyMiddleware::default().retry_on_errors(&[RetryErrorKind::Timeout]);
+
+        let timed_out = failed_download(false, true);
+        let action = Middleware::<()>::process_response(&mut middleware, timed_out)
+            .await
+            .unwrap();
+        let MiddlewareAction::Retry(retried, _) = action else {
+            panic!("timeout was not retried");
+        };
+        assert!(!retried.meta.contains_key(FAILURE_KEY));
+        assert!(!retried.meta.contains_key(FAILURE_ERROR_KEY));
+
+        let refused = failed_download(true, false);
+        let action = Middleware::<()>::process_response(&mut middleware, refused)
+            .await
+            .unwrap();
+        let MiddlewareAction::Continue(response) = action else {
+            panic!("failure of a kind not retried was not passed to the errback");
+        };
+        assert!(response.failure().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_backoff_past_the_largest_duration_is_capped() {
+        let base = Duration::from_secs(1);
+        assert_eq!(BackoffStrategy::Exponential(base).delay(100), Duration::MAX);
+        BackoffStrategy::ExponentialJitter(base).delay(100);
+
+        let mut middleware =
+            RetryPolicyMiddleware::new(RetryMiddleware::new().max_retries(100).backoff_factor(1.0))
+                .max_delay(Duration::from_secs(30));
+        let mut request = request();
+        for _ in 0..70 {
+            request.increment_retry_attempts();
+        }
+        let timeout = transport_error("operation timed out", false, true);
+        let action = Middleware::<()>::handle_error(&mut middleware, &request, &timeout)
+            .await
+            .unwrap();
+        let MiddlewareAction::Retry(_, delay) = action else {
+            panic!("error was not retried");
+        };
+        assert_eq!(delay, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_retry_limit_reads_meta() {
+        let middleware = RetryPolicyMiddleware::new(RetryMiddleware::new().max_retries(4));
+        assert_eq!(middleware.retry_limit(&request()), 4);
+        assert_eq!(middleware.retry_limit(&request().max_retries(1)), 1);
+        assert_eq!(request().max_retries(7).max_retries_override(), Some(7));
+        assert_eq!(request().max_retries_override(), None);
+    }
+
+    #[tokio::test]
+    async fn test_retried_form_keeps_method_and_body() {
+        let mut middleware = RetryMiddleware::new();
+        let request = request().form(&[("q", "rust books"), ("page", "2")]);
+
+        let action =
+            Middleware::<()>::process_response(&mut middleware, response_for(request, 503))
+                .await
+                .unwrap();
+        let MiddlewareAction::Retry(retried, _) = action else {
+            panic!("response was not retried");
+        };
+        assert_eq!(retried.method, Method::POST);
+        assert_eq!(
+            retried.headers.get(CONTENT_TYPE).unwrap(),
+            "application/x-www-form-urlencoded"
+        );
+        let Some(Body::Bytes(body)) = &retried.body else {
+            panic!("the retried request must keep its form body");
+        };
+        assert_eq!(body.as_ref(), b"q=rust+books&page=2");
+    }
+
+    #[scraped_item]
+    pub struct SearchItem {
+        pub status: u16,
+    }
+
+    pub struct SearchSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for SearchSpider {
+        type Item = SearchItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::post(
+                self.start.clone(),
+                Body::Json(serde_json::json!({"query": "books"})),
+            )])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(SearchItem {
+                status: response.status.as_u16(),
+            });
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retried_post_replays_method_and_body() {
+        let received: Arc<Mutex<Vec<TestRequest>>> = Arc::new(Mutex::new(Vec::new()));
+        let server_received = received.clone();
+        let server = TestServer::start(move |request| {
+            let mut received = server_received.lock().unwrap();
+            received.push(request.clone());
+            if received.len() == 1 {
+                TestResponse::status(503)
+            } else {
+                TestResponse::html("<p>results</p>")
+            }
+        })
+        .await;
+        let collector = spider_lib::testing::CollectorPipeline::new();
+        let crawler = CrawlerBuilder::new(SearchSpider {
+            start: server.url("/search"),
+        })
+        .add_middleware(RetryMiddleware::new().backoff_factor(0.0))
+        .add_pipeline(collector.clone())
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        for request in &received {
+            assert_eq!(request.method, "POST");
+            assert_eq!(request.body, br#"{"query":"books"}"#);
+        }
+        assert_eq!(collector.items().len(), 1);
+        assert_eq!(collector.items()[0].status, 200);
+    }
+}
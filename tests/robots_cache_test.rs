@@ -0,0 +1,131 @@
+#![cfg(feature = "middleware-robots")]
+
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "User-agent: *\nDisallow: /private/\n";
+
+    /// Serves `robots` as robots.txt with `status`, counting how often it is fetched.
+    async fn robots_server(status: u16, robots: &'static str) -> (TestServer, Arc<AtomicUsize>) {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let server = TestServer::start(move |request| {
+            if request.path == "/robots.txt" {
+                counter.fetch_add(1, Ordering::SeqCst);
+                TestResponse::new(status, "text/plain", robots.as_bytes().to_vec())
+            } else {
+                TestResponse::html("<html></html>")
+            }
+        })
+        .await;
+        (server, fetches)
+    }
+
+    async fn check(middleware: &mut RobotsCacheMiddleware, url: Url) -> Result<(), SpiderError> {
+        let client = reqwest::Client::new();
+        match middleware
+            .process_request(&client, Request::new(url))
+            .await?
+        {
+            MiddlewareAction::Continue(_) => Ok(()),
+            _ => panic!("expected the request to continue"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_robots_rules_are_cached() {
+        let (server, fetches) = robots_server(200, ROBOTS).await;
+        let mut robots = RobotsCacheMiddleware::new();
+
+        assert!(check(&mut robots, server.url("/public/a")).await.is_ok());
+        assert!(matches!(
+            check(&mut robots, server.url("/private/b")).await,
+            Err(SpiderError::BlockedByRobotsTxt)
+        ));
+        assert!(check(&mut robots, server.url("/public/c")).await.is_ok());
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(robots.misses(), 1);
+        assert_eq!(robots.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_robots_allows_everything() {
+        let (server, _) = robots_server(404, "").await;
+        let mut robots = RobotsCacheMiddleware::new();
+        assert!(check(&mut robots, server.url("/private/a")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_failure_policy_and_negative_cache() {
+        let (server, fetches) = robots_server(503, "").await;
+        let mut robots = RobotsCacheMiddleware::new().on_failure(RobotsFailurePolicy::Deny);
+
+        for path in ["/a", "/b"] {
+            assert!(matches!(
+                check(&mut robots, server.url(path)).await,
+                Err(SpiderError::BlockedByRobotsTxt)
+            ));
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        let mut retrying = RobotsCacheMiddleware::new().failure_ttl(Duration::ZERO);
+        assert!(check(&mut retrying, server.url("/a")).await.is_ok());
+        assert!(check(&mut retrying, server.url("/b")).await.is_ok());
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_preload_and_persistence() {
+        let (server, fetches) = robots_server(200, ROBOTS).await;
+        let path =
+            std::env::temp_dir().join(format!("spider-robots-cache-{}.json", std::process::id()));
+
+        let robots = RobotsCacheMiddleware::new();
+        robots
+            .preload(
+                &reqwest::Client::new(),
+                &[server.url("/"), server.url("/x")],
+            )
+            .await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        robots.save(&path).unwrap();
+
+        let mut restored = RobotsCacheMiddleware::new().load(&path).unwrap();
+        assert!(matches!(
+            check(&mut restored, server.url("/private/a")).await,
+            Err(SpiderError::BlockedByRobotsTxt)
+        ));
+        assert_eq!(restored.hits(), 1);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        let mut expired = RobotsCacheMiddleware::new()
+            .ttl(Duration::ZERO)
+            .load(&path)
+            .unwrap();
+        assert!(check(&mut expired, server.url("/public")).await.is_ok());
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_impolite_requests_are_not_checked() {
+        let (server, fetches) = robots_server(200, ROBOTS).await;
+        let mut robots = RobotsCacheMiddleware::new();
+        let client = reqwest::Client::new();
+        let request = Request::new(server.url("/private/a")).impolite();
+        let action = robots.process_request(&client, request).await.unwrap();
+        assert!(matches!(action, MiddlewareAction::Continue(_)));
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+    }
+}
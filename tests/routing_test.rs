@@ -0,0 +1,105 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::CollectorPipeline;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Entry {
+        pub kind: String,
+        pub name: String,
+    }
+
+    fn entry(kind: &str, name: &str) -> Entry {
+        Entry {
+            kind: kind.into(),
+            name: name.into(),
+        }
+    }
+
+    fn names(collector: &CollectorPipeline<Entry>) -> Vec<String> {
+        collector
+            .items()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect()
+    }
+
+    /// Drops every item it receives.
+    struct DropPipeline;
+
+    #[async_trait]
+    impl Pipeline<Entry> for DropPipeline {
+        fn name(&self) -> &str {
+            "DropPipeline"
+        }
+
+        async fn process_item(&self, _item: Entry) -> Result<Option<Entry>, PipelineError> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_items_reach_only_the_pipelines_of_their_tag() {
+        let products = CollectorPipeline::new();
+        let reviews = CollectorPipeline::new();
+        let audit = CollectorPipeline::new();
+        let other = CollectorPipeline::new();
+        let router = RoutingPipeline::by_field("kind")
+            .route("product", products.clone())
+            .route("review", reviews.clone())
+            .route("product", audit.clone())
+            .fallback(other.clone());
+
+        for item in [
+            entry("product", "lamp"),
+            entry("review", "five stars"),
+            entry("seller", "acme"),
+            entry("product", "desk"),
+        ] {
+            assert!(router.process_item(item).await.unwrap().is_some());
+        }
+        router.close().await.unwrap();
+
+        assert_eq!(names(&products), ["lamp", "desk"]);
+        assert_eq!(names(&audit), ["lamp", "desk"]);
+        assert_eq!(names(&reviews), ["five stars"]);
+        assert_eq!(names(&other), ["acme"]);
+    }
+
+    #[tokio::test]
+    async fn test_untagged_items_pass_without_a_fallback() {
+        let router = RoutingPipeline::new(|entry: &Entry| {
+            (!entry.kind.is_empty()).then(|| entry.kind.clone())
+        })
+        .route("spam", DropPipeline);
+
+        let passed = router.process_item(entry("", "plain")).await.unwrap();
+        assert_eq!(passed.map(|entry| entry.name).as_deref(), Some("plain"));
+        assert!(
+            router
+                .process_item(entry("spam", "junk"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_dropping_route_stops_the_item() {
+        let after = CollectorPipeline::new();
+        let router = RoutingPipeline::by_field("kind")
+            .route("product", DropPipeline)
+            .route("product", after.clone());
+
+        assert!(
+            router
+                .process_item(entry("product", "lamp"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(after.is_empty());
+    }
+}
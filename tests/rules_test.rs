@@ -0,0 +1,192 @@
+use spider_lib::prelude::*;
+use spider_lib::rules::RULE_KEY;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Book {
+        pub url: String,
+    }
+
+    /// Follows the listing pages and parses the books, without looking at a URL.
+    pub struct BooksSpider;
+
+    impl BooksSpider {
+        async fn parse_book(
+            &self,
+            response: Response,
+            _state: &(),
+        ) -> Result<ParseOutput<Book>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(Book {
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    #[async_trait]
+    impl Spider for BooksSpider {
+        type Item = Book;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://books.example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            _response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            Ok(ParseOutput::new())
+        }
+    }
+
+    impl CallbackSpider for BooksSpider {
+        fn callbacks() -> Callbacks<Self> {
+            Callbacks::<Self>::new().add("parse_book", |spider, response, state| {
+                Box::pin(spider.parse_book(response, state))
+            })
+        }
+    }
+
+    impl RuleSpider for BooksSpider {
+        fn rules() -> Vec<Rule> {
+            vec![
+                Rule::new(LinkExtractor::new().allow(r"/books/").unwrap()).callback("parse_book"),
+                Rule::new(LinkExtractor::new().allow(r"/page-\d+\.html$").unwrap()),
+            ]
+        }
+    }
+
+    const LISTING: &str = r#"<html><body>
+        <a href="/books/dune.html">Dune</a>
+        <a href="/books/emma.html">Emma</a>
+        <a href="/page-2.html">Next</a>
+        <a href="/about.html">About</a>
+    </body></html>"#;
+
+    fn response(path: &str) -> Response {
+        let url = Url::parse("https://books.example.com/")
+            .unwrap()
+            .join(path)
+            .unwrap();
+        MockResponse::new(200)
+            .body(LISTING)
+            .into_response(Request::new(url))
+    }
+
+    /// Returns the response a request would get back, carrying its meta.
+    fn response_to(request: &Request) -> Response {
+        let response = response(request.url.path());
+        for entry in request.meta.iter() {
+            response
+                .meta
+                .insert(entry.key().clone(), entry.value().clone());
+        }
+        response
+    }
+
+    fn paths(requests: &[Request]) -> Vec<&str> {
+        requests.iter().map(|request| request.url.path()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_rules_route_listing_to_detail() {
+        let spider = BooksSpider.with_rules();
+
+        let (items, requests) = spider.parse(response("/"), &()).await.unwrap().into_parts();
+        assert!(items.is_empty());
+        assert_eq!(
+            paths(&requests),
+            ["/books/dune.html", "/books/emma.html", "/page-2.html"]
+        );
+        assert_eq!(requests[0].callback_name().as_deref(), Some("parse_book"));
+        assert_eq!(requests[2].callback_name(), None);
+
+        // A listing page matched by a rule without a callback is followed in turn.
+        let (_, next) = spider
+            .parse(response_to(&requests[2]), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(next.len(), 3);
+
+        // A book page goes to its callback, and its links are not followed.
+        let (items, next) = spider
+            .parse(response_to(&requests[0]), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items[0].url, "https://books.example.com/books/dune.html");
+        assert!(next.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_first_matching_rule_wins() {
+        struct Greedy;
+
+        #[async_trait]
+        impl Spider for Greedy {
+            type Item = Book;
+            type State = ();
+
+            async fn parse(
+                &self,
+                _response: Response,
+                _state: &Self::State,
+            ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+                Ok(ParseOutput::new())
+            }
+        }
+
+        impl CallbackSpider for Greedy {
+            fn callbacks() -> Callbacks<Self> {
+                Callbacks::<Self>::new()
+            }
+        }
+
+        impl RuleSpider for Greedy {
+            fn rules() -> Vec<Rule> {
+                vec![
+                    Rule::new(LinkExtractor::new().allow(r"/books/dune").unwrap())
+                        .callback("parse_dune"),
+                    Rule::new(LinkExtractor::new()).callback("parse_any"),
+                ]
+            }
+        }
+
+        let (_, requests) = Greedy
+            .with_rules()
+            .parse(response("/"), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].url.path(), "/books/dune.html");
+        assert_eq!(requests[0].callback_name().as_deref(), Some("parse_dune"));
+        assert!(
+            requests[1..]
+                .iter()
+                .all(|request| request.callback_name().as_deref() == Some("parse_any"))
+        );
+        assert_eq!(requests[0].meta.get(RULE_KEY).as_deref(), Some(&0.into()));
+    }
+
+    #[test]
+    fn test_follow_defaults_to_rules_without_callback() {
+        assert!(Rule::new(LinkExtractor::new()).follows());
+        assert!(!Rule::new(LinkExtractor::new()).callback("parse").follows());
+        assert!(
+            Rule::new(LinkExtractor::new())
+                .callback("parse")
+                .follow(true)
+                .follows()
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponses;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(index: usize) -> Url {
+        Url::parse(&format!("https://example.com/page/{index}")).unwrap()
+    }
+
+    #[test]
+    fn test_sample_is_deterministic_and_close_to_the_rate() {
+        let sampling = Sampling::new(0.1);
+        let included: Vec<usize> = (0..10_000)
+            .filter(|&index| sampling.includes(&page(index)))
+            .collect();
+        assert!((800..1200).contains(&included.len()), "{}", included.len());
+
+        let again = Sampling::new(0.1);
+        assert!(included.iter().all(|&index| again.includes(&page(index))));
+
+        let larger = Sampling::new(0.5);
+        assert!(included.iter().all(|&index| larger.includes(&page(index))));
+
+        let reseeded = Sampling::with_seed(0.1, 7);
+        assert!(
+            !included
+                .iter()
+                .all(|&index| reseeded.includes(&page(index)))
+        );
+    }
+
+    #[test]
+    fn test_rate_bounds() {
+        assert!(Sampling::new(1.0).includes(&page(1)));
+        assert!(!Sampling::new(0.0).includes(&page(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "sample rate")]
+    fn test_rate_above_one_panics() {
+        Sampling::new(1.5);
+    }
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    pub struct LinksSpider;
+
+    #[async_trait]
+    impl Spider for LinksSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                output.add_requests((0..20).map(|index| Request::new(page(index))));
+            }
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_requests_are_always_crawled() {
+        let sampling = Sampling::new(0.0);
+        let mock = MockResponses::new().html("https://example.com/", "<p>home</p>");
+        let crawler = CrawlerBuilder::new(sampling.wrap(LinksSpider))
+            .add_middleware(mock.clone())
+            .build()
+            .await
+            .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(
+            mock.requested(),
+            vec![Url::parse("https://example.com/").unwrap()]
+        );
+        assert_eq!(sampling.sampled_out(), 20);
+    }
+}
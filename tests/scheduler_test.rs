@@ -0,0 +1,578 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str) -> Request {
+        Request::new(Url::parse(&format!("https://example.com{path}")).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_default_scheduler_is_fifo() {
+        let scheduler = DefaultScheduler::new();
+        for path in ["/a", "/b", "/c"] {
+            scheduler.enqueue(request(path)).await;
+        }
+        assert_eq!(scheduler.len(), 3);
+        assert_eq!(scheduler.dequeue().await.unwrap().url.path(), "/a");
+        assert_eq!(scheduler.dequeue().await.unwrap().url.path(), "/b");
+        assert_eq!(scheduler.dequeue().await.unwrap().url.path(), "/c");
+        assert!(scheduler.dequeue().await.is_none());
+        assert!(scheduler.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_restore_keep_order() {
+        let scheduler = DefaultScheduler::new();
+        scheduler.enqueue(request("/a")).await;
+        scheduler.enqueue(request("/b")).await;
+
+        let restored = DefaultScheduler::new();
+        restored.restore(scheduler.snapshot().await).await;
+        assert_eq!(scheduler.len(), 2);
+        assert_eq!(restored.dequeue().await.unwrap().url.path(), "/a");
+        assert_eq!(restored.dequeue().await.unwrap().url.path(), "/b");
+    }
+
+    #[tokio::test]
+    async fn test_priority_scheduler_orders_by_priority_then_insertion() {
+        let scheduler = PriorityScheduler::new().max_in_flight(100);
+        scheduler.enqueue(request("/next-page")).await;
+        scheduler.enqueue(request("/detail-1").priority(10)).await;
+        scheduler.enqueue(request("/skip").priority(-1)).await;
+        scheduler.enqueue(request("/detail-2").priority(10)).await;
+        scheduler.enqueue(request("/other")).await;
+
+        let restored = PriorityScheduler::new().max_in_flight(100);
+        restored.restore(scheduler.snapshot().await).await;
+
+        for scheduler in [scheduler, restored] {
+            let mut order = Vec::new();
+            while let Some(request) = scheduler.dequeue().await {
+                order.push(request.url.path().to_string());
+            }
+            assert_eq!(
+                order,
+                ["/detail-1", "/detail-2", "/next-page", "/other", "/skip"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priority_scheduler_holds_requests_past_the_limit() {
+        let scheduler = PriorityScheduler::new().max_in_flight(1);
+        scheduler.enqueue(request("/low")).await;
+        let first = scheduler.dequeue().await.unwrap();
+        assert_eq!(first.url.path(), "/low");
+
+        scheduler.enqueue(request("/later")).await;
+        scheduler.enqueue(request("/urgent").priority(5)).await;
+        assert!(scheduler.dequeue().await.is_none());
+        assert_eq!(scheduler.len(), 2);
+
+        scheduler.complete(&first.url).await;
+        assert_eq!(scheduler.dequeue().await.unwrap().url.path(), "/urgent");
+        assert_eq!(request("/x").priority(3).priority_value(), 3);
+        assert_eq!(request("/x").priority_value(), 0);
+    }
+
+    fn queue_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("spider_disk_queue_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    async fn drain(scheduler: &DiskScheduler) -> Vec<String> {
+        let mut order = Vec::new();
+        while let Some(request) = scheduler.dequeue().await {
+            scheduler.complete(&request.url).await;
+            order.push(request.url.path().to_string());
+        }
+        order
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_orders_past_its_buffer() {
+        let dir = queue_dir("order");
+        let scheduler = DiskScheduler::open(&dir).await.unwrap().buffer_size(2);
+        scheduler.enqueue(request("/next-page")).await;
+        scheduler.enqueue(request("/skip").priority(-1)).await;
+        scheduler.enqueue(request("/other")).await;
+        scheduler.enqueue(request("/detail-1").priority(10)).await;
+        scheduler.enqueue(request("/detail-2").priority(10)).await;
+        assert_eq!(scheduler.len(), 5);
+
+        assert_eq!(
+            drain(&scheduler).await,
+            ["/detail-1", "/detail-2", "/next-page", "/other", "/skip"]
+        );
+        assert!(scheduler.is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_resumes_unfinished_requests() {
+        let dir = queue_dir("resume");
+        {
+            let scheduler = DiskScheduler::open(&dir).await.unwrap();
+            for path in ["/a", "/b", "/c", "/d"] {
+                scheduler.enqueue(request(path)).await;
+            }
+            let done = scheduler.dequeue().await.unwrap();
+            scheduler.complete(&done.url).await;
+            // Dispatched, but the process dies before its response arrives.
+            scheduler.dequeue().await.unwrap();
+        }
+
+        let scheduler = DiskScheduler::open(&dir).await.unwrap();
+        assert_eq!(scheduler.len(), 3);
+        assert_eq!(
+            scheduler
+                .snapshot()
+                .await
+                .iter()
+                .map(|request| request.url.path())
+                .collect::<Vec<_>>(),
+            ["/b", "/c", "/d"]
+        );
+        assert_eq!(drain(&scheduler).await, ["/b", "/c", "/d"]);
+        drop(scheduler);
+        assert!(DiskScheduler::open(&dir).await.unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_discards_a_partly_written_entry() {
+        let dir = queue_dir("torn");
+        {
+            let scheduler = DiskScheduler::open(&dir).await.unwrap();
+            scheduler.enqueue(request("/a")).await;
+            scheduler.enqueue(request("/b").priority(1)).await;
+        }
+        let journal = dir.join("queue.jsonl");
+        let mut contents = std::fs::read(&journal).unwrap();
+        contents.extend_from_slice(br#"{"seq":2,"priority":0,"requ"#);
+        std::fs::write(&journal, contents).unwrap();
+
+        let scheduler = DiskScheduler::open(&dir).await.unwrap();
+        scheduler.enqueue(request("/c")).await;
+        assert_eq!(drain(&scheduler).await, ["/b", "/a", "/c"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_frees_the_place_of_a_failed_request() {
+        let dir = queue_dir("failed");
+        let scheduler = DiskScheduler::open(&dir).await.unwrap().max_in_flight(1);
+        scheduler.enqueue(request("/fails")).await;
+        scheduler.enqueue(request("/next")).await;
+
+        let failed = scheduler.dequeue().await.unwrap();
+        assert!(scheduler.dequeue().await.is_none());
+        // The download failed, which completes the request too.
+        scheduler.complete(&failed.url).await;
+        let next = scheduler.dequeue().await.unwrap();
+        assert_eq!(next.url.path(), "/next");
+        drop(scheduler);
+
+        let reopened = DiskScheduler::open(&dir).await.unwrap();
+        assert_eq!(reopened.snapshot().await.len(), 1);
+        assert_eq!(reopened.len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_drops_duplicates_across_restarts() {
+        let dir = queue_dir("duplicates");
+        {
+            let scheduler = DiskScheduler::open(&dir).await.unwrap();
+            scheduler.enqueue(request("/a")).await;
+            scheduler.enqueue(request("/a")).await;
+            scheduler.enqueue(request("/b")).await;
+            assert_eq!(scheduler.len(), 2);
+            let done = scheduler.dequeue().await.unwrap();
+            scheduler.complete(&done.url).await;
+        }
+
+        let scheduler = DiskScheduler::open(&dir).await.unwrap();
+        for path in ["/a", "/b", "/c"] {
+            scheduler.enqueue(request(path)).await;
+        }
+        scheduler.enqueue(request("/a").dont_filter()).await;
+        assert_eq!(drain(&scheduler).await, ["/b", "/c", "/a"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_open_leaves_the_files_alone() {
+        let dir = queue_dir("untouched");
+        {
+            let scheduler = DiskScheduler::open(&dir).await.unwrap();
+            scheduler.enqueue(request("/a")).await;
+            scheduler.enqueue(request("/b")).await;
+            let done = scheduler.dequeue().await.unwrap();
+            scheduler.complete(&done.url).await;
+        }
+        let journal = std::fs::read(dir.join("queue.jsonl")).unwrap();
+        let done = std::fs::read(dir.join("done.log")).unwrap();
+
+        let scheduler = DiskScheduler::open(&dir).await.unwrap();
+        assert_eq!(scheduler.len(), 1);
+        assert_eq!(std::fs::read(dir.join("queue.jsonl")).unwrap(), journal);
+        assert_eq!(std::fs::read(dir.join("done.log")).unwrap(), done);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_scheduler_resumes_a_crawl() {
+        let (server, visited) = site(&[]).await;
+        let dir = queue_dir("crawl");
+        {
+            let scheduler = DiskScheduler::open(&dir).await.unwrap();
+            scheduler.enqueue(Request::new(server.url("/left-1"))).await;
+            scheduler.enqueue(Request::new(server.url("/left-2"))).await;
+        }
+
+        let scheduling = Scheduling::new(DiskScheduler::open(&dir).await.unwrap());
+        let crawler = CrawlerBuilder::new(scheduling.wrap(LinkSpider {
+            start: server.url("/"),
+            priority: |_| 0,
+        }))
+        .scheduler(&scheduling)
+        .max_concurrent_downloads(1)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(*visited.lock().unwrap(), ["/", "/left-1", "/left-2"]);
+        assert!(scheduling.scheduler().is_empty());
+        drop(scheduling);
+        assert!(DiskScheduler::open(&dir).await.unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn on(host: &str, page: usize) -> Request {
+        Request::new(Url::parse(&format!("https://{host}/page/{page}")).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_fair_scheduler_rotates_across_domains() {
+        let scheduler = FairScheduler::new().max_per_domain(100);
+        for page in 0..20 {
+            scheduler.enqueue(on("big.example", page)).await;
+        }
+        for page in 0..3 {
+            scheduler.enqueue(on("small.example", page)).await;
+            scheduler.enqueue(on("tiny.example", page)).await;
+        }
+
+        let mut first_nine = Vec::new();
+        for _ in 0..9 {
+            let request = scheduler.dequeue().await.unwrap();
+            first_nine.push(request.url.host_str().unwrap().to_string());
+        }
+        for host in ["big.example", "small.example", "tiny.example"] {
+            assert_eq!(first_nine.iter().filter(|h| *h == host).count(), 3);
+        }
+        assert_eq!(scheduler.len(), 17);
+    }
+
+    #[tokio::test]
+    async fn test_fair_scheduler_respects_per_domain_limit() {
+        let scheduler = FairScheduler::new().max_per_domain(1);
+        scheduler.enqueue(on("a.example", 1)).await;
+        scheduler.enqueue(on("a.example", 2)).await;
+        scheduler.enqueue(on("b.example", 1)).await;
+
+        let first = scheduler.dequeue().await.unwrap();
+        assert_eq!(first.url.host_str(), Some("a.example"));
+        let second = scheduler.dequeue().await.unwrap();
+        assert_eq!(second.url.host_str(), Some("b.example"));
+        assert!(scheduler.dequeue().await.is_none());
+        assert_eq!(scheduler.len(), 1);
+
+        scheduler.complete(&first.url).await;
+        let third = scheduler.dequeue().await.unwrap();
+        assert_eq!(third.url.as_str(), "https://a.example/page/2");
+    }
+
+    #[tokio::test]
+    async fn test_fair_scheduler_in_flight_slots_expire() {
+        let scheduler = FairScheduler::new()
+            .max_per_domain(1)
+            .in_flight_timeout(Duration::ZERO);
+        scheduler.enqueue(on("a.example", 1)).await;
+        scheduler.enqueue(on("a.example", 2)).await;
+
+        assert!(scheduler.dequeue().await.is_some());
+        assert!(scheduler.dequeue().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_fair_scheduler_snapshot_round_trips() {
+        let scheduler = FairScheduler::new();
+        scheduler.enqueue(on("a.example", 1)).await;
+        scheduler.enqueue(on("b.example", 1)).await;
+        scheduler.enqueue(on("a.example", 2)).await;
+
+        let restored = FairScheduler::new();
+        restored.restore(scheduler.snapshot().await).await;
+        assert_eq!(restored.len(), 3);
+        let mut urls = Vec::new();
+        while let Some(request) = restored.dequeue().await {
+            urls.push(request.url.to_string());
+            restored.complete(&request.url).await;
+        }
+        assert_eq!(
+            urls,
+            [
+                "https://a.example/page/1",
+                "https://b.example/page/1",
+                "https://a.example/page/2",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_drives_a_download_loop() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/" => TestResponse::html("<a href='/page/1'>1</a><a href='/page/2'>2</a>"),
+            _ => TestResponse::html("<p>page</p>"),
+        })
+        .await;
+        let scheduler = PriorityScheduler::new().max_in_flight(1);
+        scheduler.enqueue(Request::new(server.url("/"))).await;
+
+        let downloader = HttpDownloader::new().unwrap();
+        let mut visited = Vec::new();
+        while let Some(request) = scheduler.dequeue().await {
+            let url = request.url.clone();
+            let response = downloader.download(request).await.unwrap();
+            scheduler.complete(&url).await;
+            visited.push(url.path().to_string());
+            // Later pages first.
+            for link in response.links() {
+                let page = link.url.path().trim_start_matches("/page/").parse();
+                scheduler
+                    .enqueue(Request::new(link.url.clone()).priority(page.unwrap_or(0)))
+                    .await;
+            }
+        }
+        assert_eq!(visited, ["/", "/page/2", "/page/1"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    /// Follows every link on the pages it parses, with the priority `priority` gives it.
+    pub struct LinkSpider {
+        start: Url,
+        priority: fn(&Url) -> i32,
+    }
+
+    #[async_trait]
+    impl Spider for LinkSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            for link in response.links() {
+                output.add_request(
+                    Request::new(link.url.clone()).priority((self.priority)(&link.url)),
+                );
+            }
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    /// Starts a server linking `/` to `links`, recording the paths requested.
+    async fn site(links: &'static [&'static str]) -> (TestServer, Arc<Mutex<Vec<String>>>) {
+        let visited = Arc::new(Mutex::new(Vec::new()));
+        let log = visited.clone();
+        let server = TestServer::start(move |request| {
+            log.lock().unwrap().push(request.path.clone());
+            match request.path.as_str() {
+                "/" => TestResponse::html(
+                    &links
+                        .iter()
+                        .map(|link| format!("<a href='{link}'>{link}</a>"))
+                        .collect::<String>(),
+                ),
+                _ => TestResponse::html("<a href='/'>home</a>"),
+            }
+        })
+        .await;
+        (server, visited)
+    }
+
+    /// Downloads the most recently queued request first.
+    #[derive(Default)]
+    struct LifoScheduler {
+        stack: Mutex<Vec<Request>>,
+    }
+
+    #[async_trait]
+    impl RequestScheduler for LifoScheduler {
+        async fn enqueue(&self, request: Request) {
+            self.stack.lock().unwrap().push(request);
+        }
+
+        async fn dequeue(&self) -> Option<Request> {
+            self.stack.lock().unwrap().pop()
+        }
+
+        fn len(&self) -> usize {
+            self.stack.lock().unwrap().len()
+        }
+
+        async fn snapshot(&self) -> Vec<Request> {
+            self.stack.lock().unwrap().clone()
+        }
+
+        async fn restore(&self, requests: Vec<Request>) {
+            self.stack.lock().unwrap().extend(requests);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_scheduler_orders_a_crawl() {
+        let (server, visited) = site(&["/1", "/2", "/3", "/"]).await;
+        let scheduling = Scheduling::new(LifoScheduler::default());
+        let crawler = CrawlerBuilder::new(scheduling.wrap(LinkSpider {
+            start: server.url("/"),
+            priority: |_| 0,
+        }))
+        .scheduler(&scheduling)
+        .max_concurrent_downloads(1)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        // Links back to the start page are not scheduled again.
+        assert_eq!(*visited.lock().unwrap(), ["/", "/3", "/2", "/1"]);
+        assert!(scheduling.scheduler().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_priority_orders_a_crawl() {
+        let (server, visited) = site(&["/next", "/detail-1", "/detail-2"]).await;
+        let scheduling = Scheduling::new(PriorityScheduler::new());
+        let crawler = CrawlerBuilder::new(scheduling.wrap(LinkSpider {
+            start: server.url("/"),
+            priority: |url| {
+                if url.path().starts_with("/detail") {
+                    10
+                } else {
+                    0
+                }
+            },
+        }))
+        .scheduler(&scheduling)
+        .max_concurrent_downloads(1)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(
+            *visited.lock().unwrap(),
+            ["/", "/detail-1", "/detail-2", "/next"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fair_scheduler_alternates_hosts_in_a_crawl() {
+        let hosts = Arc::new(Mutex::new(Vec::new()));
+        let log = hosts.clone();
+        // `localhost` reaches the same server under another host name.
+        let server = TestServer::start(move |request| {
+            let host = &request.headers["host"];
+            let (name, port) = host.rsplit_once(':').unwrap();
+            if request.path != "/" {
+                log.lock().unwrap().push(name.to_string());
+                return TestResponse::html("<p>page</p>");
+            }
+            let mut links: String = (1..=6)
+                .map(|page| format!("<a href='/big/{page}'>{page}</a>"))
+                .collect();
+            for page in 1..=2 {
+                links.push_str(&format!(
+                    "<a href='http://localhost:{port}/small/{page}'>{page}</a>"
+                ));
+            }
+            TestResponse::html(&links)
+        })
+        .await;
+
+        let scheduling = Scheduling::new(FairScheduler::new().max_per_domain(100));
+        let crawler = CrawlerBuilder::new(scheduling.wrap(LinkSpider {
+            start: server.url("/"),
+            priority: |_| 0,
+        }))
+        .scheduler(&scheduling)
+        .max_concurrent_downloads(1)
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let hosts = hosts.lock().unwrap();
+        assert_eq!(
+            hosts[..4],
+            ["127.0.0.1", "localhost", "127.0.0.1", "localhost"]
+        );
+        assert_eq!(hosts.iter().filter(|host| *host == "127.0.0.1").count(), 6);
+        assert_eq!(hosts.len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_parked_slots_are_left_out_of_the_stats() {
+        let (server, visited) = site(&["/1", "/2", "/3", "/4", "/5"]).await;
+        let scheduling = Scheduling::new(FairScheduler::new().max_per_domain(1));
+        let crawler = CrawlerBuilder::new(scheduling.wrap(LinkSpider {
+            start: server.url("/"),
+            priority: |_| 0,
+        }))
+        .scheduler(&scheduling)
+        .max_concurrent_downloads(4)
+        .build()
+        .await
+        .unwrap();
+        let stats = crawler.get_stats();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(visited.lock().unwrap().len(), 6);
+        // Nothing failed, so the crawl retried and dropped nothing of its own.
+        let snapshot = stats.snapshot().excluding(scheduling.traffic());
+        assert_eq!(snapshot.requests.retried, 0);
+        assert_eq!(snapshot.requests.dropped, 0);
+        let health = HealthMonitor::new(stats)
+            .excluding(scheduling.traffic())
+            .check();
+        assert_eq!(health.queued_requests, 0);
+    }
+}
@@ -0,0 +1,112 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct Page {
+        pub url: String,
+    }
+
+    pub struct CatalogueSpider {
+        denied: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl Spider for CatalogueSpider {
+        type Item = Page;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://books.example.com/index.html"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(Page {
+                url: response.url.to_string(),
+            });
+            for link in [
+                "https://books.example.com/catalogue/book_1.html",
+                "https://books.example.com/catalogue/category/travel.html",
+                "https://books.example.com/about.html",
+                "https://other.example.com/catalogue/book_2.html",
+            ] {
+                output.add_request(Request::new(Url::parse(link)?));
+            }
+            Ok(output)
+        }
+    }
+
+    impl ScopedSpider for CatalogueSpider {
+        fn allowed_url_patterns(&self) -> Vec<&'static str> {
+            vec![r"^https://books\.example\.com/catalogue/"]
+        }
+
+        fn denied_url_patterns(&self) -> Vec<&'static str> {
+            self.denied.clone()
+        }
+    }
+
+    fn response() -> Response {
+        let request = Request::new(Url::parse("https://books.example.com/index.html").unwrap());
+        MockResponse::new(200)
+            .body("<html></html>")
+            .into_response(request)
+    }
+
+    #[tokio::test]
+    async fn test_denied_takes_precedence_over_allowed() {
+        let spider = CatalogueSpider {
+            denied: vec![r"/category/"],
+        }
+        .scoped()
+        .unwrap();
+        assert_eq!(Spider::start_requests(&spider).unwrap().len(), 1);
+
+        let (items, requests) = Spider::parse(&spider, response(), &())
+            .await
+            .unwrap()
+            .into_parts();
+        assert_eq!(items.len(), 1);
+        let urls: Vec<&str> = requests
+            .iter()
+            .map(|request| request.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            vec!["https://books.example.com/catalogue/book_1.html"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern_fails_fast() {
+        let error = CatalogueSpider {
+            denied: vec![r"(unclosed"],
+        }
+        .scoped()
+        .err()
+        .unwrap();
+        let SpiderError::ConfigurationError(message) = error else {
+            panic!("an invalid pattern must be a configuration error");
+        };
+        assert!(message.contains("(unclosed"));
+    }
+
+    #[test]
+    fn test_empty_scope_allows_everything() {
+        let scope = UrlScope::new(&[], &[]).unwrap();
+        assert!(scope.allows(&Url::parse("https://anything.example.com/").unwrap()));
+
+        let scope = UrlScope::new(&[], &[r"\.pdf$"]).unwrap();
+        assert!(!scope.allows(&Url::parse("https://example.com/file.pdf").unwrap()));
+        assert!(scope.allows(&Url::parse("https://example.com/file.html").unwrap()));
+    }
+}
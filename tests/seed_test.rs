@@ -0,0 +1,98 @@
+mod common;
+
+use common::{TestRequest, TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub path: String,
+    }
+
+    /// Seeds the crawl with pages whose ids are looked up asynchronously.
+    pub struct LookupSpider {
+        base: Url,
+        parsed: Arc<Mutex<Vec<String>>>,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Spider for LookupSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["http://127.0.0.1:1/never"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let text = String::from_utf8_lossy(&response.body).to_string();
+            self.parsed.lock().unwrap().push(text);
+            Ok(ParseOutput::new())
+        }
+    }
+
+    #[async_trait]
+    impl AsyncStartSpider for LookupSpider {
+        async fn start_requests_async(&self) -> Result<Vec<Request>, SpiderError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            let ids = async { vec![3, 7] }.await;
+            ids.into_iter()
+                .map(|id| {
+                    Request::new(self.base.join(&format!("/items/{id}"))?)
+                        .with_header("x-token", "secret")
+                })
+                .collect()
+        }
+    }
+
+    fn echo(request: &TestRequest) -> TestResponse {
+        let token = request.headers.get("x-token").cloned().unwrap_or_default();
+        let body = format!("{} {}", request.path, token);
+        TestResponse::new(200, "text/plain", body.into_bytes())
+    }
+
+    /// Crawls with the seeded spider, checking the seeds are only looked up once the
+    /// crawl starts.
+    async fn crawl_seeded() {
+        let server = TestServer::start(echo).await;
+        let parsed = Arc::new(Mutex::new(Vec::new()));
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let spider = LookupSpider {
+            base: server.url("/"),
+            parsed: parsed.clone(),
+            lookups: lookups.clone(),
+        }
+        .seeded();
+        assert!(spider.requests().is_none());
+
+        let crawler = CrawlerBuilder::new(spider).build().await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 0);
+        crawler.start_crawl().await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        let mut parsed = parsed.lock().unwrap().clone();
+        parsed.sort();
+        assert_eq!(parsed, ["/items/3 secret", "/items/7 secret"]);
+    }
+
+    #[tokio::test]
+    async fn test_seeded_spider_starts_from_async_requests() {
+        crawl_seeded().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_seeded_spider_on_a_multi_threaded_runtime() {
+        crawl_seeded().await;
+    }
+}
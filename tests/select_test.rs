@@ -0,0 +1,69 @@
+use scraper::{ElementRef, Html};
+use spider_lib::prelude::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_css_helpers_on_pages_and_elements() {
+        let html = Html::parse_document(
+            r#"<article class="book">
+                <h3><a title="A Light in the Attic" href="a-light/index.html">A Light...</a></h3>
+                <p class="price">  £51.77 </p>
+            </article>
+            <article class="book">
+                <h3><a href="tipping/index.html">Tipping the Velvet</a></h3>
+                <p class="price"></p>
+            </article>"#,
+        );
+
+        assert_eq!(
+            html.css_text("h3 a").unwrap().as_deref(),
+            Some("A Light...")
+        );
+        assert_eq!(
+            html.css_attr("h3 a", "title").unwrap().as_deref(),
+            Some("A Light in the Attic")
+        );
+        assert_eq!(html.css_text("table").unwrap(), None);
+        assert_eq!(html.css_attr("h3 a", "rel").unwrap(), None);
+        assert!(html.css_first("h3 >>> a").is_err());
+
+        let second = html
+            .select(&"article.book".to_selector().unwrap())
+            .nth(1)
+            .unwrap();
+        assert_eq!(
+            second.css_attr("a", "href").unwrap().as_deref(),
+            Some("tipping/index.html")
+        );
+        assert_eq!(second.css_text(".price").unwrap().as_deref(), Some(""));
+        assert!(second.css_first("a[title]").unwrap().is_none());
+
+        let first = html.css_first("article.book").unwrap().unwrap();
+        assert_eq!(first.css_text(".price").unwrap().as_deref(), Some("£51.77"));
+    }
+
+    /// Drills into `book` and returns an element that outlives it.
+    fn title_link<'a>(book: ElementRef<'a>) -> Option<ElementRef<'a>> {
+        book.css_first("h3").unwrap()?.css_first("a").unwrap()
+    }
+
+    #[test]
+    fn test_css_first_borrows_the_page() {
+        let html = Html::parse_document(
+            r#"<article><h3><a href="one.html">One</a></h3></article>
+            <article><h3><a href="two.html">Two</a></h3></article>"#,
+        );
+        let links: Vec<ElementRef<'_>> = html
+            .select(&"article".to_selector().unwrap())
+            .filter_map(title_link)
+            .collect();
+        let hrefs: Vec<_> = links
+            .iter()
+            .filter_map(|link| link.value().attr("href"))
+            .collect();
+        assert_eq!(hrefs, ["one.html", "two.html"]);
+    }
+}
@@ -0,0 +1,80 @@
+//! Sends SIGINT to the test process, so it runs in a binary of its own.
+
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    /// Follows an endless chain of pages.
+    pub struct EndlessSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for EndlessSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let page: u64 = response.url.path()[1..].parse().unwrap_or(0);
+            let mut output = ParseOutput::new();
+            output.add_request(Request::new(response.url.join(&format!("/{}", page + 1))?));
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            Ok(output)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_shuts_the_crawl_down_gracefully() {
+        let server = TestServer::start(|_| TestResponse::html("<p>page</p>")).await;
+        let control = CrawlControl::new();
+        let crawler = CrawlerBuilder::new(EndlessSpider {
+            start: server.url("/0"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+
+        tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            std::process::Command::new("kill")
+                .args(["-INT", &std::process::id().to_string()])
+                .status()
+                .unwrap();
+        });
+        let summary = tokio::time::timeout(Duration::from_secs(30), control.run(crawler))
+            .await
+            .expect("crawl did not stop on SIGINT");
+
+        assert!(matches!(summary.reason, CloseReason::Shutdown));
+        assert!(
+            summary
+                .stats
+                .items_scraped
+                .load(std::sync::atomic::Ordering::SeqCst)
+                > 0
+        );
+    }
+}
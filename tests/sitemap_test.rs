@@ -0,0 +1,140 @@
+#![cfg(feature = "sitemap")]
+
+use flate2::{Compression, write::GzEncoder};
+use spider_lib::prelude::*;
+use std::io::Write;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <url>
+                <loc>https://example.com/</loc>
+                <lastmod>2024-05-01</lastmod>
+                <changefreq>daily</changefreq>
+            </url>
+            <url><loc> https://example.com/search?q=a&amp;page=2 </loc></url>
+            <url><loc><![CDATA[https://example.com/cdata]]></loc><lastmod></lastmod></url>
+            <url><loc>/relative/is/invalid</loc></url>
+        </urlset>"#;
+
+    fn urls(sitemap: &Sitemap) -> Vec<&str> {
+        sitemap
+            .entries()
+            .iter()
+            .map(|entry| entry.url.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_urlset() {
+        let sitemap = parse_sitemap(URLSET.as_bytes()).unwrap();
+        assert!(matches!(sitemap, Sitemap::UrlSet(_)));
+        assert_eq!(
+            urls(&sitemap),
+            vec![
+                "https://example.com/",
+                "https://example.com/search?q=a&page=2",
+                "https://example.com/cdata",
+            ]
+        );
+        assert_eq!(sitemap.entries()[0].lastmod.as_deref(), Some("2024-05-01"));
+        assert_eq!(sitemap.entries()[2].lastmod, None);
+    }
+
+    #[test]
+    fn test_parse_gzipped_index() {
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+            <sitemap>
+                <loc>https://example.com/sitemap-1.xml.gz</loc>
+                <lastmod>2024-01-01T00:00:00Z</lastmod>
+            </sitemap>
+            <sitemap><loc>https://example.com/sitemap-2.xml</loc></sitemap>
+        </sitemapindex>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(index.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let sitemap = parse_sitemap(&gzipped).unwrap();
+        assert!(matches!(sitemap, Sitemap::Index(_)));
+        assert_eq!(
+            urls(&sitemap),
+            vec![
+                "https://example.com/sitemap-1.xml.gz",
+                "https://example.com/sitemap-2.xml"
+            ]
+        );
+        assert_eq!(
+            sitemap.entries()[0].lastmod.as_deref(),
+            Some("2024-01-01T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_invalid_sitemaps() {
+        assert_eq!(
+            parse_sitemap(b"<html><body>Not found</body></html>"),
+            Err(SitemapError::NotSitemap)
+        );
+        assert_eq!(parse_sitemap(b""), Err(SitemapError::NotSitemap));
+        assert_eq!(parse_sitemap(b"<urlset/>"), Ok(Sitemap::UrlSet(Vec::new())));
+        assert!(matches!(
+            parse_sitemap(b"<urlset><url><loc>x</url></urlset>"),
+            Err(SitemapError::Xml(_))
+        ));
+        assert!(matches!(
+            parse_sitemap(&[0x1f, 0x8b, 0x08, 0x00, 0x01]),
+            Err(SitemapError::Gzip(_))
+        ));
+    }
+
+    #[test]
+    fn test_sitemap_discovery() {
+        let robots_url = Url::parse("https://example.com/robots.txt").unwrap();
+        let robots = concat!(
+            "User-agent: *\n",
+            "Disallow: /admin\n",
+            "Sitemap: https://example.com/sitemap_index.xml\n",
+            "sitemap: /news.xml # news\n",
+        );
+        let sitemaps: Vec<String> = sitemaps_from_robots(robots, &robots_url)
+            .iter()
+            .map(Url::to_string)
+            .collect();
+        assert_eq!(
+            sitemaps,
+            vec![
+                "https://example.com/sitemap_index.xml",
+                "https://example.com/news.xml"
+            ]
+        );
+
+        let page = Url::parse("https://example.com/a/b?c=d").unwrap();
+        assert_eq!(
+            default_sitemap_url(&page).unwrap().as_str(),
+            "https://example.com/sitemap.xml"
+        );
+    }
+
+    #[test]
+    fn test_sitemap_requests_mark_nested_sitemaps() {
+        let seed = Request::sitemap(Url::parse("https://example.com/sitemap.xml.gz").unwrap());
+        assert!(seed.is_sitemap());
+        assert!(!Request::new(seed.url.clone()).is_sitemap());
+
+        let index = Sitemap::Index(vec![SitemapEntry {
+            url: Url::parse("https://example.com/posts.xml").unwrap(),
+            lastmod: None,
+        }]);
+        let nested = index.requests();
+        assert_eq!(nested[0].url.as_str(), "https://example.com/posts.xml");
+        assert!(nested[0].is_sitemap());
+
+        let pages = parse_sitemap(URLSET.as_bytes()).unwrap().requests();
+        assert!(!pages.is_empty());
+        assert!(pages.iter().all(|request| !request.is_sitemap()));
+    }
+}
@@ -0,0 +1,79 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(url: &str, status: u16, body: &'static str) -> Response {
+        let request = Request::new(Url::parse(url).unwrap());
+        MockResponse::new(status).body(body).into_response(request)
+    }
+
+    /// Returns whether `middleware` passes on a response from `url`.
+    async fn kept(
+        middleware: &mut Soft404Middleware,
+        url: &str,
+        status: u16,
+        body: &'static str,
+    ) -> bool {
+        let action = Middleware::<()>::process_response(middleware, response(url, status, body))
+            .await
+            .unwrap();
+        matches!(action, MiddlewareAction::Continue(_))
+    }
+
+    #[tokio::test]
+    async fn test_soft_404s_are_dropped_per_domain() {
+        let mut middleware = Soft404Middleware::new()
+            .matcher(Soft404Matcher::contains("Page not found"))
+            .domain(
+                "shop.example",
+                Soft404Matcher::regex(r"(?i)no longer available").unwrap(),
+            );
+        let counts = middleware.clone();
+
+        let missing = "<html><h1>Page not found</h1></html>";
+        let gone = "<html><h1>This product is No Longer Available</h1></html>";
+        let product = "<html><h1>Blue kettle</h1></html>";
+
+        for (url, status, body, expected) in [
+            ("https://blog.example/a", 200, missing, false),
+            ("https://shop.example/x", 200, missing, false),
+            ("https://www.shop.example/y", 200, gone, false),
+            ("https://blog.example/b", 200, gone, true),
+            ("https://shop.example/z", 200, product, true),
+            // A real 404 is left to the status handling.
+            ("https://blog.example/c", 404, missing, true),
+        ] {
+            assert_eq!(
+                kept(&mut middleware, url, status, body).await,
+                expected,
+                "{url}"
+            );
+        }
+
+        assert_eq!(counts.dropped(), 3);
+        let by_domain = counts.dropped_by_domain();
+        assert_eq!(by_domain.get("blog.example"), Some(&1));
+        assert_eq!(by_domain.get("shop.example"), Some(&1));
+        assert_eq!(by_domain.get("www.shop.example"), Some(&1));
+
+        let snapshot = StatsSnapshot::default().with_soft_404s(by_domain);
+        assert_eq!(snapshot.to_json()["soft_404s"]["blog.example"], 1);
+    }
+
+    #[test]
+    fn test_matcher_can_be_scoped_to_a_selector() {
+        let matcher = Soft404Matcher::contains("not found")
+            .within("title, h1")
+            .unwrap();
+        let page = |body| response("https://example.com/", 200, body);
+
+        assert!(matcher.matches(&page("<title>Item not found</title><p>Try again</p>")));
+        assert!(!matcher.matches(&page("<h1>Search</h1><p>Your search: not found</p>")));
+        assert!(Soft404Matcher::contains("x").within("h1 >>> p").is_err());
+        assert!(Soft404Matcher::regex("(unclosed").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct PageItem {
+        pub url: String,
+    }
+
+    pub struct PagesSpider {
+        start: Url,
+    }
+
+    #[async_trait]
+    impl Spider for PagesSpider {
+        type Item = PageItem;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![Request::new(self.start.clone())])
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(PageItem {
+                url: response.url.to_string(),
+            });
+            if response.url.path() == "/" {
+                for path in ["/page/1", "/page/2", "/missing"] {
+                    output.add_request(Request::new(response.url.join(path)?));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    #[test]
+    fn test_snapshot_reads_every_counter() {
+        let stats = StatCollector::default();
+        stats.requests_enqueued.store(5, Ordering::SeqCst);
+        stats.requests_sent.store(4, Ordering::SeqCst);
+        stats.requests_succeeded.store(3, Ordering::SeqCst);
+        stats.requests_failed.store(1, Ordering::SeqCst);
+        stats.responses_received.store(3, Ordering::SeqCst);
+        stats.total_bytes_downloaded.store(2048, Ordering::SeqCst);
+        stats.items_scraped.store(2, Ordering::SeqCst);
+        stats.response_status_counts.insert(200, 3);
+        for url in [
+            "https://example.com/",
+            "https://example.com/a",
+            "https://docs.example.org/",
+        ] {
+            stats.record_request_time(url, Duration::from_millis(10));
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.requests.enqueued, 5);
+        assert_eq!(snapshot.requests.sent, 4);
+        assert_eq!(snapshot.requests.failed, 1);
+        assert_eq!(snapshot.responses.by_status.get(&200), Some(&3));
+        assert_eq!(snapshot.items.scraped, 2);
+        assert_eq!(snapshot.total_bytes_downloaded, 2048);
+        assert_eq!(snapshot.requests_by_domain.get("example.com"), Some(&2));
+        assert_eq!(
+            snapshot.requests_by_domain.get("docs.example.org"),
+            Some(&1)
+        );
+        assert!(snapshot.requests_per_second > 0.0);
+
+        let json = stats.to_json();
+        assert_eq!(json["requests"]["sent"], 4);
+        assert_eq!(json["responses"]["by_status"]["200"], 3);
+        assert_eq!(json["requests_by_domain"]["example.com"], 2);
+        assert!(json["elapsed_secs"].is_number());
+    }
+
+    #[tokio::test]
+    async fn test_stats_file_is_written_when_the_crawl_ends() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/missing" => TestResponse::status(404),
+            _ => TestResponse::html("<html><body>page</body></html>"),
+        })
+        .await;
+        let path = std::env::temp_dir().join(format!("spider-stats-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let control = CrawlControl::new().stats_file(&path);
+        let crawler = CrawlerBuilder::new(PagesSpider {
+            start: server.url("/"),
+        })
+        .add_middleware(control.middleware())
+        .build()
+        .await
+        .unwrap();
+        control.run(crawler).await;
+
+        let written: StatsSnapshot =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written.requests.sent, 4);
+        assert_eq!(written.responses.by_status.get(&200), Some(&3));
+        assert_eq!(written.responses.by_status.get(&404), Some(&1));
+        assert_eq!(written.items.scraped, 4);
+        assert_eq!(written.requests_by_domain.get("127.0.0.1"), Some(&4));
+        assert!(written.total_bytes_downloaded > 0);
+        let sizes = written.response_sizes.unwrap();
+        assert_eq!(sizes.responses, 4);
+        assert_eq!(
+            sizes.bytes_downloaded,
+            control.byte_stats().bytes_downloaded()
+        );
+        assert_eq!(sizes.min_bytes, Some(0));
+        assert_eq!(sizes.max_bytes, Some(30));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_byte_stats_track_response_sizes() {
+        let bytes = ByteStats::new();
+        assert_eq!(bytes.min_response_bytes(), None);
+        assert_eq!(bytes.avg_response_bytes(), None);
+        assert_eq!(bytes.to_string(), "downloaded 0 B in 0 responses");
+
+        bytes.record_body(512);
+        bytes.record_body(3 * 1024 * 1024);
+        bytes.clone().record_body(2048);
+        bytes.add_bytes(100);
+
+        assert_eq!(bytes.responses(), 3);
+        assert_eq!(bytes.bytes_downloaded(), 512 + 3 * 1024 * 1024 + 2048 + 100);
+        assert_eq!(bytes.min_response_bytes(), Some(512));
+        assert_eq!(bytes.max_response_bytes(), Some(3 * 1024 * 1024));
+        assert_eq!(
+            bytes.avg_response_bytes(),
+            Some((512 + 3 * 1024 * 1024 + 2048) as f64 / 3.0)
+        );
+        assert_eq!(
+            bytes.to_string(),
+            "downloaded 3.00 MiB in 3 responses \
+             (smallest 512 B, average 1.00 MiB, largest 3.00 MiB)"
+        );
+
+        let snapshot = StatsSnapshot::default().with_byte_stats(&bytes);
+        assert_eq!(
+            snapshot.to_json()["response_sizes"]["max_bytes"],
+            3 * 1024 * 1024
+        );
+        assert!(
+            StatsSnapshot::default()
+                .to_json()
+                .get("response_sizes")
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use spider_lib::prelude::*;
+use spider_lib::stream::BODY_TRUNCATED_KEY;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves a chunked HTML page that never ends, one link per chunk, pausing `pause`
+    /// between chunks.
+    async fn serve_endless(pause: Duration) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let head = "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\
+                                transfer-encoding: chunked\r\n\r\n";
+                    if socket.write_all(head.as_bytes()).await.is_err() {
+                        return;
+                    }
+                    let mut chunk = String::from("<html><body>");
+                    for page in 0.. {
+                        chunk.push_str(&format!("<a href=\"/page/{page}\">{page}</a>"));
+                        let frame = format!("{:x}\r\n{chunk}\r\n", chunk.len());
+                        if socket.write_all(frame.as_bytes()).await.is_err() {
+                            return;
+                        }
+                        chunk.clear();
+                        tokio::time::sleep(pause).await;
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    fn url_of(addr: SocketAddr) -> Url {
+        Url::parse(&format!("http://{addr}/")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit_truncates_endless_body() {
+        let addr = serve_endless(Duration::ZERO).await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let response = downloader
+            .stream(Request::new(url_of(addr)))
+            .await
+            .unwrap()
+            .with_byte_limit(1000)
+            .into_response()
+            .await
+            .unwrap();
+
+        assert_eq!(response.body.len(), 1000);
+        assert_eq!(
+            response.meta.get(BODY_TRUNCATED_KEY).map(|v| v.clone()),
+            Some(true.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit_can_fail_instead() {
+        let addr = serve_endless(Duration::ZERO).await;
+        let downloader = HttpDownloader::builder()
+            .max_body_bytes(1000)
+            .on_body_limit(LimitAction::Fail)
+            .build()
+            .unwrap();
+
+        let result = downloader.download(Request::new(url_of(addr))).await;
+        assert!(matches!(result, Err(SpiderError::GeneralError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_time_limit_stops_slow_body() {
+        let addr = serve_endless(Duration::from_millis(20)).await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let mut stream = downloader
+            .stream(Request::new(url_of(addr)))
+            .await
+            .unwrap()
+            .with_time_limit(Duration::from_millis(200));
+        while stream.chunk().await.unwrap().is_some() {}
+
+        assert!(stream.is_truncated());
+        assert!(stream.bytes_read() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_bytes_are_counted_as_they_arrive() {
+        let addr = serve_endless(Duration::ZERO).await;
+        let downloader = HttpDownloader::new().unwrap();
+        let bytes = downloader.byte_stats();
+
+        let mut stream = downloader.stream(Request::new(url_of(addr))).await.unwrap();
+        stream.chunk().await.unwrap().unwrap();
+        stream.chunk().await.unwrap().unwrap();
+        assert_eq!(bytes.bytes_downloaded(), stream.bytes_read() as u64);
+        assert_eq!(bytes.responses(), 0);
+        drop(stream);
+
+        downloader
+            .stream(Request::new(url_of(addr)))
+            .await
+            .unwrap()
+            .with_byte_limit(1000)
+            .into_response()
+            .await
+            .unwrap();
+        assert_eq!(bytes.responses(), 1);
+        assert_eq!(bytes.max_response_bytes(), Some(1000));
+        assert!(bytes.bytes_downloaded() > 1000);
+    }
+    #[tokio::test]
+    async fn test_into_links_reads_links_from_the_part_read() {
+        let addr = serve_endless(Duration::ZERO).await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let links = downloader
+            .stream(Request::new(url_of(addr)))
+            .await
+            .unwrap()
+            .with_byte_limit(2000)
+            .into_links(&LinkExtractor::new())
+            .await
+            .unwrap();
+
+        assert!(!links.is_empty());
+        assert_eq!(links[0].url.path(), "/page/0");
+    }
+}
@@ -0,0 +1,165 @@
+use scraper::Html;
+use spider_lib::prelude::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tables_of(html: &str) -> Vec<Table> {
+        extract_tables(&Html::parse_document(html))
+    }
+
+    #[test]
+    fn test_thead_headers_and_records() {
+        let tables = tables_of(
+            r#"<table>
+                <caption> Prices </caption>
+                <thead><tr><th>Name</th><th>Price</th></tr></thead>
+                <tbody>
+                    <tr><td>Apple</td><td> 1.00 </td></tr>
+                    <tr><td>Pear</td><td>2.50</td></tr>
+                </tbody>
+            </table>"#,
+        );
+
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0];
+        assert_eq!(table.caption.as_deref(), Some("Prices"));
+        assert_eq!(table.headers, vec!["Name", "Price"]);
+        assert_eq!(
+            table.rows,
+            vec![vec!["Apple", "1.00"], vec!["Pear", "2.50"]]
+        );
+
+        let records = table.records();
+        assert_eq!(records[1]["Name"], "Pear");
+        assert_eq!(records[1]["Price"], "2.50");
+    }
+
+    #[test]
+    fn test_leading_th_row_is_header_but_row_labels_are_data() {
+        let tables = tables_of(
+            r#"<table>
+                <tr><th>UPC</th><td>a897fe39b1053632</td></tr>
+                <tr><th>Number of reviews</th><td>0</td></tr>
+            </table>"#,
+        );
+
+        let table = &tables[0];
+        assert!(table.headers.is_empty());
+        assert_eq!(table.rows.len(), 2);
+
+        let details = table.key_values();
+        assert_eq!(details["UPC"], "a897fe39b1053632");
+        assert_eq!(details["Number of reviews"], "0");
+
+        let headed =
+            tables_of("<table><tr><th>A</th><th>B</th></tr><tr><td>1</td><td>2</td></tr></table>");
+        assert_eq!(headed[0].headers, vec!["A", "B"]);
+        assert_eq!(headed[0].rows, vec![vec!["1", "2"]]);
+    }
+
+    #[test]
+    fn test_colspan_and_rowspan_expansion() {
+        let tables = tables_of(
+            r#"<table>
+                <tr><th>Region</th><th>City</th><th>Q1</th><th>Q2</th></tr>
+                <tr><td rowspan="2">North</td><td>Oslo</td><td colspan="2">10</td></tr>
+                <tr><td>Bergen</td><td>3</td><td>4</td></tr>
+                <tr><td colspan="2">Total</td><td>13</td><td rowspan="3">14</td></tr>
+                <tr><td>x</td></tr>
+            </table>"#,
+        );
+
+        let table = &tables[0];
+        assert_eq!(table.headers, vec!["Region", "City", "Q1", "Q2"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                vec!["North", "Oslo", "10", "10"],
+                vec!["North", "Bergen", "3", "4"],
+                vec!["Total", "Total", "13", "14"],
+                vec!["x", "", "", "14"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rowspan_stays_within_its_row_group() {
+        let tables = tables_of(
+            r#"<table>
+                <thead><tr><th>Group</th><th>Item</th></tr></thead>
+                <tbody>
+                    <tr><td rowspan="0">Fruit</td><td>Apple</td></tr>
+                    <tr><td>Pear</td></tr>
+                    <tr><td>Plum</td></tr>
+                </tbody>
+                <tbody>
+                    <tr><td rowspan="5">Veg</td><td>Leek</td></tr>
+                    <tr><td>Kale</td></tr>
+                </tbody>
+                <tfoot><tr><td>Total</td><td>5</td></tr></tfoot>
+            </table>"#,
+        );
+
+        assert_eq!(
+            tables[0].rows,
+            vec![
+                vec!["Fruit", "Apple"],
+                vec!["Fruit", "Pear"],
+                vec!["Fruit", "Plum"],
+                vec!["Veg", "Leek"],
+                vec!["Veg", "Kale"],
+                vec!["Total", "5"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_values_pads_missing_values() {
+        let tables = tables_of(
+            r#"<table>
+                <tr><th>Author</th><td>Jane</td><td>extra</td></tr>
+                <tr><th>Publisher</th></tr>
+            </table>"#,
+        );
+
+        let details = tables[0].key_values();
+        assert_eq!(details["Author"], "Jane");
+        assert_eq!(details["Publisher"], "");
+        assert!(
+            tables_of("<table><tr><td>alone</td></tr></table>")[0]
+                .key_values()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_grouped_header_rows_are_joined() {
+        let tables = tables_of(
+            r#"<table>
+                <thead>
+                    <tr><th rowspan="2">Name</th><th colspan="2">Score</th></tr>
+                    <tr><th>Home</th><th>Away</th></tr>
+                </thead>
+                <tr><td>Team</td><td>1</td><td>2</td></tr>
+            </table>"#,
+        );
+
+        assert_eq!(tables[0].headers, vec!["Name", "Score Home", "Score Away"]);
+        assert_eq!(tables[0].records()[0]["Score Away"], "2");
+    }
+
+    #[test]
+    fn test_nested_tables_are_returned_separately() {
+        let tables = tables_of(
+            r#"<table>
+                <tr><td>outer <table><tr><td>inner</td></tr></table></td><td>b</td></tr>
+            </table>"#,
+        );
+
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].rows, vec![vec!["outer", "b"]]);
+        assert_eq!(tables[1].rows, vec![vec!["inner"]]);
+    }
+}
@@ -0,0 +1,114 @@
+use spider_lib::prelude::*;
+use spider_lib::testing::{CollectorPipeline, MockResponse, MockResponses};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[scraped_item]
+    pub struct TitleItem {
+        pub url: String,
+        pub title: String,
+    }
+
+    pub struct TitlesSpider;
+
+    #[async_trait]
+    impl Spider for TitlesSpider {
+        type Item = TitleItem;
+        type State = ();
+
+        fn start_urls(&self) -> Vec<&'static str> {
+            vec!["https://example.com/"]
+        }
+
+        async fn parse(
+            &self,
+            response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            if !response.status.is_success() {
+                return Ok(output);
+            }
+            let html = response.to_html()?;
+            if let Some(title) = html.select(&"title".to_selector()?).next() {
+                output.add_item(TitleItem {
+                    url: response.url.to_string(),
+                    title: title.text().collect::<String>().trim().to_string(),
+                });
+            }
+            for link in html.select(&"a".to_selector()?) {
+                if let Some(href) = link.value().attr("href") {
+                    output.add_request(Request::new(response.url.join(href)?));
+                }
+            }
+            Ok(output)
+        }
+    }
+
+    fn site() -> MockResponses {
+        MockResponses::new()
+            .html(
+                "https://example.com/",
+                r#"<title>Home</title><a href="/about">About</a><a href="/missing">Gone</a>"#,
+            )
+            .route(
+                "https://example.com/about",
+                MockResponse::new(200)
+                    .header("content-type", "text/html")
+                    .body("<title>About us</title>"),
+            )
+    }
+
+    #[tokio::test]
+    async fn test_crawl_runs_in_memory_and_collects_items() {
+        let collector = CollectorPipeline::new();
+        let mock = site();
+        let crawler = CrawlerBuilder::new(TitlesSpider)
+            .add_middleware(mock.clone())
+            .add_pipeline(collector.clone())
+            .build()
+            .await
+            .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        let mut items = collector.items();
+        items.sort_by(|a, b| a.url.cmp(&b.url));
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].url, "https://example.com/");
+        assert_eq!(items[0].title, "Home");
+        assert_eq!(items[1].url, "https://example.com/about");
+        assert_eq!(items[1].title, "About us");
+
+        let mut requested: Vec<String> =
+            mock.requested().iter().map(|url| url.to_string()).collect();
+        requested.sort();
+        assert_eq!(
+            requested,
+            vec![
+                "https://example.com/",
+                "https://example.com/about",
+                "https://example.com/missing",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collector_passes_items_on() {
+        let collector = CollectorPipeline::new();
+        assert!(collector.is_empty());
+        let item = TitleItem {
+            url: "https://example.com/".into(),
+            title: "Home".into(),
+        };
+
+        let passed = collector.process_item(item).await.unwrap();
+        assert_eq!(passed.map(|item| item.title), Some("Home".to_string()));
+        assert_eq!(collector.len(), 1);
+        assert_eq!(
+            collector.storage().lock().unwrap()[0].url,
+            "https://example.com/"
+        );
+    }
+}
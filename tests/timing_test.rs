@@ -0,0 +1,74 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_new_connection_reports_every_phase() {
+        let server = TestServer::start(|_| TestResponse::html("<p>timed</p>")).await;
+        let url = Url::parse(&format!("http://localhost:{}/", server.addr.port())).unwrap();
+        let downloader = HttpDownloader::builder()
+            .record_timings(true)
+            .build()
+            .unwrap();
+
+        let first = downloader
+            .download(Request::new(url.clone()))
+            .await
+            .unwrap();
+        let timings = first.timings().expect("timings are recorded");
+        assert!(timings.dns.is_some());
+        assert!(timings.connect.is_some());
+        assert!(timings.ttfb >= timings.dns.unwrap() + timings.connect.unwrap());
+        assert_eq!(timings.total(), timings.ttfb + timings.download);
+
+        // The second request reuses the keep-alive connection.
+        let second = downloader.download(Request::new(url)).await.unwrap();
+        let timings = second.timings().unwrap();
+        assert_eq!(timings.dns, None);
+        assert_eq!(timings.connect, None);
+
+        let stats = downloader.timing_stats().unwrap();
+        assert_eq!(stats.ttfb.count(), 2);
+        assert_eq!(stats.download.count(), 2);
+        assert_eq!(stats.dns.count(), 1);
+        assert_eq!(stats.connect.count(), 1);
+        assert!(stats.ttfb.mean().unwrap() <= stats.ttfb.max());
+        let histogram = stats.ttfb.histogram();
+        assert_eq!(histogram.iter().map(|(_, count)| count).sum::<u64>(), 2);
+        assert_eq!(histogram.last().unwrap().0, Duration::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_timings_are_off_by_default() {
+        let server = TestServer::start(|_| TestResponse::html("<p>plain</p>")).await;
+        let downloader = HttpDownloader::new().unwrap();
+
+        let response = downloader
+            .download(Request::new(server.url("/")))
+            .await
+            .unwrap();
+        assert_eq!(response.timings(), None);
+        assert!(downloader.timing_stats().is_none());
+    }
+
+    #[test]
+    fn test_timings_round_trip_through_meta() {
+        let timings = RequestTimings {
+            dns: None,
+            connect: Some(Duration::from_millis(12)),
+            ttfb: Duration::from_millis(80),
+            download: Duration::from_micros(1500),
+        };
+        assert_eq!(
+            RequestTimings::from_value(&timings.to_value()),
+            Some(timings)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn process(middleware: &mut UrlLengthMiddleware, url: &str) -> MiddlewareAction<Request> {
+        let request = Request::new(Url::parse(url).unwrap());
+        Middleware::<()>::process_request(middleware, &(), request)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pathologically_long_url_is_dropped() {
+        let mut middleware = UrlLengthMiddleware::new();
+        let long = format!("https://example.com/{}", "a/".repeat(5000));
+
+        assert!(matches!(
+            process(&mut middleware, &long).await,
+            MiddlewareAction::Drop
+        ));
+        assert!(matches!(
+            process(&mut middleware, "https://example.com/page").await,
+            MiddlewareAction::Continue(_)
+        ));
+        assert_eq!(middleware.dropped(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_limit_is_configurable() {
+        let mut middleware = UrlLengthMiddleware::with_max_length(30);
+        let counter = middleware.clone();
+
+        assert!(matches!(
+            process(&mut middleware, "https://example.com/short").await,
+            MiddlewareAction::Continue(_)
+        ));
+        assert!(matches!(
+            process(&mut middleware, "https://example.com/a/bit/longer").await,
+            MiddlewareAction::Drop
+        ));
+        assert_eq!(counter.dropped(), 1);
+        assert_eq!(counter.max_length(), 30);
+    }
+}
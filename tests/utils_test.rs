@@ -0,0 +1,323 @@
+use scraper::Html;
+use spider_lib::prelude::*;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain_of(url: &str) -> Option<String> {
+        registrable_domain(&Url::parse(url).expect("valid url"))
+    }
+
+    #[test]
+    fn test_registrable_domain_suffixes() {
+        let cases = [
+            ("https://example.com/", Some("example.com")),
+            ("https://www.example.com/", Some("example.com")),
+            ("https://WWW.Example.COM/", Some("example.com")),
+            ("https://www.example.com./", Some("example.com")),
+            ("https://a.b.example.co.uk/", Some("example.co.uk")),
+            ("https://shop.example.com.au/", Some("example.com.au")),
+            ("https://news.example.co.jp/", Some("example.co.jp")),
+            // Wildcard rule `*.kawasaki.jp` and its exception `!city.kawasaki.jp`.
+            ("https://foo.bar.kawasaki.jp/", Some("foo.bar.kawasaki.jp")),
+            ("https://www.city.kawasaki.jp/", Some("city.kawasaki.jp")),
+            // Private-section suffixes keep tenants apart.
+            ("https://alice.github.io/", Some("alice.github.io")),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(
+                domain_of(url).as_deref(),
+                expected,
+                "registrable domain of {url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_registrable_domain() {
+        let same = |a: &str, b: &str| {
+            same_registrable_domain(&Url::parse(a).unwrap(), &Url::parse(b).unwrap())
+        };
+
+        assert!(same("https://a.example.co.uk/", "http://b.example.co.uk/x"));
+        assert!(!same("https://example.co.uk/", "https://other.co.uk/"));
+        assert!(!same("https://alice.github.io/", "https://bob.github.io/"));
+        assert!(same("http://10.0.0.1/a", "http://10.0.0.1:8080/b"));
+        assert!(!same("http://10.0.0.1/", "http://10.0.0.2/"));
+        assert!(!same("http://10.0.0.1/", "https://example.com/"));
+    }
+
+    #[test]
+    fn test_registrable_domain_without_domain() {
+        let cases = [
+            "https://co.uk/",
+            "https://github.io/",
+            "http://127.0.0.1:8080/",
+            "http://[::1]/",
+            "data:text/plain,hello",
+        ];
+
+        for url in cases {
+            assert_eq!(domain_of(url), None, "registrable domain of {url}");
+        }
+    }
+
+    #[test]
+    fn test_content_disposition_plain_and_quoted() {
+        let cases = [
+            ("attachment; filename=report.pdf", Some("report.pdf")),
+            (
+                "inline; FILENAME=\"annual report.pdf\"",
+                Some("annual report.pdf"),
+            ),
+            ("attachment; filename=\"a;b.txt\"; size=10", Some("a;b.txt")),
+            (
+                "attachment; filename=\"say \\\"hi\\\".txt\"",
+                Some("say _hi_.txt"),
+            ),
+            ("attachment", None),
+            ("attachment; filename=\"\"", None),
+        ];
+
+        for (header, expected) in cases {
+            assert_eq!(
+                content_disposition_filename(header).as_deref(),
+                expected,
+                "filename of {header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_disposition_encoded_filenames() {
+        let cases = [
+            (
+                "attachment; filename*=UTF-8''%E2%82%AC%20rates.txt",
+                Some("€ rates.txt"),
+            ),
+            (
+                "attachment; filename*=utf-8'en'na%C3%AFve.csv",
+                Some("naïve.csv"),
+            ),
+            (
+                "attachment; filename*=ISO-8859-1''caf%E9.txt",
+                Some("café.txt"),
+            ),
+            // The extended parameter wins regardless of order.
+            (
+                "attachment; filename*=UTF-8''%C3%A9t%C3%A9.zip; filename=ete.zip",
+                Some("été.zip"),
+            ),
+            (
+                "attachment; filename=ete.zip; filename*=UTF-8''%C3%A9t%C3%A9.zip",
+                Some("été.zip"),
+            ),
+            // Undecodable extended values fall back to the plain parameter.
+            (
+                "attachment; filename*=KOI8-R''%C1; filename=fallback.txt",
+                Some("fallback.txt"),
+            ),
+            (
+                "attachment; filename*=UTF-8''%FF%FE.txt; filename=fallback.txt",
+                Some("fallback.txt"),
+            ),
+        ];
+
+        for (header, expected) in cases {
+            assert_eq!(
+                content_disposition_filename(header).as_deref(),
+                expected,
+                "filename of {header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_disposition_rejects_path_traversal() {
+        let cases = [
+            ("attachment; filename=\"../../etc/passwd\"", Some("passwd")),
+            (
+                "attachment; filename=\"C:\\\\Windows\\\\evil.exe\"",
+                Some("evil.exe"),
+            ),
+            (
+                "attachment; filename*=UTF-8''..%2F..%2Fsecret.txt",
+                Some("secret.txt"),
+            ),
+            ("attachment; filename=\"..\"", None),
+            ("attachment; filename=.bashrc", Some("bashrc")),
+            (
+                "attachment; filename=\"bad\u{7}name?.txt\"",
+                Some("bad_name_.txt"),
+            ),
+        ];
+
+        for (header, expected) in cases {
+            assert_eq!(
+                content_disposition_filename(header).as_deref(),
+                expected,
+                "filename of {header:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_download_filename_falls_back_to_url() {
+        let url = Url::parse("https://example.com/files/report%20v2.pdf?download=1").unwrap();
+        assert_eq!(download_filename(None, &url), "report v2.pdf");
+        assert_eq!(download_filename(Some("attachment"), &url), "report v2.pdf");
+        assert_eq!(
+            download_filename(Some("attachment; filename=q3.pdf"), &url),
+            "q3.pdf"
+        );
+
+        let url = Url::parse("https://example.com/files/").unwrap();
+        assert_eq!(download_filename(None, &url), "files");
+
+        let url = Url::parse("https://example.com/").unwrap();
+        assert_eq!(download_filename(None, &url), "download");
+    }
+
+    #[test]
+    fn test_canonical_url_resolution_and_origin_check() {
+        let base = Url::parse("https://example.com/products/42?ref=home").unwrap();
+        let canonical = |head: &str, same_origin: bool| {
+            let html =
+                Html::parse_document(&format!("<html><head>{head}</head><body></body></html>"));
+            canonical_url(&html, &base, same_origin).map(|url| url.to_string())
+        };
+
+        assert_eq!(
+            canonical(r#"<link rel="canonical" href="/products/42">"#, true).as_deref(),
+            Some("https://example.com/products/42")
+        );
+        assert_eq!(
+            canonical(
+                concat!(
+                    r#"<link rel="alternate stylesheet" href="/a.css">"#,
+                    r#"<link REL="Canonical" href=" https://example.com/p/42 ">"#,
+                ),
+                true
+            )
+            .as_deref(),
+            Some("https://example.com/p/42")
+        );
+        assert_eq!(
+            canonical(
+                r#"<link rel="canonical" href="https://mirror.example.org/p/42">"#,
+                true
+            ),
+            None
+        );
+        assert_eq!(
+            canonical(
+                r#"<link rel="canonical" href="https://mirror.example.org/p/42">"#,
+                false
+            )
+            .as_deref(),
+            Some("https://mirror.example.org/p/42")
+        );
+        assert_eq!(
+            canonical(
+                r#"<link rel="canonical" href="http://example.com/products/42">"#,
+                true
+            ),
+            None
+        );
+        assert_eq!(
+            canonical(r#"<link rel="canonical" href="javascript:void(0)">"#, false),
+            None
+        );
+        assert_eq!(canonical(r#"<link rel="canonical" href="">"#, false), None);
+        assert_eq!(
+            canonical(r#"<link rel="alternate" href="/fr/products/42">"#, false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_canonical_url_in_head_scans_link_tags() {
+        let base = Url::parse("https://example.com/products/42?ref=home").unwrap();
+        let canonical = |page: &str| {
+            canonical_url_in_head(page.as_bytes(), &base, true).map(|url| url.to_string())
+        };
+
+        assert_eq!(
+            canonical(concat!(
+                r#"<html><head><linkset rel="canonical" href="/no">"#,
+                r#"<LINK href='/p/42?a=1&amp;b=2' data-x=1 REL=canonical /></head>"#,
+            ))
+            .as_deref(),
+            Some("https://example.com/p/42?a=1&b=2")
+        );
+        assert_eq!(
+            canonical(r#"<link rel="alternate canonical" href=/p/42>"#).as_deref(),
+            Some("https://example.com/p/42")
+        );
+        assert_eq!(
+            canonical(r#"<head></head><body><link rel="canonical" href="/p/42">"#),
+            None
+        );
+        let late = format!(
+            r#"<head>{}<link rel="canonical" href="/p/42"></head>"#,
+            " ".repeat(20_000)
+        );
+        assert_eq!(canonical(&late), None);
+        assert_eq!(
+            canonical(r#"<link rel="canonical" href="https://mirror.example.org/p/42">"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_url() {
+        let cases = [
+            ("http://x/?a=1&b=2", "http://x/?a=1&b=2"),
+            ("http://x/?b=2&a=1", "http://x/?a=1&b=2"),
+            (
+                "HTTP://Example.COM:80/Path?b=2&a=1#top",
+                "http://example.com/Path?a=1&b=2",
+            ),
+            (
+                "https://example.com:443/?utm_source=x&id=7&UTM_Medium=y&fbclid=z",
+                "https://example.com/?id=7",
+            ),
+            ("https://example.com/?utm_source=x", "https://example.com/"),
+            ("https://example.com/?", "https://example.com/"),
+            (
+                "https://example.com/?q=a+b&q=a%20a",
+                "https://example.com/?q=a+a&q=a+b",
+            ),
+            ("https://example.com:8443/p", "https://example.com:8443/p"),
+        ];
+
+        for (url, expected) in cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(
+                canonicalize_url(&url).as_str(),
+                expected,
+                "canonical form of {url}"
+            );
+        }
+
+        let url = Url::parse("https://example.com/?session=1&sort=asc&ref_id=2&ref=3").unwrap();
+        assert_eq!(
+            canonicalize_url_with(&url, &["session", "ref_*"]).as_str(),
+            "https://example.com/?ref=3&sort=asc"
+        );
+    }
+
+    #[test]
+    fn test_utils_re_exports_spider_util() {
+        let a = Url::parse("https://example.com/a").unwrap();
+        let b = Url::parse("https://example.com/b").unwrap();
+        assert!(spider_lib::utils::is_same_site(&a, &b));
+        assert_eq!(
+            spider_lib::utils::canonicalize_url(&a).as_str(),
+            "https://example.com/a"
+        );
+    }
+}
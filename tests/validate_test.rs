@@ -0,0 +1,158 @@
+mod common;
+
+use common::{TestResponse, TestServer};
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: &'static str, body: &'static str) -> Response {
+        let request = Request::new(Url::parse("https://api.example.com/items").unwrap());
+        MockResponse::new(200)
+            .header("content-type", content_type)
+            .body(body)
+            .into_response(request)
+    }
+
+    #[test]
+    fn test_validators() {
+        let json = response("application/json; charset=utf-8", r#"{"items": []}"#);
+        let block = response("text/html", "<html>Please solve the captcha</html>");
+
+        assert!(ResponseValidator::Json.check(&json).is_ok());
+        assert!(ResponseValidator::Json.check(&block).is_err());
+
+        let expects_json = ResponseValidator::ContentType("application/json".into());
+        assert!(expects_json.check(&json).is_ok());
+        assert_eq!(
+            expects_json.check(&block).unwrap_err(),
+            "content type is `text/html`, expected `application/json`"
+        );
+
+        assert!(
+            ResponseValidator::Contains("items".into())
+                .check(&json)
+                .is_ok()
+        );
+        assert!(
+            ResponseValidator::Contains("items".into())
+                .check(&block)
+                .is_err()
+        );
+        assert!(
+            ResponseValidator::NotContains("captcha".into())
+                .check(&json)
+                .is_ok()
+        );
+        assert!(
+            ResponseValidator::NotContains("captcha".into())
+                .check(&block)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validators_travel_in_meta() {
+        let request = Request::new(Url::parse("https://api.example.com/items").unwrap())
+            .validate(ResponseValidator::Json)
+            .validate(ResponseValidator::NotContains("captcha".into()));
+        assert_eq!(
+            request.validators(),
+            [
+                ResponseValidator::Json,
+                ResponseValidator::NotContains("captcha".into())
+            ]
+        );
+    }
+
+    #[scraped_item]
+    pub struct Outcome {
+        pub parsed: bool,
+    }
+
+    pub struct ApiSpider {
+        start: Url,
+        errors: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ApiSpider {
+        async fn parse_error(
+            &self,
+            error: SpiderError,
+            _response: Response,
+        ) -> Result<ParseOutput<Outcome>, SpiderError> {
+            self.errors.lock().unwrap().push(error.to_string());
+            Ok(ParseOutput::new())
+        }
+    }
+
+    #[async_trait]
+    impl Spider for ApiSpider {
+        type Item = Outcome;
+        type State = ();
+
+        fn start_requests(&self) -> Result<Vec<Request>, SpiderError> {
+            Ok(vec![
+                Request::new(self.start.clone()).validate(ResponseValidator::Json),
+            ])
+        }
+
+        async fn parse(
+            &self,
+            _response: Response,
+            _state: &Self::State,
+        ) -> Result<ParseOutput<Self::Item>, SpiderError> {
+            let mut output = ParseOutput::new();
+            output.add_item(Outcome { parsed: true });
+            Ok(output)
+        }
+    }
+
+    impl CallbackSpider for ApiSpider {
+        fn callbacks() -> Callbacks<Self> {
+            Callbacks::<Self>::new().errback(|spider, error, response, _state| {
+                Box::pin(spider.parse_error(error, response))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_html_block_page_goes_to_errback_after_retries() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let server = TestServer::start(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            TestResponse::html("<html><body>Access denied</body></html>")
+        })
+        .await;
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let crawler = CrawlerBuilder::new(
+            ApiSpider {
+                start: server.url("/api/items"),
+                errors: errors.clone(),
+            }
+            .routed(),
+        )
+        .add_middleware(
+            ValidationMiddleware::new()
+                .max_retries(2)
+                .retry_delay(Duration::from_millis(10)),
+        )
+        .build()
+        .await
+        .unwrap();
+        crawler.start_crawl().await.unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("failed validation: body is not JSON"));
+    }
+}
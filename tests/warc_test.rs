@@ -0,0 +1,82 @@
+#![cfg(feature = "middleware-warc")]
+
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use url::Url;
+use warc::{RecordType, WarcHeader, WarcReader};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer whose output stays readable after the middleware takes ownership of it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn response_for(request: Request, body: &'static str) -> Response {
+        MockResponse::new(200)
+            .header("content-type", "text/html")
+            .header("content-encoding", "gzip")
+            .body(body)
+            .into_response(request)
+    }
+
+    #[tokio::test]
+    async fn test_writes_linked_request_and_response_records() {
+        let buffer = SharedBuffer::default();
+        let mut middleware = WarcWriterMiddleware::from_writer(buffer.clone()).unwrap();
+
+        let url = Url::parse("https://example.com/page?id=7").unwrap();
+        let action = Middleware::<()>::process_request(&mut middleware, &(), Request::new(url))
+            .await
+            .unwrap();
+        let MiddlewareAction::Continue(request) = action else {
+            panic!("the WARC middleware must pass requests on");
+        };
+        Middleware::<()>::process_response(&mut middleware, response_for(request, "<p>hi</p>"))
+            .await
+            .unwrap();
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let records: Vec<_> = WarcReader::new(&bytes[..])
+            .iter_records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].warc_type(), &RecordType::WarcInfo);
+        assert!(records.iter().all(|record| record.warc_version() == "1.1"));
+
+        let (request, response) = (&records[1], &records[2]);
+        assert_eq!(request.warc_type(), &RecordType::Request);
+        assert_eq!(
+            request.header(WarcHeader::TargetURI).unwrap(),
+            "https://example.com/page?id=7"
+        );
+        let request_block = String::from_utf8_lossy(request.body());
+        assert!(request_block.starts_with("GET /page?id=7 HTTP/1.1\r\nhost: example.com\r\n"));
+
+        assert_eq!(response.warc_type(), &RecordType::Response);
+        assert_eq!(
+            response.header(WarcHeader::ConcurrentTo).unwrap(),
+            request.warc_id()
+        );
+        let response_block = String::from_utf8_lossy(response.body());
+        assert!(response_block.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response_block.contains("content-length: 9\r\n"));
+        assert!(!response_block.contains("content-encoding"));
+        assert!(response_block.ends_with("\r\n\r\n<p>hi</p>"));
+    }
+}
@@ -0,0 +1,150 @@
+use scraper::Html;
+use spider_lib::prelude::*;
+use spider_lib::testing::MockResponse;
+use url::Url;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <div class="quote" id="q1">
+            <span class="text">First</span>
+            <span>by <small class="author">Ada</small></span>
+            <a class="tag" href="/tag/a">a</a><a class="tag" href="/tag/b">b</a>
+        </div>
+        <div class="quote featured" id="q2">
+            <span class="text">Second</span>
+            <span>by <small class="author">Grace</small></span>
+        </div>
+        <dl><dt>Price</dt><dd>10</dd><dt>Stock</dt><dd> 3 </dd></dl>
+    </body></html>"#;
+
+    fn texts(html: &Html, expr: &str) -> Vec<String> {
+        xpath(html, expr)
+            .unwrap_or_else(|err| panic!("{expr}: {err}"))
+            .iter()
+            .map(XPathNode::text)
+            .collect()
+    }
+
+    #[test]
+    fn test_paths_predicates_and_functions() {
+        let html = Html::parse_document(PAGE);
+
+        let cases: &[(&str, &[&str])] = &[
+            ("//div[@class='quote']/span[1]", &["First"]),
+            ("//div[contains(@class, 'quote')]//small", &["Ada", "Grace"]),
+            ("//div[2]/span[@class='text']/text()", &["Second"]),
+            ("//a[@class='tag'][last()]/@href", &["/tag/b"]),
+            ("//a[position() > 1]", &["b"]),
+            ("//div[count(a) = 2]/@id", &["q1"]),
+            ("//small[.='Grace']/ancestor::div/@id", &["q2"]),
+            ("//dt[.='Stock']/following-sibling::dd[1]", &[" 3 "]),
+            (
+                "//dd[normalize-space() = '3']/preceding-sibling::dt[1]",
+                &["Stock"],
+            ),
+            ("//dd[. > 5]", &["10"]),
+            ("//div[not(contains(@class, 'featured'))]/@id", &["q1"]),
+            ("//a[starts-with(@href, '/tag/') and . != 'a']", &["b"]),
+            (
+                "//small/../..//span[@class='text'] | //dt[1]",
+                &["First", "Second", "Price"],
+            ),
+            ("/html/body/dl/*[3]", &["Stock"]),
+            ("//nope", &[]),
+        ];
+
+        for (expr, expected) in cases {
+            assert_eq!(texts(&html, expr), *expected, "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_nodes_expose_elements_and_attributes() {
+        let html = Html::parse_document(PAGE);
+        let quotes = XPath::parse("//div[@id]").unwrap().select(&html);
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[1].attr("id"), Some("q2"));
+
+        // Relative paths run from the element; CSS works on the same nodes.
+        let first = quotes[0].as_element().unwrap();
+        let authors = XPath::parse(".//small/text()").unwrap().select_from(first);
+        assert_eq!(authors.len(), 1);
+        assert!(matches!(authors[0], XPathNode::Text("Ada")));
+        let tag = ".tag".to_selector().unwrap();
+        assert_eq!(first.select(&tag).count(), 2);
+
+        let hrefs = xpath(&html, "//a/@href").unwrap();
+        assert!(matches!(
+            hrefs[0],
+            XPathNode::Attribute {
+                name: "href",
+                value: "/tag/a"
+            }
+        ));
+        assert_eq!(hrefs[0].attr("href"), None);
+    }
+
+    #[test]
+    fn test_document_shares_one_parse_between_xpath_and_css() {
+        let page = XPathDocument::parse(PAGE);
+        let quotes = page.xpath("//div[@class]").unwrap();
+        assert_eq!(quotes.len(), 2);
+
+        let tag = ".tag".to_selector().unwrap();
+        let mut texts = Vec::new();
+        for quote in &quotes {
+            let quote = quote.as_element().unwrap();
+            let text = page.xpath_from(quote, "./span[1]").unwrap();
+            texts.push(text[0].text());
+            // Absolute paths still start at the root.
+            assert_eq!(page.xpath_from(quote, "//dt").unwrap().len(), 2);
+            assert!(quote.select(&tag).count() <= 2);
+        }
+        assert_eq!(texts, ["First", "Second"]);
+        assert_eq!(page.html().select(&tag).count(), 2);
+    }
+
+    #[test]
+    fn test_response_xpath_returns_nodes() {
+        let url = Url::parse("https://example.com/quotes").unwrap();
+        let response = MockResponse::new(200)
+            .body(PAGE)
+            .into_response(Request::new(url));
+
+        let quotes = response.xpath("//div[@class='quote']/span[1]").unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].text(), "First");
+        assert_eq!(quotes[0].name(), Some("span"));
+        assert_eq!(quotes[0].attr("class"), Some("text"));
+
+        let hrefs = response.xpath("//a/@href").unwrap();
+        assert_eq!(hrefs[1].text(), "/tag/b");
+        assert_eq!(hrefs[1].name(), Some("href"));
+        assert_eq!(hrefs[1].attr("href"), None);
+        assert!(matches!(
+            response.xpath("//div["),
+            Err(XPathError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_errors() {
+        for expr in [
+            "//div[",
+            "//div[@class='x]",
+            "//div[foo()]",
+            "following::div",
+            "//div]",
+            "count(//div)",
+            "",
+        ] {
+            assert!(
+                matches!(XPath::parse(expr), Err(XPathError::Syntax(_))),
+                "{expr} should not parse"
+            );
+        }
+    }
+}